@@ -0,0 +1,40 @@
+//! Compiles a 10-clause `AND` filter with `CompiledFilter::compile`.
+
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use sqlparser::dialect::ClickHouseDialect;
+use sqlparser::parser::Parser;
+use touchhouse::sql::CompiledFilter;
+use touchhouse::storage::{ColumnDef, Constraints, ValueType};
+
+const CLAUSES: usize = 10;
+
+fn bench_filter_compilation(c: &mut Criterion) {
+    let column_defs: Vec<ColumnDef> = (0..CLAUSES)
+        .map(|idx| ColumnDef {
+            name: format!("c{idx}"),
+            field_type: ValueType::UInt64,
+            constraints: Constraints::default(),
+        })
+        .collect();
+
+    let operators = ["=", "!=", "<", "<=", ">", ">="];
+    let filter = (0..CLAUSES)
+        .map(|idx| format!("c{idx} {} {idx}", operators[idx % operators.len()]))
+        .collect::<Vec<_>>()
+        .join(" AND ");
+    let expr = Parser::new(&ClickHouseDialect {})
+        .try_with_sql(&filter)
+        .and_then(|mut parser| parser.parse_expr())
+        .unwrap();
+
+    c.bench_function("bench_filter_compilation", |b| {
+        b.iter_batched(
+            || expr.clone(),
+            |expr| CompiledFilter::compile(expr, &column_defs).unwrap(),
+            BatchSize::SmallInput,
+        );
+    });
+}
+
+criterion_group!(benches, bench_filter_compilation);
+criterion_main!(benches);
@@ -0,0 +1,184 @@
+//! Scans a table of 1000 granules (8192 rows each) with a filter on the primary key column,
+//! which selects 1% of rows, and with a 2-conjunct filter, whose cheap conjunct matches 0.1% of
+//! rows of every granule. Pruned scan of 30 granules compares fixed chunks of 10 granules with
+//! chunks sized by the number of threads.
+
+use criterion::{Criterion, criterion_group};
+use std::path::PathBuf;
+use touchhouse::CONFIG;
+use touchhouse::access::AccessLevel;
+use touchhouse::runtime_config::TABLE_DATA;
+use touchhouse::sql::CommandRunner;
+use touchhouse::storage::{self, Column, TableDef, Value};
+use uuid::Uuid;
+
+const GRANULE_ROWS: u64 = 8192;
+const GRANULES: u64 = 1000;
+const PARTS: u64 = 10;
+const TOTAL_ROWS: u64 = GRANULE_ROWS * GRANULES;
+
+/// Points `CONFIG` at a fresh storage directory and fills `bench_db.scan` with `PARTS` parts.
+///
+/// Returns: directory to remove after benchmarks.
+fn setup() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("touchhouse-bench-scan-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let config_path = dir.join("touch_config.toml");
+    std::fs::write(
+        &config_path,
+        format!(
+            "storage_directory = \"{}\"\n\
+             tcp_socket = \"127.0.0.1:0\"\n\
+             max_connections = 1\n\
+             log_level = 3\n\
+             background_merge_available_under = 5\n",
+            dir.join("db").display()
+        ),
+    )
+    .unwrap();
+    // SAFETY: no other threads are running yet, and `CONFIG` was not read before.
+    unsafe { std::env::set_var("CONFIG_PATH", &config_path) };
+
+    storage::load_all_parts_on_startup(CONFIG.get_db_dir()).unwrap();
+    CommandRunner::execute_command(
+        "CREATE DATABASE bench_db",
+        AccessLevel::ReadWrite,
+        Uuid::now_v7(),
+    )
+    .unwrap();
+    CommandRunner::execute_command(
+        "CREATE TABLE bench_db.scan (id UInt64, value Int64) ENGINE = MergeTree ORDER BY id",
+        AccessLevel::ReadWrite,
+        Uuid::now_v7(),
+    )
+    .unwrap();
+
+    let table_def = TableDef {
+        database: "bench_db".to_string(),
+        table: "scan".to_string(),
+    };
+    let column_defs = TABLE_DATA
+        .get(&table_def)
+        .unwrap()
+        .metadata
+        .schema
+        .columns
+        .clone();
+
+    let part_rows = TOTAL_ROWS / PARTS;
+    for part in 0..PARTS {
+        let ids = part * part_rows..(part + 1) * part_rows;
+        let columns = vec![
+            Column {
+                column_def: column_defs[0].clone(),
+                data: ids.clone().map(Value::UInt64).collect(),
+            },
+            Column {
+                column_def: column_defs[1].clone(),
+                data: ids.map(|id| Value::Int64(id as i64 % 1000)).collect(),
+            },
+        ];
+        CommandRunner::insert(&table_def, columns).unwrap();
+    }
+
+    dir
+}
+
+fn bench_granule_scan(c: &mut Criterion) {
+    let start = TOTAL_ROWS / 2;
+    let query = format!(
+        "SELECT id, value FROM bench_db.scan WHERE id >= {start} AND id < {}",
+        start + TOTAL_ROWS / 100
+    );
+
+    let output =
+        CommandRunner::execute_command(&query, AccessLevel::ReadWrite, Uuid::now_v7()).unwrap();
+    assert_eq!(output.columns[0].data.len() as u64, TOTAL_ROWS / 100);
+
+    let mut group = c.benchmark_group("bench_granule_scan");
+    group.sample_size(20);
+    group.bench_function("1000 granules, 1% selectivity", |b| {
+        b.iter(|| {
+            CommandRunner::execute_command(&query, AccessLevel::ReadWrite, Uuid::now_v7()).unwrap()
+        });
+    });
+    group.finish();
+}
+
+/// Expensive conjunct is evaluated only over rows, which passed the cheap one, when it goes first.
+/// In the reversed order, it is evaluated over all rows.
+fn bench_selective_and(c: &mut Criterion) {
+    let cheap = "value = 7";
+    let expensive = "cityHash64(id) % 1000 < 999";
+
+    let mut group = c.benchmark_group("bench_selective_and");
+    group.sample_size(20);
+    for (name, filter) in [
+        ("0.1% conjunct first", format!("{cheap} AND {expensive}")),
+        ("0.1% conjunct last", format!("{expensive} AND {cheap}")),
+    ] {
+        let query = format!("SELECT id FROM bench_db.scan WHERE {filter}");
+        let output =
+            CommandRunner::execute_command(&query, AccessLevel::ReadWrite, Uuid::now_v7()).unwrap();
+        assert!(output.columns[0].data.len() as u64 <= TOTAL_ROWS / 1000);
+
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                CommandRunner::execute_command(&query, AccessLevel::ReadWrite, Uuid::now_v7())
+                    .unwrap()
+            });
+        });
+    }
+    group.finish();
+}
+
+/// Primary key selects 30 granules of a single part. Chunks of 10 granules scan them on 3 threads
+/// only, while adaptive chunks spread them among all threads.
+fn bench_pruned_scan(c: &mut Criterion) {
+    let start = TOTAL_ROWS / 2;
+    let filter = format!(
+        "id >= {start} AND id < {} AND cityHash64(id) % 1000 < 999",
+        start + 30 * GRANULE_ROWS
+    );
+
+    let mut group = c.benchmark_group("bench_pruned_scan");
+    group.sample_size(20);
+    for (name, settings) in [
+        (
+            "30 granules, chunks of 10",
+            " SETTINGS scan_chunk_granules = 10",
+        ),
+        ("30 granules, adaptive chunks", ""),
+    ] {
+        let query = format!("SELECT id, value FROM bench_db.scan WHERE {filter}{settings}");
+        let output =
+            CommandRunner::execute_command(&query, AccessLevel::ReadWrite, Uuid::now_v7()).unwrap();
+        assert!(output.columns[0].data.len() as u64 <= 30 * GRANULE_ROWS);
+
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                CommandRunner::execute_command(&query, AccessLevel::ReadWrite, Uuid::now_v7())
+                    .unwrap()
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_granule_scan,
+    bench_selective_and,
+    bench_pruned_scan
+);
+
+fn main() {
+    let dir = setup();
+    benches();
+    criterion::Criterion::default()
+        .configure_from_args()
+        .final_summary();
+    let _ = std::fs::remove_dir_all(dir);
+}
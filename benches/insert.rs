@@ -0,0 +1,126 @@
+//! Inserts 10K and 100K rows end-to-end: client sends a single `INSERT` over TCP to the server
+//! binary.
+
+use criterion::{Criterion, criterion_group};
+use db_rs_client::Client;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+
+const ROWS: usize = 10_000;
+const LARGE_ROWS: usize = 100_000;
+
+/// Server process with its own storage directory. Killed and cleaned up on drop.
+struct BenchServer {
+    process: Child,
+    dir: PathBuf,
+    addr: String,
+}
+
+impl BenchServer {
+    fn start() -> Self {
+        let dir =
+            std::env::temp_dir().join(format!("touchhouse-bench-insert-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let addr = format!("127.0.0.1:{port}");
+
+        let config_path = dir.join("touch_config.toml");
+        std::fs::write(
+            &config_path,
+            format!(
+                "storage_directory = \"{}\"\n\
+                 tcp_socket = \"{addr}\"\n\
+                 max_connections = 10\n\
+                 log_level = 3\n\
+                 background_merge_available_under = 5\n\
+                 max_parts_per_table = 0\n",
+                dir.join("db").display()
+            ),
+        )
+        .unwrap();
+
+        let process = Command::new(env!("CARGO_BIN_EXE_touchhouse"))
+            .env("CONFIG_PATH", &config_path)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+
+        Self { process, dir, addr }
+    }
+
+    async fn connect(&self) -> Client {
+        for _ in 0..100 {
+            if let Ok(client) = Client::connect(&self.addr).await {
+                return client;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("Server did not start on {}", self.addr);
+    }
+}
+
+impl Drop for BenchServer {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn bench_insert(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let server = BenchServer::start();
+    let mut client = runtime.block_on(async {
+        let mut client = server.connect().await;
+        client.execute("CREATE DATABASE bench_db").await.unwrap();
+        client
+            .execute("CREATE TABLE bench_db.insert (id UInt64, name String) ORDER BY id")
+            .await
+            .unwrap();
+        client
+    });
+
+    let query = insert_query(ROWS);
+    let large_query = insert_query(LARGE_ROWS);
+
+    let mut group = c.benchmark_group("bench_insert");
+    group.sample_size(20);
+    group.bench_function("10K rows via TCP", |b| {
+        b.iter(|| runtime.block_on(client.execute(&query)).unwrap());
+    });
+    group.sample_size(10);
+    group.bench_function("100K rows in one statement via TCP", |b| {
+        b.iter(|| runtime.block_on(client.execute(&large_query)).unwrap());
+    });
+    group.finish();
+}
+
+/// Returns `INSERT` of `rows` rows in a single `VALUES` clause.
+fn insert_query(rows: usize) -> String {
+    let values = (0..rows)
+        .map(|id| format!("({id}, 'name_{id}')"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!("INSERT INTO bench_db.insert (id, name) VALUES {values}")
+}
+
+criterion_group!(benches, bench_insert);
+
+fn main() {
+    benches();
+    criterion::Criterion::default()
+        .configure_from_args()
+        .final_summary();
+}
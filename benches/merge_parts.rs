@@ -0,0 +1,56 @@
+//! Merges two 1M-row parts the way background merge does: projects and concatenates both parts,
+//! then orders rows with `MergeTreeEngine`.
+
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use touchhouse::background_merge::BackgroundMerge;
+use touchhouse::engines::{EngineConfig, EngineName};
+use touchhouse::storage::{Column, ColumnDef, Constraints, Value, ValueType};
+
+const PART_ROWS: u64 = 1_000_000;
+
+/// Returns sorted part with ids `offset, offset + 2, ...`, so rows of both parts interleave.
+fn build_part(column_defs: &[ColumnDef], offset: u64) -> Vec<Column> {
+    let ids = (0..PART_ROWS).map(|idx| idx * 2 + offset);
+    vec![
+        Column {
+            column_def: column_defs[0].clone(),
+            data: ids.clone().map(Value::UInt64).collect(),
+        },
+        Column {
+            column_def: column_defs[1].clone(),
+            data: ids.map(|id| Value::Int64(-(id as i64))).collect(),
+        },
+    ]
+}
+
+fn bench_merge_parts(c: &mut Criterion) {
+    let column_defs =
+        [("id", ValueType::UInt64), ("value", ValueType::Int64)].map(|(name, field_type)| {
+            ColumnDef {
+                name: name.to_string(),
+                field_type,
+                constraints: Constraints::default(),
+            }
+        });
+    let order_by = &column_defs[..1];
+    let part_0 = build_part(&column_defs, 0);
+    let part_1 = build_part(&column_defs, 1);
+    let engine = EngineName::MergeTree.get_engine(EngineConfig::default());
+
+    let mut group = c.benchmark_group("bench_merge_parts");
+    group.sample_size(10);
+    group.bench_function("2x1M rows", |b| {
+        b.iter_batched(
+            || (part_0.clone(), part_1.clone()),
+            |(part_0, part_1)| {
+                let merged = BackgroundMerge::merge_parts(part_0, part_1, &column_defs).unwrap();
+                engine.order_columns(merged, order_by, order_by).unwrap()
+            },
+            BatchSize::LargeInput,
+        );
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_merge_parts);
+criterion_main!(benches);
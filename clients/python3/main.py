@@ -1,4 +1,5 @@
 import socket
+import uuid
 import struct
 import argparse
 import msgpack
@@ -39,6 +40,16 @@ def decode_and_print_table(message_bytes: bytes):
         total_ms = secs * 1000 + nanos / 1_000_000
         print(f"Execution time: {total_ms:.2f} ms")
 
+        if len(message["Ok"]) > 2:
+            queue_secs, queue_nanos = message["Ok"][2]
+            print(f"Queue time: {queue_secs * 1000 + queue_nanos / 1_000_000:.2f} ms")
+
+        if len(message["Ok"]) > 3:
+            print(f"Query id: {uuid.UUID(bytes=bytes(message['Ok'][3]))}")
+
+        if len(message["Ok"]) > 4:
+            print(f"Skipped unreadable parts: {message['Ok'][4]}")
+
     elif error := message.get("Err"):
         print(f"Error: {error}")
 
@@ -51,6 +62,9 @@ def run(host: str, port: int):
 
         while True:
             sql_command = input("> ")
+            # server rejects empty commands and closes the connection
+            if not sql_command.strip():
+                continue
 
             encoded_command = encode_message(sql_command)
             _ = sock.send(encoded_command)
@@ -0,0 +1,126 @@
+use crate::error::{Error, Result};
+use crate::types::OutputTable;
+
+use std::io::ErrorKind;
+use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+use tokio::net::{TcpStream, ToSocketAddrs};
+
+type HeaderType = u64;
+const HEADER_SIZE: usize = size_of::<HeaderType>();
+
+/// Single connection to the server. Queries are executed one at a time.
+#[derive(Debug)]
+pub struct Client {
+    stream: TcpStream,
+}
+
+impl Client {
+    /// Connects to the server.
+    ///
+    /// Returns:
+    ///   * Ok: connected `Client`.
+    ///   * Error: `Connect` when connection could not be established.
+    pub async fn connect(addr: impl ToSocketAddrs) -> Result<Self> {
+        let stream = TcpStream::connect(addr).await.map_err(Error::Connect)?;
+        stream.set_nodelay(true).map_err(Error::Connect)?;
+
+        Ok(Self { stream })
+    }
+
+    /// Executes a single SQL command.
+    ///
+    /// Returns:
+    ///   * Ok: `OutputTable` with query results.
+    ///   * Error when:
+    ///     1. Server returned error: `Server`.
+    ///     2. Connection failed: `ConnectionBroken` or `ConnectionClosed`.
+    ///     3. Response could not be decoded: `Protocol`.
+    pub async fn query(&mut self, sql: &str) -> Result<OutputTable> {
+        self.send(sql).await?;
+        self.receive().await
+    }
+
+    /// Executes a single SQL command, discarding its output (e.g., `INSERT`).
+    ///
+    /// Returns:
+    ///   * Ok: command succeeded.
+    ///   * Error: any error from `query`.
+    pub async fn execute(&mut self, sql: &str) -> Result<()> {
+        self.query(sql).await.map(|_| ())
+    }
+
+    /// Checks that connection is alive with `PING` command.
+    ///
+    /// Returns:
+    ///   * Ok: server acknowledged `PING`.
+    ///   * Error: any error from `query`.
+    pub async fn ping(&mut self) -> Result<()> {
+        self.execute("PING").await
+    }
+
+    /// Sends `exit` and waits for server to close the connection.
+    ///
+    /// Returns:
+    ///   * Ok: server acknowledged `exit` and closed the connection.
+    ///   * Error: any error from `query`, or `Protocol` when server sent more data.
+    pub async fn close(mut self) -> Result<()> {
+        self.query("exit").await?;
+
+        let mut buf = [0; 1];
+        match self.stream.read(&mut buf).await {
+            Ok(0) => Ok(()),
+            Ok(_) => Err(Error::Protocol(
+                "Server sent data after acknowledging exit".to_string(),
+            )),
+            Err(error) => Err(Error::ConnectionBroken(error)),
+        }
+    }
+
+    /// Sends SQL command as a single frame.
+    pub(crate) async fn send(&mut self, sql: &str) -> Result<()> {
+        let mut frame = Vec::with_capacity(HEADER_SIZE + sql.len());
+        frame.extend_from_slice(&(sql.len() as HeaderType).to_le_bytes()); // HEADER
+        frame.extend_from_slice(sql.as_bytes()); // BODY
+
+        self.stream
+            .write_all(&frame)
+            .await
+            .map_err(Error::ConnectionBroken)
+    }
+
+    /// Receives a single response frame.
+    pub(crate) async fn receive(&mut self) -> Result<OutputTable> {
+        let mut header = [0; HEADER_SIZE];
+        self.stream
+            .read_exact(&mut header)
+            .await
+            .map_err(read_error)?;
+
+        let body_size = usize::try_from(HeaderType::from_le_bytes(header))
+            .map_err(|_| Error::Protocol("Response is too large".to_string()))?;
+        let mut body = vec![0; body_size];
+        self.stream
+            .read_exact(&mut body)
+            .await
+            .map_err(read_error)?;
+
+        rmp_serde::from_slice::<std::result::Result<OutputTable, String>>(&body)
+            .map_err(|error| Error::Protocol(error.to_string()))?
+            .map_err(Error::Server)
+    }
+
+    /// Whether connection can no longer be used: server closed it, or sent data nobody asked for
+    /// (e.g., idle timeout notice right before closing).
+    pub(crate) fn is_stale(&self) -> bool {
+        let mut buf = [0; 1];
+        !matches!(self.stream.try_read(&mut buf), Err(error) if error.kind() == ErrorKind::WouldBlock)
+    }
+}
+
+fn read_error(error: std::io::Error) -> Error {
+    if error.kind() == ErrorKind::UnexpectedEof {
+        Error::ConnectionClosed
+    } else {
+        Error::ConnectionBroken(error)
+    }
+}
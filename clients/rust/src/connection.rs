@@ -0,0 +1,122 @@
+use crate::client::Client;
+use crate::error::{Error, Result};
+use crate::types::OutputTable;
+
+use futures::Stream;
+use tokio::sync::Mutex;
+
+/// Connection to the server, which is used through `&self`, so it can be shared by tasks.
+/// Commands of concurrent callers are sent one at a time. Broken connection is reestablished
+/// by the next command.
+#[derive(Debug)]
+pub struct Connection {
+    addr: String,
+    /// `None` when connection broke, or a command was cancelled before its response arrived.
+    client: Mutex<Option<Client>>,
+}
+
+impl Connection {
+    /// Connects to the server.
+    ///
+    /// Returns:
+    ///   * Ok: connected `Connection`.
+    ///   * Error: `Connect` when connection could not be established.
+    pub async fn connect(addr: impl Into<String>) -> Result<Self> {
+        let addr = addr.into();
+        let client = Client::connect(addr.as_str()).await?;
+        Ok(Self {
+            addr,
+            client: Mutex::new(Some(client)),
+        })
+    }
+
+    /// Executes a single SQL command, reconnecting when connection is broken (e.g., `BrokenPipe`
+    /// after the server closed an idle connection).
+    ///
+    /// Command is resent on a new connection only when it could not be sent, so it is never
+    /// executed twice. When connection breaks while waiting for response, error is returned
+    /// and the next command reconnects.
+    ///
+    /// Returns:
+    ///   * Ok: `OutputTable` with query results.
+    ///   * Error: any error from `Client::connect` or `Client::query`.
+    pub async fn query(&self, sql: &str) -> Result<OutputTable> {
+        let mut slot = self.client.lock().await;
+        // taken until the response arrives, so a cancelled command leaves no response behind
+        let mut client = match slot.take() {
+            Some(client) if !client.is_stale() => client,
+            _ => Client::connect(self.addr.as_str()).await?,
+        };
+
+        if let Err(Error::ConnectionBroken(_)) = client.send(sql).await {
+            client = Client::connect(self.addr.as_str()).await?;
+            client.send(sql).await?;
+        }
+
+        let result = client.receive().await;
+        if !matches!(
+            &result,
+            Err(Error::ConnectionBroken(_) | Error::ConnectionClosed | Error::Protocol(_))
+        ) {
+            *slot = Some(client);
+        }
+        result
+    }
+
+    /// Executes a single SQL command, discarding its output (e.g., `INSERT`). Reconnects as
+    /// `query` does.
+    ///
+    /// Returns:
+    ///   * Ok: command succeeded.
+    ///   * Error: any error from `query`.
+    pub async fn execute(&self, sql: &str) -> Result<()> {
+        self.query(sql).await.map(|_| ())
+    }
+
+    /// Checks that connection is alive with `PING` command.
+    ///
+    /// Returns:
+    ///   * Ok: server acknowledged `PING`.
+    ///   * Error: any error from `query`.
+    pub async fn ping(&self) -> Result<()> {
+        self.execute("PING").await
+    }
+
+    /// Executes `SELECT` in batches of at most `batch_rows` rows, so a large result is never
+    /// held in memory at once. Every batch runs the query again as a subquery with `LIMIT` and
+    /// `OFFSET`, so the query needs `ORDER BY`, which orders all rows (e.g., by the primary
+    /// key), and rows inserted meanwhile may shift between batches.
+    ///
+    /// Returns: stream of batches. The first batch is returned also when there are no rows,
+    /// with the columns of the result. Stream ends after the first error of `query`.
+    pub fn query_stream<'a>(
+        &'a self,
+        sql: &'a str,
+        batch_rows: usize,
+    ) -> impl Stream<Item = Result<OutputTable>> + 'a {
+        let batch_rows = batch_rows.max(1);
+        futures::stream::try_unfold(Some(0), move |offset| async move {
+            let Some(offset) = offset else {
+                return Ok(None);
+            };
+            let batch = self
+                .query(&format!(
+                    "SELECT * FROM ({sql}) LIMIT {batch_rows} OFFSET {offset}"
+                ))
+                .await?;
+            if offset > 0 && batch.row_count() == 0 {
+                return Ok(None);
+            }
+            let next = (batch.row_count() == batch_rows).then_some(offset + batch_rows);
+            Ok(Some((batch, next)))
+        })
+    }
+
+    /// Whether connection can no longer be used, see `Client::is_stale`. Connection, which
+    /// runs a command, is not stale.
+    pub(crate) fn is_stale(&self) -> bool {
+        self.client
+            .try_lock()
+            .is_ok_and(|client| client.as_ref().is_some_and(Client::is_stale))
+    }
+}
@@ -0,0 +1,39 @@
+use derive_more::Display;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Client error.
+#[derive(Debug, Display)]
+pub enum Error {
+    #[display("Could not connect to server: {_0}")]
+    Connect(std::io::Error),
+    #[display("Connection is broken: {_0}")]
+    ConnectionBroken(std::io::Error),
+    #[display("Connection closed by server.")]
+    ConnectionClosed,
+    /// Error of `Pool`, other than failure to connect.
+    #[display("Connection pool failed: {_0}")]
+    Pool(String),
+    #[display("Invalid response: {_0}")]
+    Protocol(String),
+    /// Error, returned by server for the query.
+    #[display("Server error: {_0}")]
+    Server(String),
+    #[display("Column not found: {_0}")]
+    ColumnNotFound(String),
+    #[display("Column ({column}) value of type {found} cannot be converted to {expected}")]
+    TypeMismatch {
+        column: String,
+        expected: &'static str,
+        found: &'static str,
+    },
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Connect(error) | Self::ConnectionBroken(error) => Some(error),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,32 @@
+//! Async client for TouchHouse database.
+//!
+//! Implements TouchHouse TCP protocol:
+//! - Request: 8-byte little-endian u64 body size, then UTF-8 encoded SQL command.
+//! - Response: 8-byte little-endian u64 body size, then `MessagePack` encoded `Result<OutputTable, String>`.
+//!
+//! ```no_run
+//! # async fn example() -> db_rs_client::Result<()> {
+//! let mut client = db_rs_client::Client::connect("127.0.0.1:7070").await?;
+//! let table = client.query("SELECT id, name FROM db.users").await?;
+//! for row in table.rows() {
+//!     let id: u64 = row.get("id")?;
+//!     let name: Option<String> = row.get("name")?;
+//! }
+//! client.close().await
+//! # }
+//! ```
+
+mod client;
+mod connection;
+mod error;
+mod pool;
+mod types;
+
+pub use client::Client;
+pub use connection::Connection;
+pub use error::{Error, Result};
+pub use pool::{Manager, Pool, PoolConfig, PooledConnection};
+pub use types::{
+    Column, ColumnDef, CompressionType, Constraints, DefaultExpr, FromValue, OutputTable, Row,
+    Value, ValueType,
+};
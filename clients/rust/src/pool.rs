@@ -0,0 +1,130 @@
+use crate::connection::Connection;
+use crate::error::{Error, Result};
+
+use deadpool::managed::{self, Metrics, Object, PoolError, RecycleError, RecycleResult};
+
+/// Pool of at most `max_size` connections, built on `deadpool`. Connections are reused.
+#[derive(Debug, Clone)]
+pub struct Pool {
+    inner: managed::Pool<Manager>,
+}
+
+/// Connection, borrowed from `Pool`, which returns it to the pool on drop. Dereferences to
+/// `Connection`.
+pub type PooledConnection = Object<Manager>;
+
+/// Configuration of `Pool`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolConfig {
+    /// Maximum number of connections, borrowed at the same time.
+    pub max_size: usize,
+    /// Number of connections, opened by `Pool::connect` in advance.
+    pub min_idle: usize,
+    /// Whether idle connection is checked with `PING` before it is handed out. Costs a round
+    /// trip, but detects connections, which broke silently.
+    pub ping_on_checkout: bool,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_size: 10,
+            min_idle: 0,
+            ping_on_checkout: false,
+        }
+    }
+}
+
+/// Opens connections of `Pool` and checks idle ones before they are handed out again.
+#[derive(Debug)]
+pub struct Manager {
+    addr: String,
+    ping_on_checkout: bool,
+}
+
+impl managed::Manager for Manager {
+    type Type = Connection;
+    type Error = Error;
+
+    async fn create(&self) -> Result<Connection> {
+        Connection::connect(self.addr.as_str()).await
+    }
+
+    /// Rejects idle connection, which was closed by server (e.g., after idle timeout) or fails
+    /// `PING` (when `ping_on_checkout` is set), so the pool replaces it.
+    async fn recycle(&self, connection: &mut Connection, _: &Metrics) -> RecycleResult<Error> {
+        if connection.is_stale() {
+            return Err(RecycleError::message("Connection is closed by server"));
+        }
+        if self.ping_on_checkout {
+            connection.ping().await.map_err(RecycleError::Backend)?;
+        }
+        Ok(())
+    }
+}
+
+impl Pool {
+    /// Creates a pool of at most `size` connections. No connections are opened until `get` is
+    /// called.
+    pub fn new(addr: impl Into<String>, size: usize) -> Self {
+        Self::with_config(
+            addr,
+            PoolConfig {
+                max_size: size,
+                ..PoolConfig::default()
+            },
+        )
+    }
+
+    /// Creates a pool. No connections are opened until `get` is called.
+    pub fn with_config(addr: impl Into<String>, config: PoolConfig) -> Self {
+        let manager = Manager {
+            addr: addr.into(),
+            ping_on_checkout: config.ping_on_checkout,
+        };
+        Self {
+            inner: managed::Pool::builder(manager)
+                .max_size(config.max_size)
+                .build()
+                .expect("Pool without timeouts needs no runtime"),
+        }
+    }
+
+    /// Creates a pool and opens `min_idle` connections (at most `max_size`).
+    ///
+    /// Returns:
+    ///   * Ok: `Pool` with idle connections.
+    ///   * Error: `Connect` when connection could not be established.
+    pub async fn connect(addr: impl Into<String>, config: PoolConfig) -> Result<Self> {
+        let count = config.min_idle.min(config.max_size);
+        let pool = Self::with_config(addr, config);
+
+        // connections are held together, so each of them is a new one
+        let mut connections = Vec::with_capacity(count);
+        for _ in 0..count {
+            connections.push(pool.get().await?);
+        }
+        drop(connections);
+
+        Ok(pool)
+    }
+
+    /// Returns number of idle connections.
+    pub fn idle_count(&self) -> usize {
+        self.inner.status().available
+    }
+
+    /// Waits for a free connection slot and hands out an idle connection or opens a new one.
+    /// Idle connections closed by server or failing `PING` (when `ping_on_checkout` is set) are
+    /// replaced.
+    ///
+    /// Returns:
+    ///   * Ok: `PooledConnection`, which returns connection to the pool on drop.
+    ///   * Error: `Connect` when new connection could not be established.
+    pub async fn get(&self) -> Result<PooledConnection> {
+        self.inner.get().await.map_err(|error| match error {
+            PoolError::Backend(error) => error,
+            error => Error::Pool(error.to_string()),
+        })
+    }
+}
@@ -0,0 +1,413 @@
+//! Mirrors of server response types. Must be kept in sync with the server serialization format.
+
+use crate::error::{Error, Result};
+
+use serde::Deserialize;
+use std::ops::Deref;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Single cell value.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+pub enum Value {
+    #[default]
+    Null,
+    String(String),
+    Uuid(Uuid),
+    Bool(bool),
+
+    Int8(i8),
+    Int16(i16),
+    Int32(i32),
+    Int64(i64),
+
+    UInt8(u8),
+    UInt16(u16),
+    UInt32(u32),
+    UInt64(u64),
+
+    Tuple(Vec<Value>),
+    Array(Vec<Value>),
+    /// Sorted by key, without duplicate keys.
+    Map(Vec<(Value, Value)>),
+
+    /// Address as a number. Selected addresses are received as `Value::String`, e.g.,
+    /// `10.0.0.1`, these appear only in column defaults.
+    IPv4(u32),
+    /// Address bytes in network order.
+    IPv6([u8; 16]),
+
+    /// Days since `1970-01-01`. Selected dates are received as `Value::String`, e.g.,
+    /// `2024-01-31`, these appear only in column defaults.
+    Date(u16),
+    /// Seconds since `1970-01-01 00:00:00` UTC.
+    DateTime(u32),
+}
+
+impl Value {
+    /// Returns name of the variant, e.g., `UInt64`.
+    pub const fn type_name(&self) -> &'static str {
+        match self {
+            Self::Null => "Null",
+            Self::String(_) => "String",
+            Self::Uuid(_) => "Uuid",
+            Self::Bool(_) => "Bool",
+            Self::Int8(_) => "Int8",
+            Self::Int16(_) => "Int16",
+            Self::Int32(_) => "Int32",
+            Self::Int64(_) => "Int64",
+            Self::UInt8(_) => "UInt8",
+            Self::UInt16(_) => "UInt16",
+            Self::UInt32(_) => "UInt32",
+            Self::UInt64(_) => "UInt64",
+            Self::Tuple(_) => "Tuple",
+            Self::Array(_) => "Array",
+            Self::Map(_) => "Map",
+            Self::IPv4(_) => "IPv4",
+            Self::IPv6(_) => "IPv6",
+            Self::Date(_) => "Date",
+            Self::DateTime(_) => "DateTime",
+        }
+    }
+}
+
+/// Column type.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+pub enum ValueType {
+    Null,
+    String,
+    Uuid,
+    Bool,
+
+    Int8,
+    Int16,
+    Int32,
+    Int64,
+
+    UInt8,
+    UInt16,
+    UInt32,
+    UInt64,
+
+    Tuple(Vec<ValueType>),
+    Array(Box<ValueType>),
+    Map(Box<ValueType>, Box<ValueType>),
+
+    /// Labels with their codes. Values are received as `Value::String` labels.
+    Enum8(Vec<(String, i8)>),
+    Enum16(Vec<(String, i16)>),
+
+    /// Values are received as `Value::String` in canonical form, e.g., `2001:db8::1`.
+    IPv4,
+    IPv6,
+
+    /// Values are received as `Value::String` in UTC, e.g., `2024-01-31 23:59:59`.
+    Date,
+    DateTime,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub enum CompressionType {
+    None,
+    LZ4(u8),
+    Dictionary(u8),
+}
+
+/// Default value of the column.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub enum DefaultExpr {
+    Literal(Value),
+    /// SQL expression, evaluated for each inserted row, e.g., `now()`.
+    Function(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Constraints {
+    pub nullable: bool,
+    pub default: Option<DefaultExpr>,
+    pub compression_type: CompressionType,
+    #[serde(default)]
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ColumnDef {
+    pub name: String,
+    pub field_type: ValueType,
+    pub constraints: Constraints,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Column {
+    pub column_def: ColumnDef,
+    pub data: Vec<Value>,
+}
+
+/// Result of a query. Commands without output (e.g., `INSERT`) return single `OK` column.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct OutputTable {
+    pub columns: Vec<Column>,
+    #[serde(default)]
+    pub execution_time: Option<Duration>,
+    /// Time, which command waited on the server before execution.
+    #[serde(default)]
+    pub queue_time: Option<Duration>,
+    /// Id of the query, which is also a field of its log lines on the server.
+    #[serde(default)]
+    pub query_id: Option<Uuid>,
+    /// Number of parts, which server skipped, because they could not be read
+    /// (`SETTINGS skip_unreadable_parts = 1`), so some rows may be missing.
+    #[serde(default)]
+    pub skipped_parts: Option<u64>,
+}
+
+impl OutputTable {
+    /// Returns number of rows.
+    pub fn row_count(&self) -> usize {
+        self.columns.first().map_or(0, |col| col.data.len())
+    }
+
+    /// Returns column by name.
+    pub fn column(&self, name: &str) -> Option<&Column> {
+        self.columns.iter().find(|col| col.column_def.name == name)
+    }
+
+    /// Iterates over rows. Each row holds values in the order of `columns`.
+    pub fn rows(&self) -> impl Iterator<Item = Row<'_>> {
+        (0..self.row_count()).map(|row_idx| Row {
+            columns: &self.columns,
+            values: self
+                .columns
+                .iter()
+                .map(|col| col.data.get(row_idx).cloned().unwrap_or_default())
+                .collect(),
+        })
+    }
+}
+
+/// Single row of `OutputTable`. Dereferences to values in the order of columns.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row<'a> {
+    columns: &'a [Column],
+    values: Vec<Value>,
+}
+
+impl Row<'_> {
+    /// Returns value of the column, converted to `T`.
+    ///
+    /// Returns:
+    ///   * Ok: converted value.
+    ///   * Error when:
+    ///     1. There is no column with such name: `ColumnNotFound`.
+    ///     2. Value cannot be converted to `T` without loss: `TypeMismatch`.
+    pub fn get<T: FromValue>(&self, column: &str) -> Result<T> {
+        let Some(idx) = self
+            .columns
+            .iter()
+            .position(|col| col.column_def.name == column)
+        else {
+            return Err(Error::ColumnNotFound(column.to_string()));
+        };
+
+        let value = &self.values[idx];
+        T::from_value(value).ok_or_else(|| Error::TypeMismatch {
+            column: column.to_string(),
+            expected: T::TYPE_NAME,
+            found: value.type_name(),
+        })
+    }
+
+    /// Returns owned values in the order of columns.
+    pub fn into_values(self) -> Vec<Value> {
+        self.values
+    }
+}
+
+impl Deref for Row<'_> {
+    type Target = [Value];
+
+    fn deref(&self) -> &Self::Target {
+        &self.values
+    }
+}
+
+/// Conversion from `Value` into Rust type, used by `Row::get`.
+pub trait FromValue: Sized {
+    /// Name of the expected type in `TypeMismatch` errors.
+    const TYPE_NAME: &'static str;
+
+    /// Returns `None`, when value has another type or does not fit into `Self`.
+    fn from_value(value: &Value) -> Option<Self>;
+}
+
+macro_rules! impl_from_value_for_int {
+    ($($int:ty),*) => {$(
+        impl FromValue for $int {
+            const TYPE_NAME: &'static str = stringify!($int);
+
+            fn from_value(value: &Value) -> Option<Self> {
+                match *value {
+                    Value::Int8(value) => Self::try_from(value).ok(),
+                    Value::Int16(value) => Self::try_from(value).ok(),
+                    Value::Int32(value) => Self::try_from(value).ok(),
+                    Value::Int64(value) => Self::try_from(value).ok(),
+                    Value::UInt8(value) => Self::try_from(value).ok(),
+                    Value::UInt16(value) => Self::try_from(value).ok(),
+                    Value::UInt32(value) => Self::try_from(value).ok(),
+                    Value::UInt64(value) => Self::try_from(value).ok(),
+                    _ => None,
+                }
+            }
+        }
+    )*};
+}
+
+impl_from_value_for_int!(i8, i16, i32, i64, u8, u16, u32, u64);
+
+impl FromValue for String {
+    const TYPE_NAME: &'static str = "String";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::String(value) => Some(value.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for bool {
+    const TYPE_NAME: &'static str = "bool";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for Uuid {
+    const TYPE_NAME: &'static str = "Uuid";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Uuid(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for Value {
+    const TYPE_NAME: &'static str = "Value";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        Some(value.clone())
+    }
+}
+
+/// `NULL` is converted to `None`.
+impl<T: FromValue> FromValue for Option<T> {
+    const TYPE_NAME: &'static str = T::TYPE_NAME;
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Null => Some(None),
+            value => T::from_value(value).map(Some),
+        }
+    }
+}
+
+/// Converts `Array` values.
+impl<T: FromValue> FromValue for Vec<T> {
+    const TYPE_NAME: &'static str = "Vec";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Array(values) => values.iter().map(T::from_value).collect(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> OutputTable {
+        let column = |name: &str, field_type, data| Column {
+            column_def: ColumnDef {
+                name: name.to_string(),
+                field_type,
+                constraints: Constraints {
+                    nullable: true,
+                    default: None,
+                    compression_type: CompressionType::LZ4(3),
+                    comment: None,
+                },
+            },
+            data,
+        };
+
+        OutputTable {
+            columns: vec![
+                column(
+                    "id",
+                    ValueType::UInt64,
+                    vec![Value::UInt64(1), Value::UInt64(u64::MAX)],
+                ),
+                column(
+                    "name",
+                    ValueType::String,
+                    vec![Value::String("a".to_string()), Value::Null],
+                ),
+            ],
+            execution_time: None,
+            queue_time: None,
+            query_id: None,
+            skipped_parts: None,
+        }
+    }
+
+    #[test]
+    fn test_rows() {
+        let table = table();
+        let rows: Vec<_> = table.rows().map(Row::into_values).collect();
+
+        assert_eq!(table.row_count(), 2);
+        assert_eq!(
+            rows,
+            vec![
+                vec![Value::UInt64(1), Value::String("a".to_string())],
+                vec![Value::UInt64(u64::MAX), Value::Null]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_row_get() {
+        let table = table();
+        let rows: Vec<_> = table.rows().collect();
+
+        assert_eq!(rows[0].get::<i64>("id").unwrap(), 1);
+        assert_eq!(rows[0].get::<String>("name").unwrap(), "a");
+        assert_eq!(rows[1].get::<Option<String>>("name").unwrap(), None);
+        assert_eq!(rows[1].get::<u64>("id").unwrap(), u64::MAX);
+
+        assert!(matches!(
+            rows[1].get::<i64>("id"),
+            Err(Error::TypeMismatch {
+                expected: "i64",
+                found: "UInt64",
+                ..
+            })
+        ));
+        assert!(matches!(
+            rows[1].get::<String>("name"),
+            Err(Error::TypeMismatch { found: "Null", .. })
+        ));
+        assert!(matches!(
+            rows[0].get::<bool>("missing"),
+            Err(Error::ColumnNotFound(_))
+        ));
+    }
+}
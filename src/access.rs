@@ -0,0 +1,202 @@
+use derive_more::Display;
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// Commands, which client is allowed to execute. Levels are ordered: each one allows everything
+/// the previous one does.
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessLevel {
+    /// No commands.
+    #[display("deny")]
+    Deny,
+    /// Commands, which only read data, e.g., `SELECT` and `SHOW`.
+    #[display("readonly")]
+    ReadOnly,
+    /// All commands, including DDL and `INSERT`.
+    #[display("readwrite")]
+    ReadWrite,
+}
+
+/// Address based access control (`[access]` section of the config).
+#[derive(Debug, Deserialize)]
+pub struct AccessConfig {
+    /// Addresses with `readwrite` access.
+    #[serde(default)]
+    admin_addrs: Vec<Cidr>,
+    /// Addresses with `readonly` access.
+    #[serde(default)]
+    readonly_addrs: Vec<Cidr>,
+    /// Access of addresses, which are not listed.
+    #[serde(default = "default_access")]
+    default_access: AccessLevel,
+}
+
+const fn default_access() -> AccessLevel {
+    AccessLevel::ReadWrite
+}
+
+impl Default for AccessConfig {
+    fn default() -> Self {
+        Self {
+            admin_addrs: Vec::new(),
+            readonly_addrs: Vec::new(),
+            default_access: default_access(),
+        }
+    }
+}
+
+impl AccessConfig {
+    /// Returns access level of client at `addr`. `admin_addrs` take precedence over
+    /// `readonly_addrs`, unlisted addresses get `default_access`.
+    pub fn access_level(&self, addr: IpAddr) -> AccessLevel {
+        if self.admin_addrs.iter().any(|cidr| cidr.contains(addr)) {
+            AccessLevel::ReadWrite
+        } else if self.readonly_addrs.iter().any(|cidr| cidr.contains(addr)) {
+            AccessLevel::ReadOnly
+        } else {
+            self.default_access
+        }
+    }
+}
+
+/// Network in CIDR notation, e.g., `10.0.0.0/8` or `::1/128`. Address without prefix length is
+/// a single host network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Cidr {
+    network: IpAddr,
+    prefix_len: u32,
+}
+
+impl Cidr {
+    /// Whether `addr` belongs to the network. IPv4-mapped IPv6 address (`::ffff:a.b.c.d`) is
+    /// matched as IPv4. Addresses of other family never match.
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        let (network, addr, width) = match (self.network, addr.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(addr)) => (
+                u128::from(network.to_bits()),
+                u128::from(addr.to_bits()),
+                32,
+            ),
+            (IpAddr::V6(network), IpAddr::V6(addr)) => (network.to_bits(), addr.to_bits(), 128),
+            _ => return false,
+        };
+        // host bits of `network` are ignored
+        self.prefix_len == 0 || (network ^ addr) >> (width - self.prefix_len) == 0
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (network, prefix_len) = match value.split_once('/') {
+            Some((network, prefix_len)) => (network, Some(prefix_len)),
+            None => (value, None),
+        };
+        let network = IpAddr::from_str(network.trim())
+            .map_err(|error| format!("Invalid address in CIDR ({value}): {error}"))?;
+        let max_prefix_len = if network.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len
+                .trim()
+                .parse()
+                .ok()
+                .filter(|prefix_len| *prefix_len <= max_prefix_len)
+                .ok_or_else(|| {
+                    format!(
+                        "Invalid prefix length in CIDR ({value}), expected 0..={max_prefix_len}"
+                    )
+                })?,
+            None => max_prefix_len,
+        };
+        Ok(Self {
+            network,
+            prefix_len,
+        })
+    }
+}
+
+impl TryFrom<String> for Cidr {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contains(cidr: &str, addr: &str) -> bool {
+        Cidr::from_str(cidr)
+            .unwrap()
+            .contains(IpAddr::from_str(addr).unwrap())
+    }
+
+    #[test]
+    fn test_cidr_contains() {
+        assert!(contains("10.1.2.3/32", "10.1.2.3"));
+        assert!(!contains("10.1.2.3/32", "10.1.2.4"));
+        assert!(contains("10.1.2.3", "10.1.2.3"));
+        assert!(contains("10.0.0.0/8", "10.255.255.255"));
+        assert!(!contains("10.0.0.0/8", "11.0.0.0"));
+        assert!(contains("10.1.2.3/8", "10.200.0.1"));
+        assert!(contains("0.0.0.0/0", "203.0.113.7"));
+        assert!(contains("192.168.1.128/25", "192.168.1.200"));
+        assert!(!contains("192.168.1.128/25", "192.168.1.127"));
+
+        assert!(contains("::1/128", "::1"));
+        assert!(!contains("::1/128", "::2"));
+        assert!(contains("::/0", "2001:db8::1"));
+        assert!(contains("2001:db8::/32", "2001:db8:ffff::1"));
+        assert!(!contains("2001:db8::/32", "2001:db9::1"));
+
+        // mismatched families
+        assert!(!contains("0.0.0.0/0", "::1"));
+        assert!(!contains("::/0", "127.0.0.1"));
+        assert!(contains("127.0.0.0/8", "::ffff:127.0.0.1"));
+    }
+
+    #[test]
+    fn test_parse_invalid_cidr() {
+        for cidr in [
+            "10.0.0.0/33",
+            "::/129",
+            "10.0.0.0/",
+            "10.0.0/8",
+            "host/8",
+            "::1/-1",
+        ] {
+            assert!(Cidr::from_str(cidr).is_err(), "{cidr}");
+        }
+    }
+
+    #[test]
+    fn test_access_level() {
+        let config: AccessConfig = toml::from_str(
+            r#"
+            admin_addrs = ["127.0.0.1", "10.0.0.0/24"]
+            readonly_addrs = ["10.0.0.0/8", "::/0"]
+            default_access = "deny"
+            "#,
+        )
+        .unwrap();
+        let access_level = |addr| config.access_level(IpAddr::from_str(addr).unwrap());
+
+        assert_eq!(access_level("127.0.0.1"), AccessLevel::ReadWrite);
+        assert_eq!(access_level("10.0.0.9"), AccessLevel::ReadWrite);
+        assert_eq!(access_level("10.0.1.9"), AccessLevel::ReadOnly);
+        assert_eq!(access_level("::1"), AccessLevel::ReadOnly);
+        assert_eq!(access_level("192.168.0.1"), AccessLevel::Deny);
+
+        let config = AccessConfig::default();
+        assert_eq!(
+            config.access_level(IpAddr::from_str("192.168.0.1").unwrap()),
+            AccessLevel::ReadWrite
+        );
+    }
+}
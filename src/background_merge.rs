@@ -1,308 +1,1845 @@
+use crate::engines::{EngineName, compare_key_values};
 use crate::error::{Error, Result};
-use crate::runtime_config::{DATABASE_LOAD, TABLE_DATA};
-use crate::storage::{Column, TableDef, TablePart, TablePartInfo, Value};
+use crate::runtime_config::{DATABASE_LOAD, Instance, TABLE_DATA, TableConfig};
+use crate::sql::eval_default;
+use crate::storage::table_metadata::flags;
+use crate::storage::{
+    Column, ColumnData, ColumnDef, CompressionType, DefaultExpr, MarkInfo, PartWriter, TableDef,
+    TablePart, TablePartInfo, Value, WriteOrigin, ensure_free_space, parts_bytes,
+    stop_throttling_on,
+};
 
 use crate::config::CONFIG;
+use derive_more::Display;
 use log::{error, info, warn};
-use uuid::Uuid;
+use memmap2::Mmap;
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::cmp;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex, PoisonError, mpsc};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::{oneshot, watch};
 
 /// Background merge service that combines table parts to optimize storage and queries.
 pub struct BackgroundMerge;
 
+/// How often idle workers check for shutdown.
+const STOP_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+/// How often `BackgroundMerge::exclude_tables` checks, whether running merges finished.
+const EXCLUSION_CHECK_INTERVAL: Duration = Duration::from_millis(10);
+/// Pause of merges, after disk was found full. Doubled after every next check, which finds it
+/// full, until `MAX_DISK_FULL_PAUSE`.
+const MIN_DISK_FULL_PAUSE: Duration = Duration::from_secs(1);
+const MAX_DISK_FULL_PAUSE: Duration = Duration::from_secs(64);
+/// Pause of merges, after a merge failed. Doubled after every next failed merge, until
+/// `MAX_FAILURE_PAUSE`.
+const MIN_FAILURE_PAUSE: Duration = Duration::from_secs(1);
+const MAX_FAILURE_PAUSE: Duration = Duration::from_secs(64);
+
 impl BackgroundMerge {
-    /// Starts the background merge loop.
+    /// Starts `max_background_merges` merge workers, each on a dedicated thread. Merge work runs
+    /// on a thread pool of `background_merge_threads`, separate from the global pool used by queries.
     ///
-    /// Continuously monitors tables for parts that can be merged. When database load
-    /// is below threshold and two parts exist, merges them into a single part.
-    /// Runs indefinitely until the process is terminated.
-    pub fn start() {
-        info!("Background merges started");
+    /// Workers run until `true` is sent to `shutdown`. Shutdown is checked between merges, so
+    /// a running merge finishes moving its part into place before the worker stops, without
+    /// waiting for `max_merge_bytes_per_sec`. Read-only server does not merge.
+    ///
+    /// Returns:
+    ///   * Ok: receivers, each completed by a worker once it stopped. Empty for read-only server.
+    ///   * Error: thread pool or worker thread could not be created.
+    pub fn start(shutdown: &watch::Sender<bool>) -> std::io::Result<Vec<oneshot::Receiver<()>>> {
+        if CONFIG.is_read_only() {
+            info!("Background merges are disabled in read-only mode");
+            return Ok(Vec::new());
+        }
+
+        let pool = Arc::new(
+            ThreadPoolBuilder::new()
+                .num_threads(CONFIG.get_background_merge_threads())
+                .thread_name(|idx| format!("merge-pool-{idx}"))
+                .build()
+                .map_err(std::io::Error::other)?,
+        );
+        stop_throttling_on(shutdown.subscribe());
+
+        (0..CONFIG.get_max_background_merges())
+            .map(|idx| {
+                let pool = Arc::clone(&pool);
+                let shutdown = shutdown.subscribe();
+                let (stopped_tx, stopped_rx) = oneshot::channel();
+                std::thread::Builder::new()
+                    .name(format!("merge-worker-{idx}"))
+                    .spawn(move || {
+                        Self::run_worker(&pool, &shutdown);
+                        let _ = stopped_tx.send(());
+                    })?;
+                Ok(stopped_rx)
+            })
+            .collect()
+    }
+
+    /// Stops new merges of `tables` until the returned guard is dropped, and waits for running
+    /// merges of these tables to finish.
+    pub fn exclude_tables(tables: &[TableDef]) -> MergeExclusion {
+        {
+            let mut excluded = EXCLUDED_TABLES
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            for table_def in tables {
+                *excluded.entry(table_def.clone()).or_default() += 1;
+            }
+        }
+
+        // parts are claimed after checking exclusions under the same lock,
+        // so no merge of `tables` starts once their claims are released
         loop {
-            if DATABASE_LOAD.load(std::sync::atomic::Ordering::Relaxed)
+            let running = {
+                let claimed = CLAIMED_PARTS.lock().unwrap_or_else(PoisonError::into_inner);
+                tables.iter().any(|table_def| {
+                    claimed
+                        .get(table_def)
+                        .is_some_and(|names| !names.is_empty())
+                })
+            };
+            if !running {
+                break;
+            }
+            std::thread::sleep(EXCLUSION_CHECK_INTERVAL);
+        }
+
+        MergeExclusion {
+            tables: tables.to_vec(),
+        }
+    }
+
+    /// Requests merge of all parts of each partition of the table into a single part, see
+    /// `OPTIMIZE TABLE`. Requested merges run before automatically selected ones, also when the
+    /// server is busy. Table is one of the current instance (see `Instance::enter`), which the
+    /// merge runs against.
+    ///
+    /// Returns: receiver of the result, sent once parts, which the table has when a worker takes
+    /// the request, are merged. `TableNotFound`, when table is dropped before, or
+    /// `MergesStopped`, when `no_merges` flag of the table is set before.
+    pub fn request_merge(table_def: TableDef) -> mpsc::Receiver<Result<()>> {
+        let (done, result) = mpsc::channel();
+        info!(table:% = table_def; "Merge of all parts of table {table_def} is requested");
+        MERGE_REQUESTS
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push_back(MergeRequest {
+                instance: Instance::current(),
+                table_def,
+                done,
+            });
+        MERGE_REQUESTS_ADDED.fetch_add(1, Ordering::Relaxed);
+        result
+    }
+
+    /// Returns merges, which are running, in order of their start.
+    pub fn running_merges() -> Vec<MergeProgress> {
+        let mut merges: Vec<_> = RUNNING_MERGES
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .values()
+            .cloned()
+            .collect();
+        merges.sort_by_key(|merge| merge.started_at);
+        merges
+    }
+
+    /// Merge loop of a single worker.
+    ///
+    /// Takes requested merges first (see `request_merge`). Otherwise monitors tables for parts
+    /// that can be merged: when database load is below threshold and two unclaimed parts exist,
+    /// merges them into a single part.
+    /// Merge, which would leave less than `min_free_bytes` of disk space, is not started, and
+    /// merges are paused for `MIN_DISK_FULL_PAUSE`, doubled while disk stays full. Failed merge
+    /// pauses merges for `MIN_FAILURE_PAUSE`, doubled while merges keep failing.
+    fn run_worker(pool: &ThreadPool, shutdown: &watch::Receiver<bool>) {
+        info!("Background merges started");
+        let mut disk_full_pause = MIN_DISK_FULL_PAUSE;
+        let mut failure_pause = MIN_FAILURE_PAUSE;
+        while !*shutdown.borrow() {
+            if let Some(request) = claim_requested_merge() {
+                let result = Self::run_requested_merge(pool, request.instance, request.merges);
+                // requester may have stopped waiting
+                let _ = request.done.send(result);
+                continue;
+            }
+
+            if DATABASE_LOAD.load(Ordering::Relaxed)
                 >= CONFIG.get_background_merge_available_under()
             {
                 // too busy to allocate resources for background merges
-                std::thread::sleep(std::time::Duration::from_secs(5));
+                Self::pause(Duration::from_secs(5), shutdown);
                 continue;
             }
 
             let Some(merge_data) = find_two_parts() else {
-                std::thread::sleep(std::time::Duration::from_secs(1));
+                Self::pause(Duration::from_secs(1), shutdown);
                 continue;
             };
 
-            let Some((part_0_cols, part_1_cols)) = Self::load_both_parts(&merge_data) else {
-                std::thread::sleep(std::time::Duration::from_secs(1));
-                continue;
-            };
-
-            let merged = Self::merge_parts(part_0_cols, part_1_cols);
-
-            let mut new_part = match TablePart::try_new(
-                &merge_data.table_def,
-                merged,
-                Some(merge_data.part_1.name.clone()), // use latest name of two for proper future merging
-            ) {
-                Ok(new_part) => new_part,
-                Err(error) => {
-                    error!("Failed to create new TablePart during merge: {error}");
-                    continue;
-                }
-            };
-
-            if let Err(error) = new_part.save_raw(&merge_data.table_def) {
-                error!("Failed to save merged TablePart: {error}");
+            // merged part is about as large as both parts together
+            let merge_bytes = parts_bytes(&merge_data.table_def, &merge_data.parts);
+            if let Err(error) = ensure_free_space(merge_bytes) {
+                warn!(
+                    table:% = merge_data.table_def;
+                    "Background merges are paused for {} seconds: {error}",
+                    disk_full_pause.as_secs()
+                );
+                drop(merge_data);
+                Self::pause(disk_full_pause, shutdown);
+                disk_full_pause = (disk_full_pause * 2).min(MAX_DISK_FULL_PAUSE);
                 continue;
             }
+            disk_full_pause = MIN_DISK_FULL_PAUSE;
 
-            if !Self::atomic_part_move(merge_data, new_part) {
-                error!("Failed to move merged TablePart");
-                std::thread::sleep(std::time::Duration::from_secs(1));
+            if pool.install(|| Self::merge(merge_data)).is_err() {
+                Self::pause(failure_pause, shutdown);
+                failure_pause = (failure_pause * 2).min(MAX_FAILURE_PAUSE);
+            } else {
+                failure_pause = MIN_FAILURE_PAUSE;
             }
         }
+        info!("Background merges stopped");
     }
 
-    /// Loads all columns from a table part into memory.
+    /// Runs merges of a requested merge one by one against `instance` of the request, each of
+    /// them merges all parts of a partition.
     ///
-    /// Returns:
-    ///   * Ok: `Vec<Column>` with all part data.
-    ///   * Error: `CouldNotReadData` on I/O or deserialization failure.
-    fn load_part(table_def: &TableDef, part: &TablePartInfo) -> Result<Vec<Column>> {
-        let mut columns = Vec::new();
-
-        // column-stored version
-        let mut marks = vec![Vec::new(); part.column_defs.len()];
-        for mark in &part.marks {
-            for (mark_idx, mark_info) in mark.info.iter().enumerate() {
-                marks[mark_idx].push(mark_info.clone());
+    /// Returns: Ok when all parts are merged, or the first error: `DiskFull` (see
+    /// `ensure_free_space`) or error of `merge`. Remaining parts are released.
+    fn run_requested_merge(
+        pool: &ThreadPool,
+        instance: &'static Instance,
+        merges: Vec<MergeData>,
+    ) -> Result<()> {
+        instance.enter(|| {
+            for merge_data in merges {
+                ensure_free_space(parts_bytes(&merge_data.table_def, &merge_data.parts))?;
+                // pool threads run against the default instance, unless they enter this one
+                pool.install(|| instance.enter(|| Self::merge(merge_data)))?;
             }
+            Ok(())
+        })
+    }
+
+    /// Sleeps for `duration`, or less on shutdown or when a merge is requested.
+    fn pause(duration: Duration, shutdown: &watch::Receiver<bool>) {
+        let requests = MERGE_REQUESTS_ADDED.load(Ordering::Relaxed);
+        let deadline = Instant::now() + duration;
+        while !*shutdown.borrow()
+            && Instant::now() < deadline
+            && MERGE_REQUESTS_ADDED.load(Ordering::Relaxed) == requests
+        {
+            std::thread::sleep(STOP_CHECK_INTERVAL.min(deadline - Instant::now()));
         }
+    }
 
-        for (col_idx, column_def) in part.column_defs.iter().enumerate() {
-            let mmap = Column::open_as_mmap(&part.get_column_path(table_def, column_def))?;
-
-            let mut data = Vec::new();
-            for mark_info in &marks[col_idx] {
-                let granule_data = TablePartInfo::get_granule_bytes_decompressed(
-                    &mmap,
-                    mark_info,
-                    &column_def.constraints.compression_type,
-                )?;
-                let granule_data = rkyv::from_bytes::<Vec<Value>, rkyv::rancor::Error>(
-                    &granule_data,
-                )
-                .map_err(|error| {
-                    Error::CouldNotReadData(format!("Could not read data while merging: {error}"))
-                })?;
-                data.extend(granule_data);
-            }
-            let column = Column {
-                column_def: column_def.clone(),
-                data,
-            };
-            columns.push(column);
+    /// Merges claimed parts and replaces them with the merged part, which takes name and
+    /// sequence of the newest one. Sorted parts are merged row by row (see `merge_sorted`),
+    /// others are loaded into memory (see `merge_in_memory`). Merge is reported by
+    /// `running_merges` until it finishes.
+    ///
+    /// Returns: Ok on success, or error of the failed step (error is logged).
+    fn merge(merge_data: MergeData) -> Result<()> {
+        let Some(newest) = merge_data.parts.last() else {
+            return Err(Error::Internal("Merge has no parts".to_string()));
+        };
+        let progress = ProgressEntry::new(&merge_data);
+
+        let merged = Self::merge_sorted(&merge_data, newest, &progress).inspect_err(|error| {
+            error!(
+                table:% = merge_data.table_def;
+                "Failed to merge parts of table ({}): {error}",
+                merge_data.table_def
+            );
+        })?;
+        let new_part = match merged {
+            Some(new_part) => new_part,
+            None => Self::merge_in_memory(&merge_data, newest, &progress)?,
+        };
+
+        progress.set_phase(MergePhase::Moving);
+        let table_def = merge_data.table_def.clone();
+        let merged_parts = merge_data
+            .parts
+            .iter()
+            .map(|part| part.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let (part, rows) = (new_part.info.name.clone(), new_part.info.row_count);
+        if !Self::atomic_part_move(merge_data, new_part) {
+            error!(
+                table:% = table_def, part = part.as_str();
+                "Failed to move merged TablePart"
+            );
+            return Err(Error::CouldNotInsertData(format!(
+                "Failed to move merged part {part} of table {table_def}"
+            )));
         }
-        Ok(columns)
+        info!(
+            table:% = table_def, part = part.as_str(), rows = rows;
+            "Merged parts {merged_parts} of table {table_def}"
+        );
+        Ok(())
     }
 
-    /// Merges two parts' columns into one.
+    /// Merges parts, which are sorted by ORDER BY and have columns of the schema, row by row.
+    /// Only the current granule of each column of every part and of the merged part (see
+    /// `PartWriter`) is kept in memory. Rows with equal ORDER BY values keep the order of parts,
+    /// and `ReplacingMergeTree` keeps the last row of each primary key, as the engine does with
+    /// parts merged in memory.
     ///
-    /// Extends `part_0` with data from `part_1`. If a column exists in `part_1` but not `part_0`,
-    /// fills missing rows with default values.
-    fn merge_parts(mut part_0: Vec<Column>, part_1: Vec<Column>) -> Vec<Column> {
-        for column_1 in part_1 {
-            if let Some(position) = part_0
-                .iter()
-                .position(|col| col.column_def == column_1.column_def)
+    /// Returns:
+    ///   * Ok: merged part saved into the raw directory, or `None`, when parts have other
+    ///     columns than the schema, or are not sorted, so they are left to `merge_in_memory`.
+    ///   * Error: `TableNotFound`, or error of reading or writing a part.
+    fn merge_sorted(
+        merge_data: &MergeData,
+        newest: &TablePartInfo,
+        progress: &ProgressEntry,
+    ) -> Result<Option<TablePart>> {
+        let columns = &merge_data.columns;
+        if !merge_data
+            .parts
+            .iter()
+            .all(|part| has_schema_columns(part, columns))
+        {
+            return Ok(None);
+        }
+        let (order_by, primary_key, replacing) = {
+            let Some(config) = TABLE_DATA.get(&merge_data.table_def) else {
+                return Err(Error::TableNotFound);
+            };
+            let positions = |column_defs: &[ColumnDef]| {
+                column_defs
+                    .iter()
+                    .map(|column_def| columns.iter().position(|col| col == column_def))
+                    .collect::<Option<Vec<_>>>()
+            };
+            let schema = &config.metadata.schema;
+            let (Some(order_by), Some(primary_key)) =
+                (positions(&schema.order_by), positions(&schema.primary_key))
+            else {
+                return Ok(None);
+            };
+            let replacing = config.metadata.settings.engine == EngineName::ReplacingMergeTree;
+            (order_by, primary_key, replacing)
+        };
+        if order_by.is_empty() {
+            return Ok(None);
+        }
+
+        progress.set_phase(MergePhase::Merging);
+        let mut cursors = merge_data
+            .parts
+            .iter()
+            .map(|part| PartCursor::open(&merge_data.table_def, part))
+            .collect::<Result<Vec<_>>>()?;
+        let mut writer = PartWriter::try_new(
+            &merge_data.table_def,
+            columns.clone(),
+            newest.name.clone(),
+            newest.sequence,
+            newest.partition.clone(),
+            WriteOrigin::Merge,
+        )?;
+
+        // row of `ReplacingMergeTree`, which is written once the next row has another primary key
+        let mut pending: Option<Vec<Value>> = None;
+        while let Some(part_idx) = next_part(&cursors, &order_by) {
+            let row = cursors[part_idx].take_row(&order_by)?;
+            if cursors[part_idx].unsorted {
+                warn!(
+                    table:% = merge_data.table_def, part = merge_data.parts[part_idx].name.as_str();
+                    "Part {} of table {} is not sorted by ORDER BY, parts are merged in memory",
+                    merge_data.parts[part_idx].name,
+                    merge_data.table_def
+                );
+                return Ok(None);
+            }
+            if !replacing {
+                writer.push_row(row)?;
+                continue;
+            }
+            if let Some(previous) =
+                pending.take_if(|previous| primary_key.iter().any(|&idx| previous[idx] != row[idx]))
             {
-                part_0[position].data.extend(column_1.data.into_iter()); // parts are guaranteed to be non-empty.
-            } else {
-                let default_value = column_1
-                    .column_def
-                    .constraints
-                    .default
-                    .clone()
-                    .unwrap_or_default();
-                let mut data = vec![default_value; part_0[0].data.len()];
-                data.extend(column_1.data.into_iter());
-                part_0.push(Column {
-                    column_def: column_1.column_def.clone(),
-                    data,
-                });
+                writer.push_row(previous)?;
             }
+            pending = Some(row);
+        }
+        if let Some(row) = pending {
+            writer.push_row(row)?;
         }
 
-        part_0
+        progress.set_phase(MergePhase::Writing);
+        writer.finish().map(Some)
     }
 
-    /// Loads both parts to be merged into memory.
+    /// Loads parts into memory, combines them following the table schema (see `merge_parts`),
+    /// and orders rows with the engine.
     ///
-    /// Returns: `Some((part_0_cols, part_1_cols))` on success, `None` on failure.
-    fn load_both_parts(merge_data: &MergeData) -> Option<(Vec<Column>, Vec<Column>)> {
-        let part_0_cols = Self::load_part(&merge_data.table_def, &merge_data.part_0)
-            .map_err(|error| {
+    /// Returns: merged part saved into the raw directory, or error of the failed step (error is
+    /// logged).
+    fn merge_in_memory(
+        merge_data: &MergeData,
+        newest: &TablePartInfo,
+        progress: &ProgressEntry,
+    ) -> Result<TablePart> {
+        progress.set_phase(MergePhase::Loading);
+        let loaded = Self::load_parts(merge_data)?;
+
+        progress.set_phase(MergePhase::Merging);
+        let mut loaded = loaded.into_iter();
+        let first = loaded.next().unwrap_or_default();
+        let merged = loaded
+            .try_fold(first, |merged, part| {
+                Self::merge_parts(merged, part, &merge_data.columns)
+            })
+            .inspect_err(|error| {
                 error!(
-                    "Error loading part ({}): {error:?}",
-                    &merge_data.part_0.name
+                    table:% = merge_data.table_def;
+                    "Failed to merge parts of table ({}): {error}",
+                    merge_data.table_def
                 );
-                error
-            })
-            .ok()?;
+            })?;
+
+        progress.set_phase(MergePhase::Writing);
+        let mut new_part = TablePart::try_new(
+            &merge_data.table_def,
+            merged,
+            Some(newest.name.clone()),
+            newest.partition.clone(),
+        )
+        .inspect_err(|error| {
+            error!(
+                table:% = merge_data.table_def;
+                "Failed to create new TablePart during merge: {error}"
+            );
+        })?;
+        // keep position of the newer part for proper future merging
+        new_part.info.sequence = newest.sequence;
 
-        let part_1_cols = Self::load_part(&merge_data.table_def, &merge_data.part_1)
-            .map_err(|error| {
+        new_part
+            .save_raw(&merge_data.table_def, WriteOrigin::Merge)
+            .inspect_err(|error| {
                 error!(
-                    "Error loading part ({}): {error:?}",
-                    &merge_data.part_1.name
+                    table:% = merge_data.table_def, part = new_part.info.name.as_str();
+                    "Failed to save merged TablePart: {error}"
                 );
-                error
+            })?;
+        Ok(new_part)
+    }
+
+    /// Loads all columns from a table part into memory. Columns are loaded in parallel.
+    ///
+    /// Returns:
+    ///   * Ok: `Vec<Column>` with all part data.
+    ///   * Error: `CouldNotReadData` on I/O or deserialization failure.
+    pub(crate) fn load_part(table_def: &TableDef, part: &TablePartInfo) -> Result<Vec<Column>> {
+        // pool threads read files of the instance of the caller
+        let instance = Instance::current();
+        part.column_defs
+            .par_iter()
+            .enumerate()
+            .map(|(file_idx, column_def)| {
+                instance.enter(|| Self::load_column(table_def, part, file_idx, column_def))
+            })
+            .collect()
+    }
+
+    /// Loads a single column of a table part, `file_idx` is its position in `column_defs`.
+    fn load_column(
+        table_def: &TableDef,
+        part: &TablePartInfo,
+        file_idx: usize,
+        column_def: &ColumnDef,
+    ) -> Result<Column> {
+        let reader = ColumnReader::open(table_def, part, file_idx, column_def)?;
+        let mut data = Vec::new();
+        for granule_idx in 0..reader.granules() {
+            data.extend(reader.read_granule(granule_idx)?);
+        }
+        Ok(Column {
+            column_def: column_def.clone(),
+            data: data.into(),
+        })
+    }
+
+    /// Merges two parts' columns into one with the columns of the current table schema.
+    ///
+    /// Both parts are projected onto `columns`: columns no longer in the schema are dropped,
+    /// missing columns are filled with the declared default (or `NULL` for nullable columns).
+    /// Then data of `part_1` is appended to `part_0`. NOT NULL columns are never padded with
+    /// `NULL`, so merged part keeps the constraints of the schema.
+    ///
+    /// Returns:
+    ///   * Ok: merged columns in the order of `columns`.
+    ///   * Error:
+    ///     1. `CouldNotInsertData` when part column has another type than the schema, or
+    ///        column is missing in a part, but is NOT NULL without default (or its default
+    ///        evaluates to `NULL`).
+    ///     2. Default expression of the missing column could not be evaluated.
+    pub fn merge_parts(
+        part_0: Vec<Column>,
+        part_1: Vec<Column>,
+        columns: &[ColumnDef],
+    ) -> Result<Vec<Column>> {
+        let part_0 = Self::project_part(part_0, columns)?;
+        let part_1 = Self::project_part(part_1, columns)?;
+
+        Ok(part_0
+            .into_iter()
+            .zip(part_1)
+            .map(|(mut column_0, column_1)| {
+                column_0.data.append(column_1.data);
+                column_0
+            })
+            .collect())
+    }
+
+    /// Projects part onto `columns`, see `merge_parts`.
+    fn project_part(mut part: Vec<Column>, columns: &[ColumnDef]) -> Result<Vec<Column>> {
+        let row_count = part.first().map_or(0, |col| col.data.len()); // parts are guaranteed to be non-empty
+
+        columns
+            .iter()
+            .map(|column_def| {
+                let data = if let Some(position) = part
+                    .iter()
+                    .position(|col| col.column_def.name == column_def.name)
+                {
+                    let column = part.swap_remove(position);
+                    if column.column_def.field_type != column_def.field_type {
+                        return Err(Error::CouldNotInsertData(format!(
+                            "Column ({}) has type {:?} in part, but {:?} in schema",
+                            column_def.name, column.column_def.field_type, column_def.field_type
+                        )));
+                    }
+                    column.data
+                } else if let Some(default) = &column_def.constraints.default {
+                    let values = eval_default(default, row_count)?;
+                    if !column_def.constraints.nullable && values.contains(&Value::Null) {
+                        return Err(Error::CouldNotInsertData(format!(
+                            "Column ({}) is missing in part and its default is NULL, but column is NOT NULL",
+                            column_def.name
+                        )));
+                    }
+                    values.into()
+                } else if column_def.constraints.nullable {
+                    ColumnData::constant(Value::Null, row_count)
+                } else {
+                    return Err(Error::CouldNotInsertData(format!(
+                        "Column ({}) is missing in part and is neither nullable nor has a default value",
+                        column_def.name
+                    )));
+                };
+
+                Ok(Column {
+                    column_def: column_def.clone(),
+                    data,
+                })
             })
-            .ok()?;
+            .collect()
+    }
 
-        Some((part_0_cols, part_1_cols))
+    /// Loads all parts to be merged into memory.
+    ///
+    /// Returns: columns of each part, or the first error (error is logged).
+    fn load_parts(merge_data: &MergeData) -> Result<Vec<Vec<Column>>> {
+        merge_data
+            .parts
+            .iter()
+            .map(|part| {
+                Self::load_part(&merge_data.table_def, part).inspect_err(|error| {
+                    error!(
+                        table:% = merge_data.table_def, part = part.name.as_str();
+                        "Error loading part ({}): {error:?}",
+                        &part.name
+                    );
+                })
+            })
+            .collect()
     }
 
-    /// Atomically replaces old parts with the merged part.
+    /// Atomically replaces old parts with the merged part, see `replace_parts`. Parts stay
+    /// claimed until they are replaced.
+    ///
+    /// Returns: `true` on success, `false` on failure (with rollback attempted).
+    fn atomic_part_move(merge_data: MergeData, new_part: TablePart) -> bool {
+        let MergeData {
+            table_def,
+            parts,
+            _claim,
+            ..
+        } = merge_data;
+        Self::replace_parts(&table_def, parts, new_part)
+    }
+
+    /// Atomically replaces `parts` of the table with `new_part`, which is saved into the raw
+    /// directory.
     ///
     /// Renames old parts to `.old` suffix, updates in-memory index, moves new part,
     /// and cleans up old directories. Rolls back on failure.
     ///
     /// Returns: `true` on success, `false` on failure (with rollback attempted).
-    fn atomic_part_move(merge_data: MergeData, new_part: TablePart) -> bool {
+    pub(crate) fn replace_parts(
+        table_def: &TableDef,
+        parts: Vec<TablePartInfo>,
+        new_part: TablePart,
+    ) -> bool {
         // prevent from new selects
-        let Some(mut config) = TABLE_DATA.get_mut(&merge_data.table_def) else {
-            warn!("could not get mutable table config");
+        let Some(mut config) = TABLE_DATA.get_mut(table_def) else {
+            warn!(table:% = table_def; "could not get mutable table config");
             return false;
         };
-        let part_0_old = merge_data
-            .table_def
-            .get_path()
-            .join(&merge_data.part_0.name);
-        let part_0_new = merge_data
-            .table_def
-            .get_path()
-            .join(format!("{}.old", &merge_data.part_0.name));
-        let part_1_old = merge_data
-            .table_def
-            .get_path()
-            .join(&merge_data.part_1.name);
-        let part_1_new = merge_data
-            .table_def
-            .get_path()
-            .join(format!("{}.old", &merge_data.part_1.name));
-
-        if std::fs::rename(&part_0_old, &part_0_new).is_err() {
-            warn!(
-                "Could not rename normal part to old: {}",
-                part_0_old.display()
-            );
-            return false;
-        }
+        let paths: Vec<(PathBuf, PathBuf)> = parts
+            .iter()
+            .map(|part| {
+                let path = part.get_path(table_def);
+                let old_path = path.with_file_name(format!("{}.old", &part.name));
+                (path, old_path)
+            })
+            .collect();
 
-        if std::fs::rename(&part_1_old, &part_1_new).is_err() {
-            if let Err(error) = std::fs::rename(&part_0_new, part_0_old) {
-                error!(
-                    "Couldn't move part ({}). Remove `.old` extension and solve the issue: {}",
-                    part_0_new.display(),
-                    error
+        for (idx, (path, old_path)) in paths.iter().enumerate() {
+            if std::fs::rename(path, old_path).is_err() {
+                warn!(
+                    table:% = table_def, part = parts[idx].name.as_str();
+                    "Could not rename normal part to old: {}",
+                    path.display()
                 );
+                for (path, old_path) in &paths[..idx] {
+                    restore_part(old_path, path);
+                }
+                return false;
             }
-            return false;
         }
         config
             .infos
-            .retain(|x| x.name != merge_data.part_0.name && x.name != merge_data.part_1.name);
+            .retain(|info| !parts.iter().any(|part| part.name == info.name));
         drop(config); // drop mut access for `move_to_normal`
 
-        if new_part.move_to_normal(&merge_data.table_def).is_err() {
-            let Some(mut config) = TABLE_DATA.get_mut(&merge_data.table_def) else {
+        if new_part.move_to_normal(table_def).is_err() {
+            let Some(mut config) = TABLE_DATA.get_mut(table_def) else {
                 return false;
             };
-            if let Err(error) = std::fs::rename(&part_0_new, &part_0_old) {
-                error!(
-                    "Couldn't move part ({}). Remove `.old` extension and solve the issue: {}",
-                    part_0_new.display(),
-                    error
-                );
-            } else {
-                config.infos.push(merge_data.part_0);
+            for ((path, old_path), part) in paths.iter().zip(parts) {
+                if restore_part(old_path, path) {
+                    config.infos.push(part);
+                }
             }
-            if let Err(error) = std::fs::rename(&part_1_new, &part_1_old) {
-                error!(
-                    "Couldn't move part ({}). Remove `.old` extension and solve the issue: {}",
-                    part_1_new.display(),
+            return false;
+        }
+
+        for (_, old_path) in &paths {
+            if let Err(error) = std::fs::remove_dir_all(old_path) {
+                warn!(
+                    "Couldn't remove ({}). Remove directory and solve the issue: {}",
+                    old_path.display(),
                     error
                 );
-            } else {
-                config.infos.push(merge_data.part_1);
             }
-            return false;
         }
+        true
+    }
+}
 
-        if let Err(error) = std::fs::remove_dir_all(&part_0_new) {
-            warn!(
-                "Couldn't remove ({}). Remove directory and solve the issue: {}",
-                part_0_new.display(),
-                error
-            );
+/// Checks that part has columns of the schema, in its order and with its types, so its rows need
+/// no projection (see `BackgroundMerge::merge_parts`).
+fn has_schema_columns(part: &TablePartInfo, columns: &[ColumnDef]) -> bool {
+    part.column_defs.len() == columns.len()
+        && part.column_defs.iter().zip(columns).all(|(part_col, col)| {
+            part_col.name == col.name && part_col.field_type == col.field_type
+        })
+}
+
+/// Checks that part can be projected onto `columns` (see `BackgroundMerge::project_part`):
+/// columns of the schema, which part has, are of the same types, and missing ones are nullable
+/// or have a default other than `NULL`.
+fn is_projectable(part: &TablePartInfo, columns: &[ColumnDef]) -> bool {
+    columns.iter().all(|col| {
+        match part
+            .column_defs
+            .iter()
+            .find(|part_col| part_col.name == col.name)
+        {
+            Some(part_col) => part_col.field_type == col.field_type,
+            None => match &col.constraints.default {
+                Some(DefaultExpr::Literal(Value::Null)) | None => col.constraints.nullable,
+                Some(_) => true,
+            },
         }
-        if let Err(error) = std::fs::remove_dir_all(&part_1_new) {
-            warn!(
-                "Couldn't remove ({}). Remove directory and solve the issue: {}",
-                part_1_new.display(),
-                error
-            );
+    })
+}
+
+/// Compares rows by values of `order_by` columns, see `compare_key_values`.
+fn compare_rows<'a, 'b>(
+    order_by: &[usize],
+    left: impl Fn(usize) -> &'a Value,
+    right: impl Fn(usize) -> &'b Value,
+) -> cmp::Ordering {
+    order_by
+        .iter()
+        .map(|&idx| compare_key_values(left(idx), right(idx)))
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(cmp::Ordering::Equal)
+}
+
+/// Returns index of the part, whose current row goes first by `order_by`. Of equal rows, row of
+/// the older part goes first.
+fn next_part(cursors: &[PartCursor], order_by: &[usize]) -> Option<usize> {
+    let mut next: Option<usize> = None;
+    for (idx, cursor) in cursors.iter().enumerate() {
+        if cursor.has_row()
+            && next.is_none_or(|next| {
+                compare_rows(
+                    order_by,
+                    |col| cursor.value(col),
+                    |col| cursors[next].value(col),
+                )
+                .is_lt()
+            })
+        {
+            next = Some(idx);
         }
-        true
     }
+    next
+}
+
+/// Column file of a part, read granule by granule.
+struct ColumnReader {
+    mmap: Mmap,
+    marks: Vec<MarkInfo>,
+    dictionary: Option<Vec<Value>>,
+    compression_type: CompressionType,
+    /// Table, part and column, which read errors are reported with.
+    context: String,
+}
+
+impl ColumnReader {
+    /// Opens column of a part, `file_idx` is its position in `column_defs`.
+    ///
+    /// Returns: Ok or `CouldNotReadData` when file, marks or dictionary could not be read.
+    fn open(
+        table_def: &TableDef,
+        part: &TablePartInfo,
+        file_idx: usize,
+        column_def: &ColumnDef,
+    ) -> Result<Self> {
+        let context = format!(
+            "table={table_def}, part={}, column={}",
+            part.name, column_def.name
+        );
+        let marks = part
+            .read_column_marks(table_def, file_idx)
+            .map_err(|error| error.with_read_context(&context))?;
+        let mmap = Column::open_as_mmap(&part.get_column_path(table_def, column_def))?;
+        let dictionary =
+            TablePartInfo::read_dictionary(&mmap, &column_def.constraints.compression_type)
+                .map_err(|error| error.with_read_context(&context))?;
+        Ok(Self {
+            mmap,
+            marks,
+            dictionary,
+            compression_type: column_def.constraints.compression_type.clone(),
+            context,
+        })
+    }
+
+    const fn granules(&self) -> usize {
+        self.marks.len()
+    }
+
+    /// Reads values of a granule.
+    ///
+    /// Returns: Ok or `CouldNotReadData` on decompression or deserialization failure.
+    fn read_granule(&self, granule_idx: usize) -> Result<Vec<Value>> {
+        let bytes = TablePartInfo::get_granule_bytes_decompressed(
+            &self.mmap,
+            &self.marks[granule_idx],
+            &self.compression_type,
+            self.dictionary.as_deref(),
+            granule_idx,
+        )
+        .map_err(|error| error.with_read_context(&self.context))?;
+        rkyv::from_bytes::<Vec<Value>, rkyv::rancor::Error>(&bytes).map_err(|error| {
+            Error::CouldNotReadData(format!("Could not read data while merging: {error}"))
+        })
+    }
+}
+
+/// Rows of a part, read a granule at a time, see `BackgroundMerge::merge_sorted`.
+struct PartCursor {
+    name: String,
+    columns: Vec<ColumnReader>,
+    /// Index of the next granule to read.
+    next_granule: usize,
+    /// Values of the current granule by column, rows before `row` are taken.
+    granule: Vec<Vec<Value>>,
+    row: usize,
+    /// Set, once a row is found to go before the previous one by ORDER BY.
+    unsorted: bool,
+}
+
+impl PartCursor {
+    /// Opens columns of the part and reads its first granule.
+    ///
+    /// Returns: Ok or `CouldNotReadData` on failure.
+    fn open(table_def: &TableDef, part: &TablePartInfo) -> Result<Self> {
+        let columns = part
+            .column_defs
+            .iter()
+            .enumerate()
+            .map(|(file_idx, column_def)| ColumnReader::open(table_def, part, file_idx, column_def))
+            .collect::<Result<Vec<_>>>()?;
+        let mut cursor = Self {
+            name: part.name.clone(),
+            columns,
+            next_granule: 0,
+            granule: Vec::new(),
+            row: 0,
+            unsorted: false,
+        };
+        cursor.read_granule()?;
+        Ok(cursor)
+    }
+
+    /// Reads the next non-empty granule of every column. Part has no rows left after the last
+    /// one.
+    ///
+    /// Returns: Ok or `CouldNotReadData`, also when columns of the granule have different
+    /// number of rows.
+    fn read_granule(&mut self) -> Result<()> {
+        let granules = self.columns.first().map_or(0, ColumnReader::granules);
+        self.granule.clear();
+        self.row = 0;
+        while !self.has_row() && self.next_granule < granules {
+            let granule_idx = self.next_granule;
+            self.granule = self
+                .columns
+                .par_iter()
+                .map(|column| column.read_granule(granule_idx))
+                .collect::<Result<_>>()?;
+            self.next_granule += 1;
+            if self
+                .granule
+                .iter()
+                .any(|values| values.len() != self.granule[0].len())
+            {
+                return Err(Error::CouldNotReadData(format!(
+                    "granule={granule_idx}: Columns of part {} have different number of rows",
+                    self.name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    fn has_row(&self) -> bool {
+        self.granule
+            .first()
+            .is_some_and(|values| self.row < values.len())
+    }
+
+    fn value(&self, column: usize) -> &Value {
+        &self.granule[column][self.row]
+    }
+
+    /// Takes the current row and moves to the next one, which is checked not to go before it
+    /// by `order_by` (see `unsorted`).
+    ///
+    /// Returns: values of the row, or `CouldNotReadData` when the next granule could not be read.
+    fn take_row(&mut self, order_by: &[usize]) -> Result<Vec<Value>> {
+        let row: Vec<Value> = self
+            .granule
+            .iter_mut()
+            .map(|values| std::mem::replace(&mut values[self.row], Value::Null))
+            .collect();
+        self.row += 1;
+        if !self.has_row() {
+            self.read_granule()?;
+        }
+        if self.has_row() && compare_rows(order_by, |col| self.value(col), |col| &row[col]).is_lt()
+        {
+            self.unsorted = true;
+        }
+        Ok(row)
+    }
+}
+
+/// Renames `.old` directory of a part back after failed merge.
+///
+/// Returns: whether part is restored, failure is logged.
+fn restore_part(old_path: &Path, path: &Path) -> bool {
+    if let Err(error) = std::fs::rename(old_path, path) {
+        error!(
+            "Couldn't move part ({}). Remove `.old` extension and solve the issue: {}",
+            old_path.display(),
+            error
+        );
+        return false;
+    }
+    true
 }
 
 #[derive(Debug)]
 struct MergeData {
     table_def: TableDef,
-    part_0: TablePartInfo,
-    part_1: TablePartInfo,
+    /// Columns of the current table schema, merged part has exactly these columns.
+    columns: Vec<ColumnDef>,
+    /// Parts of the same partition, in order of `TablePartInfo::sequence`.
+    parts: Vec<TablePartInfo>,
+    /// Requested by `BackgroundMerge::request_merge`, rather than selected by a worker.
+    manual: bool,
+    /// Keeps parts away from other workers until merge finishes.
+    _claim: PartClaim,
 }
 
+/// Names of parts, which are being merged, by table. Merge workers skip them.
+static CLAIMED_PARTS: LazyLock<Mutex<HashMap<TableDef, HashSet<String>>>> =
+    LazyLock::new(Mutex::default);
+
+/// RAII guard that releases claimed parts on drop.
+#[derive(Debug)]
+struct PartClaim {
+    table_def: TableDef,
+    names: Vec<String>,
+}
+
+impl Drop for PartClaim {
+    fn drop(&mut self) {
+        let mut claimed = CLAIMED_PARTS.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(names) = claimed.get_mut(&self.table_def) {
+            for name in &self.names {
+                names.remove(name);
+            }
+            if names.is_empty() {
+                claimed.remove(&self.table_def);
+            }
+        }
+    }
+}
+
+/// Number of active `MergeExclusion`s by table. Parts of these tables are not claimed.
+static EXCLUDED_TABLES: LazyLock<Mutex<HashMap<TableDef, usize>>> = LazyLock::new(Mutex::default);
+
+/// RAII guard that allows merges of excluded tables on drop, see `BackgroundMerge::exclude_tables`.
+#[derive(Debug)]
+pub struct MergeExclusion {
+    tables: Vec<TableDef>,
+}
+
+impl Drop for MergeExclusion {
+    fn drop(&mut self) {
+        let mut excluded = EXCLUDED_TABLES
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        for table_def in &self.tables {
+            if let Some(count) = excluded.get_mut(table_def) {
+                *count -= 1;
+                if *count == 0 {
+                    excluded.remove(table_def);
+                }
+            }
+        }
+    }
+}
+
+/// Finds and claims two oldest unclaimed parts of the same partition in any table.
 fn find_two_parts() -> Option<MergeData> {
-    let data = TABLE_DATA.iter().find(|x| x.infos.len() > 1)?;
+    TABLE_DATA
+        .iter()
+        .find_map(|data| claim_two_parts(data.key(), data.value()))
+}
 
-    let mut names: Vec<_> = data.infos.iter().map(|x| &x.name).collect();
-    names.sort_by(|a, b| uuid_str_cmp(a, b));
+/// Claims two oldest unclaimed parts of the same partition of the table. Table with a
+/// requested merge (see `BackgroundMerge::request_merge`) is left to it. Parts, which can not be
+/// projected onto the schema (see `is_projectable`), are skipped until the schema changes, as
+/// their merge would fail every time.
+fn claim_two_parts(table_def: &TableDef, config: &TableConfig) -> Option<MergeData> {
+    if config.metadata.has_flag(flags::NO_MERGES) || has_merge_request(table_def) {
+        return None;
+    }
+    let mut claimed = CLAIMED_PARTS.lock().unwrap_or_else(PoisonError::into_inner);
+    if EXCLUDED_TABLES
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .contains_key(table_def)
+    {
+        return None;
+    }
+    let table_claimed = claimed.entry(table_def.clone()).or_default();
 
-    let part_0 = data.infos.iter().find(|x| x.name == *names[0])?;
-    let part_1 = data.infos.iter().find(|x| x.name == *names[1])?;
+    let columns = &config.metadata.schema.columns;
+    let (part_0, part_1) = pick_two_parts(
+        config
+            .infos
+            .iter()
+            .filter(|part| !table_claimed.contains(&part.name) && is_projectable(part, columns)),
+    )?;
+    table_claimed.insert(part_0.name.clone());
+    table_claimed.insert(part_1.name.clone());
 
     Some(MergeData {
-        table_def: data.pair().0.clone(),
-        part_0: part_0.clone(),
-        part_1: part_1.clone(),
+        table_def: table_def.clone(),
+        columns: config.metadata.schema.columns.clone(),
+        parts: vec![part_0.clone(), part_1.clone()],
+        manual: false,
+        _claim: PartClaim {
+            table_def: table_def.clone(),
+            names: vec![part_0.name.clone(), part_1.name.clone()],
+        },
     })
 }
 
-/// Try to parse both UUIDs and compare their timestamps.
-/// If either fails, fall back to string comparison.
-fn uuid_str_cmp(t1: &str, t2: &str) -> std::cmp::Ordering {
-    if t1 == t2 {
-        return std::cmp::Ordering::Equal;
+/// Picks two oldest (by `TablePartInfo::sequence`) parts of the same partition.
+fn pick_two_parts<'a>(
+    infos: impl IntoIterator<Item = &'a TablePartInfo>,
+) -> Option<(&'a TablePartInfo, &'a TablePartInfo)> {
+    let mut parts: Vec<_> = infos.into_iter().collect();
+    parts.sort_by_key(|part| part.sequence);
+
+    // parts of different partitions are stored in different directories, never merge them
+    parts.iter().enumerate().find_map(|(idx, part_0)| {
+        let part_1 = parts[(idx + 1)..]
+            .iter()
+            .find(|part| part.partition == part_0.partition)?;
+        Some((*part_0, *part_1))
+    })
+}
+
+/// Groups parts by partition, keeping groups of more than one part, each in order of
+/// `TablePartInfo::sequence`.
+fn group_by_partition<'a>(
+    infos: impl IntoIterator<Item = &'a TablePartInfo>,
+) -> Vec<Vec<&'a TablePartInfo>> {
+    let mut parts: Vec<_> = infos.into_iter().collect();
+    parts.sort_by_key(|part| part.sequence);
+
+    let mut groups: Vec<Vec<&TablePartInfo>> = Vec::new();
+    for part in parts {
+        match groups
+            .iter_mut()
+            .find(|group| group[0].partition == part.partition)
+        {
+            Some(group) => group.push(part),
+            None => groups.push(vec![part]),
+        }
     }
+    groups.retain(|group| group.len() > 1);
+    groups
+}
+
+/// Merge of all parts of a table, requested by `OPTIMIZE TABLE`.
+struct MergeRequest {
+    /// Instance of the table, which may be another one than of the worker.
+    instance: &'static Instance,
+    table_def: TableDef,
+    /// Receives result, once parts are merged.
+    done: mpsc::Sender<Result<()>>,
+}
+
+/// Requested merge, taken by a worker.
+struct ClaimedRequest {
+    instance: &'static Instance,
+    /// Merge of each partition with several parts.
+    merges: Vec<MergeData>,
+    done: mpsc::Sender<Result<()>>,
+}
 
-    // (seconds, subsec_nanos)
-    let t1_unix = Uuid::parse_str(t1)
-        .ok()
-        .and_then(|uuid| uuid.get_timestamp().map(|ts| ts.to_unix()));
-    let t2_unix = Uuid::parse_str(t2)
-        .ok()
-        .and_then(|uuid| uuid.get_timestamp().map(|ts| ts.to_unix()));
+/// Requested merges in order of requests. Workers take them before selecting parts themselves.
+static MERGE_REQUESTS: Mutex<VecDeque<MergeRequest>> = Mutex::new(VecDeque::new());
+/// Number of requested merges so far, wakes up pausing workers.
+static MERGE_REQUESTS_ADDED: AtomicU64 = AtomicU64::new(0);
 
-    match (t1_unix, t2_unix) {
-        (Some(t1_unix), Some(t2_unix)) => {
-            if (t1_unix.0 > t2_unix.0) || (t1_unix.0 == t2_unix.0 && t1_unix.1 > t2_unix.1) {
-                std::cmp::Ordering::Greater
-            } else {
-                std::cmp::Ordering::Less
+/// Whether merge of the table of the current instance is requested.
+fn has_merge_request(table_def: &TableDef) -> bool {
+    let instance = Instance::current();
+    MERGE_REQUESTS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .any(|request| request.is_of(instance, table_def))
+}
+
+/// Takes the first requested merge, whose table has no running merge and is not excluded
+/// (see `BackgroundMerge::exclude_tables`), and claims all parts of the table. Request of a
+/// dropped table, or of a table with nothing to merge, is completed right away.
+fn claim_requested_merge() -> Option<ClaimedRequest> {
+    let pending: Vec<(&'static Instance, TableDef)> = MERGE_REQUESTS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .map(|request| (request.instance, request.table_def.clone()))
+        .collect();
+
+    for (instance, table_def) in pending {
+        let merges = instance.enter(|| match TABLE_DATA.get(&table_def) {
+            Some(config) => match config.metadata.check_mergeable(&table_def) {
+                Ok(()) => claim_all_parts(&table_def, &config).map(Ok),
+                Err(error) => Some(Err(error)),
+            },
+            None => Some(Err(Error::TableNotFound)),
+        });
+        let Some(merges) = merges else {
+            continue;
+        };
+        // another worker may have taken it meanwhile
+        let Some(request) = take_merge_request(instance, &table_def) else {
+            continue;
+        };
+        match merges {
+            Ok(merges) if !merges.is_empty() => {
+                return Some(ClaimedRequest {
+                    instance,
+                    merges,
+                    done: request.done,
+                });
+            }
+            result => {
+                let _ = request.done.send(result.map(|_| ()));
             }
         }
-        _ => t1.cmp(t2),
+    }
+    None
+}
+
+fn take_merge_request(instance: &'static Instance, table_def: &TableDef) -> Option<MergeRequest> {
+    let mut requests = MERGE_REQUESTS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    let position = requests
+        .iter()
+        .position(|request| request.is_of(instance, table_def))?;
+    requests.remove(position)
+}
+
+impl MergeRequest {
+    /// Whether the request is of the table of `instance`.
+    fn is_of(&self, instance: &Instance, table_def: &TableDef) -> bool {
+        std::ptr::eq(self.instance, instance) && self.table_def == *table_def
+    }
+}
+
+/// Claims all parts of each partition of the table, which has more than one part.
+///
+/// Returns: merge per partition, empty when there is nothing to merge. `None`, when some parts
+/// of the table are being merged or the table is excluded.
+fn claim_all_parts(table_def: &TableDef, config: &TableConfig) -> Option<Vec<MergeData>> {
+    let mut claimed = CLAIMED_PARTS.lock().unwrap_or_else(PoisonError::into_inner);
+    if EXCLUDED_TABLES
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .contains_key(table_def)
+        || claimed
+            .get(table_def)
+            .is_some_and(|names| !names.is_empty())
+    {
+        return None;
+    }
+
+    let groups = group_by_partition(&config.infos);
+    if groups.is_empty() {
+        return Some(Vec::new());
+    }
+    let table_claimed = claimed.entry(table_def.clone()).or_default();
+    Some(
+        groups
+            .into_iter()
+            .map(|parts| {
+                let names: Vec<String> = parts.iter().map(|part| part.name.clone()).collect();
+                table_claimed.extend(names.iter().cloned());
+                MergeData {
+                    table_def: table_def.clone(),
+                    columns: config.metadata.schema.columns.clone(),
+                    parts: parts.into_iter().cloned().collect(),
+                    manual: true,
+                    _claim: PartClaim {
+                        table_def: table_def.clone(),
+                        names,
+                    },
+                }
+            })
+            .collect(),
+    )
+}
+
+/// Stage of a running merge, see `MergeProgress`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum MergePhase {
+    /// Parts are read into memory.
+    #[display("loading")]
+    Loading,
+    /// Columns of parts are combined, following the table schema. Sorted parts are instead
+    /// merged row by row into the raw directory, without loading.
+    #[display("merging")]
+    Merging,
+    /// Merged part is sorted and written into the raw directory, or, when merged row by row,
+    /// its files are finished.
+    #[display("writing")]
+    Writing,
+    /// Merged part replaces the merged parts.
+    #[display("moving")]
+    Moving,
+}
+
+/// Running merge, reported by `system.merges`.
+#[derive(Debug, Clone)]
+pub struct MergeProgress {
+    pub table_def: TableDef,
+    pub partition: Option<String>,
+    /// Names of the merged parts, oldest first.
+    pub source_parts: Vec<String>,
+    /// Rows of the merged parts together.
+    pub rows: u64,
+    pub started_at: SystemTime,
+    pub phase: MergePhase,
+    /// Requested by `OPTIMIZE TABLE`, rather than selected by a worker.
+    pub manual: bool,
+}
+
+/// Running merges by id, see `ProgressEntry`.
+static RUNNING_MERGES: LazyLock<Mutex<HashMap<u64, MergeProgress>>> = LazyLock::new(Mutex::default);
+static NEXT_MERGE_ID: AtomicU64 = AtomicU64::new(0);
+
+/// RAII guard, which reports the merge in `RUNNING_MERGES` until drop.
+struct ProgressEntry {
+    id: u64,
+}
+
+impl ProgressEntry {
+    fn new(merge_data: &MergeData) -> Self {
+        let id = NEXT_MERGE_ID.fetch_add(1, Ordering::Relaxed);
+        let progress = MergeProgress {
+            table_def: merge_data.table_def.clone(),
+            partition: merge_data
+                .parts
+                .first()
+                .and_then(|part| part.partition.clone()),
+            source_parts: merge_data
+                .parts
+                .iter()
+                .map(|part| part.name.clone())
+                .collect(),
+            rows: merge_data.parts.iter().map(|part| part.row_count).sum(),
+            started_at: SystemTime::now(),
+            phase: MergePhase::Loading,
+            manual: merge_data.manual,
+        };
+        RUNNING_MERGES
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(id, progress);
+        Self { id }
+    }
+
+    fn set_phase(&self, phase: MergePhase) {
+        if let Some(progress) = RUNNING_MERGES
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_mut(&self.id)
+        {
+            progress.phase = phase;
+        }
+    }
+}
+
+impl Drop for ProgressEntry {
+    fn drop(&mut self) {
+        RUNNING_MERGES
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{Constraints, TableMetadata, TableSchema, TableSettings, ValueType};
+    use std::fmt::Write as _;
+
+    fn column_def(name: &str, nullable: bool, default: Option<Value>) -> ColumnDef {
+        ColumnDef {
+            name: name.to_string(),
+            field_type: ValueType::UInt64,
+            constraints: Constraints {
+                nullable,
+                default: default.map(DefaultExpr::Literal),
+                ..Constraints::default()
+            },
+        }
+    }
+
+    fn column(column_def: &ColumnDef, data: &[u64]) -> Column {
+        Column {
+            column_def: column_def.clone(),
+            data: data.iter().copied().map(Value::UInt64).collect(),
+        }
+    }
+
+    #[test]
+    fn test_merge_parts_follows_schema() {
+        let id = column_def("id", false, None);
+        let dropped = column_def("dropped", true, None);
+        let old_only = column_def("old_only", true, None);
+        let new_nullable = column_def("new_nullable", true, None);
+        let new_default = column_def("new_default", false, Some(Value::UInt64(7)));
+
+        // part_0 predates `new_*` columns, part_1 was written after `old_only` was dropped
+        let part_0 = vec![
+            column(&old_only, &[10, 20]),
+            column(&id, &[1, 2]),
+            column(&dropped, &[0, 0]),
+        ];
+        let part_1 = vec![
+            column(&id, &[3]),
+            column(&new_default, &[30]),
+            column(&dropped, &[0]),
+            column(&new_nullable, &[40]),
+        ];
+        let schema = vec![
+            id.clone(),
+            new_nullable.clone(),
+            old_only.clone(),
+            new_default.clone(),
+        ];
+
+        let merged = BackgroundMerge::merge_parts(part_0, part_1, &schema).unwrap();
+
+        let column_defs: Vec<_> = merged.iter().map(|col| col.column_def.clone()).collect();
+        assert_eq!(column_defs, schema);
+        let data: Vec<_> = merged
+            .into_iter()
+            .map(|col| col.data.into_values())
+            .collect();
+        assert_eq!(
+            data,
+            [
+                vec![Value::UInt64(1), Value::UInt64(2), Value::UInt64(3)],
+                vec![Value::Null, Value::Null, Value::UInt64(40)],
+                vec![Value::UInt64(10), Value::UInt64(20), Value::Null],
+                vec![Value::UInt64(7), Value::UInt64(7), Value::UInt64(30)],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_parts_rejects_incompatible_parts() {
+        let id = column_def("id", false, None);
+        let required = column_def("required", false, None);
+        let part = || vec![column(&id, &[1])];
+
+        let error =
+            BackgroundMerge::merge_parts(part(), part(), &[id.clone(), required]).unwrap_err();
+        assert!(error.to_string().contains("required"), "{error}");
+
+        let mut string_id = id.clone();
+        string_id.field_type = ValueType::String;
+        assert!(matches!(
+            BackgroundMerge::merge_parts(part(), part(), &[string_id]),
+            Err(Error::CouldNotInsertData(_))
+        ));
+    }
+
+    #[test]
+    fn test_merge_parts_pads_not_null_columns_with_default() {
+        let id = column_def("id", false, None);
+        let count = column_def("count", false, Some(Value::UInt64(0)));
+        let comment = column_def("comment", true, None);
+
+        // each part lacks a column of the other one
+        let part_0 = vec![column(&id, &[1, 2]), column(&count, &[5, 6])];
+        let part_1 = vec![column(&comment, &[9]), column(&id, &[3])];
+        let schema = vec![id.clone(), count.clone(), comment.clone()];
+
+        let merged = BackgroundMerge::merge_parts(part_0, part_1, &schema).unwrap();
+        let data: Vec<_> = merged
+            .into_iter()
+            .map(|col| col.data.into_values())
+            .collect();
+        assert_eq!(
+            data,
+            [
+                vec![Value::UInt64(1), Value::UInt64(2), Value::UInt64(3)],
+                vec![Value::UInt64(5), Value::UInt64(6), Value::UInt64(0)],
+                vec![Value::Null, Value::Null, Value::UInt64(9)],
+            ]
+        );
+
+        // `CREATE TABLE` rejects NOT NULL DEFAULT NULL, but merge must not rely on it
+        let null_default = column_def("null_default", false, Some(Value::Null));
+        let part = || vec![column(&id, &[1])];
+        let error =
+            BackgroundMerge::merge_parts(part(), part(), &[id.clone(), null_default]).unwrap_err();
+        assert!(matches!(error, Error::CouldNotInsertData(_)), "{error}");
+        assert!(error.to_string().contains("null_default"), "{error}");
+    }
+
+    fn part_info(name: &str, sequence: u64, partition: Option<&str>) -> TablePartInfo {
+        TablePartInfo {
+            name: name.to_string(),
+            sequence,
+            row_count: 1,
+            marks: Vec::new(),
+            pk_bounds: Vec::new(),
+            column_defs: Vec::new(),
+            partition: partition.map(str::to_string),
+            suspect: None,
+        }
+    }
+
+    fn part_names(parts: &[TablePartInfo]) -> Vec<String> {
+        parts.iter().map(|part| part.name.clone()).collect()
+    }
+
+    #[test]
+    fn test_group_by_partition() {
+        let infos = [
+            part_info("c", 4, Some("a")),
+            part_info("b", 2, None),
+            part_info("a", 1, Some("a")),
+            part_info("d", 3, Some("b")),
+            part_info("e", 5, Some("a")),
+        ];
+        let groups: Vec<Vec<&str>> = group_by_partition(&infos)
+            .into_iter()
+            .map(|group| group.iter().map(|part| part.name.as_str()).collect())
+            .collect();
+        // single part partitions have nothing to merge
+        assert_eq!(groups, vec![vec!["a", "c", "e"]]);
+    }
+
+    #[test]
+    fn test_pick_two_parts_by_sequence() {
+        // names are neither UUIDs nor in sequence order
+        let infos = vec![
+            part_info("zz_legacy", 7, Some("a")),
+            part_info("b", 3, Some("b")),
+            part_info("imported", 5, Some("a")),
+            part_info("a", 9, Some("b")),
+        ];
+
+        let (part_0, part_1) = pick_two_parts(&infos).unwrap();
+        assert_eq!((part_0.name.as_str(), part_1.name.as_str()), ("b", "a"));
+
+        let (part_0, part_1) = pick_two_parts(&infos[..3]).unwrap();
+        assert_eq!(
+            (part_0.name.as_str(), part_1.name.as_str()),
+            ("imported", "zz_legacy")
+        );
+
+        assert!(pick_two_parts(&infos[1..3]).is_none());
+    }
+
+    #[test]
+    fn test_merge_workers_claim_disjoint_parts() {
+        let table_def = TableDef {
+            database: "merge_claims".to_string(),
+            table: "t".to_string(),
+        };
+        let metadata = TableMetadata::try_new(
+            TableSchema {
+                columns: Vec::new(),
+                order_by: Vec::new(),
+                primary_key: Vec::new(),
+            },
+            TableSettings::default(),
+            None,
+        )
+        .unwrap();
+        let infos = (0..4)
+            .map(|sequence| part_info(&format!("part_{sequence}"), sequence, None))
+            .collect();
+        TABLE_DATA.insert(table_def.clone(), TableConfig { metadata, infos });
+
+        let claim = || {
+            let config = TABLE_DATA.get(&table_def).unwrap();
+            claim_two_parts(&table_def, &config)
+        };
+
+        // two workers look for parts at the same time
+        let barrier = std::sync::Barrier::new(2);
+        let (first, second) = std::thread::scope(|scope| {
+            let worker = || {
+                barrier.wait();
+                claim().unwrap()
+            };
+            let first = scope.spawn(worker);
+            let second = scope.spawn(worker);
+            (first.join().unwrap(), second.join().unwrap())
+        });
+
+        let mut names: Vec<_> = first
+            .parts
+            .iter()
+            .chain(&second.parts)
+            .map(|part| &part.name)
+            .collect();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), 4);
+        assert!(claim().is_none());
+
+        // finished merge releases its parts
+        let released = part_names(&first.parts);
+        drop(first);
+        let third = claim().unwrap();
+        assert_eq!(part_names(&third.parts), released);
+
+        TABLE_DATA.remove(&table_def);
+    }
+
+    #[test]
+    fn test_claim_skips_parts_not_projectable_onto_schema() {
+        let table_def = TableDef {
+            database: "merge_unprojectable".to_string(),
+            table: "t".to_string(),
+        };
+        let id = column_def("id", false, None);
+        let required = column_def("required", false, None);
+        let metadata = |columns: Vec<ColumnDef>| {
+            TableMetadata::try_new(
+                TableSchema {
+                    columns,
+                    order_by: Vec::new(),
+                    primary_key: Vec::new(),
+                },
+                TableSettings::default(),
+                None,
+            )
+            .unwrap()
+        };
+        // the oldest part predates NOT NULL column without a default
+        let mut infos: Vec<_> = (0..3)
+            .map(|sequence| {
+                let mut info = part_info(&format!("part_{sequence}"), sequence, None);
+                info.column_defs = vec![id.clone(), required.clone()];
+                info
+            })
+            .collect();
+        infos[0].column_defs = vec![id.clone()];
+        TABLE_DATA.insert(
+            table_def.clone(),
+            TableConfig {
+                metadata: metadata(vec![id.clone(), required.clone()]),
+                infos,
+            },
+        );
+        let claim = || {
+            let config = TABLE_DATA.get(&table_def).unwrap();
+            claim_two_parts(&table_def, &config)
+        };
+
+        let merge_data = claim().unwrap();
+        assert_eq!(part_names(&merge_data.parts), ["part_1", "part_2"]);
+        drop(merge_data);
+
+        // column of another type can not be projected either
+        let mut string_id = id.clone();
+        string_id.field_type = ValueType::String;
+        TABLE_DATA.get_mut(&table_def).unwrap().metadata = metadata(vec![string_id]);
+        assert!(claim().is_none());
+
+        // schema change, which gives the column a default, makes the part mergeable again
+        let with_default = column_def("required", false, Some(Value::UInt64(0)));
+        TABLE_DATA.get_mut(&table_def).unwrap().metadata = metadata(vec![id, with_default]);
+        let merge_data = claim().unwrap();
+        assert_eq!(part_names(&merge_data.parts), ["part_0", "part_1"]);
+        drop(merge_data);
+
+        TABLE_DATA.remove(&table_def);
+    }
+
+    #[test]
+    fn test_exclude_tables_waits_for_running_merge() {
+        let table_def = TableDef {
+            database: "merge_exclusion".to_string(),
+            table: "t".to_string(),
+        };
+        let metadata = TableMetadata::try_new(
+            TableSchema {
+                columns: Vec::new(),
+                order_by: Vec::new(),
+                primary_key: Vec::new(),
+            },
+            TableSettings::default(),
+            None,
+        )
+        .unwrap();
+        let infos = (0..4)
+            .map(|sequence| part_info(&format!("part_{sequence}"), sequence, None))
+            .collect();
+        TABLE_DATA.insert(table_def.clone(), TableConfig { metadata, infos });
+
+        let claim = || {
+            let config = TABLE_DATA.get(&table_def).unwrap();
+            claim_two_parts(&table_def, &config)
+        };
+
+        let running = claim().unwrap();
+        let exclusion = std::thread::scope(|scope| {
+            let exclusion =
+                scope.spawn(|| BackgroundMerge::exclude_tables(std::slice::from_ref(&table_def)));
+            std::thread::sleep(Duration::from_millis(50));
+            assert!(!exclusion.is_finished());
+            // excluded table is skipped, even though it has unclaimed parts
+            assert!(claim().is_none());
+
+            drop(running);
+            exclusion.join().unwrap()
+        });
+        assert!(claim().is_none());
+
+        drop(exclusion);
+        assert!(claim().is_some());
+
+        TABLE_DATA.remove(&table_def);
+    }
+
+    #[test]
+    fn test_requested_merge_claims_all_parts() {
+        let table_def = TableDef {
+            database: "merge_requests".to_string(),
+            table: "t".to_string(),
+        };
+        let metadata = TableMetadata::try_new(
+            TableSchema {
+                columns: Vec::new(),
+                order_by: Vec::new(),
+                primary_key: Vec::new(),
+            },
+            TableSettings::default(),
+            None,
+        )
+        .unwrap();
+        let infos = vec![
+            part_info("part_2", 2, None),
+            part_info("part_0", 0, None),
+            part_info("part_1", 1, None),
+        ];
+        TABLE_DATA.insert(table_def.clone(), TableConfig { metadata, infos });
+        let claim = || {
+            let config = TABLE_DATA.get(&table_def).unwrap();
+            claim_two_parts(&table_def, &config)
+        };
+
+        // automatic merge waits, until requested merge is taken
+        let running = claim().unwrap();
+        let result = BackgroundMerge::request_merge(table_def.clone());
+        assert!(claim().is_none());
+        assert!(claim_requested_merge().is_none());
+        drop(running);
+
+        let request = claim_requested_merge().unwrap();
+        assert_eq!(request.merges.len(), 1);
+        assert_eq!(
+            part_names(&request.merges[0].parts),
+            ["part_0", "part_1", "part_2"]
+        );
+        assert!(claim().is_none());
+
+        // merge is reported, until it finishes
+        let progress = ProgressEntry::new(&request.merges[0]);
+        progress.set_phase(MergePhase::Writing);
+        let running = BackgroundMerge::running_merges();
+        let reported = running
+            .iter()
+            .find(|merge| merge.table_def == table_def)
+            .unwrap();
+        assert_eq!(reported.source_parts, ["part_0", "part_1", "part_2"]);
+        assert_eq!((reported.rows, reported.phase), (3, MergePhase::Writing));
+        assert!(reported.manual);
+        drop(progress);
+        assert!(
+            BackgroundMerge::running_merges()
+                .iter()
+                .all(|merge| merge.table_def != table_def)
+        );
+
+        request.done.send(Ok(())).unwrap();
+        assert!(result.recv().unwrap().is_ok());
+        drop(request);
+
+        // request of a dropped table fails right away
+        TABLE_DATA.remove(&table_def);
+        let result = BackgroundMerge::request_merge(table_def.clone());
+        assert!(claim_requested_merge().is_none());
+        assert!(matches!(result.recv().unwrap(), Err(Error::TableNotFound)));
+    }
+
+    /// Returns rows of inserted random parts, as `INSERT` statements. Ids are from a small
+    /// range, so parts have rows with equal keys, and strings of random length move granule
+    /// boundaries of adaptive granularity.
+    fn random_inserts(table: &str, parts: usize, nullable_keys: bool) -> Vec<String> {
+        // xorshift, so failures are reproducible
+        let mut state = 0x9e37_79b9_7f4a_7c15_u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        let tags = ["'a'", "'b'", "'c'", "'d'", "NULL"];
+        let tags = if nullable_keys { &tags[..] } else { &tags[..4] };
+
+        (0..parts)
+            .map(|_| {
+                let mut sql = format!("INSERT INTO {table} (id, tag, note, score) VALUES ");
+                for row in 0..(20 + next() % 300) {
+                    let id = if nullable_keys && next() % 10 == 0 {
+                        "NULL".to_string()
+                    } else {
+                        (next() % 200).to_string()
+                    };
+                    let tag = tags[(next() % tags.len() as u64) as usize];
+                    let note = match next() % 5 {
+                        0 => "NULL".to_string(),
+                        len => format!("'{}'", "x".repeat((len * (next() % 16)) as usize)),
+                    };
+                    let score = (next() % 1000).cast_signed() - 500;
+                    let separator = if row == 0 { "" } else { ", " };
+                    write!(sql, "{separator}({id}, {tag}, {note}, {score})").unwrap();
+                }
+                sql
+            })
+            .collect()
+    }
+
+    /// Merges all parts of the table row by row, and also the way parts were merged in memory.
+    ///
+    /// Returns: part, which merge in memory gives (not saved), and info and columns of the part,
+    /// which replaced merged parts.
+    fn merge_both_ways(table_def: &TableDef) -> (TablePart, TablePartInfo, Vec<Column>) {
+        let merge_data = {
+            let config = TABLE_DATA.get(table_def).unwrap();
+            claim_all_parts(table_def, &config).unwrap().pop().unwrap()
+        };
+        let newest = merge_data.parts.last().unwrap().clone();
+        let progress = ProgressEntry::new(&merge_data);
+
+        let merged = BackgroundMerge::load_parts(&merge_data)
+            .unwrap()
+            .into_iter()
+            .reduce(|merged, part| {
+                BackgroundMerge::merge_parts(merged, part, &merge_data.columns).unwrap()
+            })
+            .unwrap();
+        let expected = TablePart::try_new(table_def, merged, None, None).unwrap();
+
+        let new_part = BackgroundMerge::merge_sorted(&merge_data, &newest, &progress)
+            .unwrap()
+            .unwrap();
+        let info = new_part.info.clone();
+        assert_eq!(
+            (info.name.as_str(), info.sequence),
+            (newest.name.as_str(), newest.sequence)
+        );
+        drop(progress);
+        assert!(BackgroundMerge::atomic_part_move(merge_data, new_part));
+
+        let columns = BackgroundMerge::load_part(table_def, &info).unwrap();
+        (expected, info, columns)
+    }
+
+    fn rows(columns: &[Column]) -> Vec<Vec<Value>> {
+        (0..columns[0].data.len())
+            .map(|row| columns.iter().map(|col| col.data[row].clone()).collect())
+            .collect()
+    }
+
+    fn mark_indexes(info: &TablePartInfo) -> Vec<Vec<Value>> {
+        info.marks.iter().map(|mark| mark.index.clone()).collect()
+    }
+
+    #[test]
+    fn test_merge_sorted_matches_merge_in_memory() {
+        let dir =
+            std::env::temp_dir().join(format!("touchhouse-merge-sorted-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let instance = Instance::open(&dir).unwrap();
+        let table_def = |table: &str| TableDef {
+            database: "merge_sorted".to_string(),
+            table: table.to_string(),
+        };
+        instance
+            .execute_command("CREATE DATABASE merge_sorted")
+            .unwrap();
+
+        // rows with equal ORDER BY values may be ordered differently by the engine
+        instance
+            .execute_command(
+                "CREATE TABLE merge_sorted.tree (id UInt64, tag LowCardinality(String), \
+                 note String, score Int64) ENGINE = MergeTree ORDER BY (id, tag) \
+                 SETTINGS index_granularity = 8",
+            )
+            .unwrap();
+        for sql in random_inserts("merge_sorted.tree", 3, false) {
+            instance.execute_command(&sql).unwrap();
+        }
+        instance.enter(|| {
+            let (expected, info, columns) = merge_both_ways(&table_def("tree"));
+            let (expected_rows, merged_rows) = (rows(&expected.data), rows(&columns));
+            let keys = |rows: &[Vec<Value>]| -> Vec<Vec<Value>> {
+                rows.iter().map(|row| row[..2].to_vec()).collect()
+            };
+            assert_eq!(keys(&merged_rows), keys(&expected_rows));
+            let sorted = |rows: Vec<Vec<Value>>| {
+                let mut rows: Vec<String> = rows.iter().map(|row| format!("{row:?}")).collect();
+                rows.sort();
+                rows
+            };
+            assert_eq!(sorted(merged_rows), sorted(expected_rows));
+            assert_eq!(mark_indexes(&info), mark_indexes(&expected.info));
+            assert_eq!(info.pk_bounds, expected.info.pk_bounds);
+            assert_eq!(info.column_defs, expected.info.column_defs);
+        });
+
+        instance
+            .execute_command(
+                "CREATE TABLE merge_sorted.replacing (id Nullable(UInt64), \
+                 tag LowCardinality(Nullable(String)), note String, score Int64) \
+                 ENGINE = ReplacingMergeTree PRIMARY KEY id ORDER BY (id, tag) \
+                 SETTINGS index_granularity = 16, adaptive_index_granularity = 1, \
+                 index_granularity_bytes = 400, allow_nullable_key = 1",
+            )
+            .unwrap();
+        for sql in random_inserts("merge_sorted.replacing", 4, true) {
+            instance.execute_command(&sql).unwrap();
+        }
+        instance.enter(|| {
+            let (expected, info, columns) = merge_both_ways(&table_def("replacing"));
+            assert_eq!(rows(&columns), rows(&expected.data));
+            assert_eq!(info.row_count, expected.info.row_count);
+            assert_eq!(mark_indexes(&info), mark_indexes(&expected.info));
+            assert_eq!(info.pk_bounds, expected.info.pk_bounds);
+            assert!(info.marks.len() > info.row_count.div_ceil(16) as usize);
+
+            // parts without a column of the schema are merged in memory
+            let mut columns = info.column_defs.clone();
+            assert!(has_schema_columns(&info, &columns));
+            columns.pop();
+            assert!(!has_schema_columns(&info, &columns));
+        });
+        let count = instance
+            .execute_command("SELECT count(*) FROM merge_sorted.replacing")
+            .unwrap();
+        let parts = instance
+            .table_data()
+            .get(&table_def("replacing"))
+            .unwrap()
+            .infos
+            .len();
+        assert_eq!(parts, 1);
+        assert!(matches!(count.columns[0].data.get(0), Some(Value::UInt64(rows)) if *rows > 0));
+
+        let _ = std::fs::remove_dir_all(&dir);
     }
 }
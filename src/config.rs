@@ -1,10 +1,28 @@
+use crate::access::AccessConfig;
+use crate::runtime_config::Instance;
+
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::io::ErrorKind;
 use std::net::SocketAddrV4;
+use std::ops::Deref;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Configuration of the current instance, see `Instance::current`.
+pub static CONFIG: CurrentConfig = CurrentConfig(());
+
+/// Refers to `Config` of the current instance.
+pub struct CurrentConfig(());
+
+impl Deref for CurrentConfig {
+    type Target = Config;
+
+    fn deref(&self) -> &Config {
+        Instance::current().config()
+    }
+}
 
-/// Global static to access server configuration
-pub static CONFIG: std::sync::LazyLock<Config> = std::sync::LazyLock::new(Config::build);
 const CONFIG_FILENAME: &str = "touch_config.toml";
 const DEFAULT_CONFIG_STR: &str = r#"# Storage directory
 storage_directory = "db_files/"
@@ -21,8 +39,89 @@ max_connections = 100
 # - 3 => Error
 log_level = 1
 
+# Format of log lines. Allowed values:
+# - "text" => human readable lines
+# - "json" => JSON object per line with timestamp, level, target, message and context fields
+log_format = "text"
+
 # Signifies when database can do background merges of parts, depending on database load
-background_merge_available_under = 5"#;
+background_merge_available_under = 5
+
+# Max size of a single SQL command in megabytes
+max_frame_size_mb = 64
+
+# Seconds of client inactivity before connection is closed. 0 means no timeout
+idle_timeout_secs = 300
+
+# Number of merges, which can run at the same time
+max_background_merges = 1
+
+# Threads, shared by running merges. Queries use separate threads
+background_merge_threads = 2
+
+# Never write to the storage directory: writes are rejected, merges are disabled and parts,
+# written by another server, are picked up every `rescan_interval_secs`
+read_only = false
+
+# Seconds between scans of the storage directory in read-only mode
+rescan_interval_secs = 5
+
+# Validation of granules before access. Allowed values:
+# - "always" => every granule is validated
+# - "on_error" => granules, failing a quick bounds check, are validated
+# - "never" => no validation, corrupted granule may crash the server
+validate_granules = "always"
+
+# Compress `part.inf` and `.metadata` files with LZ4. Both compressed and uncompressed files are
+# read regardless of this setting, but servers of older versions can not read compressed ones
+compress_info_files = false
+
+# Statements running longer than this number of milliseconds are logged at warn level.
+# 0 means no logging
+slow_query_ms = 1000
+
+# Replace literals (strings and numbers) of logged slow statements with `?`
+slow_query_redact_literals = false
+
+# Queries with higher complexity are rejected. 0 means no limit
+max_query_complexity = 100
+
+# Inserts into a table with this many active parts are rejected, until merges reduce them.
+# 0 means no limit
+max_parts_per_table = 300
+
+# Approximate max bytes of memory of a single `SELECT`, which is rejected when it uses more.
+# Only the major buffers are counted: scanned rows, decompressed granules and sorting.
+# 0 means no limit
+max_memory_per_query = 0
+
+# Max bytes per second written by background merges, so they do not starve inserts and queries
+# of disk I/O. 0 means no limit
+max_merge_bytes_per_sec = 0
+
+# Inserts and merges, which would leave less free disk space (in bytes), are not started
+min_free_bytes = 1073741824
+
+# Seconds `OPTIMIZE TABLE` waits for its merge, before it responds, that merge is scheduled.
+# 0 means no waiting
+optimize_timeout_secs = 60
+
+# Rows of `IMPORT INTO`, which are inserted as a single part
+max_insert_block_rows = 1048576
+
+# Directory, whose files `IMPORT INTO` may read. `IMPORT INTO` is rejected without it
+# import_directory = "user_files/"
+
+# Address based access control. Levels: "readwrite" (all commands), "readonly" (`SELECT`,
+# `SHOW`) and "deny" (no commands). Addresses are CIDR networks, e.g., "10.0.0.0/8" or "::1/128"
+# [access]
+# admin_addrs = ["127.0.0.1"]     # readwrite access
+# readonly_addrs = ["10.0.0.0/8"] # readonly access, unless listed in `admin_addrs`
+# default_access = "readwrite"    # access of unlisted addresses
+
+# Max bytes of part files of a database. Inserts, which would exceed it, are rejected
+# [quotas]
+# analytics = 107374182400"#;
 
 /// Server configuration
 #[derive(Debug, Deserialize)]
@@ -37,10 +136,145 @@ pub struct Config {
     /// - 2 => Warn
     /// - 3 => Error
     log_level: u8,
+    /// Format of log lines, see `LogFormat`.
+    #[serde(default)]
+    log_format: LogFormat,
     /// Max concurrent connections.
     max_connections: usize,
     /// Signifies when database can do background merges of parts, depending on database load
     background_merge_available_under: u32,
+    /// Max size of a single SQL command in megabytes.
+    #[serde(default = "default_max_frame_size_mb")]
+    max_frame_size_mb: usize,
+    /// Seconds of client inactivity before connection is closed. 0 or absent means no timeout.
+    #[serde(default)]
+    idle_timeout_secs: u64,
+    /// Number of merges, which can run at the same time.
+    #[serde(default = "default_max_background_merges")]
+    max_background_merges: usize,
+    /// Threads, shared by running merges. Queries use separate threads.
+    #[serde(default = "default_background_merge_threads")]
+    background_merge_threads: usize,
+    /// Never write to the storage directory. Writes are rejected, merges are disabled and new
+    /// parts, written by another server, are picked up every `rescan_interval_secs`.
+    #[serde(default)]
+    read_only: bool,
+    /// Seconds between scans of the storage directory in read-only mode.
+    #[serde(default = "default_rescan_interval_secs")]
+    rescan_interval_secs: u64,
+    /// Validation of granules before access, see `GranuleValidation`.
+    #[serde(default)]
+    validate_granules: GranuleValidation,
+    /// Compress data of written `part.inf` and `.metadata` files, see `encode_info_file`.
+    #[serde(default)]
+    compress_info_files: bool,
+    /// Statements running longer than this number of milliseconds are logged at warn level.
+    /// 0 means no logging.
+    #[serde(default = "default_slow_query_ms")]
+    slow_query_ms: u64,
+    /// Replace literals of logged slow statements with `?`.
+    #[serde(default)]
+    slow_query_redact_literals: bool,
+    /// Queries with higher complexity (see `PhysicalPlan::get_complexity`) are rejected.
+    /// 0 means no limit.
+    #[serde(default = "default_max_query_complexity")]
+    max_query_complexity: u32,
+    /// Inserts into a table with this many active parts are rejected with `TooManyParts`.
+    /// 0 means no limit.
+    #[serde(default = "default_max_parts_per_table")]
+    max_parts_per_table: usize,
+    /// Max bytes per second written by all background merges together. 0 means no limit.
+    #[serde(default)]
+    max_merge_bytes_per_sec: u64,
+    /// Approximate max bytes of memory of a single query, see `MemoryTracker`. 0 means no
+    /// limit.
+    #[serde(default)]
+    max_memory_per_query: usize,
+    /// Inserts and merges, which would leave less free space in the storage directory, are
+    /// not started.
+    #[serde(default = "default_min_free_bytes")]
+    min_free_bytes: u64,
+    /// Seconds `OPTIMIZE TABLE` waits for its merge. 0 means no waiting.
+    #[serde(default = "default_optimize_timeout_secs")]
+    optimize_timeout_secs: u64,
+    /// Rows of `IMPORT INTO`, which are inserted as a single part.
+    #[serde(default = "default_max_insert_block_rows")]
+    max_insert_block_rows: usize,
+    /// Directory, whose files `IMPORT INTO` may read. `None` rejects `IMPORT INTO`.
+    #[serde(default)]
+    import_directory: Option<PathBuf>,
+    /// Access levels of client addresses, see `AccessConfig`.
+    #[serde(default)]
+    access: AccessConfig,
+    /// Max bytes of part files of a database, by database name.
+    #[serde(default)]
+    quotas: HashMap<String, u64>,
+}
+
+/// Format of log lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// Human readable lines of `env_logger`. Context fields are appended as `key=value`.
+    #[default]
+    Text,
+    /// JSON object per line (see `logging::json_line`), for log aggregation.
+    Json,
+}
+
+/// Validation of granules before access (see `TablePartInfo::access_granule`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GranuleValidation {
+    /// Every granule is fully validated. Corrupted granule produces `CouldNotReadData`.
+    #[default]
+    Always,
+    /// Only top level of a granule (bounds and alignment of the values array) is checked, which
+    /// takes constant time. Granule, failing the check, is fully validated to report an error.
+    /// Corruption inside values (e.g., string bytes) is not detected.
+    OnError,
+    /// No validation. Corrupted granule causes undefined behaviour, e.g., a crash.
+    Never,
+}
+
+const fn default_max_frame_size_mb() -> usize {
+    64
+}
+
+const fn default_max_background_merges() -> usize {
+    1
+}
+
+const fn default_background_merge_threads() -> usize {
+    2
+}
+
+const fn default_rescan_interval_secs() -> u64 {
+    5
+}
+
+const fn default_slow_query_ms() -> u64 {
+    1000
+}
+
+const fn default_max_query_complexity() -> u32 {
+    100
+}
+
+const fn default_max_parts_per_table() -> usize {
+    300
+}
+
+const fn default_min_free_bytes() -> u64 {
+    1024 * 1024 * 1024
+}
+
+const fn default_optimize_timeout_secs() -> u64 {
+    60
+}
+
+const fn default_max_insert_block_rows() -> usize {
+    1024 * 1024
 }
 
 impl Config {
@@ -63,6 +297,11 @@ impl Config {
         }
     }
 
+    /// Get format of log lines from configuration
+    pub const fn get_log_format(&self) -> LogFormat {
+        self.log_format
+    }
+
     /// Get max connections from configuration
     pub const fn get_max_connections(&self) -> usize {
         self.max_connections
@@ -74,6 +313,148 @@ impl Config {
     pub const fn get_background_merge_available_under(&self) -> u32 {
         self.background_merge_available_under
     }
+
+    /// Get max size of a single SQL command in bytes
+    pub const fn get_max_frame_size(&self) -> usize {
+        self.max_frame_size_mb.saturating_mul(1024 * 1024)
+    }
+
+    /// Get idle connection timeout. `None` means connections are never closed for inactivity.
+    pub const fn get_idle_timeout(&self) -> Option<Duration> {
+        if self.idle_timeout_secs == 0 {
+            None
+        } else {
+            Some(Duration::from_secs(self.idle_timeout_secs))
+        }
+    }
+
+    /// Get number of merges, which can run at the same time. At least 1.
+    pub const fn get_max_background_merges(&self) -> usize {
+        if self.max_background_merges == 0 {
+            1
+        } else {
+            self.max_background_merges
+        }
+    }
+
+    /// Get number of threads, shared by running merges. At least 1.
+    pub const fn get_background_merge_threads(&self) -> usize {
+        if self.background_merge_threads == 0 {
+            1
+        } else {
+            self.background_merge_threads
+        }
+    }
+
+    /// Whether server never writes to the storage directory.
+    pub const fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Get interval between scans of the storage directory in read-only mode. At least 1 second.
+    pub const fn get_rescan_interval(&self) -> Duration {
+        if self.rescan_interval_secs == 0 {
+            Duration::from_secs(1)
+        } else {
+            Duration::from_secs(self.rescan_interval_secs)
+        }
+    }
+
+    /// Get validation mode of granules.
+    pub const fn get_validate_granules(&self) -> GranuleValidation {
+        self.validate_granules
+    }
+
+    /// Whether data of written `part.inf` and `.metadata` files is compressed.
+    pub const fn is_compress_info_files(&self) -> bool {
+        self.compress_info_files
+    }
+
+    /// Get duration, above which statements are logged as slow. `None` means no logging.
+    pub const fn get_slow_query_threshold(&self) -> Option<Duration> {
+        if self.slow_query_ms == 0 {
+            None
+        } else {
+            Some(Duration::from_millis(self.slow_query_ms))
+        }
+    }
+
+    /// Whether literals of logged slow statements are replaced with `?`.
+    pub const fn is_slow_query_redact_literals(&self) -> bool {
+        self.slow_query_redact_literals
+    }
+
+    /// Get max complexity of a query. `None` means no limit.
+    pub const fn get_max_query_complexity(&self) -> Option<u32> {
+        if self.max_query_complexity == 0 {
+            None
+        } else {
+            Some(self.max_query_complexity)
+        }
+    }
+
+    /// Get max number of active parts of a table, which accepts inserts. `None` means no limit.
+    pub const fn get_max_parts_per_table(&self) -> Option<usize> {
+        if self.max_parts_per_table == 0 {
+            None
+        } else {
+            Some(self.max_parts_per_table)
+        }
+    }
+
+    /// Get max bytes per second written by background merges. `None` means no limit.
+    pub const fn get_max_merge_bytes_per_sec(&self) -> Option<u64> {
+        if self.max_merge_bytes_per_sec == 0 {
+            None
+        } else {
+            Some(self.max_merge_bytes_per_sec)
+        }
+    }
+
+    /// Get max bytes of memory of a single query. `None` means no limit.
+    pub const fn get_max_memory_per_query(&self) -> Option<usize> {
+        if self.max_memory_per_query == 0 {
+            None
+        } else {
+            Some(self.max_memory_per_query)
+        }
+    }
+
+    /// Get free space in bytes, which inserts and merges leave in the storage directory.
+    pub const fn get_min_free_bytes(&self) -> u64 {
+        self.min_free_bytes
+    }
+
+    /// Get time, which `OPTIMIZE TABLE` waits for its merge.
+    pub const fn get_optimize_timeout(&self) -> Duration {
+        Duration::from_secs(self.optimize_timeout_secs)
+    }
+
+    /// Get rows of `IMPORT INTO`, which are inserted as a single part. At least 1.
+    pub const fn get_max_insert_block_rows(&self) -> usize {
+        if self.max_insert_block_rows == 0 {
+            1
+        } else {
+            self.max_insert_block_rows
+        }
+    }
+
+    /// Get directory, whose files `IMPORT INTO` may read. `None` means `IMPORT INTO` is
+    /// rejected.
+    pub const fn get_import_directory(&self) -> Option<&PathBuf> {
+        self.import_directory.as_ref()
+    }
+
+    /// Get max bytes of part files of the database. `None` means no quota.
+    pub fn get_database_quota(&self, database: &str) -> Option<u64> {
+        self.quotas.get(database).copied()
+    }
+
+    /// Get access control of client addresses.
+    pub const fn get_access(&self) -> &AccessConfig {
+        &self.access
+    }
+
     /// Ensures that directory exists and is indeed directory. Creates one, if not exists
     ///
     /// # Panics:
@@ -125,4 +506,19 @@ impl Config {
 
         raw_config
     }
+
+    /// Builds the default configuration (see `DEFAULT_CONFIG_STR`) with another storage
+    /// directory, for instances opened in process, see `Instance::open`.
+    ///
+    /// # Panics:
+    ///
+    /// When storage directory could not be created, see `ensure_directory_exists`.
+    pub fn with_storage_directory(storage_directory: PathBuf) -> Self {
+        let mut config: Self = toml::from_str(DEFAULT_CONFIG_STR).expect("Invalid default config");
+        config.storage_directory = storage_directory;
+
+        Self::ensure_directory_exists(&config.storage_directory);
+
+        config
+    }
 }
@@ -0,0 +1,306 @@
+use crate::access::AccessLevel;
+use crate::error::Error;
+use crate::runtime_config::{RUNNING_QUERIES, RunningQueryGuard};
+use crate::sql::CommandRunner;
+use crate::storage::OutputTable;
+use crate::tcp_io_parser::Parser;
+
+use futures::{SinkExt as _, StreamExt as _};
+use log::{error, info};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::codec::Decoder as _;
+use uuid::Uuid;
+
+/// Commands, which control the connection itself and never reach SQL layer.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConnectionCommand {
+    /// `exit`, `quit` or `\q`. Acknowledges with OK and closes the connection.
+    Exit,
+    /// `RESET`. Clears session state of the connection.
+    Reset,
+    /// `PING`. Acknowledges with OK, used by clients to check that connection is alive.
+    Ping,
+    /// `KILL QUERY <id>`, where id is UUID, optionally in single quotes.
+    KillQuery(Uuid),
+}
+
+impl ConnectionCommand {
+    /// Tries to recognize connection-control command.
+    /// Commands are case-insensitive and may end with semicolon and whitespace.
+    ///
+    /// Returns:
+    ///   * Some: recognized `ConnectionCommand`.
+    ///   * None: command should be handled by SQL layer.
+    pub fn parse(command: &str) -> Option<Self> {
+        let command = command
+            .trim_end_matches(|ch: char| ch == ';' || ch.is_whitespace())
+            .trim_start();
+        let mut words = command.split_whitespace();
+        let first = words.next()?.to_lowercase();
+
+        match (first.as_str(), words.next(), words.next(), words.next()) {
+            ("exit" | "quit" | "\\q", None, None, None) => Some(Self::Exit),
+            ("reset", None, None, None) => Some(Self::Reset),
+            ("ping", None, None, None) => Some(Self::Ping),
+            ("kill", Some(modifier), Some(id), None) if modifier.eq_ignore_ascii_case("query") => {
+                Uuid::parse_str(id.trim_matches('\''))
+                    .ok()
+                    .map(Self::KillQuery)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Serves a single client: decodes SQL commands, executes them and sends back results.
+///
+/// Each SQL statement gets a query id (UUID v7), registered in `RUNNING_QUERIES` while it
+/// executes. The id is added to log lines of the statement and returned with its output.
+/// When client sends nothing for `idle_timeout`, it receives `IdleTimeout` error and the
+/// connection is closed. `None` disables the timeout. SQL commands are executed with `access`
+/// level of the client.
+///
+/// Returns:
+///   * Ok: when client closed the connection, sent `exit` or was idle for too long.
+///   * Error: `SendResponse` when response could not be sent.
+pub async fn handle_connection<S>(
+    socket: S,
+    max_frame_size: usize,
+    idle_timeout: Option<Duration>,
+    access: AccessLevel,
+) -> Result<(), Error>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    // using tokio_util `Decoder, Encoder` traits to receive and send bytes
+    // link: https://docs.rs/tokio-util/latest/tokio_util/codec/index.html
+    let mut transport = Parser::new(max_frame_size).framed(socket);
+
+    loop {
+        let next_command = match idle_timeout {
+            Some(idle_timeout) => {
+                match tokio::time::timeout(idle_timeout, transport.next()).await {
+                    Ok(next_command) => next_command,
+                    Err(_elapsed) => {
+                        info!("Closing idle connection.");
+                        let error = Error::IdleTimeout(idle_timeout.as_secs());
+                        if let Err(send_error) = transport.send(Err(error)).await {
+                            error!("Failed to send response: {send_error}");
+                            return Err(Error::SendResponse);
+                        }
+                        break;
+                    }
+                }
+            }
+            None => transport.next().await,
+        };
+        let Some(sql_command) = next_command else {
+            break;
+        };
+
+        let Ok(value) = sql_command else {
+            // after a decoding error (e.g., frame too large) the stream ends, closing the connection
+            let error = sql_command.unwrap_err();
+            if let Err(send_error) = transport.send(Err(error)).await {
+                error!("Failed to send response: {send_error}");
+                return Err(Error::SendResponse);
+            }
+            continue;
+        };
+
+        let output = match ConnectionCommand::parse(&value) {
+            Some(ConnectionCommand::Exit) => {
+                if let Err(send_error) = transport
+                    .send(Ok::<_, Error>(OutputTable::build_ok()))
+                    .await
+                {
+                    error!("Failed to send response: {send_error}");
+                    return Err(Error::SendResponse);
+                }
+                break;
+            }
+            // no session state (e.g., `USE db`) is kept yet, so there is nothing to clear
+            Some(ConnectionCommand::Reset | ConnectionCommand::Ping) => Ok(OutputTable::build_ok()),
+            Some(ConnectionCommand::KillQuery(query_id)) => {
+                if RUNNING_QUERIES.contains_key(&query_id) {
+                    Err(Error::UnsupportedCommand(
+                        "Cancelling running queries is not supported yet".to_string(),
+                    ))
+                } else {
+                    Err(Error::QueryNotFound(query_id))
+                }
+            }
+            None => execute_sql(value, access, Uuid::now_v7()).await,
+        };
+
+        if let Err(send_error) = transport.send(output).await {
+            error!("Failed to send response: {send_error}");
+            return Err(Error::SendResponse);
+        }
+    }
+    info!("Connection closed.");
+    Ok(())
+}
+
+/// Executes command on the blocking pool. Time, spent waiting for a free thread, is returned as
+/// `queue_time`, while `execution_time` is measured by `CommandRunner::execute_command`.
+async fn execute_sql(
+    sql: String,
+    access: AccessLevel,
+    query_id: Uuid,
+) -> Result<OutputTable, Error> {
+    let queued_at = std::time::Instant::now();
+    tokio::task::spawn_blocking(move || {
+        let queue_time = queued_at.elapsed();
+        let _guard = RunningQueryGuard::new(query_id, sql.clone());
+
+        CommandRunner::execute_command(&sql, access, query_id)
+            .map(|output_table| output_table.with_queue_time(queue_time))
+    })
+    .await
+    .unwrap_or_else(|error| {
+        error!(query_id:% = query_id; "SQL task panicked: {error}");
+        Err(Error::Internal(
+            "Internal error during query execution".to_string(),
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::de::IgnoredAny;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+    const MAX_FRAME_SIZE: usize = 1024;
+
+    async fn send(client: &mut DuplexStream, command: &str) {
+        client
+            .write_all(&(command.len() as u64).to_le_bytes())
+            .await
+            .unwrap();
+        client.write_all(command.as_bytes()).await.unwrap();
+    }
+
+    async fn receive(client: &mut DuplexStream) -> Result<IgnoredAny, String> {
+        let mut header = [0; 8];
+        client.read_exact(&mut header).await.unwrap();
+        let mut body = vec![0; u64::from_le_bytes(header) as usize];
+        client.read_exact(&mut body).await.unwrap();
+
+        rmp_serde::from_slice(&body).unwrap()
+    }
+
+    async fn assert_closed(client: &mut DuplexStream) {
+        let mut buf = [0; 1];
+        assert_eq!(client.read(&mut buf).await.unwrap(), 0);
+    }
+
+    #[test]
+    fn test_parse_connection_commands() {
+        for command in ["exit", "EXIT", " Exit ;", "quit;", "QUIT \n", "\\q", "\\Q;"] {
+            assert_eq!(
+                ConnectionCommand::parse(command),
+                Some(ConnectionCommand::Exit),
+                "{command}"
+            );
+        }
+        assert_eq!(
+            ConnectionCommand::parse("reset;"),
+            Some(ConnectionCommand::Reset)
+        );
+        assert_eq!(
+            ConnectionCommand::parse("Ping"),
+            Some(ConnectionCommand::Ping)
+        );
+        let query_id = Uuid::parse_str("0198d5a2-7b1c-7e4a-9f3b-2c6d8e0a1b2c").unwrap();
+        for command in [
+            "KILL QUERY 0198d5a2-7b1c-7e4a-9f3b-2c6d8e0a1b2c;",
+            "kill query '0198D5A2-7B1C-7E4A-9F3B-2C6D8E0A1B2C'",
+        ] {
+            assert_eq!(
+                ConnectionCommand::parse(command),
+                Some(ConnectionCommand::KillQuery(query_id)),
+                "{command}"
+            );
+        }
+
+        for command in [
+            "exit now",
+            "SELECT exit FROM db.t",
+            "kill query abc",
+            "KILL QUERY 42",
+            "",
+        ] {
+            assert_eq!(ConnectionCommand::parse(command), None, "{command}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_exit_is_acknowledged() {
+        for command in ["exit", "EXIT;", "  Quit ; ", "\\q"] {
+            let (mut client, server) = tokio::io::duplex(MAX_FRAME_SIZE);
+            let connection = tokio::spawn(handle_connection(
+                server,
+                MAX_FRAME_SIZE,
+                None,
+                AccessLevel::ReadWrite,
+            ));
+
+            send(&mut client, command).await;
+            assert!(receive(&mut client).await.is_ok(), "{command}");
+            assert_closed(&mut client).await;
+            assert!(connection.await.unwrap().is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_connection_stays_open_after_control_commands() {
+        let (mut client, server) = tokio::io::duplex(MAX_FRAME_SIZE);
+        let connection = tokio::spawn(handle_connection(
+            server,
+            MAX_FRAME_SIZE,
+            None,
+            AccessLevel::ReadWrite,
+        ));
+
+        send(&mut client, "RESET;").await;
+        assert!(receive(&mut client).await.is_ok());
+
+        send(&mut client, "PING").await;
+        assert!(receive(&mut client).await.is_ok());
+
+        send(
+            &mut client,
+            "KILL QUERY 00000000-0000-0000-0000-000000000000",
+        )
+        .await;
+        let error = receive(&mut client).await.unwrap_err();
+        assert!(error.contains("Query not found"), "{error}");
+
+        send(&mut client, "exit").await;
+        assert!(receive(&mut client).await.is_ok());
+        assert_closed(&mut client).await;
+        assert!(connection.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_idle_connection_is_closed() {
+        let (mut client, server) = tokio::io::duplex(MAX_FRAME_SIZE);
+        let connection = tokio::spawn(handle_connection(
+            server,
+            MAX_FRAME_SIZE,
+            Some(Duration::from_millis(50)),
+            AccessLevel::ReadWrite,
+        ));
+
+        send(&mut client, "RESET;").await;
+        assert!(receive(&mut client).await.is_ok());
+
+        let error = receive(&mut client).await.unwrap_err();
+        assert!(error.contains("inactivity"), "{error}");
+        assert_closed(&mut client).await;
+        assert!(connection.await.unwrap().is_ok());
+    }
+}
@@ -1,6 +1,6 @@
-use crate::engines::{Engine, EngineConfig};
+use crate::engines::{Engine, EngineConfig, compare_key_values};
 use crate::error::{Error, Result};
-use crate::storage::{Column, ColumnDef, Value};
+use crate::storage::{Column, ColumnData, ColumnDef, Value};
 
 use std::cmp::Ordering;
 
@@ -52,7 +52,10 @@ impl Engine for MergeTreeEngine {
             else {
                 return Err(Error::InvalidColumnsSpecified);
             };
-            order_by_indices.push(idx);
+            // constant column has the same value in every row, so it never affects order
+            if let ColumnData::Dense(_) = columns[idx].data {
+                order_by_indices.push(idx);
+            }
         }
 
         let mut indices: Vec<usize> = (0..row_count).collect();
@@ -62,9 +65,7 @@ impl Engine for MergeTreeEngine {
                 let col_a = &columns[col_idx].data[a];
                 let col_b = &columns[col_idx].data[b];
 
-                let cmp = col_a
-                    .partial_cmp(col_b)
-                    .expect("Values in the same column are of the same type and ARE comparable");
+                let cmp = compare_key_values(col_a, col_b);
 
                 if cmp != Ordering::Equal {
                     return cmp;
@@ -74,7 +75,9 @@ impl Engine for MergeTreeEngine {
         });
 
         for column in &mut columns {
-            apply_permutation_in_place(&mut column.data, &indices);
+            if let ColumnData::Dense(values) = &mut column.data {
+                apply_permutation_in_place(values, &indices);
+            }
         }
 
         Ok(columns)
@@ -109,16 +112,16 @@ mod tests {
 
     macro_rules! value {
         (S $x:literal) => {
-            vec![Value::String($x.to_string())]
+            ColumnData::from(vec![Value::String($x.to_string())])
         };
         (I $x:literal) => {
-            vec![Value::Int32(i32::from($x))]
+            ColumnData::from(vec![Value::Int32(i32::from($x))])
         };
         (S $($x:literal),*) => {
-            vec![$(Value::String($x.to_string())),*]
+            ColumnData::from(vec![$(Value::String($x.to_string())),*])
         };
         (I $($x:literal),*) => {
-            vec![$(Value::Int32(i32::from($x))),*]
+            ColumnData::from(vec![$(Value::Int32(i32::from($x))),*])
         }
     }
 
@@ -184,6 +187,25 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_null_keys_are_last() {
+        let engine = MergeTreeEngine::new(EngineConfig::default());
+        let columns = vec![Column {
+            column_def: int_col_def(),
+            data: vec![Value::Null, Value::Int32(2), Value::Null, Value::Int32(1)].into(),
+        }];
+
+        assert_eq!(
+            engine
+                .order_columns(columns, &[int_col_def()], &[int_col_def()])
+                .unwrap()[0]
+                .data
+                .clone()
+                .into_values(),
+            [Value::Int32(1), Value::Int32(2), Value::Null, Value::Null]
+        );
+    }
+
     #[test]
     fn test_single_row_multiple_column() {
         let engine = MergeTreeEngine::new(EngineConfig::default());
@@ -6,9 +6,13 @@ use crate::engines::replacing_merge_tree::ReplacingMergeTreeEngine;
 use crate::error::{Error, Result};
 use crate::storage::Column;
 use crate::storage::ColumnDef;
+use crate::storage::Value;
 
+use derive_more::Display;
 use rkyv::{Archive as RkyvArchive, Deserialize as RkyvDeserialize, Serialize as RkyvSerialize};
 
+use std::cmp::Ordering;
+
 /// Interface for every engine to follow.
 pub trait Engine {
     /// Orders columns for insert by `order_by`.
@@ -20,8 +24,22 @@ pub trait Engine {
     ) -> Result<Vec<Column>>;
 }
 
+/// Compares values of a sort key column. `NULL` is greater than any other value, so rows with
+/// `NULL` keys are ordered last (possible only with `allow_nullable_key`). Other values, which
+/// are not comparable, are equal.
+pub(crate) fn compare_key_values(left: &Value, right: &Value) -> Ordering {
+    match (left, right) {
+        (Value::Null, Value::Null) => Ordering::Equal,
+        (Value::Null, _) => Ordering::Greater,
+        (_, Value::Null) => Ordering::Less,
+        _ => left.partial_cmp(right).unwrap_or(Ordering::Equal),
+    }
+}
+
 /// Used for storing engine name in metadata.
-#[derive(Debug, Eq, Hash, PartialEq, Clone, RkyvSerialize, RkyvArchive, RkyvDeserialize)]
+#[derive(
+    Debug, Display, Eq, Hash, PartialEq, Clone, RkyvSerialize, RkyvArchive, RkyvDeserialize,
+)]
 pub enum EngineName {
     MergeTree,
     ReplacingMergeTree,
@@ -33,7 +51,7 @@ impl TryFrom<&str> for EngineName {
         match value {
             "MergeTree" => Ok(Self::MergeTree),
             "ReplacingMergeTree" => Ok(Self::ReplacingMergeTree),
-            _ => Err(Error::InvalidEngineName),
+            _ => Err(Error::InvalidEngineName(value.to_string())),
         }
     }
 }
@@ -43,6 +61,22 @@ impl TryFrom<&str> for EngineName {
 pub struct EngineConfig {}
 
 impl EngineName {
+    /// All supported engines, in the order they are listed by `SHOW ENGINES`. Engine, which is
+    /// missing here, can still be used by `CREATE TABLE`, but is not listed.
+    pub const ALL: [EngineName; 2] = [EngineName::MergeTree, EngineName::ReplacingMergeTree];
+
+    /// Describes how the engine treats rows, for `SHOW ENGINES`.
+    ///
+    /// Returns: single line description, which starts with a capital letter and has no period.
+    pub const fn description(&self) -> &'static str {
+        match self {
+            EngineName::MergeTree => "Stores rows sorted by ORDER BY, keeps every inserted row",
+            EngineName::ReplacingMergeTree => {
+                "Stores rows sorted by ORDER BY, keeps the last inserted row of each primary key"
+            }
+        }
+    }
+
     /// Returns engine implementation for the given engine name.
     pub fn get_engine(&self, config: EngineConfig) -> Box<dyn Engine> {
         match self {
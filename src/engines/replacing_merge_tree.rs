@@ -1,6 +1,6 @@
-use crate::engines::{Engine, EngineConfig};
+use crate::engines::{Engine, EngineConfig, compare_key_values};
 use crate::error::{Error, Result};
-use crate::storage::{Column, ColumnDef};
+use crate::storage::{Column, ColumnData, ColumnDef};
 use std::cmp::Ordering;
 
 /// Engine for editing rows. Sorts values in ASC order.
@@ -52,9 +52,20 @@ impl Engine for ReplacingMergeTreeEngine {
             return Err(Error::NoColumnsSpecified);
         };
 
+        // constant columns have the same value in every row, so they are neither compared nor
+        // moved, only truncated to the number of remaining rows
+        let dense_idxs: Vec<_> = (0..columns.len())
+            .filter(|&idx| matches!(columns[idx].data, ColumnData::Dense(_)))
+            .collect();
+        let row_position = |col_def: &ColumnDef| {
+            dense_idxs
+                .iter()
+                .position(|&idx| columns[idx].column_def == *col_def)
+        };
+
         let mut order_by_indexes = Vec::new();
         for col_def in order_by {
-            let Some(position) = columns.iter().position(|col| &col.column_def == col_def) else {
+            let Some(position) = row_position(col_def) else {
                 continue;
             };
             order_by_indexes.push(position);
@@ -62,17 +73,17 @@ impl Engine for ReplacingMergeTreeEngine {
 
         let mut pk_indexes = Vec::new();
         for col_def in primary_key {
-            let Some(position) = columns.iter().position(|col| &col.column_def == col_def) else {
+            let Some(position) = row_position(col_def) else {
                 continue;
             };
             pk_indexes.push(position);
         }
 
         let mut data_in_row_format: Vec<Vec<_>> = (0..total_rows)
-            .map(|_| Vec::with_capacity(columns.len()))
+            .map(|_| Vec::with_capacity(dense_idxs.len()))
             .collect();
-        for col in &mut columns {
-            for (idx, value) in col.data.drain(..).enumerate() {
+        for &col_idx in &dense_idxs {
+            for (idx, value) in columns[col_idx].data.make_dense().drain(..).enumerate() {
                 data_in_row_format[idx].push(value);
             }
         }
@@ -82,9 +93,7 @@ impl Engine for ReplacingMergeTreeEngine {
                 let col_a = &left_vec[order_by_idx];
                 let col_b = &right_vec[order_by_idx];
 
-                let cmp = col_a
-                    .partial_cmp(col_b)
-                    .expect("Values in the same column are of the same type and ARE comparable");
+                let cmp = compare_key_values(col_a, col_b);
 
                 if cmp != Ordering::Equal {
                     return cmp;
@@ -99,9 +108,12 @@ impl Engine for ReplacingMergeTreeEngine {
 
         data_in_row_format.reverse();
 
+        for column in &mut columns {
+            column.data.truncate(data_in_row_format.len());
+        }
         for row in data_in_row_format {
-            for (column, value) in columns.iter_mut().zip(row) {
-                column.data.push(value);
+            for (&col_idx, value) in dense_idxs.iter().zip(row) {
+                columns[col_idx].data.push(value);
             }
         }
 
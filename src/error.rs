@@ -1,5 +1,6 @@
 use derive_more::Display;
 use serde::Serialize;
+use uuid::Uuid;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -13,12 +14,16 @@ pub enum Error {
     DatabaseNotFound,
     #[display("Table not found.")]
     TableNotFound,
+    #[display("Part not found: {_0}.")]
+    PartNotFound(String),
     #[display("Invalid database name.")]
     InvalidDatabaseName,
     #[display("Invalid column name: {_0}")]
     InvalidColumnName(String),
     #[display("Database already exists.")]
     DatabaseAlreadyExists,
+    #[display("Database name {_0} is reserved.")]
+    ReservedDatabaseName(String),
     #[display("Table already exists.")]
     TableAlreadyExists,
 
@@ -27,10 +32,12 @@ pub enum Error {
     SqlToAstConversion(String),
     #[display("Unsupported command: {_0}.")]
     UnsupportedCommand(String),
+    #[display("Recursive CTE is not supported: {_0}.")]
+    RecursiveCte(String),
     #[display("Unsupported column name: {_0}.")]
     UnsupportedColumnType(String),
-    #[display("Invalid engine name.")]
-    InvalidEngineName,
+    #[display("Invalid engine name: {_0}.")]
+    InvalidEngineName(String),
     #[display("Unsupported table option: {_0}")]
     UnsupportedTableOption(String),
     #[display("Invalid ORDER BY.")]
@@ -39,8 +46,8 @@ pub enum Error {
     InvalidPrimaryKey(String),
     #[display("Invalid pair of ORDER BY and PRIMARY KEY. PRIMARY KEY should be prefix of ORDER BY")]
     InvalidOrderByPrimaryKeyPair,
-    #[display("Invalid table name.")]
-    InvalidTableName,
+    #[display("Invalid table name: {_0}")]
+    InvalidTableName(String),
     #[display("No columns specified.")]
     NoColumnsSpecified,
     #[display("Invalid columns specified.")]
@@ -57,8 +64,8 @@ pub enum Error {
     CouldNotCreateTable(String),
     #[display("No values provided")]
     EmptySource,
-    #[display("Permission denied")]
-    PermissionDenied,
+    #[display("Permission denied: {_0}.")]
+    PermissionDenied(String),
     #[display("Unsupported filter: {_0}")]
     UnsupportedFilter(String),
     #[display("Column not found: {_0}")]
@@ -69,6 +76,33 @@ pub enum Error {
     InvalidLimitValue(String),
     #[display("Invalid number of params specified: {_0}")]
     InvalidNumberOfParamsSpecified(String),
+    #[display("Invalid tuple access: {_0}")]
+    InvalidTupleAccess(String),
+    #[display("Unsupported function: {_0}")]
+    UnsupportedFunction(String),
+    #[display("Invalid function arguments: {_0}")]
+    InvalidFunctionArguments(String),
+    #[display("Server is read-only: {_0} is not allowed.")]
+    ReadOnly(String),
+    #[display("Query is too complex: complexity {_0} is above max_query_complexity.")]
+    QueryTooComplex(u32),
+    #[display("Table {_0} is read-only.")]
+    TableReadOnly(String),
+    #[display("Merges of table {_0} are stopped by no_merges.")]
+    MergesStopped(String),
+    #[display("Query uses {used} bytes of memory, above max_memory_per_query of {limit} bytes.")]
+    MemoryLimitExceeded {
+        used: usize,
+        limit: usize,
+    },
+    #[display(
+        "Too many parts ({_1}) in table {_0}, max_parts_per_table is {_2}. Merges are processing significantly slower than inserts."
+    )]
+    TooManyParts(String, usize, usize),
+    #[display("Not enough disk space: {_0}.")]
+    DiskFull(String),
+    #[display("Database {_0} uses {_1} bytes, insert would exceed its quota of {_2} bytes.")]
+    QuotaExceeded(String, u64, u64),
 
     // mod engines
     #[display("No ORDER BY columns found")]
@@ -76,5 +110,23 @@ pub enum Error {
 
     // mod main
     SendResponse, // does not need display
+    #[display("Query not found: {_0}")]
+    QueryNotFound(Uuid),
+    #[display("Connection closed after {_0} seconds of inactivity")]
+    IdleTimeout(u64),
     Internal(String),
 }
+
+impl Error {
+    /// Prefixes `CouldNotReadData` message with `context`, e.g., table, part and column names.
+    /// Other errors are returned unchanged.
+    #[must_use]
+    pub fn with_read_context(self, context: impl std::fmt::Display) -> Self {
+        match self {
+            Self::CouldNotReadData(message) => {
+                Self::CouldNotReadData(format!("{context}: {message}"))
+            }
+            error => error,
+        }
+    }
+}
@@ -0,0 +1,15 @@
+//! TouchHouse server internals, shared by the `touchhouse` binary and benchmarks.
+
+pub mod access;
+pub mod background_merge;
+pub mod config;
+pub mod connection;
+pub mod engines;
+pub mod error;
+pub mod logging;
+pub mod runtime_config;
+pub mod sql;
+pub mod storage;
+pub mod tcp_io_parser;
+
+pub use crate::config::CONFIG;
@@ -0,0 +1,205 @@
+use crate::config::LogFormat;
+
+use log::kv::{self, Key, Source, Value, VisitSource};
+use log::{Log, Metadata, Record};
+use serde_json::Value as JsonValue;
+use std::cell::Cell;
+use std::io::Write as _;
+use uuid::Uuid;
+
+thread_local! {
+    /// Id of the query, which the thread executes.
+    static QUERY_ID: Cell<Option<Uuid>> = const { Cell::new(None) };
+}
+
+/// Sets id of the query, which current thread executes, until dropped. Log lines of the thread
+/// get `query_id` field.
+pub struct QueryIdGuard {
+    previous: Option<Uuid>,
+}
+
+impl QueryIdGuard {
+    pub fn enter(query_id: Uuid) -> Self {
+        Self {
+            previous: QUERY_ID.replace(Some(query_id)),
+        }
+    }
+}
+
+impl Drop for QueryIdGuard {
+    fn drop(&mut self) {
+        QUERY_ID.set(self.previous);
+    }
+}
+
+/// Returns id of the query, which current thread executes (see `QueryIdGuard`).
+pub fn current_query_id() -> Option<Uuid> {
+    QUERY_ID.get()
+}
+
+/// Initializes global logger of the server with `level` and `format`. Lines, logged while thread
+/// executes a query, get its `query_id` field. Filters of `RUST_LOG` environment variable are
+/// applied on top of `level` (e.g., `RUST_LOG=touchhouse::storage=trace`).
+///
+/// # Panics:
+///
+/// When logger is already initialized.
+pub fn init(level: log::LevelFilter, format: LogFormat) {
+    let mut builder = env_logger::Builder::from_default_env();
+    builder.filter_level(level);
+    if format == LogFormat::Json {
+        builder.format(|buf, record| {
+            let line = json_line(record, &buf.timestamp().to_string());
+            writeln!(buf, "{line}")
+        });
+    }
+    let logger = builder.build();
+    log::set_max_level(logger.filter());
+    log::set_boxed_logger(Box::new(QueryIdLogger(logger))).expect("Logger is already initialized");
+}
+
+/// Adds `query_id` of the thread (see `QueryIdGuard`) to fields of every record.
+struct QueryIdLogger(env_logger::Logger);
+
+impl Log for QueryIdLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.0.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        match current_query_id() {
+            Some(query_id) => {
+                let fields = WithQueryId {
+                    fields: record.key_values(),
+                    query_id,
+                };
+                self.0.log(&record.to_builder().key_values(&fields).build());
+            }
+            None => self.0.log(record),
+        }
+    }
+
+    fn flush(&self) {
+        self.0.flush();
+    }
+}
+
+/// Fields of the record followed by `query_id`.
+struct WithQueryId<'a> {
+    fields: &'a dyn Source,
+    query_id: Uuid,
+}
+
+impl Source for WithQueryId<'_> {
+    fn visit<'kvs>(&'kvs self, visitor: &mut dyn VisitSource<'kvs>) -> Result<(), kv::Error> {
+        self.fields.visit(visitor)?;
+        visitor.visit_pair(Key::from("query_id"), Value::from_display(&self.query_id))
+    }
+}
+
+/// Formats record as a single line JSON object: `timestamp`, `level`, `target`, `message` and
+/// context fields of the record (e.g., `table`, `part`), in this order. Numbers and booleans of
+/// context fields stay JSON numbers and booleans, other values are written as strings. Context
+/// field does not replace a field with the same name.
+///
+/// Returns: JSON object without trailing newline, so it can be written with `writeln!`.
+pub fn json_line(record: &log::Record, timestamp: &str) -> String {
+    let mut fields = vec![
+        ("timestamp".to_string(), JsonValue::from(timestamp)),
+        ("level".to_string(), record.level().as_str().into()),
+        ("target".to_string(), record.target().into()),
+        ("message".to_string(), record.args().to_string().into()),
+    ];
+    // visitor never fails
+    let _ = record.key_values().visit(&mut FieldVisitor(&mut fields));
+
+    let fields: Vec<_> = fields
+        .into_iter()
+        .map(|(key, value)| format!("{}:{value}", JsonValue::from(key)))
+        .collect();
+    format!("{{{}}}", fields.join(","))
+}
+
+/// Collects context fields of the record.
+struct FieldVisitor<'a>(&'a mut Vec<(String, JsonValue)>);
+
+impl<'kvs> VisitSource<'kvs> for FieldVisitor<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        let value = if let Some(value) = value.to_bool() {
+            value.into()
+        } else if let Some(value) = value.to_u64() {
+            value.into()
+        } else if let Some(value) = value.to_i64() {
+            value.into()
+        } else {
+            value.to_string().into()
+        };
+        if !self.0.iter().any(|(name, _)| name == key.as_str()) {
+            self.0.push((key.as_str().to_string(), value));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_line() {
+        let fields: [(&str, Value); 4] = [
+            ("table", Value::from("db.events")),
+            ("rows", Value::from(42_u64)),
+            ("merged", Value::from(true)),
+            ("message", Value::from("ignored")),
+        ];
+        let line = json_line(
+            &log::Record::builder()
+                .args(format_args!("Merged \"parts\"\nof table"))
+                .level(log::Level::Warn)
+                .target("touchhouse::background_merge")
+                .key_values(&fields)
+                .build(),
+            "2025-01-01T00:00:00Z",
+        );
+
+        assert_eq!(
+            line,
+            r#"{"timestamp":"2025-01-01T00:00:00Z","level":"WARN","target":"touchhouse::background_merge","message":"Merged \"parts\"\nof table","table":"db.events","rows":42,"merged":true}"#
+        );
+        assert!(!line.contains('\n'));
+    }
+
+    #[test]
+    fn test_query_id_is_added_to_fields() {
+        let query_id = Uuid::now_v7();
+        let fields = [("table", "db.events")];
+        let fields = WithQueryId {
+            fields: &fields,
+            query_id,
+        };
+        let line = json_line(
+            &log::Record::builder()
+                .args(format_args!("Slow query"))
+                .level(log::Level::Warn)
+                .key_values(&fields)
+                .build(),
+            "2025-01-01T00:00:00Z",
+        );
+        assert!(
+            line.ends_with(&format!(r#""table":"db.events","query_id":"{query_id}"}}"#)),
+            "{line}"
+        );
+
+        assert_eq!(current_query_id(), None);
+        {
+            let _outer = QueryIdGuard::enter(query_id);
+            {
+                let _inner = QueryIdGuard::enter(Uuid::nil());
+                assert_eq!(current_query_id(), Some(Uuid::nil()));
+            }
+            assert_eq!(current_query_id(), Some(query_id));
+        }
+        assert_eq!(current_query_id(), None);
+    }
+}
@@ -1,38 +1,67 @@
-mod background_merge;
-mod config;
-mod engines;
-mod error;
-mod runtime_config;
-mod sql;
-mod storage;
-mod tcp_io_parser;
+use touchhouse::CONFIG;
+use touchhouse::background_merge::BackgroundMerge;
+use touchhouse::connection::handle_connection;
+use touchhouse::logging;
+use touchhouse::storage;
 
-use crate::background_merge::BackgroundMerge;
-use crate::config::CONFIG;
-use crate::error::Error;
-use crate::sql::CommandRunner;
-use crate::tcp_io_parser::Parser;
-
-use futures::{SinkExt as _, StreamExt as _};
 use log::{error, info};
 use std::sync::Arc;
-use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::Semaphore;
-use tokio_util::codec::Decoder as _;
+use tokio::net::TcpListener;
+use tokio::sync::{Semaphore, watch};
 
 #[tokio::main]
 async fn main() -> Result<(), String> {
-    env_logger::Builder::from_default_env()
-        .filter_level(CONFIG.get_log_level())
-        .init();
+    logging::init(CONFIG.get_log_level(), CONFIG.get_log_format());
 
     storage::load_all_parts_on_startup(CONFIG.get_db_dir())
         .map_err(|error| format!("Failed to load parts on startup: {error:?}"))?;
 
-    std::thread::spawn(|| {
-        BackgroundMerge::start();
-    });
+    let (shutdown_tx, _) = watch::channel(false);
+    let merges_stopped = BackgroundMerge::start(&shutdown_tx)
+        .map_err(|error| format!("Failed to start background merges: {error}"))?;
+
+    if CONFIG.is_read_only() {
+        tokio::spawn(rescan_storage());
+    }
+
+    let result = tokio::select! {
+        result = serve() => result,
+        signal = tokio::signal::ctrl_c() => match signal {
+            Ok(()) => {
+                info!("Received Ctrl-C, shutting down");
+                Ok(())
+            }
+            Err(error) => Err(format!("Failed to listen for Ctrl-C: {error}")),
+        },
+    };
+
+    // let running merges finish, so parts are not left half-moved
+    shutdown_tx.send_replace(true);
+    for stopped in merges_stopped {
+        let _ = stopped.await;
+    }
+    result
+}
+
+/// Picks up tables and parts, written to the storage directory by another server.
+async fn rescan_storage() {
+    let mut interval = tokio::time::interval(CONFIG.get_rescan_interval());
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    interval.tick().await; // first tick completes immediately, right after startup loading
+
+    loop {
+        interval.tick().await;
+        let rescan = tokio::task::spawn_blocking(|| storage::rescan_parts(CONFIG.get_db_dir()));
+        match rescan.await {
+            Ok(Ok(())) => {}
+            Ok(Err(error)) => error!("Failed to rescan storage directory: {error:?}"),
+            Err(error) => error!("Storage rescan panicked: {error}"),
+        }
+    }
+}
 
+/// Accepts connections until listener fails.
+async fn serve() -> Result<(), String> {
     let max_conn = Arc::new(Semaphore::new(CONFIG.get_max_connections()));
 
     let listener = TcpListener::bind(&CONFIG.get_tcp_socket_addr())
@@ -47,6 +76,9 @@ async fn main() -> Result<(), String> {
     info!("TCP server listening on {}", CONFIG.get_tcp_socket_addr());
     info!("Database directory: {}", CONFIG.get_db_dir().display());
     info!("Log level: {:?}", CONFIG.get_log_level());
+    if CONFIG.is_read_only() {
+        info!("Read-only mode: writes are rejected");
+    }
 
     loop {
         let Ok(connection_permit) = Arc::clone(&max_conn).acquire_owned().await else {
@@ -54,12 +86,19 @@ async fn main() -> Result<(), String> {
             return Err("Semaphore closed unexpectedly.".to_string());
         };
         match listener.accept().await {
-            Ok((mut socket, addr)) => {
+            Ok((socket, addr)) => {
                 tokio::spawn(async move {
-                    if handle_connection(&mut socket).await.is_err() {
+                    if handle_connection(
+                        socket,
+                        CONFIG.get_max_frame_size(),
+                        CONFIG.get_idle_timeout(),
+                        CONFIG.get_access().access_level(addr.ip()),
+                    )
+                    .await
+                    .is_err()
+                    {
                         error!("Could not send to {addr}. Closing connection.");
                     }
-                    drop(socket);
                     drop(connection_permit);
                 });
             }
@@ -67,46 +106,3 @@ async fn main() -> Result<(), String> {
         }
     }
 }
-
-async fn handle_connection(socket: &mut TcpStream) -> Result<(), Error> {
-    // using tokio_util `Decoder, Encoder` traits to receive and send bytes
-    // link: https://docs.rs/tokio-util/latest/tokio_util/codec/index.html
-    let mut transport = Parser.framed(socket);
-
-    while let Some(sql_command) = transport.next().await {
-        let Ok(value) = sql_command else {
-            let error = sql_command.unwrap_err();
-            if let Err(send_error) = transport.send(Err(error)).await {
-                error!("Failed to send response: {send_error}");
-                return Err(Error::SendResponse);
-            }
-            continue;
-        };
-
-        if value == "exit" {
-            break;
-        }
-
-        let output = tokio::task::spawn_blocking(move || {
-            let start = std::time::Instant::now();
-            let result = CommandRunner::execute_command(&value);
-            let elapsed = start.elapsed();
-
-            result.map(|output_table| output_table.with_execution_time(elapsed))
-        })
-        .await
-        .unwrap_or_else(|error| {
-            error!("SQL task panicked: {error}");
-            Err(Error::Internal(
-                "Internal error during query execution".to_string(),
-            ))
-        });
-
-        if let Err(send_error) = transport.send(output).await {
-            error!("Failed to send response: {send_error}");
-            return Err(Error::SendResponse);
-        }
-    }
-    info!("Connection closed.");
-    Ok(())
-}
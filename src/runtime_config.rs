@@ -1,7 +1,16 @@
 use dashmap::DashMap;
-use std::sync::atomic::AtomicU32;
+use std::cell::Cell;
+use std::ops::Deref;
+use std::path::PathBuf;
+use std::sync::LazyLock;
+use std::sync::atomic::{AtomicU32, AtomicU64};
+use uuid::Uuid;
 
-use crate::storage::{TableDef, TableMetadata, TablePartInfo};
+use crate::access::AccessLevel;
+use crate::config::Config;
+use crate::error::Result;
+use crate::sql::CommandRunner;
+use crate::storage::{self, OutputTable, TableDef, TableMetadata, TablePartInfo};
 
 #[derive(Debug, Clone)]
 pub struct TableConfig {
@@ -9,8 +18,122 @@ pub struct TableConfig {
     pub infos: Vec<TablePartInfo>,
 }
 
-pub static TABLE_DATA: std::sync::LazyLock<DashMap<TableDef, TableConfig>> =
-    std::sync::LazyLock::new(DashMap::default);
+impl TableConfig {
+    /// Returns number of rows in all active parts, summed up from their `row_count`. Rows of
+    /// `ReplacingMergeTree` parts, which are not merged yet, are counted with their duplicates.
+    pub fn total_rows(&self) -> u64 {
+        self.infos.iter().map(|info| info.row_count).sum()
+    }
+}
+
+/// Tables of a storage directory with their active parts.
+pub type TableData = DashMap<TableDef, TableConfig>;
+
+/// Engine instance: configuration and tables of its storage directory.
+///
+/// Server runs the default instance, built from the config file (see `Config::build`). Tests and
+/// embedding programs may open more instances in one process (see `Instance::open`), each on
+/// its own storage directory. Code runs against the current instance of its thread (see
+/// `Instance::enter`), which is what `CONFIG` and `TABLE_DATA` refer to.
+///
+/// Query load, running queries, sequence numbers of parts and background merges are shared by
+/// the whole process. Parts are selected for merges only in the default instance, while merges,
+/// requested by `OPTIMIZE TABLE`, run against the instance of the request.
+pub struct Instance {
+    config: Config,
+    table_data: TableData,
+}
+
+static DEFAULT_INSTANCE: LazyLock<Instance> = LazyLock::new(|| Instance {
+    config: Config::build(),
+    table_data: TableData::default(),
+});
+
+thread_local! {
+    /// Instance, entered by the thread. `None` means the default instance.
+    static CURRENT_INSTANCE: Cell<Option<&'static Instance>> = const { Cell::new(None) };
+}
+
+impl Instance {
+    /// Opens an instance on `storage_directory` with default configuration (see
+    /// `Config::with_storage_directory`), and loads its tables. Instance is kept until the
+    /// process exits, as the default one.
+    ///
+    /// Returns:
+    ///   * Ok: the instance.
+    ///   * Error: any error from `load_all_parts_on_startup`.
+    ///
+    /// # Panics:
+    ///
+    /// When storage directory could not be created, see `Config::with_storage_directory`.
+    pub fn open(storage_directory: impl Into<PathBuf>) -> Result<&'static Self> {
+        let instance: &'static Self = Box::leak(Box::new(Self {
+            config: Config::with_storage_directory(storage_directory.into()),
+            table_data: TableData::default(),
+        }));
+        instance.enter(|| storage::load_all_parts_on_startup(instance.config.get_db_dir()))?;
+        Ok(instance)
+    }
+
+    /// Returns the current instance of the thread, see `Instance::enter`.
+    pub fn current() -> &'static Self {
+        CURRENT_INSTANCE
+            .get()
+            .unwrap_or_else(|| LazyLock::force(&DEFAULT_INSTANCE))
+    }
+
+    /// Runs `f` with this instance as the current one of the thread. Threads, started by `f`,
+    /// run against the default instance, unless they enter this one.
+    pub fn enter<R>(&'static self, f: impl FnOnce() -> R) -> R {
+        let _restore = RestoreInstance(CURRENT_INSTANCE.replace(Some(self)));
+        f()
+    }
+
+    /// Executes SQL command against this instance with `readwrite` access, blocking the thread
+    /// until it completes, see `CommandRunner::execute_command`.
+    ///
+    /// Returns:
+    ///   * Ok: `OutputTable` of the command.
+    ///   * Error: any error from executing the command.
+    pub fn execute_command(&'static self, command: &str) -> Result<OutputTable> {
+        self.enter(|| {
+            CommandRunner::execute_command(command, AccessLevel::ReadWrite, Uuid::now_v7())
+        })
+    }
+
+    /// Returns configuration of the instance.
+    pub const fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Returns tables of the instance.
+    pub const fn table_data(&self) -> &TableData {
+        &self.table_data
+    }
+}
+
+/// Restores the previous current instance of the thread on drop.
+struct RestoreInstance(Option<&'static Instance>);
+
+impl Drop for RestoreInstance {
+    fn drop(&mut self) {
+        CURRENT_INSTANCE.set(self.0);
+    }
+}
+
+/// Tables of the current instance, see `Instance::current`.
+pub static TABLE_DATA: CurrentTableData = CurrentTableData(());
+
+/// Refers to `TableData` of the current instance.
+pub struct CurrentTableData(());
+
+impl Deref for CurrentTableData {
+    type Target = TableData;
+
+    fn deref(&self) -> &TableData {
+        Instance::current().table_data()
+    }
+}
 
 /// Signifies when it's ok to lock `TABLE_DATA` to merge `TablePart`
 pub static DATABASE_LOAD: std::sync::LazyLock<AtomicU32> =
@@ -35,3 +158,30 @@ impl Drop for ComplexityGuard {
         DATABASE_LOAD.fetch_sub(self.complexity, std::sync::atomic::Ordering::Relaxed);
     }
 }
+
+/// Source of sequence numbers of new table parts, see `TablePartInfo::sequence`.
+/// Set above the largest sequence of loaded parts on startup.
+pub static NEXT_PART_SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+/// SQL statements, which are currently executing, by their query id.
+pub static RUNNING_QUERIES: std::sync::LazyLock<DashMap<Uuid, String>> =
+    std::sync::LazyLock::new(DashMap::default);
+
+/// RAII guard that registers statement in `RUNNING_QUERIES` and removes it on drop.
+pub struct RunningQueryGuard {
+    query_id: Uuid,
+}
+
+impl RunningQueryGuard {
+    /// Registers the statement with `query_id` as running.
+    pub fn new(query_id: Uuid, sql: String) -> Self {
+        RUNNING_QUERIES.insert(query_id, sql);
+        Self { query_id }
+    }
+}
+
+impl Drop for RunningQueryGuard {
+    fn drop(&mut self) {
+        RUNNING_QUERIES.remove(&self.query_id);
+    }
+}
@@ -1,7 +1,21 @@
-use crate::error::Result;
+use crate::access::AccessLevel;
+use crate::config::CONFIG;
+use crate::error::{Error, Result};
+use crate::logging::QueryIdGuard;
 use crate::runtime_config::{ComplexityGuard, DATABASE_LOAD};
+use crate::sql::execution::memory::MemoryTracker;
 use crate::sql::sql_parser::{LogicalPlan, PhysicalPlan};
-use crate::storage::OutputTable;
+use crate::storage::{Column, ColumnDef, Constraints, OutputTable, Value, ValueType};
+
+use log::warn;
+use sqlparser::dialect::ClickHouseDialect;
+use sqlparser::keywords::Keyword;
+use sqlparser::tokenizer::{Token, Tokenizer};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Max number of characters of the statement in the slow query log.
+const SLOW_QUERY_LOG_MAX_CHARS: usize = 1000;
 
 /// Main runner struct which executes received command.
 #[derive(Debug)]
@@ -11,21 +25,116 @@ impl CommandRunner {
     /// Handles full command execution pipeline.
     ///
     /// Parses SQL, optimizes logical plan, converts to physical plan, and executes.
+    /// Command with `VALIDATE` prefix (e.g., `VALIDATE CREATE TABLE ...`) is planned, but not
+    /// executed (see `validate`). Command with `FORMAT PRETTY` suffix returns its output rendered
+    /// as text (see `OutputTable::to_pretty_string`) in a single `pretty` column and row.
+    /// `SELECT` with `EXPLAIN ANALYZE` prefix is executed, but returns timing of its stages
+    /// instead of rows (see `explain_analyze`).
+    ///
+    /// Wall-clock duration of planning and execution is set as `execution_time` of the output.
+    /// Command, running longer than `slow_query_ms`, is logged (see `log_slow_query`).
+    /// Memory of the command is tracked and limited by `max_memory_per_query` (see
+    /// `MemoryTracker`).
+    ///
+    /// Command, which requires higher access level than `access` of the client (see
+    /// `LogicalPlan::required_access`), is rejected.
+    ///
+    /// Log lines, written while command executes, get `query_id` field, which is also returned
+    /// with the output.
     ///
     /// Returns:
     ///   * Ok: `OutputTable` with query results or success status.
-    ///   * Error: Any error from parsing, optimization, or execution stages.
-    pub fn execute_command(command: &str) -> Result<OutputTable> {
+    ///   * Error: Any error from parsing, optimization, or execution stages, `PermissionDenied`
+    ///     when `access` is insufficient, `ReadOnly` when command modifies storage of
+    ///     read-only server, or `UnsupportedCommand` for `FORMAT` other than `PRETTY`.
+    pub fn execute_command(
+        command: &str,
+        access: AccessLevel,
+        query_id: Uuid,
+    ) -> Result<OutputTable> {
+        let _query_id_guard = QueryIdGuard::enter(query_id);
+        let memory = MemoryTracker::new(CONFIG.get_max_memory_per_query());
+        let _memory_guard = memory.enter();
+        let start = Instant::now();
+        let output = Self::plan_and_execute(command, access)?;
+        let execution_time = start.elapsed();
+
+        if let Some(threshold) = CONFIG.get_slow_query_threshold()
+            && execution_time > threshold
+        {
+            log_slow_query(command, execution_time, output.row_count(), memory.peak());
+        }
+
+        Ok(output
+            .with_execution_time(execution_time)
+            .with_query_id(query_id))
+    }
+
+    fn plan_and_execute(command: &str, access: AccessLevel) -> Result<OutputTable> {
+        if let Some((command, format)) = strip_format_suffix(command) {
+            if !format.eq_ignore_ascii_case("PRETTY") {
+                return Err(Error::UnsupportedCommand(format!(
+                    "FORMAT {format}, only FORMAT PRETTY is supported"
+                )));
+            }
+            let output = Self::plan_and_execute(&command, access)?;
+            return Ok(OutputTable::new(vec![Column {
+                column_def: ColumnDef {
+                    name: "pretty".to_string(),
+                    field_type: ValueType::String,
+                    constraints: Constraints::default(),
+                },
+                data: vec![Value::String(output.to_pretty_string())].into(),
+            }]));
+        }
+
+        let (command, validate_only) = match strip_validate_prefix(command) {
+            Some(command) => (command, true),
+            None => (command, false),
+        };
+        let (command, explain_analyze) = match strip_explain_analyze_prefix(command) {
+            Some(command) => (command, true),
+            None => (command, false),
+        };
+
+        let plan_start = Instant::now();
         let logical_plan = LogicalPlan::try_from(command)?;
 
+        let required_access = logical_plan.required_access();
+        if access < required_access {
+            return Err(Error::PermissionDenied(format!(
+                "{required_access} access is required"
+            )));
+        }
+
         let logical_plan = logical_plan.optimize();
 
         let physical_plan = PhysicalPlan::from(logical_plan);
+        let plan_time = plan_start.elapsed();
 
         let complexity = physical_plan.get_complexity();
+        if let Some(max_complexity) = CONFIG.get_max_query_complexity()
+            && complexity > max_complexity
+        {
+            return Err(Error::QueryTooComplex(complexity));
+        }
+
+        if validate_only {
+            return Self::validate(physical_plan);
+        }
+
+        if CONFIG.is_read_only()
+            && let Some(command) = physical_plan.write_command()
+        {
+            return Err(Error::ReadOnly(command.to_string()));
+        }
+
         DATABASE_LOAD.fetch_add(complexity, std::sync::atomic::Ordering::Relaxed);
         let _guard = ComplexityGuard::new(complexity);
 
+        if explain_analyze {
+            return Self::explain_analyze(physical_plan, plan_time);
+        }
         Self::execute_physical_plan(physical_plan)
     }
 
@@ -44,25 +153,289 @@ impl CommandRunner {
                 settings,
                 order_by,
                 primary_key,
-            } => Self::create_table(&table_def, columns, settings, order_by, primary_key),
-            PhysicalPlan::Insert { table_def, columns } => Self::insert(&table_def, columns),
+                comment,
+            } => Self::create_table(
+                &table_def,
+                columns,
+                settings,
+                order_by,
+                primary_key,
+                comment,
+            ),
+            PhysicalPlan::ShowCreateTable { name } => Self::show_create_table(&name),
+            PhysicalPlan::ShowEngines => Ok(Self::show_engines()),
+            PhysicalPlan::DiskUsage { table } => Self::disk_usage(table.as_ref()),
+            PhysicalPlan::CountStar { table_def } => Self::count_star(&table_def),
+            PhysicalPlan::SystemPartInfo {
+                table_def,
+                part_name,
+            } => Self::part_info(&table_def, &part_name),
+            PhysicalPlan::Insert {
+                table_def,
+                columns,
+                on_duplicate,
+            } => {
+                if on_duplicate.is_empty() {
+                    Self::insert(&table_def, columns)
+                } else {
+                    Self::upsert(&table_def, columns, &on_duplicate)
+                }
+            }
+            PhysicalPlan::Import {
+                table_def,
+                columns,
+                source,
+            } => Self::import(&table_def, &columns, &source),
             PhysicalPlan::DropDatabase { name, if_exists } => Self::drop_database(&name, if_exists),
             PhysicalPlan::DropTable { name, if_exists } => Self::drop_table(&name, if_exists),
+            PhysicalPlan::DropPartition { name, partition } => {
+                Self::drop_partition(&name, &partition)
+            }
+            PhysicalPlan::ExchangeTables { first, second } => {
+                Self::exchange_tables(&first, &second)
+            }
+            PhysicalPlan::ModifyTableFlags { name, set, clear } => {
+                Self::modify_table_flags(&name, set, clear)
+            }
+            PhysicalPlan::OptimizeTable { name } => Self::optimize_table(&name),
+            PhysicalPlan::Resample {
+                key,
+                interval,
+                columns,
+                plan,
+            } => Self::resample(&key, interval, &columns, *plan),
             PhysicalPlan::Select {
                 scan_source,
                 columns,
+                computed,
                 filter,
                 sort_by,
+                row_number,
                 limit,
                 offset,
+                settings,
             } => Self::select(
                 scan_source,
                 columns,
+                computed,
                 filter,
                 sort_by.as_ref(),
+                row_number,
                 limit,
                 offset,
+                &settings,
+                None,
             ),
         }
     }
 }
+
+/// Logs command at warn level with its duration, peak memory (see `MemoryTracker`) and number
+/// of returned rows. Command is truncated to `SLOW_QUERY_LOG_MAX_CHARS` and its literals are
+/// redacted, when `slow_query_redact_literals` is set.
+fn log_slow_query(command: &str, execution_time: Duration, row_count: usize, peak_memory: usize) {
+    let statement = if CONFIG.is_slow_query_redact_literals() {
+        redact_literals(command)
+    } else {
+        command.to_string()
+    };
+
+    warn!(
+        execution_time_ms = execution_time.as_millis() as u64, rows = row_count,
+        peak_memory_bytes = peak_memory;
+        "Slow query ({execution_time:?}, peak memory {peak_memory} bytes, {row_count} rows): {}",
+        truncate(statement.trim(), SLOW_QUERY_LOG_MAX_CHARS)
+    );
+}
+
+/// Replaces string and number literals with `?`. Command, which could not be tokenized, is
+/// replaced completely, as literals could not be found.
+fn redact_literals(command: &str) -> String {
+    let Ok(tokens) = Tokenizer::new(&ClickHouseDialect {}, command).tokenize() else {
+        return "?".to_string();
+    };
+
+    tokens
+        .into_iter()
+        .map(|token| match token {
+            Token::Number(..)
+            | Token::SingleQuotedString(_)
+            | Token::TripleSingleQuotedString(_)
+            | Token::TripleDoubleQuotedString(_)
+            | Token::NationalStringLiteral(_)
+            | Token::EscapedStringLiteral(_)
+            | Token::UnicodeStringLiteral(_)
+            | Token::HexStringLiteral(_)
+            | Token::SingleQuotedByteStringLiteral(_)
+            | Token::DoubleQuotedByteStringLiteral(_)
+            | Token::DollarQuotedString(_) => "?".to_string(),
+            token => token.to_string(),
+        })
+        .collect()
+}
+
+/// Returns first `max_chars` characters of `string`, with `...` appended when it was longer.
+fn truncate(string: &str, max_chars: usize) -> String {
+    match string.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}...", &string[..end]),
+        None => string.to_string(),
+    }
+}
+
+/// Returns command after case-insensitive `VALIDATE` keyword, when command starts with it.
+fn strip_validate_prefix(command: &str) -> Option<&str> {
+    strip_keyword_prefix(command, "VALIDATE")
+}
+
+/// Returns command after case-insensitive `EXPLAIN ANALYZE` keywords, when command starts with
+/// them.
+fn strip_explain_analyze_prefix(command: &str) -> Option<&str> {
+    strip_keyword_prefix(strip_keyword_prefix(command, "EXPLAIN")?, "ANALYZE")
+}
+
+/// Returns command after case-insensitive `keyword`, when command starts with it, followed by
+/// whitespace.
+fn strip_keyword_prefix<'a>(command: &'a str, keyword: &str) -> Option<&'a str> {
+    let command = command.trim_start();
+    let (prefix, rest) = command.split_at_checked(keyword.len())?;
+    if prefix.eq_ignore_ascii_case(keyword) && rest.starts_with(char::is_whitespace) {
+        Some(rest)
+    } else {
+        None
+    }
+}
+
+/// Cuts trailing `FORMAT name` clause (before optional `;`) out of the command. `INSERT` is
+/// never cut, as its `FORMAT` is followed by data, nor `IMPORT`, whose `FORMAT` is of the file.
+///
+/// Returns: command without the clause and name of the format, or `None`, when command has no
+/// such clause or could not be tokenized (parser reports the error later).
+fn strip_format_suffix(command: &str) -> Option<(String, String)> {
+    let tokens = Tokenizer::new(&ClickHouseDialect {}, command)
+        .tokenize()
+        .ok()?;
+    // semicolons are skipped, so that the clause is found before them
+    let significant: Vec<_> = tokens
+        .iter()
+        .enumerate()
+        .filter(|(_, token)| !matches!(token, Token::Whitespace(_) | Token::SemiColon))
+        .map(|(idx, _)| idx)
+        .collect();
+
+    let (&first, [.., format_idx, name_idx]) = (significant.first()?, significant.as_slice())
+    else {
+        return None;
+    };
+    let is_keyword =
+        |idx: usize, keyword| matches!(&tokens[idx], Token::Word(word) if word.keyword == keyword);
+    if is_keyword(first, Keyword::INSERT)
+        || is_keyword(first, Keyword::IMPORT)
+        || !is_keyword(*format_idx, Keyword::FORMAT)
+    {
+        return None;
+    }
+    let Token::Word(name) = &tokens[*name_idx] else {
+        return None;
+    };
+    if name.quote_style.is_some() {
+        return None;
+    }
+
+    let command = tokens[..*format_idx]
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    Some((command, name.value.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_validate_prefix() {
+        assert_eq!(
+            strip_validate_prefix("VALIDATE CREATE DATABASE db"),
+            Some(" CREATE DATABASE db")
+        );
+        assert_eq!(
+            strip_validate_prefix(" validate\nSELECT id FROM db.t"),
+            Some("\nSELECT id FROM db.t")
+        );
+        for command in [
+            "VALIDATE",
+            "VALIDATED SELECT 1",
+            "SELECT validate FROM db.t",
+            "ü",
+        ] {
+            assert_eq!(strip_validate_prefix(command), None, "{command}");
+        }
+    }
+
+    #[test]
+    fn test_strip_format_suffix() {
+        assert_eq!(
+            strip_format_suffix("SELECT id FROM db.t FORMAT PRETTY"),
+            Some(("SELECT id FROM db.t ".to_string(), "PRETTY".to_string()))
+        );
+        assert_eq!(
+            strip_format_suffix("show create table db.t\nformat Pretty ;\n"),
+            Some(("show create table db.t\n".to_string(), "Pretty".to_string()))
+        );
+        assert_eq!(
+            strip_format_suffix("SELECT 'FORMAT PRETTY' FROM db.t FORMAT JSON"),
+            Some((
+                "SELECT 'FORMAT PRETTY' FROM db.t ".to_string(),
+                "JSON".to_string()
+            ))
+        );
+        for command in [
+            "SELECT id FROM db.t",
+            "SELECT format FROM db.t",
+            "SELECT id FROM db.t FORMAT",
+            "SELECT id FROM db.t FORMAT \"PRETTY\"",
+            "INSERT INTO db.t FORMAT PRETTY",
+            "IMPORT INTO db.t (id) FROM 'a.tsv' FORMAT TSV",
+            "SELECT 'unterminated FORMAT PRETTY",
+        ] {
+            assert_eq!(strip_format_suffix(command), None, "{command}");
+        }
+    }
+
+    #[test]
+    fn test_strip_explain_analyze_prefix() {
+        assert_eq!(
+            strip_explain_analyze_prefix("explain\n Analyze SELECT id FROM db.t"),
+            Some(" SELECT id FROM db.t")
+        );
+        for command in [
+            "EXPLAIN SELECT id FROM db.t",
+            "EXPLAIN ANALYZED SELECT id FROM db.t",
+            "ANALYZE SELECT id FROM db.t",
+        ] {
+            assert_eq!(strip_explain_analyze_prefix(command), None, "{command}");
+        }
+    }
+
+    #[test]
+    fn test_redact_literals() {
+        assert_eq!(
+            redact_literals(
+                "INSERT INTO db.t (id, name) VALUES (1, 'secret'), (-2.5, '')  -- note"
+            ),
+            "INSERT INTO db.t (id, name) VALUES (?, ?), (-?, ?)  -- note"
+        );
+        assert_eq!(
+            redact_literals("SELECT \"my col\" FROM db.t WHERE a = 'it''s'"),
+            "SELECT \"my col\" FROM db.t WHERE a = ?"
+        );
+        assert_eq!(redact_literals("SELECT 'unterminated"), "?");
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("SELECT 1", 8), "SELECT 1");
+        assert_eq!(truncate("SELECT 1", 6), "SELECT...");
+        assert_eq!(truncate("ü ü", 2), "ü ...");
+    }
+}
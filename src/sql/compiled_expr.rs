@@ -0,0 +1,1033 @@
+use crate::error::{Error, Result};
+use crate::sql::scalar_function::ScalarFunction;
+use crate::sql::{function_args, parse_value};
+use crate::storage::{ColumnDef, Value, ValueType};
+use sqlparser::ast::{
+    AccessExpr, BinaryOperator, DateTimeField, Expr, Ident, Interval, Subscript, UnaryOperator,
+    Value as SQLValue,
+};
+
+/// Expression, which computes a single value per row from the table columns.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompiledExpr {
+    Column(usize),
+    Literal(Value),
+    /// Element of the tuple column. `field_idx` is 0-based, while SQL notation (`col.1`) is 1-based.
+    TupleAccess {
+        col_idx: usize,
+        field_idx: usize,
+    },
+    /// Value of the map column by key (`col['key']`). Missing key produces `NULL`.
+    MapAccess {
+        col_idx: usize,
+        key: Value,
+    },
+    Function {
+        function: ScalarFunction,
+        args: Vec<CompiledExpr>,
+        return_type: ValueType,
+    },
+    /// Arithmetic of two integers of the same type. Overflow wraps around.
+    Arithmetic {
+        op: ArithmeticOp,
+        left: Box<CompiledExpr>,
+        right: Box<CompiledExpr>,
+    },
+    /// Negation of a signed integer (`-col`). Overflow wraps around, as in `Arithmetic`.
+    Neg(Box<CompiledExpr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArithmeticOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+impl TryFrom<&BinaryOperator> for ArithmeticOp {
+    type Error = Error;
+
+    fn try_from(op: &BinaryOperator) -> Result<Self> {
+        match op {
+            BinaryOperator::Plus => Ok(Self::Add),
+            BinaryOperator::Minus => Ok(Self::Sub),
+            BinaryOperator::Multiply => Ok(Self::Mul),
+            BinaryOperator::Divide => Ok(Self::Div),
+            BinaryOperator::Modulo => Ok(Self::Rem),
+            _ => Err(Error::UnsupportedCommand(format!(
+                "Unsupported operator: {op}"
+            ))),
+        }
+    }
+}
+
+impl ArithmeticOp {
+    /// Returns:
+    ///   * Ok: result of the operation. `NULL` operand produces `NULL`.
+    ///   * Error: `InvalidSource` on division by zero or operands of different types.
+    fn eval(self, left: Value, right: Value) -> Result<Value> {
+        macro_rules! apply {
+            ($variant:ident, $left:expr, $right:expr) => {{
+                let (left, right) = ($left, $right);
+                let result = match self {
+                    Self::Add => left.wrapping_add(right),
+                    Self::Sub => left.wrapping_sub(right),
+                    Self::Mul => left.wrapping_mul(right),
+                    Self::Div | Self::Rem if right == 0 => {
+                        return Err(Error::InvalidSource("Division by zero".to_string()));
+                    }
+                    Self::Div => left.wrapping_div(right),
+                    Self::Rem => left.wrapping_rem(right),
+                };
+                Ok(Value::$variant(result))
+            }};
+        }
+
+        match (left, right) {
+            (Value::Null, _) | (_, Value::Null) => Ok(Value::Null),
+            (Value::Int8(left), Value::Int8(right)) => apply!(Int8, left, right),
+            (Value::Int16(left), Value::Int16(right)) => apply!(Int16, left, right),
+            (Value::Int32(left), Value::Int32(right)) => apply!(Int32, left, right),
+            (Value::Int64(left), Value::Int64(right)) => apply!(Int64, left, right),
+            (Value::UInt8(left), Value::UInt8(right)) => apply!(UInt8, left, right),
+            (Value::UInt16(left), Value::UInt16(right)) => apply!(UInt16, left, right),
+            (Value::UInt32(left), Value::UInt32(right)) => apply!(UInt32, left, right),
+            (Value::UInt64(left), Value::UInt64(right)) => apply!(UInt64, left, right),
+            (left, right) => Err(Error::InvalidSource(format!(
+                "Arithmetic is not supported for {left:?} and {right:?}"
+            ))),
+        }
+    }
+}
+
+impl CompiledExpr {
+    /// Compiles a SQL expression into a `CompiledExpr`.
+    ///
+    /// Supports: column references, tuple element access (`col.1`), map access (`col['key']`),
+    /// scalar functions, integer arithmetic (`+`, `-`, `*`, `/`, `%`), negation of signed
+    /// integers (`-col`), `Date`/`DateTime` plus or minus `INTERVAL n unit` and parenthesized
+    /// expressions.
+    /// Literal operand of arithmetic takes the type of the other operand, e.g., `10` in `id % 10`.
+    ///
+    /// Returns:
+    ///   * Ok: `CompiledExpr` representing the compiled expression.
+    ///   * Error when:
+    ///     1. Column not found in table: `ColumnNotFound`.
+    ///     2. Tuple element is accessed on non-tuple column or out of range: `InvalidTupleAccess`.
+    ///     3. Unknown function: `UnsupportedFunction`.
+    ///     4. Arguments or map key do not match expected types: `InvalidFunctionArguments` or `InvalidSource`.
+    ///     5. Arithmetic operands are not integers of the same type, negated operand is not a
+    ///        signed integer, or interval is added to neither `Date` nor `DateTime`: `InvalidSource`.
+    ///     6. Unsupported expression type: `UnsupportedCommand`.
+    pub fn compile(expr: &Expr, column_defs: &[ColumnDef]) -> Result<Self> {
+        match expr {
+            Expr::Identifier(ident) => Ok(Self::Column(find_column(ident, column_defs)?)),
+            Expr::CompoundIdentifier(idents) => {
+                let [column, field] = idents.as_slice() else {
+                    return Err(Error::UnsupportedCommand(format!(
+                        "Only single level tuple access is supported: {expr}"
+                    )));
+                };
+                let col_idx = find_column(column, column_defs)?;
+                let field_idx = parse_tuple_index(field, &column_defs[col_idx])?;
+
+                Ok(Self::TupleAccess { col_idx, field_idx })
+            }
+            Expr::CompoundFieldAccess { root, access_chain } => {
+                let (Expr::Identifier(column), [AccessExpr::Subscript(Subscript::Index { index })]) =
+                    (root.as_ref(), access_chain.as_slice())
+                else {
+                    return Err(Error::UnsupportedCommand(format!(
+                        "Only single key access of map column is supported: {expr}"
+                    )));
+                };
+                let col_idx = find_column(column, column_defs)?;
+                let ValueType::Map(key_type, _) = &column_defs[col_idx].field_type else {
+                    return Err(Error::InvalidSource(format!(
+                        "Column ({}) is not a map",
+                        column.value
+                    )));
+                };
+                let key = parse_value(index, key_type)?;
+
+                Ok(Self::MapAccess { col_idx, key })
+            }
+            Expr::Function(function) => {
+                let scalar_function = ScalarFunction::try_from(function.name.to_string().as_str())?;
+
+                let mut args = Vec::new();
+                let mut arg_types = Vec::new();
+                for (arg_idx, arg) in function_args(function)?.into_iter().enumerate() {
+                    let arg = if let Some(first_arg_type) = arg_types.first()
+                        && is_literal(arg)
+                    {
+                        let arg_type = scalar_function.get_arg_type(arg_idx, first_arg_type)?;
+                        Self::Literal(parse_value(arg, &arg_type)?)
+                    } else {
+                        Self::compile(arg, column_defs)?
+                    };
+                    arg_types.push(arg.get_type(column_defs));
+                    args.push(arg);
+                }
+                let return_type = scalar_function.get_return_type(&arg_types)?;
+
+                Ok(Self::Function {
+                    function: scalar_function,
+                    args,
+                    return_type,
+                })
+            }
+            Expr::BinaryOp {
+                left,
+                op: op @ (BinaryOperator::Plus | BinaryOperator::Minus),
+                right,
+            } if matches!(right.as_ref(), Expr::Interval(_)) => {
+                let Expr::Interval(interval) = right.as_ref() else {
+                    unreachable!("Right operand is matched as interval");
+                };
+                let subtract = *op == BinaryOperator::Minus;
+                Self::compile_interval_arithmetic(expr, left, interval, subtract, column_defs)
+            }
+            Expr::BinaryOp {
+                left,
+                op: BinaryOperator::Plus,
+                right,
+            } if matches!(left.as_ref(), Expr::Interval(_)) => {
+                let Expr::Interval(interval) = left.as_ref() else {
+                    unreachable!("Left operand is matched as interval");
+                };
+                Self::compile_interval_arithmetic(expr, right, interval, false, column_defs)
+            }
+            Expr::BinaryOp { left, op, right } => {
+                let op = ArithmeticOp::try_from(op)?;
+                let (left, right) = match (is_literal(left), is_literal(right)) {
+                    (false, true) => {
+                        let left = Self::compile(left, column_defs)?;
+                        let right = parse_value(right, &left.get_type(column_defs))?;
+                        (left, Self::Literal(right))
+                    }
+                    (true, false) => {
+                        let right = Self::compile(right, column_defs)?;
+                        let left = parse_value(left, &right.get_type(column_defs))?;
+                        (Self::Literal(left), right)
+                    }
+                    _ => (
+                        Self::compile(left, column_defs)?,
+                        Self::compile(right, column_defs)?,
+                    ),
+                };
+
+                let left_type = left.get_type(column_defs);
+                let right_type = right.get_type(column_defs);
+                if !left_type.is_integer() || left_type != right_type {
+                    return Err(Error::InvalidSource(format!(
+                        "Arithmetic ({expr}) requires integers of the same type, received: {left_type} and {right_type}"
+                    )));
+                }
+
+                Ok(Self::Arithmetic {
+                    op,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                })
+            }
+            Expr::UnaryOp {
+                op: UnaryOperator::Minus,
+                expr: operand,
+            } => {
+                let operand = Self::compile(operand, column_defs)?;
+                let operand_type = operand.get_type(column_defs);
+                if !operand_type.is_signed_integer() {
+                    return Err(Error::InvalidSource(format!(
+                        "Negation ({expr}) requires a signed integer, received: {operand_type}"
+                    )));
+                }
+
+                Ok(Self::Neg(Box::new(operand)))
+            }
+            Expr::Nested(inner) => Self::compile(inner, column_defs),
+            // string literal has a single type, e.g., the first argument of `toDate('2024-01-31')`
+            Expr::Value(value) if matches!(value.value, SQLValue::SingleQuotedString(_)) => {
+                Ok(Self::Literal(parse_value(expr, &ValueType::String)?))
+            }
+            expr => Err(Error::UnsupportedCommand(format!(
+                "Unsupported expression: {expr}"
+            ))),
+        }
+    }
+
+    /// Compiles `date + INTERVAL n unit` (or `date - ...`) into a date arithmetic function with
+    /// `Int64` amount: seconds of the interval for `DateTime` (and for `Date` with units shorter
+    /// than a day, which produces `DateTime`), days for `Date`, and months for `MONTH`, `QUARTER`
+    /// and `YEAR`, which have no fixed length. Amount, which overflows `Int64`, is `NULL`, so the
+    /// result is `NULL`.
+    ///
+    /// Returns:
+    ///   * Ok: `CompiledExpr::Function` of `addSeconds`, `addDays`, `addMonths` or their
+    ///     `subtract*` counterparts.
+    ///   * Error: `InvalidSource` when `date` is neither `Date` nor `DateTime`, or
+    ///     `UnsupportedCommand` when interval is not an integer number of a supported unit.
+    fn compile_interval_arithmetic(
+        expr: &Expr,
+        date: &Expr,
+        interval: &Interval,
+        subtract: bool,
+        column_defs: &[ColumnDef],
+    ) -> Result<Self> {
+        let date = Self::compile(date, column_defs)?;
+        let date_type = date.get_type(column_defs);
+        if !matches!(date_type, ValueType::Date | ValueType::DateTime) {
+            return Err(Error::InvalidSource(format!(
+                "Interval arithmetic ({expr}) requires Date or DateTime, received: {date_type}"
+            )));
+        }
+
+        let unsupported = || {
+            Error::UnsupportedCommand(format!(
+                "Expected INTERVAL n SECOND, MINUTE, HOUR, DAY, WEEK, MONTH, QUARTER or YEAR, received: {interval}"
+            ))
+        };
+        let Interval {
+            value,
+            leading_field: Some(unit),
+            leading_precision: None,
+            last_field: None,
+            fractional_seconds_precision: None,
+        } = interval
+        else {
+            return Err(unsupported());
+        };
+        let Ok(Value::Int64(count)) = parse_value(value, &ValueType::Int64) else {
+            return Err(unsupported());
+        };
+        let is_date = date_type == ValueType::Date;
+        let (function, multiplier) = match unit {
+            DateTimeField::Second | DateTimeField::Seconds => (ScalarFunction::AddSeconds, 1),
+            DateTimeField::Minute | DateTimeField::Minutes => (ScalarFunction::AddSeconds, 60),
+            DateTimeField::Hour | DateTimeField::Hours => (ScalarFunction::AddSeconds, 3600),
+            DateTimeField::Day | DateTimeField::Days if is_date => (ScalarFunction::AddDays, 1),
+            DateTimeField::Week(None) | DateTimeField::Weeks if is_date => {
+                (ScalarFunction::AddDays, 7)
+            }
+            DateTimeField::Day | DateTimeField::Days => (ScalarFunction::AddSeconds, 86_400),
+            DateTimeField::Week(None) | DateTimeField::Weeks => {
+                (ScalarFunction::AddSeconds, 604_800)
+            }
+            DateTimeField::Month | DateTimeField::Months => (ScalarFunction::AddMonths, 1),
+            DateTimeField::Quarter => (ScalarFunction::AddMonths, 3),
+            DateTimeField::Year | DateTimeField::Years => (ScalarFunction::AddMonths, 12),
+            _ => return Err(unsupported()),
+        };
+        let function = match (function, subtract) {
+            (ScalarFunction::AddSeconds, true) => ScalarFunction::SubtractSeconds,
+            (ScalarFunction::AddDays, true) => ScalarFunction::SubtractDays,
+            (ScalarFunction::AddMonths, true) => ScalarFunction::SubtractMonths,
+            (function, _) => function,
+        };
+        let amount = count
+            .checked_mul(multiplier)
+            .map_or(Value::Null, Value::Int64);
+        let return_type = function.get_return_type(&[date_type, ValueType::Int64])?;
+
+        Ok(Self::Function {
+            function,
+            args: vec![date, Self::Literal(amount)],
+            return_type,
+        })
+    }
+
+    /// Returns the type of values, produced by this expression.
+    pub fn get_type(&self, column_defs: &[ColumnDef]) -> ValueType {
+        match self {
+            Self::Column(col_idx) => column_defs[*col_idx].field_type.clone(),
+            Self::Literal(value) => value.get_type(),
+            Self::MapAccess { col_idx, .. } => {
+                let ValueType::Map(_, value_type) = &column_defs[*col_idx].field_type else {
+                    unreachable!("Map access is validated during compilation");
+                };
+                value_type.as_ref().clone()
+            }
+            Self::Function { return_type, .. } => return_type.clone(),
+            Self::Arithmetic { left, .. } => left.get_type(column_defs),
+            Self::Neg(operand) => operand.get_type(column_defs),
+            Self::TupleAccess { col_idx, field_idx } => {
+                let ValueType::Tuple(field_types) = &column_defs[*col_idx].field_type else {
+                    unreachable!("Tuple access is validated during compilation");
+                };
+                field_types[*field_idx].clone()
+            }
+        }
+    }
+
+    /// Collects all column indices referenced by this expression.
+    pub fn get_column_defs(&self, col_def_idxs: &mut Vec<usize>) {
+        match self {
+            Self::Column(col_idx)
+            | Self::TupleAccess { col_idx, .. }
+            | Self::MapAccess { col_idx, .. } => {
+                if !col_def_idxs.contains(col_idx) {
+                    col_def_idxs.push(*col_idx);
+                }
+            }
+            Self::Literal(_) => {}
+            Self::Function { args, .. } => {
+                for arg in args {
+                    arg.get_column_defs(col_def_idxs);
+                }
+            }
+            Self::Arithmetic { left, right, .. } => {
+                left.get_column_defs(col_def_idxs);
+                right.get_column_defs(col_def_idxs);
+            }
+            Self::Neg(operand) => operand.get_column_defs(col_def_idxs),
+        }
+    }
+
+    /// Evaluates expression for a single row.
+    ///
+    /// `get_column` returns value of the column (by its index in table columns) for the current row.
+    ///
+    /// Returns:
+    ///   * Ok: computed `Value`. `NULL` tuples and maps produce `NULL` elements.
+    ///   * Error: any error from `get_column`, function evaluation or arithmetic (division by zero).
+    pub fn eval<F>(&self, get_column: &F) -> Result<Value>
+    where
+        F: Fn(usize) -> Result<Value>,
+    {
+        match self {
+            Self::Column(col_idx) => get_column(*col_idx),
+            Self::Literal(value) => Ok(value.clone()),
+            Self::MapAccess { col_idx, key } => Ok(get_column(*col_idx)?
+                .map_get(key)
+                .cloned()
+                .unwrap_or(Value::Null)),
+            Self::Function { function, args, .. } => {
+                let args = args
+                    .iter()
+                    .map(|arg| arg.eval(get_column))
+                    .collect::<Result<Vec<_>>>()?;
+                function.eval(args)
+            }
+            Self::Arithmetic { op, left, right } => {
+                op.eval(left.eval(get_column)?, right.eval(get_column)?)
+            }
+            Self::Neg(operand) => match operand.eval(get_column)? {
+                Value::Null => Ok(Value::Null),
+                Value::Int8(value) => Ok(Value::Int8(value.wrapping_neg())),
+                Value::Int16(value) => Ok(Value::Int16(value.wrapping_neg())),
+                Value::Int32(value) => Ok(Value::Int32(value.wrapping_neg())),
+                Value::Int64(value) => Ok(Value::Int64(value.wrapping_neg())),
+                value => Err(Error::InvalidSource(format!(
+                    "Negation is not supported for {value:?}"
+                ))),
+            },
+            Self::TupleAccess { col_idx, field_idx } => match get_column(*col_idx)? {
+                Value::Tuple(mut values) if *field_idx < values.len() => {
+                    Ok(values.swap_remove(*field_idx))
+                }
+                _ => Ok(Value::Null),
+            },
+        }
+    }
+}
+
+/// Literals are typed by the function signature, instead of being compiled on their own.
+fn is_literal(expr: &Expr) -> bool {
+    match expr {
+        Expr::Value(_) | Expr::Tuple(_) | Expr::Array(_) => true,
+        Expr::UnaryOp { expr, .. } => is_literal(expr),
+        _ => false,
+    }
+}
+
+fn find_column(ident: &Ident, column_defs: &[ColumnDef]) -> Result<usize> {
+    column_defs
+        .iter()
+        .position(|col_def| col_def.name == ident.value)
+        .ok_or(Error::ColumnNotFound(ident.value.clone()))
+}
+
+/// Parses 1-based tuple element index and validates it against the column type.
+///
+/// Returns:
+///   * Ok: 0-based element index.
+///   * Error: `InvalidTupleAccess` when column is not a tuple, or index is not a number in range.
+fn parse_tuple_index(field: &Ident, column_def: &ColumnDef) -> Result<usize> {
+    let ValueType::Tuple(field_types) = &column_def.field_type else {
+        return Err(Error::InvalidTupleAccess(format!(
+            "Column ({}) is not a tuple",
+            column_def.name
+        )));
+    };
+    match field.value.parse::<usize>() {
+        Ok(idx) if (1..=field_types.len()).contains(&idx) => Ok(idx - 1),
+        _ => Err(Error::InvalidTupleAccess(format!(
+            "Invalid element ({}) of column ({}) with {} elements",
+            field.value,
+            column_def.name,
+            field_types.len()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::sql_parser::parse_statements;
+    use crate::storage::Constraints;
+    use sqlparser::ast::{SelectItem, SetExpr, Statement};
+
+    fn column_defs() -> Vec<ColumnDef> {
+        vec![
+            ColumnDef {
+                name: "id".to_string(),
+                field_type: ValueType::UInt64,
+                constraints: Constraints::default(),
+            },
+            ColumnDef {
+                name: "point".to_string(),
+                field_type: ValueType::Tuple(vec![ValueType::Int32, ValueType::String]),
+                constraints: Constraints::default(),
+            },
+            ColumnDef {
+                name: "attrs".to_string(),
+                field_type: ValueType::Map(Box::new(ValueType::String), Box::new(ValueType::UInt8)),
+                constraints: Constraints::default(),
+            },
+        ]
+    }
+
+    fn projection_expr(sql: &str) -> Expr {
+        let statements = parse_statements(sql).unwrap();
+        let Statement::Query(query) = &statements[0] else {
+            panic!("Expected query");
+        };
+        let SetExpr::Select(select) = query.body.as_ref() else {
+            panic!("Expected select");
+        };
+        let SelectItem::UnnamedExpr(expr) = &select.projection[0] else {
+            panic!("Expected expression");
+        };
+        expr.clone()
+    }
+
+    #[test]
+    fn test_compile_tuple_access() {
+        let expr = projection_expr("SELECT point.2 FROM db.table");
+        let compiled = CompiledExpr::compile(&expr, &column_defs()).unwrap();
+
+        assert_eq!(
+            compiled,
+            CompiledExpr::TupleAccess {
+                col_idx: 1,
+                field_idx: 1
+            }
+        );
+        assert_eq!(compiled.get_type(&column_defs()), ValueType::String);
+        assert_eq!(expr.to_string(), "point.2");
+    }
+
+    #[test]
+    fn test_compile_invalid_tuple_access() {
+        let out_of_range = projection_expr("SELECT point.3 FROM db.table");
+        let zero = projection_expr("SELECT point.0 FROM db.table");
+        let not_tuple = projection_expr("SELECT id.1 FROM db.table");
+
+        for expr in [out_of_range, zero, not_tuple] {
+            assert!(matches!(
+                CompiledExpr::compile(&expr, &column_defs()),
+                Err(Error::InvalidTupleAccess(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_eval_tuple_access() {
+        let compiled = CompiledExpr::TupleAccess {
+            col_idx: 1,
+            field_idx: 0,
+        };
+        let row = [
+            Value::UInt64(1),
+            Value::Tuple(vec![Value::Int32(-5), Value::String("a".to_string())]),
+        ];
+        assert_eq!(
+            compiled.eval(&|idx| Ok(row[idx].clone())).unwrap(),
+            Value::Int32(-5)
+        );
+
+        let null_row = [Value::UInt64(1), Value::Null];
+        assert_eq!(
+            compiled.eval(&|idx| Ok(null_row[idx].clone())).unwrap(),
+            Value::Null
+        );
+    }
+
+    #[test]
+    fn test_compile_map_access_and_functions() {
+        let access = CompiledExpr::compile(
+            &projection_expr("SELECT attrs['a'] FROM db.table"),
+            &column_defs(),
+        )
+        .unwrap();
+        assert_eq!(
+            access,
+            CompiledExpr::MapAccess {
+                col_idx: 2,
+                key: Value::String("a".to_string())
+            }
+        );
+        assert_eq!(access.get_type(&column_defs()), ValueType::UInt8);
+
+        let keys = CompiledExpr::compile(
+            &projection_expr("SELECT mapKeys(attrs) FROM db.table"),
+            &column_defs(),
+        )
+        .unwrap();
+        assert_eq!(
+            keys.get_type(&column_defs()),
+            ValueType::Array(Box::new(ValueType::String))
+        );
+
+        let contains = CompiledExpr::compile(
+            &projection_expr("SELECT mapContains(attrs, 'b') FROM db.table"),
+            &column_defs(),
+        )
+        .unwrap();
+        assert_eq!(contains.get_type(&column_defs()), ValueType::Bool);
+
+        for sql in [
+            "SELECT mapKeys(id) FROM db.table",
+            "SELECT mapContains(attrs, 1) FROM db.table",
+            "SELECT mapContains(attrs) FROM db.table",
+        ] {
+            assert!(
+                CompiledExpr::compile(&projection_expr(sql), &column_defs()).is_err(),
+                "{sql}"
+            );
+        }
+        assert!(matches!(
+            CompiledExpr::compile(
+                &projection_expr("SELECT mapSize(attrs) FROM db.table"),
+                &column_defs()
+            ),
+            Err(Error::UnsupportedFunction(_))
+        ));
+    }
+
+    #[test]
+    fn test_eval_map_functions() {
+        let map = Value::new_map(vec![
+            (Value::String("b".to_string()), Value::UInt8(2)),
+            (Value::String("a".to_string()), Value::UInt8(1)),
+            (Value::String("b".to_string()), Value::UInt8(3)),
+        ])
+        .unwrap();
+        let row = [Value::UInt64(1), Value::Null, map];
+        let eval = |sql: &str| {
+            CompiledExpr::compile(&projection_expr(sql), &column_defs())
+                .unwrap()
+                .eval(&|idx| Ok(row[idx].clone()))
+                .unwrap()
+        };
+
+        assert_eq!(eval("SELECT attrs['b'] FROM db.table"), Value::UInt8(3));
+        assert_eq!(eval("SELECT attrs['c'] FROM db.table"), Value::Null);
+        assert_eq!(
+            eval("SELECT mapKeys(attrs) FROM db.table"),
+            Value::Array(vec![
+                Value::String("a".to_string()),
+                Value::String("b".to_string())
+            ])
+        );
+        assert_eq!(
+            eval("SELECT mapValues(attrs) FROM db.table"),
+            Value::Array(vec![Value::UInt8(1), Value::UInt8(3)])
+        );
+        assert_eq!(
+            eval("SELECT mapContains(attrs, 'a') FROM db.table"),
+            Value::Bool(true)
+        );
+        assert_eq!(
+            eval("SELECT mapContains(attrs, 'c') FROM db.table"),
+            Value::Bool(false)
+        );
+    }
+
+    #[test]
+    fn test_eval_hash_functions() {
+        let row = [Value::UInt64(42), Value::Null, Value::Null];
+        let eval = |sql: &str| {
+            CompiledExpr::compile(&projection_expr(sql), &column_defs())
+                .unwrap()
+                .eval(&|idx| Ok(row[idx].clone()))
+                .unwrap()
+        };
+
+        assert_eq!(
+            eval("SELECT cityHash64(id) FROM db.table"),
+            Value::UInt64(10501932312358040811)
+        );
+        assert_eq!(
+            eval("SELECT cityHash64(point.2) FROM db.table"),
+            Value::Null
+        );
+        assert_eq!(
+            eval("SELECT intHash64(id) FROM db.table"),
+            eval("SELECT intHash64(id) FROM db.table")
+        );
+        assert!(matches!(
+            eval("SELECT intHash32(id) FROM db.table"),
+            Value::UInt32(_)
+        ));
+
+        let hash = |function: ScalarFunction, value: &str| {
+            function
+                .eval(vec![Value::String(value.to_string())])
+                .unwrap()
+        };
+        assert_eq!(
+            hash(ScalarFunction::CityHash64, "hello"),
+            Value::UInt64(2578220239953316063)
+        );
+        assert_eq!(
+            hash(ScalarFunction::Hash64, "hello"),
+            Value::UInt64(0x26c7_827d_889f_6da3)
+        );
+        assert_ne!(
+            hash(ScalarFunction::SipHash64, "hello"),
+            hash(ScalarFunction::SipHash64, "world")
+        );
+        assert_eq!(
+            ScalarFunction::IntHash64
+                .eval(vec![Value::UInt64(0)])
+                .unwrap(),
+            Value::UInt64(0)
+        );
+
+        for sql in [
+            "SELECT intHash64(point.2) FROM db.table",
+            "SELECT cityHash64(attrs) FROM db.table",
+            "SELECT hash64() FROM db.table",
+        ] {
+            assert!(
+                CompiledExpr::compile(&projection_expr(sql), &column_defs()).is_err(),
+                "{sql}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_eval_date_part_functions() {
+        // 2024-02-29 12:30:05, Thursday
+        let time = Value::DateTime(1_709_209_805);
+        let day = Value::Date(19_782);
+        let expected = [
+            (
+                ScalarFunction::ToYear,
+                Value::UInt16(2024),
+                Value::UInt16(2024),
+            ),
+            (ScalarFunction::ToMonth, Value::UInt8(2), Value::UInt8(2)),
+            (
+                ScalarFunction::ToDayOfMonth,
+                Value::UInt8(29),
+                Value::UInt8(29),
+            ),
+            (
+                ScalarFunction::ToDayOfWeek,
+                Value::UInt8(4),
+                Value::UInt8(4),
+            ),
+            (ScalarFunction::ToHour, Value::UInt8(12), Value::UInt8(0)),
+            (ScalarFunction::ToMinute, Value::UInt8(30), Value::UInt8(0)),
+            (ScalarFunction::ToSecond, Value::UInt8(5), Value::UInt8(0)),
+            (
+                ScalarFunction::ToYYYYMM,
+                Value::UInt32(202_402),
+                Value::UInt32(202_402),
+            ),
+            (
+                ScalarFunction::ToYYYYMMDD,
+                Value::UInt32(20_240_229),
+                Value::UInt32(20_240_229),
+            ),
+        ];
+        for (function, of_time, of_day) in expected {
+            assert_eq!(function.eval(vec![time.clone()]).unwrap(), of_time);
+            assert_eq!(function.eval(vec![day.clone()]).unwrap(), of_day);
+            assert_eq!(function.eval(vec![Value::Null]).unwrap(), Value::Null);
+        }
+
+        // 1970-01-04 is Sunday, 1970-01-05 is Monday
+        let day_of_week = |days: u16| ScalarFunction::ToDayOfWeek.eval(vec![Value::Date(days)]);
+        assert_eq!(day_of_week(3).unwrap(), Value::UInt8(7));
+        assert_eq!(day_of_week(4).unwrap(), Value::UInt8(1));
+        assert_eq!(
+            ScalarFunction::ToYear
+                .eval(vec![Value::DateTime(u32::MAX)])
+                .unwrap(),
+            Value::UInt16(2106)
+        );
+
+        for sql in [
+            "SELECT toYear(id) FROM db.table",
+            "SELECT toHour(point.2) FROM db.table",
+            "SELECT toMonth() FROM db.table",
+        ] {
+            assert!(
+                CompiledExpr::compile(&projection_expr(sql), &column_defs()).is_err(),
+                "{sql}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_compile_and_eval_arithmetic() {
+        let row = [Value::UInt64(17), Value::Null, Value::Null];
+        let compile = |sql: &str| CompiledExpr::compile(&projection_expr(sql), &column_defs());
+        let eval = |sql: &str| compile(sql).unwrap().eval(&|idx| Ok(row[idx].clone()));
+
+        assert_eq!(
+            compile("SELECT id % 10 FROM db.table").unwrap(),
+            CompiledExpr::Arithmetic {
+                op: ArithmeticOp::Rem,
+                left: Box::new(CompiledExpr::Column(0)),
+                right: Box::new(CompiledExpr::Literal(Value::UInt64(10))),
+            }
+        );
+        assert_eq!(
+            eval("SELECT id % 10 FROM db.table").unwrap(),
+            Value::UInt64(7)
+        );
+        assert_eq!(
+            eval("SELECT 20 - id FROM db.table").unwrap(),
+            Value::UInt64(3)
+        );
+        assert_eq!(
+            eval("SELECT (id + 3) * 2 / 4 FROM db.table").unwrap(),
+            Value::UInt64(10)
+        );
+        assert_eq!(
+            eval("SELECT id - 18 FROM db.table").unwrap(),
+            Value::UInt64(u64::MAX)
+        );
+        assert_eq!(
+            eval("SELECT point.1 + 1 FROM db.table").unwrap(),
+            Value::Null
+        );
+        assert!(matches!(
+            eval("SELECT id % 0 FROM db.table"),
+            Err(Error::InvalidSource(_))
+        ));
+
+        for sql in [
+            "SELECT point.2 + 'a' FROM db.table",
+            "SELECT id + point.1 FROM db.table",
+            "SELECT id + -1 FROM db.table",
+        ] {
+            assert!(compile(sql).is_err(), "{sql}");
+        }
+    }
+
+    #[test]
+    fn test_compile_and_eval_date_arithmetic() {
+        let column_defs = vec![
+            ColumnDef {
+                name: "d".to_string(),
+                field_type: ValueType::Date,
+                constraints: Constraints::default(),
+            },
+            ColumnDef {
+                name: "ts".to_string(),
+                field_type: ValueType::DateTime,
+                constraints: Constraints::default(),
+            },
+        ];
+        // 2024-01-31 and 2024-01-31 12:30:05
+        let row = [Value::Date(19_753), Value::DateTime(1_706_704_205)];
+        let compile = |sql: &str| CompiledExpr::compile(&projection_expr(sql), &column_defs);
+        let eval = |sql: &str| {
+            let compiled = compile(sql).unwrap();
+            let value = compiled.eval(&|idx| Ok(row[idx].clone())).unwrap();
+            (compiled.get_type(&column_defs), value.to_sql_literal())
+        };
+        let literal = |text: &str| match text {
+            "NULL" => text.to_string(),
+            text => format!("'{text}'"),
+        };
+        let date = |text: &str| (ValueType::Date, literal(text));
+        let date_time = |text: &str| (ValueType::DateTime, literal(text));
+
+        assert_eq!(
+            compile("SELECT ts - INTERVAL 7 DAY FROM db.table").unwrap(),
+            CompiledExpr::Function {
+                function: ScalarFunction::SubtractSeconds,
+                args: vec![
+                    CompiledExpr::Column(1),
+                    CompiledExpr::Literal(Value::Int64(604_800))
+                ],
+                return_type: ValueType::DateTime,
+            }
+        );
+        assert_eq!(
+            compile("SELECT d + INTERVAL 2 WEEK FROM db.table").unwrap(),
+            CompiledExpr::Function {
+                function: ScalarFunction::AddDays,
+                args: vec![
+                    CompiledExpr::Column(0),
+                    CompiledExpr::Literal(Value::Int64(14))
+                ],
+                return_type: ValueType::Date,
+            }
+        );
+
+        for (sql, expected) in [
+            ("SELECT addDays(d, 1) FROM db.table", date("2024-02-01")),
+            (
+                "SELECT subtractDays(d, 31) FROM db.table",
+                date("2023-12-31"),
+            ),
+            ("SELECT addMonths(d, 1) FROM db.table", date("2024-02-29")),
+            ("SELECT addYears(d, -1) FROM db.table", date("2023-01-31")),
+            (
+                "SELECT subtractMonths(d, 11) FROM db.table",
+                date("2023-02-28"),
+            ),
+            (
+                "SELECT addHours(d, 1) FROM db.table",
+                date_time("2024-01-31 01:00:00"),
+            ),
+            (
+                "SELECT addSeconds(ts, 55) FROM db.table",
+                date_time("2024-01-31 12:31:00"),
+            ),
+            (
+                "SELECT subtractMinutes(ts, 31) FROM db.table",
+                date_time("2024-01-31 11:59:05"),
+            ),
+            (
+                "SELECT addDays(ts, 1) FROM db.table",
+                date_time("2024-02-01 12:30:05"),
+            ),
+            (
+                "SELECT addMonths(ts, 13) FROM db.table",
+                date_time("2025-02-28 12:30:05"),
+            ),
+            (
+                "SELECT ts + INTERVAL 1 HOUR FROM db.table",
+                date_time("2024-01-31 13:30:05"),
+            ),
+            (
+                "SELECT INTERVAL 1 QUARTER + d FROM db.table",
+                date("2024-04-30"),
+            ),
+            (
+                "SELECT d - INTERVAL 1 YEAR FROM db.table",
+                date("2023-01-31"),
+            ),
+            (
+                "SELECT d + INTERVAL 30 MINUTE FROM db.table",
+                date_time("2024-01-31 00:30:00"),
+            ),
+            // out of range and overflow produce NULL
+            ("SELECT subtractYears(d, 55) FROM db.table", date("NULL")),
+            (
+                "SELECT addDays(d, 9223372036854775807) FROM db.table",
+                date("NULL"),
+            ),
+            (
+                "SELECT subtractSeconds(ts, -9223372036854775808) FROM db.table",
+                date_time("NULL"),
+            ),
+            (
+                "SELECT ts + INTERVAL 9223372036854775807 DAY FROM db.table",
+                date_time("NULL"),
+            ),
+            (
+                "SELECT addHours(ts, 1000000) FROM db.table",
+                date_time("NULL"),
+            ),
+        ] {
+            assert_eq!(eval(sql), expected, "{sql}");
+        }
+
+        for sql in [
+            "SELECT addDays(d, 'a') FROM db.table",
+            "SELECT addDays(1, 1) FROM db.table",
+            "SELECT INTERVAL 1 DAY - d FROM db.table",
+            "SELECT d + INTERVAL 1.5 DAY FROM db.table",
+            "SELECT d + INTERVAL 1 MILLISECOND FROM db.table",
+        ] {
+            assert!(compile(sql).is_err(), "{sql}");
+        }
+    }
+
+    #[test]
+    fn test_eval_json_functions() {
+        let document = r#"{"user": {"name": "Ann", "age": 31, "tags": ["a", "b"]}, "n": 1.5}"#;
+        // JSON is the string of `point` tuple
+        let eval = |function: &str, json: &str, path: &str| {
+            let row = [
+                Value::UInt64(1),
+                Value::Tuple(vec![Value::Int32(0), Value::String(json.to_string())]),
+                Value::Null,
+            ];
+            let sql = format!("SELECT {function}(point.2, '{path}') FROM db.table");
+            CompiledExpr::compile(&projection_expr(&sql), &column_defs())?
+                .eval(&|idx| Ok(row[idx].clone()))
+        };
+        let string = |text: &str| Value::String(text.to_string());
+
+        let expected = [
+            ("JSONExtractString", "$.user.name", string("Ann")),
+            ("JSONExtractString", "$.user.tags[1]", string("b")),
+            ("JSONExtractString", "$.user.age", Value::Null),
+            ("JSONExtractString", "$.user.missing", Value::Null),
+            ("JSONExtractInt", "$.user.age", Value::Int64(31)),
+            ("JSONExtractInt", "$.n", Value::Null),
+            ("JSONExtractInt", "$.user.name", Value::Null),
+            ("JSONHas", "$.user.tags", Value::Bool(true)),
+            ("JSONHas", "$.user.tags[2]", Value::Bool(false)),
+            ("JSONHas", "$", Value::Bool(true)),
+            ("JSONLength", "$.user.tags", Value::UInt64(2)),
+            ("JSONLength", "$.user", Value::UInt64(3)),
+            ("JSONLength", "$.n", Value::Null),
+        ];
+        for (function, path, value) in expected {
+            assert_eq!(
+                eval(function, document, path).unwrap(),
+                value,
+                "{function} {path}"
+            );
+        }
+
+        // malformed JSON is not an error
+        for function in [
+            "JSONExtractString",
+            "JSONExtractInt",
+            "JSONHas",
+            "JSONLength",
+        ] {
+            assert_eq!(eval(function, "{\"a\": ", "$.a").unwrap(), Value::Null);
+        }
+        for path in ["a", "$.", "$..a", "$[x]", "$.a[1"] {
+            assert!(
+                matches!(
+                    eval("JSONHas", document, path),
+                    Err(Error::InvalidFunctionArguments(_))
+                ),
+                "{path}"
+            );
+        }
+        assert!(
+            CompiledExpr::compile(
+                &projection_expr("SELECT JSONHas(id, '$.a') FROM db.table"),
+                &column_defs()
+            )
+            .is_err()
+        );
+    }
+}
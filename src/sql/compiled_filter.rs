@@ -1,7 +1,12 @@
 use crate::error::{Error, Result};
-use crate::storage::{ColumnDef, Value};
+use crate::sql::compiled_expr::CompiledExpr;
+use crate::sql::constant_folding::fold_constants;
+use crate::sql::scalar_function::ScalarFunction;
+use crate::storage::{ColumnDef, Value, ValueType, date};
 use sqlparser::ast::{BinaryOperator, Expr, UnaryOperator, Value as SQLValue};
+use std::cmp::Ordering;
 
+#[derive(Debug, Clone, Copy)]
 pub enum BinOp {
     Gt,
     Lt,
@@ -11,6 +16,7 @@ pub enum BinOp {
     NotEq,
 }
 
+#[derive(Debug, Clone)]
 pub enum CompiledFilter {
     Compare {
         col_idx: usize,
@@ -22,6 +28,14 @@ pub enum CompiledFilter {
         op: BinOp,
         right_idx: usize,
     },
+    /// Compares value computed from columns (e.g., tuple element) with a constant.
+    CompareExpr {
+        expr: CompiledExpr,
+        op: BinOp,
+        value: Value,
+    },
+    /// Boolean expression computed from columns (e.g., `mapContains(col, 'key')`).
+    BoolExpr(CompiledExpr),
     And(Box<CompiledFilter>, Box<CompiledFilter>),
     Or(Box<CompiledFilter>, Box<CompiledFilter>),
     Not(Box<CompiledFilter>),
@@ -52,6 +66,9 @@ impl CompiledFilter {
                     col_def_idxs.push(*right_idx);
                 }
             }
+            CompiledFilter::CompareExpr { expr, .. } | CompiledFilter::BoolExpr(expr) => {
+                expr.get_column_defs(col_def_idxs);
+            }
             CompiledFilter::And(left, right) => {
                 left.get_column_defs(col_def_idxs);
                 right.get_column_defs(col_def_idxs);
@@ -72,6 +89,42 @@ impl CompiledFilter {
         }
     }
 
+    /// Splits filter into a part, which references only primary key columns (`pk_cols` are
+    /// indices of table columns), and a residual part with the other conditions. Conditions are
+    /// taken apart only on `AND`, so `OR`, `NOT` or a function over primary key and other columns
+    /// stays in the residual part as a whole. Rows matching the filter always match both parts.
+    ///
+    /// Returns: `(primary key part, residual part)`. Primary key part is `None`, when no
+    /// condition references only primary key columns. Residual part is `Const(true)`, when all do.
+    pub fn split_pk_prunable(&self, pk_cols: &[usize]) -> (Option<Self>, Self) {
+        match self {
+            CompiledFilter::And(left, right) => {
+                let (left_pk, left_residual) = left.split_pk_prunable(pk_cols);
+                let (right_pk, right_residual) = right.split_pk_prunable(pk_cols);
+
+                let pk = match (left_pk, right_pk) {
+                    (Some(left), Some(right)) => Some(Self::And(Box::new(left), Box::new(right))),
+                    (left, right) => left.or(right),
+                };
+                let residual = match (left_residual, right_residual) {
+                    (Self::Const(true), residual) | (residual, Self::Const(true)) => residual,
+                    (left, right) => Self::And(Box::new(left), Box::new(right)),
+                };
+                (pk, residual)
+            }
+            filter => {
+                let mut col_idxs = Vec::new();
+                filter.get_column_defs(&mut col_idxs);
+
+                if col_idxs.iter().all(|col_idx| pk_cols.contains(col_idx)) {
+                    (Some(filter.clone()), Self::Const(true))
+                } else {
+                    (None, filter.clone())
+                }
+            }
+        }
+    }
+
     /// Allow cmp for
     /// * `Value` and `Value`
     /// * `Value` and `ArchivedValue`
@@ -87,15 +140,32 @@ impl CompiledFilter {
             BinOp::Lt => a < b,
             BinOp::GtEq => a >= b,
             BinOp::LtEq => a <= b,
-            BinOp::Eq => a == b,
-            BinOp::NotEq => a != b,
+            BinOp::Eq => Self::equal(a, b),
+            BinOp::NotEq => !Self::equal(a, b),
         }
     }
 
+    /// Values of different types are unequal, except integers, which are compared by value
+    /// (`Int32(1)` equals `Int64(1)`), like they are ordered by `PartialOrd`.
+    fn equal<T, K>(a: &T, b: &K) -> bool
+    where
+        T: PartialEq<K> + PartialOrd<K>,
+    {
+        a == b || a.partial_cmp(b) == Some(Ordering::Equal)
+    }
+
     /// Compiles a SQL expression into a `CompiledFilter` for efficient evaluation.
     ///
-    /// Supports: AND, OR, NOT, comparison operators, column references, and literal values.
-    /// Performs constant folding for boolean expressions.
+    /// Supports: AND, OR, NOT, comparison operators, `[NOT] BETWEEN`, `[NOT] IN` with a list,
+    /// `[NOT] LIKE`, column references, tuple elements (`col.1`), map values (`col['key']`),
+    /// boolean functions, parentheses and literal values. Negated forms are compiled as `Not` of
+    /// the positive form, so they select exactly the rows, which the positive form filters out.
+    /// Performs constant folding for boolean expressions, and folds literal arithmetic of
+    /// comparison operands (see `fold_constants`), so `ts > 1000 + 500` compares `ts` with `1500`.
+    /// Computed operand without columns is evaluated once (see `eval_constant`), so
+    /// `ts > toDateTime(now()) - INTERVAL 7 DAY` compares `ts` with a literal.
+    /// Negated column is compared as an expression: `-temperature > 10` is
+    /// `CompareExpr { Neg(temperature) > 10 }`.
     ///
     /// Returns:
     ///   * Ok: `CompiledFilter` representing the compiled expression.
@@ -103,6 +173,11 @@ impl CompiledFilter {
     ///     1. Column not found in table: `ColumnNotFound`.
     ///     2. Unsupported expression type: `UnsupportedFilter` or `InvalidSource`.
     ///     3. Value conversion fails: type conversion error.
+    ///     4. Invalid tuple element access: `InvalidTupleAccess`.
+    ///     5. Literal arithmetic could not be folded (overflow, division by zero, literals of
+    ///        different types), or arithmetic with columns is compared with a column:
+    ///        `InvalidSource`.
+    ///     6. `UUID` column is compared with a column of another type: `InvalidSource`.
     pub fn compile(filter: Expr, table_column_defs: &[ColumnDef]) -> Result<Self> {
         match filter {
             Expr::BinaryOp { op, left, right } => match op {
@@ -150,16 +225,29 @@ impl CompiledFilter {
                 }
                 _ => {
                     let op = BinOp::try_from(op)?;
-                    match (*left, *right) {
+                    let left = eval_constant(fold_constants(*left), table_column_defs)?;
+                    let right = eval_constant(fold_constants(*right), table_column_defs)?;
+                    match (left, right) {
+                        (left, right)
+                            if is_literal_arithmetic(&left) || is_literal_arithmetic(&right) =>
+                        {
+                            let expr = if is_literal_arithmetic(&left) {
+                                left
+                            } else {
+                                right
+                            };
+                            Err(Error::InvalidSource(format!(
+                                "Could not evaluate constant arithmetic ({expr}) in filter: it \
+                                 overflows, divides by zero or mixes types of literals"
+                            )))
+                        }
                         (Expr::Identifier(left), Expr::Value(right)) => {
                             let left = table_column_defs
                                 .iter()
                                 .position(|col_def| *col_def.name == left.value)
                                 .ok_or(Error::ColumnNotFound(left.value.clone()))?;
-                            let right = Value::try_from((
-                                right.value,
-                                &table_column_defs[left].field_type,
-                            ))?;
+                            let right =
+                                literal_value(right.value, &table_column_defs[left].field_type)?;
 
                             Ok(Self::Compare {
                                 col_idx: left,
@@ -172,10 +260,8 @@ impl CompiledFilter {
                                 .iter()
                                 .position(|col_def| *col_def.name == right.value)
                                 .ok_or(Error::ColumnNotFound(right.value.clone()))?;
-                            let left = Value::try_from((
-                                left.value,
-                                &table_column_defs[right].field_type,
-                            ))?;
+                            let left =
+                                literal_value(left.value, &table_column_defs[right].field_type)?;
 
                             Ok(Self::Compare {
                                 col_idx: right,
@@ -183,6 +269,24 @@ impl CompiledFilter {
                                 value: left,
                             })
                         }
+                        (left, Expr::Value(right)) if is_computed(&left) => {
+                            let expr = CompiledExpr::compile(&left, table_column_defs)?;
+                            let value =
+                                literal_value(right.value, &expr.get_type(table_column_defs))?;
+
+                            Ok(Self::CompareExpr { expr, op, value })
+                        }
+                        (Expr::Value(left), right) if is_computed(&right) => {
+                            let expr = CompiledExpr::compile(&right, table_column_defs)?;
+                            let value =
+                                literal_value(left.value, &expr.get_type(table_column_defs))?;
+
+                            Ok(Self::CompareExpr {
+                                expr,
+                                op: op.flip(),
+                                value,
+                            })
+                        }
                         (Expr::Value(left), Expr::Value(right)) => {
                             let left = parse_sql_value(left.value)?;
                             let right = parse_sql_value(right.value)?;
@@ -198,12 +302,28 @@ impl CompiledFilter {
                                 .iter()
                                 .position(|col_def| *col_def.name == right.value)
                                 .ok_or(Error::ColumnNotFound(right.value.clone()))?;
+                            // UUID is never equal to, nor ordered with other types, so such
+                            // comparison would silently match nothing
+                            let left_type = &table_column_defs[left_idx].field_type;
+                            let right_type = &table_column_defs[right_idx].field_type;
+                            if (*left_type == ValueType::Uuid) != (*right_type == ValueType::Uuid) {
+                                return Err(Error::InvalidSource(format!(
+                                    "Can not compare {} ({left_type}) with {} ({right_type})",
+                                    left.value, right.value
+                                )));
+                            }
                             Ok(Self::CompareColumns {
                                 left_idx,
                                 op,
                                 right_idx,
                             })
                         }
+                        (left, right) if is_computed(&left) || is_computed(&right) => {
+                            Err(Error::InvalidSource(format!(
+                                "Expression with columns can only be compared with a literal in \
+                                 filter: ({left}) and ({right})"
+                            )))
+                        }
                         (left, right) => Err(Error::InvalidSource(format!(
                             "Unsupported comparison operands in filter: ({left}) and ({right})"
                         ))),
@@ -237,6 +357,81 @@ impl CompiledFilter {
                 .position(|col_def| *col_def.name == ident.value)
                 .map(Self::Column)
                 .ok_or(Error::ColumnNotFound(ident.value.clone())),
+            Expr::Nested(expr) => Self::compile(*expr, table_column_defs),
+            Expr::Between {
+                expr,
+                negated,
+                low,
+                high,
+            } => {
+                let between = Expr::BinaryOp {
+                    left: Box::new(Expr::BinaryOp {
+                        left: expr.clone(),
+                        op: BinaryOperator::GtEq,
+                        right: low,
+                    }),
+                    op: BinaryOperator::And,
+                    right: Box::new(Expr::BinaryOp {
+                        left: expr,
+                        op: BinaryOperator::LtEq,
+                        right: high,
+                    }),
+                };
+                Self::compile(between, table_column_defs).map(|filter| filter.negate_if(negated))
+            }
+            Expr::InList {
+                expr,
+                list,
+                negated,
+            } => {
+                let comparisons = list
+                    .into_iter()
+                    .map(|item| {
+                        Self::compile(
+                            Expr::BinaryOp {
+                                left: expr.clone(),
+                                op: BinaryOperator::Eq,
+                                right: Box::new(item),
+                            },
+                            table_column_defs,
+                        )
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(Self::any_of(comparisons).negate_if(negated))
+            }
+            Expr::Like {
+                negated,
+                any: false,
+                expr,
+                pattern,
+                escape_char: None,
+            } => {
+                let expr = CompiledExpr::compile(&expr, table_column_defs)?;
+                let pattern = match *pattern {
+                    Expr::Value(pattern) => CompiledExpr::Literal(parse_sql_value(pattern.value)?),
+                    pattern => CompiledExpr::compile(&pattern, table_column_defs)?,
+                };
+                let arg_types = [
+                    expr.get_type(table_column_defs),
+                    pattern.get_type(table_column_defs),
+                ];
+                let return_type = ScalarFunction::Like.get_return_type(&arg_types)?;
+                let like = Self::BoolExpr(CompiledExpr::Function {
+                    function: ScalarFunction::Like,
+                    args: vec![expr, pattern],
+                    return_type,
+                });
+                Ok(like.negate_if(negated))
+            }
+            expr @ Expr::Function(_) => {
+                let expr = CompiledExpr::compile(&expr, table_column_defs)?;
+                if expr.get_type(table_column_defs) != ValueType::Bool {
+                    return Err(Error::UnsupportedFilter(format!(
+                        "Filter expression must be boolean: {expr:?}"
+                    )));
+                }
+                Ok(Self::BoolExpr(expr))
+            }
             expr => Err(Error::UnsupportedFilter(format!(
                 "Unsupported expression type in filter: {expr}"
             ))),
@@ -244,6 +439,37 @@ impl CompiledFilter {
     }
 }
 
+impl CompiledFilter {
+    fn negate_if(self, negated: bool) -> Self {
+        match (negated, self) {
+            (false, filter) => filter,
+            (true, Self::Const(value)) => Self::Const(!value),
+            (true, filter) => Self::Not(Box::new(filter)),
+        }
+    }
+
+    /// Joins filters with `OR` into a balanced tree, so that long `IN` lists do not recurse
+    /// deeply during evaluation. Empty list matches nothing.
+    fn any_of(mut filters: Vec<Self>) -> Self {
+        if filters
+            .iter()
+            .any(|filter| matches!(filter, Self::Const(true)))
+        {
+            return Self::Const(true);
+        }
+        filters.retain(|filter| !matches!(filter, Self::Const(false)));
+
+        if filters.len() <= 1 {
+            return filters.pop().unwrap_or(Self::Const(false));
+        }
+        let right = filters.split_off(filters.len() / 2);
+        Self::Or(
+            Box::new(Self::any_of(filters)),
+            Box::new(Self::any_of(right)),
+        )
+    }
+}
+
 impl TryFrom<BinaryOperator> for BinOp {
     type Error = Error;
 
@@ -273,6 +499,103 @@ impl BinOp {
     }
 }
 
+/// Whether expression is arithmetic of literals only, e.g., `1 / 0`, which `fold_constants` left
+/// as is.
+fn is_literal_arithmetic(expr: &Expr) -> bool {
+    fn is_literal(expr: &Expr) -> bool {
+        match expr {
+            Expr::Value(_) => true,
+            Expr::Nested(inner) | Expr::UnaryOp { expr: inner, .. } => is_literal(inner),
+            Expr::BinaryOp { left, right, .. } => is_literal(left) && is_literal(right),
+            _ => false,
+        }
+    }
+
+    let expr = match expr {
+        Expr::Nested(inner) => inner.as_ref(),
+        expr => expr,
+    };
+    matches!(
+        expr,
+        Expr::BinaryOp {
+            op: BinaryOperator::Plus
+                | BinaryOperator::Minus
+                | BinaryOperator::Multiply
+                | BinaryOperator::Divide
+                | BinaryOperator::Modulo,
+            ..
+        } | Expr::UnaryOp {
+            op: UnaryOperator::Minus | UnaryOperator::Plus,
+            ..
+        }
+    ) && is_literal(expr)
+}
+
+/// Whether expression is computed from columns with `CompiledExpr`, e.g., tuple element, function,
+/// arithmetic or negation (`-col`).
+fn is_computed(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::CompoundIdentifier(_)
+            | Expr::CompoundFieldAccess { .. }
+            | Expr::Function(_)
+            | Expr::Nested(_)
+            | Expr::BinaryOp {
+                op: BinaryOperator::Plus
+                    | BinaryOperator::Minus
+                    | BinaryOperator::Multiply
+                    | BinaryOperator::Divide
+                    | BinaryOperator::Modulo,
+                ..
+            }
+            | Expr::UnaryOp {
+                op: UnaryOperator::Minus,
+                ..
+            }
+    )
+}
+
+/// Evaluates computed expression without columns, e.g., `toDateTime(now()) - INTERVAL 7 DAY`,
+/// and replaces it with the literal of its value, so it is compared as a literal. Expressions,
+/// which could not be compiled, and values without a literal (e.g., tuples), are kept as is.
+///
+/// Returns:
+///   * Ok: literal of the value, or the expression as is.
+///   * Error: evaluation fails, e.g., `toDate('not a date')`.
+fn eval_constant(expr: Expr, table_column_defs: &[ColumnDef]) -> Result<Expr> {
+    if !is_computed(&expr) {
+        return Ok(expr);
+    }
+    let Ok(compiled) = CompiledExpr::compile(&expr, table_column_defs) else {
+        return Ok(expr);
+    };
+    let mut col_idxs = Vec::new();
+    compiled.get_column_defs(&mut col_idxs);
+    if !col_idxs.is_empty() {
+        return Ok(expr);
+    }
+
+    let value = compiled.eval(&|col_idx| {
+        Err(Error::Internal(format!(
+            "Constant expression reads column {col_idx}"
+        )))
+    })?;
+    let literal = match value {
+        Value::Null => SQLValue::Null,
+        Value::Bool(flag) => SQLValue::Boolean(flag),
+        Value::String(string) => SQLValue::SingleQuotedString(string),
+        Value::Date(days) => SQLValue::SingleQuotedString(date::format_days(days.into())),
+        Value::DateTime(seconds) => {
+            SQLValue::SingleQuotedString(date::format_seconds(seconds.into()))
+        }
+        value => match value.as_i128() {
+            Some(integer) => SQLValue::Number(integer.to_string(), false),
+            None => return Ok(expr),
+        },
+    };
+    Ok(Expr::Value(literal.with_empty_span()))
+}
+
 fn parse_sql_value(value: SQLValue) -> Result<Value> {
     match value {
         SQLValue::Null => Ok(Value::Null),
@@ -290,3 +613,482 @@ fn parse_sql_value(value: SQLValue) -> Result<Value> {
         ))),
     }
 }
+
+/// Converts literal compared with a value of `value_type`. Integer literal, which does not fit
+/// into the integer type (e.g., `1000` compared with `Int8`), is kept as `Int64` or `UInt64`, so
+/// it is compared by value, instead of failing the query.
+///
+/// Returns:
+///   * Ok: `Value` of the literal.
+///   * Error: `InvalidSource` when literal is not a value of `value_type`.
+fn literal_value(value: SQLValue, value_type: &ValueType) -> Result<Value> {
+    match Value::try_from((value.clone(), value_type)) {
+        Err(err) if value_type.is_integer() && matches!(value, SQLValue::Number(..)) => {
+            Value::try_from((value.clone(), &ValueType::Int64))
+                .or_else(|_| Value::try_from((value, &ValueType::UInt64)))
+                .map_err(|_| err)
+        }
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::execution::select::GranuleBuffer;
+    use crate::storage::Constraints;
+    use proptest::prelude::*;
+
+    /// Types of the generated table columns, each column is nullable.
+    const COLUMN_TYPES: [ValueType; 4] = [
+        ValueType::Int64,
+        ValueType::Int64,
+        ValueType::String,
+        ValueType::Bool,
+    ];
+    const BOOL_COL_IDX: usize = 3;
+
+    fn table_col_defs() -> Vec<ColumnDef> {
+        COLUMN_TYPES
+            .iter()
+            .enumerate()
+            .map(|(idx, field_type)| ColumnDef {
+                name: format!("c{idx}"),
+                field_type: field_type.clone(),
+                constraints: Constraints {
+                    nullable: true,
+                    ..Constraints::default()
+                },
+            })
+            .collect()
+    }
+
+    /// Small domains, so that equal values and `NULL`s are common.
+    fn value(field_type: &ValueType) -> BoxedStrategy<Value> {
+        let value = match field_type {
+            ValueType::Int64 => (-3..3_i64).prop_map(Value::Int64).boxed(),
+            ValueType::String => prop_oneof![Just(""), Just("a"), Just("ab"), Just("b")]
+                .prop_map(|string| Value::String(string.to_string()))
+                .boxed(),
+            ValueType::Bool => any::<bool>().prop_map(Value::Bool).boxed(),
+            field_type => unreachable!("no values generated for {field_type:?}"),
+        };
+        prop_oneof![1 => Just(Value::Null), 4 => value].boxed()
+    }
+
+    fn rows() -> impl Strategy<Value = Vec<Vec<Value>>> {
+        let row = COLUMN_TYPES.iter().map(value).collect::<Vec<_>>();
+        prop::collection::vec(row, 0..64)
+    }
+
+    fn bin_op() -> impl Strategy<Value = BinOp> {
+        prop_oneof![
+            Just(BinOp::Gt),
+            Just(BinOp::Lt),
+            Just(BinOp::GtEq),
+            Just(BinOp::LtEq),
+            Just(BinOp::Eq),
+            Just(BinOp::NotEq),
+        ]
+    }
+
+    fn col_idx() -> impl Strategy<Value = usize> {
+        0..COLUMN_TYPES.len()
+    }
+
+    fn filter() -> impl Strategy<Value = CompiledFilter> {
+        let leaf = prop_oneof![
+            (col_idx(), bin_op()).prop_flat_map(|(col_idx, op)| {
+                value(&COLUMN_TYPES[col_idx]).prop_map(move |value| CompiledFilter::Compare {
+                    col_idx,
+                    op,
+                    value,
+                })
+            }),
+            // columns of different types are compared as well
+            (col_idx(), bin_op(), col_idx()).prop_map(|(left_idx, op, right_idx)| {
+                CompiledFilter::CompareColumns {
+                    left_idx,
+                    op,
+                    right_idx,
+                }
+            }),
+            (col_idx(), bin_op()).prop_flat_map(|(col_idx, op)| {
+                value(&COLUMN_TYPES[col_idx]).prop_map(move |value| CompiledFilter::CompareExpr {
+                    expr: CompiledExpr::Column(col_idx),
+                    op,
+                    value,
+                })
+            }),
+            Just(BOOL_COL_IDX)
+                .prop_map(|col_idx| CompiledFilter::BoolExpr(CompiledExpr::Column(col_idx))),
+            col_idx().prop_map(CompiledFilter::Column),
+            any::<bool>().prop_map(CompiledFilter::Const),
+        ];
+
+        leaf.prop_recursive(4, 32, 2, |inner| {
+            prop_oneof![
+                (inner.clone(), inner.clone())
+                    .prop_map(|(left, right)| CompiledFilter::And(Box::new(left), Box::new(right))),
+                (inner.clone(), inner.clone())
+                    .prop_map(|(left, right)| CompiledFilter::Or(Box::new(left), Box::new(right))),
+                inner.prop_map(|inner| CompiledFilter::Not(Box::new(inner))),
+            ]
+        })
+    }
+
+    /// Reference implementation: evaluates filter on a single row of deserialized values.
+    fn eval_row(filter: &CompiledFilter, row: &[Value]) -> bool {
+        match filter {
+            CompiledFilter::Compare { col_idx, op, value } => {
+                CompiledFilter::cmp_vals(&row[*col_idx], value, op)
+            }
+            CompiledFilter::CompareColumns {
+                left_idx,
+                op,
+                right_idx,
+            } => CompiledFilter::cmp_vals(&row[*left_idx], &row[*right_idx], op),
+            CompiledFilter::CompareExpr { expr, op, value } => {
+                let row_value = expr.eval(&|col_idx| Ok(row[col_idx].clone())).unwrap();
+                CompiledFilter::cmp_vals(&row_value, value, op)
+            }
+            CompiledFilter::BoolExpr(expr) => {
+                expr.eval(&|col_idx| Ok(row[col_idx].clone())).unwrap() == Value::Bool(true)
+            }
+            CompiledFilter::And(left, right) => eval_row(left, row) && eval_row(right, row),
+            CompiledFilter::Or(left, right) => eval_row(left, row) || eval_row(right, row),
+            CompiledFilter::Not(inner) => !eval_row(inner, row),
+            // non-boolean values (including `NULL`) pass the filter
+            CompiledFilter::Column(col_idx) => !matches!(row[*col_idx], Value::Bool(false)),
+            CompiledFilter::Const(value) => *value,
+        }
+    }
+
+    fn selection(sql: &str) -> Expr {
+        let statements = crate::sql::sql_parser::parse_statements(sql).unwrap();
+        let sqlparser::ast::Statement::Query(query) = &statements[0] else {
+            panic!("Expected query");
+        };
+        let sqlparser::ast::SetExpr::Select(select) = query.body.as_ref() else {
+            panic!("Expected select");
+        };
+        select.selection.clone().unwrap()
+    }
+
+    #[test]
+    fn test_compile_negated_between_in_like() {
+        let col_defs = table_col_defs();
+        let rows = [
+            (1, "Alice"),
+            (2, "Bob"),
+            (3, "Anna"),
+            (4, "a_b"),
+            (5, "%"),
+            (6, ""),
+            (7, "axb"),
+        ]
+        .map(|(id, name)| {
+            vec![
+                Value::Int64(id),
+                Value::Null,
+                Value::String(name.to_string()),
+                Value::Null,
+            ]
+        });
+        let matching = |condition: &str| {
+            let sql = format!("SELECT * FROM db.t WHERE {condition}");
+            let filter = CompiledFilter::compile(selection(&sql), &col_defs).unwrap();
+            rows.iter()
+                .filter(|row| eval_row(&filter, row))
+                .map(|row| row[0].clone())
+                .collect::<Vec<_>>()
+        };
+        let ids = |ids: &[i64]| ids.iter().copied().map(Value::Int64).collect::<Vec<_>>();
+
+        for (condition, expected) in [
+            ("c0 BETWEEN 2 AND 4", ids(&[2, 3, 4])),
+            ("c0 IN (1, 2, 3)", ids(&[1, 2, 3])),
+            ("c0 IN (5)", ids(&[5])),
+            ("c2 LIKE 'A%'", ids(&[1, 3])),
+            ("c2 LIKE '%b'", ids(&[2, 4, 7])),
+            ("c2 LIKE '_'", ids(&[5])),
+            ("c2 LIKE 'a\\\\_b'", ids(&[4])),
+            ("c2 LIKE '\\\\%'", ids(&[5])),
+            ("c2 LIKE '%'", ids(&[1, 2, 3, 4, 5, 6, 7])),
+            ("c2 LIKE 'A%n%a'", ids(&[3])),
+        ] {
+            assert_eq!(matching(condition), expected, "{condition}");
+
+            let negated = condition
+                .replacen(" BETWEEN", " NOT BETWEEN", 1)
+                .replacen(" IN", " NOT IN", 1)
+                .replacen(" LIKE", " NOT LIKE", 1);
+            let complement = ids(&[1, 2, 3, 4, 5, 6, 7])
+                .into_iter()
+                .filter(|id| !expected.contains(id))
+                .collect::<Vec<_>>();
+            assert_eq!(matching(&negated), complement, "{negated}");
+        }
+
+        assert!(matches!(
+            CompiledFilter::compile(selection("SELECT * FROM db.t WHERE c0 LIKE 'a'"), &col_defs),
+            Err(Error::InvalidFunctionArguments(_))
+        ));
+    }
+
+    #[test]
+    fn test_compare_integers_of_different_types() {
+        let col_defs = [ValueType::Int8, ValueType::Int64, ValueType::UInt8]
+            .into_iter()
+            .enumerate()
+            .map(|(idx, field_type)| ColumnDef {
+                name: format!("c{idx}"),
+                field_type,
+                constraints: Constraints::default(),
+            })
+            .collect::<Vec<_>>();
+        let rows = [(-5, -5, 0), (1, 300, 1), (100, 100, 255)]
+            .map(|(a, b, c)| vec![Value::Int8(a), Value::Int64(b), Value::UInt8(c)]);
+        let matching = |condition: &str| {
+            let sql = format!("SELECT * FROM db.t WHERE {condition}");
+            let filter = CompiledFilter::compile(selection(&sql), &col_defs).unwrap();
+            rows.iter()
+                .filter(|row| eval_row(&filter, row))
+                .map(|row| row[0].clone())
+                .collect::<Vec<_>>()
+        };
+        let ids = |ids: &[i8]| ids.iter().copied().map(Value::Int8).collect::<Vec<_>>();
+
+        for (condition, expected) in [
+            ("c0 = c1", ids(&[-5, 100])),
+            ("c0 != c1", ids(&[1])),
+            ("c0 < c1", ids(&[1])),
+            ("c0 >= c2", ids(&[1])),
+            ("c0 < 1000", ids(&[-5, 1, 100])),
+            ("c0 = 1000", ids(&[])),
+            ("c2 < 18446744073709551615", ids(&[-5, 1, 100])),
+            ("c1 BETWEEN c0 AND 300", ids(&[-5, 1, 100])),
+        ] {
+            assert_eq!(matching(condition), expected, "{condition}");
+        }
+        assert!(
+            CompiledFilter::compile(selection("SELECT * FROM db.t WHERE c0 < 1e3"), &col_defs)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_compile_folds_literal_arithmetic() {
+        let col_defs = table_col_defs();
+        let compile = |condition: &str| {
+            CompiledFilter::compile(
+                selection(&format!("SELECT * FROM db.t WHERE {condition}")),
+                &col_defs,
+            )
+        };
+
+        for (condition, expected_op, expected) in [
+            ("c0 > 1000 + 500", BinOp::Gt, 1500),
+            ("(2 * 3) - 1 <= c0", BinOp::GtEq, 5),
+            ("c0 = -(10 / 3)", BinOp::Eq, -3),
+        ] {
+            let Ok(CompiledFilter::Compare {
+                col_idx: 0,
+                op,
+                value,
+            }) = compile(condition)
+            else {
+                panic!("Expected comparison with a literal: {condition}");
+            };
+            assert_eq!(
+                (format!("{op:?}"), value),
+                (format!("{expected_op:?}"), Value::Int64(expected)),
+                "{condition}"
+            );
+        }
+        assert!(matches!(
+            compile("1 + 1 = 2"),
+            Ok(CompiledFilter::Const(true))
+        ));
+
+        for condition in ["c0 > 1 / 0", "c0 < 1.5 + 1", "c0 = (1 + c0)", "c0 + 1 > c0"] {
+            let error = compile(condition).unwrap_err();
+            assert!(
+                matches!(error, Error::InvalidSource(_)),
+                "{condition}: {error}"
+            );
+        }
+        let error = compile("c0 > 1 % 0").unwrap_err().to_string();
+        assert!(error.contains("constant arithmetic (1 % 0)"), "{error}");
+    }
+
+    #[test]
+    fn test_compile_unary_minus() {
+        let col_defs = [ValueType::Int8, ValueType::UInt8]
+            .into_iter()
+            .enumerate()
+            .map(|(idx, field_type)| ColumnDef {
+                name: format!("c{idx}"),
+                field_type,
+                constraints: Constraints::default(),
+            })
+            .collect::<Vec<_>>();
+        let rows = [-128, -20, -10, 0, 5, 20]
+            .map(|value| vec![Value::Int8(value), Value::UInt8(value.unsigned_abs())]);
+        let compile = |condition: &str| {
+            CompiledFilter::compile(
+                selection(&format!("SELECT * FROM db.t WHERE {condition}")),
+                &col_defs,
+            )
+        };
+        let matching = |condition: &str| {
+            let filter = compile(condition).unwrap();
+            rows.iter()
+                .filter(|row| eval_row(&filter, row))
+                .map(|row| row[0].clone())
+                .collect::<Vec<_>>()
+        };
+        let ids = |ids: &[i8]| ids.iter().copied().map(Value::Int8).collect::<Vec<_>>();
+
+        for (condition, expected) in [
+            // negation of -128 wraps around to -128
+            ("-c0 > 10", ids(&[-20])),
+            ("c0 < -10", ids(&[-128, -20])),
+            ("10 < -c0", ids(&[-20])),
+            ("-c0 = -(5)", ids(&[5])),
+            ("-(c0) <= -20", ids(&[-128, 20])),
+            ("-c0 IN (10, 0)", ids(&[-10, 0])),
+        ] {
+            assert_eq!(matching(condition), expected, "{condition}");
+        }
+        assert!(matches!(
+            compile("-c0 > 10"),
+            Ok(CompiledFilter::CompareExpr {
+                expr: CompiledExpr::Neg(_),
+                ..
+            })
+        ));
+
+        for condition in ["-c1 > 10", "-c0 > c0"] {
+            let error = compile(condition).unwrap_err();
+            assert!(
+                matches!(error, Error::InvalidSource(_)),
+                "{condition}: {error}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_split_pk_prunable() {
+        let col_defs = table_col_defs();
+        let compile = |condition: &str| {
+            let sql = format!("SELECT * FROM db.t WHERE {condition}");
+            CompiledFilter::compile(selection(&sql), &col_defs).unwrap()
+        };
+        // `c0` and `c1` are in primary key
+        let split = |condition: &str| {
+            let (pk, residual) = compile(condition).split_pk_prunable(&[0, 1]);
+            (pk.map(|pk| format!("{pk:?}")), format!("{residual:?}"))
+        };
+        let debug = |condition: &str| format!("{:?}", compile(condition));
+
+        for (condition, pk, residual) in [
+            (
+                "c0 > 1 AND c2 = 'a' AND c1 < 2",
+                "c0 > 1 AND c1 < 2",
+                "c2 = 'a'",
+            ),
+            ("c0 = c1 AND c3", "c0 = c1", "c3"),
+            (
+                "(c0 = 1 OR c1 = 2) AND NOT c2 = 'a'",
+                "c0 = 1 OR c1 = 2",
+                "NOT c2 = 'a'",
+            ),
+            (
+                "c0 IN (1, 2) AND c3 AND c2 LIKE 'a%'",
+                "c0 IN (1, 2)",
+                "c3 AND c2 LIKE 'a%'",
+            ),
+        ] {
+            assert_eq!(
+                split(condition),
+                (Some(debug(pk)), debug(residual)),
+                "{condition}"
+            );
+        }
+
+        assert_eq!(
+            split("c0 > 1 AND c1 < 2"),
+            (Some(debug("c0 > 1 AND c1 < 2")), debug("true"))
+        );
+        for condition in [
+            "c2 = 'a'",
+            "c0 = 1 OR c2 = 'a'",
+            "NOT (c0 = 1 AND c2 = 'a')",
+            "c0 = c2",
+        ] {
+            assert_eq!(split(condition), (None, debug(condition)), "{condition}");
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_eval_filter_vectorized_matches_row_by_row(rows in rows(), filter in filter()) {
+            let col_defs = table_col_defs();
+            let granule_data = (0..col_defs.len())
+                .map(|col_idx| {
+                    let values = rows.iter().map(|row| row[col_idx].clone()).collect::<Vec<_>>();
+                    Some(rkyv::to_bytes::<rkyv::rancor::Error>(&values).unwrap().to_vec())
+                })
+                .collect::<Vec<_>>();
+
+            let expected = rows.iter().map(|row| eval_row(&filter, row)).collect::<Vec<_>>();
+            let mask = GranuleBuffer::eval_filter_vectorized(
+                &filter,
+                &granule_data,
+                &col_defs,
+                &col_defs,
+                rows.len(),
+            )
+            .unwrap();
+
+            prop_assert_eq!(mask, expected);
+        }
+
+        #[test]
+        fn test_selection_vectors_match_full_masks(
+            rows in rows(),
+            filter in filter(),
+            has_data in prop::collection::vec(any::<bool>(), COLUMN_TYPES.len()),
+        ) {
+            let col_defs = table_col_defs();
+            // columns without data, e.g., added after the part was written
+            let granule_data = (0..col_defs.len())
+                .map(|col_idx| {
+                    let values = rows.iter().map(|row| row[col_idx].clone()).collect::<Vec<_>>();
+                    has_data[col_idx]
+                        .then(|| rkyv::to_bytes::<rkyv::rancor::Error>(&values).unwrap().to_vec())
+                })
+                .collect::<Vec<_>>();
+
+            let full = GranuleBuffer::eval_filter_full(
+                &filter,
+                &granule_data,
+                &col_defs,
+                &col_defs,
+                rows.len(),
+            )
+            .unwrap();
+            let selected = GranuleBuffer::eval_filter_vectorized(
+                &filter,
+                &granule_data,
+                &col_defs,
+                &col_defs,
+                rows.len(),
+            )
+            .unwrap();
+
+            prop_assert_eq!(selected, full);
+        }
+    }
+}
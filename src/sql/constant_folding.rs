@@ -0,0 +1,172 @@
+use sqlparser::ast::{BinaryOperator, Expr, UnaryOperator, Value as SQLValue};
+
+/// Replaces operations on literals with their result: arithmetic of integers (e.g., `2 + 3` with
+/// `5`, `-(1 + 2)` with `-3` and `7 / 2` with `3`, as `CompiledExpr` divides integers), comparison of integers, strings or booleans (`2 > 1` with
+/// `true`), `AND`, `OR` and `NOT` of booleans. Other expressions are kept as is, including
+/// arithmetic, which overflows or divides by zero, and operations on literals of different types.
+pub fn fold_constants(expr: Expr) -> Expr {
+    match expr {
+        Expr::BinaryOp { left, op, right } => {
+            let left = fold_constants(*left);
+            let right = fold_constants(*right);
+
+            if let Some(result) = eval_binary(&left, &op, &right) {
+                return result;
+            }
+            Expr::BinaryOp {
+                left: Box::new(left),
+                op,
+                right: Box::new(right),
+            }
+        }
+        Expr::UnaryOp { op, expr } => {
+            // operand of an operator is not a tuple, so `-(7)` is `-7`
+            let expr = match fold_constants(*expr) {
+                Expr::Nested(inner) if matches!(inner.as_ref(), Expr::Value(_)) => *inner,
+                expr => expr,
+            };
+
+            let folded = match (&op, as_integer(&expr), as_bool(&expr)) {
+                (UnaryOperator::Minus, Some(value), _) => value.checked_neg().map(integer_literal),
+                (UnaryOperator::Plus, Some(value), _) => Some(integer_literal(value)),
+                (UnaryOperator::Not, _, Some(value)) => Some(bool_literal(!value)),
+                _ => None,
+            };
+            folded.unwrap_or_else(|| Expr::UnaryOp {
+                op,
+                expr: Box::new(expr),
+            })
+        }
+        // `(7)` is kept, as it is also a single element tuple
+        Expr::Nested(inner) if !matches!(inner.as_ref(), Expr::Value(_)) => {
+            let inner = fold_constants(*inner);
+            if as_integer(&inner).is_some() || as_bool(&inner).is_some() {
+                inner
+            } else {
+                Expr::Nested(Box::new(inner))
+            }
+        }
+        expr => expr,
+    }
+}
+
+/// Returns result of `op`, when both operands are literals of the same type.
+fn eval_binary(left: &Expr, op: &BinaryOperator, right: &Expr) -> Option<Expr> {
+    if let (Some(left), Some(right)) = (as_integer(left), as_integer(right)) {
+        return match eval_arithmetic(left, op, right) {
+            Some(result) => Some(integer_literal(result)),
+            None => compare(&left, op, &right).map(bool_literal),
+        };
+    }
+    if let (Some(left), Some(right)) = (as_string(left), as_string(right)) {
+        return compare(left, op, right).map(bool_literal);
+    }
+    if let (Some(left), Some(right)) = (as_bool(left), as_bool(right)) {
+        let result = match op {
+            BinaryOperator::And => Some(left && right),
+            BinaryOperator::Or => Some(left || right),
+            op => compare(&left, op, &right),
+        };
+        return result.map(bool_literal);
+    }
+    None
+}
+
+fn as_integer(expr: &Expr) -> Option<i128> {
+    match expr {
+        Expr::Value(value) => match &value.value {
+            SQLValue::Number(number, _) => number.parse().ok(),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn as_string(expr: &Expr) -> Option<&str> {
+    match expr {
+        Expr::Value(value) => match &value.value {
+            SQLValue::SingleQuotedString(string) => Some(string),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn as_bool(expr: &Expr) -> Option<bool> {
+    match expr {
+        Expr::Value(value) => match value.value {
+            SQLValue::Boolean(value) => Some(value),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn integer_literal(value: i128) -> Expr {
+    Expr::Value(SQLValue::Number(value.to_string(), false).with_empty_span())
+}
+
+fn bool_literal(value: bool) -> Expr {
+    Expr::Value(SQLValue::Boolean(value).with_empty_span())
+}
+
+fn eval_arithmetic(left: i128, op: &BinaryOperator, right: i128) -> Option<i128> {
+    match op {
+        BinaryOperator::Plus => left.checked_add(right),
+        BinaryOperator::Minus => left.checked_sub(right),
+        BinaryOperator::Multiply => left.checked_mul(right),
+        BinaryOperator::Divide => left.checked_div(right),
+        BinaryOperator::Modulo => left.checked_rem(right),
+        _ => None,
+    }
+}
+
+fn compare<T: PartialOrd + ?Sized>(left: &T, op: &BinaryOperator, right: &T) -> Option<bool> {
+    match op {
+        BinaryOperator::Eq => Some(left == right),
+        BinaryOperator::NotEq => Some(left != right),
+        BinaryOperator::Lt => Some(left < right),
+        BinaryOperator::LtEq => Some(left <= right),
+        BinaryOperator::Gt => Some(left > right),
+        BinaryOperator::GtEq => Some(left >= right),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::sql_parser::parse_expr;
+
+    fn fold(sql: &str) -> String {
+        fold_constants(parse_expr(sql).unwrap()).to_string()
+    }
+
+    #[test]
+    fn test_fold_constants() {
+        assert_eq!(fold("0 + 1"), "1");
+        assert_eq!(fold("2 * (3 - 5)"), "-4");
+        assert_eq!(fold("-(1 + 2)"), "-3");
+        assert_eq!(fold("7 % 4 + id"), "3 + id");
+        assert_eq!(fold("(7)"), "(7)");
+        assert_eq!(fold("-(7)"), "-7");
+        assert_eq!(fold("1 % 0"), "1 % 0");
+        assert_eq!(fold("7 / 2 - 10 / -3"), "6");
+        assert_eq!(fold("1 / 0"), "1 / 0");
+        assert_eq!(fold("now()"), "now()");
+    }
+
+    #[test]
+    fn test_fold_constants_in_filter() {
+        assert_eq!(fold("id > 2 + 3"), "id > 5");
+        assert_eq!(fold("id >= -(4 * 2)"), "id >= -8");
+        assert_eq!(fold("id = 1 AND 2 > 1"), "id = 1 AND true");
+        assert_eq!(fold("NOT (1 = 2) OR id"), "true OR id");
+        assert_eq!(fold("'a' < 'b' AND NOT false"), "true");
+        assert_eq!(fold("true AND (false OR true)"), "true");
+        assert_eq!(fold("1 = 1.0"), "1 = 1.0");
+        assert_eq!(fold("1 = 'a'"), "1 = 'a'");
+        assert_eq!(fold("NULL = NULL"), "NULL = NULL");
+        assert_eq!(fold("NOT id"), "NOT id");
+    }
+}
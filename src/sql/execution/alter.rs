@@ -0,0 +1,31 @@
+use crate::background_merge::BackgroundMerge;
+use crate::error::{Error, Result};
+use crate::runtime_config::TABLE_DATA;
+use crate::sql::CommandRunner;
+use crate::storage::table_metadata::flags;
+use crate::storage::{OutputTable, TableDef};
+
+impl CommandRunner {
+    /// Sets and clears flags of the table (see `flags`) and writes its metadata.
+    ///
+    /// When `flags::NO_MERGES` is set, running merges of the table are awaited first, so no
+    /// part is rewritten after the statement returns.
+    ///
+    /// Returns:
+    ///   * Ok: `OutputTable` with success status.
+    ///   * Error: `TableNotFound`, or `CouldNotInsertData` when metadata could not be written.
+    pub fn modify_table_flags(table_def: &TableDef, set: u32, clear: u32) -> Result<OutputTable> {
+        let _merges = (set & flags::NO_MERGES != 0)
+            .then(|| BackgroundMerge::exclude_tables(std::slice::from_ref(table_def)));
+
+        let Some(mut table_config) = TABLE_DATA.get_mut(table_def) else {
+            return Err(Error::TableNotFound);
+        };
+        let mut metadata = table_config.metadata.clone();
+        metadata.flags = (metadata.flags | set) & !clear;
+        metadata.write_to(table_def)?;
+        table_config.metadata = metadata;
+
+        Ok(OutputTable::build_ok())
+    }
+}
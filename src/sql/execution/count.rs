@@ -0,0 +1,37 @@
+use crate::error::{Error, Result};
+use crate::runtime_config::TABLE_DATA;
+use crate::sql::CommandRunner;
+use crate::storage::{Column, ColumnDef, Constraints, OutputTable, TableDef, Value, ValueType};
+
+impl CommandRunner {
+    /// Counts rows of the table by summing `row_count` of its active parts (see
+    /// `TableConfig::total_rows`), so no column file is opened. Rows of `ReplacingMergeTree`
+    /// parts, which are not merged yet, are counted with their duplicates.
+    ///
+    /// Returns:
+    ///   * Ok: `OutputTable` with single `count()` column (`UInt64`) and row.
+    ///   * Error: `TableNotFound` when table does not exist, `CouldNotReadData` when `row_count`
+    ///     of some part can not be trusted (see `TablePartInfo::suspect`).
+    pub fn count_star(table_def: &TableDef) -> Result<OutputTable> {
+        let table_config = TABLE_DATA.get(table_def).ok_or(Error::TableNotFound)?;
+        if let Some(reason) = table_config
+            .infos
+            .iter()
+            .find_map(|info| info.suspect.as_ref())
+        {
+            return Err(Error::CouldNotReadData(format!(
+                "count(*) of table {table_def} relies on row count of a suspect part: {reason}"
+            )));
+        }
+        let rows = table_config.total_rows();
+
+        Ok(OutputTable::new(vec![Column {
+            column_def: ColumnDef {
+                name: "count()".to_string(),
+                field_type: ValueType::UInt64,
+                constraints: Constraints::default(),
+            },
+            data: vec![Value::UInt64(rows)].into(),
+        }]))
+    }
+}
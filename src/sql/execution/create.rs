@@ -11,21 +11,23 @@ impl CommandRunner {
     /// Creates a database directory.
     ///
     /// Returns:
-    ///   * Ok: `OutputTable` with success status
+    ///   * Ok: `OutputTable` with success status and name of the created object
     ///   * Error: `InvalidDatabaseName` if directory creation fails
     pub fn create_database(name: String) -> Result<OutputTable> {
         if !validate_name(&name) {
             return Err(Error::InvalidDatabaseName);
         }
-        std::fs::create_dir(CONFIG.get_db_dir().join(name)).map_err(|error| {
+        std::fs::create_dir(CONFIG.get_db_dir().join(&name)).map_err(|error| {
             match error.kind() {
                 std::io::ErrorKind::AlreadyExists => Error::DatabaseAlreadyExists,
-                std::io::ErrorKind::PermissionDenied => Error::PermissionDenied,
+                std::io::ErrorKind::PermissionDenied => {
+                    Error::PermissionDenied("could not create database directory".to_string())
+                }
                 _ => Error::InvalidDatabaseName,
             }
         })?;
 
-        Ok(OutputTable::build_ok())
+        Ok(OutputTable::build_object_ok(name))
     }
 
     /// Creates a table.
@@ -33,7 +35,7 @@ impl CommandRunner {
     /// Reserves table entry in memory, creates directory, and writes metadata.
     ///
     /// Returns:
-    ///   * Ok: `OutputTable` with success status
+    ///   * Ok: `OutputTable` with success status and name of the created object
     ///   * Error: `TableEntryAlreadyExists` or `CouldNotInsertData` on failure
     pub fn create_table(
         table_def: &TableDef,
@@ -41,13 +43,14 @@ impl CommandRunner {
         settings: TableSettings,
         order_by: Vec<ColumnDef>,
         primary_key: Vec<ColumnDef>,
+        comment: Option<String>,
     ) -> Result<OutputTable> {
         let table_schema = TableSchema {
             columns,
             order_by,
             primary_key,
         };
-        let table_metadata = TableMetadata::try_new(table_schema, settings)?;
+        let table_metadata = TableMetadata::try_new(table_schema, settings, comment)?;
 
         let table_path = table_def.get_path();
         // will lock for mutual access
@@ -72,6 +75,6 @@ impl CommandRunner {
 
         entry.insert(table_config);
 
-        Ok(OutputTable::build_ok())
+        Ok(OutputTable::build_object_ok(table_def.qualified_name()))
     }
 }
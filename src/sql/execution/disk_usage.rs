@@ -0,0 +1,220 @@
+use crate::error::{Error, Result};
+use crate::runtime_config::TABLE_DATA;
+use crate::sql::CommandRunner;
+use crate::storage::value::ArchivedValue;
+use crate::storage::{
+    Column, ColumnDef, Constraints, OutputTable, PART_INFO_FILENAME, TableDef, TablePartInfo,
+    Value, ValueType,
+};
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Part of the report, copied from `TABLE_DATA`.
+struct PartEntry {
+    table_def: TableDef,
+    sequence: u64,
+    path: PathBuf,
+    name: String,
+    row_count: u64,
+    column_names: Vec<String>,
+}
+
+/// Size of the part, see `CommandRunner::disk_usage`.
+struct PartUsage {
+    compressed_bytes: u64,
+    uncompressed_bytes: u64,
+}
+
+/// Totals of the database in the global report.
+#[derive(Default)]
+struct DatabaseUsage {
+    tables: u64,
+    parts: u64,
+    compressed_bytes: u64,
+    uncompressed_bytes: u64,
+}
+
+impl CommandRunner {
+    /// Reports disk usage of the table parts, or of all tables grouped by database.
+    ///
+    /// Compressed size is the size of column `.bin` files and `part.inf`. Uncompressed size is
+    /// estimated as `row_count * avg_row_size`, where every value counts as an in-memory
+    /// `Value` granule entry, so it is a lower bound for strings and nested values.
+    ///
+    /// Returns:
+    ///   * Ok: for the table, `OutputTable` with `part_name`, `compressed_bytes`,
+    ///     `uncompressed_bytes` and `compression_ratio` columns, row per part in creation order.
+    ///     Otherwise, `database`, `tables` and `parts` columns followed by the same size columns,
+    ///     row per database.
+    ///   * Error: `TableNotFound` when table does not exist, or `CouldNotReadData` when part
+    ///     files could not be read.
+    pub fn disk_usage(table: Option<&TableDef>) -> Result<OutputTable> {
+        // files are read after `TABLE_DATA` is unlocked, so that queries and merges are not blocked
+        let mut parts = Vec::new();
+        let mut databases: BTreeMap<String, DatabaseUsage> = BTreeMap::new();
+        match table {
+            Some(table_def) => {
+                let table_data = TABLE_DATA.get(table_def).ok_or(Error::TableNotFound)?;
+                collect_parts(table_def, &table_data.infos, &mut parts);
+            }
+            None => {
+                for entry in TABLE_DATA.iter() {
+                    databases
+                        .entry(entry.key().database.clone())
+                        .or_default()
+                        .tables += 1;
+                    collect_parts(entry.key(), &entry.value().infos, &mut parts);
+                }
+            }
+        }
+        parts.sort_by_key(|part| part.sequence);
+
+        let mut part_rows = Vec::with_capacity(parts.len());
+        for part in parts {
+            let usage = match part_usage(&part) {
+                Ok(usage) => usage,
+                // merged away since `TABLE_DATA` was read
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(error) => {
+                    return Err(Error::CouldNotReadData(format!(
+                        "Could not read {}: {error}",
+                        part.path.display()
+                    )));
+                }
+            };
+
+            if table.is_some() {
+                part_rows.push((part.name, usage));
+            } else {
+                let database = databases.entry(part.table_def.database).or_default();
+                database.parts += 1;
+                database.compressed_bytes += usage.compressed_bytes;
+                database.uncompressed_bytes += usage.uncompressed_bytes;
+            }
+        }
+
+        if table.is_some() {
+            let mut names = Vec::with_capacity(part_rows.len());
+            let mut usages = Vec::with_capacity(part_rows.len());
+            for (name, usage) in part_rows {
+                names.push(Value::String(name));
+                usages.push(usage);
+            }
+
+            let mut columns = vec![column("part_name", ValueType::String, names)];
+            columns.extend(size_columns(&usages));
+            return Ok(OutputTable::new(columns));
+        }
+
+        let mut names = Vec::with_capacity(databases.len());
+        let mut tables = Vec::with_capacity(databases.len());
+        let mut part_counts = Vec::with_capacity(databases.len());
+        let mut usages = Vec::with_capacity(databases.len());
+        for (name, database) in databases {
+            names.push(Value::String(name));
+            tables.push(Value::UInt64(database.tables));
+            part_counts.push(Value::UInt64(database.parts));
+            usages.push(PartUsage {
+                compressed_bytes: database.compressed_bytes,
+                uncompressed_bytes: database.uncompressed_bytes,
+            });
+        }
+
+        let mut columns = vec![
+            column("database", ValueType::String, names),
+            column("tables", ValueType::UInt64, tables),
+            column("parts", ValueType::UInt64, part_counts),
+        ];
+        columns.extend(size_columns(&usages));
+        Ok(OutputTable::new(columns))
+    }
+}
+
+fn collect_parts(table_def: &TableDef, infos: &[TablePartInfo], parts: &mut Vec<PartEntry>) {
+    for info in infos {
+        parts.push(PartEntry {
+            table_def: table_def.clone(),
+            sequence: info.sequence,
+            path: info.get_path(table_def),
+            name: info.name.clone(),
+            row_count: info.row_count,
+            column_names: info
+                .column_defs
+                .iter()
+                .map(|column_def| column_def.name.clone())
+                .collect(),
+        });
+    }
+}
+
+/// Returns compressed size of the part files (column, mark and part info files) and estimated
+/// uncompressed size of its rows.
+fn part_usage(part: &PartEntry) -> std::io::Result<PartUsage> {
+    let mut compressed_bytes = std::fs::metadata(part.path.join(PART_INFO_FILENAME))?.len();
+    for name in &part.column_names {
+        compressed_bytes += std::fs::metadata(part.path.join(format!("{name}.bin")))?.len();
+        // parts written before mark files keep marks in `PART_INFO_FILENAME`
+        match std::fs::metadata(part.path.join(format!("{name}.mrk"))) {
+            Ok(metadata) => compressed_bytes += metadata.len(),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error),
+        }
+    }
+
+    let avg_row_size = (part.column_names.len() * size_of::<ArchivedValue>()) as u64;
+    Ok(PartUsage {
+        compressed_bytes,
+        uncompressed_bytes: part.row_count * avg_row_size,
+    })
+}
+
+/// Returns `compressed_bytes`, `uncompressed_bytes` and `compression_ratio` columns.
+fn size_columns(usages: &[PartUsage]) -> [Column; 3] {
+    [
+        column(
+            "compressed_bytes",
+            ValueType::UInt64,
+            usages
+                .iter()
+                .map(|usage| Value::UInt64(usage.compressed_bytes))
+                .collect(),
+        ),
+        column(
+            "uncompressed_bytes",
+            ValueType::UInt64,
+            usages
+                .iter()
+                .map(|usage| Value::UInt64(usage.uncompressed_bytes))
+                .collect(),
+        ),
+        column(
+            "compression_ratio",
+            ValueType::String,
+            usages.iter().map(compression_ratio).collect(),
+        ),
+    ]
+}
+
+/// Returns `uncompressed / compressed` with two decimals, `NULL` for empty usage.
+fn compression_ratio(usage: &PartUsage) -> Value {
+    if usage.compressed_bytes == 0 {
+        return Value::Null;
+    }
+
+    let hundredths = (u128::from(usage.uncompressed_bytes) * 100
+        + u128::from(usage.compressed_bytes) / 2)
+        / u128::from(usage.compressed_bytes);
+    Value::String(format!("{}.{:02}", hundredths / 100, hundredths % 100))
+}
+
+fn column(name: &str, field_type: ValueType, data: Vec<Value>) -> Column {
+    Column {
+        column_def: ColumnDef {
+            name: name.to_string(),
+            field_type,
+            constraints: Constraints::default(),
+        },
+        data: data.into(),
+    }
+}
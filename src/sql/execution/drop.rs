@@ -4,24 +4,31 @@ use crate::runtime_config::TABLE_DATA;
 use crate::sql::CommandRunner;
 use crate::storage::{OutputTable, TableDef};
 
+use log::warn;
+use uuid::Uuid;
+
 impl CommandRunner {
     /// Drops a table.
     ///
     /// Removes table entry in memory, deletes table directory.
     ///
     /// Returns:
-    ///   * Ok: `OutputTable` with success status
-    ///   * Error: `TableNotFound` or `Internal` on failure
+    ///   * Ok: `OutputTable` with success status and name of the dropped object
+    ///   * Error: `TableNotFound`, `TableReadOnly` (see `TableMetadata::check_droppable`) or
+    ///     `Internal` on failure
     pub fn drop_table(table_def: &TableDef, if_exists: bool) -> Result<OutputTable> {
+        if let Some(table_config) = TABLE_DATA.get(table_def) {
+            table_config.metadata.check_droppable(table_def)?;
+        }
         let _ = TABLE_DATA.remove(table_def);
 
         let table_path = table_def.get_path();
 
         let remove_result = std::fs::remove_dir_all(&table_path);
         match (remove_result, if_exists) {
-            (Ok(()), _) => Ok(OutputTable::build_ok()),
+            (Ok(()), _) => Ok(OutputTable::build_object_ok(table_def.qualified_name())),
             (Err(error), true) if error.kind() == std::io::ErrorKind::NotFound => {
-                Ok(OutputTable::build_ok())
+                Ok(OutputTable::build_object_ok(table_def.qualified_name()))
             }
             (Err(error), false) if error.kind() == std::io::ErrorKind::NotFound => {
                 Err(Error::TableNotFound)
@@ -34,21 +41,75 @@ impl CommandRunner {
         }
     }
 
+    /// Drops a partition of the table.
+    ///
+    /// Under exclusive table lock moves partition directory into `raw` directory, so all its
+    /// parts disappear at once, and removes them from memory. Directory is deleted afterwards,
+    /// or on the next startup, as `raw` is cleaned then. Missing partition is not an error.
+    ///
+    /// Returns:
+    ///   * Ok: `OutputTable` with success status and name of the dropped object
+    ///   * Error: `TableNotFound`, `TableReadOnly` or `Internal` on failure
+    pub fn drop_partition(table_def: &TableDef, partition: &str) -> Result<OutputTable> {
+        let Some(mut table_config) = TABLE_DATA.get_mut(table_def) else {
+            return Err(Error::TableNotFound);
+        };
+        table_config.metadata.check_writable(table_def)?;
+
+        let partition_path = table_def.get_path().join(partition);
+        let raw_dir = table_def.get_path().join("raw");
+        let dropped_path = raw_dir.join(format!("{}.dropped", Uuid::now_v7()));
+        let dropped = format!("{}/{partition}", table_def.qualified_name());
+
+        std::fs::create_dir_all(&raw_dir)
+            .map_err(|error| Error::Internal(format!("Could not create raw directory: {error}")))?;
+        match std::fs::rename(&partition_path, &dropped_path) {
+            Ok(()) => {}
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(OutputTable::build_object_ok(dropped));
+            }
+            Err(error) => {
+                return Err(Error::Internal(format!(
+                    "Could not move partition {partition} for removal: {error}"
+                )));
+            }
+        }
+        table_config
+            .infos
+            .retain(|info| info.partition.as_deref() != Some(partition));
+        drop(table_config);
+
+        if let Err(error) = std::fs::remove_dir_all(&dropped_path) {
+            warn!(
+                "Couldn't remove dropped partition ({}), it will be removed on restart: {error}",
+                dropped_path.display()
+            );
+        }
+
+        Ok(OutputTable::build_object_ok(dropped))
+    }
+
     /// Drops a database.
     ///
     /// Removes table entries in memory, deletes database directory.
     ///
     /// Returns:
-    ///   * Ok: `OutputTable` with success status
-    ///   * Error: `DatabaseNotFound` or `Internal` on failure
+    ///   * Ok: `OutputTable` with success status and name of the dropped object
+    ///   * Error: `DatabaseNotFound`, `TableReadOnly` when any table can not be dropped
+    ///     (see `TableMetadata::check_droppable`) or `Internal` on failure
     pub fn drop_database(name: &str, if_exists: bool) -> Result<OutputTable> {
+        for entry in TABLE_DATA.iter() {
+            if entry.key().database == name {
+                entry.value().metadata.check_droppable(entry.key())?;
+            }
+        }
         TABLE_DATA.retain(|x, _| x.database != name);
 
         let remove_result = std::fs::remove_dir_all(CONFIG.get_db_dir().join(name));
         match (remove_result, if_exists) {
-            (Ok(()), _) => Ok(OutputTable::build_ok()),
+            (Ok(()), _) => Ok(OutputTable::build_object_ok(name.to_string())),
             (Err(error), true) if error.kind() == std::io::ErrorKind::NotFound => {
-                Ok(OutputTable::build_ok())
+                Ok(OutputTable::build_object_ok(name.to_string()))
             }
             (Err(error), false) if error.kind() == std::io::ErrorKind::NotFound => {
                 Err(Error::DatabaseNotFound)
@@ -0,0 +1,55 @@
+use crate::background_merge::BackgroundMerge;
+use crate::config::CONFIG;
+use crate::error::{Error, Result};
+use crate::runtime_config::TABLE_DATA;
+use crate::sql::CommandRunner;
+use crate::storage::{OutputTable, TableDef, exchange_table_dirs};
+
+use std::sync::{Mutex, PoisonError};
+
+/// Exchanges run one at a time, as they share the journal (see `exchange_table_dirs`).
+static EXCHANGE_LOCK: Mutex<()> = Mutex::new(());
+
+impl CommandRunner {
+    /// Atomically swaps two tables: their directories on disk and entries of `TABLE_DATA`
+    /// (metadata and parts).
+    ///
+    /// Merges of both tables are stopped and awaited first. Then both tables are locked
+    /// exclusively, so running queries finish before, and new ones start after the swap.
+    /// `TABLE_DATA` locks whole shards, and a table can share its shard with the other one,
+    /// so both are locked with a single `iter_mut`, which always takes shards in the same order.
+    /// Inserts into either table, which are running during the exchange, may fail.
+    ///
+    /// Returns:
+    ///   * Ok: `OutputTable` with success status.
+    ///   * Error: `TableNotFound` when either table was dropped meanwhile, `TableReadOnly`
+    ///     when either table is read-only, or `Internal` when directories could not be swapped.
+    pub fn exchange_tables(first: &TableDef, second: &TableDef) -> Result<OutputTable> {
+        let _exchange = EXCHANGE_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let _merges = BackgroundMerge::exclude_tables(&[first.clone(), second.clone()]);
+
+        let mut first_config = None;
+        let mut second_config = None;
+        for entry in TABLE_DATA.iter_mut() {
+            if entry.key() == first {
+                first_config = Some(entry);
+            } else if entry.key() == second {
+                second_config = Some(entry);
+            }
+            if first_config.is_some() && second_config.is_some() {
+                break;
+            }
+        }
+        let (Some(mut first_config), Some(mut second_config)) = (first_config, second_config)
+        else {
+            return Err(Error::TableNotFound);
+        };
+        first_config.metadata.check_writable(first)?;
+        second_config.metadata.check_writable(second)?;
+
+        exchange_table_dirs(CONFIG.get_db_dir(), first, second)?;
+        std::mem::swap(first_config.value_mut(), second_config.value_mut());
+
+        Ok(OutputTable::build_ok())
+    }
+}
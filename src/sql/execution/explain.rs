@@ -0,0 +1,280 @@
+use crate::error::{Error, Result};
+use crate::sql::CommandRunner;
+use crate::sql::execution::memory::MemoryTracker;
+use crate::sql::sql_parser::{LogicalPlan, PhysicalPlan, ScanSource};
+use crate::storage::{Column, ColumnDef, Constraints, OutputTable, Value, ValueType};
+
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Stage of `SELECT` execution, timed by `QueryStats`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Stage {
+    /// Reading rows of the source: table parts, system table or `PIVOT` subquery.
+    Scan,
+    /// Decompression of column granules, summed over scan threads.
+    Decompression,
+    /// Evaluation of `WHERE` on granule rows, summed over scan threads.
+    Filter,
+    /// Deserialization of matching values, summed over scan threads.
+    Deserialization,
+    /// Computed columns, sorting, `LIMIT`, `OFFSET`, `row_number()` and projection.
+    PostProcessing,
+    /// Sorting by `ORDER BY`, part of `PostProcessing`.
+    Sort,
+}
+
+impl Stage {
+    const COUNT: usize = 6;
+}
+
+/// Scan of a single part.
+#[derive(Debug)]
+pub(crate) struct PartStats {
+    pub name: String,
+    pub granules: u64,
+    /// Granules skipped by part min/max, dictionaries or primary key conditions.
+    pub granules_pruned: u64,
+    /// Granules read. Granules, which are neither pruned nor scanned, were skipped after
+    /// `LIMIT` was reached.
+    pub granules_scanned: u64,
+    /// Rows, which matched `WHERE`.
+    pub rows: u64,
+    /// Time of opening part files and reading its granules, summed over scan threads.
+    pub time: Duration,
+}
+
+/// Statistics of `SELECT` execution, collected only for `EXPLAIN ANALYZE`. Stages, which run
+/// on scan threads, are summed with atomics, so shared reference is enough.
+#[derive(Debug, Default)]
+pub(crate) struct QueryStats {
+    /// Time of each `Stage` in nanoseconds.
+    stage_nanos: [AtomicU64; Stage::COUNT],
+    /// Scanned parts, in scan order.
+    parts: Mutex<Vec<PartStats>>,
+    /// Peak memory of the query in bytes, see `MemoryTracker`.
+    peak_memory: AtomicUsize,
+}
+
+/// Adds time from its creation until drop to the stage, see `QueryStats::time`.
+pub(crate) struct StageTimer<'a> {
+    stats: &'a QueryStats,
+    stage: Stage,
+    start: Instant,
+}
+
+impl Drop for StageTimer<'_> {
+    fn drop(&mut self) {
+        let nanos = u64::try_from(self.start.elapsed().as_nanos()).unwrap_or(u64::MAX);
+        self.stats.stage_nanos[self.stage as usize].fetch_add(nanos, Ordering::Relaxed);
+    }
+}
+
+impl QueryStats {
+    /// Starts timing of `stage`, which lasts until returned timer is dropped. Without `stats`
+    /// nothing is measured, so disabled statistics cost a single branch.
+    pub(crate) fn time(stats: Option<&Self>, stage: Stage) -> Option<StageTimer<'_>> {
+        stats.map(|stats| StageTimer {
+            stats,
+            stage,
+            start: Instant::now(),
+        })
+    }
+
+    /// Adds scan of the part.
+    pub(crate) fn add_part(&self, part: PartStats) {
+        self.parts
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .push(part);
+    }
+
+    /// Sets peak memory of the query, once it is executed.
+    pub(crate) fn set_peak_memory(&self, bytes: usize) {
+        self.peak_memory.store(bytes, Ordering::Relaxed);
+    }
+
+    /// Renders statistics as a tree of steps (children are indented by two spaces) followed by
+    /// `Total` row, which also has peak memory of the query.
+    fn into_output(
+        self,
+        source: &str,
+        plan_time: Duration,
+        execution_time: Duration,
+        result_rows: u64,
+    ) -> OutputTable {
+        let parts = self
+            .parts
+            .into_inner()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        let sum = |field: fn(&PartStats) -> u64| Value::UInt64(parts.iter().map(field).sum());
+        let stage_nanos = self.stage_nanos.map(AtomicU64::into_inner);
+        let stage_time = |stage: Stage| Duration::from_nanos(stage_nanos[stage as usize]);
+
+        let mut rows = ExplainRows::default();
+        rows.push("Parse and plan", plan_time, None);
+        rows.push(
+            &format!("Scan {source}"),
+            stage_time(Stage::Scan),
+            Some([
+                sum(|part| part.granules),
+                sum(|part| part.granules_pruned),
+                sum(|part| part.granules_scanned),
+                sum(|part| part.rows),
+            ]),
+        );
+        for part in &parts {
+            rows.push(
+                &format!("  Part {}", part.name),
+                part.time,
+                Some([
+                    Value::UInt64(part.granules),
+                    Value::UInt64(part.granules_pruned),
+                    Value::UInt64(part.granules_scanned),
+                    Value::UInt64(part.rows),
+                ]),
+            );
+        }
+        for (step, stage) in [
+            ("  Decompression", Stage::Decompression),
+            ("  Filter", Stage::Filter),
+            ("  Deserialization", Stage::Deserialization),
+        ] {
+            rows.push(step, stage_time(stage), None);
+        }
+        rows.push(
+            "Post-processing",
+            stage_time(Stage::PostProcessing),
+            Some([
+                Value::Null,
+                Value::Null,
+                Value::Null,
+                Value::UInt64(result_rows),
+            ]),
+        );
+        rows.push("  Sort", stage_time(Stage::Sort), None);
+        rows.push(
+            "Total",
+            plan_time + execution_time,
+            Some([
+                sum(|part| part.granules),
+                sum(|part| part.granules_pruned),
+                sum(|part| part.granules_scanned),
+                Value::UInt64(result_rows),
+            ]),
+        );
+        rows.into_output(self.peak_memory.into_inner() as u64)
+    }
+}
+
+/// Columns of `EXPLAIN ANALYZE` output.
+#[derive(Default)]
+struct ExplainRows {
+    steps: Vec<Value>,
+    times: Vec<Value>,
+    /// `granules`, `granules_pruned`, `granules_scanned` and `rows`.
+    counts: [Vec<Value>; 4],
+}
+
+impl ExplainRows {
+    fn push(&mut self, step: &str, time: Duration, counts: Option<[Value; 4]>) {
+        self.steps.push(Value::String(step.to_string()));
+        self.times.push(Value::UInt64(
+            u64::try_from(time.as_micros()).unwrap_or(u64::MAX),
+        ));
+        let counts = counts.unwrap_or([Value::Null, Value::Null, Value::Null, Value::Null]);
+        for (column, count) in self.counts.iter_mut().zip(counts) {
+            column.push(count);
+        }
+    }
+
+    /// Returns the output, `peak_memory_bytes` is set only in the last (`Total`) row.
+    fn into_output(self, peak_memory: u64) -> OutputTable {
+        let column = |name: &str, field_type: ValueType, data: Vec<Value>| Column {
+            column_def: ColumnDef {
+                name: name.to_string(),
+                field_type,
+                constraints: Constraints::default(),
+            },
+            data: data.into(),
+        };
+        let [granules, granules_pruned, granules_scanned, rows] = self.counts;
+        let mut memory = vec![Value::Null; self.steps.len()];
+        if let Some(total) = memory.last_mut() {
+            *total = Value::UInt64(peak_memory);
+        }
+
+        OutputTable::new(vec![
+            column("step", ValueType::String, self.steps),
+            column("time_us", ValueType::UInt64, self.times),
+            column("granules", ValueType::UInt64, granules),
+            column("granules_pruned", ValueType::UInt64, granules_pruned),
+            column("granules_scanned", ValueType::UInt64, granules_scanned),
+            column("rows", ValueType::UInt64, rows),
+            column("peak_memory_bytes", ValueType::UInt64, memory),
+        ])
+    }
+}
+
+impl CommandRunner {
+    /// Executes `SELECT` with `QueryStats` and reports where time went, instead of its rows.
+    /// `plan_time` is the time of parsing and planning, measured by the caller.
+    ///
+    /// Returns:
+    ///   * Ok: `OutputTable` with `step`, `time_us`, `granules`, `granules_pruned`,
+    ///     `granules_scanned`, `rows` and `peak_memory_bytes` columns: row per stage of the plan
+    ///     tree (see `QueryStats::into_output`) and `Total` row. Counts are `NULL` for stages
+    ///     without them.
+    ///   * Error: `UnsupportedCommand` for statements other than `SELECT`, or any error of
+    ///     `select`.
+    pub fn explain_analyze(plan: PhysicalPlan, plan_time: Duration) -> Result<OutputTable> {
+        let PhysicalPlan::Select {
+            scan_source,
+            columns,
+            computed,
+            filter,
+            sort_by,
+            row_number,
+            limit,
+            offset,
+            settings,
+        } = plan
+        else {
+            return Err(Error::UnsupportedCommand(
+                "EXPLAIN ANALYZE of statements other than SELECT".to_string(),
+            ));
+        };
+        let source = match &scan_source {
+            ScanSource::Table(table_def) => table_def.to_string(),
+            ScanSource::Subquery(plan) if matches!(**plan, LogicalPlan::Flatten { .. }) => {
+                "FLATTEN".to_string()
+            }
+            ScanSource::Subquery(_) => "PIVOT".to_string(),
+        };
+
+        let stats = QueryStats::default();
+        let start = Instant::now();
+        let output = Self::select(
+            scan_source,
+            columns,
+            computed,
+            filter,
+            sort_by.as_ref(),
+            row_number,
+            limit,
+            offset,
+            &settings,
+            Some(&stats),
+        )?;
+        let execution_time = start.elapsed();
+        stats.set_peak_memory(MemoryTracker::current().peak());
+
+        Ok(stats.into_output(
+            &source,
+            plan_time,
+            execution_time,
+            output.row_count() as u64,
+        ))
+    }
+}
@@ -0,0 +1,60 @@
+use crate::error::{Error, Result};
+use crate::sql::CommandRunner;
+use crate::sql::logical_plan::flatten::flatten_columns;
+use crate::sql::sql_parser::{LogicalPlan, PhysicalPlan};
+use crate::storage::{Column, ColumnDef, OutputTable, Value};
+
+impl CommandRunner {
+    /// Executes the query of `plan` and returns a row per element of `array_col`, see
+    /// `LogicalPlan::Flatten`. Rows keep the order of the query and of elements of the array.
+    ///
+    /// Returns:
+    ///   * Ok: `OutputTable` with columns of the query, `index` and `value`.
+    ///   * Error: any error from executing `plan`, or `Internal` when it did not return
+    ///     `array_col`.
+    pub fn flatten(array_col: &ColumnDef, plan: LogicalPlan) -> Result<OutputTable> {
+        let source = Self::execute_physical_plan(PhysicalPlan::from(plan))?.columns;
+        let array_data = &source
+            .iter()
+            .find(|column| column.column_def == *array_col)
+            .ok_or_else(|| {
+                Error::Internal(format!("FLATTEN source has no column {}", array_col.name))
+            })?
+            .data;
+
+        let mut rows = Vec::new();
+        let mut indexes = Vec::new();
+        let mut values = Vec::new();
+        for (row_idx, array) in array_data.iter().enumerate() {
+            if let Value::Array(elements) = array {
+                for (elem_idx, element) in elements.iter().enumerate() {
+                    rows.push(row_idx);
+                    indexes.push(Value::UInt64(elem_idx as u64));
+                    values.push(element.clone());
+                }
+            }
+        }
+
+        let source_defs: Vec<_> = source
+            .iter()
+            .map(|column| column.column_def.clone())
+            .collect();
+        let mut output = flatten_columns(&source_defs, array_col).into_iter();
+        let mut columns: Vec<_> = source
+            .iter()
+            .zip(&mut output)
+            .map(|(column, column_def)| Column {
+                column_def,
+                data: rows.iter().map(|&row| column.data[row].clone()).collect(),
+            })
+            .collect();
+        for (column_def, data) in output.zip([indexes, values]) {
+            columns.push(Column {
+                column_def,
+                data: data.into(),
+            });
+        }
+
+        Ok(OutputTable::new(columns))
+    }
+}
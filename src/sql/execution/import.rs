@@ -0,0 +1,390 @@
+use crate::config::CONFIG;
+use crate::error::{Error, Result};
+use crate::runtime_config::TABLE_DATA;
+use crate::sql::CommandRunner;
+use crate::sql::logical_plan::insert::fill_omitted_columns;
+use crate::sql::parse_value;
+use crate::sql::sql_parser::{ImportFormat, ImportSource, parse_expr};
+use crate::storage::{
+    Column, ColumnData, ColumnDef, OutputTable, TableDef, TableSchema, Value, ValueType,
+};
+
+use sqlparser::ast::Value as SQLValue;
+use std::borrow::Cow;
+use std::fs::File;
+use std::path::{Component, Path, PathBuf};
+
+impl CommandRunner {
+    /// Executes `IMPORT INTO`: reads the file record by record and inserts its rows in blocks of
+    /// `max_insert_block_rows` rows, each block as a single `insert`.
+    ///
+    /// Fields are parsed by types of their columns, see `parse_field`. Line with another number
+    /// of fields, or with a field, which could not be parsed, is skipped while fewer than
+    /// `allow_errors_num` lines were skipped, and fails the import otherwise. Import is not
+    /// atomic: blocks inserted before the failure are kept, and the error tells their rows.
+    ///
+    /// Returns:
+    ///   * Ok: `OutputTable` with numbers of imported and skipped rows.
+    ///   * Error:
+    ///     1. `PermissionDenied` when path is not in `import_directory`, see
+    ///        `resolve_import_path`.
+    ///     2. `CouldNotReadData` when file could not be opened or read.
+    ///     3. `InvalidSource` naming the line (and column) of a bad line.
+    ///     4. Errors of `insert`, e.g., `TableReadOnly` or `TooManyParts`.
+    pub fn import(
+        table_def: &TableDef,
+        columns: &[ColumnDef],
+        source: &ImportSource,
+    ) -> Result<OutputTable> {
+        let schema = {
+            let Some(table_config) = TABLE_DATA.get(table_def) else {
+                return Err(Error::TableNotFound);
+            };
+            table_config.metadata.check_writable(table_def)?;
+            table_config.metadata.schema.clone()
+        };
+
+        let path = resolve_import_path(&source.path)?;
+        let file = File::open(&path).map_err(|error| {
+            Error::CouldNotReadData(format!("Could not open '{}': {error}", source.path))
+        })?;
+        // `csv::Reader` buffers the file itself
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(source.with_header)
+            .delimiter(source.delimiter)
+            .quoting(source.format == ImportFormat::Csv)
+            .flexible(true)
+            .from_reader(file);
+
+        let block_rows = CONFIG.get_max_insert_block_rows();
+        let mut block = empty_block(columns);
+        let mut rows_imported = 0;
+        let mut rows_skipped = 0;
+        let mut record = csv::StringRecord::new();
+        loop {
+            let row = match reader.read_record(&mut record) {
+                Ok(false) => break,
+                Ok(true) => {
+                    let line = record.position().map_or(0, csv::Position::line);
+                    parse_line(&record, columns, source.format)
+                        .map_err(|reason| format!("line {line}{reason}"))
+                }
+                // record is consumed, so reading goes on with the next line
+                Err(error) => match error.kind() {
+                    csv::ErrorKind::Utf8 { pos, err } => Err(format!(
+                        "line {}: {err}",
+                        pos.as_ref().map_or(0, csv::Position::line)
+                    )),
+                    _ => {
+                        return Err(Error::CouldNotReadData(format!(
+                            "Could not read '{}': {error}",
+                            source.path
+                        )));
+                    }
+                },
+            };
+
+            match row {
+                Ok(row) => {
+                    for (column, value) in block.iter_mut().zip(row) {
+                        column.data.push(value);
+                    }
+                }
+                Err(_) if rows_skipped < source.allow_errors_num => rows_skipped += 1,
+                Err(reason) if rows_imported == 0 => return Err(Error::InvalidSource(reason)),
+                Err(reason) => {
+                    return Err(Error::InvalidSource(format!(
+                        "{reason}. {rows_imported} rows were imported before"
+                    )));
+                }
+            }
+
+            if block[0].data.len() >= block_rows {
+                rows_imported += insert_block(table_def, &schema, &mut block, columns)?;
+            }
+        }
+        if !block[0].data.is_empty() {
+            rows_imported += insert_block(table_def, &schema, &mut block, columns)?;
+        }
+
+        Ok(OutputTable::build_rows_imported(
+            rows_imported,
+            rows_skipped,
+        ))
+    }
+}
+
+/// Resolves `path` of `IMPORT INTO` against `import_directory`. Path may be relative to the
+/// directory or absolute. Paths with `..` and absolute paths outside of the directory are
+/// rejected before the file system is accessed, so errors do not tell, which files exist
+/// outside of it. Symbolic links are resolved, and must stay in the directory as well.
+///
+/// Returns:
+///   * Ok: canonical path of the file.
+///   * Error:
+///     1. `PermissionDenied` when `import_directory` is not set, or path is outside of it.
+///     2. `CouldNotReadData` when directory or file could not be resolved, e.g., is missing.
+fn resolve_import_path(path: &str) -> Result<PathBuf> {
+    let Some(configured) = CONFIG.get_import_directory() else {
+        return Err(Error::PermissionDenied(
+            "IMPORT INTO is disabled, as import_directory is not set".to_string(),
+        ));
+    };
+    let directory = configured.canonicalize().map_err(|error| {
+        Error::CouldNotReadData(format!(
+            "Could not open import_directory {}: {error}",
+            configured.display()
+        ))
+    })?;
+
+    let outside = || {
+        Error::PermissionDenied(format!(
+            "File '{path}' is outside of import_directory {}",
+            configured.display()
+        ))
+    };
+    let path = Path::new(path);
+    if path
+        .components()
+        .any(|component| component == Component::ParentDir)
+        || (path.is_absolute() && !path.starts_with(configured) && !path.starts_with(&directory))
+    {
+        return Err(outside());
+    }
+
+    let resolved = directory.join(path).canonicalize().map_err(|error| {
+        Error::CouldNotReadData(format!("Could not open '{}': {error}", path.display()))
+    })?;
+    if !resolved.starts_with(&directory) {
+        return Err(outside());
+    }
+    Ok(resolved)
+}
+
+/// Returns listed columns without rows.
+fn empty_block(columns: &[ColumnDef]) -> Vec<Column> {
+    columns
+        .iter()
+        .map(|column_def| Column {
+            column_def: column_def.clone(),
+            data: ColumnData::default(),
+        })
+        .collect()
+}
+
+/// Inserts rows of `block` with omitted columns, see `fill_omitted_columns`, and leaves it
+/// empty.
+///
+/// Returns:
+///   * Ok: number of inserted rows.
+///   * Error: errors of `fill_omitted_columns` and `CommandRunner::insert`.
+fn insert_block(
+    table_def: &TableDef,
+    schema: &TableSchema,
+    block: &mut Vec<Column>,
+    columns: &[ColumnDef],
+) -> Result<u64> {
+    let mut full_block = std::mem::replace(block, empty_block(columns));
+    let row_count = full_block[0].data.len();
+    fill_omitted_columns(schema, &mut full_block, row_count)?;
+    CommandRunner::insert(table_def, full_block)?;
+    Ok(row_count as u64)
+}
+
+/// Parses fields of the record into values of `columns`, matched by position.
+///
+/// Returns:
+///   * Ok: a value per column.
+///   * Error: reason, why the line is bad, starting with `: ` or `, column 'name': `.
+fn parse_line(
+    record: &csv::StringRecord,
+    columns: &[ColumnDef],
+    format: ImportFormat,
+) -> std::result::Result<Vec<Value>, String> {
+    if record.len() != columns.len() {
+        return Err(format!(
+            ": expected {} fields (one per listed column), got {}",
+            columns.len(),
+            record.len()
+        ));
+    }
+    record
+        .iter()
+        .zip(columns)
+        .map(|(field, column_def)| {
+            parse_field(field, format, column_def)
+                .map_err(|reason| format!(", column '{}': {reason}", column_def.name))
+        })
+        .collect()
+}
+
+/// Parses a field of the file into a value of the column type:
+/// - Empty field (and `\N` of TSV) is `NULL` of nullable column, empty string of NOT NULL
+///   `String` column, and an error otherwise.
+/// - Integers are written as numbers, `Bool` as `true`, `false`, `1` or `0`.
+/// - Tuples, arrays and maps are written as SQL literals, e.g., `[1, 2]`.
+/// - Other types are written as their string literals without quotes, e.g., `2024-01-31`.
+///
+/// Returns:
+///   * Ok: parsed value.
+///   * Error: reason, why the field could not be parsed.
+fn parse_field(
+    field: &str,
+    format: ImportFormat,
+    column_def: &ColumnDef,
+) -> std::result::Result<Value, String> {
+    let is_null = format == ImportFormat::Tsv && field == "\\N";
+    if field.is_empty() || is_null {
+        return if column_def.constraints.nullable {
+            Ok(Value::Null)
+        } else if !is_null && column_def.field_type == ValueType::String {
+            Ok(Value::String(String::new()))
+        } else {
+            Err("NULL value not allowed".to_string())
+        };
+    }
+
+    let field = match format {
+        ImportFormat::Csv => Cow::Borrowed(field),
+        ImportFormat::Tsv => unescape_tsv(field),
+    };
+    let value_type = &column_def.field_type;
+    let value = match value_type {
+        ValueType::Int8
+        | ValueType::Int16
+        | ValueType::Int32
+        | ValueType::Int64
+        | ValueType::UInt8
+        | ValueType::UInt16
+        | ValueType::UInt32
+        | ValueType::UInt64 => {
+            Value::try_from((SQLValue::Number(field.to_string(), false), value_type))
+        }
+        ValueType::Bool => match field.to_ascii_lowercase().as_str() {
+            "true" | "1" => Ok(Value::Bool(true)),
+            "false" | "0" => Ok(Value::Bool(false)),
+            _ => Err(Error::InvalidSource(
+                "expected true, false, 1 or 0".to_string(),
+            )),
+        },
+        ValueType::Tuple(_) | ValueType::Array(_) | ValueType::Map(..) => {
+            parse_expr(&field).and_then(|expr| parse_value(&expr, value_type))
+        }
+        _ => Value::try_from((SQLValue::SingleQuotedString(field.to_string()), value_type)),
+    };
+    value.map_err(|error| {
+        let reason = match error {
+            Error::InvalidSource(reason) => reason,
+            error => error.to_string(),
+        };
+        format!("could not parse '{field}' as {value_type:?}: {reason}")
+    })
+}
+
+/// Replaces escape sequences of a TSV field: `\t`, `\n`, `\r`, `\0` and `\\`. Backslash
+/// before another character is dropped, e.g., `\'` is `'`.
+fn unescape_tsv(field: &str) -> Cow<'_, str> {
+    if !field.contains('\\') {
+        return Cow::Borrowed(field);
+    }
+
+    let mut unescaped = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(char) = chars.next() {
+        if char != '\\' {
+            unescaped.push(char);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some('0') => unescaped.push('\0'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\'),
+        }
+    }
+    Cow::Owned(unescaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Constraints;
+
+    fn column_def(field_type: ValueType, nullable: bool) -> ColumnDef {
+        ColumnDef {
+            name: "c".to_string(),
+            field_type,
+            constraints: Constraints {
+                nullable,
+                ..Constraints::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_parse_field() {
+        let csv = |field: &str, field_type: ValueType, nullable: bool| {
+            parse_field(field, ImportFormat::Csv, &column_def(field_type, nullable))
+        };
+
+        assert_eq!(csv("-5", ValueType::Int8, false), Ok(Value::Int8(-5)));
+        assert_eq!(csv("1", ValueType::Bool, false), Ok(Value::Bool(true)));
+        assert_eq!(csv("FALSE", ValueType::Bool, false), Ok(Value::Bool(false)));
+        assert_eq!(
+            csv("a, \"b\"", ValueType::String, false),
+            Ok(Value::String("a, \"b\"".to_string()))
+        );
+        assert_eq!(
+            csv("2024-01-31", ValueType::Date, false),
+            Ok(Value::Date(19753))
+        );
+        assert_eq!(
+            csv(
+                "[1, 2]",
+                ValueType::Array(Box::new(ValueType::UInt8)),
+                false
+            ),
+            Ok(Value::Array(vec![Value::UInt8(1), Value::UInt8(2)]))
+        );
+
+        // empty field is NULL, or empty string of NOT NULL string column
+        assert_eq!(csv("", ValueType::UInt8, true), Ok(Value::Null));
+        assert_eq!(csv("", ValueType::String, true), Ok(Value::Null));
+        assert_eq!(
+            csv("", ValueType::String, false),
+            Ok(Value::String(String::new()))
+        );
+        assert!(
+            csv("", ValueType::UInt8, false)
+                .unwrap_err()
+                .contains("NULL")
+        );
+
+        let error = csv("300", ValueType::UInt8, false).unwrap_err();
+        assert!(error.contains("could not parse '300' as UInt8"), "{error}");
+        let error = csv("yes", ValueType::Bool, false).unwrap_err();
+        assert!(error.contains("expected true"), "{error}");
+    }
+
+    #[test]
+    fn test_parse_tsv_field() {
+        let tsv = |field: &str, nullable: bool| {
+            parse_field(
+                field,
+                ImportFormat::Tsv,
+                &column_def(ValueType::String, nullable),
+            )
+        };
+
+        assert_eq!(
+            tsv("a\\tb\\\\n\\nc\\'", false),
+            Ok(Value::String("a\tb\\n\nc'".to_string()))
+        );
+        assert_eq!(tsv("\\N", true), Ok(Value::Null));
+        assert!(tsv("\\N", false).is_err());
+        // only CSV fields are quoted
+        assert_eq!(tsv("\"a\"", false), Ok(Value::String("\"a\"".to_string())));
+    }
+}
@@ -1,23 +1,442 @@
-use crate::error::Result;
+use crate::background_merge::BackgroundMerge;
+use crate::config::CONFIG;
+use crate::engines::EngineName;
+use crate::error::{Error, Result};
+use crate::runtime_config::TABLE_DATA;
 use crate::sql::CommandRunner;
-use crate::storage::{Column, OutputTable, TableDef, TablePart};
+use crate::sql::compiled_expr::CompiledExpr;
+use crate::sql::sql_parser::parse_expr;
+use crate::storage::{
+    Column, ColumnData, ColumnDef, OutputTable, TableDef, TablePart, TablePartInfo, Value,
+    WriteOrigin, ensure_free_space, ensure_quota, estimate_part_bytes,
+};
+
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+
+/// Held by `CommandRunner::upsert` during its read-modify-write, so parts read by one upsert are
+/// not replaced by another one meanwhile.
+static UPSERTS: Mutex<()> = Mutex::new(());
 
 impl CommandRunner {
     /// Executes INSERT operation by creating new table part.
     ///
     /// Creates a new part, saves it to raw directory, then atomically moves to normal directory.
-    /// Which results in atomic inserts.
+    /// Which results in atomic inserts. When table has `PARTITION BY`, rows are split into
+    /// a part per partition, and all parts are saved before any of them is moved.
+    ///
+    /// Table with `max_parts_per_table` active parts rejects inserts, until background merges
+    /// reduce them, so that parts are not created faster than they are merged. Insert, which
+    /// would leave less than `min_free_bytes` of disk space or exceed the quota of the database
+    /// (estimated by uncompressed size of rows), is rejected before writing any file.
     ///
     /// Returns:
-    ///   * Ok: `OutputTable` with success status
-    ///   * Error: `TableNotFound` or `CouldNotInsertData` on failure
+    ///   * Ok: `OutputTable` with success status and number of rows in created parts
+    ///   * Error: `TableNotFound`, `TableReadOnly`, `TooManyParts`, `DiskFull`, `QuotaExceeded`
+    ///     or `CouldNotInsertData` on failure
     pub fn insert(table_def: &TableDef, columns: Vec<Column>) -> Result<OutputTable> {
-        let mut table_part = TablePart::try_new(table_def, columns, None)?;
+        let (partition_by, table_col_defs) = {
+            let Some(table_config) = TABLE_DATA.get(table_def) else {
+                return Err(Error::TableNotFound);
+            };
+            table_config.metadata.check_writable(table_def)?;
+            let part_count = table_config.infos.len();
+            if let Some(max_parts) = CONFIG.get_max_parts_per_table()
+                && part_count >= max_parts
+            {
+                return Err(Error::TooManyParts(
+                    table_def.to_string(),
+                    part_count,
+                    max_parts,
+                ));
+            }
+            (
+                table_config.metadata.settings.partition_by.clone(),
+                table_config.metadata.schema.columns.clone(),
+            )
+        };
+
+        // checked before any file is written, so a full disk does not leave partial parts
+        let part_bytes = estimate_part_bytes(&columns);
+        ensure_free_space(part_bytes)?;
+        ensure_quota(&table_def.database, part_bytes)?;
+
+        let batches = match partition_by {
+            Some(partition_by) => {
+                Self::split_by_partition(&partition_by, columns, &table_col_defs)?
+            }
+            None => vec![(None, columns)],
+        };
+
+        let mut table_parts = Vec::with_capacity(batches.len());
+        for (partition, columns) in batches {
+            let mut table_part = TablePart::try_new(table_def, columns, None, partition)?;
+            table_part.save_raw(table_def, WriteOrigin::Insert)?;
+            table_parts.push(table_part);
+        }
+
+        // rows, which the engine kept, e.g., without duplicates of `ReplacingMergeTree`
+        let rows_written = table_parts.iter().map(|part| part.info.row_count).sum();
+        for table_part in table_parts {
+            table_part.move_to_normal(table_def)?;
+        }
+
+        Ok(OutputTable::build_rows_written(rows_written))
+    }
+
+    /// Executes `INSERT ... ON DUPLICATE KEY UPDATE`: inserts rows with new PRIMARY KEY values,
+    /// and applies `on_duplicate` assignments to rows with the key of an inserted row instead.
+    ///
+    /// `ReplacingMergeTree` tables insert all rows as usual, the latest row of each key replaces
+    /// older ones, so assignments are not applied. For `MergeTree` tables, merges of the table
+    /// are paused and rows of each partition are read. Partition, where existing rows are
+    /// updated, is rewritten as a single part with updated and new rows, which atomically
+    /// replaces its parts. Otherwise, new rows are written as a new part. Rows of the statement
+    /// are applied in order, so a repeated key updates the row inserted before it. Assignments
+    /// are evaluated against the row before it is updated, e.g., `hits = hits + 1` increments
+    /// `hits` of the existing row.
+    ///
+    /// Returns:
+    ///   * Ok: `OutputTable` with success status and number of inserted and updated rows.
+    ///   * Error: errors of `insert`, `CouldNotReadData` when parts could not be read, or errors of
+    ///     `apply_on_duplicate`.
+    pub fn upsert(
+        table_def: &TableDef,
+        columns: Vec<Column>,
+        on_duplicate: &[(ColumnDef, CompiledExpr)],
+    ) -> Result<OutputTable> {
+        let (engine, partition_by, table_col_defs, primary_key) = {
+            let Some(table_config) = TABLE_DATA.get(table_def) else {
+                return Err(Error::TableNotFound);
+            };
+            table_config.metadata.check_writable(table_def)?;
+            (
+                table_config.metadata.settings.engine.clone(),
+                table_config.metadata.settings.partition_by.clone(),
+                table_config.metadata.schema.columns.clone(),
+                table_config.metadata.schema.primary_key.clone(),
+            )
+        };
+        if engine == EngineName::ReplacingMergeTree {
+            return Self::insert(table_def, columns);
+        }
+
+        let _upserts = UPSERTS.lock().unwrap_or_else(PoisonError::into_inner);
+        let _merges = BackgroundMerge::exclude_tables(std::slice::from_ref(table_def));
+
+        let batches = match partition_by {
+            Some(partition_by) => {
+                Self::split_by_partition(&partition_by, columns, &table_col_defs)?
+            }
+            None => vec![(None, columns)],
+        };
+
+        let mut rows_written = 0;
+        // new part of each partition, with parts it replaces
+        let mut table_parts: Vec<(TablePart, Vec<TablePartInfo>)> = Vec::new();
+        for (partition, columns) in batches {
+            let mut parts: Vec<TablePartInfo> = TABLE_DATA
+                .get(table_def)
+                .ok_or(Error::TableNotFound)?
+                .infos
+                .iter()
+                .filter(|part| part.partition == partition)
+                .cloned()
+                .collect();
+            parts.sort_by_key(|part| part.sequence);
+
+            let mut loaded = parts
+                .iter()
+                .map(|part| BackgroundMerge::load_part(table_def, part))
+                .collect::<Result<Vec<_>>>()?;
+            let existing_rows: usize = loaded
+                .iter()
+                .map(|part| part.first().map_or(0, |col| col.data.len()))
+                .sum();
+            loaded.push(columns);
+            let mut loaded = loaded.into_iter();
+            let first = loaded.next().unwrap_or_default();
+            let mut merged = loaded.try_fold(first, |merged, part| {
+                BackgroundMerge::merge_parts(merged, part, &table_col_defs)
+            })?;
+
+            let (keep, updated) = apply_on_duplicate(
+                &mut merged,
+                existing_rows,
+                &primary_key,
+                &table_col_defs,
+                on_duplicate,
+            )?;
+            let inserted = keep[existing_rows..].iter().filter(|keep| **keep).count();
+            let updated = updated[..existing_rows]
+                .iter()
+                .filter(|updated| **updated)
+                .count();
+            rows_written += (inserted + updated) as u64;
+
+            let replaced = if updated > 0 {
+                for column in &mut merged {
+                    column.data.retain_mask(&keep);
+                }
+                ensure_free_space(estimate_part_bytes(&merged))?;
+                parts
+            } else if inserted > 0 {
+                for column in &mut merged {
+                    column.data.remove_first(existing_rows);
+                    column.data.retain_mask(&keep[existing_rows..]);
+                }
+                let part_bytes = estimate_part_bytes(&merged);
+                ensure_free_space(part_bytes)?;
+                ensure_quota(&table_def.database, part_bytes)?;
+                Vec::new()
+            } else {
+                continue;
+            };
+
+            let mut table_part = TablePart::try_new(table_def, merged, None, partition)?;
+            table_part.save_raw(table_def, WriteOrigin::Insert)?;
+            table_parts.push((table_part, replaced));
+        }
+
+        for (table_part, replaced) in table_parts {
+            if replaced.is_empty() {
+                table_part.move_to_normal(table_def)?;
+            } else if !BackgroundMerge::replace_parts(table_def, replaced, table_part) {
+                return Err(Error::CouldNotInsertData(format!(
+                    "Failed to replace parts of table {table_def} with updated rows"
+                )));
+            }
+        }
+
+        Ok(OutputTable::build_rows_written(rows_written))
+    }
 
-        table_part.save_raw(table_def)?;
+    /// Evaluates `PARTITION BY` expression for each row and groups rows by its value.
+    ///
+    /// Returns:
+    ///   * Ok: partition directory name and columns with its rows, for each partition.
+    ///   * Error: any error from parsing, compiling or evaluating the expression.
+    fn split_by_partition(
+        partition_by: &str,
+        mut columns: Vec<Column>,
+        table_col_defs: &[ColumnDef],
+    ) -> Result<Vec<(Option<String>, Vec<Column>)>> {
+        let expr = CompiledExpr::compile(&parse_expr(partition_by)?, table_col_defs)?;
+
+        let data_idxs: Vec<_> = table_col_defs
+            .iter()
+            .map(|table_col_def| {
+                columns
+                    .iter()
+                    .position(|col| col.column_def == *table_col_def)
+            })
+            .collect();
+        let row_count = columns.first().map_or(0, |col| col.data.len());
+
+        let mut partitions = Vec::new();
+        let mut partition_idxs = HashMap::new();
+        let mut row_partitions = Vec::with_capacity(row_count);
+        for row_idx in 0..row_count {
+            let value = expr.eval(&|col_idx| {
+                Ok(data_idxs[col_idx].map_or(Value::Null, |data_idx| {
+                    columns[data_idx].data[row_idx].clone()
+                }))
+            })?;
+
+            let partition = value.to_partition_id();
+            let partition_idx = *partition_idxs.entry(partition.clone()).or_insert_with(|| {
+                partitions.push(partition);
+                partitions.len() - 1
+            });
+            row_partitions.push(partition_idx);
+        }
+
+        if partitions.len() == 1 {
+            return Ok(vec![(partitions.pop(), columns)]);
+        }
+
+        let mut batches: Vec<Vec<Column>> =
+            vec![Vec::with_capacity(columns.len()); partitions.len()];
+        for column in &mut columns {
+            let mut batch_data = vec![ColumnData::default(); partitions.len()];
+            if let ColumnData::Constant { value, .. } = &column.data {
+                for &partition_idx in &row_partitions {
+                    batch_data[partition_idx].push_constant(value.clone(), 1);
+                }
+            } else {
+                for (value, &partition_idx) in
+                    column.data.make_dense().drain(..).zip(&row_partitions)
+                {
+                    batch_data[partition_idx].push(value);
+                }
+            }
+            for (batch, data) in batches.iter_mut().zip(batch_data) {
+                batch.push(Column {
+                    column_def: column.column_def.clone(),
+                    data,
+                });
+            }
+        }
+
+        Ok(partitions.into_iter().map(Some).zip(batches).collect())
+    }
+}
+
+/// Applies rows of `columns` after the first `existing_rows` in order, see
+/// `CommandRunner::upsert`. Row with new PRIMARY KEY values is kept. Row with the key of
+/// a previous row is dropped, and `on_duplicate` assignments are applied to all previous rows
+/// with this key. Expressions of assignments use `table_col_defs`, and are all evaluated against
+/// the row before any of them is assigned.
+///
+/// Returns:
+///   * Ok: mask of kept rows and mask of updated rows.
+///   * Error: any error from evaluating an assignment, or `CouldNotInsertData` when it is `NULL`
+///     for NOT NULL column.
+fn apply_on_duplicate(
+    columns: &mut [Column],
+    existing_rows: usize,
+    primary_key: &[ColumnDef],
+    table_col_defs: &[ColumnDef],
+    on_duplicate: &[(ColumnDef, CompiledExpr)],
+) -> Result<(Vec<bool>, Vec<bool>)> {
+    let position = |col_def: &ColumnDef| {
+        columns
+            .iter()
+            .position(|col| col.column_def.name == col_def.name)
+    };
+    let key_idxs: Vec<usize> = primary_key.iter().filter_map(position).collect();
+    let data_idxs: Vec<Option<usize>> = table_col_defs.iter().map(position).collect();
+    let assignments: Vec<(usize, &ColumnDef, &CompiledExpr)> = on_duplicate
+        .iter()
+        .filter_map(|(col_def, expr)| Some((position(col_def)?, col_def, expr)))
+        .collect();
+    // `Value` is not hashable, its debug representation is unambiguous for values of the same
+    // types, which key columns have
+    let row_key = |columns: &[Column], row: usize| {
+        let key: Vec<_> = key_idxs
+            .iter()
+            .map(|&idx| &columns[idx].data[row])
+            .collect();
+        format!("{key:?}")
+    };
+
+    let row_count = columns.first().map_or(0, |col| col.data.len());
+    let mut rows_by_key: HashMap<String, Vec<usize>> = HashMap::new();
+    for row in 0..existing_rows {
+        rows_by_key
+            .entry(row_key(columns, row))
+            .or_default()
+            .push(row);
+    }
+
+    let mut keep = vec![true; row_count];
+    let mut updated = vec![false; row_count];
+    for (row, keep_row) in keep.iter_mut().enumerate().skip(existing_rows) {
+        let key = row_key(columns, row);
+        let Some(rows) = rows_by_key.get(&key) else {
+            rows_by_key.insert(key, vec![row]);
+            continue;
+        };
+        *keep_row = false;
+        for &updated_row in rows {
+            let get_column = |col_idx: usize| {
+                Ok(data_idxs[col_idx].map_or(Value::Null, |data_idx| {
+                    columns[data_idx].data[updated_row].clone()
+                }))
+            };
+            let values = assignments
+                .iter()
+                .map(|(_, col_def, expr)| {
+                    let value = expr.eval(&get_column)?;
+                    if value == Value::Null && !col_def.constraints.nullable {
+                        return Err(Error::CouldNotInsertData(format!(
+                            "NULL value not allowed for column '{}'",
+                            col_def.name
+                        )));
+                    }
+                    Ok(value)
+                })
+                .collect::<Result<Vec<_>>>()?;
+            for (&(idx, ..), value) in assignments.iter().zip(values) {
+                columns[idx].data[updated_row] = value;
+            }
+            updated[updated_row] = true;
+        }
+    }
+    Ok((keep, updated))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::compiled_expr::ArithmeticOp;
+    use crate::storage::{Constraints, ValueType};
+
+    fn column(name: &str, values: &[u8]) -> Column {
+        Column {
+            column_def: ColumnDef {
+                name: name.to_string(),
+                field_type: ValueType::UInt8,
+                constraints: Constraints::default(),
+            },
+            data: values.iter().map(|&value| Value::UInt8(value)).collect(),
+        }
+    }
+
+    #[test]
+    fn test_apply_on_duplicate() {
+        // rows (1, 1) and (2, 2) exist twice, then (2, 3), (4, 4) and (4, 5) are inserted
+        let mut columns = vec![
+            column("id", &[1, 2, 2, 2, 4, 4]),
+            column("n", &[1, 2, 2, 3, 4, 5]),
+        ];
+        let primary_key = [columns[0].column_def.clone()];
+        let table_col_defs: Vec<_> = columns.iter().map(|col| col.column_def.clone()).collect();
+        let on_duplicate = [(
+            columns[1].column_def.clone(),
+            CompiledExpr::Literal(Value::UInt8(9)),
+        )];
+
+        let (keep, updated) = apply_on_duplicate(
+            &mut columns,
+            3,
+            &primary_key,
+            &table_col_defs,
+            &on_duplicate,
+        )
+        .unwrap();
+        assert_eq!(keep, [true, true, true, false, true, false]);
+        assert_eq!(updated, [false, true, true, false, true, false]);
+        assert_eq!(
+            columns[1].data,
+            [1, 9, 9, 3, 9, 5].map(Value::UInt8).into_iter().collect()
+        );
+    }
 
-        table_part.move_to_normal(table_def)?;
+    #[test]
+    fn test_apply_on_duplicate_expression() {
+        // key 1 exists, then is inserted twice; `n` is incremented by `id` once per duplicate,
+        // table columns are in another order than the loaded ones
+        let mut columns = vec![column("id", &[1, 1, 1]), column("n", &[5, 0, 0])];
+        let primary_key = [columns[0].column_def.clone()];
+        let table_col_defs = [columns[1].column_def.clone(), columns[0].column_def.clone()];
+        let on_duplicate = [(
+            columns[1].column_def.clone(),
+            CompiledExpr::Arithmetic {
+                op: ArithmeticOp::Add,
+                left: Box::new(CompiledExpr::Column(0)),
+                right: Box::new(CompiledExpr::Column(1)),
+            },
+        )];
 
-        Ok(OutputTable::build_ok())
+        apply_on_duplicate(
+            &mut columns,
+            1,
+            &primary_key,
+            &table_col_defs,
+            &on_duplicate,
+        )
+        .unwrap();
+        assert_eq!(columns[1].data[0], Value::UInt8(7));
     }
 }
@@ -0,0 +1,148 @@
+use crate::error::{Error, Result};
+
+use std::cell::RefCell;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+thread_local! {
+    /// Tracker of the query, which current thread executes, see `MemoryTracker::enter`.
+    static CURRENT_TRACKER: RefCell<Option<MemoryTracker>> = const { RefCell::new(None) };
+}
+
+/// Approximate memory usage of a single query, limited by `max_memory_per_query`.
+///
+/// There is no tracking allocator, so only the major buffers of `SELECT` report their sizes:
+/// rows buffered by scan threads (which become rows of the result), decompressed granules and
+/// sorting in post-processing. Sizes are estimated from the number of values and the size of
+/// decompressed granules, so usage is neither exact nor complete. Clones share the counters,
+/// so scan threads report to the tracker of their query.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct MemoryTracker {
+    used: Arc<AtomicUsize>,
+    peak: Arc<AtomicUsize>,
+    /// `None` means no limit.
+    limit: Option<usize>,
+}
+
+impl MemoryTracker {
+    pub(crate) fn new(limit: Option<usize>) -> Self {
+        Self {
+            limit,
+            ..Self::default()
+        }
+    }
+
+    /// Returns tracker of the query, which current thread executes, or a new tracker without
+    /// limit, when there is none (e.g., in tests).
+    pub(crate) fn current() -> Self {
+        CURRENT_TRACKER
+            .with_borrow(Clone::clone)
+            .unwrap_or_default()
+    }
+
+    /// Makes the tracker current for the thread, until returned guard is dropped.
+    pub(crate) fn enter(&self) -> MemoryTrackerGuard {
+        MemoryTrackerGuard {
+            previous: CURRENT_TRACKER.replace(Some(self.clone())),
+        }
+    }
+
+    /// Adds `bytes`, which stay allocated until the query finishes.
+    ///
+    /// Returns:
+    ///   * Ok: usage is within the limit.
+    ///   * Error: `MemoryLimitExceeded` when usage would exceed the limit, then `bytes` are not
+    ///     added.
+    pub(crate) fn alloc(&self, bytes: usize) -> Result<()> {
+        let used = self.used.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        if let Some(limit) = self.limit
+            && used > limit
+        {
+            self.free(bytes);
+            return Err(Error::MemoryLimitExceeded { used, limit });
+        }
+        self.peak.fetch_max(used, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Adds `bytes` of a temporary buffer, until returned reservation is dropped.
+    ///
+    /// Returns:
+    ///   * Ok: reservation of `bytes`.
+    ///   * Error: `MemoryLimitExceeded`, see `alloc`.
+    pub(crate) fn reserve(&self, bytes: usize) -> Result<MemoryReservation<'_>> {
+        self.alloc(bytes)?;
+        Ok(MemoryReservation {
+            tracker: self,
+            bytes,
+        })
+    }
+
+    /// Removes `bytes`, which were added by `alloc`, e.g., of rows, which were dropped.
+    pub(crate) fn free(&self, bytes: usize) {
+        self.used.fetch_sub(bytes, Ordering::Relaxed);
+    }
+
+    /// Returns max usage since the tracker was created.
+    pub(crate) fn peak(&self) -> usize {
+        self.peak.load(Ordering::Relaxed)
+    }
+}
+
+/// Restores previous tracker of the thread on drop, see `MemoryTracker::enter`.
+pub(crate) struct MemoryTrackerGuard {
+    previous: Option<MemoryTracker>,
+}
+
+impl Drop for MemoryTrackerGuard {
+    fn drop(&mut self) {
+        CURRENT_TRACKER.set(self.previous.take());
+    }
+}
+
+/// Temporary buffer, which is counted by the tracker until drop, see `MemoryTracker::reserve`.
+pub(crate) struct MemoryReservation<'a> {
+    tracker: &'a MemoryTracker,
+    bytes: usize,
+}
+
+impl Drop for MemoryReservation<'_> {
+    fn drop(&mut self) {
+        self.tracker.free(self.bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_tracker_limit_and_peak() {
+        let tracker = MemoryTracker::new(Some(100));
+        tracker.alloc(40).unwrap();
+        {
+            let _buffer = tracker.reserve(50).unwrap();
+            assert_eq!(
+                tracker.reserve(20).err(),
+                Some(Error::MemoryLimitExceeded {
+                    used: 110,
+                    limit: 100
+                })
+            );
+        }
+        // reservations are released, rejected bytes are not added
+        tracker.alloc(60).unwrap();
+        assert_eq!(tracker.peak(), 100);
+        assert!(tracker.alloc(1).is_err());
+
+        // clones share usage, current tracker is restored on drop of the guard
+        assert_eq!(MemoryTracker::current().limit, None);
+        {
+            let _guard = tracker.enter();
+            let current = MemoryTracker::current();
+            assert_eq!(current.limit, Some(100));
+            assert!(current.alloc(1).is_err());
+        }
+        assert_eq!(MemoryTracker::current().limit, None);
+    }
+}
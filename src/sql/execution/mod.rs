@@ -1,8 +1,36 @@
+/// Module for `ALTER TABLE ... MODIFY SETTING` queries.
+mod alter;
+/// Module for `SELECT count(*)` queries.
+mod count;
 /// Module for `CREATE TABLE` and `CREATE DATABASE` queries.
 mod create;
+/// Module for `SYSTEM DISK USAGE` queries.
+mod disk_usage;
 /// Module for `DROP TABLE` and `DROP DATABASE` queries.
 mod drop;
+/// Module for `EXCHANGE TABLES` queries.
+mod exchange;
+/// Module for `EXPLAIN ANALYZE` queries.
+mod explain;
+/// Module for `FLATTEN` of `SELECT` source.
+mod flatten;
+/// Module for `IMPORT INTO` queries.
+mod import;
 /// Module for `INSERT INTO` queries.
 mod insert;
+/// Module for memory usage of queries, see `max_memory_per_query`.
+pub(super) mod memory;
+/// Module for `OPTIMIZE TABLE` queries.
+mod optimize;
+/// Module for `SYSTEM PART INFO` queries.
+mod part_info;
+/// Module for `PIVOT` of `SELECT` source.
+mod pivot;
+/// Module for `RESAMPLE BY` of `SELECT`.
+mod resample;
 /// Module for `SELECT` queries.
-mod select;
+pub(super) mod select;
+/// Module for `SHOW` queries.
+mod show;
+/// Module for `VALIDATE` queries.
+mod validate;
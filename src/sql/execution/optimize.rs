@@ -0,0 +1,98 @@
+use crate::background_merge::BackgroundMerge;
+use crate::config::CONFIG;
+use crate::error::{Error, Result};
+use crate::runtime_config::TABLE_DATA;
+use crate::sql::CommandRunner;
+use crate::storage::{Column, ColumnDef, Constraints, OutputTable, TableDef, Value, ValueType};
+
+use std::collections::HashSet;
+use std::sync::mpsc::RecvTimeoutError;
+
+impl CommandRunner {
+    /// Merges all parts of each partition of the table into one, ahead of automatically
+    /// selected merges (see `BackgroundMerge::request_merge`), and waits for it up to
+    /// `optimize_timeout_secs`. Parts, inserted meanwhile, are not merged. Table with
+    /// `no_merges` flag is not merged.
+    ///
+    /// Returns:
+    ///   * Ok: `OutputTable` with a single row of `status` ("merged", "merge scheduled", when
+    ///     the merge is still running, or "nothing to merge", when every partition has a single
+    ///     part), `rows_before`, `rows_after`, `parts_before` and `parts_after` columns.
+    ///   * Error: `TableNotFound` when table was dropped, `MergesStopped` when table has
+    ///     `no_merges` flag, or error of the merge, e.g., `DiskFull`.
+    pub fn optimize_table(table_def: &TableDef) -> Result<OutputTable> {
+        TABLE_DATA
+            .get(table_def)
+            .ok_or(Error::TableNotFound)?
+            .metadata
+            .check_mergeable(table_def)?;
+        let (rows_before, parts_before) = table_stats(table_def)?;
+        if !has_parts_to_merge(table_def)? {
+            return Ok(optimize_output(
+                "nothing to merge",
+                (rows_before, parts_before),
+                (rows_before, parts_before),
+            ));
+        }
+
+        let result = BackgroundMerge::request_merge(table_def.clone());
+        let status = match result.recv_timeout(CONFIG.get_optimize_timeout()) {
+            Ok(merged) => {
+                merged?;
+                "merged"
+            }
+            Err(RecvTimeoutError::Timeout) => "merge scheduled",
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(Error::Internal(format!(
+                    "Merge of table {table_def} was dropped"
+                )));
+            }
+        };
+        Ok(optimize_output(
+            status,
+            (rows_before, parts_before),
+            table_stats(table_def)?,
+        ))
+    }
+}
+
+/// Returns rows and number of active parts of the table.
+fn table_stats(table_def: &TableDef) -> Result<(u64, u64)> {
+    let table_data = TABLE_DATA.get(table_def).ok_or(Error::TableNotFound)?;
+    Ok((table_data.total_rows(), table_data.infos.len() as u64))
+}
+
+/// Returns whether some partition of the table has more than one part.
+fn has_parts_to_merge(table_def: &TableDef) -> Result<bool> {
+    let table_data = TABLE_DATA.get(table_def).ok_or(Error::TableNotFound)?;
+    let mut partitions = HashSet::with_capacity(table_data.infos.len());
+    Ok(!table_data
+        .infos
+        .iter()
+        .all(|info| partitions.insert(&info.partition)))
+}
+
+fn optimize_output(status: &str, before: (u64, u64), after: (u64, u64)) -> OutputTable {
+    OutputTable::new(vec![
+        column(
+            "status",
+            ValueType::String,
+            Value::String(status.to_string()),
+        ),
+        column("rows_before", ValueType::UInt64, Value::UInt64(before.0)),
+        column("rows_after", ValueType::UInt64, Value::UInt64(after.0)),
+        column("parts_before", ValueType::UInt64, Value::UInt64(before.1)),
+        column("parts_after", ValueType::UInt64, Value::UInt64(after.1)),
+    ])
+}
+
+fn column(name: &str, field_type: ValueType, value: Value) -> Column {
+    Column {
+        column_def: ColumnDef {
+            name: name.to_string(),
+            field_type,
+            constraints: Constraints::default(),
+        },
+        data: vec![value].into(),
+    }
+}
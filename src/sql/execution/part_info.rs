@@ -0,0 +1,161 @@
+use crate::error::{Error, Result};
+use crate::runtime_config::TABLE_DATA;
+use crate::sql::CommandRunner;
+use crate::storage::{
+    Column, ColumnDef, Constraints, MarkInfo, OutputTable, TableDef, TablePartInfo, Value,
+    ValueType,
+};
+
+impl CommandRunner {
+    /// Reports the sparse index of the active part: row per mark and `ORDER BY` column of the
+    /// mark, in part order. Row count of a granule is read from the file of the first index
+    /// column, so every granule of it is decompressed.
+    ///
+    /// Returns:
+    ///   * Ok: `OutputTable` with `mark_idx`, `pk_col_name`, `pk_min_value` (value of the first
+    ///     row of the granule as SQL literal), `start_byte`, `end_byte` and `compressed_size`
+    ///     (of the granule in the column file) and `granule_row_count` columns.
+    ///   * Error: `TableNotFound` when table does not exist, `PartNotFound` when table has no
+    ///     such active part, or `CouldNotReadData` when column file could not be read or its marks
+    ///     do not match marks of the part.
+    pub fn part_info(table_def: &TableDef, part_name: &str) -> Result<OutputTable> {
+        // files are read after `TABLE_DATA` is unlocked, so that queries and merges are not blocked
+        let (part_info, order_by) = {
+            let table_data = TABLE_DATA.get(table_def).ok_or(Error::TableNotFound)?;
+            let part_info = table_data
+                .infos
+                .iter()
+                .find(|info| info.name == part_name)
+                .ok_or_else(|| Error::PartNotFound(format!("{table_def}/{part_name}")))?
+                .clone();
+            (part_info, table_data.metadata.schema.order_by.clone())
+        };
+
+        // values of a mark belong to `ORDER BY` columns in part column order, see
+        // `generate_indexes`
+        let index_columns: Vec<(usize, &ColumnDef)> = part_info
+            .column_defs
+            .iter()
+            .enumerate()
+            .filter(|(_, col_def)| order_by.contains(col_def))
+            .collect();
+
+        let index_marks = index_columns
+            .iter()
+            .map(|&(file_idx, col_def)| {
+                part_info
+                    .read_column_marks(table_def, file_idx)
+                    .map_err(|error| {
+                        error.with_read_context(format!(
+                            "table={table_def}, part={}, column={}",
+                            part_info.name, col_def.name
+                        ))
+                    })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let granule_row_counts = match (index_columns.first(), index_marks.first()) {
+            (Some(&(_, col_def)), Some(marks)) => {
+                granule_row_counts(table_def, &part_info, col_def, marks)?
+            }
+            _ => Vec::new(),
+        };
+
+        let mut mark_idxs = Vec::new();
+        let mut col_names = Vec::new();
+        let mut min_values = Vec::new();
+        let mut start_bytes = Vec::new();
+        let mut end_bytes = Vec::new();
+        let mut compressed_sizes = Vec::new();
+        let mut row_counts = Vec::new();
+        for (mark_idx, mark) in part_info.marks.iter().enumerate() {
+            for ((&(_, col_def), marks), value) in
+                index_columns.iter().zip(&index_marks).zip(&mark.index)
+            {
+                // marks of column file may disagree with marks of the part, when files are corrupted
+                let corrupted = |message: &str| {
+                    Error::CouldNotReadData(format!(
+                        "table={table_def}, part={}, column={}: {message} of mark {mark_idx}",
+                        part_info.name, col_def.name
+                    ))
+                };
+                let mark_info = marks
+                    .get(mark_idx)
+                    .ok_or_else(|| corrupted("Missing column mark"))?;
+                let compressed_size = mark_info
+                    .end
+                    .checked_sub(mark_info.start)
+                    .ok_or_else(|| corrupted("End byte before start byte"))?;
+                let row_count = *granule_row_counts
+                    .get(mark_idx)
+                    .ok_or_else(|| corrupted("Missing granule"))?;
+                mark_idxs.push(Value::UInt64(mark_idx as u64));
+                col_names.push(Value::String(col_def.name.clone()));
+                min_values.push(Value::String(value.to_sql_literal()));
+                start_bytes.push(Value::UInt64(mark_info.start));
+                end_bytes.push(Value::UInt64(mark_info.end));
+                compressed_sizes.push(Value::UInt64(compressed_size));
+                row_counts.push(Value::UInt64(row_count));
+            }
+        }
+
+        Ok(OutputTable::new(vec![
+            column("mark_idx", ValueType::UInt64, mark_idxs),
+            column("pk_col_name", ValueType::String, col_names),
+            column("pk_min_value", ValueType::String, min_values),
+            column("start_byte", ValueType::UInt64, start_bytes),
+            column("end_byte", ValueType::UInt64, end_bytes),
+            column("compressed_size", ValueType::UInt64, compressed_sizes),
+            column("granule_row_count", ValueType::UInt64, row_counts),
+        ]))
+    }
+}
+
+/// Returns number of rows of every granule of the part, read from the column file.
+fn granule_row_counts(
+    table_def: &TableDef,
+    part_info: &TablePartInfo,
+    col_def: &ColumnDef,
+    marks: &[MarkInfo],
+) -> Result<Vec<u64>> {
+    let read_context = || {
+        format!(
+            "table={table_def}, part={}, column={}",
+            part_info.name, col_def.name
+        )
+    };
+    let compression_type = &col_def.constraints.compression_type;
+    let mmap = Column::open_as_mmap(&part_info.get_column_path(table_def, col_def))
+        .map_err(|error| error.with_read_context(read_context()))?;
+    Column::validate_mmap(&mmap, &col_def.name)
+        .map_err(|error| error.with_read_context(read_context()))?;
+    let dictionary = TablePartInfo::read_dictionary(&mmap, compression_type)
+        .map_err(|error| error.with_read_context(read_context()))?;
+
+    marks
+        .iter()
+        .enumerate()
+        .map(|(granule_idx, mark_info)| {
+            let granule_bytes = TablePartInfo::get_granule_bytes_decompressed(
+                &mmap,
+                mark_info,
+                compression_type,
+                dictionary.as_deref(),
+                granule_idx,
+            )?;
+            let granule = TablePartInfo::access_granule(&granule_bytes)?;
+            Ok(granule.len() as u64)
+        })
+        .collect::<Result<_>>()
+        .map_err(|error: Error| error.with_read_context(read_context()))
+}
+
+fn column(name: &str, field_type: ValueType, data: Vec<Value>) -> Column {
+    Column {
+        column_def: ColumnDef {
+            name: name.to_string(),
+            field_type,
+            constraints: Constraints::default(),
+        },
+        data: data.into(),
+    }
+}
@@ -0,0 +1,99 @@
+use crate::error::{Error, Result};
+use crate::sql::CommandRunner;
+use crate::sql::logical_plan::pivot::pivot_columns;
+use crate::sql::sql_parser::{LogicalPlan, PhysicalPlan};
+use crate::storage::{Column, ColumnDef, OutputTable, Value, ValueType};
+
+use std::collections::HashMap;
+
+impl CommandRunner {
+    /// Executes the query of `plan` and reshapes its rows, see `LogicalPlan::Pivot`.
+    ///
+    /// Groups are returned in order of their first row. Rows with other values of `pivot_col`
+    /// are ignored. Sum over no rows, or over `NULL` values only, is `NULL`, and it wraps on
+    /// overflow like integer arithmetic.
+    ///
+    /// Returns:
+    ///   * Ok: `OutputTable` with `group_by` columns and a column per value of `pivot_values`.
+    ///   * Error: any error from executing `plan`, or `Internal` when it did not return
+    ///     pivoted columns.
+    pub fn pivot(
+        group_by: &[ColumnDef],
+        pivot_col: &ColumnDef,
+        value_col: &ColumnDef,
+        pivot_values: &[Value],
+        plan: LogicalPlan,
+    ) -> Result<OutputTable> {
+        let mut source = Self::execute_physical_plan(PhysicalPlan::from(plan))?.columns;
+        let mut take_column = |col_def: &ColumnDef| {
+            source
+                .iter_mut()
+                .find(|column| column.column_def == *col_def)
+                .map(|column| std::mem::take(&mut column.data))
+                .ok_or_else(|| {
+                    Error::Internal(format!("PIVOT source has no column {}", col_def.name))
+                })
+        };
+        let group_data = group_by
+            .iter()
+            .map(&mut take_column)
+            .collect::<Result<Vec<_>>>()?;
+        let pivot_data = take_column(pivot_col)?;
+        let value_data = take_column(value_col)?;
+
+        // `Value` is not hashable, literals of values of the same column are unique
+        let mut group_idxs = HashMap::new();
+        let mut first_rows = Vec::new();
+        let mut sums: Vec<Vec<Option<i128>>> = Vec::new();
+        for (row_idx, (pivot_value, value)) in pivot_data.iter().zip(&value_data).enumerate() {
+            let Some(value_idx) = pivot_values.iter().position(|val| val == pivot_value) else {
+                continue;
+            };
+            let key: Vec<_> = group_data
+                .iter()
+                .map(|data| data[row_idx].to_sql_literal())
+                .collect();
+            let group_idx = *group_idxs.entry(key).or_insert_with(|| {
+                first_rows.push(row_idx);
+                sums.push(vec![None; pivot_values.len()]);
+                sums.len() - 1
+            });
+
+            if let Some(value) = value.as_i128() {
+                let sum = &mut sums[group_idx][value_idx];
+                *sum = Some(sum.unwrap_or(0).wrapping_add(value));
+            }
+        }
+
+        let col_defs = pivot_columns(group_by, value_col, pivot_values);
+        let mut columns: Vec<_> = col_defs
+            .iter()
+            .zip(&group_data)
+            .map(|(column_def, data)| Column {
+                column_def: column_def.clone(),
+                data: first_rows
+                    .iter()
+                    .map(|row_idx| data[*row_idx].clone())
+                    .collect(),
+            })
+            .collect();
+        for (value_idx, column_def) in col_defs[group_by.len()..].iter().enumerate() {
+            let data = sums
+                .iter()
+                .map(|group_sums| match group_sums[value_idx] {
+                    None => Value::Null,
+                    Some(sum) if column_def.field_type == ValueType::Int64 => {
+                        Value::Int64(sum as i64)
+                    }
+                    Some(sum) => Value::UInt64(sum as u64),
+                })
+                .collect();
+            columns.push(Column {
+                column_def: column_def.clone(),
+                data,
+            });
+        }
+
+        Ok(OutputTable::new(columns))
+    }
+}
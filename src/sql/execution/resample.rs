@@ -0,0 +1,154 @@
+use crate::error::{Error, Result};
+use crate::sql::CommandRunner;
+use crate::sql::sql_parser::{LogicalPlan, PhysicalPlan, ResampleAggregate, ResampleColumn};
+use crate::storage::{Column, ColumnData, ColumnDef, OutputTable, Value, ValueType, date};
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+impl CommandRunner {
+    /// Executes the query of `plan` and groups its rows into buckets of `interval` by `key`,
+    /// see `LogicalPlan::Resample`.
+    ///
+    /// Only buckets with rows are returned, in ascending order. Rows with `NULL` key are
+    /// skipped. `DateTime` keys are bucketed as unix seconds and `Date` keys as seconds of the
+    /// start of the day. Sum, minimum and maximum over `NULL` values only are `NULL`, and sum wraps on
+    /// overflow like integer arithmetic.
+    ///
+    /// Returns:
+    ///   * Ok: `OutputTable` with `columns`, row per bucket.
+    ///   * Error: any error from executing `plan`, or `Internal` when it did not return key or
+    ///     aggregated columns.
+    pub fn resample(
+        key: &ColumnDef,
+        interval: u64,
+        columns: &[ResampleColumn],
+        plan: LogicalPlan,
+    ) -> Result<OutputTable> {
+        let source = Self::execute_physical_plan(PhysicalPlan::from(plan))?.columns;
+        let find_column = |col_def: &ColumnDef| {
+            source
+                .iter()
+                .find(|column| column.column_def == *col_def)
+                .map(|column| &column.data)
+                .ok_or_else(|| {
+                    Error::Internal(format!("RESAMPLE BY source has no column {}", col_def.name))
+                })
+        };
+
+        let interval = i128::from(interval);
+        let mut buckets: BTreeMap<i128, Vec<usize>> = BTreeMap::new();
+        for (row_idx, value) in find_column(key)?.iter().enumerate() {
+            if let Some(value) = key_seconds(value) {
+                let bucket = value - value.rem_euclid(interval);
+                buckets.entry(bucket).or_default().push(row_idx);
+            }
+        }
+
+        let columns = columns
+            .iter()
+            .map(|column| {
+                let data: ColumnData = match &column.aggregate {
+                    ResampleAggregate::Bucket => buckets
+                        .keys()
+                        .map(|&bucket| bucket_value(bucket, &column.column_def.field_type))
+                        .collect(),
+                    ResampleAggregate::CountRows => buckets
+                        .values()
+                        .map(|rows| Value::UInt64(rows.len() as u64))
+                        .collect(),
+                    ResampleAggregate::Count(col_def) => {
+                        let data = find_column(col_def)?;
+                        buckets
+                            .values()
+                            .map(|rows| {
+                                let count = rows.iter().filter(|&&row| data[row] != Value::Null);
+                                Value::UInt64(count.count() as u64)
+                            })
+                            .collect()
+                    }
+                    ResampleAggregate::Sum(col_def) => {
+                        let data = find_column(col_def)?;
+                        buckets
+                            .values()
+                            .map(|rows| {
+                                rows.iter()
+                                    .filter_map(|&row| data[row].as_i128())
+                                    .reduce(i128::wrapping_add)
+                                    .map_or(Value::Null, |sum| {
+                                        integer_value(sum, &column.column_def.field_type)
+                                    })
+                            })
+                            .collect()
+                    }
+                    ResampleAggregate::Min(col_def) => {
+                        extreme(find_column(col_def)?, &buckets, Ordering::Less)
+                    }
+                    ResampleAggregate::Max(col_def) => {
+                        extreme(find_column(col_def)?, &buckets, Ordering::Greater)
+                    }
+                };
+                Ok(Column {
+                    column_def: column.column_def.clone(),
+                    data,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(OutputTable::new(columns))
+    }
+}
+
+/// Returns key as a number to bucket by: the value of integers, and unix seconds of `Date` and
+/// `DateTime` values, which the query returns as text (see `ValueType::to_output_value`).
+/// None for `NULL`.
+fn key_seconds(value: &Value) -> Option<i128> {
+    match value {
+        Value::String(text) => date::parse_seconds(text).map(i128::from),
+        value => value.as_i128(),
+    }
+}
+
+/// Returns start of the bucket as `field_type` (see `bucket_type`), as it is returned to
+/// clients. `Date` bucket is the day, on which it starts.
+fn bucket_value(bucket: i128, field_type: &ValueType) -> Value {
+    match field_type {
+        ValueType::Date => {
+            field_type.to_output_value(Value::Date(bucket.div_euclid(86_400) as u16))
+        }
+        ValueType::DateTime => field_type.to_output_value(Value::DateTime(bucket as u32)),
+        field_type => integer_value(bucket, field_type),
+    }
+}
+
+/// Returns `Value::Int64` or `Value::UInt64` of `value`, by `field_type`, wrapping on overflow.
+fn integer_value(value: i128, field_type: &ValueType) -> Value {
+    if *field_type == ValueType::Int64 {
+        Value::Int64(value as i64)
+    } else {
+        Value::UInt64(value as u64)
+    }
+}
+
+/// Returns non-`NULL` value of every bucket, which is `ordering` to all other ones, i.e.
+/// minimum for `Ordering::Less`.
+fn extreme(
+    data: &ColumnData,
+    buckets: &BTreeMap<i128, Vec<usize>>,
+    ordering: Ordering,
+) -> ColumnData {
+    buckets
+        .values()
+        .map(|rows| {
+            let mut best: Option<&Value> = None;
+            for value in rows.iter().map(|&row| &data[row]) {
+                if *value != Value::Null
+                    && best.is_none_or(|best| value.partial_cmp(best) == Some(ordering))
+                {
+                    best = Some(value);
+                }
+            }
+            best.cloned().unwrap_or(Value::Null)
+        })
+        .collect()
+}
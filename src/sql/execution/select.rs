@@ -1,27 +1,92 @@
-use crate::engines::{EngineConfig, EngineName};
+use crate::engines::{EngineConfig, EngineName, compare_key_values};
 use crate::error::{Error, Result};
-use crate::runtime_config::TABLE_DATA;
+use crate::logging::{self, QueryIdGuard};
+use crate::runtime_config::{Instance, TABLE_DATA};
 use crate::sql::CommandRunner;
+use crate::sql::compiled_expr::CompiledExpr;
 use crate::sql::compiled_filter::{BinOp, CompiledFilter};
-use crate::sql::sql_parser::ScanSource;
+use crate::sql::execution::explain::{PartStats, QueryStats, Stage};
+use crate::sql::execution::memory::MemoryTracker;
+use crate::sql::logical_plan::pivot::pivot_columns;
+use crate::sql::sql_parser::{ComputedColumn, LogicalPlan, QuerySettings, RowNumber, ScanSource};
+use crate::sql::system_table::SystemTable;
 use crate::storage::value::ArchivedValue;
-use crate::storage::{Column, ColumnDef, Mark, OutputTable, TableDef, TablePartInfo, Value};
-use std::cell::RefCell;
+use crate::storage::{
+    Column, ColumnData, ColumnDef, Constraints, Mark, MarkInfo, OutputTable, TableDef,
+    TablePartInfo, Value, ValueType,
+};
+use log::warn;
+use memmap2::Mmap;
+use std::cell::{Cell, RefCell};
 
 use rayon::prelude::*;
 use rkyv::vec::ArchivedVec;
 use sqlparser::ast::Expr;
-use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
-use std::sync::{Arc, RwLock};
+use std::cmp::Ordering as CmpOrdering;
+use std::collections::BinaryHeap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
 
 thread_local! {
-    static LOCAL_BUFFER: RefCell<Vec<Vec<Value>>> = const { RefCell::new(Vec::new()) };
+    static LOCAL_BUFFER: RefCell<Vec<ColumnData>> = const { RefCell::new(Vec::new()) };
 }
 
-struct ScanConfig {
-    result: Arc<RwLock<Vec<Column>>>,
+/// Clears `LOCAL_BUFFER` when dropped, so rows of a failed chunk are not left to the next scan
+/// on the same thread.
+struct LocalBufferGuard;
+
+impl Drop for LocalBufferGuard {
+    fn drop(&mut self) {
+        LOCAL_BUFFER.take();
+    }
+}
+
+/// Target number of scan chunks per thread, see `CommandRunner::scan_chunk_size`.
+const SCAN_CHUNKS_PER_THREAD: usize = 4;
+/// Max compressed size of granules in a single scan chunk.
+const SCAN_CHUNK_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Range of values of a primary key column in a granule, see `CommandRunner::granule_bounds`.
+/// `None` bound is unknown.
+#[derive(Debug, Clone, Copy, Default)]
+struct GranuleBounds<'a> {
+    lower: Option<&'a Value>,
+    upper: Option<&'a Value>,
+}
+
+impl GranuleBounds<'_> {
+    /// Checks whether any value within bounds may satisfy `op` with `value`. Conservative:
+    /// returns `true` for unknown bounds and values, which are not comparable.
+    ///
+    /// `NULL` keys are ordered last, so granule starting with `NULL` holds only `NULL`s.
+    fn may_match(&self, op: &BinOp, value: &Value) -> bool {
+        if self.lower == Some(&Value::Null) {
+            return CompiledFilter::cmp_vals(&Value::Null, value, op);
+        }
+        let lower = self.lower.and_then(|lower| lower.partial_cmp(value));
+        let upper = self.upper.and_then(|upper| upper.partial_cmp(value));
+        match op {
+            BinOp::Eq => {
+                !lower.is_some_and(CmpOrdering::is_gt) && !upper.is_some_and(CmpOrdering::is_lt)
+            }
+            // cannot determine if other values are present without reading
+            BinOp::NotEq => true,
+            BinOp::Lt => !lower.is_some_and(CmpOrdering::is_ge),
+            BinOp::LtEq => !lower.is_some_and(CmpOrdering::is_gt),
+            BinOp::Gt => !upper.is_some_and(CmpOrdering::is_le),
+            BinOp::GtEq => !upper.is_some_and(CmpOrdering::is_lt),
+        }
+    }
+}
+
+struct ScanConfig<'a> {
+    result: Arc<Mutex<Vec<Column>>>,
     infos: Vec<TablePartInfo>,
-    use_filter_optimization: bool,
+    /// Conditions of `compiled_filter` on primary key columns, which select granules to scan.
+    pk_filter: Option<CompiledFilter>,
     compiled_filter: Option<CompiledFilter>,
     table_col_defs: Vec<ColumnDef>,
     pk_col_defs: Vec<ColumnDef>,
@@ -30,45 +95,437 @@ struct ScanConfig {
     table_def: TableDef,
     limit: Option<u64>,
     offset: u64,
+    /// Granules per scan chunk from `SETTINGS scan_chunk_granules`, computed for each part when `None`.
+    chunk_granules: Option<usize>,
+    /// Query, which scans the table. Scan threads add it to their log lines.
+    query_id: Option<Uuid>,
+    /// Parts, which could not be read, are skipped, see `QuerySettings::skip_unreadable_parts`.
+    skip_unreadable_parts: bool,
+    /// Collected only for `EXPLAIN ANALYZE`.
+    stats: Option<&'a QueryStats>,
+    /// Memory of the query, rows and decompressed granules are counted by scan threads.
+    memory: MemoryTracker,
+}
+
+/// Column files of a part, opened by `CommandRunner::open_part` for reading its granules.
+struct PartReader<'a> {
+    part_info: &'a TablePartInfo,
+    config: &'a ScanConfig<'a>,
+    /// File of each column of `part_info`.
+    file_mmaps: Vec<Mmap>,
+    /// Dictionary of each file, read only for columns of the result.
+    dictionaries: Vec<Option<Vec<Value>>>,
+    /// `MarkInfo` of each granule of each file, read only for columns of the result.
+    file_marks: Vec<Vec<MarkInfo>>,
+    /// Position of each file in `result_col_defs`, `None` for files, which are not read.
+    result_idxs: Vec<Option<usize>>,
+    /// Bytes of matched rows, which are counted by `memory` of the query, see `read_granule`.
+    allocated_bytes: AtomicUsize,
+    /// Counters below are updated only, when `stats` are collected.
+    open_time: Option<Duration>,
+    granules_scanned: AtomicUsize,
+    rows_read: AtomicUsize,
+    read_nanos: AtomicU64,
+}
+
+impl PartReader<'_> {
+    /// Reads values of the granule into `rows` (`Vec` per result column), keeping only rows,
+    /// which match the filter. Columns, which are missing in the part (added after it was
+    /// written), get `NULL` for each row. Decompressed granule and matched rows are counted by
+    /// `memory` of the query.
+    ///
+    /// Returns:
+    ///   * Ok: number of matched rows.
+    ///   * Error: `CouldNotReadData` when granule could not be decompressed or deserialized, or
+    ///     its columns have different row counts, `MemoryLimitExceeded` when query uses too much
+    ///     memory.
+    fn read_granule(
+        &self,
+        granule_idx: usize,
+        granule_buffer: &mut GranuleBuffer,
+        rows: &mut [ColumnData],
+    ) -> Result<usize> {
+        let Some(start) = self.config.stats.map(|_| Instant::now()) else {
+            return self.read_granule_rows(granule_idx, granule_buffer, rows);
+        };
+
+        let matched = self.read_granule_rows(granule_idx, granule_buffer, rows)?;
+        let nanos = u64::try_from(start.elapsed().as_nanos()).unwrap_or(u64::MAX);
+        self.read_nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.granules_scanned.fetch_add(1, Ordering::Relaxed);
+        self.rows_read.fetch_add(matched, Ordering::Relaxed);
+        Ok(matched)
+    }
+
+    fn read_granule_rows(
+        &self,
+        granule_idx: usize,
+        granule_buffer: &mut GranuleBuffer,
+        rows: &mut [ColumnData],
+    ) -> Result<usize> {
+        let config = self.config;
+        let table_def = &config.table_def;
+        let part_name = &self.part_info.name;
+
+        let mut row_count = None;
+        let mut decompressed_bytes = 0;
+        for (file_idx, file_mmap) in self.file_mmaps.iter().enumerate() {
+            let Some(result_idx) = self.result_idxs[file_idx] else {
+                continue;
+            };
+            let col_def = &config.result_col_defs[result_idx];
+
+            let decompression_timer = QueryStats::time(config.stats, Stage::Decompression);
+            let granule_bytes = TablePartInfo::get_granule_bytes_decompressed(
+                file_mmap,
+                &self.file_marks[file_idx][granule_idx],
+                &col_def.constraints.compression_type,
+                self.dictionaries[file_idx].as_deref(),
+                granule_idx,
+            )
+            .map_err(|error| {
+                error.with_read_context(format!(
+                    "table={table_def}, part={part_name}, column={}",
+                    col_def.name
+                ))
+            })?;
+            drop(decompression_timer);
+
+            let granule_len = TablePartInfo::access_granule(&granule_bytes)
+                .map(ArchivedVec::len)
+                .map_err(|error| {
+                    error.with_read_context(format!(
+                        "table={table_def}, part={part_name}, column={}, granule={granule_idx}",
+                        col_def.name
+                    ))
+                })?;
+            match row_count {
+                None => row_count = Some(granule_len),
+                Some(row_count) if row_count != granule_len => {
+                    return Err(Error::CouldNotReadData(format!(
+                        "table={table_def}, part={part_name}, column={}, granule={granule_idx}: Granule has {granule_len} rows, expected {row_count}",
+                        col_def.name
+                    )));
+                }
+                Some(_) => {}
+            }
+            decompressed_bytes += granule_bytes.len();
+            granule_buffer.data_bytes[result_idx] = Some(granule_bytes);
+        }
+        let Some(row_count) = row_count else {
+            return Ok(0);
+        };
+        let _decompressed = config.memory.reserve(decompressed_bytes)?;
+
+        if let Some(compiled_filter) = &config.compiled_filter {
+            let _timer = QueryStats::time(config.stats, Stage::Filter);
+            granule_buffer.fill_mask(
+                compiled_filter,
+                &config.result_col_defs,
+                &config.table_col_defs,
+                row_count,
+            )?;
+        }
+
+        let _timer = QueryStats::time(config.stats, Stage::Deserialization);
+        let mask = &granule_buffer.mask;
+        let matched = if mask.is_empty() {
+            row_count
+        } else {
+            mask.iter().filter(|is_match| **is_match).count()
+        };
+        for (result_idx, column_rows) in rows.iter_mut().enumerate() {
+            let Some(granule_bytes) = &granule_buffer.data_bytes[result_idx] else {
+                column_rows.push_constant(Value::Null, matched);
+                continue;
+            };
+            let values = TablePartInfo::access_granule(granule_bytes)?;
+            for (row_idx, value) in values.iter().enumerate() {
+                if mask.is_empty() || mask[row_idx] {
+                    let value = rkyv::deserialize::<Value, rkyv::rancor::Error>(value).map_err(
+                        |error| {
+                            Error::CouldNotReadData(format!(
+                                "Could not deserialize value in column ({}): {error}",
+                                config.result_col_defs[result_idx].name
+                            ))
+                        },
+                    )?;
+                    column_rows.push(value);
+                }
+            }
+        }
+        // values are estimated by their size in the granule
+        let row_bytes = matched * rows.len() * size_of::<Value>()
+            + decompressed_bytes * matched / row_count.max(1);
+        config.memory.alloc(row_bytes)?;
+        self.allocated_bytes.fetch_add(row_bytes, Ordering::Relaxed);
+
+        for granule_bytes in &mut granule_buffer.data_bytes {
+            *granule_bytes = None;
+        }
+        granule_buffer.mask.clear();
+        Ok(matched)
+    }
+
+    /// Returns average compressed size of granules of `marks_to_scan` in files of columns of
+    /// the result.
+    fn avg_granule_bytes(&self, marks_to_scan: &[usize]) -> u64 {
+        let total_bytes: u64 = marks_to_scan
+            .iter()
+            .flat_map(|&granule_idx| {
+                self.file_marks
+                    .iter()
+                    .filter_map(move |marks| marks.get(granule_idx))
+                    .map(|mark| mark.end.saturating_sub(mark.start))
+            })
+            // marks of a corrupted file may be anywhere in `u64`
+            .fold(0, u64::saturating_add);
+        total_bytes / marks_to_scan.len().max(1) as u64
+    }
+
+    /// Adds scan of the part to `stats`, when they are collected. `granules_to_scan` is the
+    /// number of granules, which were not pruned by primary key.
+    fn add_stats(&self, granules_to_scan: usize) {
+        let (Some(stats), Some(open_time)) = (self.config.stats, self.open_time) else {
+            return;
+        };
+        let granules = self.part_info.marks.len() as u64;
+        stats.add_part(PartStats {
+            name: self.part_info.name.clone(),
+            granules,
+            granules_pruned: granules - granules_to_scan as u64,
+            granules_scanned: self.granules_scanned.load(Ordering::Relaxed) as u64,
+            rows: self.rows_read.load(Ordering::Relaxed) as u64,
+            time: open_time + Duration::from_nanos(self.read_nanos.load(Ordering::Relaxed)),
+        });
+    }
+}
+
+/// Next row of a part, which is merged by `CommandRunner::merge_sorted_parts`.
+struct PartCursor<'a> {
+    reader: PartReader<'a>,
+    marks_to_scan: Vec<usize>,
+    /// Position in `marks_to_scan` of the next granule to read.
+    next_granule: usize,
+    /// Matching rows of the last read granule, per result column.
+    rows: Vec<ColumnData>,
+    /// Position of the next row in `rows`.
+    row: usize,
+}
+
+impl PartCursor<'_> {
+    /// Reads granules, until cursor has a row.
+    ///
+    /// Returns:
+    ///   * Ok: `true` when cursor has a row, `false` when all granules are read.
+    ///   * Error: any error of `PartReader::read_granule`.
+    fn fill(&mut self, granule_buffer: &mut GranuleBuffer) -> Result<bool> {
+        while self.row >= self.rows.first().map_or(0, ColumnData::len) {
+            let Some(&granule_idx) = self.marks_to_scan.get(self.next_granule) else {
+                return Ok(false);
+            };
+            self.next_granule += 1;
+            for column in &mut self.rows {
+                *column = ColumnData::default();
+            }
+            self.row = 0;
+            self.reader
+                .read_granule(granule_idx, granule_buffer, &mut self.rows)?;
+        }
+        Ok(true)
+    }
+
+    /// Returns values of `sort_key` columns of the current row.
+    fn key(&self, sort_key: &[usize]) -> Vec<Value> {
+        sort_key
+            .iter()
+            .map(|&idx| self.rows[idx][self.row].clone())
+            .collect()
+    }
+
+    /// Moves the current row to `merged` and steps to the next one.
+    fn take_row(&mut self, merged: &mut [ColumnData]) {
+        for (column, rows) in merged.iter_mut().zip(&mut self.rows) {
+            column.push(match rows {
+                ColumnData::Dense(values) => std::mem::take(&mut values[self.row]),
+                ColumnData::Constant { value, .. } => value.clone(),
+            });
+        }
+        self.row += 1;
+    }
+}
+
+/// Current row of a `PartCursor` in the heap of `CommandRunner::merge_sorted_parts`.
+/// `BinaryHeap` pops the greatest entry, so entries are ordered in reverse: the smallest key
+/// first, and of equal keys the row of the earlier part.
+struct MergeEntry {
+    key: Vec<Value>,
+    cursor_idx: usize,
+}
+
+impl Ord for MergeEntry {
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        compare_keys(&other.key, &self.key).then(other.cursor_idx.cmp(&self.cursor_idx))
+    }
+}
+
+impl PartialOrd for MergeEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for MergeEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == CmpOrdering::Equal
+    }
+}
+
+impl Eq for MergeEntry {}
+
+/// Compares sort keys column by column, in the same order as engines sort parts: `NULL` last
+/// (see `compare_key_values`).
+fn compare_keys(left: &[Value], right: &[Value]) -> CmpOrdering {
+    for (left, right) in left.iter().zip(right) {
+        let ordering = compare_key_values(left, right);
+        if ordering != CmpOrdering::Equal {
+            return ordering;
+        }
+    }
+    CmpOrdering::Equal
 }
 
 impl CommandRunner {
     /// Executes SELECT operation by scanning all table parts.
     ///
-    /// Reads all table parts, optionally filters and orders data.
+    /// Reads all table parts, optionally filters and orders data. System table and `PIVOT` are
+    /// built in memory instead (see `select_in_memory`).
+    /// Computed columns are evaluated from the read columns after the scan.
+    /// `row_number()` column is appended to the final result.
+    /// Granules of a part are scanned in parallel chunks, see `scan_chunk_size`.
+    /// Stages are timed into `stats`, when it is given (see `CommandRunner::explain_analyze`).
     ///
     /// Returns:
     ///   * Ok: `OutputTable` with success status
     ///   * Error: `TableNotFound`, `CouldNotReadData` or `Internal` on failure
-    pub fn select(
+    #[allow(clippy::too_many_arguments)] // mirrors fields of `PhysicalPlan::Select`
+    pub(crate) fn select(
         table_def: ScanSource,
         columns_to_read: Vec<ColumnDef>,
+        computed: Vec<ComputedColumn>,
         filter: Option<Box<Expr>>,
         order_by: Option<&Vec<Vec<ColumnDef>>>,
+        row_number: Option<RowNumber>,
         limit: Option<u64>,
         offset: u64,
+        settings: &QuerySettings,
+        stats: Option<&QueryStats>,
     ) -> Result<OutputTable> {
         let table_def = match table_def {
             ScanSource::Table(table_def) => table_def,
-            ScanSource::Subquery(_) => {
-                return Err(Error::Internal(
-                    "Subqueries should've been removed during optimization. Cannot proceed"
-                        .to_string(),
-                ));
+            // only `PIVOT` and `FLATTEN` are kept as subqueries, their rows are reshaped in memory
+            ScanSource::Subquery(plan) => {
+                let (source_columns, rows) = match *plan {
+                    LogicalPlan::Pivot {
+                        group_by,
+                        pivot_col,
+                        value_col,
+                        pivot_values,
+                        plan,
+                    } => {
+                        let _timer = QueryStats::time(stats, Stage::Scan);
+                        let pivoted =
+                            Self::pivot(&group_by, &pivot_col, &value_col, &pivot_values, *plan)?;
+                        (
+                            pivot_columns(&group_by, &value_col, &pivot_values),
+                            pivoted.columns,
+                        )
+                    }
+                    LogicalPlan::Flatten { array_col, plan } => {
+                        let _timer = QueryStats::time(stats, Stage::Scan);
+                        let flattened = Self::flatten(&array_col, *plan)?;
+                        let source_columns = flattened
+                            .columns
+                            .iter()
+                            .map(|column| column.column_def.clone())
+                            .collect();
+                        (source_columns, flattened.columns)
+                    }
+                    _ => {
+                        return Err(Error::Internal(
+                            "Subqueries should've been removed during optimization. Cannot proceed"
+                                .to_string(),
+                        ));
+                    }
+                };
+                return Self::select_in_memory(
+                    source_columns,
+                    rows,
+                    columns_to_read,
+                    computed,
+                    filter,
+                    order_by,
+                    row_number,
+                    limit,
+                    offset,
+                    stats,
+                );
             }
         };
+        if let Some(system_table) = SystemTable::from_table_def(&table_def)? {
+            let rows = {
+                let _timer = QueryStats::time(stats, Stage::Scan);
+                system_table.read()?
+            };
+            return Self::select_in_memory(
+                system_table.column_defs(),
+                rows,
+                columns_to_read,
+                computed,
+                filter,
+                order_by,
+                row_number,
+                limit,
+                offset,
+                stats,
+            );
+        }
         let Some(table_config) = TABLE_DATA.get(&table_def) else {
             return Err(Error::TableNotFound);
         };
         let index_granularity = table_config.metadata.settings.index_granularity as usize;
 
-        let avg_rows = Self::estimate_avg_rows(limit, index_granularity);
+        let table_col_defs = &table_config.metadata.schema.columns;
 
         let mut result = Vec::new();
-        Self::add_columns(&mut result, columns_to_read.clone(), avg_rows);
+        Self::add_columns(
+            &mut result,
+            columns_to_read
+                .iter()
+                .filter(|col_def| !computed.iter().any(|col| col.column_def == **col_def))
+                .cloned()
+                .collect(),
+        );
+
+        let mut compiled_exprs = Vec::with_capacity(computed.len());
+        for computed_col in computed {
+            let expr = CompiledExpr::compile(&computed_col.expr, table_col_defs)?;
+
+            let mut columns_to_compute = Vec::new();
+            expr.get_column_defs(&mut columns_to_compute);
+            Self::add_columns(
+                &mut result,
+                columns_to_compute
+                    .into_iter()
+                    .map(|col_idx| table_col_defs[col_idx].clone())
+                    .collect(),
+            );
+
+            compiled_exprs.push((computed_col.column_def, expr));
+        }
 
         let mut compiled_filter = None;
-        let mut use_filter_optimization = false;
+        let mut pk_filter = None;
 
         if let Some(filter) = filter {
             let filter = CompiledFilter::compile(*filter, &table_config.metadata.schema.columns)?;
@@ -76,153 +533,347 @@ impl CommandRunner {
             let mut columns_to_filter = Vec::new();
 
             filter.get_column_defs(&mut columns_to_filter);
+
+            // whole filter is still evaluated on the rows of selected granules
+            let pk_cols: Vec<_> = table_col_defs
+                .iter()
+                .enumerate()
+                .filter(|(_, col_def)| table_config.metadata.schema.primary_key.contains(col_def))
+                .map(|(col_idx, _)| col_idx)
+                .collect();
+            (pk_filter, _) = filter.split_pk_prunable(&pk_cols);
             compiled_filter = Some(filter);
 
             let columns_to_filter: Vec<_> = columns_to_filter
                 .into_iter()
                 .map(|col_idx| table_config.metadata.schema.columns[col_idx].clone())
                 .collect();
-
-            // TODO: allow partial cmp, e.g., part is in PK, part is not.
-            if columns_to_filter
-                .iter()
-                .all(|col_def| table_config.metadata.schema.primary_key.contains(col_def))
-            {
-                use_filter_optimization = true;
-            }
-            Self::add_columns(&mut result, columns_to_filter, avg_rows);
+            Self::add_columns(&mut result, columns_to_filter);
         }
 
         if let Some(order_by) = &order_by {
             Self::add_columns(
                 &mut result,
-                order_by.iter().flatten().cloned().collect(),
-                avg_rows,
+                order_by
+                    .iter()
+                    .flatten()
+                    .filter(|col_def| !compiled_exprs.iter().any(|(col, _)| col == *col_def))
+                    .cloned()
+                    .collect(),
             );
         }
 
-        let result_col_defs: Vec<_> = result.iter().map(|col| col.column_def.clone()).collect();
-        let result = Arc::new(RwLock::new(result));
+        if let Some(row_number) = &row_number {
+            Self::add_columns(&mut result, row_number.order_by.clone());
+        }
 
-        Self::scan_table_parts(ScanConfig {
+        let result_col_defs: Vec<_> = result.iter().map(|col| col.column_def.clone()).collect();
+        let result = Arc::new(Mutex::new(result));
+
+        // merged parts are read at once, so a part can not be skipped without leaving its rows
+        let sort_key = limit
+            .filter(|_| !settings.skip_unreadable_parts)
+            .filter(|_| {
+                !order_by.into_iter().flatten().flatten().any(|col_def| {
+                    compiled_exprs
+                        .iter()
+                        .any(|(computed_col, _)| computed_col == col_def)
+                })
+            })
+            .and_then(|_| {
+                Self::sorted_merge_key(
+                    order_by,
+                    &table_config.metadata.settings.engine,
+                    &table_config.metadata.schema.order_by,
+                    &result_col_defs,
+                )
+            });
+
+        let scan_timer = QueryStats::time(stats, Stage::Scan);
+        let config = ScanConfig {
             result: Arc::clone(&result),
             infos: table_config.infos.clone(),
-            use_filter_optimization,
+            pk_filter,
             compiled_filter,
             table_col_defs: table_config.metadata.schema.columns.clone(),
             pk_col_defs: table_config.metadata.schema.primary_key.clone(),
             result_col_defs,
             index_granularity,
             table_def: table_def.clone(),
-            limit,
+            // rows must be sorted before `LIMIT` is applied, so scan cannot stop early
+            limit: limit.filter(|_| order_by.is_none()),
             offset,
-        })?;
+            chunk_granules: settings.scan_chunk_granules,
+            query_id: logging::current_query_id(),
+            skip_unreadable_parts: settings.skip_unreadable_parts,
+            stats,
+            memory: MemoryTracker::current(),
+        };
+        let (order_by, skipped_parts) = match (&sort_key, limit) {
+            (Some(sort_key), Some(limit)) => {
+                Self::merge_sorted_parts(&config, sort_key, limit.saturating_add(offset))?;
+                drop(config);
+                // rows are already merged in order
+                (None, 0)
+            }
+            _ => (order_by, Self::scan_table_parts(config)?),
+        };
+        drop(scan_timer);
 
         let result = Arc::try_unwrap(result)
             .map_err(|_| {
                 Error::Internal("Some threads are leaked and have not finished.".to_string())
             })?
             .into_inner()
-            .map_err(|error| Error::Internal(format!("Failed to get inner Arc data: {error}")))?;
+            .unwrap_or_else(PoisonError::into_inner);
+
+        let _post_processing_timer = QueryStats::time(stats, Stage::PostProcessing);
+        let result = Self::compute_columns(result, compiled_exprs, table_col_defs)?;
 
-        let result = Self::apply_post_processing(
+        let mut result = Self::apply_post_processing(
             result,
             order_by,
             &table_config.metadata.settings.engine,
             &table_config.metadata.schema.primary_key,
-            &columns_to_read,
             limit,
             offset,
+            stats,
+        )?;
+
+        if let Some(row_number) = &row_number {
+            result = Self::append_row_number(result, row_number)?;
+        }
+        let result = Self::project_columns(result, &columns_to_read, row_number.as_ref())?;
+
+        let output = OutputTable::new(result);
+        if settings.skip_unreadable_parts {
+            return Ok(output.with_skipped_parts(skipped_parts));
+        }
+        Ok(output)
+    }
+
+    /// Executes SELECT over all rows of the table, which is built in memory (system table or
+    /// `PIVOT`), and filtered as a single granule. `result` has data of `table_col_defs` columns.
+    ///
+    /// Returns:
+    ///   * Ok: `OutputTable` with selected rows.
+    ///   * Error: any error from compiling filter or expressions.
+    #[allow(clippy::too_many_arguments)] // mirrors fields of `PhysicalPlan::Select`
+    fn select_in_memory(
+        table_col_defs: Vec<ColumnDef>,
+        mut result: Vec<Column>,
+        columns_to_read: Vec<ColumnDef>,
+        computed: Vec<ComputedColumn>,
+        filter: Option<Box<Expr>>,
+        order_by: Option<&Vec<Vec<ColumnDef>>>,
+        row_number: Option<RowNumber>,
+        limit: Option<u64>,
+        offset: u64,
+        stats: Option<&QueryStats>,
+    ) -> Result<OutputTable> {
+        let _post_processing_timer = QueryStats::time(stats, Stage::PostProcessing);
+        let row_count = result.first().map_or(0, |col| col.data.len());
+
+        if let Some(filter) = filter {
+            let filter = CompiledFilter::compile(*filter, &table_col_defs)?;
+            let granule_data = result
+                .iter_mut()
+                .map(|column| {
+                    rkyv::to_bytes::<rkyv::rancor::Error>(column.data.make_dense())
+                        .map(|bytes| Some(bytes.to_vec()))
+                        .map_err(|error| {
+                            Error::Internal(format!("Could not serialize system table: {error}"))
+                        })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let mask = GranuleBuffer::eval_filter_vectorized(
+                &filter,
+                &granule_data,
+                &table_col_defs,
+                &table_col_defs,
+                row_count,
+            )?;
+
+            for column in &mut result {
+                column.data.retain_mask(&mask);
+            }
+        }
+
+        let compiled_exprs = computed
+            .into_iter()
+            .map(|computed_col| {
+                CompiledExpr::compile(&computed_col.expr, &table_col_defs)
+                    .map(|expr| (computed_col.column_def, expr))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let result = Self::compute_columns(result, compiled_exprs, &table_col_defs)?;
+
+        let mut result = Self::apply_post_processing(
+            result,
+            order_by,
+            &EngineName::MergeTree,
+            &[],
+            limit,
+            offset,
+            stats,
         )?;
 
+        if let Some(row_number) = &row_number {
+            result = Self::append_row_number(result, row_number)?;
+        }
+        let result = Self::project_columns(result, &columns_to_read, row_number.as_ref())?;
+
         Ok(OutputTable::new(result))
     }
 
-    fn load_values<'a>(
-        marks: &'a [Mark],
+    /// Evaluates computed columns row by row and appends them to the result.
+    ///
+    /// Returns:
+    ///   * Ok: result with computed columns.
+    ///   * Error: any error from `CompiledExpr::eval`.
+    fn compute_columns(
+        mut result: Vec<Column>,
+        compiled_exprs: Vec<(ColumnDef, CompiledExpr)>,
+        table_col_defs: &[ColumnDef],
+    ) -> Result<Vec<Column>> {
+        let row_count = result.first().map_or(0, |col| col.data.len());
+
+        for (column_def, expr) in compiled_exprs {
+            let data_idxs: Vec<_> = table_col_defs
+                .iter()
+                .map(|table_col_def| {
+                    result
+                        .iter()
+                        .position(|col| col.column_def == *table_col_def)
+                })
+                .collect();
+
+            let mut data = Vec::with_capacity(row_count);
+            for row_idx in 0..row_count {
+                data.push(expr.eval(&|col_idx| {
+                    Ok(data_idxs[col_idx].map_or(Value::Null, |data_idx| {
+                        result[data_idx].data[row_idx].clone()
+                    }))
+                })?);
+            }
+
+            result.push(Column {
+                column_def,
+                data: data.into(),
+            });
+        }
+
+        Ok(result)
+    }
+
+    /// Returns bounds of values of `col_def` in each granule of the part, see `GranuleBounds`.
+    ///
+    /// Marks hold values of primary key columns of the first row of the granule, in order of
+    /// columns of the part. Rows are sorted by primary key, so the first primary key column of a
+    /// granule lies between its mark and the mark of the next granule. Other columns are sorted
+    /// only within equal values of previous ones, so their bounds are known only when both marks
+    /// have the same previous values.
+    ///
+    /// Returns: bounds per granule, unknown for all granules when `col_def` is not in primary key.
+    fn granule_bounds<'a>(
+        part_info: &'a TablePartInfo,
         pk_col_defs: &[ColumnDef],
         col_def: &ColumnDef,
-    ) -> Vec<&'a Value> {
-        marks
+    ) -> Vec<GranuleBounds<'a>> {
+        let marks = &part_info.marks;
+        // position of each primary key column in marks
+        let positions: Vec<Option<usize>> = pk_col_defs
             .iter()
-            .map(|mark| {
-                let idx = pk_col_defs
+            .map(|pk_col_def| {
+                part_info
+                    .column_defs
                     .iter()
-                    .position(|pk_col_def| pk_col_def == col_def);
-                if let Some(idx) = idx {
-                    &mark.index[idx]
-                } else {
-                    &Value::Null
+                    .filter(|part_col_def| pk_col_defs.contains(part_col_def))
+                    .position(|part_col_def| part_col_def == pk_col_def)
+            })
+            .collect();
+        let key_idx = pk_col_defs
+            .iter()
+            .position(|pk_col_def| pk_col_def == col_def);
+        let Some((key_idx, Some(position))) = key_idx.map(|key_idx| (key_idx, positions[key_idx]))
+        else {
+            return vec![GranuleBounds::default(); marks.len()];
+        };
+        let value = |mark: &'a Mark, position: usize| mark.index.get(position);
+
+        (0..marks.len())
+            .map(|idx| {
+                let lower = value(&marks[idx], position);
+                match marks.get(idx + 1) {
+                    Some(next) => {
+                        let same_prefix = positions[..key_idx].iter().all(|prefix| {
+                            prefix.is_some_and(|prefix| {
+                                let current = value(&marks[idx], prefix);
+                                current.is_some() && current == value(next, prefix)
+                            })
+                        });
+                        if same_prefix {
+                            GranuleBounds {
+                                lower,
+                                upper: value(next, position),
+                            }
+                        } else {
+                            GranuleBounds::default()
+                        }
+                    }
+                    // last granule is not bounded from above
+                    None if key_idx == 0 => GranuleBounds { lower, upper: None },
+                    None => GranuleBounds::default(),
                 }
             })
             .collect()
     }
 
+    /// Selects granules of the part, which may hold rows matching `pk_filter` (all granules
+    /// without it).
+    ///
+    /// Returns: index of each granule to scan, in part order.
+    fn marks_to_scan(
+        part_info: &TablePartInfo,
+        pk_filter: Option<&CompiledFilter>,
+        pk_col_defs: &[ColumnDef],
+        table_col_defs: &[ColumnDef],
+    ) -> Vec<usize> {
+        let Some(pk_filter) = pk_filter else {
+            return (0..part_info.marks.len()).collect();
+        };
+
+        let mut marks_indexes =
+            Self::parse_complex_filter_granule(part_info, pk_filter, pk_col_defs, table_col_defs);
+        marks_indexes.sort_unstable();
+        marks_indexes
+    }
+
     fn parse_complex_filter_granule(
-        marks: &[Mark],
+        part_info: &TablePartInfo,
         filter: &CompiledFilter,
         pk_col_defs: &[ColumnDef],
         table_col_defs: &[ColumnDef],
     ) -> Vec<usize> {
+        let all_granules = || (0..part_info.marks.len()).collect();
         match filter {
             CompiledFilter::Compare { col_idx, op, value } => {
-                let values = Self::load_values(marks, pk_col_defs, &table_col_defs[*col_idx]);
-
-                match *op {
-                    BinOp::Eq => {
-                        let start = values.partition_point(|&v| v < value);
-                        let start = start.saturating_sub(1);
-                        let end = values.partition_point(|&v| v <= value);
-                        (start..end).collect()
-                    }
-                    BinOp::NotEq => (0..marks.len()).collect(), // cannot determine if it's present without reading
-                    BinOp::Lt => {
-                        let end = values.partition_point(|&v| v < value);
-                        (0..end).collect()
-                    }
-                    BinOp::LtEq => {
-                        let end = values.partition_point(|&v| v <= value);
-                        (0..end).collect()
-                    }
-                    BinOp::Gt => {
-                        let start = values.partition_point(|&v| v <= value);
-                        let start = start.saturating_sub(1);
-                        (start..marks.len()).collect()
-                    }
-                    BinOp::GtEq => {
-                        let start = values.partition_point(|&v| v < value);
-                        let start = start.saturating_sub(1);
-                        (start..marks.len()).collect()
-                    }
-                }
-            }
-            CompiledFilter::CompareColumns {
-                left_idx,
-                op,
-                right_idx,
-            } => {
-                let left_values = Self::load_values(marks, pk_col_defs, &table_col_defs[*left_idx]);
-                let right_values =
-                    Self::load_values(marks, pk_col_defs, &table_col_defs[*right_idx]);
-
-                left_values
+                Self::granule_bounds(part_info, pk_col_defs, &table_col_defs[*col_idx])
                     .into_iter()
-                    .zip(right_values)
                     .enumerate()
-                    .filter_map(|(idx, (a, b))| {
-                        if CompiledFilter::cmp_vals(a, b, op) {
-                            Some(idx)
-                        } else {
-                            None
-                        }
-                    })
+                    .filter(|(_, bounds)| bounds.may_match(op, value))
+                    .map(|(idx, _)| idx)
                     .collect()
             }
+            // marks do not bound the difference of columns, and store only whole column values
+            CompiledFilter::CompareColumns { .. }
+            | CompiledFilter::CompareExpr { .. }
+            | CompiledFilter::BoolExpr(_) => all_granules(),
             CompiledFilter::Or(a, b) => {
                 let mut left =
-                    Self::parse_complex_filter_granule(marks, a, pk_col_defs, table_col_defs);
+                    Self::parse_complex_filter_granule(part_info, a, pk_col_defs, table_col_defs);
                 let right =
-                    Self::parse_complex_filter_granule(marks, b, pk_col_defs, table_col_defs);
+                    Self::parse_complex_filter_granule(part_info, b, pk_col_defs, table_col_defs);
 
                 for i in right {
                     if !left.contains(&i) {
@@ -234,274 +885,632 @@ impl CommandRunner {
             }
             CompiledFilter::And(a, b) => {
                 let mut left =
-                    Self::parse_complex_filter_granule(marks, a, pk_col_defs, table_col_defs);
+                    Self::parse_complex_filter_granule(part_info, a, pk_col_defs, table_col_defs);
                 let right =
-                    Self::parse_complex_filter_granule(marks, b, pk_col_defs, table_col_defs);
+                    Self::parse_complex_filter_granule(part_info, b, pk_col_defs, table_col_defs);
 
                 left.retain(|idx| right.contains(idx));
                 left
             }
-            CompiledFilter::Not(inner) => {
-                let result =
-                    Self::parse_complex_filter_granule(marks, inner, pk_col_defs, table_col_defs);
-                (0..marks.len()).filter(|x| !result.contains(x)).collect()
-            }
+            // granules of `inner` may also hold rows, which do not match it, so they can not be
+            // skipped
+            CompiledFilter::Not(_) => all_granules(),
             CompiledFilter::Const(value) => {
                 if *value {
-                    (0..marks.len()).collect()
+                    all_granules()
                 } else {
                     Vec::new()
                 }
             }
+            // granule is skipped only when all its rows are false
             CompiledFilter::Column(col_idx) => {
-                let left_values = Self::load_values(marks, pk_col_defs, &table_col_defs[*col_idx]);
-
-                left_values
-                    .iter()
+                Self::granule_bounds(part_info, pk_col_defs, &table_col_defs[*col_idx])
+                    .into_iter()
                     .enumerate()
-                    .filter_map(|(idx, &value)| {
-                        if let Value::Bool(val) = value
-                            && !*val
-                        {
-                            None
-                        } else {
-                            Some(idx)
-                        }
+                    .filter(|(_, bounds)| {
+                        let all_false = Some(&Value::Bool(false));
+                        !(bounds.lower == all_false && bounds.upper == all_false)
                     })
+                    .map(|(idx, _)| idx)
                     .collect()
             }
         }
     }
 
-    fn estimate_avg_rows(limit: Option<u64>, index_granularity: usize) -> usize {
-        if let Some(limit) = limit {
-            (limit as usize).min(5 * index_granularity)
-        } else {
-            5 * index_granularity
+    /// Checks whether any row of the part may satisfy the filter, using part primary key bounds.
+    /// Conservative: returns `true`, when it cannot be determined without reading the part.
+    fn part_may_match(
+        filter: &CompiledFilter,
+        part_info: &TablePartInfo,
+        pk_col_defs: &[ColumnDef],
+        table_col_defs: &[ColumnDef],
+    ) -> bool {
+        let get_bounds = |col_idx: usize| {
+            let pk_idx = pk_col_defs
+                .iter()
+                .position(|pk_col_def| *pk_col_def == table_col_defs[col_idx])?;
+            match part_info.pk_bounds.get(pk_idx)? {
+                (Value::Null, _) | (_, Value::Null) => None,
+                (min, max) => Some((min, max)),
+            }
+        };
+
+        match filter {
+            CompiledFilter::Compare { col_idx, op, value } => {
+                let Some((min, max)) = get_bounds(*col_idx) else {
+                    return true;
+                };
+                let (Some(min_cmp), Some(max_cmp)) =
+                    (min.partial_cmp(value), max.partial_cmp(value))
+                else {
+                    return true;
+                };
+
+                match op {
+                    BinOp::Eq => min_cmp.is_le() && max_cmp.is_ge(),
+                    BinOp::NotEq => true,
+                    BinOp::Lt => min_cmp.is_lt(),
+                    BinOp::LtEq => min_cmp.is_le(),
+                    BinOp::Gt => max_cmp.is_gt(),
+                    BinOp::GtEq => max_cmp.is_ge(),
+                }
+            }
+            CompiledFilter::Column(col_idx) => {
+                !matches!(get_bounds(*col_idx), Some((_, Value::Bool(false))))
+            }
+            CompiledFilter::Const(value) => *value,
+            CompiledFilter::Or(left, right) => {
+                Self::part_may_match(left, part_info, pk_col_defs, table_col_defs)
+                    || Self::part_may_match(right, part_info, pk_col_defs, table_col_defs)
+            }
+            CompiledFilter::And(left, right) => {
+                Self::part_may_match(left, part_info, pk_col_defs, table_col_defs)
+                    && Self::part_may_match(right, part_info, pk_col_defs, table_col_defs)
+            }
+            CompiledFilter::CompareColumns { .. }
+            | CompiledFilter::CompareExpr { .. }
+            | CompiledFilter::BoolExpr(_)
+            | CompiledFilter::Not(_) => true,
+        }
+    }
+
+    /// Checks whether any row of the part may satisfy the filter, comparing filter values with
+    /// distinct values of dictionary-encoded columns instead of every row.
+    /// Conservative: returns `true`, when it cannot be determined without reading the part.
+    fn dictionaries_may_match(
+        filter: &CompiledFilter,
+        part_info: &TablePartInfo,
+        dictionaries: &[Option<Vec<Value>>],
+        table_col_defs: &[ColumnDef],
+    ) -> bool {
+        match filter {
+            CompiledFilter::Compare { col_idx, op, value } => {
+                let dictionary = part_info
+                    .column_defs
+                    .iter()
+                    .position(|col_def| *col_def == table_col_defs[*col_idx])
+                    .and_then(|idx| dictionaries[idx].as_ref());
+                dictionary.is_none_or(|dictionary| {
+                    dictionary
+                        .iter()
+                        .any(|entry| CompiledFilter::cmp_vals(entry, value, op))
+                })
+            }
+            CompiledFilter::Or(left, right) => {
+                Self::dictionaries_may_match(left, part_info, dictionaries, table_col_defs)
+                    || Self::dictionaries_may_match(right, part_info, dictionaries, table_col_defs)
+            }
+            CompiledFilter::And(left, right) => {
+                Self::dictionaries_may_match(left, part_info, dictionaries, table_col_defs)
+                    && Self::dictionaries_may_match(right, part_info, dictionaries, table_col_defs)
+            }
+            CompiledFilter::Const(value) => *value,
+            CompiledFilter::Column(_)
+            | CompiledFilter::CompareColumns { .. }
+            | CompiledFilter::CompareExpr { .. }
+            | CompiledFilter::BoolExpr(_)
+            | CompiledFilter::Not(_) => true,
         }
     }
 
-    fn add_columns(result: &mut Vec<Column>, columns_defs: Vec<ColumnDef>, avg_rows: usize) {
+    /// Adds empty columns, which are not in `result` yet. Rows of the first scanned part are
+    /// moved into them, so they are not preallocated.
+    fn add_columns(result: &mut Vec<Column>, columns_defs: Vec<ColumnDef>) {
         for column_def in columns_defs {
             if !result.iter().any(|col| col.column_def == column_def) {
                 result.push(Column {
                     column_def,
-                    data: Vec::with_capacity(avg_rows),
+                    data: ColumnData::default(),
                 });
             }
         }
     }
 
-    fn scan_table_parts(config: ScanConfig) -> Result<()> {
-        let ScanConfig {
-            result,
-            infos,
-            use_filter_optimization,
-            compiled_filter,
-            table_col_defs,
-            pk_col_defs,
-            result_col_defs,
-            index_granularity,
-            table_def,
-            limit,
-            offset,
-        } = config;
-
-        let table_col_defs = &table_col_defs;
-        let pk_col_defs = &pk_col_defs;
-        let table_def = &table_def;
-        let should_stop = Arc::new(AtomicBool::new(false));
-        let result_col_defs = Arc::new(result_col_defs);
-        let total_len = Arc::new(AtomicUsize::new(0));
-
-        for part_info in &infos {
-            if should_stop.load(Ordering::Relaxed) {
-                break;
+    /// Opens column files of the part for reading its granules and reads marks of columns of the
+    /// result (see `TablePartInfo::read_column_marks`), unless filter rules out all
+    /// rows of the part by its min/max (see `part_may_match`) or dictionaries (see
+    /// `dictionaries_may_match`). Pruned part is added to `stats`.
+    ///
+    /// Returns:
+    ///   * Ok: `PartReader` of the part, `None` when it is pruned.
+    ///   * Error: `CouldNotReadData` when files could not be opened or are corrupted.
+    fn open_part<'a>(
+        part_info: &'a TablePartInfo,
+        config: &'a ScanConfig<'a>,
+    ) -> Result<Option<PartReader<'a>>> {
+        let part_start = config.stats.map(|_| Instant::now());
+        let add_pruned_part = || {
+            if let (Some(stats), Some(part_start)) = (config.stats, part_start) {
+                stats.add_part(PartStats {
+                    name: part_info.name.clone(),
+                    granules: part_info.marks.len() as u64,
+                    granules_pruned: part_info.marks.len() as u64,
+                    granules_scanned: 0,
+                    rows: 0,
+                    time: part_start.elapsed(),
+                });
             }
+        };
 
-            let mut file_mmaps = Vec::with_capacity(part_info.column_defs.len());
+        if let Some(compiled_filter) = &config.compiled_filter
+            && !Self::part_may_match(
+                compiled_filter,
+                part_info,
+                &config.pk_col_defs,
+                &config.table_col_defs,
+            )
+        {
+            add_pruned_part();
+            return Ok(None);
+        }
 
-            for col_def in &part_info.column_defs {
-                let mmap = Column::open_as_mmap(&part_info.get_column_path(table_def, col_def))?;
-                Column::validate_mmap(&mmap, &col_def.name)?;
+        let table_def = &config.table_def;
+        let mut file_mmaps = Vec::with_capacity(part_info.column_defs.len());
+        let mut dictionaries = Vec::with_capacity(part_info.column_defs.len());
+        let mut result_idxs = Vec::with_capacity(part_info.column_defs.len());
+        let mut file_marks = Vec::with_capacity(part_info.column_defs.len());
+
+        for (file_idx, col_def) in part_info.column_defs.iter().enumerate() {
+            let read_context = || {
+                format!(
+                    "table={table_def}, part={}, column={}",
+                    part_info.name, col_def.name
+                )
+            };
+            let mmap = Column::open_as_mmap(&part_info.get_column_path(table_def, col_def))
+                .map_err(|error| error.with_read_context(read_context()))?;
+            Column::validate_mmap(&mmap, &col_def.name)
+                .map_err(|error| error.with_read_context(read_context()))?;
+            let result_idx = config
+                .result_col_defs
+                .iter()
+                .position(|result_col_def| result_col_def == col_def);
+            let (dictionary, marks) = if result_idx.is_some() {
+                (
+                    TablePartInfo::read_dictionary(&mmap, &col_def.constraints.compression_type)
+                        .map_err(|error| error.with_read_context(read_context()))?,
+                    part_info
+                        .read_column_marks(table_def, file_idx)
+                        .map_err(|error| error.with_read_context(read_context()))?,
+                )
+            } else {
+                (None, Vec::new())
+            };
+
+            file_mmaps.push(mmap);
+            dictionaries.push(dictionary);
+            file_marks.push(marks);
+            result_idxs.push(result_idx);
+        }
 
-                file_mmaps.push(mmap);
-            }
+        if let Some(compiled_filter) = &config.compiled_filter
+            && !Self::dictionaries_may_match(
+                compiled_filter,
+                part_info,
+                &dictionaries,
+                &config.table_col_defs,
+            )
+        {
+            add_pruned_part();
+            return Ok(None);
+        }
 
-            let file_mmaps = Arc::new(file_mmaps);
+        Ok(Some(PartReader {
+            part_info,
+            config,
+            file_mmaps,
+            dictionaries,
+            file_marks,
+            result_idxs,
+            open_time: part_start.map(|part_start| part_start.elapsed()),
+            allocated_bytes: AtomicUsize::new(0),
+            granules_scanned: AtomicUsize::new(0),
+            rows_read: AtomicUsize::new(0),
+            read_nanos: AtomicU64::new(0),
+        }))
+    }
 
-            let marks_to_scan: Vec<_> =
-                if use_filter_optimization && let Some(compiled_filter) = &compiled_filter {
-                    let marks_indexes = Self::parse_complex_filter_granule(
-                        &part_info.marks,
-                        compiled_filter,
-                        pk_col_defs,
-                        table_col_defs,
-                    );
-                    marks_indexes
-                        .into_iter()
-                        .map(|mark_idx| &part_info.marks[mark_idx].info)
-                        .collect()
-                } else {
-                    part_info.marks.iter().map(|mark| &mark.info).collect()
-                };
+    /// Scans parts one by one, granules of each part in parallel chunks.
+    ///
+    /// Returns:
+    ///   * Ok: number of parts, which were skipped, because they could not be read (only with
+    ///     `skip_unreadable_parts`). Rows are appended to `result` of `config`.
+    ///   * Error: any error of `scan_part`.
+    fn scan_table_parts(config: ScanConfig<'_>) -> Result<u64> {
+        let should_stop = AtomicBool::new(false);
+        let total_len = AtomicUsize::new(0);
+        let mut skipped_parts = 0;
+
+        for part_info in &config.infos {
             if should_stop.load(Ordering::Relaxed) {
                 break;
             }
+            // parts are scanned one at a time, so rows of the failed part are counted only here
+            let len_before = total_len.load(Ordering::Relaxed);
+            match Self::scan_part(&config, part_info, &should_stop, &total_len) {
+                Ok(()) => {}
+                Err(error @ Error::CouldNotReadData(_)) if config.skip_unreadable_parts => {
+                    warn!(
+                        table:% = config.table_def, part = part_info.name.as_str();
+                        "Skipped unreadable part {} of table {}: {error}",
+                        part_info.name,
+                        config.table_def
+                    );
+                    skipped_parts += 1;
+                    total_len.store(len_before, Ordering::Relaxed);
+                    should_stop.store(false, Ordering::Relaxed);
+                }
+                Err(error) => return Err(error),
+            }
+        }
 
-            marks_to_scan
-                .par_chunks(10)
-                .try_for_each(|chunk_granule_marks| {
-                    LOCAL_BUFFER.with(|buffer| {
-                        let mut buffer = buffer.borrow_mut();
-                        *buffer = vec![Vec::with_capacity(index_granularity); result_col_defs.len()];
-                    });
+        Ok(skipped_parts)
+    }
 
-                    if should_stop.load(Ordering::Relaxed) {
-                        return Ok(());
-                    }
+    /// Scans granules of the part in parallel chunks and appends its matching rows to `result`
+    /// of `config` at once, so rows of a part, which fails, are not added, and memory counted
+    /// for them is released. Sets `should_stop`, when `total_len` rows reach `limit` and
+    /// `offset`.
+    ///
+    /// Returns:
+    ///   * Ok: when part is scanned or pruned.
+    ///   * Error: any error of `open_part` or `PartReader::read_granule`.
+    fn scan_part(
+        config: &ScanConfig<'_>,
+        part_info: &TablePartInfo,
+        should_stop: &AtomicBool,
+        total_len: &AtomicUsize,
+    ) -> Result<()> {
+        let Some(reader) = Self::open_part(part_info, config)? else {
+            return Ok(());
+        };
+        Self::scan_part_rows(config, part_info, &reader, should_stop, total_len).inspect_err(|_| {
+            config
+                .memory
+                .free(reader.allocated_bytes.load(Ordering::Relaxed));
+        })
+    }
+
+    /// Scans granules of the opened part, see `scan_part`.
+    fn scan_part_rows(
+        config: &ScanConfig<'_>,
+        part_info: &TablePartInfo,
+        reader: &PartReader<'_>,
+        should_stop: &AtomicBool,
+        total_len: &AtomicUsize,
+    ) -> Result<()> {
+        let ScanConfig {
+            result,
+            pk_filter,
+            table_col_defs,
+            pk_col_defs,
+            result_col_defs,
+            index_granularity,
+            table_def,
+            limit,
+            offset,
+            chunk_granules,
+            query_id,
+            ..
+        } = config;
 
-                    let mut granule_buffer = GranuleBuffer {
-                        data_bytes: vec![None; result_col_defs.len()],
-                        mask: Vec::with_capacity(index_granularity),
-                    };
+        let marks_to_scan =
+            Self::marks_to_scan(part_info, pk_filter.as_ref(), pk_col_defs, table_col_defs);
+        if should_stop.load(Ordering::Relaxed) {
+            return Ok(());
+        }
 
-                    for &granule_marks in chunk_granule_marks {
-                        if should_stop.load(Ordering::Relaxed) {
-                            return Ok(());
-                        }
+        let chunk_size = chunk_granules.unwrap_or_else(|| {
+            Self::scan_chunk_size(
+                marks_to_scan.len(),
+                rayon::current_num_threads(),
+                reader.avg_granule_bytes(&marks_to_scan),
+            )
+        });
+
+        // rows of chunks are merged in order of granules, and appended to `result` at once;
+        // pool threads scan against the instance of the query
+        let instance = Instance::current();
+        let part_rows = marks_to_scan
+            .par_chunks(chunk_size)
+            .map(|chunk_granules| {
+                instance.enter(|| {
+                    Self::scan_chunk(
+                        result_col_defs.len(),
+                        *index_granularity,
+                        table_def,
+                        &part_info.name,
+                        *query_id,
+                        |current_granule| {
+                            let mut granule_buffer = GranuleBuffer {
+                                data_bytes: vec![None; result_col_defs.len()],
+                                mask: Vec::with_capacity(*index_granularity),
+                            };
 
-                        let mut row_count = None;
-
-                        for (file_and_col_idx, file_mmap) in file_mmaps.iter().enumerate()
-                        {
-
-                            let result_idx = result_col_defs.iter().position(|col_def| {
-                                *col_def == part_info.column_defs[file_and_col_idx]
-                            });
-                            if let Some(result_idx) = result_idx {
-                                let granule_bytes = TablePartInfo::get_granule_bytes_decompressed(
-                                    file_mmap,
-                                    &granule_marks[file_and_col_idx],
-                                    &result_col_defs[result_idx].constraints.compression_type,
-                                )?;
-                                if row_count.is_none() {
-                                    row_count = Some(unsafe {
-                                        rkyv::access_unchecked::<ArchivedVec<ArchivedValue>>(
-                                            &granule_bytes,
+                            for &granule_idx in chunk_granules {
+                                current_granule.set(Some(granule_idx));
+                                if should_stop.load(Ordering::Relaxed) {
+                                    return Ok(());
+                                }
+                                let matched = LOCAL_BUFFER
+                                    .with(|buffer| {
+                                        reader.read_granule(
+                                            granule_idx,
+                                            &mut granule_buffer,
+                                            &mut buffer.borrow_mut(),
                                         )
-                                        .len()
-                                    });
+                                    })
+                                    // other chunks stop, e.g., once memory limit is exceeded
+                                    .inspect_err(|_| should_stop.store(true, Ordering::Relaxed))?;
+                                total_len.fetch_add(matched, Ordering::Relaxed);
+
+                                if let Some(limit) = limit
+                                    && total_len.load(Ordering::Relaxed) as u64
+                                        >= limit.saturating_add(*offset)
+                                {
+                                    should_stop.store(true, Ordering::Relaxed);
+                                    return Ok(());
                                 }
-                                granule_buffer.data_bytes[result_idx] = Some(granule_bytes);
                             }
-                        }
+                            Ok(())
+                        },
+                    )
+                })
+            })
+            .try_reduce(Vec::new, |mut rows, chunk_rows| {
+                if rows.is_empty() {
+                    return Ok(chunk_rows);
+                }
+                for (column, chunk_column) in rows.iter_mut().zip(chunk_rows) {
+                    column.append(chunk_column);
+                }
+                Ok(rows)
+            })?;
+
+        reader.add_stats(marks_to_scan.len());
+
+        // every row of a part, which is read whole without filter, is in the result
+        let fully_scanned = config.compiled_filter.is_none()
+            && marks_to_scan.len() == part_info.marks.len()
+            && reader.result_idxs.iter().any(Option::is_some)
+            && !should_stop.load(Ordering::Relaxed);
+        let rows_read = part_rows.first().map_or(0, ColumnData::len) as u64;
+        if fully_scanned && rows_read != part_info.row_count {
+            return Err(Error::Internal(format!(
+                "table={table_def}, part={}: Read {rows_read} rows, but part has row_count {}",
+                part_info.name, part_info.row_count
+            )));
+        }
 
-                        if let Some(row_count) = row_count {
-                            if let Some(compiled_filter) = &compiled_filter {
-                                granule_buffer.fill_mask(
-                                    compiled_filter,
-                                    &result_col_defs,
-                                    table_col_defs,
-                                    row_count,
-                                )?;
-                            }
+        let mut result = result.lock().unwrap_or_else(PoisonError::into_inner);
+        for (column, rows) in result.iter_mut().zip(part_rows) {
+            column.data.append(rows);
+        }
 
-                            let mut archived_values = Vec::with_capacity(granule_buffer.data_bytes.len());
+        Ok(())
+    }
 
-                            for col in &granule_buffer.data_bytes {
-                                if let Some(col_bytes) = col {
-                                    let values = unsafe {
-                                        rkyv::access_unchecked::<ArchivedVec<ArchivedValue>>(
-                                            col_bytes,
-                                        )
-                                    };
-                                    archived_values.push(Some(values));
-                                } else {
-                                    archived_values.push(None);
-                                }
-                            }
-                            let allowed_count = granule_buffer.mask.iter().filter(|x| **x).count();
-                            if should_stop.load(Ordering::Relaxed) {
-                                return Ok(());
-                            }
+    /// Returns positions in `result_col_defs` of the combined `ORDER BY` key (see
+    /// `apply_post_processing`), when it is a prefix of `ORDER BY` of the table, so that rows of
+    /// every part are already sorted by it and parts can be merged (see `merge_sorted_parts`).
+    /// `ReplacingMergeTree` deduplicates rows while sorting, so it is always sorted in memory.
+    fn sorted_merge_key(
+        order_by: Option<&Vec<Vec<ColumnDef>>>,
+        engine_name: &EngineName,
+        table_order_by: &[ColumnDef],
+        result_col_defs: &[ColumnDef],
+    ) -> Option<Vec<usize>> {
+        if *engine_name != EngineName::MergeTree {
+            return None;
+        }
+        let sort_by: Vec<&ColumnDef> = order_by?.iter().rev().flatten().collect();
+        if sort_by.is_empty()
+            || sort_by.len() > table_order_by.len()
+            || sort_by
+                .iter()
+                .zip(table_order_by)
+                .any(|(col_def, table_col_def)| *col_def != table_col_def)
+        {
+            return None;
+        }
 
-                            for (idx, col_values) in archived_values.iter().enumerate() {
-                                let col_values = if let Some(col_values_) = col_values {
-                                    let mut res = Vec::with_capacity(col_values_.len());
-                                    for (val_idx, col_value) in col_values_.iter().enumerate() {
-                                        if granule_buffer.mask.is_empty()
-                                            || granule_buffer.mask[val_idx]
-                                        {
-                                            let col_values =
-                                                rkyv::deserialize::<Value, rkyv::rancor::Error>(
-                                                    col_value,
-                                                )
-                                                .map_err(|error| {
-                                                    Error::CouldNotReadData(format!("Could not deserialize value in column ({}): {error}", result_col_defs[idx].name))
-                                                })?;
-                                            res.push(col_values);
-                                        }
-                                    }
-
-                                    res
-                                } else {
-                                    vec![Value::Null; allowed_count]
-                                };
-                                LOCAL_BUFFER.with(|buffer| {
-                                    let mut buffer = buffer.borrow_mut();
-                                    buffer[idx].extend(col_values);
-                                });
-                            }
+        sort_by
+            .into_iter()
+            .map(|col_def| {
+                result_col_defs
+                    .iter()
+                    .position(|result_col_def| result_col_def == col_def)
+            })
+            .collect()
+    }
+
+    /// Reads first `row_limit` rows in order of `sort_key` columns (see `sorted_merge_key`).
+    /// Rows of each part are already sorted, so parts are read granule by granule in order, and
+    /// merged with a binary heap, which holds the next row of each part. Only granules, which
+    /// hold the first rows of each part, are read, instead of scanning and sorting whole table.
+    /// Rows with equal keys are taken from parts in their order.
+    ///
+    /// Returns:
+    ///   * Ok: rows are appended to `result` of `config`.
+    ///   * Error: `CouldNotReadData` when part could not be read.
+    fn merge_sorted_parts(
+        config: &ScanConfig<'_>,
+        sort_key: &[usize],
+        row_limit: u64,
+    ) -> Result<()> {
+        let mut cursors = Vec::new();
+        for part_info in &config.infos {
+            if let Some(reader) = Self::open_part(part_info, config)? {
+                let marks_to_scan = Self::marks_to_scan(
+                    part_info,
+                    config.pk_filter.as_ref(),
+                    &config.pk_col_defs,
+                    &config.table_col_defs,
+                );
+                cursors.push(PartCursor {
+                    reader,
+                    marks_to_scan,
+                    next_granule: 0,
+                    rows: vec![ColumnData::default(); config.result_col_defs.len()],
+                    row: 0,
+                });
+            }
+        }
 
-                            total_len.fetch_add(allowed_count, Ordering::Relaxed);
+        let mut granule_buffer = GranuleBuffer {
+            data_bytes: vec![None; config.result_col_defs.len()],
+            mask: Vec::with_capacity(config.index_granularity),
+        };
+        let mut merged = vec![ColumnData::default(); config.result_col_defs.len()];
+        let mut heap = BinaryHeap::with_capacity(cursors.len());
+        if row_limit > 0 {
+            for (cursor_idx, cursor) in cursors.iter_mut().enumerate() {
+                if cursor.fill(&mut granule_buffer)? {
+                    heap.push(MergeEntry {
+                        key: cursor.key(sort_key),
+                        cursor_idx,
+                    });
+                }
+            }
+        }
 
-                            if let Some(limit) = limit && total_len.load(Ordering::Relaxed) as u64 >= limit.saturating_add(offset) {
-                                    should_stop.store(true, Ordering::Relaxed);
-                                    return Ok(());
-                            }
+        let mut row_count = 0;
+        while row_count < row_limit
+            && let Some(MergeEntry { cursor_idx, .. }) = heap.pop()
+        {
+            let cursor = &mut cursors[cursor_idx];
+            cursor.take_row(&mut merged);
+            row_count += 1;
+            if row_count < row_limit && cursor.fill(&mut granule_buffer)? {
+                heap.push(MergeEntry {
+                    key: cursor.key(sort_key),
+                    cursor_idx,
+                });
+            }
+        }
 
-                            for archived_vec in &mut granule_buffer.data_bytes {
-                                *archived_vec = None;
-                            }
-                            granule_buffer.mask.clear();
-                        }
-                    }
-                    let mut guard = result.write().map_err(|error| Error::Internal(format!("RwLock poisoning while reading: {error}")))?;
-                    for (idx, col) in LOCAL_BUFFER.take().into_iter().enumerate() {
-                        guard[idx].data.extend(col);
-                    }
+        for cursor in &cursors {
+            cursor.reader.add_stats(cursor.marks_to_scan.len());
+        }
+
+        let mut result = config.result.lock().unwrap_or_else(PoisonError::into_inner);
+        for (column, rows) in result.iter_mut().zip(merged) {
+            column.data.append(rows);
+        }
+        Ok(())
+    }
 
-                    Ok(())
-                })?;
+    /// Returns number of granules in a scan chunk: `granule_count` granules are split into
+    /// `SCAN_CHUNKS_PER_THREAD` chunks per thread, so threads, which finish early, take over
+    /// remaining chunks. Compressed size of a chunk is kept within `SCAN_CHUNK_BYTES`, as rows
+    /// of a chunk are buffered until it is scanned. Chunk has at least one granule.
+    fn scan_chunk_size(granule_count: usize, threads: usize, avg_granule_bytes: u64) -> usize {
+        let by_threads = granule_count / (threads.max(1) * SCAN_CHUNKS_PER_THREAD);
+        let by_bytes =
+            usize::try_from(SCAN_CHUNK_BYTES / avg_granule_bytes.max(1)).unwrap_or(usize::MAX);
+        by_threads.min(by_bytes).max(1)
+    }
+
+    /// Scans chunk of granules with `scan`, which collects rows into `LOCAL_BUFFER` and
+    /// stores index of the granule being read into `current_granule`. `LOCAL_BUFFER` is cleared
+    /// on every path. Log lines of the chunk get `query_id` field.
+    ///
+    /// Returns:
+    ///   * Ok: rows of the chunk, values per column.
+    ///   * Error: error of `scan`, or `Internal` with panic message, part and granule, when
+    ///     `scan` panics.
+    fn scan_chunk(
+        column_count: usize,
+        index_granularity: usize,
+        table_def: &TableDef,
+        part_name: &str,
+        query_id: Option<Uuid>,
+        scan: impl FnOnce(&Cell<Option<usize>>) -> Result<()>,
+    ) -> Result<Vec<ColumnData>> {
+        let _guard = LocalBufferGuard;
+        let _query_id_guard = query_id.map(QueryIdGuard::enter);
+        LOCAL_BUFFER.with(|buffer| {
+            *buffer.borrow_mut() = vec![Vec::with_capacity(index_granularity).into(); column_count];
+        });
+
+        let current_granule = Cell::new(None);
+        match panic::catch_unwind(AssertUnwindSafe(|| scan(&current_granule))) {
+            Ok(scanned) => scanned?,
+            Err(payload) => {
+                let message = payload
+                    .downcast_ref::<&str>()
+                    .copied()
+                    .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                    .unwrap_or("unknown panic");
+                let granule = current_granule
+                    .get()
+                    .map_or("none".to_string(), |granule_idx| granule_idx.to_string());
+                return Err(Error::Internal(format!(
+                    "Scan panicked (table={table_def}, part={part_name}, granule={granule}): {message}"
+                )));
+            }
         }
 
-        Ok(())
+        Ok(LOCAL_BUFFER.take())
     }
 
+    /// Sorts rows by `order_by`, then applies `OFFSET` and `LIMIT`. Rows, which are copied
+    /// while sorting, are counted by the memory tracker of the query.
+    ///
+    /// Returns:
+    ///   * Ok: processed rows.
+    ///   * Error: any error of `Engine::order_columns`, or `MemoryLimitExceeded`.
     fn apply_post_processing(
         mut result: Vec<Column>,
         order_by: Option<&Vec<Vec<ColumnDef>>>,
         engine_name: &EngineName,
         pk_col_defs: &[ColumnDef],
-        columns_to_read: &[ColumnDef],
         limit: Option<u64>,
         offset: u64,
+        stats: Option<&QueryStats>,
     ) -> Result<Vec<Column>> {
-        if let Some(sort_by) = &order_by {
-            let engine = engine_name.get_engine(EngineConfig::default());
-            for sort_by_ in *sort_by {
-                result = engine.order_columns(result, sort_by_, pk_col_defs)?;
+        if let Some(order_by) = order_by {
+            // sorting by each key in turn would keep only the last one, so keys are combined
+            // into a single lexicographic key: outer `ORDER BY` first, inner ones break ties
+            let sort_by: Vec<ColumnDef> = order_by.iter().rev().flatten().cloned().collect();
+            if !sort_by.is_empty() {
+                let _timer = QueryStats::time(stats, Stage::Sort);
+                // engines sort rows, which are moved into a `Vec` per row
+                let row_count = result.first().map_or(0, |col| col.data.len());
+                let memory = MemoryTracker::current();
+                let _sorted_rows = memory.reserve(
+                    row_count * (result.len() * size_of::<Value>() + size_of::<Vec<Value>>()),
+                )?;
+                let engine = engine_name.get_engine(EngineConfig::default());
+                result = engine.order_columns(result, &sort_by, pk_col_defs)?;
             }
         }
 
-        result.retain(|col| columns_to_read.contains(&col.column_def));
-
         let row_count = result.first().map_or(0, |col| col.data.len());
 
         let offset = offset.min(row_count as u64) as usize;
         for column in &mut result {
-            column.data.drain(0..offset);
+            column.data.remove_first(offset);
         }
 
         if let Some(limit) = limit {
@@ -512,10 +1521,91 @@ impl CommandRunner {
         }
         Ok(result)
     }
+
+    /// Keeps only projected columns, in the order of the projection, dropping columns
+    /// which were read only for filtering or sorting. Codes of `Enum8`/`Enum16` values are
+    /// replaced with their labels.
+    ///
+    /// Returns:
+    ///   * Ok: columns in the order of `columns_to_read`, with `row_number()` column at its position.
+    ///   * Error: `Internal` when projected column was not read.
+    fn project_columns(
+        mut result: Vec<Column>,
+        columns_to_read: &[ColumnDef],
+        row_number: Option<&RowNumber>,
+    ) -> Result<Vec<Column>> {
+        let mut projected = Vec::with_capacity(columns_to_read.len() + 1);
+        for col_def in columns_to_read {
+            let Some(idx) = result.iter().position(|col| col.column_def == *col_def) else {
+                return Err(Error::Internal(format!(
+                    "Projected column {} was not read",
+                    col_def.name
+                )));
+            };
+            projected.push(result.swap_remove(idx));
+        }
+
+        if let Some(row_number) = row_number {
+            let Some(idx) = result
+                .iter()
+                .position(|col| col.column_def.name == row_number.alias)
+            else {
+                return Err(Error::Internal(
+                    "row_number() column was not computed".to_string(),
+                ));
+            };
+            projected.insert(
+                row_number.position.min(projected.len()),
+                result.swap_remove(idx),
+            );
+        }
+
+        for column in &mut projected {
+            let field_type = &column.column_def.field_type;
+            if field_type.has_output_conversion() {
+                column.data = match std::mem::take(&mut column.data) {
+                    ColumnData::Constant { value, len } => {
+                        ColumnData::constant(field_type.to_output_value(value), len)
+                    }
+                    ColumnData::Dense(values) => values
+                        .into_iter()
+                        .map(|value| field_type.to_output_value(value))
+                        .collect(),
+                };
+            }
+        }
+
+        Ok(projected)
+    }
+
+    /// Sorts rows by `OVER (ORDER BY ...)` columns, if any, and appends `UInt64` column
+    /// with row numbers starting from 1.
+    fn append_row_number(mut result: Vec<Column>, row_number: &RowNumber) -> Result<Vec<Column>> {
+        if !row_number.order_by.is_empty() {
+            // plain sort, as `ReplacingMergeTree` would also deduplicate rows
+            let engine = EngineName::MergeTree.get_engine(EngineConfig::default());
+            result = engine.order_columns(result, &row_number.order_by, &[])?;
+        }
+
+        let row_count = result.first().map_or(0, |col| col.data.len()) as u64;
+        result.push(Column {
+            column_def: ColumnDef {
+                name: row_number.alias.clone(),
+                field_type: ValueType::UInt64,
+                constraints: Constraints {
+                    compression_type: ValueType::UInt64.get_optimal_compression(),
+                    ..Constraints::default()
+                },
+            },
+            data: (1..=row_count).map(Value::UInt64).collect(),
+        });
+
+        Ok(result)
+    }
 }
 
 #[derive(Debug)]
-struct GranuleBuffer {
+pub(crate) struct GranuleBuffer {
     data_bytes: Vec<Option<Vec<u8>>>,
     mask: Vec<bool>,
 }
@@ -540,7 +1630,310 @@ impl GranuleBuffer {
         Ok(())
     }
 
-    fn eval_filter_vectorized(
+    /// Evaluates filter for each row of the granule. `granule_data` holds archived values of
+    /// `granule_col_defs` columns, `None` for columns without data.
+    ///
+    /// Right side of `AND` is evaluated only over rows, which passed the left side, and right side
+    /// of `OR` only over rows, which the left side rejected (see `eval_filter_selected`). Mask is
+    /// the same as of `eval_filter_full`, but errors of expressions are not raised for rows,
+    /// which are skipped this way.
+    pub(crate) fn eval_filter_vectorized(
+        filter: &CompiledFilter,
+        granule_data: &[Option<Vec<u8>>],
+        granule_col_defs: &[ColumnDef],
+        table_col_defs: &[ColumnDef],
+        row_count: usize,
+    ) -> Result<Vec<bool>> {
+        Self::eval_filter_selected(
+            filter,
+            granule_data,
+            granule_col_defs,
+            table_col_defs,
+            row_count,
+            None,
+        )
+    }
+
+    /// Evaluates filter for rows of `selection` (all rows, when `None`). Rows, which are not
+    /// selected, are `false` in the mask.
+    ///
+    /// Selection is passed down only when it keeps at most half of the rows. Otherwise, random
+    /// access costs more than it saves, and the other side of `AND`/`OR` is evaluated over all
+    /// rows by `eval_filter_full`.
+    fn eval_filter_selected(
+        filter: &CompiledFilter,
+        granule_data: &[Option<Vec<u8>>],
+        granule_col_defs: &[ColumnDef],
+        table_col_defs: &[ColumnDef],
+        row_count: usize,
+        selection: Option<&[u32]>,
+    ) -> Result<Vec<bool>> {
+        match filter {
+            CompiledFilter::And(left, right) => {
+                let left_mask = Self::eval_filter_selected(
+                    left,
+                    granule_data,
+                    granule_col_defs,
+                    table_col_defs,
+                    row_count,
+                    selection,
+                )?;
+                // rows outside of `selection` are already rejected by the left side
+                let passed = Self::selected_rows(&left_mask, None, true);
+                if passed.len() * 2 <= row_count {
+                    return Self::eval_filter_selected(
+                        right,
+                        granule_data,
+                        granule_col_defs,
+                        table_col_defs,
+                        row_count,
+                        Some(&passed),
+                    );
+                }
+
+                let right_mask = Self::eval_filter_full(
+                    right,
+                    granule_data,
+                    granule_col_defs,
+                    table_col_defs,
+                    row_count,
+                )?;
+                Ok(left_mask
+                    .into_iter()
+                    .zip(right_mask)
+                    .map(|(l, r)| l && r)
+                    .collect())
+            }
+            CompiledFilter::Or(left, right) => {
+                let mut mask = Self::eval_filter_selected(
+                    left,
+                    granule_data,
+                    granule_col_defs,
+                    table_col_defs,
+                    row_count,
+                    selection,
+                )?;
+                let rejected = Self::selected_rows(&mask, selection, false);
+                // rows outside of `selection` must stay rejected, so the right side is evaluated
+                // over all rows only without one
+                if selection.is_none() && rejected.len() * 2 > row_count {
+                    let right_mask = Self::eval_filter_full(
+                        right,
+                        granule_data,
+                        granule_col_defs,
+                        table_col_defs,
+                        row_count,
+                    )?;
+                    return Ok(mask
+                        .into_iter()
+                        .zip(right_mask)
+                        .map(|(l, r)| l || r)
+                        .collect());
+                }
+
+                let right_mask = Self::eval_filter_selected(
+                    right,
+                    granule_data,
+                    granule_col_defs,
+                    table_col_defs,
+                    row_count,
+                    Some(&rejected),
+                )?;
+                for row_idx in rejected {
+                    mask[row_idx as usize] = right_mask[row_idx as usize];
+                }
+                Ok(mask)
+            }
+            CompiledFilter::Not(inner) => {
+                let mask = Self::eval_filter_selected(
+                    inner,
+                    granule_data,
+                    granule_col_defs,
+                    table_col_defs,
+                    row_count,
+                    selection,
+                )?;
+                match selection {
+                    None => Ok(mask.into_iter().map(|b| !b).collect()),
+                    Some(selection) => Ok(Self::scatter(
+                        row_count,
+                        selection,
+                        selection.iter().map(|row_idx| !mask[*row_idx as usize]),
+                    )),
+                }
+            }
+            filter => match selection {
+                None => Self::eval_filter_full(
+                    filter,
+                    granule_data,
+                    granule_col_defs,
+                    table_col_defs,
+                    row_count,
+                ),
+                Some(selection) => Self::eval_leaf_selected(
+                    filter,
+                    granule_data,
+                    granule_col_defs,
+                    table_col_defs,
+                    row_count,
+                    selection,
+                ),
+            },
+        }
+    }
+
+    /// Evaluates filter without `AND`, `OR` and `NOT` over rows of `selection` only. Mask has
+    /// `row_count` rows, other rows are `false`.
+    fn eval_leaf_selected(
+        filter: &CompiledFilter,
+        granule_data: &[Option<Vec<u8>>],
+        granule_col_defs: &[ColumnDef],
+        table_col_defs: &[ColumnDef],
+        row_count: usize,
+        selection: &[u32],
+    ) -> Result<Vec<bool>> {
+        let values = |col_idx: usize| {
+            Self::granule_values(col_idx, granule_data, granule_col_defs, table_col_defs)
+        };
+        let rows = selection.iter().map(|row_idx| *row_idx as usize);
+
+        match filter {
+            CompiledFilter::Compare { col_idx, op, value } => match values(*col_idx)? {
+                Some(values) => Ok(Self::scatter(
+                    row_count,
+                    selection,
+                    rows.map(|row_idx| CompiledFilter::cmp_vals(&values[row_idx], value, op)),
+                )),
+                None => Ok(vec![false; row_count]),
+            },
+            CompiledFilter::CompareColumns {
+                left_idx,
+                op,
+                right_idx,
+            } => {
+                let (left_values, right_values) = (values(*left_idx)?, values(*right_idx)?);
+                if left_values.is_none() && right_values.is_none() {
+                    return Ok(vec![false; row_count]);
+                }
+                Ok(Self::scatter(
+                    row_count,
+                    selection,
+                    rows.map(|row_idx| {
+                        // column without data is compared as `NULL`
+                        let left =
+                            left_values.map_or(&ArchivedValue::Null, |values| &values[row_idx]);
+                        let right =
+                            right_values.map_or(&ArchivedValue::Null, |values| &values[row_idx]);
+                        CompiledFilter::cmp_vals(left, right, op)
+                    }),
+                ))
+            }
+            CompiledFilter::CompareExpr { expr, op, value } => {
+                let row_values = Self::eval_expr_vectorized(
+                    expr,
+                    granule_data,
+                    granule_col_defs,
+                    table_col_defs,
+                    rows,
+                )?;
+                Ok(Self::scatter(
+                    row_count,
+                    selection,
+                    row_values
+                        .iter()
+                        .map(|row_value| CompiledFilter::cmp_vals(row_value, value, op)),
+                ))
+            }
+            CompiledFilter::BoolExpr(expr) => {
+                let row_values = Self::eval_expr_vectorized(
+                    expr,
+                    granule_data,
+                    granule_col_defs,
+                    table_col_defs,
+                    rows,
+                )?;
+                Ok(Self::scatter(
+                    row_count,
+                    selection,
+                    row_values
+                        .iter()
+                        .map(|row_value| *row_value == Value::Bool(true)),
+                ))
+            }
+            CompiledFilter::Column(col_idx) => match values(*col_idx)? {
+                Some(values) => Ok(Self::scatter(
+                    row_count,
+                    selection,
+                    rows.map(|row_idx| {
+                        if let ArchivedValue::Bool(val) = &values[row_idx] {
+                            *val
+                        } else {
+                            true
+                        }
+                    }),
+                )),
+                None => Ok(vec![false; row_count]),
+            },
+            CompiledFilter::Const(value) => Ok(Self::scatter(
+                row_count,
+                selection,
+                selection.iter().map(|_| *value),
+            )),
+            CompiledFilter::And(..) | CompiledFilter::Or(..) | CompiledFilter::Not(_) => {
+                unreachable!("Boolean operators are evaluated by `eval_filter_selected`")
+            }
+        }
+    }
+
+    /// Returns archived values of the table column in the granule, `None` when granule has no
+    /// data of the column.
+    fn granule_values<'a>(
+        col_idx: usize,
+        granule_data: &'a [Option<Vec<u8>>],
+        granule_col_defs: &[ColumnDef],
+        table_col_defs: &[ColumnDef],
+    ) -> Result<Option<&'a ArchivedVec<ArchivedValue>>> {
+        let data_idx = granule_col_defs
+            .iter()
+            .position(|col_def| *col_def == table_col_defs[col_idx]);
+        match data_idx.and_then(|data_idx| granule_data[data_idx].as_ref()) {
+            Some(col_data) => TablePartInfo::access_granule(col_data).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns indices of rows of `selection` (all rows, when `None`), where `mask` is `matched`.
+    fn selected_rows(mask: &[bool], selection: Option<&[u32]>, matched: bool) -> Vec<u32> {
+        match selection {
+            None => (0..mask.len() as u32)
+                .filter(|row_idx| mask[*row_idx as usize] == matched)
+                .collect(),
+            Some(selection) => selection
+                .iter()
+                .copied()
+                .filter(|row_idx| mask[*row_idx as usize] == matched)
+                .collect(),
+        }
+    }
+
+    /// Builds mask of `row_count` rows from results of `selection` rows, in the same order.
+    /// Other rows are `false`.
+    fn scatter(
+        row_count: usize,
+        selection: &[u32],
+        selected_mask: impl Iterator<Item = bool>,
+    ) -> Vec<bool> {
+        let mut mask = vec![false; row_count];
+        for (row_idx, matched) in selection.iter().zip(selected_mask) {
+            mask[*row_idx as usize] = matched;
+        }
+        mask
+    }
+
+    /// Evaluates filter for each row of the granule, both sides of `AND`/`OR` over all rows.
+    /// `granule_data` holds archived values of `granule_col_defs` columns, `None` for columns
+    /// without data.
+    pub(crate) fn eval_filter_full(
         filter: &CompiledFilter,
         granule_data: &[Option<Vec<u8>>],
         granule_col_defs: &[ColumnDef],
@@ -556,8 +1949,7 @@ impl GranuleBuffer {
                 if let Some(data_idx) = data_idx
                     && let Some(col_data) = &granule_data[data_idx]
                 {
-                    let values =
-                        unsafe { rkyv::access_unchecked::<ArchivedVec<ArchivedValue>>(col_data) };
+                    let values = TablePartInfo::access_granule(col_data)?;
                     Ok(values
                         .iter()
                         .map(|row_value| CompiledFilter::cmp_vals(row_value, value, op))
@@ -582,12 +1974,8 @@ impl GranuleBuffer {
                     (Some(left_idx), Some(right_idx)) => {
                         match (&granule_data[left_idx], &granule_data[right_idx]) {
                             (Some(left_data), Some(right_data)) => {
-                                let left_values = unsafe {
-                                    rkyv::access_unchecked::<ArchivedVec<ArchivedValue>>(left_data)
-                                };
-                                let right_values = unsafe {
-                                    rkyv::access_unchecked::<ArchivedVec<ArchivedValue>>(right_data)
-                                };
+                                let left_values = TablePartInfo::access_granule(left_data)?;
+                                let right_values = TablePartInfo::access_granule(right_data)?;
                                 Ok(left_values
                                     .iter()
                                     .zip(right_values.iter())
@@ -597,9 +1985,7 @@ impl GranuleBuffer {
                                     .collect())
                             }
                             (Some(left_data), None) => {
-                                let left_values = unsafe {
-                                    rkyv::access_unchecked::<ArchivedVec<ArchivedValue>>(left_data)
-                                };
+                                let left_values = TablePartInfo::access_granule(left_data)?;
 
                                 Ok(left_values
                                     .iter()
@@ -609,9 +1995,7 @@ impl GranuleBuffer {
                                     .collect())
                             }
                             (None, Some(right_data)) => {
-                                let right_values = unsafe {
-                                    rkyv::access_unchecked::<ArchivedVec<ArchivedValue>>(right_data)
-                                };
+                                let right_values = TablePartInfo::access_granule(right_data)?;
 
                                 Ok(right_values
                                     .iter()
@@ -629,9 +2013,7 @@ impl GranuleBuffer {
                     }
                     (Some(left_idx), None) => {
                         if let Some(left_data) = &granule_data[left_idx] {
-                            let left_values = unsafe {
-                                rkyv::access_unchecked::<ArchivedVec<ArchivedValue>>(left_data)
-                            };
+                            let left_values = TablePartInfo::access_granule(left_data)?;
                             Ok(left_values
                                 .iter()
                                 .map(|left_val| {
@@ -644,9 +2026,7 @@ impl GranuleBuffer {
                     }
                     (None, Some(right_idx)) => {
                         if let Some(right_data) = &granule_data[right_idx] {
-                            let right_values = unsafe {
-                                rkyv::access_unchecked::<ArchivedVec<ArchivedValue>>(right_data)
-                            };
+                            let right_values = TablePartInfo::access_granule(right_data)?;
 
                             Ok(right_values
                                 .iter()
@@ -661,15 +2041,35 @@ impl GranuleBuffer {
                     (None, None) => Ok(vec![false; row_count]),
                 }
             }
+            CompiledFilter::CompareExpr { expr, op, value } => Ok(Self::eval_expr_vectorized(
+                expr,
+                granule_data,
+                granule_col_defs,
+                table_col_defs,
+                0..row_count,
+            )?
+            .iter()
+            .map(|row_value| CompiledFilter::cmp_vals(row_value, value, op))
+            .collect()),
+            CompiledFilter::BoolExpr(expr) => Ok(Self::eval_expr_vectorized(
+                expr,
+                granule_data,
+                granule_col_defs,
+                table_col_defs,
+                0..row_count,
+            )?
+            .iter()
+            .map(|row_value| *row_value == Value::Bool(true))
+            .collect()),
             CompiledFilter::And(left, right) => {
-                let left_mask = Self::eval_filter_vectorized(
+                let left_mask = Self::eval_filter_full(
                     left,
                     granule_data,
                     granule_col_defs,
                     table_col_defs,
                     row_count,
                 )?;
-                let right_mask = Self::eval_filter_vectorized(
+                let right_mask = Self::eval_filter_full(
                     right,
                     granule_data,
                     granule_col_defs,
@@ -684,14 +2084,14 @@ impl GranuleBuffer {
                     .collect())
             }
             CompiledFilter::Or(left, right) => {
-                let left_mask = Self::eval_filter_vectorized(
+                let left_mask = Self::eval_filter_full(
                     left,
                     granule_data,
                     granule_col_defs,
                     table_col_defs,
                     row_count,
                 )?;
-                let right_mask = Self::eval_filter_vectorized(
+                let right_mask = Self::eval_filter_full(
                     right,
                     granule_data,
                     granule_col_defs,
@@ -706,7 +2106,7 @@ impl GranuleBuffer {
                     .collect())
             }
             CompiledFilter::Not(inner) => {
-                let mask = Self::eval_filter_vectorized(
+                let mask = Self::eval_filter_full(
                     inner,
                     granule_data,
                     granule_col_defs,
@@ -724,8 +2124,7 @@ impl GranuleBuffer {
                 if let Some(data_idx) = data_idx
                     && let Some(col_data) = &granule_data[data_idx]
                 {
-                    let values =
-                        unsafe { rkyv::access_unchecked::<ArchivedVec<ArchivedValue>>(col_data) };
+                    let values = TablePartInfo::access_granule(col_data)?;
 
                     Ok(values
                         .iter()
@@ -744,4 +2143,398 @@ impl GranuleBuffer {
             CompiledFilter::Const(value) => Ok(vec![*value; row_count]),
         }
     }
+
+    /// Evaluates expression for `rows` of the granule, in the same order. Missing columns are
+    /// read as `NULL`.
+    fn eval_expr_vectorized(
+        expr: &CompiledExpr,
+        granule_data: &[Option<Vec<u8>>],
+        granule_col_defs: &[ColumnDef],
+        table_col_defs: &[ColumnDef],
+        rows: impl Iterator<Item = usize>,
+    ) -> Result<Vec<Value>> {
+        let mut col_idxs = Vec::new();
+        expr.get_column_defs(&mut col_idxs);
+
+        let mut columns = vec![None; table_col_defs.len()];
+        for col_idx in col_idxs {
+            let data_idx = granule_col_defs
+                .iter()
+                .position(|col_def| *col_def == table_col_defs[col_idx]);
+
+            if let Some(data_idx) = data_idx
+                && let Some(col_data) = &granule_data[data_idx]
+            {
+                columns[col_idx] = Some(TablePartInfo::access_granule(col_data)?);
+            }
+        }
+
+        rows.map(|row_idx| {
+            expr.eval(&|col_idx| {
+                let Some(values) = columns[col_idx] else {
+                    return Ok(Value::Null);
+                };
+                rkyv::deserialize::<Value, rkyv::rancor::Error>(&values[row_idx]).map_err(|error| {
+                    Error::CouldNotReadData(format!(
+                        "Could not deserialize value in column ({}): {error}",
+                        table_col_defs[col_idx].name
+                    ))
+                })
+            })
+        })
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::sql_parser::parse_statements;
+    use sqlparser::ast::{SetExpr, Statement};
+
+    fn col_def(name: &str) -> ColumnDef {
+        ColumnDef {
+            name: name.to_string(),
+            field_type: ValueType::UInt8,
+            constraints: Default::default(),
+        }
+    }
+
+    fn column(name: &str, values: &[u8]) -> Column {
+        Column {
+            column_def: col_def(name),
+            data: values.iter().copied().map(Value::UInt8).collect(),
+        }
+    }
+
+    fn sort(order_by: &Vec<Vec<ColumnDef>>) -> Vec<Column> {
+        // sorting only by `b` would produce `b = [0, 1, 1, 2]`
+        let columns = vec![column("a", &[2, 1, 2, 1]), column("b", &[1, 2, 0, 1])];
+
+        CommandRunner::apply_post_processing(
+            columns,
+            Some(order_by),
+            &EngineName::MergeTree,
+            &[],
+            None,
+            0,
+            None,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_order_by_multiple_columns_is_lexicographic() {
+        let sorted = sort(&vec![vec![col_def("a"), col_def("b")]]);
+
+        assert_eq!(
+            sorted,
+            vec![column("a", &[1, 1, 2, 2]), column("b", &[1, 2, 0, 1])]
+        );
+    }
+
+    #[test]
+    fn test_outer_order_by_takes_precedence() {
+        // inner `ORDER BY b` only breaks ties of outer `ORDER BY a`
+        let sorted = sort(&vec![vec![col_def("b")], vec![col_def("a")]]);
+
+        assert_eq!(
+            sorted,
+            vec![column("a", &[1, 1, 2, 2]), column("b", &[1, 2, 0, 1])]
+        );
+    }
+
+    #[test]
+    fn test_row_number_is_appended_after_sort() {
+        let columns = vec![column("a", &[2, 1, 2, 1]), column("b", &[3, 2, 0, 1])];
+        let row_number = RowNumber {
+            alias: "row_number() OVER (ORDER BY b)".to_string(),
+            order_by: vec![col_def("b")],
+            position: 2,
+        };
+
+        let numbered = CommandRunner::append_row_number(columns, &row_number).unwrap();
+
+        assert_eq!(numbered[0], column("a", &[2, 1, 1, 2]));
+        assert_eq!(numbered[1], column("b", &[0, 1, 2, 3]));
+        assert_eq!(numbered[2].column_def.name, row_number.alias);
+        assert_eq!(
+            numbered[2].data,
+            ColumnData::from(vec![
+                Value::UInt64(1),
+                Value::UInt64(2),
+                Value::UInt64(3),
+                Value::UInt64(4)
+            ])
+        );
+    }
+
+    #[test]
+    fn test_project_columns_follows_projection_order() {
+        // scan appends filter and sort columns after projected ones
+        let result = vec![
+            column("b", &[1]),
+            column("a", &[2]),
+            column("c", &[3]),
+            column("row_number() OVER ()", &[4]),
+        ];
+        let row_number = RowNumber {
+            alias: "row_number() OVER ()".to_string(),
+            order_by: Vec::new(),
+            position: 1,
+        };
+
+        let projected = CommandRunner::project_columns(
+            result,
+            &[col_def("a"), col_def("b")],
+            Some(&row_number),
+        )
+        .unwrap();
+
+        assert_eq!(
+            projected,
+            vec![
+                column("a", &[2]),
+                column("row_number() OVER ()", &[4]),
+                column("b", &[1])
+            ]
+        );
+    }
+
+    fn part(name: &str, min: u8, max: u8) -> TablePartInfo {
+        TablePartInfo {
+            name: name.to_string(),
+            sequence: 0,
+            row_count: 0,
+            marks: Vec::new(),
+            pk_bounds: vec![(Value::UInt8(min), Value::UInt8(max))],
+            column_defs: vec![col_def("a"), col_def("b")],
+            partition: None,
+            suspect: None,
+        }
+    }
+
+    /// Compiles filter on table with columns `a` and `b`.
+    fn compile_filter(filter_sql: &str) -> CompiledFilter {
+        let sql = format!("SELECT * FROM db.t WHERE {filter_sql}");
+        let statements = parse_statements(&sql).unwrap();
+        let Statement::Query(query) = &statements[0] else {
+            panic!("Expected query");
+        };
+        let SetExpr::Select(select) = query.body.as_ref() else {
+            panic!("Expected select");
+        };
+        CompiledFilter::compile(
+            select.selection.clone().unwrap(),
+            &[col_def("a"), col_def("b")],
+        )
+        .unwrap()
+    }
+
+    fn matching_parts(filter_sql: &str) -> Vec<String> {
+        let table_col_defs = [col_def("a"), col_def("b")];
+        let filter = compile_filter(filter_sql);
+
+        [part("low", 0, 9), part("mid", 10, 19), part("high", 20, 29)]
+            .into_iter()
+            .filter(|info| {
+                CommandRunner::part_may_match(&filter, info, &[col_def("a")], &table_col_defs)
+            })
+            .map(|info| info.name)
+            .collect()
+    }
+
+    #[test]
+    fn test_parts_with_disjoint_pk_ranges_are_skipped() {
+        assert_eq!(matching_parts("a = 15"), ["mid"]);
+        assert_eq!(matching_parts("a = 10 OR a = 29"), ["mid", "high"]);
+        assert_eq!(matching_parts("a < 10"), ["low"]);
+        assert_eq!(matching_parts("a >= 19"), ["mid", "high"]);
+        assert_eq!(matching_parts("a > 5 AND a <= 12"), ["low", "mid"]);
+        assert_eq!(matching_parts("a > 100"), Vec::<String>::new());
+
+        // cannot be decided by bounds
+        assert_eq!(matching_parts("a != 15"), ["low", "mid", "high"]);
+        assert_eq!(matching_parts("b = 15"), ["low", "mid", "high"]);
+        assert_eq!(matching_parts("NOT a = 15"), ["low", "mid", "high"]);
+        assert_eq!(matching_parts("a = 15 OR b = 1"), ["low", "mid", "high"]);
+        assert_eq!(matching_parts("a = 15 AND b = 1"), ["mid"]);
+    }
+
+    #[test]
+    fn test_part_without_pk_bounds_may_match() {
+        let mut info = part("legacy", 0, 9);
+        info.pk_bounds.clear();
+        let filter = compile_filter("a = 15");
+
+        assert!(CommandRunner::part_may_match(
+            &filter,
+            &info,
+            &[col_def("a")],
+            &[col_def("a"), col_def("b")]
+        ));
+    }
+
+    /// Returns granules of a part with primary key `a`, whose granules start at `a` = 0, 10, 20
+    /// and 30, which are scanned for the filter.
+    fn scanned_granules(filter_sql: &str) -> Vec<usize> {
+        let mut info = part("part", 0, 39);
+        info.marks = [0, 10, 20, 30]
+            .into_iter()
+            .map(|first| Mark {
+                index: vec![Value::UInt8(first)],
+                info: Vec::new(),
+            })
+            .collect();
+
+        let (pk_filter, _) = compile_filter(filter_sql).split_pk_prunable(&[0]);
+        CommandRunner::marks_to_scan(
+            &info,
+            pk_filter.as_ref(),
+            &[col_def("a")],
+            &[col_def("a"), col_def("b")],
+        )
+    }
+
+    #[test]
+    fn test_granules_are_pruned_by_pk_conditions() {
+        assert_eq!(scanned_granules("a = 25"), [2]);
+        // conditions on other columns no longer disable pruning
+        assert_eq!(scanned_granules("a > 15 AND b = 1"), [1, 2, 3]);
+        assert_eq!(scanned_granules("b = 1 AND a < 10 AND b > 0"), [0]);
+        assert_eq!(scanned_granules("a = 25 AND (b = 1 OR a = 3)"), [2]);
+        assert_eq!(scanned_granules("(a = 5 OR a = 35) AND b = 1"), [0, 3]);
+
+        // cannot be decided by primary key alone
+        assert_eq!(scanned_granules("b = 1"), [0, 1, 2, 3]);
+        assert_eq!(scanned_granules("a > 15 OR b = 1"), [0, 1, 2, 3]);
+        assert_eq!(scanned_granules("NOT (a > 15 AND b = 1)"), [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_granules_are_pruned_by_composite_key() {
+        // key is (a, b), while marks follow the column order of the part: (b, a)
+        let mut info = part("part", 0, 2);
+        info.column_defs = vec![col_def("b"), col_def("a")];
+        info.marks = [(0, 5), (0, 9), (1, 0), (2, 7)]
+            .into_iter()
+            .map(|(a, b)| Mark {
+                index: vec![Value::UInt8(b), Value::UInt8(a)],
+                info: Vec::new(),
+            })
+            .collect();
+        let scanned_granules = |filter_sql: &str| {
+            let (pk_filter, _) = compile_filter(filter_sql).split_pk_prunable(&[0, 1]);
+            CommandRunner::marks_to_scan(
+                &info,
+                pk_filter.as_ref(),
+                &[col_def("a"), col_def("b")],
+                &[col_def("a"), col_def("b")],
+            )
+        };
+
+        assert_eq!(scanned_granules("a = 0"), [0, 1]);
+        assert_eq!(scanned_granules("a >= 2"), [2, 3]);
+        // `b` is bounded only between marks with equal `a`
+        assert_eq!(scanned_granules("b = 10"), [1, 2, 3]);
+        assert_eq!(scanned_granules("b = 6"), [0, 1, 2, 3]);
+        assert_eq!(scanned_granules("a = 0 AND b > 9"), [1]);
+        // first rows of granules do not bound the other rows
+        assert_eq!(scanned_granules("a = b"), [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_panicked_chunk_errors_and_next_chunk_succeeds() {
+        let table_def = TableDef {
+            database: "db".to_string(),
+            table: "t".to_string(),
+        };
+        let push_row = |value| {
+            LOCAL_BUFFER.with(|buffer| buffer.borrow_mut()[0].push(Value::UInt8(value)));
+        };
+
+        let error = CommandRunner::scan_chunk(1, 8, &table_def, "part_1", None, |granule| {
+            granule.set(Some(3));
+            push_row(1);
+            panic!("injected panic");
+        })
+        .unwrap_err();
+        let Error::Internal(message) = error else {
+            panic!("Expected internal error, got {error:?}");
+        };
+        assert!(
+            message.contains("table=(db.t), part=part_1, granule=3"),
+            "{message}"
+        );
+        assert!(message.contains("injected panic"), "{message}");
+        assert!(LOCAL_BUFFER.take().is_empty());
+
+        let rows = CommandRunner::scan_chunk(1, 8, &table_def, "part_1", None, |_| {
+            push_row(2);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(rows, vec![ColumnData::from(vec![Value::UInt8(2)])]);
+        assert!(LOCAL_BUFFER.take().is_empty());
+    }
+
+    #[test]
+    fn test_scan_chunk_size() {
+        const MB: u64 = 1024 * 1024;
+
+        // 4 chunks per thread
+        assert_eq!(CommandRunner::scan_chunk_size(1000, 8, MB / 10), 31);
+        assert_eq!(CommandRunner::scan_chunk_size(64, 4, MB / 10), 4);
+        // granules of a pruned scan are spread among all threads
+        assert_eq!(CommandRunner::scan_chunk_size(3, 8, MB / 10), 1);
+        assert_eq!(CommandRunner::scan_chunk_size(0, 8, MB / 10), 1);
+        // big granules are limited by `SCAN_CHUNK_BYTES`
+        assert_eq!(CommandRunner::scan_chunk_size(1000, 2, 4 * MB), 4);
+        assert_eq!(CommandRunner::scan_chunk_size(1000, 2, 100 * MB), 1);
+        // empty granules and a single thread pool
+        assert_eq!(CommandRunner::scan_chunk_size(1000, 1, 0), 250);
+        assert_eq!(CommandRunner::scan_chunk_size(1000, 0, MB / 10), 160);
+    }
+
+    #[test]
+    fn test_sorted_merge_key_is_prefix_of_table_order_by() {
+        let table_order_by = [col_def("a"), col_def("b")];
+        let result_col_defs = [col_def("c"), col_def("b"), col_def("a")];
+        let merge_key = |order_by: Vec<Vec<ColumnDef>>, engine_name| {
+            CommandRunner::sorted_merge_key(
+                Some(&order_by),
+                &engine_name,
+                &table_order_by,
+                &result_col_defs,
+            )
+        };
+
+        assert_eq!(
+            merge_key(vec![vec![col_def("a")]], EngineName::MergeTree),
+            Some(vec![2])
+        );
+        // outer `ORDER BY a` comes first
+        assert_eq!(
+            merge_key(
+                vec![vec![col_def("b")], vec![col_def("a")]],
+                EngineName::MergeTree
+            ),
+            Some(vec![2, 1])
+        );
+        assert_eq!(
+            merge_key(vec![vec![col_def("b")]], EngineName::MergeTree),
+            None
+        );
+        assert_eq!(
+            merge_key(
+                vec![vec![col_def("a"), col_def("b"), col_def("c")]],
+                EngineName::MergeTree
+            ),
+            None
+        );
+        assert_eq!(
+            merge_key(vec![vec![col_def("a")]], EngineName::ReplacingMergeTree),
+            None
+        );
+    }
 }
@@ -0,0 +1,65 @@
+use crate::engines::EngineName;
+use crate::error::{Error, Result};
+use crate::runtime_config::TABLE_DATA;
+use crate::sql::CommandRunner;
+use crate::storage::{Column, ColumnDef, Constraints, OutputTable, TableDef, Value, ValueType};
+
+impl CommandRunner {
+    /// Reconstructs `CREATE TABLE` statement of the table from its metadata.
+    ///
+    /// Returns:
+    ///   * Ok: `OutputTable` with single `statement` column and row.
+    ///   * Error: `TableNotFound` when table does not exist.
+    pub fn show_create_table(table_def: &TableDef) -> Result<OutputTable> {
+        let statement = TABLE_DATA
+            .get(table_def)
+            .ok_or(Error::TableNotFound)?
+            .metadata
+            .to_create_sql(table_def);
+
+        Ok(statement_output(statement))
+    }
+
+    /// Lists supported table engines.
+    ///
+    /// Returns: `OutputTable` with `engine_name` and `description` columns, row per engine.
+    pub fn show_engines() -> OutputTable {
+        let column = |name: &str, data: Vec<Value>| Column {
+            column_def: ColumnDef {
+                name: name.to_string(),
+                field_type: ValueType::String,
+                constraints: Constraints::default(),
+            },
+            data: data.into(),
+        };
+
+        OutputTable::new(vec![
+            column(
+                "engine_name",
+                EngineName::ALL
+                    .iter()
+                    .map(|engine| Value::String(engine.to_string()))
+                    .collect(),
+            ),
+            column(
+                "description",
+                EngineName::ALL
+                    .iter()
+                    .map(|engine| Value::String(engine.description().to_string()))
+                    .collect(),
+            ),
+        ])
+    }
+}
+
+/// Returns `OutputTable` with single `statement` column and row.
+pub(super) fn statement_output(statement: String) -> OutputTable {
+    OutputTable::new(vec![Column {
+        column_def: ColumnDef {
+            name: "statement".to_string(),
+            field_type: ValueType::String,
+            constraints: Constraints::default(),
+        },
+        data: vec![Value::String(statement)].into(),
+    }])
+}
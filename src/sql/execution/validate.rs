@@ -0,0 +1,37 @@
+use crate::error::Result;
+use crate::sql::CommandRunner;
+use crate::sql::execution::show::statement_output;
+use crate::sql::sql_parser::PhysicalPlan;
+use crate::storage::{OutputTable, TableMetadata, TableSchema};
+
+impl CommandRunner {
+    /// Reports result of planning without executing the plan, so nothing is modified.
+    ///
+    /// Returns:
+    ///   * Ok: `OutputTable` with `CREATE TABLE` statement of the resolved schema (single
+    ///     `statement` column and row, as in `SHOW CREATE TABLE`) for `CREATE TABLE`, success
+    ///     status for other commands.
+    ///   * Error: `SystemTimeWentBackword` when metadata of the table could not be built.
+    pub fn validate(plan: PhysicalPlan) -> Result<OutputTable> {
+        let PhysicalPlan::CreateTable {
+            name,
+            columns,
+            settings,
+            order_by,
+            primary_key,
+            comment,
+        } = plan
+        else {
+            return Ok(OutputTable::build_ok());
+        };
+
+        let table_schema = TableSchema {
+            columns,
+            order_by,
+            primary_key,
+        };
+        let table_metadata = TableMetadata::try_new(table_schema, settings, comment)?;
+
+        Ok(statement_output(table_metadata.to_create_sql(&name)))
+    }
+}
@@ -0,0 +1,121 @@
+use sqlparser::ast::{AlterTableOperation, ObjectName, SqlOption};
+
+use crate::error::{Error, Result};
+use crate::runtime_config::TABLE_DATA;
+use crate::sql::compiled_expr::CompiledExpr;
+use crate::sql::parse_value;
+use crate::sql::sql_parser::{LogicalPlan, parse_expr};
+use crate::storage::table_metadata::flags;
+use crate::storage::{TableDef, Value, ValueType};
+
+impl LogicalPlan {
+    /// Parses `ALTER TABLE` statement. Currently `DROP PARTITION expr` and
+    /// `MODIFY SETTING key = value, ...` (see `parse_table_flags`) are supported.
+    ///
+    /// Returns:
+    ///   * Ok: `LogicalPlan::DropPartition` with partition directory name, or
+    ///     `LogicalPlan::ModifyTableFlags`.
+    ///   * Error when:
+    ///     1. Table does not exist: `TableNotFound`.
+    ///     2. Operation is not a single `DROP PARTITION` of one partition or `MODIFY SETTING`:
+    ///        `UnsupportedCommand`.
+    ///     3. Table has no `PARTITION BY`: `UnsupportedCommand`.
+    ///     4. Partition is not a literal of `PARTITION BY` expression type: error from `parse_value`.
+    ///     5. Setting is unknown or has invalid value: `UnsupportedTableOption`.
+    pub fn from_alter_table(name: &ObjectName, operations: &[AlterTableOperation]) -> Result<Self> {
+        let table_def = TableDef::try_from(name)?;
+
+        let partitions = match operations {
+            [AlterTableOperation::DropPartitions { partitions, .. }] => partitions,
+            [AlterTableOperation::SetOptionsParens { options }] => {
+                let (set, clear) = parse_table_flags(options)?;
+                if !TABLE_DATA.contains_key(&table_def) {
+                    return Err(Error::TableNotFound);
+                }
+                return Ok(Self::ModifyTableFlags {
+                    name: table_def,
+                    set,
+                    clear,
+                });
+            }
+            _ => {
+                return Err(Error::UnsupportedCommand(
+                    "Only ALTER TABLE ... DROP PARTITION and MODIFY SETTING are supported"
+                        .to_string(),
+                ));
+            }
+        };
+        let [partition] = partitions.as_slice() else {
+            return Err(Error::UnsupportedCommand(
+                "DROP PARTITION accepts a single partition".to_string(),
+            ));
+        };
+
+        let Some(table_config) = TABLE_DATA.get(&table_def) else {
+            return Err(Error::TableNotFound);
+        };
+        let Some(partition_by) = &table_config.metadata.settings.partition_by else {
+            return Err(Error::UnsupportedCommand(format!(
+                "Table {table_def} has no PARTITION BY"
+            )));
+        };
+
+        let columns = &table_config.metadata.schema.columns;
+        let partition_type =
+            CompiledExpr::compile(&parse_expr(partition_by)?, columns)?.get_type(columns);
+        let partition = parse_value(partition, &partition_type)?.to_partition_id();
+
+        Ok(Self::DropPartition {
+            name: table_def,
+            partition,
+        })
+    }
+}
+
+/// Parses `MODIFY SETTING` options into flags to set and to clear. Settings take `0`, `1`,
+/// `true` or `false`:
+///   * `read_only` - `flags::READ_ONLY`.
+///   * `read_only_allow_drop` - `flags::READ_ONLY_ALLOW_DROP`.
+///   * `no_merges` - `flags::NO_MERGES`.
+///
+/// Returns:
+///   * Ok: `(set, clear)` flags.
+///   * Error: `UnsupportedTableOption` when setting is unknown or value is not a boolean.
+fn parse_table_flags(options: &[SqlOption]) -> Result<(u32, u32)> {
+    let (mut set, mut clear) = (flags::NONE, flags::NONE);
+    for option in options {
+        let SqlOption::KeyValue { key, value } = option else {
+            return Err(Error::UnsupportedTableOption(option.to_string()));
+        };
+        let flag = match key.value.to_lowercase().as_str() {
+            "read_only" => flags::READ_ONLY,
+            "read_only_allow_drop" => flags::READ_ONLY_ALLOW_DROP,
+            "no_merges" => flags::NO_MERGES,
+            _ => {
+                return Err(Error::UnsupportedTableOption(format!(
+                    "Unknown table setting {key}, expected read_only, read_only_allow_drop \
+                     or no_merges"
+                )));
+            }
+        };
+        let enabled = match parse_value(value, &ValueType::Bool) {
+            Ok(Value::Bool(enabled)) => enabled,
+            _ => match parse_value(value, &ValueType::UInt8) {
+                Ok(Value::UInt8(enabled @ (0 | 1))) => enabled == 1,
+                _ => {
+                    return Err(Error::UnsupportedTableOption(format!(
+                        "{key} expects 0, 1, true or false: {value}"
+                    )));
+                }
+            },
+        };
+        if enabled {
+            set |= flag;
+            clear &= !flag;
+        } else {
+            clear |= flag;
+            set &= !flag;
+        }
+    }
+    Ok((set, clear))
+}
@@ -3,6 +3,7 @@ use sqlparser::ast::{ObjectName, ObjectNamePart};
 use crate::CONFIG;
 use crate::error::{Error, Result};
 use crate::sql::sql_parser::LogicalPlan;
+use crate::sql::system_table::SYSTEM_DATABASE;
 use crate::sql::validate_name;
 
 impl LogicalPlan {
@@ -17,6 +18,7 @@ impl LogicalPlan {
     ///     2. Function passed instead of name: `InvalidDatabaseName`.
     ///     3. Name has invalid characters: `InvalidDatabaseName`.
     ///     4. Folder (database) already exists and `IF NOT EXISTS` is not passed: `DatabaseAlreadyExists`.
+    ///     5. Name is reserved for system tables (`system`): `ReservedDatabaseName`.
     pub fn from_create_database(db_name: &ObjectName, if_not_exists: bool) -> Result<Self> {
         if db_name.0.len() != 1 {
             return Err(Error::InvalidDatabaseName);
@@ -30,6 +32,9 @@ impl LogicalPlan {
         if !validate_name(name) {
             return Err(Error::InvalidDatabaseName);
         }
+        if name == SYSTEM_DATABASE {
+            return Err(Error::ReservedDatabaseName(name.clone()));
+        }
 
         let path = CONFIG.get_db_dir().join(name);
         let exists = path.exists();
@@ -72,6 +77,10 @@ mod tests {
         invalid = build_from_string_two("invalid`");
         assert!(LogicalPlan::from_create_database(&invalid, false).is_err());
         assert!(LogicalPlan::from_create_database(&invalid, true).is_err());
+        assert_eq!(
+            LogicalPlan::from_create_database(&build_from_string_one("system"), true),
+            Err(Error::ReservedDatabaseName("system".to_string()))
+        );
     }
 
     #[test]
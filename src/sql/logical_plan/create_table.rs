@@ -1,15 +1,23 @@
 use sqlparser::ast::{
-    ColumnOption, ColumnOptionDef, CreateTable, CreateTableOptions, Expr, OneOrManyWithParens,
-    SqlOption,
+    ColumnOption, ColumnOptionDef, CommentDef, CreateTable, CreateTableOptions, DataType, Expr,
+    FunctionArguments, OneOrManyWithParens, SqlOption,
 };
 use std::collections::HashSet;
 
 use crate::engines::EngineName;
 use crate::error::{Error, Result};
-use crate::sql::sql_parser::LogicalPlan;
-use crate::sql::{parse_ident, validate_name};
+use crate::sql::compiled_expr::CompiledExpr;
+use crate::sql::constant_folding::fold_constants;
+use crate::sql::sql_parser::{LogicalPlan, SAMPLE_BY_OPTION};
+use crate::sql::{parse_ident, parse_value, validate_name};
 use crate::storage::table_metadata::TableSettings;
-use crate::storage::{ColumnDef, Constraints, TableDef, Value, ValueType};
+use crate::storage::{
+    ColumnDef, CompressionType, Constraints, DefaultExpr, TableDef, Value, ValueType,
+};
+
+/// Setting, which allows nullable ORDER BY and PRIMARY KEY columns, see
+/// `LogicalPlan::set_key_nullability`.
+const ALLOW_NULLABLE_KEY_SETTING: &str = "allow_nullable_key";
 
 impl LogicalPlan {
     /// Create a table as directory and .metadata file.
@@ -19,20 +27,19 @@ impl LogicalPlan {
     ///     1. Database name and table name (does not exist) provided, columns, their types and order by are valid: `LogicalPlan::CreateTable`
     ///     2. Database name and table name (exists and `IF NOT EXISTS` specified) provided, columns, their types and order by are valid: `LogicalPlan::Skip`
     ///   * Error when:
-    ///     1. Could not parse table and database names from query: `UnsupportedCommand`.
+    ///     1. Could not parse table and database names from query: `InvalidTableName`.
     ///     2. Table already exists and `IF NOT EXISTS` is not passed: `TableAlreadyExists`.
     ///     3. Any column name provided is invalid: `InvalidColumnName`.
     ///     4. Any column name is repeated in specification: `InvalidColumnName`.
-    ///     5. Unsupported column type was provided: `UnsupportedColumnType`.
-    ///     6. `parse_column_constraints` returns error.
+    ///     5. Unsupported column type was provided (including `LowCardinality` of non-String): `UnsupportedColumnType`.
+    ///     6. `parse_column_constraints` returns error, or `Nullable(T)` column is NOT NULL.
     ///     7. `parse_order_by` returns error.
+    ///     8. `parse_partition_by` returns error.
+    ///     9. ORDER BY or PRIMARY KEY column is nullable without `allow_nullable_key`, see
+    ///        `set_key_nullability`.
     pub fn from_create_table(create_table: &CreateTable) -> Result<Self> {
         let table_def = TableDef::try_from(&create_table.name)?;
 
-        if !validate_name(&table_def.table) {
-            return Err(Error::InvalidTableName);
-        }
-
         let table_exists = table_def.exists_or_err();
         if create_table.if_not_exists && table_exists.is_ok() {
             return Ok(Self::Skip);
@@ -48,6 +55,8 @@ impl LogicalPlan {
         let mut columns: Vec<ColumnDef> = Vec::with_capacity(create_table.columns.len());
         let mut columns_names: HashSet<&String> =
             HashSet::with_capacity(create_table.columns.len());
+        // columns declared as `Nullable(T)`, `NULL` or `DEFAULT NULL`
+        let mut explicitly_nullable: HashSet<&String> = HashSet::new();
 
         for table_column in &create_table.columns {
             let column_name = &table_column.name.value;
@@ -59,18 +68,43 @@ impl LogicalPlan {
                 return Err(Error::InvalidColumnName(column_name.to_owned()));
             }
 
-            let field_type = ValueType::try_from(&table_column.data_type)?;
+            let (data_type, low_cardinality) = match &table_column.data_type {
+                DataType::LowCardinality(inner_type) => (inner_type.as_ref(), true),
+                data_type => (data_type, false),
+            };
+            let field_type = ValueType::try_from(data_type)?;
 
             let constraints = Self::parse_column_constraints(&table_column.options, &field_type)?;
-
-            columns.push(ColumnDef {
+            if matches!(data_type, DataType::Nullable(_)) && !constraints.nullable {
+                return Err(Error::UnsupportedColumnConstraint(format!(
+                    "Column ({column_name}) cannot be both {} and NOT NULL",
+                    table_column.data_type
+                )));
+            }
+            if matches!(data_type, DataType::Nullable(_))
+                || constraints.default == Some(DefaultExpr::Literal(Value::Null))
+                || table_column
+                    .options
+                    .iter()
+                    .any(|option| option.option == ColumnOption::Null)
+            {
+                explicitly_nullable.insert(column_name);
+            }
+            let mut column_def = ColumnDef {
                 name: column_name.clone(),
                 field_type,
                 constraints,
-            });
+            };
+            if low_cardinality {
+                Self::set_low_cardinality(&mut column_def)?;
+            }
+            columns.push(column_def);
         }
 
-        let settings = Self::parse_table_options(&create_table.table_options)?;
+        let mut settings = Self::parse_table_options(&create_table.table_options, &mut columns)?;
+        if let Some(partition_by) = &create_table.partition_by {
+            settings.partition_by = Some(Self::parse_partition_by(partition_by, &columns)?);
+        }
 
         let (order_by, primary_key) = match (&create_table.order_by, &create_table.primary_key) {
             (Some(order_by), Some(primary_key)) => {
@@ -101,6 +135,13 @@ impl LogicalPlan {
             }
             (None, None) => (vec![columns[0].clone()], vec![columns[0].clone()]),
         };
+        let (order_by, primary_key) = Self::set_key_nullability(
+            &mut columns,
+            order_by,
+            primary_key,
+            &explicitly_nullable,
+            Self::parse_allow_nullable_key(&create_table.table_options)?,
+        )?;
 
         Ok(Self::CreateTable {
             name: table_def,
@@ -108,35 +149,146 @@ impl LogicalPlan {
             settings,
             order_by,
             primary_key,
+            comment: Self::parse_table_comment(create_table),
         })
     }
 
-    /// Tries to parse `EngineName` from create table tree.
+    /// Sorting keys are NOT NULL by default: ORDER BY and PRIMARY KEY columns, which are
+    /// nullable only because columns are nullable unless NOT NULL is given, become NOT NULL.
+    /// Explicitly nullable key columns are kept with `SETTINGS allow_nullable_key = 1`, then
+    /// rows with `NULL` keys are ordered last (see `compare_key_values`).
+    ///
+    /// Returns:
+    ///   * Ok: ORDER BY and PRIMARY KEY with the constraints of updated `columns`.
+    ///   * Error: `UnsupportedColumnConstraint` when key column is explicitly nullable without
+    ///     `allow_nullable_key`.
+    fn set_key_nullability(
+        columns: &mut [ColumnDef],
+        mut order_by: Vec<ColumnDef>,
+        mut primary_key: Vec<ColumnDef>,
+        explicitly_nullable: &HashSet<&String>,
+        allow_nullable_key: bool,
+    ) -> Result<(Vec<ColumnDef>, Vec<ColumnDef>)> {
+        if allow_nullable_key {
+            return Ok((order_by, primary_key));
+        }
+        // primary key is a prefix of ORDER BY
+        for key in &order_by {
+            if !key.constraints.nullable {
+                continue;
+            }
+            if explicitly_nullable.contains(&key.name) {
+                return Err(Error::UnsupportedColumnConstraint(format!(
+                    "Column ({}) of ORDER BY or PRIMARY KEY is nullable, \
+                     use SETTINGS allow_nullable_key = 1 to allow NULL keys",
+                    key.name
+                )));
+            }
+            if let Some(column_def) = columns.iter_mut().find(|col| col.name == key.name) {
+                column_def.constraints.nullable = false;
+            }
+        }
+        for key in order_by.iter_mut().chain(&mut primary_key) {
+            if let Some(column_def) = columns.iter().find(|col| col.name == key.name) {
+                key.clone_from(column_def);
+            }
+        }
+        Ok((order_by, primary_key))
+    }
+
+    /// Returns value of `allow_nullable_key` setting, `false` when not specified. The setting is
+    /// not stored: tables with nullable keys are recreated with it (see `to_create_sql`).
+    ///
+    /// Returns:
+    ///   * Ok: whether nullable key columns are allowed.
+    ///   * Error: `UnsupportedTableOption` when value is not 0, 1, true or false.
+    fn parse_allow_nullable_key(table_options: &CreateTableOptions) -> Result<bool> {
+        let CreateTableOptions::Plain(options) = table_options else {
+            return Ok(false);
+        };
+        let mut allow = false;
+        for option in options {
+            if let SqlOption::KeyValue { key, value } = option
+                && key.value.eq_ignore_ascii_case(ALLOW_NULLABLE_KEY_SETTING)
+            {
+                allow = parse_bool_setting(ALLOW_NULLABLE_KEY_SETTING, value)?;
+            }
+        }
+        Ok(allow)
+    }
+
+    /// Returns table-level `COMMENT 'text'` (or `COMMENT = 'text'`), if specified.
+    fn parse_table_comment(create_table: &CreateTable) -> Option<String> {
+        let options = match &create_table.table_options {
+            CreateTableOptions::Plain(options) => options.as_slice(),
+            _ => &[],
+        };
+        let from_options = options.iter().find_map(|option| match option {
+            SqlOption::Comment(comment) => Some(comment),
+            _ => None,
+        });
+
+        match create_table.comment.as_ref().or(from_options)? {
+            CommentDef::WithEq(comment) | CommentDef::WithoutEq(comment) => Some(comment.clone()),
+        }
+    }
+
+    /// Tries to parse table options: `ENGINE`, `SAMPLE BY` and `SETTINGS` (see
+    /// `parse_statements`). `low_cardinality = 'a,b'` setting makes listed columns
+    /// `LowCardinality`, `index_granularity`, `adaptive_index_granularity` and
+    /// `index_granularity_bytes` set `TableSettings`.
     ///
     /// Returns:
     ///   * Ok when:
     ///     1. None is provided: `EngineName::MergeTree`.
-    ///     2. `"Engine".lowercase()` option is provided and name is valid: `EngineName::{SPECIFIED_ENGINE_NAME}`
+    ///     2. `"Engine".lowercase()` option is provided and name is valid (see `parse_engine`): `EngineName::{SPECIFIED_ENGINE_NAME}`
     ///   * Error when:
-    ///     1. More than 1 option is provided: `InvalidEngineName`
-    ///     2. When option name is not `"Engine".lowercase()`: `InvalidEngineName`
-    ///     3. When engine name is not valid, return error from `EngineName::try_from`
-    fn parse_table_options(table_options: &CreateTableOptions) -> Result<TableSettings> {
+    ///     1. Options are not `ENGINE`, `COMMENT` or settings: `InvalidEngineName` with the parsed options.
+    ///     2. When engine is not valid, return error from `parse_engine`.
+    ///     3. Setting is unknown or its value is not a string: `UnsupportedTableOption`.
+    ///     5. `low_cardinality` lists unknown or non-String column: `UnsupportedColumnType`.
+    ///     6. `parse_sample_by` returns error.
+    fn parse_table_options(
+        table_options: &CreateTableOptions,
+        columns: &mut [ColumnDef],
+    ) -> Result<TableSettings> {
         match table_options {
             CreateTableOptions::None => Ok(TableSettings::default()),
             CreateTableOptions::Plain(options) => {
                 let mut table_settings = TableSettings::default();
 
                 for option in options {
+                    match option {
+                        SqlOption::Comment(_) => continue, // see `parse_table_comment`
+                        SqlOption::KeyValue { key, value } if key.value == SAMPLE_BY_OPTION => {
+                            table_settings.sample_by = Some(Self::parse_sample_by(value, columns)?);
+                            continue;
+                        }
+                        SqlOption::KeyValue { key, value }
+                            if key.value.eq_ignore_ascii_case("engine") =>
+                        {
+                            table_settings.engine = Self::parse_engine_expr(value)?;
+                            continue;
+                        }
+                        SqlOption::KeyValue { key, value } => {
+                            Self::parse_setting(&key.value, value, columns, &mut table_settings)?;
+                            continue;
+                        }
+                        _ => {}
+                    }
                     let SqlOption::NamedParenthesizedList(option) = option else {
-                        return Err(Error::InvalidEngineName);
+                        return Err(Error::InvalidEngineName(option.to_string()));
                     };
                     let name = option.key.value.to_lowercase();
 
                     match name.as_str() {
                         "engine" => {
-                            let key = option.name.as_ref().ok_or(Error::InvalidEngineName)?;
-                            table_settings.engine = EngineName::try_from(key.value.as_str())?;
+                            let engine = option.name.as_ref().ok_or_else(|| {
+                                Error::InvalidEngineName(option.key.value.clone())
+                            })?;
+                            let arguments: Vec<String> =
+                                option.values.iter().map(ToString::to_string).collect();
+                            table_settings.engine = Self::parse_engine(&engine.value, &arguments)?;
                             Ok(())
                         }
                         _ => Err(Error::UnsupportedTableOption(name)),
@@ -144,8 +296,178 @@ impl LogicalPlan {
                 }
                 Ok(table_settings)
             }
-            _ => Err(Error::InvalidEngineName),
+            table_options => Err(Error::InvalidEngineName(table_options.to_string())),
+        }
+    }
+
+    /// Parses engine of `ENGINE = name`, `ENGINE = name()` and `ENGINE = name(args)`.
+    ///
+    /// Returns:
+    ///   * Ok: engine with the name.
+    ///   * Error when:
+    ///     1. Name is not a known engine: `InvalidEngineName`.
+    ///     2. Arguments are provided, which no engine accepts yet: `UnsupportedTableOption`.
+    fn parse_engine(name: &str, arguments: &[String]) -> Result<EngineName> {
+        let engine = EngineName::try_from(name)?;
+        if !arguments.is_empty() {
+            return Err(Error::UnsupportedTableOption(format!(
+                "engine arguments are not yet supported for {engine}: ({})",
+                arguments.join(", ")
+            )));
+        }
+        Ok(engine)
+    }
+
+    /// Parses engine, given as `ENGINE = value` option: name or call of the name.
+    ///
+    /// Returns:
+    ///   * Ok: engine with the name.
+    ///   * Error: `InvalidEngineName` when value is neither, or error from `parse_engine`.
+    fn parse_engine_expr(value: &Expr) -> Result<EngineName> {
+        match value {
+            Expr::Identifier(name) => Self::parse_engine(&name.value, &[]),
+            Expr::Function(function) if function.name.0.len() == 1 => {
+                let arguments = match &function.args {
+                    FunctionArguments::None => Vec::new(),
+                    FunctionArguments::List(list) => {
+                        list.args.iter().map(ToString::to_string).collect()
+                    }
+                    FunctionArguments::Subquery(_) => {
+                        return Err(Error::InvalidEngineName(value.to_string()));
+                    }
+                };
+                Self::parse_engine(&function.name.to_string(), &arguments)
+            }
+            _ => Err(Error::InvalidEngineName(value.to_string())),
+        }
+    }
+
+    /// Applies a single `SETTINGS name = value` setting.
+    ///
+    /// Returns:
+    ///   * Ok: on applied setting.
+    ///   * Error when:
+    ///     1. Setting is unknown or its value has wrong type: `UnsupportedTableOption`.
+    ///     2. `low_cardinality` lists unknown or non-String column: `UnsupportedColumnType`.
+    fn parse_setting(
+        name: &str,
+        value: &Expr,
+        columns: &mut [ColumnDef],
+        table_settings: &mut TableSettings,
+    ) -> Result<()> {
+        match name.to_lowercase().as_str() {
+            "adaptive_index_granularity" => {
+                table_settings.adaptive_index_granularity =
+                    parse_bool_setting("adaptive_index_granularity", value)?;
+                Ok(())
+            }
+            // applied to columns by `from_create_table`, see `parse_allow_nullable_key`
+            ALLOW_NULLABLE_KEY_SETTING => parse_bool_setting(name, value).map(|_| ()),
+            "index_granularity" => {
+                let Ok(Value::UInt32(rows @ 1..)) = parse_value(value, &ValueType::UInt32) else {
+                    return Err(Error::UnsupportedTableOption(format!(
+                        "index_granularity expects positive UInt32: {value}"
+                    )));
+                };
+                table_settings.index_granularity = rows;
+                Ok(())
+            }
+            "index_granularity_bytes" => {
+                let Ok(Value::UInt32(bytes @ 1..)) = parse_value(value, &ValueType::UInt32) else {
+                    return Err(Error::UnsupportedTableOption(format!(
+                        "index_granularity_bytes expects positive UInt32: {value}"
+                    )));
+                };
+                table_settings.index_granularity_bytes = bytes;
+                Ok(())
+            }
+            "low_cardinality" => {
+                let Value::String(column_names) = parse_value(value, &ValueType::String)? else {
+                    return Err(Error::UnsupportedTableOption(format!(
+                        "low_cardinality expects comma-separated column names: {value}"
+                    )));
+                };
+                for column_name in column_names.split(',').map(str::trim) {
+                    let column_def = columns
+                        .iter_mut()
+                        .find(|column_def| column_def.name == column_name)
+                        .ok_or_else(|| {
+                            Error::UnsupportedColumnType(format!(
+                                "low_cardinality column ({column_name}) does not exist"
+                            ))
+                        })?;
+                    Self::set_low_cardinality(column_def)?;
+                }
+                Ok(())
+            }
+            name => Err(Error::UnsupportedTableOption(name.to_string())),
+        }
+    }
+
+    /// Makes column dictionary-encoded (see `CompressionType::Dictionary`).
+    ///
+    /// Returns:
+    ///   * Ok: on String column.
+    ///   * Error: `UnsupportedColumnType` on column of other type.
+    fn set_low_cardinality(column_def: &mut ColumnDef) -> Result<()> {
+        if column_def.field_type != ValueType::String {
+            return Err(Error::UnsupportedColumnType(format!(
+                "LowCardinality is supported only for String: {} {}",
+                column_def.name, column_def.field_type
+            )));
+        }
+        column_def.constraints.compression_type = CompressionType::Dictionary(3);
+        Ok(())
+    }
+
+    /// Validates `PARTITION BY` expression against table columns.
+    ///
+    /// Returns:
+    ///   * Ok: expression as SQL to be stored in `TableSettings`.
+    ///   * Error when:
+    ///     1. Expression could not be compiled, e.g., unknown column: error from `CompiledExpr::compile`.
+    ///     2. Expression does not use any column: `UnsupportedTableOption`.
+    fn parse_partition_by(partition_by: &Expr, columns: &[ColumnDef]) -> Result<String> {
+        let compiled = CompiledExpr::compile(partition_by, columns)?;
+
+        let mut col_def_idxs = Vec::new();
+        compiled.get_column_defs(&mut col_def_idxs);
+        if col_def_idxs.is_empty() {
+            return Err(Error::UnsupportedTableOption(format!(
+                "PARTITION BY {partition_by} does not depend on columns"
+            )));
         }
+
+        Ok(partition_by.to_string())
+    }
+
+    /// Validates `SAMPLE BY` expression against table columns.
+    ///
+    /// Returns:
+    ///   * Ok: expression as SQL to be stored in `TableSettings`.
+    ///   * Error when:
+    ///     1. Expression could not be compiled, e.g., unknown column: error from `CompiledExpr::compile`.
+    ///     2. Expression does not use any column, or is not an integer, `Date` or `DateTime`:
+    ///        `UnsupportedTableOption`.
+    fn parse_sample_by(sample_by: &Expr, columns: &[ColumnDef]) -> Result<String> {
+        let compiled = CompiledExpr::compile(sample_by, columns)?;
+
+        let mut col_def_idxs = Vec::new();
+        compiled.get_column_defs(&mut col_def_idxs);
+        if col_def_idxs.is_empty() {
+            return Err(Error::UnsupportedTableOption(format!(
+                "SAMPLE BY {sample_by} does not depend on columns"
+            )));
+        }
+        let field_type = compiled.get_type(columns);
+        if !field_type.is_integer() && !matches!(field_type, ValueType::Date | ValueType::DateTime)
+        {
+            return Err(Error::UnsupportedTableOption(format!(
+                "SAMPLE BY {sample_by} must be an integer time, Date or DateTime, it is {field_type}"
+            )));
+        }
+
+        Ok(sample_by.to_string())
     }
 
     /// Tries to parse ORDER BY column names.
@@ -241,12 +563,16 @@ impl LogicalPlan {
     ///   * Error when:
     ///     1. Both NULL and NOT NULL are supplied for the column: `UnsupportedColumnConstraint`
     ///     2. Unsupported column constraint is provided: `UnsupportedColumnConstraint`
+    ///     3. NOT NULL column has `DEFAULT NULL`: `UnsupportedColumnConstraint`
+    ///     4. `parse_default` returns error.
+    ///     5. `COMMENT` is repeated: `UnsupportedColumnConstraint`
     pub fn parse_column_constraints(
         options: &[ColumnOptionDef],
         column_type: &ValueType,
     ) -> Result<Constraints> {
         let mut nullable = None;
         let mut default = None;
+        let mut comment = None;
         let compression_type = column_type.get_optimal_compression(); // currently `sqlparser` does not support `CODEC(compression_type)` param
 
         for option in options {
@@ -260,13 +586,14 @@ impl LogicalPlan {
                     nullable = Some(matches!(constraint, ColumnOption::Null));
                 }
                 ColumnOption::Default(expr) => {
-                    let Expr::Value(value) = expr else {
+                    default = Some(Self::parse_default(expr, column_type)?);
+                }
+                ColumnOption::Comment(text) => {
+                    if comment.replace(text.clone()).is_some() {
                         return Err(Error::UnsupportedColumnConstraint(
-                            "Non-literal default values are not supported".to_string(),
+                            "Repeated COMMENT".to_string(),
                         ));
-                    };
-                    let value = Value::try_from((value.value.clone(), column_type))?;
-                    default = Some(value);
+                    }
                 }
                 _ => {
                     return Err(Error::UnsupportedColumnConstraint(
@@ -276,18 +603,74 @@ impl LogicalPlan {
             }
         }
 
+        let nullable = nullable.unwrap_or(true);
+        if !nullable && default == Some(DefaultExpr::Literal(Value::Null)) {
+            return Err(Error::UnsupportedColumnConstraint(
+                "NOT NULL column cannot have DEFAULT NULL".to_string(),
+            ));
+        }
+
         Ok(Constraints {
-            nullable: nullable.unwrap_or(true),
+            nullable,
             default,
             compression_type,
+            comment,
         })
     }
+
+    /// Parses `DEFAULT` expression of the column. Literals and arithmetic of integer literals
+    /// are evaluated once, other expressions (e.g., `now()`) are kept to be evaluated for
+    /// each inserted row. Integer expression of `DateTime` column is taken as unix seconds,
+    /// as integer literals are, so `DEFAULT now()` is kept as `toDateTime(now())`.
+    ///
+    /// Returns:
+    ///   * Ok: `DefaultExpr`.
+    ///   * Error when:
+    ///     1. Literal does not match column type: error from `parse_value`.
+    ///     2. Expression references columns or is not supported: error from `CompiledExpr::compile`.
+    ///     3. Expression type is not the column type: `UnsupportedColumnConstraint`.
+    fn parse_default(expr: &Expr, column_type: &ValueType) -> Result<DefaultExpr> {
+        let expr = fold_constants(expr.clone());
+        if matches!(expr, Expr::Value(_) | Expr::UnaryOp { .. }) {
+            return Ok(DefaultExpr::Literal(parse_value(&expr, column_type)?));
+        }
+
+        let expr_type = CompiledExpr::compile(&expr, &[])?.get_type(&[]);
+        if *column_type == ValueType::DateTime && expr_type.is_integer() {
+            return Ok(DefaultExpr::Function(format!("toDateTime({expr})")));
+        }
+        if expr_type != *column_type {
+            return Err(Error::UnsupportedColumnConstraint(format!(
+                "Default expression ({expr}) has type {expr_type:?}, expected: {column_type:?}"
+            )));
+        }
+        Ok(DefaultExpr::Function(expr.to_string()))
+    }
+}
+
+/// Parses value of a boolean setting: 0, 1, true or false.
+///
+/// Returns:
+///   * Ok: value of the setting.
+///   * Error: `UnsupportedTableOption` on other values.
+fn parse_bool_setting(name: &str, value: &Expr) -> Result<bool> {
+    match parse_value(value, &ValueType::Bool) {
+        Ok(Value::Bool(enabled)) => Ok(enabled),
+        _ => match parse_value(value, &ValueType::UInt8) {
+            Ok(Value::UInt8(enabled @ (0 | 1))) => Ok(enabled == 1),
+            _ => Err(Error::UnsupportedTableOption(format!(
+                "{name} expects 0, 1, true or false: {value}"
+            ))),
+        },
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use sqlparser::ast::Ident;
+    use crate::sql::sql_parser::{parse_expr, parse_statements};
+    use crate::storage::{TableMetadata, TableSchema};
+    use sqlparser::ast::{Ident, Statement, Value as SQLValue};
 
     #[test]
     fn test_parse_column_constraints_valid() {
@@ -307,6 +690,7 @@ mod tests {
                 nullable: false,
                 default: None,
                 compression_type: ValueType::String.get_optimal_compression(),
+                comment: None,
             }
         );
 
@@ -317,6 +701,7 @@ mod tests {
                 nullable: true,
                 default: None,
                 compression_type: ValueType::String.get_optimal_compression(),
+                comment: None,
             }
         );
 
@@ -352,6 +737,437 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_parse_default_expressions() {
+        let default = |sql: &str, column_type: &ValueType| {
+            LogicalPlan::parse_default(&parse_expr(sql).unwrap(), column_type)
+        };
+
+        assert_eq!(
+            default("0 + 1", &ValueType::UInt8).unwrap(),
+            DefaultExpr::Literal(Value::UInt8(1))
+        );
+        assert_eq!(
+            default("-(2 * 3)", &ValueType::Int32).unwrap(),
+            DefaultExpr::Literal(Value::Int32(-6))
+        );
+        assert_eq!(
+            default("now()", &ValueType::UInt64).unwrap(),
+            DefaultExpr::Function("now()".to_string())
+        );
+
+        assert_eq!(
+            default("generateUUIDv7()", &ValueType::Uuid).unwrap(),
+            DefaultExpr::Function("generateUUIDv7()".to_string())
+        );
+        assert_eq!(
+            default("generateUUIDv4()", &ValueType::Uuid).unwrap(),
+            DefaultExpr::Function("generateUUIDv4()".to_string())
+        );
+        assert_eq!(
+            default("now()", &ValueType::DateTime).unwrap(),
+            DefaultExpr::Function("toDateTime(now())".to_string())
+        );
+        assert_eq!(
+            default("toDate(now())", &ValueType::Date).unwrap(),
+            DefaultExpr::Function("toDate(now())".to_string())
+        );
+        assert_eq!(
+            default("toDate('2020-01-01')", &ValueType::Date).unwrap(),
+            DefaultExpr::Function("toDate('2020-01-01')".to_string())
+        );
+
+        assert!(matches!(
+            default("now()", &ValueType::String),
+            Err(Error::UnsupportedColumnConstraint(_))
+        ));
+        // `Date` is days, not seconds
+        assert!(matches!(
+            default("now()", &ValueType::Date),
+            Err(Error::UnsupportedColumnConstraint(_))
+        ));
+        assert!(matches!(
+            default("255 + 1", &ValueType::UInt8),
+            Err(Error::InvalidSource(_))
+        ));
+        assert!(matches!(
+            default("other_column", &ValueType::UInt8),
+            Err(Error::ColumnNotFound(_))
+        ));
+        assert!(matches!(
+            default("unknownFunction()", &ValueType::UInt8),
+            Err(Error::UnsupportedFunction(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_column_constraints_default_null() {
+        let option = |option| ColumnOptionDef { name: None, option };
+        let default = |value| option(ColumnOption::Default(Expr::value(value)));
+
+        // explicit `DEFAULT NULL` is kept apart from absent default
+        let result = LogicalPlan::parse_column_constraints(
+            &[option(ColumnOption::Null), default(SQLValue::Null)],
+            &ValueType::String,
+        );
+        assert_eq!(
+            result.unwrap().default,
+            Some(DefaultExpr::Literal(Value::Null))
+        );
+
+        let result =
+            LogicalPlan::parse_column_constraints(&[default(SQLValue::Null)], &ValueType::String);
+        assert_eq!(
+            result.unwrap().default,
+            Some(DefaultExpr::Literal(Value::Null))
+        );
+
+        let result = LogicalPlan::parse_column_constraints(
+            &[option(ColumnOption::NotNull)],
+            &ValueType::String,
+        );
+        assert_eq!(result.unwrap().default, None);
+
+        let result = LogicalPlan::parse_column_constraints(
+            &[
+                option(ColumnOption::NotNull),
+                default(SQLValue::SingleQuotedString("x".to_string())),
+            ],
+            &ValueType::String,
+        );
+        assert_eq!(
+            result.unwrap().default,
+            Some(DefaultExpr::Literal(Value::String("x".to_string())))
+        );
+
+        // rejected at CREATE TABLE regardless of constraint order
+        for options in [
+            [option(ColumnOption::NotNull), default(SQLValue::Null)],
+            [default(SQLValue::Null), option(ColumnOption::NotNull)],
+        ] {
+            assert_eq!(
+                LogicalPlan::parse_column_constraints(&options, &ValueType::String),
+                Err(Error::UnsupportedColumnConstraint(
+                    "NOT NULL column cannot have DEFAULT NULL".to_string()
+                ))
+            );
+        }
+    }
+
+    fn create_table(sql: &str) -> Result<LogicalPlan> {
+        let statements = parse_statements(sql).unwrap();
+        let Statement::CreateTable(create_table) = &statements[0] else {
+            panic!("Expected create table");
+        };
+        LogicalPlan::from_create_table(create_table)
+    }
+
+    #[test]
+    fn test_parse_nullable_key() {
+        let keys = |sql: &str| {
+            let LogicalPlan::CreateTable {
+                columns,
+                order_by,
+                primary_key,
+                ..
+            } = create_table(sql)?
+            else {
+                panic!("Expected create table plan");
+            };
+            // keys keep the constraints of their columns
+            for key in order_by.iter().chain(&primary_key) {
+                assert!(columns.contains(key), "{key:?}");
+            }
+            Ok::<_, Error>(
+                columns
+                    .iter()
+                    .map(|col| (col.name.clone(), col.constraints.nullable))
+                    .collect::<Vec<_>>(),
+            )
+        };
+        let nullable = |names: &[(&str, bool)]| {
+            names
+                .iter()
+                .map(|(name, nullable)| (name.to_string(), *nullable))
+                .collect::<Vec<_>>()
+        };
+
+        // key columns without NULL become NOT NULL, including the default key
+        assert_eq!(
+            keys(
+                "CREATE TABLE key_db.t (a UInt64, b UInt64, c UInt64) PRIMARY KEY a ORDER BY (a, b)"
+            )
+            .unwrap(),
+            nullable(&[("a", false), ("b", false), ("c", true)])
+        );
+        assert_eq!(
+            keys("CREATE TABLE key_db.t (a UInt64, b UInt64)").unwrap(),
+            nullable(&[("a", false), ("b", true)])
+        );
+
+        for column in [
+            "a Nullable(UInt64)",
+            "a UInt64 NULL",
+            "a UInt64 DEFAULT NULL",
+        ] {
+            let sql = format!("CREATE TABLE key_db.t ({column}, b UInt64) ORDER BY (b, a)");
+            let error = keys(&sql).unwrap_err();
+            assert!(
+                matches!(&error, Error::UnsupportedColumnConstraint(message) if message.contains("allow_nullable_key")),
+                "{column}: {error}"
+            );
+            assert_eq!(
+                keys(&format!("{sql} SETTINGS allow_nullable_key = 1")).unwrap(),
+                nullable(&[("a", true), ("b", true)])
+            );
+        }
+        assert!(matches!(
+            keys(
+                "CREATE TABLE key_db.t (a UInt64 NULL) ORDER BY a SETTINGS allow_nullable_key = 0"
+            ),
+            Err(Error::UnsupportedColumnConstraint(_))
+        ));
+        assert!(matches!(
+            keys("CREATE TABLE key_db.t (a UInt64) ORDER BY a SETTINGS allow_nullable_key = 2"),
+            Err(Error::UnsupportedTableOption(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_nullable_type() {
+        let plan = create_table(
+            "CREATE TABLE nullable_db.t (id UInt64, a Nullable(Int32), b Nullable(String) NULL, \
+             c Array(Nullable(UInt8)), d Int32 NOT NULL) ORDER BY id",
+        )
+        .unwrap();
+        let LogicalPlan::CreateTable { columns, .. } = plan else {
+            panic!("Expected create table plan");
+        };
+        let types: Vec<_> = columns
+            .iter()
+            .map(|col| (col.field_type.clone(), col.constraints.nullable))
+            .collect();
+        // ORDER BY column is NOT NULL, see `test_parse_nullable_key`
+        assert_eq!(
+            types,
+            vec![
+                (ValueType::UInt64, false),
+                (ValueType::Int32, true),
+                (ValueType::String, true),
+                (ValueType::Array(Box::new(ValueType::UInt8)), true),
+                (ValueType::Int32, false),
+            ]
+        );
+
+        assert!(matches!(
+            create_table("CREATE TABLE nullable_db.t (a Nullable(Int32) NOT NULL) ORDER BY a"),
+            Err(Error::UnsupportedColumnConstraint(_))
+        ));
+        assert!(matches!(
+            create_table("CREATE TABLE nullable_db.t (a Nullable(Nullable(Int32))) ORDER BY a"),
+            Err(Error::UnsupportedColumnType(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_low_cardinality_type() {
+        let plan = create_table(
+            "CREATE TABLE lc_db.t (id UInt64, a LowCardinality(String), \
+             b LowCardinality(Nullable(String)), c String) ORDER BY id",
+        )
+        .unwrap();
+        let LogicalPlan::CreateTable { columns, .. } = plan else {
+            panic!("Expected create table plan");
+        };
+        let types: Vec<_> = columns
+            .iter()
+            .map(|col| (&col.field_type, &col.constraints.compression_type))
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                (&ValueType::UInt64, &CompressionType::LZ4(3)),
+                (&ValueType::String, &CompressionType::Dictionary(3)),
+                (&ValueType::String, &CompressionType::Dictionary(3)),
+                (&ValueType::String, &CompressionType::LZ4(3)),
+            ]
+        );
+
+        assert!(matches!(
+            create_table("CREATE TABLE lc_db.t (a LowCardinality(UInt8)) ORDER BY a"),
+            Err(Error::UnsupportedColumnType(_))
+        ));
+        assert!(matches!(
+            create_table(
+                "CREATE TABLE lc_db.t (a LowCardinality(Nullable(String)) NOT NULL) ORDER BY a"
+            ),
+            Err(Error::UnsupportedColumnConstraint(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_low_cardinality_setting() {
+        let plan = create_table(
+            "CREATE TABLE lc_db.t (id UInt64, status String, country Nullable(String)) \
+             ENGINE = MergeTree PARTITION BY status ORDER BY id \
+             SETTINGS low_cardinality = 'status, country'",
+        )
+        .unwrap();
+        let LogicalPlan::CreateTable {
+            columns, settings, ..
+        } = plan
+        else {
+            panic!("Expected create table plan");
+        };
+        let compression: Vec<_> = columns
+            .iter()
+            .map(|col| &col.constraints.compression_type)
+            .collect();
+        assert_eq!(
+            compression,
+            vec![
+                &CompressionType::LZ4(3),
+                &CompressionType::Dictionary(3),
+                &CompressionType::Dictionary(3),
+            ]
+        );
+        assert_eq!(settings.partition_by.as_deref(), Some("status"));
+
+        assert!(matches!(
+            create_table(
+                "CREATE TABLE lc_db.t (id UInt64) ORDER BY id SETTINGS low_cardinality = 'id'"
+            ),
+            Err(Error::UnsupportedColumnType(_))
+        ));
+        assert!(matches!(
+            create_table(
+                "CREATE TABLE lc_db.t (id UInt64) ORDER BY id SETTINGS low_cardinality = 'name'"
+            ),
+            Err(Error::UnsupportedColumnType(_))
+        ));
+        assert!(matches!(
+            create_table("CREATE TABLE lc_db.t (id UInt64) ORDER BY id SETTINGS unknown = 1"),
+            Err(Error::UnsupportedTableOption(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_adaptive_granularity_settings() {
+        let settings = |sql_settings: &str| {
+            create_table(&format!(
+                "CREATE TABLE g_db.t (id UInt64) ORDER BY id SETTINGS {sql_settings}"
+            ))
+            .map(|plan| {
+                let LogicalPlan::CreateTable { settings, .. } = plan else {
+                    panic!("Expected create table plan");
+                };
+                (
+                    settings.index_granularity,
+                    settings.adaptive_index_granularity,
+                    settings.index_granularity_bytes,
+                )
+            })
+        };
+
+        assert_eq!(
+            settings("adaptive_index_granularity = 1, index_granularity_bytes = 1024"),
+            Ok((8192, true, 1024))
+        );
+        assert_eq!(
+            settings("adaptive_index_granularity = true"),
+            Ok((8192, true, 10_485_760))
+        );
+        assert_eq!(
+            settings("adaptive_index_granularity = 0, index_granularity_bytes = 7"),
+            Ok((8192, false, 7))
+        );
+        assert_eq!(
+            settings("index_granularity = 128, adaptive_index_granularity = 1"),
+            Ok((128, true, 10_485_760))
+        );
+        for sql_settings in [
+            "index_granularity = 0",
+            "index_granularity = 'many'",
+            "adaptive_index_granularity = 2",
+            "adaptive_index_granularity = 'yes'",
+            "index_granularity_bytes = 0",
+            "index_granularity_bytes = -1",
+            "index_granularity_bytes = 4294967296",
+        ] {
+            assert!(
+                matches!(
+                    settings(sql_settings),
+                    Err(Error::UnsupportedTableOption(_))
+                ),
+                "{sql_settings}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_comments() {
+        let plan = create_table(
+            "CREATE TABLE comment_db.t (id UInt64 COMMENT 'it''s \"id\" ✓', name String) \
+             ENGINE = MergeTree COMMENT 'таблица' ORDER BY id",
+        )
+        .unwrap();
+        let LogicalPlan::CreateTable {
+            columns, comment, ..
+        } = plan
+        else {
+            panic!("Expected create table plan");
+        };
+        assert_eq!(
+            columns[0].constraints.comment.as_deref(),
+            Some("it's \"id\" ✓")
+        );
+        assert_eq!(columns[1].constraints.comment, None);
+        assert_eq!(comment.as_deref(), Some("таблица"));
+
+        assert!(matches!(
+            create_table(
+                "CREATE TABLE comment_db.t (id UInt64 COMMENT 'a' COMMENT 'b') ORDER BY id"
+            ),
+            Err(Error::UnsupportedColumnConstraint(_))
+        ));
+    }
+
+    #[test]
+    fn test_create_sql_round_trip() {
+        let sql = "CREATE TABLE round_trip_db.t (id UInt64 NOT NULL COMMENT 'it''s \"id\" ✓', \
+                   name LowCardinality(String) DEFAULT 'a''b', created UInt64 DEFAULT now(), \
+                   attrs Map(String, Array(Int32)), point Tuple(Int8, UUID), n Int8 DEFAULT -3) \
+                   ENGINE = ReplacingMergeTree COMMENT 'таблица ''x''' PARTITION BY n \
+                   PRIMARY KEY id ORDER BY (id, name) \
+                   SETTINGS index_granularity = 128, adaptive_index_granularity = 1, \
+                   index_granularity_bytes = 4096";
+        let plan = create_table(sql).unwrap();
+        let LogicalPlan::CreateTable {
+            name,
+            columns,
+            settings,
+            order_by,
+            primary_key,
+            comment,
+        } = &plan
+        else {
+            panic!("Expected create table plan");
+        };
+        let metadata = TableMetadata::try_new(
+            TableSchema {
+                columns: columns.clone(),
+                order_by: order_by.clone(),
+                primary_key: primary_key.clone(),
+            },
+            settings.clone(),
+            comment.clone(),
+        )
+        .unwrap();
+
+        let shown = metadata.to_create_sql(name);
+        assert_eq!(create_table(&shown).unwrap(), plan);
+    }
+
     #[test]
     fn test_parse_order_by_invalid() {
         let col1 = ColumnDef {
@@ -415,8 +1231,60 @@ mod tests {
 
     #[test]
     fn test_parse_table_options_default() {
-        let result = LogicalPlan::parse_table_options(&CreateTableOptions::None);
+        let result = LogicalPlan::parse_table_options(&CreateTableOptions::None, &mut []);
         assert!(result.is_ok());
         assert_eq!(result.unwrap().engine, EngineName::MergeTree);
     }
+
+    #[test]
+    fn test_parse_engine_spellings() {
+        let engine = |sql_engine: &str| {
+            create_table(&format!(
+                "CREATE TABLE engine_db.t (id UInt64, ver UInt64) {sql_engine} ORDER BY id"
+            ))
+            .map(|plan| {
+                let LogicalPlan::CreateTable { settings, .. } = plan else {
+                    panic!("Expected create table plan");
+                };
+                settings.engine
+            })
+        };
+
+        for sql_engine in [
+            "ENGINE = ReplacingMergeTree",
+            "ENGINE = ReplacingMergeTree()",
+            "ENGINE ReplacingMergeTree",
+        ] {
+            assert_eq!(
+                engine(sql_engine),
+                Ok(EngineName::ReplacingMergeTree),
+                "{sql_engine}"
+            );
+        }
+        assert_eq!(engine(""), Ok(EngineName::MergeTree));
+
+        // `ENGINE` as a plain option
+        for value in ["ReplacingMergeTree", "ReplacingMergeTree()"] {
+            let options = CreateTableOptions::Plain(vec![SqlOption::KeyValue {
+                key: Ident::new("ENGINE"),
+                value: parse_expr(value).unwrap(),
+            }]);
+            let settings = LogicalPlan::parse_table_options(&options, &mut []).unwrap();
+            assert_eq!(settings.engine, EngineName::ReplacingMergeTree, "{value}");
+        }
+
+        assert_eq!(
+            engine("ENGINE = Bogus()"),
+            Err(Error::InvalidEngineName("Bogus".to_string()))
+        );
+        let Err(Error::UnsupportedTableOption(message)) =
+            engine("ENGINE = ReplacingMergeTree(ver)")
+        else {
+            panic!("Expected unsupported engine arguments");
+        };
+        assert!(
+            message.contains("not yet supported for ReplacingMergeTree"),
+            "{message}"
+        );
+    }
 }
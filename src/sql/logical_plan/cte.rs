@@ -0,0 +1,102 @@
+use sqlparser::ast::{Cte, ObjectName, Query, With};
+use std::collections::HashSet;
+
+use crate::error::{Error, Result};
+
+/// Common table expressions (`WITH name AS (subquery)`), which a query can read by name.
+/// Query sees CTEs of its own `WITH` and of enclosing queries, while a CTE sees only CTEs
+/// defined before it.
+#[derive(Clone, Copy)]
+pub(super) struct CteScope<'a> {
+    /// CTEs of the enclosing query.
+    parent: Option<&'a CteScope<'a>>,
+    ctes: &'a [Cte],
+    /// Number of `ctes`, which are visible, the rest are defined later.
+    visible: usize,
+}
+
+impl<'a> CteScope<'a> {
+    /// Returns scope of a query with `with` clause inside of `parent` scope.
+    ///
+    /// Returns:
+    ///   * Ok: scope with all CTEs of `with` visible.
+    ///   * Error when:
+    ///     1. `WITH RECURSIVE` is used: `RecursiveCte`.
+    ///     2. CTE has column list, e.g., `WITH t (a, b) AS (...)`, or its name is repeated:
+    ///        `UnsupportedCommand`.
+    pub(super) fn try_new(
+        with: Option<&'a With>,
+        parent: Option<&'a CteScope<'a>>,
+    ) -> Result<Self> {
+        let Some(with) = with else {
+            return Ok(Self {
+                parent,
+                ctes: &[],
+                visible: 0,
+            });
+        };
+        if with.recursive {
+            return Err(Error::RecursiveCte(with.to_string()));
+        }
+
+        let mut names = HashSet::with_capacity(with.cte_tables.len());
+        for cte in &with.cte_tables {
+            if !cte.alias.columns.is_empty() {
+                return Err(Error::UnsupportedCommand(format!(
+                    "Column list of CTE is not supported: {}",
+                    cte.alias
+                )));
+            }
+            if !names.insert(&cte.alias.name.value) {
+                return Err(Error::UnsupportedCommand(format!(
+                    "CTE {} is defined more than once",
+                    cte.alias.name
+                )));
+            }
+        }
+
+        Ok(Self {
+            parent,
+            ctes: &with.cte_tables,
+            visible: with.cte_tables.len(),
+        })
+    }
+
+    /// Finds CTE, which FROM clause reads by `name`. Only one-part names refer to CTEs, so a CTE
+    /// takes precedence over tables of the same name only within the query.
+    ///
+    /// Returns:
+    ///   * Ok: query of the CTE and the scope it is planned in, which has only CTEs defined
+    ///     before it. `None` when no CTE has the name.
+    ///   * Error: `RecursiveCte` when CTE reads itself or a CTE, defined after it.
+    pub(super) fn resolve(&self, name: &ObjectName) -> Result<Option<(&'a Query, Self)>> {
+        let [part] = name.0.as_slice() else {
+            return Ok(None);
+        };
+        let Some(ident) = part.as_ident() else {
+            return Ok(None);
+        };
+
+        let mut scope = Some(self);
+        while let Some(current) = scope {
+            if let Some(idx) = current
+                .ctes
+                .iter()
+                .position(|cte| cte.alias.name.value == ident.value)
+            {
+                if idx >= current.visible {
+                    return Err(Error::RecursiveCte(format!(
+                        "{ident} is read before its definition ends"
+                    )));
+                }
+                let cte_scope = Self {
+                    visible: idx,
+                    ..*current
+                };
+                return Ok(Some((&current.ctes[idx].query, cte_scope)));
+            }
+            scope = current.parent;
+        }
+        Ok(None)
+    }
+}
@@ -0,0 +1,31 @@
+use crate::error::{Error, Result};
+use crate::runtime_config::TABLE_DATA;
+use crate::sql::sql_parser::LogicalPlan;
+use crate::storage::TableDef;
+use sqlparser::ast::ObjectName;
+
+impl LogicalPlan {
+    /// Parses `EXCHANGE TABLES db.first AND db.second`.
+    ///
+    /// Returns:
+    ///   * Ok: `LogicalPlan::ExchangeTables`.
+    ///   * Error when:
+    ///     1. Table name is invalid, see `TableDef::try_from`.
+    ///     2. Both names refer to the same table: `InvalidSource`.
+    ///     3. Either table does not exist: `TableNotFound`.
+    pub fn from_exchange_tables(first: &ObjectName, second: &ObjectName) -> Result<Self> {
+        let first = TableDef::try_from(first)?;
+        let second = TableDef::try_from(second)?;
+
+        if first == second {
+            return Err(Error::InvalidSource(format!(
+                "Can not exchange table {first} with itself"
+            )));
+        }
+        if !TABLE_DATA.contains_key(&first) || !TABLE_DATA.contains_key(&second) {
+            return Err(Error::TableNotFound);
+        }
+
+        Ok(Self::ExchangeTables { first, second })
+    }
+}
@@ -0,0 +1,177 @@
+use sqlparser::ast::{
+    Expr, FunctionArg, FunctionArgExpr, Ident, ObjectName, TableAlias, TableFactor,
+    TableFunctionArgs,
+};
+
+use crate::error::{Error, Result};
+use crate::sql::logical_plan::cte::CteScope;
+use crate::sql::parse_ident;
+use crate::sql::sql_parser::LogicalPlan;
+use crate::storage::{ColumnDef, Constraints, ValueType};
+
+/// Name of the column with position of the element in the array, starting from 0.
+const FLATTEN_INDEX_COLUMN: &str = "index";
+/// Name of the column with the element of the array.
+const FLATTEN_VALUE_COLUMN: &str = "value";
+
+impl LogicalPlan {
+    /// Parses `source, FLATTEN(array_col)` of FROM clause (also `LATERAL FLATTEN(input =>
+    /// array_col)`, as in Snowflake). Column may be qualified by the name or alias of the source,
+    /// e.g., `FROM db.t, FLATTEN(t.tags)`.
+    ///
+    /// Each row of `source` becomes a row per element of its array, see `LogicalPlan::Flatten`.
+    ///
+    /// Returns:
+    ///   * Ok: `LogicalPlan::Flatten` over all columns of the source.
+    ///   * Error when:
+    ///     1. `FLATTEN` has an alias, or other arguments than a single column:
+    ///        `UnsupportedCommand` or `InvalidFunctionArguments`.
+    ///     2. Column is not an array: `InvalidFunctionArguments`.
+    ///     3. Column not found in source: `ColumnNotFound`.
+    ///     4. Source has `index` or `value` column: `DuplicateColumn`.
+    ///     5. Any error from `parse_table_factor` of the source.
+    pub(super) fn from_flatten(
+        source: &TableFactor,
+        flatten: &TableFactor,
+        ctes: &CteScope,
+    ) -> Result<Self> {
+        let scan = Self::Scan {
+            source: Self::parse_table_factor(source, ctes)?,
+        };
+        let source_columns = Self::extract_columns_from_plan(&scan)?;
+
+        let (args, alias) = match flatten {
+            TableFactor::Table {
+                args: Some(TableFunctionArgs { args, settings }),
+                alias,
+                ..
+            } if settings.is_none() => (args, alias),
+            TableFactor::Function { args, alias, .. } => (args, alias),
+            _ => {
+                return Err(Error::UnsupportedCommand(format!(
+                    "Unsupported FLATTEN: {flatten}"
+                )));
+            }
+        };
+        if alias.is_some() {
+            return Err(Error::UnsupportedCommand(format!(
+                "Aliases of FLATTEN are not supported, its columns are {FLATTEN_INDEX_COLUMN} \
+                 and {FLATTEN_VALUE_COLUMN}: {flatten}"
+            )));
+        }
+        let invalid_args = || {
+            Error::InvalidFunctionArguments(format!("FLATTEN expects a single column: {flatten}"))
+        };
+        let arg = match args.as_slice() {
+            [FunctionArg::Unnamed(FunctionArgExpr::Expr(arg))] => arg,
+            [
+                FunctionArg::Named {
+                    name,
+                    arg: FunctionArgExpr::Expr(arg),
+                    ..
+                }
+                | FunctionArg::ExprNamed {
+                    name: Expr::Identifier(name),
+                    arg: FunctionArgExpr::Expr(arg),
+                    ..
+                },
+            ] if name.value.eq_ignore_ascii_case("input") => arg,
+            _ => return Err(invalid_args()),
+        };
+        let ident = match arg {
+            Expr::Identifier(ident) => ident,
+            Expr::CompoundIdentifier(idents) => match idents.split_last() {
+                Some((ident, qualifier)) if is_source_name(source, qualifier) => ident,
+                _ => return Err(invalid_args()),
+            },
+            _ => return Err(invalid_args()),
+        };
+        let array_col = parse_ident(ident, &source_columns)?;
+        if !matches!(array_col.field_type, ValueType::Array(_)) {
+            return Err(Error::InvalidFunctionArguments(format!(
+                "FLATTEN expects an array column, {} is {}",
+                array_col.name, array_col.field_type
+            )));
+        }
+
+        for col_def in &source_columns {
+            if col_def.name == FLATTEN_INDEX_COLUMN || col_def.name == FLATTEN_VALUE_COLUMN {
+                return Err(Error::DuplicateColumn(col_def.name.clone()));
+            }
+        }
+
+        Ok(Self::Flatten {
+            array_col,
+            plan: Box::new(Self::Projection {
+                columns: source_columns,
+                computed: Vec::new(),
+                plan: Box::new(scan),
+            }),
+        })
+    }
+}
+
+/// Whether `FROM` item is `FLATTEN(...)` table function.
+pub(super) fn is_flatten(relation: &TableFactor) -> bool {
+    let name = match relation {
+        TableFactor::Table {
+            name,
+            args: Some(_),
+            ..
+        }
+        | TableFactor::Function { name, .. } => name,
+        _ => return false,
+    };
+    name.to_string().eq_ignore_ascii_case("flatten")
+}
+
+/// Whether `qualifier` of a column refers to `source`: its alias, or trailing parts of the
+/// table name, e.g., `t` or `db.t` for `db.t`.
+fn is_source_name(source: &TableFactor, qualifier: &[Ident]) -> bool {
+    let (name, alias): (Option<&ObjectName>, &Option<TableAlias>) = match source {
+        TableFactor::Table { name, alias, .. } => (Some(name), alias),
+        TableFactor::Derived { alias, .. } | TableFactor::Pivot { alias, .. } => (None, alias),
+        _ => return false,
+    };
+    if let Some(alias) = alias {
+        return matches!(qualifier, [ident] if ident.value == alias.name.value);
+    }
+    let Some(name) = name else {
+        return false;
+    };
+    let parts: Vec<_> = name.0.iter().filter_map(|part| part.as_ident()).collect();
+    !qualifier.is_empty()
+        && parts.len() >= qualifier.len()
+        && parts[parts.len() - qualifier.len()..]
+            .iter()
+            .zip(qualifier)
+            .all(|(part, ident)| part.value == ident.value)
+}
+
+/// Returns output columns of `FLATTEN`: all `source_columns`, followed by `index` (`UInt64`) and
+/// nullable `value` with the element type of `array_col`.
+pub(crate) fn flatten_columns(
+    source_columns: &[ColumnDef],
+    array_col: &ColumnDef,
+) -> Vec<ColumnDef> {
+    let ValueType::Array(elem_type) = &array_col.field_type else {
+        unreachable!("Flattened column is validated during planning");
+    };
+
+    let mut columns = source_columns.to_vec();
+    for (name, field_type, nullable) in [
+        (FLATTEN_INDEX_COLUMN, ValueType::UInt64, false),
+        (FLATTEN_VALUE_COLUMN, elem_type.as_ref().clone(), true),
+    ] {
+        columns.push(ColumnDef {
+            name: name.to_string(),
+            constraints: Constraints {
+                nullable,
+                compression_type: field_type.get_optimal_compression(),
+                ..Constraints::default()
+            },
+            field_type,
+        });
+    }
+    columns
+}
@@ -0,0 +1,96 @@
+use crate::error::{Error, Result};
+use crate::runtime_config::TABLE_DATA;
+use crate::sql::logical_plan::insert::check_omitted_columns;
+use crate::sql::parse_value;
+use crate::sql::sql_parser::{ImportFormat, ImportSource, ImportStatement, LogicalPlan};
+use crate::storage::{TableDef, Value, ValueType};
+
+use std::collections::HashSet;
+
+impl LogicalPlan {
+    /// Parses `IMPORT INTO db.t (col1, ...) FROM 'path' FORMAT CSV|TSV ...`.
+    ///
+    /// Columns follow the rules of `INSERT` (see `from_insert`): the list is required, names
+    /// each column once, and omitted NOT NULL columns must have a default. The file itself is
+    /// opened only on execution.
+    ///
+    /// Returns:
+    ///   * Ok: `LogicalPlan::Import`.
+    ///   * Error when:
+    ///     1. Table name is invalid, see `TableDef::try_from`.
+    ///     2. Table does not exist: `TableNotFound`.
+    ///     3. Column is unknown or listed twice: `InvalidColumnName`.
+    ///     4. Omitted column is NOT NULL without default, or delimiter is not a single ASCII
+    ///        character: `InvalidSource`.
+    ///     5. Setting is unknown or its value has wrong type: `UnsupportedCommand`.
+    pub fn from_import(import: &ImportStatement) -> Result<Self> {
+        let table_def = TableDef::try_from(&import.table)?;
+        let Some(table_config) = TABLE_DATA.get(&table_def) else {
+            return Err(Error::TableNotFound);
+        };
+        let schema = &table_config.metadata.schema;
+
+        let mut listed = HashSet::new();
+        let mut columns = Vec::with_capacity(import.columns.len());
+        for ident in &import.columns {
+            if !listed.insert(&ident.value) {
+                return Err(Error::InvalidColumnName(format!(
+                    "Duplicate column: {}",
+                    ident.value
+                )));
+            }
+            let column_def = schema
+                .columns
+                .iter()
+                .find(|column_def| column_def.name == ident.value)
+                .ok_or_else(|| Error::InvalidColumnName(ident.value.clone()))?;
+            columns.push(column_def.clone());
+        }
+        check_omitted_columns(schema, &listed)?;
+
+        let delimiter = match (&import.delimiter, import.format) {
+            (None, ImportFormat::Csv) => b',',
+            (None, ImportFormat::Tsv) => b'\t',
+            (Some(delimiter), _) => match delimiter.as_bytes() {
+                &[byte] if byte.is_ascii() && byte != b'\n' && byte != b'\r' => byte,
+                _ => {
+                    return Err(Error::InvalidSource(format!(
+                        "Delimiter must be a single ASCII character, other than line break, \
+                         received: '{delimiter}'"
+                    )));
+                }
+            },
+        };
+
+        let mut allow_errors_num = 0;
+        for setting in &import.settings {
+            match setting.key.value.to_lowercase().as_str() {
+                "input_allow_errors_num" => {
+                    let Ok(Value::UInt64(errors)) = parse_value(&setting.value, &ValueType::UInt64)
+                    else {
+                        return Err(Error::UnsupportedCommand(format!(
+                            "input_allow_errors_num expects UInt64: {}",
+                            setting.value
+                        )));
+                    };
+                    allow_errors_num = errors;
+                }
+                name => {
+                    return Err(Error::UnsupportedCommand(format!("Unknown setting {name}")));
+                }
+            }
+        }
+
+        Ok(Self::Import {
+            table_def,
+            columns,
+            source: ImportSource {
+                path: import.path.clone(),
+                format: import.format,
+                with_header: import.with_header,
+                delimiter,
+                allow_errors_num,
+            },
+        })
+    }
+}
@@ -1,21 +1,31 @@
-use sqlparser::ast::{Expr, Insert, SetExpr, TableObject, UnaryOperator, Value as SQLValue};
+use sqlparser::ast::{AssignmentTarget, Expr, Insert, OnInsert, SetExpr, TableObject};
+use std::collections::HashSet;
 
 use crate::error::{Error, Result};
 use crate::runtime_config::TABLE_DATA;
+use crate::sql::compiled_expr::CompiledExpr;
 use crate::sql::sql_parser::LogicalPlan;
-use crate::storage::{Column, TableDef, Value};
+use crate::sql::{eval_default, parse_value};
+use crate::storage::{Column, ColumnData, ColumnDef, TableDef, TableSchema, Value};
 
 impl LogicalPlan {
     /// Parses INSERT statement into `LogicalPlan::Insert` variant.
     ///
-    /// Validates that:
-    /// - Table exists and columns are valid
-    /// - All NOT NULL and ORDER BY columns are provided
-    /// - Values match column types
+    /// Rules:
+    /// - Column list is required, may list any subset of table columns in any order, each once.
+    /// - Every row has exactly one value per listed column, matched by position.
+    /// - Omitted column is filled with its default (evaluated for each row), or `NULL` when it is
+    ///   nullable without default. Omitted NOT NULL column without default is an error, including
+    ///   ORDER BY and PRIMARY KEY columns.
+    /// - Values match column types, `DEFAULT` cells are replaced with column defaults.
+    /// - `DEFAULT VALUES` (without column list) inserts one row, where every column is omitted.
+    /// - `ON DUPLICATE KEY UPDATE col = expr, ...` assigns literals or expressions of the existing
+    ///   row to columns, which are not in PRIMARY KEY, see `parse_on_duplicate`.
     ///
     /// Returns:
-    ///   * Ok: `LogicalPlan::Insert` with validated columns and data
-    ///   * Error: `TableNotFound`, `InvalidColumnName`, `InvalidColumnsSpecified`, `InvalidSource`, or `EmptySource`
+    ///   * Ok: `LogicalPlan::Insert` with listed columns first, followed by filled omitted ones.
+    ///   * Error: `TableNotFound`, `NoColumnsSpecified`, `InvalidColumnName`, `InvalidSource`,
+    ///     `EmptySource`, `UnsupportedCommand` or `CouldNotInsertData`
     pub fn from_insert(insert: &Insert) -> Result<Self> {
         let TableObject::TableName(ref table) = insert.table else {
             return Err(Error::UnsupportedCommand(
@@ -28,10 +38,11 @@ impl LogicalPlan {
             return Err(Error::TableNotFound);
         };
 
-        if insert.columns.is_empty() {
+        let default_values = is_default_values(insert);
+        if insert.columns.is_empty() && !default_values {
             return Err(Error::NoColumnsSpecified);
         }
-        let mut seen = std::collections::HashSet::new();
+        let mut seen = HashSet::new();
         for col in &insert.columns {
             if !seen.insert(&col.value) {
                 return Err(Error::InvalidColumnName(format!(
@@ -42,7 +53,7 @@ impl LogicalPlan {
         }
 
         let mut insert_columns = Vec::with_capacity(insert.columns.len());
-        let mut insert_column_set = std::collections::HashSet::new();
+        let mut insert_column_set = HashSet::new();
         for input_column in &insert.columns {
             let column_def = table_config
                 .metadata
@@ -55,145 +66,307 @@ impl LogicalPlan {
             insert_column_set.insert(&column_def.name);
         }
 
-        let missing_not_null_not_default = table_config
-            .metadata
-            .schema
-            .columns
-            .iter()
-            .filter(|col| !insert_column_set.contains(&col.name))
-            .find(|col| !col.constraints.nullable && col.constraints.default.is_none());
-
-        if let Some(col_def) = missing_not_null_not_default {
-            return Err(Error::InvalidSource(format!(
-                "Column ({}) is not specified and is neither nullable nor have a default value.",
-                col_def.name
-            )));
-        }
-
-        for order_by_col in &table_config.metadata.schema.order_by {
-            if !insert_column_set.contains(&order_by_col.name)
-                && !order_by_col.constraints.nullable
-                && order_by_col.constraints.default.is_none()
-            {
-                return Err(Error::InvalidColumnsSpecified);
-            }
-        }
-
-        for pk_col in &table_config.metadata.schema.primary_key {
-            if !insert_column_set.contains(&pk_col.name)
-                && !pk_col.constraints.nullable
-                && pk_col.constraints.default.is_none()
-            {
-                return Err(Error::InvalidColumnsSpecified);
-            }
-        }
+        check_omitted_columns(&table_config.metadata.schema, &insert_column_set)?;
 
         let mut columns: Vec<Column> = insert_columns
             .into_iter()
             .map(|x| Column {
                 column_def: x,
-                data: Vec::new(),
+                data: ColumnData::default(),
             })
             .collect();
 
-        let Some(source) = &insert.source else {
-            return Err(Error::InvalidSource(
-                "No source of values was specified.".to_string(),
-            ));
-        };
-        let SetExpr::Values(source) = source.body.as_ref() else {
-            return Err(Error::InvalidSource("Provide direct values".to_string())); // todo: allow source to be from select
+        let row_count = if default_values {
+            1
+        } else {
+            parse_values(insert, &mut columns)?
         };
 
-        let Some(val_count) = source.rows.first().map(Vec::len) else {
-            return Err(Error::EmptySource);
+        fill_omitted_columns(&table_config.metadata.schema, &mut columns, row_count)?;
+
+        let on_duplicate = match &insert.on {
+            Some(on) => parse_on_duplicate(on, &table_config.metadata.schema)?,
+            None => Vec::new(),
         };
 
-        if source.rows.iter().any(|x| x.len() != val_count) {
-            return Err(Error::InvalidSource("Columns length mismatch.".to_string()));
+        Ok(LogicalPlan::Insert {
+            table_def,
+            columns,
+            on_duplicate,
+        })
+    }
+}
+
+/// Checks, that every NOT NULL column without default is in `listed` columns. ORDER BY and
+/// PRIMARY KEY columns are table columns, so they are checked as well.
+///
+/// Returns:
+///   * Ok: when omitted columns can be filled by `fill_omitted_columns`.
+///   * Error: `InvalidSource` naming all omitted NOT NULL columns without default.
+pub(super) fn check_omitted_columns(schema: &TableSchema, listed: &HashSet<&String>) -> Result<()> {
+    let missing_not_null_not_default: Vec<&str> = schema
+        .columns
+        .iter()
+        .filter(|col| !listed.contains(&col.name))
+        .filter(|col| !col.constraints.nullable && col.constraints.default.is_none())
+        .map(|col| col.name.as_str())
+        .collect();
+
+    if !missing_not_null_not_default.is_empty() {
+        return Err(Error::InvalidSource(format!(
+            "Columns ({}) are not specified and are neither nullable nor have a default value.",
+            missing_not_null_not_default.join(", ")
+        )));
+    }
+    Ok(())
+}
+
+/// Appends table columns, which are not in `columns`, filled for `row_count` rows with their
+/// default (evaluated for each row), or `NULL` when nullable without default. NOT NULL columns
+/// without default are skipped, see `check_omitted_columns`.
+///
+/// Returns:
+///   * Ok: when omitted columns were appended.
+///   * Error: default expression could not be evaluated, see `eval_default`.
+pub(in crate::sql) fn fill_omitted_columns(
+    schema: &TableSchema,
+    columns: &mut Vec<Column>,
+    row_count: usize,
+) -> Result<()> {
+    for column_def in &schema.columns {
+        if columns
+            .iter()
+            .any(|column| column.column_def.name == column_def.name)
+        {
+            continue;
         }
+        let data = if let Some(default) = &column_def.constraints.default {
+            eval_default(default, row_count)?.into()
+        } else if column_def.constraints.nullable {
+            ColumnData::constant(Value::Null, row_count)
+        } else {
+            continue;
+        };
+        columns.push(Column {
+            column_def: column_def.clone(),
+            data,
+        });
+    }
+    Ok(())
+}
 
-        if columns.len() != val_count {
+/// Parses assignments of `ON DUPLICATE KEY UPDATE col = expr, ...`. Expression is a literal of
+/// the column type (`NULL` only for nullable columns), or an expression of columns of the table
+/// of the column type, e.g., `hits = hits + 1`, which is evaluated against the existing row
+/// (see `CommandRunner::upsert`).
+///
+/// Returns:
+///   * Ok: column and compiled expression of each assignment.
+///   * Error:
+///     1. `UnsupportedCommand` for `ON CONFLICT`, assignment of a tuple, or table without
+///        PRIMARY KEY.
+///     2. `InvalidColumnName` when column does not exist or is assigned twice.
+///     3. `InvalidSource` when column is in PRIMARY KEY, or expression does not match its type.
+///     4. `CouldNotInsertData` when value is `NULL`, but column is NOT NULL.
+///     5. `ColumnNotFound` when expression uses a column, which does not exist.
+fn parse_on_duplicate(
+    on: &OnInsert,
+    schema: &TableSchema,
+) -> Result<Vec<(ColumnDef, CompiledExpr)>> {
+    let OnInsert::DuplicateKeyUpdate(assignments) = on else {
+        return Err(Error::UnsupportedCommand(format!(
+            "Only ON DUPLICATE KEY UPDATE is supported, received: {on}"
+        )));
+    };
+    if schema.primary_key.is_empty() {
+        return Err(Error::UnsupportedCommand(
+            "ON DUPLICATE KEY UPDATE requires PRIMARY KEY of the table".to_string(),
+        ));
+    }
+
+    let mut on_duplicate: Vec<(ColumnDef, CompiledExpr)> = Vec::with_capacity(assignments.len());
+    for assignment in assignments {
+        let AssignmentTarget::ColumnName(name) = &assignment.target else {
+            return Err(Error::UnsupportedCommand(format!(
+                "ON DUPLICATE KEY UPDATE supports only a column per assignment, received: {assignment}"
+            )));
+        };
+        let name = name.to_string();
+        let column_def = schema
+            .columns
+            .iter()
+            .find(|column_def| column_def.name == name)
+            .ok_or_else(|| Error::InvalidColumnName(name.clone()))?;
+        if on_duplicate
+            .iter()
+            .any(|(assigned, _)| assigned.name == column_def.name)
+        {
+            return Err(Error::InvalidColumnName(format!(
+                "Column {name} is assigned twice"
+            )));
+        }
+        if schema.primary_key.contains(column_def) {
             return Err(Error::InvalidSource(format!(
-                "Invalid number of values specified. Expected: {}, got: {}",
-                columns.len(),
-                val_count
+                "PRIMARY KEY column {name} can not be updated"
             )));
         }
 
-        for row in &source.rows {
-            for (col_idx, expr) in row.iter().enumerate() {
-                let sql_value = match expr {
-                    Expr::Value(sql_value) => sql_value.value.clone(),
-                    Expr::UnaryOp { op, expr } => {
-                        let Expr::Value(inner) = expr.as_ref() else {
-                            return Err(Error::InvalidSource(format!(
-                                "Expected direct value, received: {expr}"
-                            )));
-                        };
-                        match (&op, &inner.value) {
-                            (UnaryOperator::Minus, SQLValue::Number(n, exact)) => {
-                                SQLValue::Number(format!("-{n}"), *exact)
-                            }
-                            (UnaryOperator::Plus, SQLValue::Number(n, exact)) => {
-                                SQLValue::Number(n.clone(), *exact)
-                            }
-                            _ => {
-                                return Err(Error::InvalidSource(format!(
-                                    "Expected plus or minus as operator and a number, received: {} and {}",
-                                    op, inner.value
-                                )));
-                            }
-                        }
-                    }
-                    _ => {
-                        return Err(Error::InvalidSource(format!(
-                            "Expected a value, received: {expr}"
-                        )));
-                    }
-                };
-
-                let column_type = &columns[col_idx].column_def.field_type;
-                let value = Value::try_from((sql_value, column_type))?;
-
-                if value == Value::Null && !columns[col_idx].column_def.constraints.nullable {
+        let could_not_use = |reason: String| {
+            Error::InvalidSource(format!(
+                "column '{name}': could not use ({}) as {:?}: {reason}",
+                assignment.value, column_def.field_type
+            ))
+        };
+        let expr = match parse_value(&assignment.value, &column_def.field_type) {
+            Ok(value) => {
+                if value == Value::Null && !column_def.constraints.nullable {
                     return Err(Error::CouldNotInsertData(format!(
-                        "NULL value not allowed for column '{}'",
-                        columns[col_idx].column_def.name
+                        "NULL value not allowed for column '{name}'"
                     )));
                 }
-
-                columns[col_idx].data.push(value);
+                CompiledExpr::Literal(value)
             }
+            Err(error) => {
+                let literal_error = || {
+                    could_not_use(match error {
+                        Error::InvalidSource(reason) => reason,
+                        error => error.to_string(),
+                    })
+                };
+                let compiled = match CompiledExpr::compile(&assignment.value, &schema.columns) {
+                    Ok(compiled) => compiled,
+                    Err(error @ Error::ColumnNotFound(_)) => return Err(error),
+                    Err(_) => return Err(literal_error()),
+                };
+                let mut col_def_idxs = Vec::new();
+                compiled.get_column_defs(&mut col_def_idxs);
+                // literal, which does not match the column type
+                if col_def_idxs.is_empty() {
+                    return Err(literal_error());
+                }
+                let expr_type = compiled.get_type(&schema.columns);
+                if expr_type != column_def.field_type {
+                    return Err(could_not_use(format!("expression is {expr_type:?}")));
+                }
+                compiled
+            }
+        };
+        on_duplicate.push((column_def.clone(), expr));
+    }
+    Ok(on_duplicate)
+}
+
+/// Whether statement is `INSERT INTO db.t DEFAULT VALUES`: `sqlparser` represents it without
+/// columns, source and assignments.
+fn is_default_values(insert: &Insert) -> bool {
+    insert.columns.is_empty()
+        && insert.source.is_none()
+        && insert.assignments.is_empty()
+        && insert.settings.is_none()
+        && insert.format_clause.is_none()
+}
+
+/// Fills `columns`, which are listed columns of the statement, with rows of `VALUES` source.
+/// Data of each column is allocated once for all rows.
+///
+/// Returns:
+///   * Ok: number of rows.
+///   * Error: `InvalidSource` when source is not `VALUES` or row has wrong number of values,
+///     `EmptySource`, or errors of `parse_cell`.
+fn parse_values(insert: &Insert, columns: &mut [Column]) -> Result<usize> {
+    let Some(source) = &insert.source else {
+        return Err(Error::InvalidSource(
+            "No source of values was specified.".to_string(),
+        ));
+    };
+    let SetExpr::Values(source) = source.body.as_ref() else {
+        return Err(Error::InvalidSource("Provide direct values".to_string())); // todo: allow source to be from select
+    };
+
+    if source.rows.is_empty() {
+        return Err(Error::EmptySource);
+    }
+
+    if let Some((row_idx, row)) = source
+        .rows
+        .iter()
+        .enumerate()
+        .find(|(_, row)| row.len() != columns.len())
+    {
+        return Err(Error::InvalidSource(format!(
+            "Invalid number of values specified in row {}. Expected: {} (one per listed column), got: {}",
+            row_idx + 1,
+            columns.len(),
+            row.len()
+        )));
+    }
+
+    for column in columns.iter_mut() {
+        column.data = Vec::with_capacity(source.rows.len()).into();
+    }
+    for (row_idx, row) in source.rows.iter().enumerate() {
+        for (column, expr) in columns.iter_mut().zip(row) {
+            let value = parse_cell(expr, &column.column_def, row_idx)?;
+            column.data.push(value);
         }
+    }
+
+    Ok(source.rows.len())
+}
 
-        for column_def in &table_config.metadata.schema.columns {
-            if insert_column_set.contains(&column_def.name) {
-                continue;
+/// Parses a single cell of `VALUES`. `DEFAULT` is replaced with the default value of the column,
+/// or `NULL` for nullable columns without one. Errors name the 1-based row.
+///
+/// Returns:
+///   * Ok: value of the cell.
+///   * Error:
+///     1. `InvalidSource` when value does not match the column type.
+///     2. `CouldNotInsertData` when value is `NULL` (or `DEFAULT` without default value),
+///        but column is NOT NULL.
+fn parse_cell(expr: &Expr, column_def: &ColumnDef, row_idx: usize) -> Result<Value> {
+    let row = row_idx + 1;
+    // `sqlparser` has no dedicated expression for `DEFAULT` in `VALUES`, it is an identifier
+    let value = match expr {
+        Expr::Identifier(ident)
+            if ident.quote_style.is_none() && ident.value.eq_ignore_ascii_case("DEFAULT") =>
+        {
+            match &column_def.constraints.default {
+                Some(default) => eval_default(default, 1)?.swap_remove(0),
+                None => Value::Null,
             }
-            let default_value_ref = {
-                if let Some(default_value) = column_def.constraints.default.as_ref() {
-                    default_value
-                } else if column_def.constraints.nullable {
-                    &Value::Null
-                } else {
-                    continue;
-                }
-            };
-            columns.push(Column {
-                column_def: column_def.clone(),
-                data: vec![default_value_ref.clone(); source.rows.len()],
-            });
         }
+        expr => parse_value(expr, &column_def.field_type).map_err(|error| {
+            let reason = match error {
+                Error::InvalidSource(reason) => reason,
+                error => error.to_string(),
+            };
+            Error::InvalidSource(format!(
+                "row {row}, column '{}': could not use ({expr}) as {:?}: {reason}",
+                column_def.name, column_def.field_type
+            ))
+        })?,
+    };
 
-        Ok(LogicalPlan::Insert { table_def, columns })
+    if value == Value::Null && !column_def.constraints.nullable {
+        return Err(Error::CouldNotInsertData(format!(
+            "NULL value not allowed for column '{}' in row {row}",
+            column_def.name
+        )));
     }
+
+    Ok(value)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use sqlparser::ast::{Ident, ObjectName, ObjectNamePart};
+    use crate::runtime_config::TableConfig;
+    use crate::sql::compiled_expr::ArithmeticOp;
+    use crate::sql::sql_parser::parse_statements;
+    use crate::storage::{
+        Constraints, DefaultExpr, TableMetadata, TableSchema, TableSettings, ValueType,
+    };
+    use sqlparser::ast::{Ident, ObjectName, ObjectNamePart, Statement};
 
     fn build_table_name(db: &str, table: &str) -> ObjectName {
         ObjectName(vec![
@@ -272,4 +445,358 @@ mod tests {
             ),
         }
     }
+
+    fn rows(sql: &str) -> Vec<Vec<Expr>> {
+        let statements = parse_statements(sql).unwrap();
+        let Statement::Insert(insert) = &statements[0] else {
+            panic!("Expected insert");
+        };
+        let SetExpr::Values(values) = insert.source.as_ref().unwrap().body.as_ref() else {
+            panic!("Expected values");
+        };
+        values.rows.clone()
+    }
+
+    fn column_def(name: &str, nullable: bool, default: Option<Value>) -> ColumnDef {
+        ColumnDef {
+            name: name.to_string(),
+            field_type: ValueType::UInt8,
+            constraints: Constraints {
+                nullable,
+                default: default.map(DefaultExpr::Literal),
+                ..Constraints::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_parse_cell_default() {
+        let rows =
+            rows("INSERT INTO db.t (a, b, c) VALUES (1, DEFAULT, NULL), (default, default, 2)");
+        let columns = [
+            column_def("a", false, Some(Value::UInt8(7))),
+            column_def("b", true, None),
+            column_def("c", true, Some(Value::UInt8(9))),
+        ];
+
+        let parsed: Vec<Vec<Value>> = rows
+            .iter()
+            .enumerate()
+            .map(|(row_idx, row)| {
+                row.iter()
+                    .zip(&columns)
+                    .map(|(expr, column_def)| parse_cell(expr, column_def, row_idx).unwrap())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            parsed,
+            [
+                vec![Value::UInt8(1), Value::Null, Value::Null],
+                vec![Value::UInt8(7), Value::Null, Value::UInt8(2)],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_cell_function_default() {
+        let rows = rows("INSERT INTO db.t (ts) VALUES (DEFAULT)");
+        let mut ts = column_def("ts", false, None);
+        ts.field_type = ValueType::UInt64;
+        ts.constraints.default = Some(DefaultExpr::Function("now()".to_string()));
+
+        let Value::UInt64(now) = parse_cell(&rows[0][0], &ts, 0).unwrap() else {
+            panic!("Expected UInt64");
+        };
+        assert!(now > 1_700_000_000 && now < 10_000_000_000, "{now}");
+    }
+
+    #[test]
+    fn test_parse_cell_errors_name_row() {
+        let rows = rows("INSERT INTO db.t (a) VALUES (DEFAULT), (NULL), ('x'), (300)");
+        let not_null = column_def("a", false, None);
+
+        let errors: Vec<String> = rows
+            .iter()
+            .enumerate()
+            .map(|(row_idx, row)| {
+                parse_cell(&row[0], &not_null, row_idx)
+                    .unwrap_err()
+                    .to_string()
+            })
+            .collect();
+        assert!(errors[0].contains("column 'a' in row 1"), "{}", errors[0]);
+        assert!(errors[1].contains("column 'a' in row 2"), "{}", errors[1]);
+        assert!(errors[2].contains("row 3, column 'a'"), "{}", errors[2]);
+        assert!(errors[2].contains("('x')"), "{}", errors[2]);
+        assert!(errors[3].contains("row 4, column 'a'"), "{}", errors[3]);
+        assert!(errors[3].contains("(300)"), "{}", errors[3]);
+    }
+
+    /// Registers `insert_db.{table}` with `columns`, ordered by the first one.
+    fn register_table(table: &str, columns: Vec<ColumnDef>) {
+        let order_by = vec![columns[0].clone()];
+        let metadata = TableMetadata::try_new(
+            TableSchema {
+                columns,
+                primary_key: order_by.clone(),
+                order_by,
+            },
+            TableSettings::default(),
+            None,
+        )
+        .unwrap();
+        let table_def = TableDef {
+            database: "insert_db".to_string(),
+            table: table.to_string(),
+        };
+        TABLE_DATA.insert(
+            table_def,
+            TableConfig {
+                metadata,
+                infos: Vec::new(),
+            },
+        );
+    }
+
+    /// Returns data of inserted columns, by column name.
+    fn plan_insert(sql: &str) -> Result<Vec<(String, Vec<Value>)>> {
+        let statements = parse_statements(sql).unwrap();
+        let Statement::Insert(insert) = &statements[0] else {
+            panic!("Expected insert");
+        };
+        let LogicalPlan::Insert { columns, .. } = LogicalPlan::from_insert(insert)? else {
+            panic!("Expected insert plan");
+        };
+        Ok(columns
+            .into_iter()
+            .map(|column| (column.column_def.name, column.data.into_values()))
+            .collect())
+    }
+
+    #[test]
+    fn test_insert_column_subset_matrix() {
+        register_table(
+            "matrix",
+            vec![
+                column_def("id", false, None),
+                column_def("not_null_default", false, Some(Value::UInt8(5))),
+                column_def("nullable", true, None),
+                column_def("nullable_default", true, Some(Value::UInt8(7))),
+                column_def("required", false, None),
+            ],
+        );
+
+        // omitted columns are filled after listed ones, listed ones keep their order
+        let columns =
+            plan_insert("INSERT INTO insert_db.matrix (required, id) VALUES (1, 10), (2, 20)")
+                .unwrap();
+        assert_eq!(
+            columns,
+            [
+                (
+                    "required".to_string(),
+                    vec![Value::UInt8(1), Value::UInt8(2)]
+                ),
+                ("id".to_string(), vec![Value::UInt8(10), Value::UInt8(20)]),
+                (
+                    "not_null_default".to_string(),
+                    vec![Value::UInt8(5), Value::UInt8(5)]
+                ),
+                ("nullable".to_string(), vec![Value::Null, Value::Null]),
+                (
+                    "nullable_default".to_string(),
+                    vec![Value::UInt8(7), Value::UInt8(7)]
+                ),
+            ]
+        );
+
+        // explicit values override defaults, `DEFAULT` and `NULL` cells are positional
+        let columns = plan_insert(
+            "INSERT INTO insert_db.matrix (id, required, not_null_default, nullable_default) \
+             VALUES (1, 2, 3, NULL), (4, 5, DEFAULT, DEFAULT)",
+        )
+        .unwrap();
+        assert_eq!(columns[2].1, [Value::UInt8(3), Value::UInt8(5)]);
+        assert_eq!(columns[3].1, [Value::Null, Value::UInt8(7)]);
+        assert_eq!(columns[4], ("nullable".to_string(), vec![Value::Null; 2]));
+
+        // omitted NOT NULL columns without default, including ORDER BY column, are all named
+        let error = plan_insert("INSERT INTO insert_db.matrix (nullable) VALUES (1)").unwrap_err();
+        let Error::InvalidSource(message) = error else {
+            panic!("Expected InvalidSource, got: {error:?}");
+        };
+        assert!(message.contains("(id, required)"), "{message}");
+
+        let error = plan_insert("INSERT INTO insert_db.matrix (id) VALUES (1)").unwrap_err();
+        assert!(error.to_string().contains("(required)"), "{error}");
+
+        // values must match listed columns in every row
+        let error =
+            plan_insert("INSERT INTO insert_db.matrix (id, required) VALUES (1)").unwrap_err();
+        assert!(error.to_string().contains("row 1"), "{error}");
+        let error = plan_insert(
+            "INSERT INTO insert_db.matrix (id, required) VALUES (1, 2), (3, 4), (5, 6, 7)",
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("row 3"), "{error}");
+
+        assert!(matches!(
+            plan_insert("INSERT INTO insert_db.matrix (id, required, missing) VALUES (1, 2, 3)"),
+            Err(Error::InvalidColumnName(name)) if name == "missing"
+        ));
+        assert!(matches!(
+            plan_insert("INSERT INTO insert_db.matrix (id, ID) VALUES (1, 2)"),
+            Err(Error::InvalidColumnName(_))
+        ));
+    }
+
+    #[test]
+    fn test_insert_key_and_one_column_into_wide_table() {
+        let mut columns = vec![column_def("id", false, None)];
+        for idx in 0..30_u8 {
+            // even columns are NOT NULL with default, odd ones are nullable, `c3` is required
+            let default = idx.is_multiple_of(2).then_some(Value::UInt8(idx));
+            columns.push(column_def(
+                &format!("c{idx}"),
+                !idx.is_multiple_of(2) && idx != 3,
+                default,
+            ));
+        }
+        register_table("wide", columns);
+
+        let columns =
+            plan_insert("INSERT INTO insert_db.wide (id, c3) VALUES (1, 100), (2, 200)").unwrap();
+        assert_eq!(columns.len(), 31);
+        assert_eq!(
+            columns[1],
+            ("c3".to_string(), vec![Value::UInt8(100), Value::UInt8(200)])
+        );
+        for (name, data) in &columns[2..] {
+            let idx: u8 = name[1..].parse().unwrap();
+            let expected = if idx.is_multiple_of(2) {
+                Value::UInt8(idx)
+            } else {
+                Value::Null
+            };
+            assert_eq!(data, &[expected.clone(), expected], "{name}");
+        }
+
+        let error = plan_insert("INSERT INTO insert_db.wide (id, c4) VALUES (1, 2)").unwrap_err();
+        assert!(error.to_string().contains("(c3)"), "{error}");
+    }
+
+    #[test]
+    fn test_insert_default_values() {
+        let mut uid = column_def("uid", false, None);
+        uid.field_type = ValueType::Uuid;
+        uid.constraints.default = Some(DefaultExpr::Function("generateUUIDv4()".to_string()));
+        let mut ts = column_def("ts", false, None);
+        ts.field_type = ValueType::UInt64;
+        ts.constraints.default = Some(DefaultExpr::Function("now()".to_string()));
+        register_table(
+            "defaults",
+            vec![
+                uid,
+                ts,
+                column_def("n", false, Some(Value::UInt8(5))),
+                column_def("note", true, None),
+            ],
+        );
+
+        let columns = plan_insert("INSERT INTO insert_db.defaults DEFAULT VALUES").unwrap();
+        let names: Vec<_> = columns.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["uid", "ts", "n", "note"]);
+        assert!(matches!(columns[0].1[..], [Value::Uuid(_)]));
+        assert!(matches!(columns[1].1[..], [Value::UInt64(now)] if now > 1_700_000_000));
+        assert_eq!(columns[2].1, [Value::UInt8(5)]);
+        assert_eq!(columns[3].1, [Value::Null]);
+
+        // each statement generates new values
+        let other = plan_insert("insert into insert_db.defaults default values;").unwrap();
+        assert_ne!(columns[0].1, other[0].1);
+
+        register_table(
+            "required",
+            vec![
+                column_def("id", false, None),
+                column_def("n", false, Some(Value::UInt8(5))),
+                column_def("m", false, None),
+            ],
+        );
+        let error = plan_insert("INSERT INTO insert_db.required DEFAULT VALUES").unwrap_err();
+        assert!(error.to_string().contains("(id, m)"), "{error}");
+    }
+
+    #[test]
+    fn test_insert_on_duplicate_key_update() {
+        register_table(
+            "upsert",
+            vec![
+                column_def("id", false, None),
+                column_def("n", false, None),
+                column_def("note", true, None),
+            ],
+        );
+        let on_duplicate = |sql: &str| -> Result<Vec<(String, CompiledExpr)>> {
+            let statements = parse_statements(sql).unwrap();
+            let Statement::Insert(insert) = &statements[0] else {
+                panic!("Expected insert");
+            };
+            match LogicalPlan::from_insert(insert)? {
+                LogicalPlan::Insert { on_duplicate, .. } => Ok(on_duplicate
+                    .into_iter()
+                    .map(|(column_def, expr)| (column_def.name, expr))
+                    .collect()),
+                _ => panic!("Expected insert plan"),
+            }
+        };
+
+        assert_eq!(
+            on_duplicate("INSERT INTO insert_db.upsert (id, n) VALUES (1, 2)").unwrap(),
+            []
+        );
+        assert_eq!(
+            on_duplicate(
+                "INSERT INTO insert_db.upsert (id, n) VALUES (1, 2) \
+                 ON DUPLICATE KEY UPDATE note = NULL, n = 7"
+            )
+            .unwrap(),
+            [
+                ("note".to_string(), CompiledExpr::Literal(Value::Null)),
+                ("n".to_string(), CompiledExpr::Literal(Value::UInt8(7)))
+            ]
+        );
+        assert_eq!(
+            on_duplicate(
+                "INSERT INTO insert_db.upsert (id, n) VALUES (1, 2) \
+                 ON DUPLICATE KEY UPDATE n = n + 1"
+            )
+            .unwrap(),
+            [(
+                "n".to_string(),
+                CompiledExpr::Arithmetic {
+                    op: ArithmeticOp::Add,
+                    left: Box::new(CompiledExpr::Column(1)),
+                    right: Box::new(CompiledExpr::Literal(Value::UInt8(1))),
+                }
+            )]
+        );
+
+        for (sql, expected) in [
+            ("n = 300", "could not use (300)"),
+            ("n = NULL", "NULL value not allowed"),
+            ("n = intHash32(id)", "could not use (intHash32(id))"),
+            ("n = other + 1", "other"),
+            ("id = 2", "PRIMARY KEY column id"),
+            ("n = 1, n = 2", "assigned twice"),
+            ("missing = 1", "missing"),
+        ] {
+            let error = on_duplicate(&format!(
+                "INSERT INTO insert_db.upsert (id, n) VALUES (1, 2) ON DUPLICATE KEY UPDATE {sql}"
+            ))
+            .unwrap_err();
+            assert!(error.to_string().contains(expected), "{sql}: {error}");
+        }
+    }
 }
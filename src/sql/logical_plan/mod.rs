@@ -1,5 +1,14 @@
+mod alter_table;
 mod create_database;
 mod create_table;
+mod cte;
 mod drop;
-mod insert;
+mod exchange_tables;
+pub(super) mod flatten;
+mod import;
+pub(super) mod insert;
+mod optimize_table;
+pub(super) mod pivot;
+mod resample;
 mod select;
+mod show;
@@ -0,0 +1,45 @@
+use crate::error::{Error, Result};
+use crate::runtime_config::TABLE_DATA;
+use crate::sql::sql_parser::LogicalPlan;
+use crate::storage::TableDef;
+use sqlparser::ast::{Deduplicate, Ident, ObjectName, Partition};
+
+impl LogicalPlan {
+    /// Parses `OPTIMIZE TABLE db.table [FINAL]`. Merge always combines all parts of a
+    /// partition, so `FINAL` changes nothing.
+    ///
+    /// Returns:
+    ///   * Ok: `LogicalPlan::OptimizeTable`.
+    ///   * Error when:
+    ///     1. Table name is invalid, see `TableDef::try_from`.
+    ///     2. `ON CLUSTER`, `PARTITION` or `DEDUPLICATE` is used: `UnsupportedCommand`.
+    ///     3. Table does not exist: `TableNotFound`.
+    pub fn from_optimize_table(
+        name: &ObjectName,
+        on_cluster: Option<&Ident>,
+        partition: Option<&Partition>,
+        deduplicate: Option<&Deduplicate>,
+    ) -> Result<Self> {
+        if let Some(cluster) = on_cluster {
+            return Err(Error::UnsupportedCommand(format!(
+                "OPTIMIZE TABLE ... ON CLUSTER {cluster}"
+            )));
+        }
+        if let Some(partition) = partition {
+            return Err(Error::UnsupportedCommand(format!(
+                "OPTIMIZE TABLE ... {partition}"
+            )));
+        }
+        if let Some(deduplicate) = deduplicate {
+            return Err(Error::UnsupportedCommand(format!(
+                "OPTIMIZE TABLE ... {deduplicate}"
+            )));
+        }
+
+        let name = TableDef::try_from(name)?;
+        if !TABLE_DATA.contains_key(&name) {
+            return Err(Error::TableNotFound);
+        }
+        Ok(Self::OptimizeTable { name })
+    }
+}
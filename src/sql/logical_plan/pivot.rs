@@ -0,0 +1,173 @@
+use sqlparser::ast::{Expr, ExprWithAlias, PivotValueSource, TableFactor};
+
+use crate::error::{Error, Result};
+use crate::sql::logical_plan::cte::CteScope;
+use crate::sql::sql_parser::LogicalPlan;
+use crate::sql::{function_args, parse_ident, parse_value};
+use crate::storage::{ColumnDef, Constraints, Value, ValueType};
+
+impl LogicalPlan {
+    /// Parses `source PIVOT (SUM(value_col) FOR pivot_col IN (val1, val2, ...))`.
+    ///
+    /// Rows of `source` are grouped by all its other columns. Each value of the list becomes a
+    /// column with sum of `value_col` over rows of the group, which have this value in `pivot_col`.
+    ///
+    /// Returns:
+    ///   * Ok: `LogicalPlan::Pivot` over all columns of the source.
+    ///   * Error when:
+    ///     1. Aggregate is not `SUM` of a single column: `UnsupportedFunction` or `InvalidFunctionArguments`.
+    ///     2. Summed column is not an integer: `InvalidFunctionArguments`.
+    ///     3. Pivot column is not a single column, or values are not a list of literals without
+    ///        aliases, or `DEFAULT ON NULL` is given: `UnsupportedCommand`.
+    ///     4. Value does not match type of the pivot column: `InvalidSource`.
+    ///     5. Column of a value has the name of another column: `DuplicateColumn`.
+    ///     6. Column not found in source: `ColumnNotFound`.
+    pub(super) fn from_pivot(
+        table: &TableFactor,
+        aggregate_functions: &[ExprWithAlias],
+        value_column: &[Expr],
+        value_source: &PivotValueSource,
+        default_on_null: Option<&Expr>,
+        ctes: &CteScope,
+    ) -> Result<Self> {
+        let scan = Self::Scan {
+            source: Self::parse_table_factor(table, ctes)?,
+        };
+        let source_columns = Self::extract_columns_from_plan(&scan)?;
+
+        let [
+            ExprWithAlias {
+                expr: Expr::Function(function),
+                alias: None,
+            },
+        ] = aggregate_functions
+        else {
+            return Err(Error::UnsupportedCommand(
+                "PIVOT supports a single SUM(column) without alias".to_string(),
+            ));
+        };
+        if !function.name.to_string().eq_ignore_ascii_case("sum") {
+            return Err(Error::UnsupportedFunction(function.to_string()));
+        }
+        let [Expr::Identifier(value_ident)] = function_args(function)?.as_slice() else {
+            return Err(Error::InvalidFunctionArguments(format!(
+                "SUM in PIVOT expects a single column: {function}"
+            )));
+        };
+        let value_col = parse_ident(value_ident, &source_columns)?;
+        if pivot_sum_type(&value_col.field_type).is_none() {
+            return Err(Error::InvalidFunctionArguments(format!(
+                "SUM in PIVOT expects an integer column, {} is {:?}",
+                value_col.name, value_col.field_type
+            )));
+        }
+
+        let [Expr::Identifier(pivot_ident)] = value_column else {
+            return Err(Error::UnsupportedCommand(
+                "PIVOT supports a single column after FOR".to_string(),
+            ));
+        };
+        let pivot_col = parse_ident(pivot_ident, &source_columns)?;
+        if pivot_col == value_col {
+            return Err(Error::UnsupportedCommand(format!(
+                "PIVOT column {} is also summed",
+                pivot_col.name
+            )));
+        }
+
+        if default_on_null.is_some() {
+            return Err(Error::UnsupportedCommand(
+                "DEFAULT ON NULL in PIVOT is not supported".to_string(),
+            ));
+        }
+        let PivotValueSource::List(values) = value_source else {
+            return Err(Error::UnsupportedCommand(
+                "PIVOT supports only a list of values after IN".to_string(),
+            ));
+        };
+        let pivot_values = values
+            .iter()
+            .map(|value| {
+                if value.alias.is_some() {
+                    return Err(Error::UnsupportedCommand(
+                        "Aliases of PIVOT values are not supported".to_string(),
+                    ));
+                }
+                // enum column is read as labels, so values are compared as labels too
+                let value = parse_value(&value.expr, &pivot_col.field_type)?;
+                Ok(pivot_col.field_type.to_output_value(value))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let group_by: Vec<_> = source_columns
+            .into_iter()
+            .filter(|col_def| *col_def != pivot_col && *col_def != value_col)
+            .collect();
+
+        let output_columns = pivot_columns(&group_by, &value_col, &pivot_values);
+        for (idx, col_def) in output_columns.iter().enumerate() {
+            if output_columns[..idx]
+                .iter()
+                .any(|other| other.name == col_def.name)
+            {
+                return Err(Error::DuplicateColumn(col_def.name.clone()));
+            }
+        }
+
+        let mut read_columns = group_by.clone();
+        read_columns.extend([pivot_col.clone(), value_col.clone()]);
+
+        Ok(Self::Pivot {
+            group_by,
+            pivot_col,
+            value_col,
+            pivot_values,
+            plan: Box::new(Self::Projection {
+                columns: read_columns,
+                computed: Vec::new(),
+                plan: Box::new(scan),
+            }),
+        })
+    }
+}
+
+/// Returns output columns of `PIVOT`: `group_by` columns, followed by a nullable column per
+/// value of `pivot_values`, named after the value (e.g., `Q1` for `'Q1'`).
+pub(crate) fn pivot_columns(
+    group_by: &[ColumnDef],
+    value_col: &ColumnDef,
+    pivot_values: &[Value],
+) -> Vec<ColumnDef> {
+    let Some(field_type) = pivot_sum_type(&value_col.field_type) else {
+        unreachable!("Summed column is validated during planning");
+    };
+
+    let mut columns = group_by.to_vec();
+    columns.extend(pivot_values.iter().map(|value| ColumnDef {
+        name: match value {
+            Value::String(value) => value.clone(),
+            Value::Uuid(value) => value.to_string(),
+            value => value.to_sql_literal(),
+        },
+        field_type: field_type.clone(),
+        constraints: Constraints {
+            compression_type: field_type.get_optimal_compression(),
+            ..Constraints::default()
+        },
+    }));
+    columns
+}
+
+/// Returns type of `SUM` of the column type: `Int64` for signed integers, `UInt64` for unsigned
+/// ones, `None` for other types.
+pub(crate) const fn pivot_sum_type(value_type: &ValueType) -> Option<ValueType> {
+    match value_type {
+        ValueType::Int8 | ValueType::Int16 | ValueType::Int32 | ValueType::Int64 => {
+            Some(ValueType::Int64)
+        }
+        ValueType::UInt8 | ValueType::UInt16 | ValueType::UInt32 | ValueType::UInt64 => {
+            Some(ValueType::UInt64)
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,284 @@
+use sqlparser::ast::{
+    DateTimeField, Expr, FunctionArguments, GroupByExpr, Ident, Interval, Query, SelectItem,
+    SetExpr, TableFactor,
+};
+
+use crate::error::{Error, Result};
+use crate::runtime_config::TABLE_DATA;
+use crate::sql::logical_plan::pivot::pivot_sum_type;
+use crate::sql::logical_plan::select::is_count_star;
+use crate::sql::sql_parser::{LogicalPlan, ResampleAggregate, ResampleColumn, parse_expr};
+use crate::sql::{function_args, parse_ident, parse_value};
+use crate::storage::{ColumnDef, Constraints, TableDef, Value, ValueType};
+
+impl LogicalPlan {
+    /// Parses `SELECT ... FROM table [WHERE ...] RESAMPLE BY interval` (see `parse_resample`).
+    ///
+    /// Projection lists the `SAMPLE BY` expression of the table, which is the start of the
+    /// bucket (it is the first column, when it is not listed), and aggregates: `count(*)`,
+    /// `count(column)`, `sum(column)`, `min(column)` and `max(column)`, with optional aliases.
+    ///
+    /// Returns:
+    ///   * Ok: `LogicalPlan::Resample` over the query of the table.
+    ///   * Error when:
+    ///     1. Interval is not `INTERVAL n unit` with positive integer `n` and unit `SECOND`,
+    ///        `MINUTE`, `HOUR`, `DAY` or `WEEK`: `UnsupportedCommand`.
+    ///     2. Query is not a `SELECT` of a single table, or has `WITH`, `DISTINCT`, `GROUP BY`,
+    ///        `HAVING`, `ORDER BY` or `LIMIT`: `UnsupportedCommand`.
+    ///     3. Table does not exist: `TableNotFound`, or has no `SAMPLE BY`: `UnsupportedCommand`.
+    ///     4. Projection has other expressions: `UnsupportedCommand`, or `sum` of non-integer
+    ///        column: `InvalidFunctionArguments`.
+    ///     5. Output column name is repeated: `DuplicateColumn`.
+    ///     6. Any error from `from_query` of the query.
+    pub(crate) fn from_resample(query: &Query, interval: &Expr) -> Result<Self> {
+        let interval = parse_interval_seconds(interval)?;
+
+        let SetExpr::Select(select) = &*query.body else {
+            return Err(Error::UnsupportedCommand(
+                "RESAMPLE BY is supported only in SELECT".to_string(),
+            ));
+        };
+        let no_group_by =
+            matches!(&select.group_by, GroupByExpr::Expressions(exprs, _) if exprs.is_empty());
+        if query.with.is_some()
+            || select.distinct.is_some()
+            || !no_group_by
+            || select.having.is_some()
+            || query.order_by.is_some()
+            || query.limit_clause.is_some()
+        {
+            return Err(Error::UnsupportedCommand(
+                "RESAMPLE BY is supported only without WITH, DISTINCT, GROUP BY, HAVING, \
+                 ORDER BY and LIMIT"
+                    .to_string(),
+            ));
+        }
+        let [table] = select.from.as_slice() else {
+            return Err(Error::UnsupportedCommand(
+                "RESAMPLE BY is supported only for a single table".to_string(),
+            ));
+        };
+        let TableFactor::Table { name, .. } = &table.relation else {
+            return Err(Error::UnsupportedCommand(
+                "RESAMPLE BY is supported only for a table".to_string(),
+            ));
+        };
+        if !table.joins.is_empty() {
+            return Err(Error::UnsupportedCommand(
+                "JOIN clauses are not currently supported".to_string(),
+            ));
+        }
+
+        let table_def = TableDef::try_from(name)?;
+        let (sample_by, table_columns) = {
+            let table_config = TABLE_DATA.get(&table_def).ok_or(Error::TableNotFound)?;
+            (
+                table_config.metadata.settings.sample_by.clone(),
+                table_config.metadata.schema.columns.clone(),
+            )
+        };
+        let Some(sample_by) = sample_by else {
+            return Err(Error::UnsupportedCommand(format!(
+                "RESAMPLE BY requires SAMPLE BY of the table {table_def}"
+            )));
+        };
+        let key_expr = parse_expr(&sample_by)?;
+
+        let mut columns = Vec::with_capacity(select.projection.len() + 1);
+        for item in &select.projection {
+            let (expr, alias) = match item {
+                SelectItem::UnnamedExpr(expr) => (expr, None),
+                SelectItem::ExprWithAlias { expr, alias } => (expr, Some(&alias.value)),
+                item => {
+                    return Err(Error::UnsupportedCommand(format!(
+                        "RESAMPLE BY does not support {item}"
+                    )));
+                }
+            };
+            let aggregate = if *expr == key_expr {
+                ResampleAggregate::Bucket
+            } else {
+                parse_aggregate(expr, &table_columns)?
+            };
+            let name = alias.cloned().unwrap_or_else(|| expr.to_string());
+            if columns
+                .iter()
+                .any(|column: &(String, ResampleAggregate)| column.0 == name)
+            {
+                return Err(Error::DuplicateColumn(name));
+            }
+            columns.push((name, aggregate));
+        }
+        if !columns
+            .iter()
+            .any(|(_, aggregate)| *aggregate == ResampleAggregate::Bucket)
+        {
+            let name = key_expr.to_string();
+            if columns.iter().any(|column| column.0 == name) {
+                return Err(Error::DuplicateColumn(name));
+            }
+            columns.insert(0, (name, ResampleAggregate::Bucket));
+        }
+
+        // query reads the `SAMPLE BY` expression and arguments of aggregates
+        let mut projection = vec![SelectItem::UnnamedExpr(key_expr.clone())];
+        for (_, aggregate) in &columns {
+            if let ResampleAggregate::Count(col_def)
+            | ResampleAggregate::Sum(col_def)
+            | ResampleAggregate::Min(col_def)
+            | ResampleAggregate::Max(col_def) = aggregate
+            {
+                let expr = Expr::Identifier(Ident::new(&col_def.name));
+                if expr != key_expr && !projection.contains(&SelectItem::UnnamedExpr(expr.clone()))
+                {
+                    projection.push(SelectItem::UnnamedExpr(expr));
+                }
+            }
+        }
+        let mut source = query.clone();
+        let SetExpr::Select(source_select) = &mut *source.body else {
+            unreachable!("Query body is checked above");
+        };
+        source_select.projection = projection;
+        let plan = Self::from_query(&source)?;
+        let key = Self::extract_columns_from_plan(&plan)?.swap_remove(0);
+
+        let columns = columns
+            .into_iter()
+            .map(|(name, aggregate)| {
+                let (field_type, nullable) = match &aggregate {
+                    ResampleAggregate::Bucket => (bucket_type(&key)?, false),
+                    ResampleAggregate::CountRows | ResampleAggregate::Count(_) => {
+                        (ValueType::UInt64, false)
+                    }
+                    ResampleAggregate::Sum(col_def) => {
+                        let Some(field_type) = pivot_sum_type(&col_def.field_type) else {
+                            return Err(Error::InvalidFunctionArguments(format!(
+                                "sum in RESAMPLE BY expects an integer column, {} is {}",
+                                col_def.name, col_def.field_type
+                            )));
+                        };
+                        (field_type, true)
+                    }
+                    ResampleAggregate::Min(col_def) | ResampleAggregate::Max(col_def) => {
+                        (col_def.field_type.clone(), true)
+                    }
+                };
+                Ok(ResampleColumn {
+                    column_def: ColumnDef {
+                        name,
+                        constraints: Constraints {
+                            nullable,
+                            compression_type: field_type.get_optimal_compression(),
+                            ..Constraints::default()
+                        },
+                        field_type,
+                    },
+                    aggregate,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self::Resample {
+            key,
+            interval,
+            columns,
+            plan: Box::new(plan),
+        })
+    }
+}
+
+/// Parses `INTERVAL n unit` into seconds.
+///
+/// Returns:
+///   * Ok: positive number of seconds.
+///   * Error: `UnsupportedCommand` when `n` is not a positive integer, or unit is not `SECOND`,
+///     `MINUTE`, `HOUR`, `DAY` or `WEEK`, or the interval overflows.
+fn parse_interval_seconds(interval: &Expr) -> Result<u64> {
+    let unsupported = || {
+        Error::UnsupportedCommand(format!(
+            "RESAMPLE BY expects INTERVAL n SECOND, MINUTE, HOUR, DAY or WEEK, received: {interval}"
+        ))
+    };
+    let Expr::Interval(Interval {
+        value,
+        leading_field: Some(unit),
+        leading_precision: None,
+        last_field: None,
+        fractional_seconds_precision: None,
+    }) = interval
+    else {
+        return Err(unsupported());
+    };
+    let unit_seconds = match unit {
+        DateTimeField::Second | DateTimeField::Seconds => 1,
+        DateTimeField::Minute | DateTimeField::Minutes => 60,
+        DateTimeField::Hour | DateTimeField::Hours => 3600,
+        DateTimeField::Day | DateTimeField::Days => 86_400,
+        DateTimeField::Week(None) | DateTimeField::Weeks => 604_800,
+        _ => return Err(unsupported()),
+    };
+    let Ok(Value::UInt64(count @ 1..)) = parse_value(value, &ValueType::UInt64) else {
+        return Err(unsupported());
+    };
+    count.checked_mul(unit_seconds).ok_or_else(unsupported)
+}
+
+/// Parses aggregate of the projection, see `LogicalPlan::from_resample`.
+///
+/// Returns:
+///   * Ok: aggregate of a column of the table.
+///   * Error: `UnsupportedCommand` when expression is not a supported aggregate, or
+///     `ColumnNotFound` when its column does not exist.
+fn parse_aggregate(expr: &Expr, columns: &[ColumnDef]) -> Result<ResampleAggregate> {
+    let unsupported = || {
+        Error::UnsupportedCommand(format!(
+            "RESAMPLE BY supports the SAMPLE BY expression and count, sum, min or max of a \
+             column, received: {expr}"
+        ))
+    };
+    let Expr::Function(function) = expr else {
+        return Err(unsupported());
+    };
+    if is_count_star(function) {
+        return Ok(ResampleAggregate::CountRows);
+    }
+    let modified = match &function.args {
+        FunctionArguments::List(list) => {
+            list.duplicate_treatment.is_some() || !list.clauses.is_empty()
+        }
+        _ => false,
+    };
+    if modified || function.over.is_some() || function.filter.is_some() {
+        return Err(unsupported());
+    }
+    let [Expr::Identifier(ident)] = function_args(function)?.as_slice() else {
+        return Err(unsupported());
+    };
+    let col_def = parse_ident(ident, columns)?;
+    match function.name.to_string().to_lowercase().as_str() {
+        "count" => Ok(ResampleAggregate::Count(col_def)),
+        "sum" => Ok(ResampleAggregate::Sum(col_def)),
+        "min" => Ok(ResampleAggregate::Min(col_def)),
+        "max" => Ok(ResampleAggregate::Max(col_def)),
+        _ => Err(unsupported()),
+    }
+}
+
+/// Returns type of bucket starts: `Int64` for signed integer keys, `UInt64` for unsigned ones,
+/// and the type of the key for `Date` and `DateTime` keys.
+///
+/// Returns:
+///   * Ok: the type.
+///   * Error: `InvalidSource` when key is not an integer, `Date` or `DateTime`.
+fn bucket_type(key: &ColumnDef) -> Result<ValueType> {
+    if matches!(key.field_type, ValueType::Date | ValueType::DateTime) {
+        return Ok(key.field_type.clone());
+    }
+    pivot_sum_type(&key.field_type).ok_or_else(|| {
+        Error::InvalidSource(format!(
+            "SAMPLE BY {} must be an integer, Date or DateTime, it is {}",
+            key.name, key.field_type
+        ))
+    })
+}
@@ -1,66 +1,88 @@
 use sqlparser::ast::{
-    Expr, LimitClause, OrderByKind, Query, SelectItem, SetExpr, TableFactor, Value as SQLValue,
+    Expr, Function, FunctionArg, FunctionArgExpr, FunctionArguments, GroupByExpr, LimitClause,
+    OrderByKind, Query, SelectItem, SetExpr, Setting, TableFactor, Value as SQLValue, WindowType,
 };
 
 use crate::error::{Error, Result};
 use crate::runtime_config::TABLE_DATA;
-use crate::sql::parse_ident;
-use crate::sql::sql_parser::{LogicalPlan, ScanSource};
-use crate::storage::{ColumnDef, TableDef};
+use crate::sql::compiled_expr::CompiledExpr;
+use crate::sql::constant_folding::fold_constants;
+use crate::sql::logical_plan::cte::CteScope;
+use crate::sql::logical_plan::flatten::{flatten_columns, is_flatten};
+use crate::sql::logical_plan::pivot::pivot_columns;
+use crate::sql::sql_parser::{ComputedColumn, LogicalPlan, QuerySettings, RowNumber, ScanSource};
+use crate::sql::system_table::SystemTable;
+use crate::sql::{function_args, parse_ident, parse_value};
+use crate::storage::{ColumnDef, Constraints, TableDef, Value, ValueType};
 
 impl LogicalPlan {
     /// Parses SELECT query into a logical plan tree.
     ///
-    /// Builds a tree of `LogicalPlan` nodes: Scan -> Filter -> Projection -> OrderBy -> Limit.
+    /// Builds a tree of `LogicalPlan` nodes: Scan -> Filter -> Projection -> OrderBy -> Limit -> RowNumber -> Settings.
+    /// Operations on literals in `WHERE` are evaluated beforehand, see `fold_constants`.
     ///
     /// Returns:
     ///   * Ok when:
     ///     1. Query has single FROM table/subquery, valid projections, optional WHERE/ORDER BY/LIMIT: `LogicalPlan` tree
     ///   * Error when:
     ///     1. Query is not a SELECT statement: `UnsupportedCommand`.
-    ///     2. Multiple tables in FROM clause: `UnsupportedCommand`.
+    ///     2. Multiple tables in FROM clause, other than a table and `FLATTEN` (see
+    ///        `from_flatten`): `UnsupportedCommand`.
     ///     3. JOIN clause present: `UnsupportedCommand`.
     ///     4. Empty projection: `UnsupportedCommand`.
     ///     5. Multiple wildcards or columns after wildcard: `UnsupportedCommand`.
-    ///     6. Non-identifier expressions in projection: `UnsupportedCommand`.
+    ///     6. Unsupported expressions in projection: `UnsupportedCommand` or `InvalidTupleAccess`.
     ///     7. Duplicate column in projection: `DuplicateColumn`.
     ///     8. Column not found in table: `ColumnNotFound`.
     ///     9. Invalid LIMIT/OFFSET value: `InvalidLimitValue`.
+    ///     10. Invalid or repeated `row_number()`, or `row_number()` in subquery: `UnsupportedCommand`.
+    ///     11. Invalid `ORDER BY` expression: any error from `CompiledExpr::compile`.
+    ///     12. Unknown setting or invalid value in `SETTINGS`: `UnsupportedCommand`.
+    ///     13. Invalid `WITH` clause: see `CteScope::try_new` and `CteScope::resolve`.
     pub fn from_query(query: &Query) -> Result<Self> {
+        Self::from_query_in(query, None)
+    }
+
+    /// Parses SELECT query, which may read CTEs of enclosing queries (see `from_query`).
+    /// CTE is planned as a subquery at every place, which reads it.
+    fn from_query_in(query: &Query, outer_ctes: Option<&CteScope>) -> Result<Self> {
+        let ctes = CteScope::try_new(query.with.as_ref(), outer_ctes)?;
         let SetExpr::Select(select) = &*query.body else {
             return Err(Error::UnsupportedCommand(
                 "Only SELECT queries are supported".to_string(),
             ));
         };
 
-        if select.from.len() != 1 {
-            return Err(Error::UnsupportedCommand(
-                "Currently do not support multiple table selects".to_string(),
-            ));
-        }
-        let table = &select.from[0];
+        let (table, flatten) = match select.from.as_slice() {
+            [table] => (table, None),
+            [table, flatten] if is_flatten(&flatten.relation) => (table, Some(flatten)),
+            _ => {
+                return Err(Error::UnsupportedCommand(
+                    "Currently do not support multiple table selects".to_string(),
+                ));
+            }
+        };
 
-        if !table.joins.is_empty() {
+        if !table.joins.is_empty() || flatten.is_some_and(|flatten| !flatten.joins.is_empty()) {
             return Err(Error::UnsupportedCommand(
                 "JOIN clauses are not currently supported".to_string(),
             ));
         }
-        let scan_source = match &table.relation {
-            TableFactor::Table { name, .. } => {
-                let table_def = TableDef::try_from(name)?;
-                ScanSource::Table(table_def)
-            }
-            TableFactor::Derived { subquery, .. } => {
-                let subquery_plan = Self::from_query(subquery)?;
-                ScanSource::Subquery(Box::new(subquery_plan))
-            }
-            _ => {
-                return Err(Error::UnsupportedCommand(
-                    "Only simple table references and subqueries are supported".to_string(),
-                ));
-            }
+        let scan_source = match flatten {
+            Some(flatten) => ScanSource::Subquery(Box::new(Self::from_flatten(
+                &table.relation,
+                &flatten.relation,
+                &ctes,
+            )?)),
+            None => Self::parse_table_factor(&table.relation, &ctes)?,
         };
 
+        if let [SelectItem::UnnamedExpr(Expr::Function(function))] = select.projection.as_slice()
+            && is_count_star(function)
+        {
+            return Self::from_count_star(query, scan_source);
+        }
+
         if select.projection.is_empty() {
             return Err(Error::UnsupportedCommand(
                 "No projection specified.".to_string(),
@@ -72,6 +94,8 @@ impl LogicalPlan {
         };
 
         let mut read_columns = Vec::with_capacity(select.projection.len());
+        let mut computed = Vec::new();
+        let mut row_number = None;
 
         let available_columns = Self::extract_columns_from_plan(&plan)?;
 
@@ -96,15 +120,34 @@ impl LogicalPlan {
                             "Columns after wildcard are not supported".to_string(),
                         ));
                     }
-                    let Expr::Identifier(ident) = expr else {
-                        return Err(Error::UnsupportedCommand(
-                            "Only column identifiers are supported in projections".to_string(),
-                        ));
+                    if let Expr::Function(function) = expr
+                        && function.over.is_some()
+                    {
+                        if row_number.is_some() {
+                            return Err(Error::UnsupportedCommand(
+                                "Only one row_number() column is supported".to_string(),
+                            ));
+                        }
+                        row_number = Some(Self::parse_row_number(
+                            function,
+                            &available_columns,
+                            read_columns.len(),
+                        )?);
+                        continue;
+                    }
+
+                    let column_def = if let Expr::Identifier(ident) = expr {
+                        parse_ident(ident, &available_columns)?
+                    } else {
+                        let computed_column =
+                            Self::parse_computed_column(expr, &available_columns)?;
+                        let column_def = computed_column.column_def.clone();
+                        computed.push(computed_column);
+                        column_def
                     };
 
-                    let column_def = parse_ident(ident, &available_columns)?;
                     if read_columns.contains(&column_def) {
-                        return Err(Error::DuplicateColumn(ident.value.clone()));
+                        return Err(Error::DuplicateColumn(column_def.name));
                     }
                     read_columns.push(column_def);
                 }
@@ -128,15 +171,22 @@ impl LogicalPlan {
             }
         }
 
+        if row_number.is_some() && read_columns.is_empty() {
+            return Err(Error::UnsupportedCommand(
+                "row_number() requires at least one other column".to_string(),
+            ));
+        }
+
         if let Some(ref selection) = select.selection {
             plan = LogicalPlan::Filter {
-                expr: Box::new(selection.clone()),
+                expr: Box::new(fold_constants(selection.clone())),
                 plan: Box::new(plan),
             };
         }
 
         plan = LogicalPlan::Projection {
             columns: read_columns.clone(),
+            computed,
             plan: Box::new(plan),
         };
 
@@ -145,19 +195,26 @@ impl LogicalPlan {
                 OrderByKind::All(_params) => {
                     plan = LogicalPlan::OrderBy {
                         column_defs: vec![read_columns], // todo save as Cow<> of projection maybe, or even indexes?
+                        computed: Vec::new(),
                         plan: Box::new(plan),
                     };
                 }
                 OrderByKind::Expressions(order_by_given) => {
+                    // `ORDER BY a, b` and `ORDER BY (a, b)` both sort lexicographically by (a, b)
                     let mut order_by_all = Vec::with_capacity(order_by_given.len());
+                    let mut computed_keys = Vec::new();
                     for order_by_expr in order_by_given {
-                        let order_by_cols =
-                            Self::parse_primary_key(&order_by_expr.expr, &available_columns)?; // OrderBy cols is interpreted in the same way as PK in `CREATE TABLE`
-                        order_by_all.push(order_by_cols);
+                        Self::parse_order_by_key(
+                            &order_by_expr.expr,
+                            &available_columns,
+                            &mut order_by_all,
+                            &mut computed_keys,
+                        )?;
                     }
 
                     plan = LogicalPlan::OrderBy {
-                        column_defs: order_by_all,
+                        column_defs: vec![order_by_all],
+                        computed: computed_keys,
                         plan: Box::new(plan),
                     };
                 }
@@ -165,63 +222,332 @@ impl LogicalPlan {
         }
 
         if let Some(limit_clause) = &query.limit_clause {
-            let LimitClause::LimitOffset {
-                limit: limit_expr,
-                offset: offset_expr,
-                ..
-            } = limit_clause
-            else {
-                return Err(Error::InvalidLimitValue(
-                    "Only LIMIT OFFSET clause is supported".to_string(),
-                ));
+            let (limit, offset) = Self::parse_limit_clause(limit_clause)?;
+            plan = LogicalPlan::Limit {
+                limit,
+                offset,
+                plan: Box::new(plan),
             };
+        }
 
-            let mut limit = None;
-            let mut offset = 0;
+        if let Some(RowNumber {
+            alias,
+            order_by,
+            position,
+        }) = row_number
+        {
+            plan = LogicalPlan::RowNumber {
+                alias,
+                order_by,
+                position,
+                plan: Box::new(plan),
+            };
+        }
 
-            if let Some(limit_expr) = limit_expr {
-                let Expr::Value(limit_expr) = &limit_expr else {
-                    return Err(Error::InvalidLimitValue(
-                        "LIMIT must be a literal value".to_string(),
-                    ));
-                };
-                let SQLValue::Number(limit_expr, _) = &limit_expr.value else {
-                    return Err(Error::InvalidLimitValue(
-                        "LIMIT must be a number".to_string(),
-                    ));
-                };
-                limit = Some(
-                    limit_expr
-                        .parse()
-                        .map_err(|_| Error::InvalidLimitValue(limit_expr.clone()))?,
-                );
+        if let Some(settings) = &query.settings {
+            plan = LogicalPlan::Settings {
+                settings: Self::parse_query_settings(settings)?,
+                plan: Box::new(plan),
+            };
+        }
+
+        Ok(plan)
+    }
+
+    /// Parses `SELECT count(*) FROM table`, which is answered from part infos without reading
+    /// columns, see `LogicalPlan::CountStar`.
+    ///
+    /// Returns:
+    ///   * Ok: `LogicalPlan::CountStar`.
+    ///   * Error: `UnsupportedCommand` when source is not a table, is a system table, or query
+    ///     has any other clause, e.g., `WHERE` or `LIMIT`.
+    fn from_count_star(query: &Query, scan_source: ScanSource) -> Result<Self> {
+        let SetExpr::Select(select) = &*query.body else {
+            unreachable!("count(*) is parsed from SELECT projection")
+        };
+        let ScanSource::Table(table_def) = scan_source else {
+            return Err(Error::UnsupportedCommand(
+                "count(*) is supported only for tables".to_string(),
+            ));
+        };
+        if SystemTable::from_table_def(&table_def)?.is_some() {
+            return Err(Error::UnsupportedCommand(format!(
+                "count(*) of system table {table_def}"
+            )));
+        }
+        let no_group_by =
+            matches!(&select.group_by, GroupByExpr::Expressions(exprs, _) if exprs.is_empty());
+        if select.selection.is_some()
+            || !no_group_by
+            || select.having.is_some()
+            || query.order_by.is_some()
+            || query.limit_clause.is_some()
+            || query.settings.is_some()
+        {
+            return Err(Error::UnsupportedCommand(
+                "count(*) is supported only without WHERE, GROUP BY, ORDER BY, LIMIT and SETTINGS"
+                    .to_string(),
+            ));
+        }
+
+        Ok(Self::CountStar { table_def })
+    }
+
+    /// Parses `LIMIT n`, `OFFSET m`, `LIMIT n OFFSET m` (in any order) and ClickHouse
+    /// `LIMIT m, n` into the same limit and offset.
+    ///
+    /// Returns:
+    ///   * Ok: `(limit, offset)`, limit is `None` for `OFFSET m` and `LIMIT ALL OFFSET m`, offset
+    ///     defaults to 0.
+    ///   * Error: `InvalidLimitValue` when a value is not a non-negative integer literal, or for
+    ///     `LIMIT n BY ...`.
+    fn parse_limit_clause(limit_clause: &LimitClause) -> Result<(Option<u64>, u64)> {
+        let parse_number = |expr: &Expr, clause: &str| -> Result<u64> {
+            let Expr::Value(value) = expr else {
+                return Err(Error::InvalidLimitValue(format!(
+                    "{clause} must be a literal value"
+                )));
+            };
+            let SQLValue::Number(number, _) = &value.value else {
+                return Err(Error::InvalidLimitValue(format!(
+                    "{clause} must be a number"
+                )));
+            };
+            number
+                .parse()
+                .map_err(|_| Error::InvalidLimitValue(number.clone()))
+        };
+
+        match limit_clause {
+            LimitClause::LimitOffset { limit_by, .. } if !limit_by.is_empty() => Err(
+                Error::InvalidLimitValue("LIMIT BY is not supported".to_string()),
+            ),
+            LimitClause::LimitOffset { limit, offset, .. } => Ok((
+                limit
+                    .as_ref()
+                    .map(|limit| parse_number(limit, "LIMIT"))
+                    .transpose()?,
+                offset
+                    .as_ref()
+                    .map_or(Ok(0), |offset| parse_number(&offset.value, "OFFSET"))?,
+            )),
+            LimitClause::OffsetCommaLimit { offset, limit } => Ok((
+                Some(parse_number(limit, "LIMIT")?),
+                parse_number(offset, "OFFSET")?,
+            )),
+        }
+    }
+
+    /// Parses `SETTINGS name = value, ...` of `SELECT`.
+    ///
+    /// Returns:
+    ///   * Ok: `QuerySettings` with given settings.
+    ///   * Error: `UnsupportedCommand` when setting is unknown or its value has wrong type.
+    fn parse_query_settings(settings: &[Setting]) -> Result<QuerySettings> {
+        let mut query_settings = QuerySettings::default();
+        for Setting { key, value } in settings {
+            match key.value.to_lowercase().as_str() {
+                "scan_chunk_granules" => {
+                    let Ok(Value::UInt32(granules @ 1..)) = parse_value(value, &ValueType::UInt32)
+                    else {
+                        return Err(Error::UnsupportedCommand(format!(
+                            "scan_chunk_granules expects positive UInt32: {value}"
+                        )));
+                    };
+                    query_settings.scan_chunk_granules = Some(granules as usize);
+                }
+                "skip_unreadable_parts" => {
+                    query_settings.skip_unreadable_parts =
+                        match parse_value(value, &ValueType::Bool) {
+                            Ok(Value::Bool(enabled)) => enabled,
+                            _ => match parse_value(value, &ValueType::UInt8) {
+                                Ok(Value::UInt8(enabled @ (0 | 1))) => enabled == 1,
+                                _ => {
+                                    return Err(Error::UnsupportedCommand(format!(
+                                        "skip_unreadable_parts expects 0, 1, true or false: \
+                                         {value}"
+                                    )));
+                                }
+                            },
+                        };
+                }
+                name => {
+                    return Err(Error::UnsupportedCommand(format!("Unknown setting {name}")));
+                }
             }
+        }
+        Ok(query_settings)
+    }
 
-            if let Some(offset_expr) = offset_expr {
-                let Expr::Value(offset_expr) = &offset_expr.value else {
-                    return Err(Error::InvalidLimitValue(
-                        "OFFSET must be a literal value".to_string(),
-                    ));
-                };
-                let SQLValue::Number(offset_expr, _) = &offset_expr.value else {
-                    return Err(Error::InvalidLimitValue(
-                        "OFFSET must be a number".to_string(),
-                    ));
-                };
+    /// Parses source of the query: table, CTE, subquery or `PIVOT` of either.
+    ///
+    /// Returns:
+    ///   * Ok: `ScanSource`, CTE and `PIVOT` are `Subquery` sources.
+    ///   * Error when:
+    ///     1. Table name is invalid: see `TableDef::try_from`.
+    ///     2. Subquery or CTE has `row_number()` or `SETTINGS`: `UnsupportedCommand`.
+    ///     3. Other source, e.g., table function: `UnsupportedCommand`.
+    ///     4. Any error from `from_query`, `from_pivot` or `CteScope::resolve`.
+    pub(super) fn parse_table_factor(
+        relation: &TableFactor,
+        ctes: &CteScope,
+    ) -> Result<ScanSource> {
+        match relation {
+            TableFactor::Table { name, .. } => match ctes.resolve(name)? {
+                Some((cte_query, cte_scope)) => Self::parse_subquery(cte_query, &cte_scope),
+                None => Ok(ScanSource::Table(TableDef::try_from(name)?)),
+            },
+            TableFactor::Derived { subquery, .. } => Self::parse_subquery(subquery, ctes),
+            TableFactor::Pivot {
+                table,
+                aggregate_functions,
+                value_column,
+                value_source,
+                default_on_null,
+                ..
+            } => Ok(ScanSource::Subquery(Box::new(Self::from_pivot(
+                table,
+                aggregate_functions,
+                value_column,
+                value_source,
+                default_on_null.as_ref(),
+                ctes,
+            )?))),
+            _ => Err(Error::UnsupportedCommand(
+                "Only simple table references, subqueries and PIVOT are supported".to_string(),
+            )),
+        }
+    }
 
-                offset = offset_expr
-                    .parse()
-                    .map_err(|_| Error::InvalidLimitValue(offset_expr.clone()))?;
+    /// Parses subquery of FROM clause or of a CTE.
+    ///
+    /// Returns:
+    ///   * Ok: `ScanSource::Subquery`.
+    ///   * Error: `UnsupportedCommand` when subquery has `row_number()`, `SETTINGS` or is
+    ///     `count(*)`, or any error from `from_query`.
+    fn parse_subquery(subquery: &Query, ctes: &CteScope) -> Result<ScanSource> {
+        let subquery_plan = Self::from_query_in(subquery, Some(ctes))?;
+        match subquery_plan {
+            LogicalPlan::RowNumber { .. } => {
+                return Err(Error::UnsupportedCommand(
+                    "row_number() is supported only in the outermost query".to_string(),
+                ));
             }
+            LogicalPlan::Settings { .. } => {
+                return Err(Error::UnsupportedCommand(
+                    "SETTINGS is supported only in the outermost query".to_string(),
+                ));
+            }
+            LogicalPlan::CountStar { .. } => {
+                return Err(Error::UnsupportedCommand(
+                    "count(*) is supported only in the outermost query".to_string(),
+                ));
+            }
+            _ => {}
+        }
+        Ok(ScanSource::Subquery(Box::new(subquery_plan)))
+    }
 
-            plan = LogicalPlan::Limit {
-                limit,
-                offset,
-                plan: Box::new(plan),
-            };
+    /// Parses `row_number() OVER ()` and `row_number() OVER (ORDER BY ...)` window functions.
+    ///
+    /// Output column is named after the expression and placed at `position` in the result.
+    ///
+    /// Returns:
+    ///   * Ok: `RowNumber` with columns to sort by before numbering.
+    ///   * Error when:
+    ///     1. Window function is not `row_number` or has arguments: `UnsupportedFunction`.
+    ///     2. Window has name, `PARTITION BY` or frame: `UnsupportedCommand`.
+    ///     3. `ORDER BY` is not a column or tuple of columns: `InvalidPrimaryKey`.
+    fn parse_row_number(
+        function: &Function,
+        columns: &[ColumnDef],
+        position: usize,
+    ) -> Result<RowNumber> {
+        if !function.name.to_string().eq_ignore_ascii_case("row_number")
+            || !function_args(function)?.is_empty()
+        {
+            return Err(Error::UnsupportedFunction(function.to_string()));
+        }
+        let Some(WindowType::WindowSpec(window)) = &function.over else {
+            return Err(Error::UnsupportedCommand(format!(
+                "Named windows are not supported: {function}"
+            )));
+        };
+        if window.window_name.is_some()
+            || !window.partition_by.is_empty()
+            || window.window_frame.is_some()
+        {
+            return Err(Error::UnsupportedCommand(format!(
+                "Only ORDER BY is supported in OVER clause: {function}"
+            )));
         }
 
-        Ok(plan)
+        let mut order_by = Vec::with_capacity(window.order_by.len());
+        for order_by_expr in &window.order_by {
+            order_by.extend(Self::parse_primary_key(&order_by_expr.expr, columns)?);
+        }
+
+        Ok(RowNumber {
+            alias: function.to_string(),
+            order_by,
+            position,
+        })
+    }
+
+    /// Parses `ORDER BY` key into `order_by`. Columns and tuples are sorted by as they are, like
+    /// primary key in `CREATE TABLE`. Any other expression, e.g., `length(name)` or `price * qty`,
+    /// is added to `computed` and sorted by its transient column, named after the expression.
+    ///
+    /// Returns:
+    ///   * Ok: keys are appended to `order_by`.
+    ///   * Error: `ColumnNotFound` or any error from `parse_computed_column`.
+    fn parse_order_by_key(
+        expr: &Expr,
+        columns: &[ColumnDef],
+        order_by: &mut Vec<ColumnDef>,
+        computed: &mut Vec<ComputedColumn>,
+    ) -> Result<()> {
+        match expr {
+            Expr::Identifier(ident) => order_by.push(parse_ident(ident, columns)?),
+            Expr::Tuple(keys) => {
+                for key in keys {
+                    Self::parse_order_by_key(key, columns, order_by, computed)?;
+                }
+            }
+            // single element tuple or parenthesized expression
+            Expr::Nested(key) => Self::parse_order_by_key(key, columns, order_by, computed)?,
+            expr => {
+                let computed_column = Self::parse_computed_column(expr, columns)?;
+                order_by.push(computed_column.column_def.clone());
+                if !computed.contains(&computed_column) {
+                    computed.push(computed_column);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses projection expression, which is not a plain column, e.g., tuple element `col.1`.
+    ///
+    /// Output column is named after the expression.
+    ///
+    /// Returns:
+    ///   * Ok: `ComputedColumn` with the type of expression result.
+    ///   * Error: any error from `CompiledExpr::compile`.
+    fn parse_computed_column(expr: &Expr, columns: &[ColumnDef]) -> Result<ComputedColumn> {
+        let field_type = CompiledExpr::compile(expr, columns)?.get_type(columns);
+
+        Ok(ComputedColumn {
+            column_def: ColumnDef {
+                name: expr.to_string(),
+                constraints: Constraints {
+                    compression_type: field_type.get_optimal_compression(),
+                    ..Constraints::default()
+                },
+                field_type,
+            },
+            expr: expr.clone(),
+        })
     }
 
     /// Extracts column definitions from a logical plan.
@@ -231,20 +557,27 @@ impl LogicalPlan {
     /// Returns:
     ///   * Ok when:
     ///     1. Plan is Projection: columns from projection.
-    ///     2. Plan is Filter/OrderBy/Limit: columns from inner plan.
-    ///     3. Plan is Scan with Table: columns from table metadata.
+    ///     2. Plan is Filter/OrderBy/Limit/RowNumber/Settings: columns from inner plan.
+    ///     3. Plan is Scan with Table: columns from table metadata, or of the system table.
     ///     4. Plan is Scan with Subquery: columns from subquery plan.
+    ///     5. Plan is Pivot: grouped columns and a column per pivoted value.
+    ///     6. Plan is Flatten: columns of its source, `index` and `value`.
     ///   * Error when:
     ///     1. Table not found in runtime config: `TableNotFound`.
     ///     2. Unsupported plan type: `UnsupportedCommand`.
-    fn extract_columns_from_plan(plan: &LogicalPlan) -> Result<Vec<ColumnDef>> {
+    pub(super) fn extract_columns_from_plan(plan: &LogicalPlan) -> Result<Vec<ColumnDef>> {
         match plan {
             LogicalPlan::Projection { columns, .. } => Ok(columns.clone()),
             LogicalPlan::Filter { plan, .. }
             | LogicalPlan::OrderBy { plan, .. }
-            | LogicalPlan::Limit { plan, .. } => Self::extract_columns_from_plan(plan),
+            | LogicalPlan::Limit { plan, .. }
+            | LogicalPlan::RowNumber { plan, .. }
+            | LogicalPlan::Settings { plan, .. } => Self::extract_columns_from_plan(plan),
             LogicalPlan::Scan { source } => match source {
                 ScanSource::Table(table_def) => {
+                    if let Some(system_table) = SystemTable::from_table_def(table_def)? {
+                        return Ok(system_table.column_defs());
+                    }
                     let Some(table_config) = TABLE_DATA.get(table_def) else {
                         return Err(Error::TableNotFound);
                     };
@@ -254,9 +587,39 @@ impl LogicalPlan {
                     Self::extract_columns_from_plan(subquery_plan)
                 }
             },
+            LogicalPlan::Pivot {
+                group_by,
+                value_col,
+                pivot_values,
+                ..
+            } => Ok(pivot_columns(group_by, value_col, pivot_values)),
+            LogicalPlan::Flatten { array_col, plan } => Ok(flatten_columns(
+                &Self::extract_columns_from_plan(plan)?,
+                array_col,
+            )),
             _ => Err(Error::UnsupportedCommand(
                 "Cannot extract columns from this plan type".to_string(),
             )),
         }
     }
 }
+
+/// Whether the function is `count(*)` or `count()` (any case), counting all rows.
+pub(super) fn is_count_star(function: &Function) -> bool {
+    let no_args = match &function.args {
+        FunctionArguments::None => true,
+        FunctionArguments::List(list) => {
+            list.duplicate_treatment.is_none()
+                && list.clauses.is_empty()
+                && matches!(
+                    list.args.as_slice(),
+                    [] | [FunctionArg::Unnamed(FunctionArgExpr::Wildcard)]
+                )
+        }
+        FunctionArguments::Subquery(_) => false,
+    };
+    function.name.to_string().eq_ignore_ascii_case("count")
+        && no_args
+        && function.over.is_none()
+        && function.filter.is_none()
+}
@@ -0,0 +1,68 @@
+use crate::error::{Error, Result};
+use crate::runtime_config::TABLE_DATA;
+use crate::sql::sql_parser::LogicalPlan;
+use crate::storage::TableDef;
+use sqlparser::ast::{Ident, ObjectName};
+
+impl LogicalPlan {
+    /// Parses `SHOW CREATE TABLE db.table`.
+    ///
+    /// Returns:
+    ///   * Ok: `LogicalPlan::ShowCreateTable`.
+    ///   * Error: table name is invalid, see `TableDef::try_from`.
+    pub fn from_show_create_table(name: &ObjectName) -> Result<Self> {
+        Ok(Self::ShowCreateTable {
+            name: TableDef::try_from(name)?,
+        })
+    }
+
+    /// Parses `SYSTEM DISK USAGE [TABLE db.table]` (see `parse_system_disk_usage`).
+    ///
+    /// Returns:
+    ///   * Ok: `LogicalPlan::DiskUsage`.
+    ///   * Error: table name is invalid (see `TableDef::try_from`), or `TableNotFound`.
+    pub fn from_disk_usage(table: Option<&ObjectName>) -> Result<Self> {
+        let table = table.map(TableDef::try_from).transpose()?;
+        if let Some(table_def) = &table
+            && !TABLE_DATA.contains_key(table_def)
+        {
+            return Err(Error::TableNotFound);
+        }
+        Ok(Self::DiskUsage { table })
+    }
+
+    /// Parses `SYSTEM PART INFO db.table 'part_name'` (see `parse_system_command`). Part is
+    /// looked up on execution, as it may be merged away meanwhile.
+    ///
+    /// Returns:
+    ///   * Ok: `LogicalPlan::SystemPartInfo`.
+    ///   * Error: table name is invalid (see `TableDef::try_from`), or `TableNotFound`.
+    pub fn from_system_part_info(table: &ObjectName, part_name: String) -> Result<Self> {
+        let table_def = TableDef::try_from(table)?;
+        if !TABLE_DATA.contains_key(&table_def) {
+            return Err(Error::TableNotFound);
+        }
+        Ok(Self::SystemPartInfo {
+            table_def,
+            part_name,
+        })
+    }
+
+    /// Parses `SHOW ENGINES`, which `sqlparser` reads as `SHOW` of a variable.
+    ///
+    /// Returns:
+    ///   * Ok: `LogicalPlan::ShowEngines`.
+    ///   * Error: `UnsupportedCommand` for any other `SHOW`.
+    pub fn from_show_variable(variable: &[Ident]) -> Result<Self> {
+        match variable {
+            [ident] if ident.value.eq_ignore_ascii_case("ENGINES") => Ok(Self::ShowEngines),
+            _ => {
+                let variable: Vec<_> = variable.iter().map(ToString::to_string).collect();
+                Err(Error::UnsupportedCommand(format!(
+                    "SHOW {}",
+                    variable.join(" ")
+                )))
+            }
+        }
+    }
+}
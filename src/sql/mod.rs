@@ -1,27 +1,46 @@
+mod city_hash;
 mod command_runner;
+mod compiled_expr;
 mod compiled_filter;
+mod constant_folding;
 mod execution;
 mod logical_plan;
 mod plan_optimization;
+mod scalar_function;
 mod sql_parser;
+mod system_table;
 
 pub use command_runner::CommandRunner;
+pub use compiled_filter::CompiledFilter;
 
 use crate::error::{Error, Result};
-use crate::storage::ColumnDef;
+use crate::sql::compiled_expr::CompiledExpr;
+use crate::storage::{ColumnDef, DefaultExpr, Value, ValueType};
 
-use sqlparser::ast::Ident;
+use sqlparser::ast::{
+    Expr, Function, FunctionArg, FunctionArgExpr, FunctionArguments, Ident, UnaryOperator,
+    Value as SQLValue,
+};
 
-/// Validates the name of fields, databases, columns.
+/// Maximum length of a name in bytes. Names are used as directory and file names (with `.bin`).
+const MAX_NAME_LEN: usize = 200;
+
+/// Validates the name of fields, databases, columns. Names are case-sensitive and stored
+/// exactly as written, so quoted identifiers (e.g., `"My Column"`) may also contain spaces,
+/// hyphens and non-ASCII letters.
 ///
 /// Returns:
-///   * `true` when: name is non-empty and consists only of ASCII alphanumeric characters or underscore.
-///   * `false` when: name is empty or contains invalid characters.
+///   * `true` when: name is non-empty, at most `MAX_NAME_LEN` bytes, consists only of
+///     alphanumeric characters, underscore, hyphen or space, and does not start or end with space.
+///   * `false` when: name is empty, too long or contains invalid characters.
 pub fn validate_name(name: &str) -> bool {
     !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && !name.starts_with(' ')
+        && !name.ends_with(' ')
         && name
             .chars()
-            .all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+            .all(|ch| ch.is_alphanumeric() || matches!(ch, '_' | '-' | ' '))
 }
 
 /// Parses an identifier and finds matching column definition.
@@ -40,9 +59,206 @@ pub fn parse_ident(ident: &Ident, columns: &[ColumnDef]) -> Result<ColumnDef> {
     }
 }
 
+/// Parses literal value of the specified type, e.g., inserted value.
+///
+/// Supports literals, signed numbers, tuples (`(1, 'a')`), arrays (`[1, 2]`) and maps (`map('a', 1)`).
+///
+/// Returns:
+///   * Ok: parsed `Value`.
+///   * Error: `InvalidSource` when expression is not a value or does not match the type,
+///     or error from `Value::try_from`.
+pub fn parse_value(expr: &Expr, value_type: &ValueType) -> Result<Value> {
+    let sql_value = match expr {
+        Expr::Value(sql_value) => sql_value.value.clone(),
+        Expr::UnaryOp { op, expr } => {
+            let Expr::Value(inner) = expr.as_ref() else {
+                return Err(Error::InvalidSource(format!(
+                    "Expected direct value, received: {expr}"
+                )));
+            };
+            match (&op, &inner.value) {
+                (UnaryOperator::Minus, SQLValue::Number(n, exact)) => {
+                    SQLValue::Number(format!("-{n}"), *exact)
+                }
+                (UnaryOperator::Plus, SQLValue::Number(n, exact)) => {
+                    SQLValue::Number(n.clone(), *exact)
+                }
+                _ => {
+                    return Err(Error::InvalidSource(format!(
+                        "Expected plus or minus as operator and a number, received: {} and {}",
+                        op, inner.value
+                    )));
+                }
+            }
+        }
+        Expr::Tuple(exprs) => {
+            let ValueType::Tuple(field_types) = value_type else {
+                return Err(Error::InvalidSource(format!(
+                    "Could not convert tuple {expr} to {value_type:?}"
+                )));
+            };
+            return parse_tuple(exprs, field_types);
+        }
+        // `sqlparser` parses single element tuple as `Expr::Nested`
+        Expr::Nested(inner) if let ValueType::Tuple(field_types) = value_type => {
+            return parse_tuple(std::slice::from_ref(inner.as_ref()), field_types);
+        }
+        Expr::Array(array) => {
+            let ValueType::Array(elem_type) = value_type else {
+                return Err(Error::InvalidSource(format!(
+                    "Could not convert array {expr} to {value_type:?}"
+                )));
+            };
+            return array
+                .elem
+                .iter()
+                .map(|elem| parse_value(elem, elem_type))
+                .collect::<Result<Vec<_>>>()
+                .map(Value::Array);
+        }
+        Expr::Function(function) if function.name.to_string().eq_ignore_ascii_case("map") => {
+            let ValueType::Map(key_type, elem_type) = value_type else {
+                return Err(Error::InvalidSource(format!(
+                    "Could not convert map {expr} to {value_type:?}"
+                )));
+            };
+            let args = function_args(function)?;
+            if args.len() % 2 != 0 {
+                return Err(Error::InvalidSource(format!(
+                    "Map expects even number of arguments (key, value, ...), received: {expr}"
+                )));
+            }
+            let entries = args
+                .chunks(2)
+                .map(|pair| {
+                    Ok((
+                        parse_value(pair[0], key_type)?,
+                        parse_value(pair[1], elem_type)?,
+                    ))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            return Value::new_map(entries);
+        }
+        _ => {
+            return Err(Error::InvalidSource(format!(
+                "Expected a value, received: {expr}"
+            )));
+        }
+    };
+
+    Value::try_from((sql_value, value_type))
+}
+
+fn parse_tuple(exprs: &[Expr], field_types: &[ValueType]) -> Result<Value> {
+    if exprs.len() != field_types.len() {
+        return Err(Error::InvalidSource(format!(
+            "Invalid number of tuple elements. Expected: {}, got: {}",
+            field_types.len(),
+            exprs.len()
+        )));
+    }
+
+    exprs
+        .iter()
+        .zip(field_types)
+        .map(|(expr, field_type)| parse_value(expr, field_type))
+        .collect::<Result<Vec<_>>>()
+        .map(Value::Tuple)
+}
+
+/// Evaluates default value of the column for `row_count` rows. Expression defaults
+/// (e.g., `now()`) are evaluated for each row separately.
+///
+/// Returns:
+///   * Ok: `row_count` default values.
+///   * Error: expression could not be parsed, compiled or evaluated.
+pub fn eval_default(default: &DefaultExpr, row_count: usize) -> Result<Vec<Value>> {
+    let sql = match default {
+        DefaultExpr::Literal(value) => return Ok(vec![value.clone(); row_count]),
+        DefaultExpr::Function(sql) => sql,
+    };
+
+    let expr = CompiledExpr::compile(&sql_parser::parse_expr(sql)?, &[])?;
+    let no_columns = |col_idx: usize| -> Result<Value> {
+        Err(Error::Internal(format!(
+            "Default expression ({sql}) references column ({col_idx})"
+        )))
+    };
+    // collecting `Result`s would not preallocate, as its size hint has no lower bound
+    let mut values = Vec::with_capacity(row_count);
+    for _ in 0..row_count {
+        values.push(expr.eval(&no_columns)?);
+    }
+    Ok(values)
+}
+
+/// Extracts positional arguments of the function call.
+///
+/// Returns:
+///   * Ok: argument expressions.
+///   * Error: `InvalidFunctionArguments` when arguments are named, wildcards or subqueries.
+pub fn function_args(function: &Function) -> Result<Vec<&Expr>> {
+    match &function.args {
+        FunctionArguments::None => Ok(Vec::new()),
+        FunctionArguments::List(list) => list
+            .args
+            .iter()
+            .map(|arg| match arg {
+                FunctionArg::Unnamed(FunctionArgExpr::Expr(expr)) => Ok(expr),
+                arg => Err(Error::InvalidFunctionArguments(format!(
+                    "Unsupported argument ({arg}) of {}",
+                    function.name
+                ))),
+            })
+            .collect(),
+        FunctionArguments::Subquery(_) => Err(Error::InvalidFunctionArguments(format!(
+            "Subquery as argument of {} is not supported",
+            function.name
+        ))),
+    }
+}
+
 #[cfg(test)]
 pub mod tests {
-    use crate::sql::validate_name;
+    use super::*;
+    use crate::sql::sql_parser::{
+        ImportFormat, SAMPLE_BY_OPTION, SystemCommand, parse_exchange_tables, parse_import,
+        parse_resample, parse_statements, parse_system_command,
+    };
+    use sqlparser::ast::{CreateTableOptions, SetExpr, SqlOption, Statement};
+
+    #[test]
+    fn test_eval_default_generates_uuid_per_row() {
+        for function in ["generateUUIDv4()", "generateUUIDv7()"] {
+            let values = eval_default(&DefaultExpr::Function(function.to_string()), 100).unwrap();
+
+            let mut uuids: Vec<_> = values
+                .iter()
+                .map(|value| match value {
+                    Value::Uuid(uuid) => *uuid,
+                    value => panic!("Expected UUID, got: {value:?}"),
+                })
+                .collect();
+            uuids.sort();
+            uuids.dedup();
+            assert_eq!(uuids.len(), 100, "{function}");
+        }
+    }
+
+    #[test]
+    fn test_eval_default_of_date_columns() {
+        let eval = |sql: &str| eval_default(&DefaultExpr::Function(sql.to_string()), 2).unwrap();
+
+        assert!(
+            eval("toDateTime(now())")
+                .iter()
+                .all(|value| matches!(value, Value::DateTime(_)))
+        );
+        assert_eq!(
+            eval("toDate('2020-01-01')"),
+            [Value::Date(18_262), Value::Date(18_262)]
+        );
+    }
 
     #[test]
     fn test_invalid_names() {
@@ -50,6 +266,10 @@ pub mod tests {
         assert!(!validate_name("csji="));
         assert!(!validate_name("csji122yrd01/"));
         assert!(!validate_name(""));
+        assert!(!validate_name(".."));
+        assert!(!validate_name(" padded"));
+        assert!(!validate_name("line\nbreak"));
+        assert!(!validate_name(&"a".repeat(MAX_NAME_LEN + 1)));
     }
 
     #[test]
@@ -57,5 +277,891 @@ pub mod tests {
         assert!(validate_name("coffee_shop"));
         assert!(validate_name("amsterdam"));
         assert!(validate_name("John_Data"));
+        assert!(validate_name("My Column"));
+        assert!(validate_name("order-id"));
+        assert!(validate_name("страна"));
+    }
+
+    fn first_row(sql: &str) -> Vec<Expr> {
+        let statements = parse_statements(sql).unwrap();
+        let Statement::Insert(insert) = &statements[0] else {
+            panic!("Expected insert");
+        };
+        let SetExpr::Values(values) = insert.source.as_ref().unwrap().body.as_ref() else {
+            panic!("Expected values");
+        };
+        values.rows[0].clone()
+    }
+
+    #[test]
+    fn test_parse_tuple_value() {
+        let row = first_row("INSERT INTO db.table (point, single) VALUES ((-1, 'a'), (7))");
+        let point_type = ValueType::Tuple(vec![ValueType::Int32, ValueType::String]);
+        let single_type = ValueType::Tuple(vec![ValueType::UInt8]);
+
+        assert_eq!(
+            parse_value(&row[0], &point_type).unwrap(),
+            Value::Tuple(vec![Value::Int32(-1), Value::String("a".to_string())])
+        );
+        assert_eq!(
+            parse_value(&row[1], &single_type).unwrap(),
+            Value::Tuple(vec![Value::UInt8(7)])
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_tuple_value() {
+        let row = first_row("INSERT INTO db.table (a, b) VALUES ((1, 2, 3), (1, 2))");
+        let tuple_type = ValueType::Tuple(vec![ValueType::Int32, ValueType::Int32]);
+
+        assert!(matches!(
+            parse_value(&row[0], &tuple_type),
+            Err(Error::InvalidSource(_))
+        ));
+        assert!(matches!(
+            parse_value(&row[1], &ValueType::Int32),
+            Err(Error::InvalidSource(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_map_and_array_values() {
+        let row =
+            first_row("INSERT INTO db.table (m, a) VALUES (map('b', 2, 'a', 1, 'b', 3), [1, -2])");
+        let map_type = ValueType::Map(Box::new(ValueType::String), Box::new(ValueType::UInt8));
+        let array_type = ValueType::Array(Box::new(ValueType::Int8));
+
+        // sorted by key, the last value of the repeated key wins
+        assert_eq!(
+            parse_value(&row[0], &map_type).unwrap(),
+            Value::Map(vec![
+                (Value::String("a".to_string()), Value::UInt8(1)),
+                (Value::String("b".to_string()), Value::UInt8(3)),
+            ])
+        );
+        assert_eq!(
+            parse_value(&row[1], &array_type).unwrap(),
+            Value::Array(vec![Value::Int8(1), Value::Int8(-2)])
+        );
+        assert!(matches!(
+            parse_value(&row[1], &map_type),
+            Err(Error::InvalidSource(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_partition_by() {
+        let statements = parse_statements(
+            "CREATE TABLE db.t (d String, m Map(String, UInt8), id UInt64) \
+             ENGINE = MergeTree PARTITION BY (d, m['k']) ORDER BY id",
+        )
+        .unwrap();
+        let Statement::CreateTable(create_table) = &statements[0] else {
+            panic!("Expected create table");
+        };
+        assert_eq!(
+            create_table.partition_by.as_ref().unwrap().to_string(),
+            "(d, m['k'])"
+        );
+        assert_eq!(create_table.order_by.as_ref().unwrap().to_string(), "id");
+        assert_eq!(create_table.table_options.to_string(), "ENGINE = MergeTree");
+
+        assert!(matches!(
+            parse_statements("CREATE TABLE db.t (id UInt64) PARTITION BY ORDER BY id"),
+            Err(Error::SqlToAstConversion(_))
+        ));
+        assert!(matches!(
+            parse_statements("CREATE TABLE db.t (id UInt64) PARTITION BY id; SELECT 1"),
+            Err(Error::SqlToAstConversion(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_sample_by() {
+        let statements = parse_statements(
+            "CREATE TABLE db.t (ts UInt64, v Int32) ENGINE = MergeTree \
+             PARTITION BY v ORDER BY ts SAMPLE BY intHash32(ts) SETTINGS index_granularity = 2",
+        )
+        .unwrap();
+        let Statement::CreateTable(create_table) = &statements[0] else {
+            panic!("Expected create table");
+        };
+        assert_eq!(create_table.partition_by.as_ref().unwrap().to_string(), "v");
+        assert_eq!(create_table.order_by.as_ref().unwrap().to_string(), "ts");
+        let CreateTableOptions::Plain(options) = &create_table.table_options else {
+            panic!("Expected plain options: {:?}", create_table.table_options);
+        };
+        let sample_by = options.iter().find_map(|option| match option {
+            SqlOption::KeyValue { key, value } if key.value == SAMPLE_BY_OPTION => Some(value),
+            _ => None,
+        });
+        assert_eq!(sample_by.unwrap().to_string(), "intHash32(ts)");
+        assert_eq!(options.len(), 3);
+
+        assert!(matches!(
+            parse_statements("CREATE TABLE db.t (ts UInt64) SAMPLE BY ts; SELECT 1"),
+            Err(Error::SqlToAstConversion(_))
+        ));
+
+        use crate::sql::sql_parser::LogicalPlan;
+        assert!(matches!(
+            LogicalPlan::try_from(
+                "CREATE TABLE db.sample_t (ts UInt64, name String) ORDER BY ts SAMPLE BY name"
+            ),
+            Err(Error::UnsupportedTableOption(_))
+        ));
+        assert!(
+            LogicalPlan::try_from("CREATE TABLE db.sample_t (ts UInt64) ORDER BY ts SAMPLE BY 1")
+                .is_err()
+        );
+        let LogicalPlan::CreateTable { settings, .. } =
+            LogicalPlan::try_from("CREATE TABLE db.sample_t (ts Int64) ORDER BY ts SAMPLE BY ts")
+                .unwrap()
+        else {
+            panic!("Expected create table");
+        };
+        assert_eq!(settings.sample_by.as_deref(), Some("ts"));
+    }
+
+    #[test]
+    fn test_parse_resample() {
+        let (query, interval) = parse_resample(
+            "SELECT count(*), max(v) FROM db.t WHERE v > 'resample' \
+             RESAMPLE BY INTERVAL 1 HOUR;",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            query.to_string(),
+            "SELECT count(*), max(v) FROM db.t WHERE v > 'resample'"
+        );
+        assert_eq!(interval.to_string(), "INTERVAL 1 HOUR");
+
+        // only the modifier at the top level of a query
+        for sql in [
+            "SELECT resample FROM db.t",
+            "SELECT 'RESAMPLE BY' FROM db.t",
+            "INSERT INTO db.t (resample) VALUES (1)",
+        ] {
+            assert!(parse_resample(sql).unwrap().is_none(), "{sql}");
+        }
+        assert!(matches!(
+            parse_resample("SELECT ts FROM db.t RESAMPLE BY INTERVAL 1 HOUR; SELECT 1"),
+            Err(Error::SqlToAstConversion(_))
+        ));
+
+        use crate::sql::sql_parser::LogicalPlan;
+        for sql in [
+            "SELECT ts FROM db.t RESAMPLE BY INTERVAL 0 HOUR",
+            "SELECT ts FROM db.t RESAMPLE BY INTERVAL 1 MONTH",
+            "SELECT ts FROM db.t RESAMPLE BY 3600",
+            "SELECT ts FROM db.t GROUP BY ts RESAMPLE BY INTERVAL 1 HOUR",
+            "SELECT ts FROM db.t ORDER BY ts RESAMPLE BY INTERVAL 1 HOUR",
+        ] {
+            assert!(
+                matches!(
+                    LogicalPlan::try_from(sql),
+                    Err(Error::UnsupportedCommand(_))
+                ),
+                "{sql}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_order_by_expressions() {
+        use crate::sql::sql_parser::LogicalPlan;
+
+        let plan = LogicalPlan::try_from(
+            "SELECT table FROM system.tables \
+             ORDER BY total_rows * 2, (database, part_count % 3), total_rows * 2",
+        )
+        .unwrap();
+        let LogicalPlan::OrderBy {
+            column_defs,
+            computed,
+            ..
+        } = plan
+        else {
+            panic!("Expected order by, received: {plan:?}");
+        };
+        let names: Vec<_> = column_defs[0].iter().map(|col| col.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "total_rows * 2",
+                "database",
+                "part_count % 3",
+                "total_rows * 2"
+            ]
+        );
+        let computed: Vec<_> = computed
+            .iter()
+            .map(|col| {
+                (
+                    col.column_def.name.as_str(),
+                    col.column_def.field_type.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            computed,
+            [
+                ("total_rows * 2", ValueType::UInt64),
+                ("part_count % 3", ValueType::UInt64)
+            ]
+        );
+
+        // plain columns are sorted by as they are
+        let plan =
+            LogicalPlan::try_from("SELECT table FROM system.tables ORDER BY (database, table)")
+                .unwrap();
+        assert!(
+            matches!(&plan, LogicalPlan::OrderBy { computed, .. } if computed.is_empty()),
+            "{plan:?}"
+        );
+
+        for sql in [
+            "SELECT table FROM system.tables ORDER BY missing * 2",
+            "SELECT table FROM system.tables ORDER BY unknownFunction(table)",
+        ] {
+            assert!(LogicalPlan::try_from(sql).is_err(), "{sql}");
+        }
+    }
+
+    #[test]
+    fn test_parse_count_star() {
+        use crate::sql::sql_parser::LogicalPlan;
+        use crate::storage::TableDef;
+
+        for sql in [
+            "SELECT count(*) FROM db.t",
+            "SELECT COUNT(*) FROM db.t",
+            "SELECT count() FROM db.t;",
+        ] {
+            assert_eq!(
+                LogicalPlan::try_from(sql).unwrap(),
+                LogicalPlan::CountStar {
+                    table_def: TableDef {
+                        database: "db".to_string(),
+                        table: "t".to_string(),
+                    }
+                },
+                "{sql}"
+            );
+        }
+
+        for sql in [
+            "SELECT count(*) FROM db.t WHERE id > 1",
+            "SELECT count(*) FROM db.t LIMIT 1",
+            "SELECT count(*) FROM db.t GROUP BY id",
+            "SELECT count(*) FROM system.tables",
+            "SELECT count(*) FROM (SELECT table FROM system.tables)",
+            "SELECT * FROM (SELECT count(*) FROM db.t)",
+        ] {
+            assert!(
+                matches!(
+                    LogicalPlan::try_from(sql),
+                    Err(Error::UnsupportedCommand(_))
+                ),
+                "{sql}"
+            );
+        }
+        // other aggregates are parsed as usual projections
+        for sql in [
+            "SELECT count(id) FROM db.t",
+            "SELECT count(DISTINCT *) FROM db.t",
+        ] {
+            assert!(
+                matches!(LogicalPlan::try_from(sql), Err(Error::TableNotFound)),
+                "{sql}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_limit_variants() {
+        use crate::sql::sql_parser::LogicalPlan;
+
+        let limit_of = |sql: &str| match LogicalPlan::try_from(sql) {
+            Ok(LogicalPlan::Limit { limit, offset, .. }) => (limit, offset),
+            plan => panic!("Expected limit for {sql}, received: {plan:?}"),
+        };
+        for (sqls, expected) in [
+            (
+                vec!["LIMIT 10", "LIMIT 10 OFFSET 0", "LIMIT 0, 10"],
+                (Some(10), 0),
+            ),
+            (vec!["OFFSET 5", "LIMIT ALL OFFSET 5"], (None, 5)),
+            (
+                vec![
+                    "LIMIT 10 OFFSET 5",
+                    "OFFSET 5 LIMIT 10",
+                    "LIMIT 5, 10",
+                    "LIMIT 10 OFFSET 5 ROWS",
+                ],
+                (Some(10), 5),
+            ),
+        ] {
+            let plans: Vec<_> = sqls
+                .iter()
+                .map(|clause| {
+                    let sql = format!("SELECT table FROM system.tables {clause}");
+                    assert_eq!(limit_of(&sql), expected, "{sql}");
+                    LogicalPlan::try_from(sql.as_str()).unwrap()
+                })
+                .collect();
+            assert!(plans.windows(2).all(|pair| pair[0] == pair[1]), "{sqls:?}");
+        }
+
+        for clause in [
+            "LIMIT -1",
+            "LIMIT 1.5",
+            "LIMIT 'a'",
+            "LIMIT 1 + 1",
+            "OFFSET -1",
+            "LIMIT 5, -10",
+            "LIMIT 1 BY table",
+        ] {
+            let sql = format!("SELECT table FROM system.tables {clause}");
+            assert!(
+                matches!(
+                    LogicalPlan::try_from(sql.as_str()),
+                    Err(Error::InvalidLimitValue(_))
+                ),
+                "{sql}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_pivot() {
+        use crate::sql::sql_parser::{LogicalPlan, ScanSource};
+
+        let plan = LogicalPlan::try_from(
+            "SELECT * FROM system.tables \
+             PIVOT (SUM(total_rows) FOR engine IN ('MergeTree', 'ReplacingMergeTree'))",
+        )
+        .unwrap()
+        .optimize();
+        let LogicalPlan::Projection { columns, plan, .. } = plan else {
+            panic!("Expected projection, received: {plan:?}");
+        };
+        let names: Vec<_> = columns.iter().map(|col| col.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "database",
+                "table",
+                "part_count",
+                "MergeTree",
+                "ReplacingMergeTree"
+            ]
+        );
+        assert!(
+            columns[3..]
+                .iter()
+                .all(|col| col.field_type == ValueType::UInt64 && col.constraints.nullable)
+        );
+
+        // pivot is not merged into the outer query
+        let LogicalPlan::Scan {
+            source: ScanSource::Subquery(pivot),
+        } = *plan
+        else {
+            panic!("Expected pivot source, received: {plan:?}");
+        };
+        let LogicalPlan::Pivot {
+            group_by,
+            pivot_col,
+            value_col,
+            pivot_values,
+            ..
+        } = *pivot
+        else {
+            panic!("Expected pivot, received: {pivot:?}");
+        };
+        let group_by: Vec<_> = group_by.iter().map(|col| col.name.as_str()).collect();
+        assert_eq!(group_by, ["database", "table", "part_count"]);
+        assert_eq!(pivot_col.name, "engine");
+        assert_eq!(value_col.name, "total_rows");
+        assert_eq!(
+            pivot_values,
+            [
+                Value::String("MergeTree".to_string()),
+                Value::String("ReplacingMergeTree".to_string())
+            ]
+        );
+
+        for sql in [
+            "SELECT * FROM system.tables PIVOT (COUNT(total_rows) FOR engine IN ('MergeTree'))",
+            "SELECT * FROM system.tables PIVOT (SUM(table) FOR engine IN ('MergeTree'))",
+            "SELECT * FROM system.tables PIVOT (SUM(total_rows) FOR missing IN ('MergeTree'))",
+            "SELECT * FROM system.tables PIVOT (SUM(total_rows) FOR engine IN ('MergeTree' AS m))",
+            "SELECT * FROM system.tables PIVOT (SUM(total_rows) FOR engine IN ('a', 'a'))",
+            "SELECT * FROM system.tables PIVOT (SUM(total_rows) FOR engine IN ('table'))",
+            "SELECT * FROM system.tables PIVOT (SUM(total_rows) FOR total_rows IN (1))",
+            "SELECT engine FROM system.tables PIVOT (SUM(total_rows) FOR engine IN ('a'))",
+        ] {
+            assert!(LogicalPlan::try_from(sql).is_err(), "{sql}");
+        }
+    }
+
+    #[test]
+    fn test_parse_drop_partition_without_parentheses() {
+        for sql in [
+            "ALTER TABLE db.t DROP PARTITION '2024-01'",
+            "ALTER TABLE db.t DROP PARTITION ('2024-01');",
+        ] {
+            let statements = parse_statements(sql).unwrap();
+            let Statement::AlterTable { operations, .. } = &statements[0] else {
+                panic!("Expected alter table");
+            };
+            assert_eq!(operations[0].to_string(), "DROP PARTITION ('2024-01')");
+        }
+    }
+
+    #[test]
+    fn test_parse_exchange_tables() {
+        let (first, second) =
+            parse_exchange_tables(" exchange tables db.\"Live Table\" AND other.b;")
+                .unwrap()
+                .unwrap();
+        assert_eq!(first.to_string(), "db.\"Live Table\"");
+        assert_eq!(second.to_string(), "other.b");
+
+        assert!(
+            parse_exchange_tables("SELECT id FROM db.t")
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            parse_exchange_tables("EXCHANGED TABLES db.a AND db.b")
+                .unwrap()
+                .is_none()
+        );
+        for sql in [
+            "EXCHANGE TABLES db.a",
+            "EXCHANGE TABLE db.a AND db.b",
+            "EXCHANGE TABLES db.a AND db.b, db.c",
+        ] {
+            assert!(
+                matches!(
+                    parse_exchange_tables(sql),
+                    Err(Error::SqlToAstConversion(_))
+                ),
+                "{sql}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_system_command() {
+        assert_eq!(
+            parse_system_command("system disk usage;").unwrap(),
+            Some(SystemCommand::DiskUsage { table: None })
+        );
+        let Some(SystemCommand::DiskUsage { table: Some(table) }) =
+            parse_system_command(" SYSTEM DISK USAGE TABLE db.\"Live Table\"").unwrap()
+        else {
+            panic!("Expected disk usage of the table");
+        };
+        assert_eq!(table.to_string(), "db.\"Live Table\"");
+
+        let Some(SystemCommand::PartInfo { table, part_name }) =
+            parse_system_command("system part info db.t '0198-part';").unwrap()
+        else {
+            panic!("Expected part info");
+        };
+        assert_eq!(table.to_string(), "db.t");
+        assert_eq!(part_name, "0198-part");
+
+        for sql in ["SELECT id FROM db.t", "SYSTEMS DISK USAGE"] {
+            assert!(parse_system_command(sql).unwrap().is_none(), "{sql}");
+        }
+        for sql in [
+            "SYSTEM DISK",
+            "SYSTEM DISK SIZE",
+            "SYSTEM DISK USAGE TABLE",
+            "SYSTEM DISK USAGE TABLE db.a, db.b",
+            "SYSTEM PART",
+            "SYSTEM PART INFO db.t",
+            "SYSTEM PART INFO db.t part",
+            "SYSTEM PART INFO db.t 'a' 'b'",
+        ] {
+            assert!(
+                matches!(parse_system_command(sql), Err(Error::SqlToAstConversion(_))),
+                "{sql}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_import() {
+        let import = parse_import(
+            " import into db.t (id, \"Name\") FROM 'data/a.csv' FORMAT CSV WITH HEADER \
+             DELIMITER ';' SETTINGS input_allow_errors_num = 3;",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(import.table.to_string(), "db.t");
+        let columns: Vec<_> = import.columns.iter().map(|ident| &ident.value).collect();
+        assert_eq!(columns, ["id", "Name"]);
+        assert_eq!(import.path, "data/a.csv");
+        assert_eq!(import.format, ImportFormat::Csv);
+        assert!(import.with_header);
+        assert_eq!(import.delimiter.as_deref(), Some(";"));
+        assert_eq!(import.settings[0].to_string(), "input_allow_errors_num = 3");
+
+        let import = parse_import("IMPORT INTO db.t (id) FROM 'a.tsv' FORMAT TSV")
+            .unwrap()
+            .unwrap();
+        assert_eq!(import.format, ImportFormat::Tsv);
+        assert!(!import.with_header);
+        assert_eq!(import.delimiter, None);
+        assert!(import.settings.is_empty());
+
+        for sql in ["SELECT id FROM db.t", "IMPORTS INTO db.t (id) FROM 'a'"] {
+            assert!(parse_import(sql).unwrap().is_none(), "{sql}");
+        }
+        for sql in [
+            "IMPORT INTO db.t FROM 'a.csv' FORMAT CSV",
+            "IMPORT INTO db.t () FROM 'a.csv' FORMAT CSV",
+            "IMPORT INTO db.t (id) FROM a.csv FORMAT CSV",
+            "IMPORT INTO db.t (id) FROM 'a.csv'",
+            "IMPORT INTO db.t (id) FROM 'a.csv' FORMAT JSON",
+            "IMPORT INTO db.t (id) FROM 'a.csv' FORMAT CSV WITH",
+            "IMPORT INTO db.t (id) FROM 'a.csv' FORMAT CSV DELIMITER",
+            "IMPORT INTO db.t (id) FROM 'a.csv' FORMAT CSV SETTINGS",
+        ] {
+            assert!(
+                matches!(parse_import(sql), Err(Error::SqlToAstConversion(_))),
+                "{sql}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_modify_setting() {
+        use crate::sql::sql_parser::{LogicalPlan, parse_statements};
+        use sqlparser::ast::{AlterTableOperation, SqlOption, Statement};
+
+        let statements =
+            parse_statements("ALTER TABLE db.t MODIFY SETTING read_only = 1, no_merges = false;")
+                .unwrap();
+        let [Statement::AlterTable { operations, .. }] = statements.as_slice() else {
+            panic!("Expected ALTER TABLE: {statements:?}");
+        };
+        let [AlterTableOperation::SetOptionsParens { options }] = operations.as_slice() else {
+            panic!("Expected settings: {operations:?}");
+        };
+        let keys: Vec<_> = options
+            .iter()
+            .map(|option| match option {
+                SqlOption::KeyValue { key, value } => format!("{key}={value}"),
+                option => panic!("Expected key = value: {option}"),
+            })
+            .collect();
+        assert_eq!(keys, ["read_only=1", "no_merges=false"]);
+
+        for sql in [
+            "ALTER TABLE db.t MODIFY SETTING unknown = 1",
+            "ALTER TABLE db.t MODIFY SETTING read_only = 2",
+            "ALTER TABLE db.t MODIFY SETTING read_only = 'yes'",
+        ] {
+            assert!(
+                matches!(
+                    LogicalPlan::try_from(sql),
+                    Err(Error::UnsupportedTableOption(_))
+                ),
+                "{sql}"
+            );
+        }
+        assert!(matches!(
+            LogicalPlan::try_from("ALTER TABLE db.missing MODIFY SETTING read_only = 1"),
+            Err(Error::TableNotFound)
+        ));
+    }
+
+    #[test]
+    fn test_parse_optimize_table() {
+        use crate::sql::sql_parser::{LogicalPlan, parse_statements};
+
+        for sql in ["OPTIMIZE TABLE db.t", "optimize table db.t final;"] {
+            let statements = parse_statements(sql).unwrap();
+            let [Statement::OptimizeTable { name, .. }] = statements.as_slice() else {
+                panic!("Expected OPTIMIZE TABLE: {statements:?}");
+            };
+            assert_eq!(name.to_string(), "db.t");
+        }
+
+        for sql in [
+            "OPTIMIZE TABLE db.t ON CLUSTER main",
+            "OPTIMIZE TABLE db.t PARTITION '2024'",
+            "OPTIMIZE TABLE db.t DEDUPLICATE",
+        ] {
+            assert!(
+                matches!(
+                    LogicalPlan::try_from(sql),
+                    Err(Error::UnsupportedCommand(_))
+                ),
+                "{sql}"
+            );
+        }
+        assert!(matches!(
+            LogicalPlan::try_from("OPTIMIZE TABLE db.missing"),
+            Err(Error::TableNotFound)
+        ));
+    }
+
+    #[test]
+    fn test_parse_cte() {
+        use crate::sql::sql_parser::{LogicalPlan, PhysicalPlan, ScanSource};
+
+        let plan = |sql: &str| LogicalPlan::try_from(sql).map(LogicalPlan::optimize);
+
+        // chained CTEs with filters inside and outside are flattened as inline subqueries
+        let with_ctes = plan(
+            "WITH active AS (SELECT table, engine, total_rows FROM system.tables \
+             WHERE total_rows > 0), \
+             merge_tree AS (SELECT table, engine FROM active WHERE engine = 'MergeTree') \
+             SELECT table FROM merge_tree WHERE table = 't' LIMIT 1",
+        )
+        .unwrap();
+        let inline = plan(
+            "SELECT table FROM (SELECT table, engine FROM (SELECT table, engine, total_rows \
+             FROM system.tables WHERE total_rows > 0) WHERE engine = 'MergeTree') \
+             WHERE table = 't' LIMIT 1",
+        )
+        .unwrap();
+        assert_eq!(with_ctes, inline);
+        let PhysicalPlan::Select {
+            scan_source: ScanSource::Table(table_def),
+            filter: Some(filter),
+            limit: Some(1),
+            ..
+        } = PhysicalPlan::from(with_ctes)
+        else {
+            panic!("Expected a single scan of system.tables");
+        };
+        assert_eq!(
+            (table_def.database.as_str(), table_def.table.as_str()),
+            ("system", "tables")
+        );
+        assert_eq!(
+            filter.to_string(),
+            "table = 't' AND engine = 'MergeTree' AND total_rows > 0"
+        );
+
+        // nested queries and PIVOT see CTEs of the outer query
+        for sql in [
+            "WITH t AS (SELECT table FROM system.tables), u AS (SELECT table FROM t) \
+             SELECT * FROM (WITH v AS (SELECT table FROM u) SELECT table FROM v)",
+            "WITH t AS (SELECT table, total_rows FROM system.tables) \
+             SELECT * FROM t PIVOT (SUM(total_rows) FOR table IN ('a'))",
+        ] {
+            assert!(plan(sql).is_ok(), "{sql}");
+        }
+
+        for sql in [
+            "WITH RECURSIVE t AS (SELECT table FROM system.tables) SELECT table FROM t",
+            "WITH t AS (SELECT table FROM t) SELECT table FROM t",
+            "WITH a AS (SELECT table FROM b), b AS (SELECT table FROM system.tables) \
+             SELECT table FROM a",
+        ] {
+            assert!(matches!(plan(sql), Err(Error::RecursiveCte(_))), "{sql}");
+        }
+        for sql in [
+            "WITH t AS (SELECT table FROM system.tables), t AS (SELECT table FROM system.tables) \
+             SELECT table FROM t",
+            "WITH t (name) AS (SELECT table FROM system.tables) SELECT name FROM t",
+            "WITH t AS (SELECT table FROM system.tables SETTINGS scan_chunk_granules = 1) \
+             SELECT table FROM t",
+        ] {
+            assert!(
+                matches!(plan(sql), Err(Error::UnsupportedCommand(_))),
+                "{sql}"
+            );
+        }
+        // CTE is visible only within its query
+        assert!(matches!(
+            plan(
+                "WITH a AS (WITH t AS (SELECT table FROM system.tables) SELECT table FROM t) \
+                 SELECT table FROM t"
+            ),
+            Err(Error::InvalidTableName(_))
+        ));
+    }
+
+    #[test]
+    fn test_complexity_adds_up_subqueries() {
+        use crate::sql::sql_parser::{LogicalPlan, PhysicalPlan};
+
+        let complexity = |sql: &str| {
+            PhysicalPlan::from(LogicalPlan::try_from(sql).unwrap().optimize()).get_complexity()
+        };
+
+        assert_eq!(complexity("CREATE DATABASE db"), 1);
+        assert_eq!(complexity("SELECT table FROM system.tables"), 4);
+        // merged into a single scan
+        assert_eq!(
+            complexity("SELECT table FROM (SELECT table, engine FROM system.tables)"),
+            4
+        );
+        // pivot reads its source as a subquery
+        assert_eq!(
+            complexity(
+                "SELECT * FROM system.tables PIVOT (SUM(total_rows) FOR engine IN ('MergeTree'))"
+            ),
+            8
+        );
+        assert_eq!(
+            complexity(
+                "SELECT * FROM (SELECT database, table, MergeTree FROM system.tables \
+                 PIVOT (SUM(total_rows) FOR engine IN ('MergeTree'))) \
+                 PIVOT (SUM(MergeTree) FOR table IN ('t')) LIMIT 1"
+            ),
+            12
+        );
+    }
+
+    #[test]
+    fn test_required_access_of_every_plan() {
+        use crate::access::AccessLevel;
+        use crate::sql::sql_parser::{ImportSource, LogicalPlan, ScanSource};
+        use crate::storage::TableDef;
+
+        let table_def = || TableDef {
+            database: "db".to_string(),
+            table: "t".to_string(),
+        };
+        let scan = || {
+            Box::new(LogicalPlan::Scan {
+                source: ScanSource::Table(table_def()),
+            })
+        };
+        let column = |name: &str, field_type| ColumnDef {
+            name: name.to_string(),
+            field_type,
+            constraints: Default::default(),
+        };
+
+        let write_plans = [
+            LogicalPlan::CreateDatabase {
+                name: "db".to_string(),
+            },
+            LogicalPlan::CreateTable {
+                name: table_def(),
+                columns: Vec::new(),
+                settings: Default::default(),
+                order_by: Vec::new(),
+                primary_key: Vec::new(),
+                comment: None,
+            },
+            LogicalPlan::Insert {
+                table_def: table_def(),
+                columns: Vec::new(),
+                on_duplicate: Vec::new(),
+            },
+            LogicalPlan::Import {
+                table_def: table_def(),
+                columns: Vec::new(),
+                source: ImportSource {
+                    path: "a.csv".to_string(),
+                    format: ImportFormat::Csv,
+                    with_header: false,
+                    delimiter: b',',
+                    allow_errors_num: 0,
+                },
+            },
+            LogicalPlan::DropDatabase {
+                name: "db".to_string(),
+                if_exists: false,
+            },
+            LogicalPlan::DropTable {
+                name: table_def(),
+                if_exists: true,
+            },
+            LogicalPlan::DropPartition {
+                name: table_def(),
+                partition: "2024".to_string(),
+            },
+            LogicalPlan::ExchangeTables {
+                first: table_def(),
+                second: table_def(),
+            },
+            LogicalPlan::ModifyTableFlags {
+                name: table_def(),
+                set: 0,
+                clear: 0,
+            },
+            LogicalPlan::OptimizeTable { name: table_def() },
+        ];
+        for plan in write_plans {
+            assert_eq!(plan.required_access(), AccessLevel::ReadWrite, "{plan:?}");
+        }
+
+        let read_plans = [
+            LogicalPlan::Skip,
+            LogicalPlan::ShowCreateTable { name: table_def() },
+            LogicalPlan::ShowEngines,
+            LogicalPlan::DiskUsage { table: None },
+            LogicalPlan::CountStar {
+                table_def: table_def(),
+            },
+            LogicalPlan::SystemPartInfo {
+                table_def: table_def(),
+                part_name: "part".to_string(),
+            },
+            *scan(),
+            LogicalPlan::Scan {
+                source: ScanSource::Subquery(scan()),
+            },
+            LogicalPlan::Projection {
+                columns: Vec::new(),
+                computed: Vec::new(),
+                plan: scan(),
+            },
+            LogicalPlan::Filter {
+                expr: Box::new(Expr::value(sqlparser::ast::Value::Boolean(true))),
+                plan: scan(),
+            },
+            LogicalPlan::OrderBy {
+                column_defs: Vec::new(),
+                computed: Vec::new(),
+                plan: scan(),
+            },
+            LogicalPlan::Limit {
+                limit: Some(1),
+                offset: 0,
+                plan: scan(),
+            },
+            LogicalPlan::RowNumber {
+                alias: "row_number() OVER ()".to_string(),
+                order_by: Vec::new(),
+                position: 0,
+                plan: scan(),
+            },
+            LogicalPlan::Pivot {
+                group_by: Vec::new(),
+                pivot_col: column("quarter", ValueType::String),
+                value_col: column("amount", ValueType::Int32),
+                pivot_values: Vec::new(),
+                plan: scan(),
+            },
+            LogicalPlan::Flatten {
+                array_col: column("tags", ValueType::Array(Box::new(ValueType::String))),
+                plan: scan(),
+            },
+            LogicalPlan::Resample {
+                key: column("ts", ValueType::UInt64),
+                interval: 3600,
+                columns: Vec::new(),
+                plan: scan(),
+            },
+        ];
+        for plan in read_plans {
+            assert_eq!(plan.required_access(), AccessLevel::ReadOnly, "{plan:?}");
+        }
     }
 }
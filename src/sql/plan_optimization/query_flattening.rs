@@ -1,4 +1,4 @@
-use crate::sql::sql_parser::{LogicalPlan, ScanSource};
+use crate::sql::sql_parser::{ComputedColumn, LogicalPlan, ScanSource};
 
 use crate::storage::ColumnDef;
 use sqlparser::ast::{BinaryOperator, Expr};
@@ -7,7 +7,7 @@ impl LogicalPlan {
     /// Flattens a logical plan by merging nested query structures.
     ///
     /// Applies optimizations: merge scans, filters, projections, order by, and limits.
-    /// Non-query plans (Skip, `CreateDatabase`, `CreateTable`, `Insert`, `Drop`) are returned unchanged.
+    /// Non-query plans (Skip, `CreateDatabase`, `CreateTable`, `Insert`, `Import`, `Drop`, `ExchangeTables`, `ModifyTableFlags`, `OptimizeTable`, `ShowCreateTable`, `ShowEngines`, `DiskUsage`, `CountStar`, `SystemPartInfo`) are returned unchanged.
     ///
     /// Returns: Flattened `LogicalPlan`.
     pub fn flatten(self) -> Self {
@@ -16,13 +16,71 @@ impl LogicalPlan {
             | Self::CreateDatabase { .. }
             | Self::CreateTable { .. }
             | Self::Insert { .. }
+            | Self::Import { .. }
             | Self::DropDatabase { .. }
-            | Self::DropTable { .. } => self,
+            | Self::DropTable { .. }
+            | Self::DropPartition { .. }
+            | Self::ExchangeTables { .. }
+            | Self::ModifyTableFlags { .. }
+            | Self::ShowCreateTable { .. }
+            | Self::ShowEngines
+            | Self::DiskUsage { .. }
+            | Self::CountStar { .. }
+            | Self::SystemPartInfo { .. }
+            | Self::OptimizeTable { .. } => self,
+            // numbering is applied to the final result, so it stays on top of the flattened query
+            Self::RowNumber {
+                alias,
+                order_by,
+                position,
+                plan,
+            } => Self::RowNumber {
+                alias,
+                order_by,
+                position,
+                plan: Box::new(plan.flatten()),
+            },
+            // settings apply to the whole query, so they stay on top as well
+            Self::Settings { settings, plan } => Self::Settings {
+                settings,
+                plan: Box::new(plan.flatten()),
+            },
+            // reshaped rows are read as a whole, so only the query inside is flattened
+            Self::Pivot {
+                group_by,
+                pivot_col,
+                value_col,
+                pivot_values,
+                plan,
+            } => Self::Pivot {
+                group_by,
+                pivot_col,
+                value_col,
+                pivot_values,
+                plan: Box::new(plan.flatten()),
+            },
+            // rows are exploded in memory, so only the query inside is flattened
+            Self::Flatten { array_col, plan } => Self::Flatten {
+                array_col,
+                plan: Box::new(plan.flatten()),
+            },
+            // buckets are built from the whole result of the query
+            Self::Resample {
+                key,
+                interval,
+                columns,
+                plan,
+            } => Self::Resample {
+                key,
+                interval,
+                columns,
+                plan: Box::new(plan.flatten()),
+            },
             plan => plan
                 .merge_scans()
                 .merge_filters(Vec::new())
-                .merge_projections(Vec::new())
-                .merge_order_by(Vec::new())
+                .merge_projections(Vec::new(), Vec::new())
+                .merge_order_by(Vec::new(), Vec::new())
                 .merge_limit(None, 0),
         }
     }
@@ -30,19 +88,36 @@ impl LogicalPlan {
     fn merge_scans(self) -> Self {
         match self {
             Self::Scan { source } => match source {
+                ScanSource::Subquery(plan)
+                    if matches!(*plan, Self::Pivot { .. } | Self::Flatten { .. }) =>
+                {
+                    Self::Scan {
+                        source: ScanSource::Subquery(Box::new(plan.flatten())),
+                    }
+                }
                 ScanSource::Subquery(plan) => plan.merge_scans(),
                 ScanSource::Table(_) => Self::Scan { source },
             },
-            Self::Projection { columns, plan } => Self::Projection {
+            Self::Projection {
+                columns,
+                computed,
+                plan,
+            } => Self::Projection {
                 columns,
+                computed,
                 plan: Box::new(plan.merge_scans()),
             },
             Self::Filter { expr, plan } => Self::Filter {
                 expr,
                 plan: Box::new(plan.merge_scans()),
             },
-            Self::OrderBy { column_defs, plan } => Self::OrderBy {
+            Self::OrderBy {
                 column_defs,
+                computed,
+                plan,
+            } => Self::OrderBy {
+                column_defs,
+                computed,
                 plan: Box::new(plan.merge_scans()),
             },
             Self::Limit {
@@ -58,8 +133,23 @@ impl LogicalPlan {
             | Self::CreateDatabase { .. }
             | Self::CreateTable { .. }
             | Self::Insert { .. }
+            | Self::Import { .. }
             | Self::DropDatabase { .. }
-            | Self::DropTable { .. } => unreachable!(), // it's already filtered by `flatten`
+            | Self::DropTable { .. }
+            | Self::DropPartition { .. }
+            | Self::ExchangeTables { .. }
+            | Self::ModifyTableFlags { .. }
+            | Self::ShowCreateTable { .. }
+            | Self::ShowEngines
+            | Self::DiskUsage { .. }
+            | Self::CountStar { .. }
+            | Self::SystemPartInfo { .. }
+            | Self::OptimizeTable { .. }
+            | Self::RowNumber { .. }
+            | Self::Settings { .. }
+            | Self::Pivot { .. }
+            | Self::Flatten { .. }
+            | Self::Resample { .. } => unreachable!(), // it's already filtered by `flatten`
         }
     }
 
@@ -80,12 +170,22 @@ impl LogicalPlan {
                     }
                 }
             }
-            Self::Projection { columns, plan } => Self::Projection {
+            Self::Projection {
+                columns,
+                computed,
+                plan,
+            } => Self::Projection {
                 columns,
+                computed,
                 plan: Box::new(plan.merge_filters(filters)),
             },
-            Self::OrderBy { column_defs, plan } => Self::OrderBy {
+            Self::OrderBy {
                 column_defs,
+                computed,
+                plan,
+            } => Self::OrderBy {
+                column_defs,
+                computed,
                 plan: Box::new(plan.merge_filters(filters)),
             },
             Self::Limit {
@@ -101,36 +201,72 @@ impl LogicalPlan {
             | Self::CreateDatabase { .. }
             | Self::CreateTable { .. }
             | Self::Insert { .. }
+            | Self::Import { .. }
             | Self::DropDatabase { .. }
-            | Self::DropTable { .. } => unreachable!(), // it's already filtered by `flatten`
+            | Self::DropTable { .. }
+            | Self::DropPartition { .. }
+            | Self::ExchangeTables { .. }
+            | Self::ModifyTableFlags { .. }
+            | Self::ShowCreateTable { .. }
+            | Self::ShowEngines
+            | Self::DiskUsage { .. }
+            | Self::CountStar { .. }
+            | Self::SystemPartInfo { .. }
+            | Self::OptimizeTable { .. }
+            | Self::RowNumber { .. }
+            | Self::Settings { .. }
+            | Self::Pivot { .. }
+            | Self::Flatten { .. }
+            | Self::Resample { .. } => unreachable!(), // it's already filtered by `flatten`
         }
     }
 
-    fn merge_projections(self, mut columns: Vec<ColumnDef>) -> Self {
+    fn merge_projections(
+        self,
+        mut columns: Vec<ColumnDef>,
+        mut computed: Vec<ComputedColumn>,
+    ) -> Self {
         match self {
             Self::Projection {
                 columns: proj_cols,
+                computed: proj_computed,
                 plan,
             } => {
                 if columns.is_empty() {
                     columns = proj_cols;
                 }
-                plan.merge_projections(columns)
+                // outer projection may select columns computed in subquery
+                for computed_col in proj_computed {
+                    if !computed
+                        .iter()
+                        .any(|col| col.column_def == computed_col.column_def)
+                    {
+                        computed.push(computed_col);
+                    }
+                }
+                plan.merge_projections(columns, computed)
             }
             Self::Filter { .. } | Self::Scan { .. } => {
                 // we assume filters and scans are merged, so they are 100% at the very bottom
                 if columns.is_empty() {
                     self
                 } else {
+                    computed.retain(|col| columns.contains(&col.column_def));
                     Self::Projection {
                         columns,
+                        computed,
                         plan: Box::new(self), // subquery was already removed in `merge_scans`
                     }
                 }
             }
-            Self::OrderBy { column_defs, plan } => Self::OrderBy {
+            Self::OrderBy {
                 column_defs,
-                plan: Box::new(plan.merge_projections(columns)),
+                computed: computed_keys,
+                plan,
+            } => Self::OrderBy {
+                column_defs,
+                computed: computed_keys,
+                plan: Box::new(plan.merge_projections(columns, computed)),
             },
             Self::Limit {
                 limit,
@@ -139,26 +275,57 @@ impl LogicalPlan {
             } => Self::Limit {
                 limit,
                 offset,
-                plan: Box::new(plan.merge_projections(columns)),
+                plan: Box::new(plan.merge_projections(columns, computed)),
             },
             Self::Skip
             | Self::CreateDatabase { .. }
             | Self::CreateTable { .. }
             | Self::Insert { .. }
+            | Self::Import { .. }
             | Self::DropDatabase { .. }
-            | Self::DropTable { .. } => unreachable!(), // it's already filtered by `flatten`
+            | Self::DropTable { .. }
+            | Self::DropPartition { .. }
+            | Self::ExchangeTables { .. }
+            | Self::ModifyTableFlags { .. }
+            | Self::ShowCreateTable { .. }
+            | Self::ShowEngines
+            | Self::DiskUsage { .. }
+            | Self::CountStar { .. }
+            | Self::SystemPartInfo { .. }
+            | Self::OptimizeTable { .. }
+            | Self::RowNumber { .. }
+            | Self::Settings { .. }
+            | Self::Pivot { .. }
+            | Self::Flatten { .. }
+            | Self::Resample { .. } => unreachable!(), // it's already filtered by `flatten`
         }
     }
 
-    fn merge_order_by(self, mut order_by: Vec<Vec<ColumnDef>>) -> Self {
+    fn merge_order_by(
+        self,
+        mut order_by: Vec<Vec<ColumnDef>>,
+        mut computed: Vec<ComputedColumn>,
+    ) -> Self {
         match self {
-            Self::OrderBy { column_defs, plan } => {
+            Self::OrderBy {
+                column_defs,
+                computed: computed_keys,
+                plan,
+            } => {
                 // todo: remove unnecessary repeating order_by
                 // todo: simplify
                 for (idx, own_order_by) in column_defs.into_iter().enumerate() {
                     order_by.insert(idx, own_order_by);
                 }
-                plan.merge_order_by(order_by)
+                for computed_key in computed_keys {
+                    if !computed
+                        .iter()
+                        .any(|col| col.column_def == computed_key.column_def)
+                    {
+                        computed.push(computed_key);
+                    }
+                }
+                plan.merge_order_by(order_by, computed)
             }
             Self::Projection { .. } => {
                 if order_by.is_empty() {
@@ -166,6 +333,7 @@ impl LogicalPlan {
                 } else {
                     Self::OrderBy {
                         column_defs: order_by,
+                        computed,
                         plan: Box::new(self),
                     }
                 }
@@ -177,14 +345,29 @@ impl LogicalPlan {
             } => Self::Limit {
                 limit,
                 offset,
-                plan: Box::new(plan.merge_order_by(order_by)),
+                plan: Box::new(plan.merge_order_by(order_by, computed)),
             },
             Self::Skip
             | Self::CreateDatabase { .. }
             | Self::CreateTable { .. }
             | Self::Insert { .. }
+            | Self::Import { .. }
             | Self::DropDatabase { .. }
-            | Self::DropTable { .. } => unreachable!(), // it's already filtered by `flatten`
+            | Self::DropTable { .. }
+            | Self::DropPartition { .. }
+            | Self::ExchangeTables { .. }
+            | Self::ModifyTableFlags { .. }
+            | Self::ShowCreateTable { .. }
+            | Self::ShowEngines
+            | Self::DiskUsage { .. }
+            | Self::CountStar { .. }
+            | Self::SystemPartInfo { .. }
+            | Self::OptimizeTable { .. }
+            | Self::RowNumber { .. }
+            | Self::Settings { .. }
+            | Self::Pivot { .. }
+            | Self::Flatten { .. }
+            | Self::Resample { .. } => unreachable!(), // it's already filtered by `flatten`
             Self::Filter { .. } | Self::Scan { .. } => unreachable!(), // no need to check for filter/scan, as each select MUST have `Self::Projection`
         }
     }
@@ -220,8 +403,23 @@ impl LogicalPlan {
             | Self::CreateDatabase { .. }
             | Self::CreateTable { .. }
             | Self::Insert { .. }
+            | Self::Import { .. }
             | Self::DropDatabase { .. }
-            | Self::DropTable { .. } => unreachable!(), // it's already filtered by `flatten`
+            | Self::DropTable { .. }
+            | Self::DropPartition { .. }
+            | Self::ExchangeTables { .. }
+            | Self::ModifyTableFlags { .. }
+            | Self::ShowCreateTable { .. }
+            | Self::ShowEngines
+            | Self::DiskUsage { .. }
+            | Self::CountStar { .. }
+            | Self::SystemPartInfo { .. }
+            | Self::OptimizeTable { .. }
+            | Self::RowNumber { .. }
+            | Self::Settings { .. }
+            | Self::Pivot { .. }
+            | Self::Flatten { .. }
+            | Self::Resample { .. } => unreachable!(), // it's already filtered by `flatten`
             Self::Filter { .. } | Self::Scan { .. } => unreachable!(), // no need to check for filter/scan, as each select MUST have `Self::Projection`
         }
     }
@@ -266,6 +464,7 @@ mod tests {
     fn projection(columns: Vec<ColumnDef>, plan: LogicalPlan) -> LogicalPlan {
         LogicalPlan::Projection {
             columns,
+            computed: Vec::new(),
             plan: Box::new(plan),
         }
     }
@@ -299,6 +498,7 @@ mod tests {
     fn order_by(column_defs: Vec<Vec<ColumnDef>>, plan: LogicalPlan) -> LogicalPlan {
         LogicalPlan::OrderBy {
             column_defs,
+            computed: Vec::new(),
             plan: Box::new(plan),
         }
     }
@@ -596,7 +796,7 @@ mod tests {
             ),
         );
 
-        assert_eq!(plan.merge_projections(Vec::new()), merged);
+        assert_eq!(plan.merge_projections(Vec::new(), Vec::new()), merged);
     }
 
     #[test]
@@ -621,7 +821,7 @@ mod tests {
         let plan = get_start_stage()
             .merge_scans()
             .merge_filters(Vec::new())
-            .merge_projections(Vec::new());
+            .merge_projections(Vec::new(), Vec::new());
 
         let merged = limit(
             Some(2),
@@ -655,7 +855,7 @@ mod tests {
             ),
         );
 
-        assert_eq!(plan.merge_order_by(Vec::new()), merged);
+        assert_eq!(plan.merge_order_by(Vec::new(), Vec::new()), merged);
     }
 
     #[test]
@@ -676,8 +876,8 @@ mod tests {
         let plan = get_start_stage()
             .merge_scans()
             .merge_filters(Vec::new())
-            .merge_projections(Vec::new())
-            .merge_order_by(Vec::new());
+            .merge_projections(Vec::new(), Vec::new())
+            .merge_order_by(Vec::new(), Vec::new());
 
         let merged = limit(
             Some(2),
@@ -0,0 +1,701 @@
+use crate::error::{Error, Result};
+use crate::sql::city_hash::city_hash_64;
+use crate::storage::{Value, ValueType, date, get_unix_time};
+use siphasher::sip::SipHasher24;
+use uuid::Uuid;
+use xxhash_rust::xxh64::xxh64;
+
+/// Functions, which compute a single value from their arguments for each row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)] // variants are named after SQL functions
+pub enum ScalarFunction {
+    /// `mapKeys(map)` - keys of the map as an array.
+    MapKeys,
+    /// `mapValues(map)` - values of the map as an array.
+    MapValues,
+    /// `mapContains(map, key)` - whether map contains the key.
+    MapContains,
+    /// `now()` - current unix time in seconds.
+    Now,
+    /// `generateUUIDv4()` - random UUID, new for each row.
+    GenerateUUIDv4,
+    /// `generateUUIDv7()` - time-ordered UUID, new for each row.
+    GenerateUUIDv7,
+    /// `hash64(value)` - xxHash64 of the value bytes.
+    Hash64,
+    /// `cityHash64(value)` - CityHash64 (v1.0.2) of the value bytes.
+    CityHash64,
+    /// `sipHash64(value)` - SipHash-2-4 with zero key of the value bytes.
+    SipHash64,
+    /// `intHash32(int)` - 32-bit hash of the integer.
+    IntHash32,
+    /// `intHash64(int)` - 64-bit hash of the integer.
+    IntHash64,
+    /// `like(string, pattern)` - whether string matches the pattern, same as `string LIKE pattern`.
+    Like,
+    /// `toDate(value)` - date of `DateTime`, unix seconds or `YYYY-MM-DD[ hh:mm:ss]` string.
+    ToDate,
+    /// `toDateTime(value)` - time of unix seconds, midnight of `Date`, or `YYYY-MM-DD[ hh:mm:ss]`
+    /// string.
+    ToDateTime,
+    /// `toYear(date)` - year of `Date` or `DateTime`.
+    ToYear,
+    /// `toMonth(date)` - month (1-12) of `Date` or `DateTime`.
+    ToMonth,
+    /// `toDayOfMonth(date)` - day of the month (1-31) of `Date` or `DateTime`.
+    ToDayOfMonth,
+    /// `toDayOfWeek(date)` - day of the week of `Date` or `DateTime`, 1 is Monday, 7 is Sunday.
+    ToDayOfWeek,
+    /// `toHour(date)` - hour (0-23) of `DateTime`, 0 for `Date`.
+    ToHour,
+    /// `toMinute(date)` - minute (0-59) of `DateTime`, 0 for `Date`.
+    ToMinute,
+    /// `toSecond(date)` - second (0-59) of `DateTime`, 0 for `Date`.
+    ToSecond,
+    /// `toYYYYMM(date)` - year and month of `Date` or `DateTime` as a number, e.g., `202401`.
+    ToYYYYMM,
+    /// `toYYYYMMDD(date)` - date of `Date` or `DateTime` as a number, e.g., `20240131`.
+    ToYYYYMMDD,
+    /// `addSeconds(date, n)` - `DateTime` (also for `Date`) `n` seconds later.
+    AddSeconds,
+    /// `addMinutes(date, n)` - `DateTime` (also for `Date`) `n` minutes later.
+    AddMinutes,
+    /// `addHours(date, n)` - `DateTime` (also for `Date`) `n` hours later.
+    AddHours,
+    /// `addDays(date, n)` - `Date` or `DateTime` `n` days later.
+    AddDays,
+    /// `addMonths(date, n)` - `Date` or `DateTime` `n` months later. Day of the month is clamped
+    /// to the last day of the resulting month, e.g., `2024-01-31` plus a month is `2024-02-29`.
+    AddMonths,
+    /// `addYears(date, n)` - `Date` or `DateTime` `n` years later, clamped as in `addMonths`.
+    AddYears,
+    /// `subtractSeconds(date, n)` - `DateTime` (also for `Date`) `n` seconds earlier.
+    SubtractSeconds,
+    /// `subtractMinutes(date, n)` - `DateTime` (also for `Date`) `n` minutes earlier.
+    SubtractMinutes,
+    /// `subtractHours(date, n)` - `DateTime` (also for `Date`) `n` hours earlier.
+    SubtractHours,
+    /// `subtractDays(date, n)` - `Date` or `DateTime` `n` days earlier.
+    SubtractDays,
+    /// `subtractMonths(date, n)` - `Date` or `DateTime` `n` months earlier, clamped as in
+    /// `addMonths`.
+    SubtractMonths,
+    /// `subtractYears(date, n)` - `Date` or `DateTime` `n` years earlier, clamped as in
+    /// `addMonths`.
+    SubtractYears,
+    /// `JSONExtractString(json, path)` - string at the path of JSON document.
+    JSONExtractString,
+    /// `JSONExtractInt(json, path)` - integer at the path of JSON document, as `Int64`.
+    JSONExtractInt,
+    /// `JSONHas(json, path)` - whether the path exists in JSON document.
+    JSONHas,
+    /// `JSONLength(json, path)` - number of elements of the array (or keys of the object) at
+    /// the path of JSON document.
+    JSONLength,
+}
+
+impl TryFrom<&str> for ScalarFunction {
+    type Error = Error;
+
+    fn try_from(name: &str) -> Result<Self> {
+        match name {
+            "mapKeys" => Ok(Self::MapKeys),
+            "mapValues" => Ok(Self::MapValues),
+            "mapContains" => Ok(Self::MapContains),
+            "now" => Ok(Self::Now),
+            "generateUUIDv4" => Ok(Self::GenerateUUIDv4),
+            "generateUUIDv7" => Ok(Self::GenerateUUIDv7),
+            "hash64" => Ok(Self::Hash64),
+            "cityHash64" => Ok(Self::CityHash64),
+            "sipHash64" => Ok(Self::SipHash64),
+            "intHash32" => Ok(Self::IntHash32),
+            "intHash64" => Ok(Self::IntHash64),
+            "like" => Ok(Self::Like),
+            "toDate" => Ok(Self::ToDate),
+            "toDateTime" => Ok(Self::ToDateTime),
+            "toYear" => Ok(Self::ToYear),
+            "toMonth" => Ok(Self::ToMonth),
+            "toDayOfMonth" => Ok(Self::ToDayOfMonth),
+            "toDayOfWeek" => Ok(Self::ToDayOfWeek),
+            "toHour" => Ok(Self::ToHour),
+            "toMinute" => Ok(Self::ToMinute),
+            "toSecond" => Ok(Self::ToSecond),
+            "toYYYYMM" => Ok(Self::ToYYYYMM),
+            "toYYYYMMDD" => Ok(Self::ToYYYYMMDD),
+            "addSeconds" => Ok(Self::AddSeconds),
+            "addMinutes" => Ok(Self::AddMinutes),
+            "addHours" => Ok(Self::AddHours),
+            "addDays" => Ok(Self::AddDays),
+            "addMonths" => Ok(Self::AddMonths),
+            "addYears" => Ok(Self::AddYears),
+            "subtractSeconds" => Ok(Self::SubtractSeconds),
+            "subtractMinutes" => Ok(Self::SubtractMinutes),
+            "subtractHours" => Ok(Self::SubtractHours),
+            "subtractDays" => Ok(Self::SubtractDays),
+            "subtractMonths" => Ok(Self::SubtractMonths),
+            "subtractYears" => Ok(Self::SubtractYears),
+            "JSONExtractString" => Ok(Self::JSONExtractString),
+            "JSONExtractInt" => Ok(Self::JSONExtractInt),
+            "JSONHas" => Ok(Self::JSONHas),
+            "JSONLength" => Ok(Self::JSONLength),
+            _ => Err(Error::UnsupportedFunction(name.to_string())),
+        }
+    }
+}
+
+impl ScalarFunction {
+    /// Returns the type of the first argument, which determines the types of other arguments.
+    ///
+    /// Returns:
+    ///   * Ok: `ValueType` of the argument at `arg_idx`.
+    ///   * Error: `InvalidFunctionArguments` when argument at `arg_idx` is not expected.
+    pub fn get_arg_type(self, arg_idx: usize, first_arg_type: &ValueType) -> Result<ValueType> {
+        match (self, arg_idx, first_arg_type) {
+            (Self::MapContains, 1, ValueType::Map(key_type, _)) => Ok(key_type.as_ref().clone()),
+            (
+                Self::Like
+                | Self::JSONExtractString
+                | Self::JSONExtractInt
+                | Self::JSONHas
+                | Self::JSONLength,
+                1,
+                ValueType::String,
+            ) => Ok(ValueType::String),
+            (_, 1, ValueType::Date | ValueType::DateTime) if self.is_date_arithmetic() => {
+                Ok(ValueType::Int64)
+            }
+            _ => Err(self.invalid_arguments()),
+        }
+    }
+
+    /// Validates the number and types of arguments.
+    ///
+    /// Returns:
+    ///   * Ok: `ValueType` of the function result.
+    ///   * Error: `InvalidFunctionArguments` when arguments do not match the function signature.
+    pub fn get_return_type(self, arg_types: &[ValueType]) -> Result<ValueType> {
+        match (self, arg_types) {
+            (Self::MapKeys, [ValueType::Map(key_type, _)]) => {
+                Ok(ValueType::Array(key_type.clone()))
+            }
+            (Self::MapValues, [ValueType::Map(_, value_type)]) => {
+                Ok(ValueType::Array(value_type.clone()))
+            }
+            (Self::MapContains, [ValueType::Map(key_type, _), arg_type])
+                if key_type.as_ref() == arg_type =>
+            {
+                Ok(ValueType::Bool)
+            }
+            (Self::Now, []) => Ok(ValueType::UInt64),
+            (Self::GenerateUUIDv4 | Self::GenerateUUIDv7, []) => Ok(ValueType::Uuid),
+            (
+                Self::Hash64 | Self::CityHash64 | Self::SipHash64,
+                [ValueType::String | ValueType::Uuid | ValueType::Bool],
+            ) => Ok(ValueType::UInt64),
+            (Self::Hash64 | Self::CityHash64 | Self::SipHash64 | Self::IntHash64, [arg_type])
+                if arg_type.is_integer() =>
+            {
+                Ok(ValueType::UInt64)
+            }
+            (Self::IntHash32, [arg_type]) if arg_type.is_integer() => Ok(ValueType::UInt32),
+            (Self::Like, [ValueType::String, ValueType::String]) => Ok(ValueType::Bool),
+            (Self::ToDate | Self::ToDateTime, [arg_type])
+                if arg_type.is_integer()
+                    || matches!(
+                        arg_type,
+                        ValueType::String | ValueType::Date | ValueType::DateTime
+                    ) =>
+            {
+                Ok(if self == Self::ToDate {
+                    ValueType::Date
+                } else {
+                    ValueType::DateTime
+                })
+            }
+            (Self::ToYear, [ValueType::Date | ValueType::DateTime]) => Ok(ValueType::UInt16),
+            (
+                Self::ToMonth
+                | Self::ToDayOfMonth
+                | Self::ToDayOfWeek
+                | Self::ToHour
+                | Self::ToMinute
+                | Self::ToSecond,
+                [ValueType::Date | ValueType::DateTime],
+            ) => Ok(ValueType::UInt8),
+            (Self::ToYYYYMM | Self::ToYYYYMMDD, [ValueType::Date | ValueType::DateTime]) => {
+                Ok(ValueType::UInt32)
+            }
+            (
+                Self::AddSeconds
+                | Self::AddMinutes
+                | Self::AddHours
+                | Self::SubtractSeconds
+                | Self::SubtractMinutes
+                | Self::SubtractHours,
+                [ValueType::Date | ValueType::DateTime, amount_type],
+            ) if amount_type.is_integer() => Ok(ValueType::DateTime),
+            (
+                _,
+                [
+                    date_type @ (ValueType::Date | ValueType::DateTime),
+                    amount_type,
+                ],
+            ) if self.is_date_arithmetic() && amount_type.is_integer() => Ok(date_type.clone()),
+            (Self::JSONExtractString, [ValueType::String, ValueType::String]) => {
+                Ok(ValueType::String)
+            }
+            (Self::JSONExtractInt, [ValueType::String, ValueType::String]) => Ok(ValueType::Int64),
+            (Self::JSONHas, [ValueType::String, ValueType::String]) => Ok(ValueType::Bool),
+            (Self::JSONLength, [ValueType::String, ValueType::String]) => Ok(ValueType::UInt64),
+            _ => Err(self.invalid_arguments()),
+        }
+    }
+
+    /// Evaluates the function. Arguments are expected to be validated by `get_return_type`.
+    ///
+    /// Returns:
+    ///   * Ok: computed `Value`. `NULL` map produces `NULL`. Date arithmetic produces `NULL`
+    ///     for `NULL` amount and when result is out of range of its type. JSON functions produce `NULL` for
+    ///     malformed JSON, and for a missing path or a value of another type (except `JSONHas`,
+    ///     which is `false` for a missing path).
+    ///   * Error: `InvalidFunctionArguments` when values do not match the function signature,
+    ///     or JSON path is invalid, or `InvalidSource` when value can not be converted to
+    ///     `Date`/`DateTime` (invalid string, or out of range).
+    pub fn eval(self, mut args: Vec<Value>) -> Result<Value> {
+        match (self, args.as_mut_slice()) {
+            (Self::Now, []) => Ok(Value::UInt64(get_unix_time()? / 1000)),
+            (Self::GenerateUUIDv4, []) => Ok(Value::Uuid(Uuid::new_v4())),
+            (Self::GenerateUUIDv7, []) => Ok(Value::Uuid(Uuid::now_v7())),
+            (_, [Value::Null, ..]) => Ok(Value::Null),
+            (Self::MapKeys, [Value::Map(entries)]) => Ok(Value::Array(
+                std::mem::take(entries)
+                    .into_iter()
+                    .map(|(key, _)| key)
+                    .collect(),
+            )),
+            (Self::MapValues, [Value::Map(entries)]) => Ok(Value::Array(
+                std::mem::take(entries)
+                    .into_iter()
+                    .map(|(_, value)| value)
+                    .collect(),
+            )),
+            (Self::MapContains, [map @ Value::Map(_), key]) => {
+                Ok(Value::Bool(map.map_get(key).is_some()))
+            }
+            (Self::Hash64 | Self::CityHash64 | Self::SipHash64, [value]) => {
+                let bytes = hash_input(value).ok_or_else(|| self.invalid_arguments())?;
+                let hash = match self {
+                    Self::Hash64 => xxh64(&bytes, 0),
+                    Self::CityHash64 => city_hash_64(&bytes),
+                    _ => SipHasher24::new().hash(&bytes),
+                };
+                Ok(Value::UInt64(hash))
+            }
+            (Self::IntHash32, [value]) => {
+                let key = int_hash_input(value).ok_or_else(|| self.invalid_arguments())?;
+                Ok(Value::UInt32(int_hash_32(key)))
+            }
+            (Self::IntHash64, [value]) => {
+                let key = int_hash_input(value).ok_or_else(|| self.invalid_arguments())?;
+                Ok(Value::UInt64(int_hash_64(key)))
+            }
+            (Self::Like, [Value::String(string), Value::String(pattern)]) => {
+                Ok(Value::Bool(like_matches(string, pattern)))
+            }
+            (Self::Like, [Value::String(_), Value::Null]) => Ok(Value::Null),
+            (Self::ToDate | Self::ToDateTime, [value]) => {
+                let seconds = match &*value {
+                    Value::String(text) => date::parse_seconds(text),
+                    Value::Date(days) => Some(i64::from(*days) * date::SECONDS_PER_DAY),
+                    Value::DateTime(seconds) => Some(i64::from(*seconds)),
+                    integer => integer
+                        .as_i128()
+                        .and_then(|integer| i64::try_from(integer).ok()),
+                };
+                let converted = match self {
+                    Self::ToDate => seconds.and_then(|seconds| {
+                        Value::date_from_days(seconds.div_euclid(date::SECONDS_PER_DAY))
+                    }),
+                    _ => seconds.and_then(Value::date_time_from_seconds),
+                };
+                converted.ok_or_else(|| {
+                    let value_type = if self == Self::ToDate {
+                        ValueType::Date
+                    } else {
+                        ValueType::DateTime
+                    };
+                    Error::InvalidSource(format!(
+                        "Could not convert {} to {value_type}",
+                        value.to_sql_literal()
+                    ))
+                })
+            }
+            (
+                Self::ToYear
+                | Self::ToMonth
+                | Self::ToDayOfMonth
+                | Self::ToDayOfWeek
+                | Self::ToHour
+                | Self::ToMinute
+                | Self::ToSecond
+                | Self::ToYYYYMM
+                | Self::ToYYYYMMDD,
+                [value],
+            ) => {
+                let seconds = match value {
+                    Value::Date(days) => i64::from(*days) * date::SECONDS_PER_DAY,
+                    Value::DateTime(seconds) => i64::from(*seconds),
+                    _ => return Err(self.invalid_arguments()),
+                };
+                Ok(self.date_part(seconds))
+            }
+            (_, [date, amount]) if self.is_date_arithmetic() => {
+                let Some(amount) = amount.as_i128() else {
+                    return Ok(Value::Null);
+                };
+                let seconds = match date {
+                    Value::Date(days) => i64::from(*days) * date::SECONDS_PER_DAY,
+                    Value::DateTime(seconds) => i64::from(*seconds),
+                    _ => return Err(self.invalid_arguments()),
+                };
+                let is_date = matches!(date, Value::Date(_));
+                Ok(self
+                    .shift_date(seconds, is_date, amount)
+                    .unwrap_or(Value::Null))
+            }
+            (
+                Self::JSONExtractString | Self::JSONExtractInt | Self::JSONHas | Self::JSONLength,
+                [Value::String(json), Value::String(path)],
+            ) => {
+                let pointer = json_pointer(path)?;
+                let Ok(document) = serde_json::from_str::<serde_json::Value>(json) else {
+                    return Ok(Value::Null);
+                };
+                let found = document.pointer(&pointer);
+                Ok(match (self, found) {
+                    (Self::JSONHas, found) => Value::Bool(found.is_some()),
+                    (Self::JSONExtractString, Some(serde_json::Value::String(string))) => {
+                        Value::String(string.clone())
+                    }
+                    (Self::JSONExtractInt, Some(serde_json::Value::Number(number))) => {
+                        number.as_i64().map_or(Value::Null, Value::Int64)
+                    }
+                    (Self::JSONLength, Some(serde_json::Value::Array(elements))) => {
+                        Value::UInt64(elements.len() as u64)
+                    }
+                    (Self::JSONLength, Some(serde_json::Value::Object(entries))) => {
+                        Value::UInt64(entries.len() as u64)
+                    }
+                    _ => Value::Null,
+                })
+            }
+            (
+                Self::JSONExtractString | Self::JSONExtractInt | Self::JSONHas | Self::JSONLength,
+                [Value::String(_), Value::Null],
+            ) => Ok(Value::Null),
+            _ => Err(self.invalid_arguments()),
+        }
+    }
+
+    /// Returns component of the time (seconds since `1970-01-01 00:00:00` UTC) for a date
+    /// extraction function, e.g., `toMonth`. Time of `Date` and `DateTime` fits into `u32`, so
+    /// year has at most 4 digits.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // components are small
+    fn date_part(self, seconds: i64) -> Value {
+        let days = seconds.div_euclid(date::SECONDS_PER_DAY);
+        let time = seconds.rem_euclid(date::SECONDS_PER_DAY);
+        let (year, month, day) = date::civil_from_days(days);
+        match self {
+            Self::ToYear => Value::UInt16(year as u16),
+            Self::ToMonth => Value::UInt8(month as u8),
+            Self::ToDayOfMonth => Value::UInt8(day as u8),
+            // 1970-01-01 is Thursday
+            Self::ToDayOfWeek => Value::UInt8((days + 3).rem_euclid(7) as u8 + 1),
+            Self::ToHour => Value::UInt8((time / 3600) as u8),
+            Self::ToMinute => Value::UInt8((time / 60 % 60) as u8),
+            Self::ToSecond => Value::UInt8((time % 60) as u8),
+            Self::ToYYYYMM => Value::UInt32(year as u32 * 100 + month),
+            _ => Value::UInt32((year as u32 * 100 + month) * 100 + day),
+        }
+    }
+
+    fn is_date_arithmetic(self) -> bool {
+        matches!(
+            self,
+            Self::AddSeconds
+                | Self::AddMinutes
+                | Self::AddHours
+                | Self::AddDays
+                | Self::AddMonths
+                | Self::AddYears
+                | Self::SubtractSeconds
+                | Self::SubtractMinutes
+                | Self::SubtractHours
+                | Self::SubtractDays
+                | Self::SubtractMonths
+                | Self::SubtractYears
+        )
+    }
+
+    /// Shifts the time (seconds since `1970-01-01 00:00:00` UTC) of `Date` (when `is_date`) or
+    /// `DateTime` by `amount` units of a date arithmetic function.
+    ///
+    /// Returns:
+    ///   * Some: shifted value, `DateTime` for units shorter than a day.
+    ///   * None: result (or an intermediate value) overflows or is out of range of its type.
+    fn shift_date(self, seconds: i64, is_date: bool, amount: i128) -> Option<Value> {
+        let amount = i64::try_from(amount).ok()?;
+        let amount = match self {
+            Self::SubtractSeconds
+            | Self::SubtractMinutes
+            | Self::SubtractHours
+            | Self::SubtractDays
+            | Self::SubtractMonths
+            | Self::SubtractYears => amount.checked_neg()?,
+            _ => amount,
+        };
+        let days = seconds.div_euclid(date::SECONDS_PER_DAY);
+        let time = seconds.rem_euclid(date::SECONDS_PER_DAY);
+        let seconds_per_unit = match self {
+            Self::AddSeconds | Self::SubtractSeconds => 1,
+            Self::AddMinutes | Self::SubtractMinutes => 60,
+            Self::AddHours | Self::SubtractHours => 3600,
+            Self::AddDays | Self::SubtractDays if is_date => {
+                return Value::date_from_days(days.checked_add(amount)?);
+            }
+            Self::AddDays | Self::SubtractDays => date::SECONDS_PER_DAY,
+            _ => {
+                let months = match self {
+                    Self::AddYears | Self::SubtractYears => amount.checked_mul(12)?,
+                    _ => amount,
+                };
+                let (year, month, day) = date::civil_from_days(days);
+                let month_idx = (year * 12 + i64::from(month) - 1).checked_add(months)?;
+                let year = month_idx.div_euclid(12);
+                // both types end before 2150, so larger years are out of range anyway
+                if !(1970..=2150).contains(&year) {
+                    return None;
+                }
+                #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // 0..12
+                let month = month_idx.rem_euclid(12) as u32 + 1;
+                let day = day.min(date::days_in_month(year, month));
+                let days = date::days_from_civil(year, month, day);
+                return if is_date {
+                    Value::date_from_days(days)
+                } else {
+                    Value::date_time_from_seconds(days * date::SECONDS_PER_DAY + time)
+                };
+            }
+        };
+        let shifted = seconds.checked_add(amount.checked_mul(seconds_per_unit)?)?;
+        Value::date_time_from_seconds(shifted)
+    }
+
+    fn invalid_arguments(self) -> Error {
+        let signature = match self {
+            Self::MapKeys => "mapKeys(Map(K, V))",
+            Self::MapValues => "mapValues(Map(K, V))",
+            Self::MapContains => "mapContains(Map(K, V), K)",
+            Self::Now => "now()",
+            Self::GenerateUUIDv4 => "generateUUIDv4()",
+            Self::GenerateUUIDv7 => "generateUUIDv7()",
+            Self::Hash64 => "hash64(String | UUID | Bool | integer)",
+            Self::CityHash64 => "cityHash64(String | UUID | Bool | integer)",
+            Self::SipHash64 => "sipHash64(String | UUID | Bool | integer)",
+            Self::IntHash32 => "intHash32(integer)",
+            Self::IntHash64 => "intHash64(integer)",
+            Self::Like => "like(String, String)",
+            Self::ToDate => "toDate(String | Date | DateTime | integer)",
+            Self::ToDateTime => "toDateTime(String | Date | DateTime | integer)",
+            Self::ToYear => "toYear(Date | DateTime)",
+            Self::ToMonth => "toMonth(Date | DateTime)",
+            Self::ToDayOfMonth => "toDayOfMonth(Date | DateTime)",
+            Self::ToDayOfWeek => "toDayOfWeek(Date | DateTime)",
+            Self::ToHour => "toHour(Date | DateTime)",
+            Self::ToMinute => "toMinute(Date | DateTime)",
+            Self::ToSecond => "toSecond(Date | DateTime)",
+            Self::ToYYYYMM => "toYYYYMM(Date | DateTime)",
+            Self::ToYYYYMMDD => "toYYYYMMDD(Date | DateTime)",
+            Self::AddSeconds => "addSeconds(Date | DateTime, integer)",
+            Self::AddMinutes => "addMinutes(Date | DateTime, integer)",
+            Self::AddHours => "addHours(Date | DateTime, integer)",
+            Self::AddDays => "addDays(Date | DateTime, integer)",
+            Self::AddMonths => "addMonths(Date | DateTime, integer)",
+            Self::AddYears => "addYears(Date | DateTime, integer)",
+            Self::SubtractSeconds => "subtractSeconds(Date | DateTime, integer)",
+            Self::SubtractMinutes => "subtractMinutes(Date | DateTime, integer)",
+            Self::SubtractHours => "subtractHours(Date | DateTime, integer)",
+            Self::SubtractDays => "subtractDays(Date | DateTime, integer)",
+            Self::SubtractMonths => "subtractMonths(Date | DateTime, integer)",
+            Self::SubtractYears => "subtractYears(Date | DateTime, integer)",
+            Self::JSONExtractString => "JSONExtractString(String, String)",
+            Self::JSONExtractInt => "JSONExtractInt(String, String)",
+            Self::JSONHas => "JSONHas(String, String)",
+            Self::JSONLength => "JSONLength(String, String)",
+        };
+        Error::InvalidFunctionArguments(format!("expected {signature}"))
+    }
+}
+
+/// Converts JSON path of `$.key`, `$.key.nested` and `$.array[0]` form (`$` is the whole
+/// document) into JSON pointer (`/key/nested`, `/array/0`) for `serde_json::Value::pointer`.
+///
+/// Returns:
+///   * Ok: JSON pointer.
+///   * Error: `InvalidFunctionArguments` when path does not start with `$`, has an empty key
+///     or an index, which is not a non-negative integer.
+fn json_pointer(path: &str) -> Result<String> {
+    let invalid_path = || {
+        Error::InvalidFunctionArguments(format!(
+            "JSON path must look like '$.key.nested' or '$.array[0]': {path}"
+        ))
+    };
+    let mut rest = path.strip_prefix('$').ok_or_else(invalid_path)?;
+    let mut pointer = String::new();
+    while !rest.is_empty() {
+        let (segment, tail) = if let Some(key) = rest.strip_prefix('.') {
+            let end = key.find(['.', '[']).unwrap_or(key.len());
+            (&key[..end], &key[end..])
+        } else if let Some(index) = rest.strip_prefix('[') {
+            let (index, tail) = index.split_once(']').ok_or_else(invalid_path)?;
+            if !index.bytes().all(|byte| byte.is_ascii_digit()) {
+                return Err(invalid_path());
+            }
+            (index, tail)
+        } else {
+            return Err(invalid_path());
+        };
+        if segment.is_empty() {
+            return Err(invalid_path());
+        }
+        pointer.push('/');
+        pointer.push_str(&segment.replace('~', "~0").replace('/', "~1"));
+        rest = tail;
+    }
+    Ok(pointer)
+}
+
+/// Matches string with `LIKE` pattern: `%` matches any sequence of characters (including
+/// empty), `_` matches a single character and `\` escapes the next character.
+fn like_matches(string: &str, pattern: &str) -> bool {
+    enum Token {
+        Any,
+        One,
+        Char(char),
+    }
+
+    let mut tokens = Vec::with_capacity(pattern.len());
+    let mut pattern_chars = pattern.chars();
+    while let Some(char) = pattern_chars.next() {
+        tokens.push(match char {
+            '%' => Token::Any,
+            '_' => Token::One,
+            '\\' => Token::Char(pattern_chars.next().unwrap_or('\\')),
+            char => Token::Char(char),
+        });
+    }
+    let chars: Vec<char> = string.chars().collect();
+
+    // greedy matching, which backtracks to the last `%` on mismatch
+    let (mut char_idx, mut token_idx) = (0, 0);
+    let mut last_any: Option<(usize, usize)> = None;
+    while char_idx < chars.len() {
+        match tokens.get(token_idx) {
+            Some(Token::Any) => {
+                last_any = Some((token_idx, char_idx));
+                token_idx += 1;
+                continue;
+            }
+            Some(Token::One) => {
+                char_idx += 1;
+                token_idx += 1;
+                continue;
+            }
+            Some(Token::Char(char)) if *char == chars[char_idx] => {
+                char_idx += 1;
+                token_idx += 1;
+                continue;
+            }
+            _ => {}
+        }
+        let Some((any_token_idx, any_char_idx)) = last_any else {
+            return false;
+        };
+        // `%` takes one more character
+        last_any = Some((any_token_idx, any_char_idx + 1));
+        token_idx = any_token_idx + 1;
+        char_idx = any_char_idx + 1;
+    }
+
+    tokens[token_idx..]
+        .iter()
+        .all(|token| matches!(token, Token::Any))
+}
+
+/// Returns bytes, which are hashed for the value: UTF-8 of strings, 16 bytes of UUIDs, single
+/// byte of bools, little-endian bytes of integers of their own width, of `IPv4` and `DateTime`
+/// (as `UInt32`) and of `Date` (as `UInt16`), and 16 bytes of `IPv6` in network order. Same
+/// bytes are used on every platform, so hashes are stable.
+///
+/// Returns:
+///   * Some: bytes of the value.
+///   * None: value is `NULL` or nested (tuple, array or map).
+fn hash_input(value: &Value) -> Option<Vec<u8>> {
+    let bytes = match value {
+        Value::String(text) => text.as_bytes().to_vec(),
+        Value::Uuid(uuid) => uuid.as_bytes().to_vec(),
+        Value::Bool(flag) => vec![u8::from(*flag)],
+        Value::Int8(value) => value.to_le_bytes().to_vec(),
+        Value::Int16(value) => value.to_le_bytes().to_vec(),
+        Value::Int32(value) => value.to_le_bytes().to_vec(),
+        Value::Int64(value) => value.to_le_bytes().to_vec(),
+        Value::UInt8(value) => value.to_le_bytes().to_vec(),
+        Value::UInt16(value) => value.to_le_bytes().to_vec(),
+        Value::UInt32(value) => value.to_le_bytes().to_vec(),
+        Value::UInt64(value) => value.to_le_bytes().to_vec(),
+        Value::IPv4(address) => address.to_le_bytes().to_vec(),
+        Value::IPv6(address) => address.to_vec(),
+        Value::Date(days) => days.to_le_bytes().to_vec(),
+        Value::DateTime(seconds) => seconds.to_le_bytes().to_vec(),
+        Value::Null | Value::Tuple(_) | Value::Array(_) | Value::Map(_) => return None,
+    };
+    Some(bytes)
+}
+
+/// Returns integer as `u64`. Signed integers are sign-extended, so `-1` is `u64::MAX`.
+#[allow(clippy::cast_sign_loss)] // reinterpreting bits is intended
+fn int_hash_input(value: &Value) -> Option<u64> {
+    match *value {
+        Value::Int8(value) => Some(i64::from(value) as u64),
+        Value::Int16(value) => Some(i64::from(value) as u64),
+        Value::Int32(value) => Some(i64::from(value) as u64),
+        Value::Int64(value) => Some(value as u64),
+        Value::UInt8(value) => Some(u64::from(value)),
+        Value::UInt16(value) => Some(u64::from(value)),
+        Value::UInt32(value) => Some(u64::from(value)),
+        Value::UInt64(value) => Some(value),
+        _ => None,
+    }
+}
+
+/// Thomas Wang's 64 to 32 bit hash, same as ClickHouse `intHash32`.
+#[allow(clippy::cast_possible_truncation)] // only lower 32 bits are the hash
+fn int_hash_32(mut key: u64) -> u32 {
+    key = (!key).wrapping_add(key << 18);
+    key ^= key.rotate_right(31);
+    key = key.wrapping_mul(21);
+    key ^= key.rotate_right(11);
+    key = key.wrapping_add(key << 6);
+    key ^= key.rotate_right(22);
+    key as u32
+}
+
+/// MurmurHash3 64-bit finalizer, same as ClickHouse `intHash64`.
+fn int_hash_64(mut key: u64) -> u64 {
+    key ^= key >> 33;
+    key = key.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    key ^= key >> 33;
+    key = key.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    key ^= key >> 33;
+    key
+}
@@ -1,10 +1,18 @@
-use sqlparser::ast::{BinaryOperator, Expr, Statement};
+use sqlparser::ast::{
+    BinaryOperator, CreateTableOptions, Expr, Ident, ObjectName, Query, Setting, ShowCreateObject,
+    SqlOption, Statement,
+};
 use sqlparser::dialect::ClickHouseDialect;
-use sqlparser::parser::Parser;
+use sqlparser::keywords::Keyword;
+use sqlparser::parser::{IsOptional, Parser, ParserError};
+use sqlparser::tokenizer::{Token, TokenWithSpan, Tokenizer, Word};
 
+use crate::access::AccessLevel;
 use crate::error::{Error, Result};
+use crate::runtime_config::TABLE_DATA;
+use crate::sql::compiled_expr::CompiledExpr;
 use crate::storage::table_metadata::TableSettings;
-use crate::storage::{Column, ColumnDef, TableDef};
+use crate::storage::{Column, ColumnDef, TableDef, Value};
 
 /// Source for a Scan operation
 #[derive(Debug, PartialEq)]
@@ -13,6 +21,72 @@ pub enum ScanSource {
     Subquery(Box<LogicalPlan>),
 }
 
+/// Column of the projection, which is computed from the expression instead of being read from storage.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComputedColumn {
+    pub column_def: ColumnDef,
+    pub expr: Expr,
+}
+
+/// `row_number() OVER (...)` column, which numbers rows of the final result starting from 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowNumber {
+    pub alias: String,
+    /// Rows are sorted by these columns before numbering (`OVER (ORDER BY ...)`).
+    pub order_by: Vec<ColumnDef>,
+    /// Index of the column in the projection.
+    pub position: usize,
+}
+
+/// Output column of `RESAMPLE BY`, see `LogicalPlan::Resample`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResampleColumn {
+    pub column_def: ColumnDef,
+    pub aggregate: ResampleAggregate,
+}
+
+/// Value of `ResampleColumn` per bucket. Arguments are columns of the source query.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResampleAggregate {
+    /// Start of the bucket: the largest multiple of the interval, which is not greater than
+    /// `SAMPLE BY` values of its rows (seconds of `Date` and `DateTime` values).
+    Bucket,
+    /// `count(*)`, number of rows.
+    CountRows,
+    /// `count(column)`, number of non-`NULL` values.
+    Count(ColumnDef),
+    Sum(ColumnDef),
+    Min(ColumnDef),
+    Max(ColumnDef),
+}
+
+/// `SETTINGS name = value, ...` of the outermost `SELECT`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QuerySettings {
+    /// `scan_chunk_granules` - granules scanned by a single task, instead of the size computed
+    /// from the number of granules and their size. For benchmarking.
+    pub scan_chunk_granules: Option<usize>,
+    /// `skip_unreadable_parts` - parts, which could not be read (e.g., corrupted files), are
+    /// skipped with a warning, and their number is reported as `OutputTable::skipped_parts`.
+    /// Query fails on such part without it.
+    pub skip_unreadable_parts: bool,
+}
+
+/// File of `IMPORT INTO` with its options, see `LogicalPlan::Import`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportSource {
+    /// Path as written, relative to `import_directory`.
+    pub path: String,
+    pub format: ImportFormat,
+    /// First line of the file is skipped.
+    pub with_header: bool,
+    /// Field delimiter, `,` for CSV and tab for TSV by default.
+    pub delimiter: u8,
+    /// `input_allow_errors_num` - lines, which could not be parsed, are skipped up to this number,
+    /// import fails on the next one. 0 by default.
+    pub allow_errors_num: u64,
+}
+
 /// High level representation of the SQL query.
 #[derive(Debug, PartialEq)]
 pub enum LogicalPlan {
@@ -31,12 +105,33 @@ pub enum LogicalPlan {
         settings: TableSettings,
         order_by: Vec<ColumnDef>,
         primary_key: Vec<ColumnDef>,
+        comment: Option<String>,
+    },
+
+    /// Return `CREATE TABLE` statement of the existing table.
+    ShowCreateTable {
+        name: TableDef,
     },
 
+    /// List supported table engines.
+    ShowEngines,
+
     /// Insert values.
     Insert {
         table_def: TableDef,
         columns: Vec<Column>,
+        /// Column and expression of each assignment of `ON DUPLICATE KEY UPDATE`, empty without
+        /// it. Expressions use columns of the table.
+        on_duplicate: Vec<(ColumnDef, CompiledExpr)>,
+    },
+
+    /// Insert rows of the file, read in blocks of `max_insert_block_rows` rows. Each block is
+    /// inserted as `Insert`, omitted columns are filled for each block.
+    Import {
+        table_def: TableDef,
+        /// Columns of the fields of each line, in order.
+        columns: Vec<ColumnDef>,
+        source: ImportSource,
     },
 
     DropDatabase {
@@ -49,12 +144,56 @@ pub enum LogicalPlan {
         if_exists: bool,
     },
 
+    /// Delete all parts of the partition.
+    DropPartition {
+        name: TableDef,
+        /// Partition directory name.
+        partition: String,
+    },
+
+    /// Atomically swap two tables.
+    ExchangeTables {
+        first: TableDef,
+        second: TableDef,
+    },
+
+    /// Set and clear flags of the table (see `table_metadata::flags`).
+    ModifyTableFlags {
+        name: TableDef,
+        set: u32,
+        clear: u32,
+    },
+
+    /// Merge all parts of each partition of the table into one, see
+    /// `BackgroundMerge::request_merge`.
+    OptimizeTable {
+        name: TableDef,
+    },
+
+    /// Report size of parts of the table, or of all tables by database, when `table` is `None`.
+    DiskUsage {
+        table: Option<TableDef>,
+    },
+
+    /// `SELECT count(*) FROM table` without other clauses. Rows of active parts are summed up
+    /// from `TablePartInfo::row_count`, without reading any column.
+    CountStar {
+        table_def: TableDef,
+    },
+
+    /// Report marks of the active part of the table: sparse index and granule positions.
+    SystemPartInfo {
+        table_def: TableDef,
+        part_name: String,
+    },
+
     Scan {
         source: ScanSource,
     },
 
     Projection {
         columns: Vec<ColumnDef>,
+        computed: Vec<ComputedColumn>,
         plan: Box<LogicalPlan>,
     },
 
@@ -63,8 +202,13 @@ pub enum LogicalPlan {
         plan: Box<LogicalPlan>,
     },
 
+    /// Each inner `Vec` is a lexicographic key of a single `ORDER BY` clause.
+    /// Multiple keys appear after merging nested queries: from the innermost to the outermost.
     OrderBy {
         column_defs: Vec<Vec<ColumnDef>>,
+        /// Keys, which are expressions instead of columns, e.g., `length(name)`. They are
+        /// evaluated into transient columns before sorting and are not returned.
+        computed: Vec<ComputedColumn>,
         plan: Box<LogicalPlan>,
     },
 
@@ -73,6 +217,84 @@ pub enum LogicalPlan {
         offset: u64, // default 0
         plan: Box<LogicalPlan>,
     },
+
+    /// Appends `row_number()` column after all other processing. Always the outermost query node.
+    RowNumber {
+        alias: String,
+        order_by: Vec<ColumnDef>,
+        position: usize,
+        plan: Box<LogicalPlan>,
+    },
+
+    /// Applies `SETTINGS` to the whole query. Always the outermost query node.
+    Settings {
+        settings: QuerySettings,
+        plan: Box<LogicalPlan>,
+    },
+
+    /// Groups rows of `plan` by `group_by` and sums `value_col` into a column per value of
+    /// `pivot_col` in `pivot_values`. Always the source of `Scan`, as columns of the query are
+    /// known only after reshaping.
+    Pivot {
+        group_by: Vec<ColumnDef>,
+        pivot_col: ColumnDef,
+        value_col: ColumnDef,
+        pivot_values: Vec<Value>,
+        plan: Box<LogicalPlan>,
+    },
+
+    /// Returns a row per element of `array_col` of every row of `plan` with all columns of the
+    /// row, `index` of the element and the element as `value`. Rows with empty or `NULL`
+    /// arrays are skipped. Always the source of `Scan`, like `Pivot`.
+    Flatten {
+        array_col: ColumnDef,
+        plan: Box<LogicalPlan>,
+    },
+
+    /// `SELECT ... RESAMPLE BY INTERVAL n unit`: groups rows of `plan` into buckets of
+    /// `interval` seconds by `key`, the `SAMPLE BY` column of the table, and returns a row of
+    /// `columns` per bucket. Always the outermost query node.
+    Resample {
+        key: ColumnDef,
+        interval: u64,
+        columns: Vec<ResampleColumn>,
+        plan: Box<LogicalPlan>,
+    },
+}
+
+impl LogicalPlan {
+    /// Returns access level, which client needs to execute the plan: `ReadWrite` for DDL and
+    /// `INSERT`, `ReadOnly` for queries and `SHOW`.
+    pub const fn required_access(&self) -> AccessLevel {
+        match self {
+            LogicalPlan::CreateDatabase { .. }
+            | LogicalPlan::CreateTable { .. }
+            | LogicalPlan::Insert { .. }
+            | LogicalPlan::Import { .. }
+            | LogicalPlan::DropDatabase { .. }
+            | LogicalPlan::DropTable { .. }
+            | LogicalPlan::DropPartition { .. }
+            | LogicalPlan::ExchangeTables { .. }
+            | LogicalPlan::ModifyTableFlags { .. }
+            | LogicalPlan::OptimizeTable { .. } => AccessLevel::ReadWrite,
+            LogicalPlan::Skip
+            | LogicalPlan::ShowCreateTable { .. }
+            | LogicalPlan::ShowEngines
+            | LogicalPlan::DiskUsage { .. }
+            | LogicalPlan::CountStar { .. }
+            | LogicalPlan::SystemPartInfo { .. }
+            | LogicalPlan::Scan { .. }
+            | LogicalPlan::Projection { .. }
+            | LogicalPlan::Filter { .. }
+            | LogicalPlan::OrderBy { .. }
+            | LogicalPlan::Limit { .. }
+            | LogicalPlan::RowNumber { .. }
+            | LogicalPlan::Settings { .. }
+            | LogicalPlan::Pivot { .. }
+            | LogicalPlan::Flatten { .. }
+            | LogicalPlan::Resample { .. } => AccessLevel::ReadOnly,
+        }
+    }
 }
 
 /// Tries to convert SQL to `LogicalPlan` by using Datafusion `SQLParser`
@@ -80,13 +302,36 @@ pub enum LogicalPlan {
 ///   1. `CREATE DATABASE`
 ///   2. `CREATE TABLE`
 ///   3. `INSERT INTO`
+///   4. `SHOW CREATE TABLE`
+///   5. `EXCHANGE TABLES`
+///   6. `SHOW ENGINES`
+///   7. `OPTIMIZE TABLE`
+///   8. `SELECT ... RESAMPLE BY INTERVAL n unit`
+///   9. `IMPORT INTO`
 impl TryFrom<&str> for LogicalPlan {
     type Error = Error;
 
     fn try_from(sql: &str) -> Result<Self> {
-        let dialect = ClickHouseDialect {};
-        let ast = Parser::parse_sql(&dialect, sql)
-            .map_err(|error| Error::SqlToAstConversion(error.to_string()))?;
+        if let Some((first, second)) = parse_exchange_tables(sql)? {
+            return Self::from_exchange_tables(&first, &second);
+        }
+        if let Some(import) = parse_import(sql)? {
+            return Self::from_import(&import);
+        }
+        if let Some((query, interval)) = parse_resample(sql)? {
+            return Self::from_resample(&query, &interval);
+        }
+        match parse_system_command(sql)? {
+            Some(SystemCommand::DiskUsage { table }) => {
+                return Self::from_disk_usage(table.as_ref());
+            }
+            Some(SystemCommand::PartInfo { table, part_name }) => {
+                return Self::from_system_part_info(&table, part_name);
+            }
+            None => {}
+        }
+
+        let ast = parse_statements(sql)?;
         if ast.len() != 1 {
             return Err(Error::SqlToAstConversion(
                 "Currently support only statement per request".to_string(),
@@ -110,12 +355,667 @@ impl TryFrom<&str> for LogicalPlan {
                 names,
                 ..
             } => Self::from_drop(object_type, *if_exists, names),
+            Statement::AlterTable {
+                name, operations, ..
+            } => Self::from_alter_table(name, operations),
+            Statement::OptimizeTable {
+                name,
+                on_cluster,
+                partition,
+                deduplicate,
+                ..
+            } => Self::from_optimize_table(
+                name,
+                on_cluster.as_ref(),
+                partition.as_ref(),
+                deduplicate.as_ref(),
+            ),
+            Statement::ShowCreate {
+                obj_type: ShowCreateObject::Table,
+                obj_name,
+            } => Self::from_show_create_table(obj_name),
+            Statement::ShowVariable { variable } => Self::from_show_variable(variable),
 
             statement => Err(Error::UnsupportedCommand(statement.to_string())),
         }
     }
 }
 
+/// `sqlparser` does not support `EXCHANGE TABLES db.first AND db.second`, it is parsed here.
+///
+/// Returns:
+///   * Ok: names of both tables, or `None` when `sql` is not `EXCHANGE TABLES`.
+///   * Error: `SqlToAstConversion` when statement could not be tokenized or is malformed.
+pub fn parse_exchange_tables(sql: &str) -> Result<Option<(ObjectName, ObjectName)>> {
+    const EXCHANGE: &str = "EXCHANGE";
+
+    // avoid tokenizing other statements twice
+    if !starts_with_word(sql, EXCHANGE) {
+        return Ok(None);
+    }
+
+    let dialect = ClickHouseDialect {};
+    let parse = || -> std::result::Result<_, ParserError> {
+        let mut parser = Parser::new(&dialect).try_with_sql(sql)?;
+        parser.expect_keyword_is(Keyword::EXCHANGE)?;
+        parser.expect_keyword_is(Keyword::TABLES)?;
+        let first = parser.parse_object_name(false)?;
+        parser.expect_keyword_is(Keyword::AND)?;
+        let second = parser.parse_object_name(false)?;
+        while parser.consume_token(&Token::SemiColon) {}
+        parser.expect_token(&Token::EOF)?;
+        Ok((first, second))
+    };
+    parse()
+        .map(Some)
+        .map_err(|error| Error::SqlToAstConversion(error.to_string()))
+}
+
+/// `sqlparser` does not support `RESAMPLE BY interval` modifier of `SELECT`, it is cut out of
+/// the statement here. It is the last clause of the query.
+///
+/// Returns:
+///   * Ok: query without the modifier and the interval expression, or `None` when `sql` is not
+///     a query with `RESAMPLE BY`.
+///   * Error: `SqlToAstConversion` when statement could not be tokenized or parsed, or is not
+///     a single query.
+pub fn parse_resample(sql: &str) -> Result<Option<(Box<Query>, Expr)>> {
+    const RESAMPLE: &str = "RESAMPLE";
+
+    // avoid tokenizing other statements twice
+    if !sql.to_ascii_uppercase().contains(RESAMPLE) {
+        return Ok(None);
+    }
+
+    let mut tokens = Tokenizer::new(&ClickHouseDialect {}, sql)
+        .tokenize_with_location()
+        .map_err(|error| Error::SqlToAstConversion(error.to_string()))?;
+    let significant = significant_tokens(&tokens);
+    if !significant.first().is_some_and(|&idx| {
+        is_keyword(&tokens[idx], Keyword::SELECT) || is_keyword(&tokens[idx], Keyword::WITH)
+    }) {
+        return Ok(None);
+    }
+
+    let mut depth = 0_usize;
+    let mut clause_start = None;
+    for (pos, &idx) in significant.iter().enumerate() {
+        match &tokens[idx].token {
+            Token::LParen => depth += 1,
+            Token::RParen => depth = depth.saturating_sub(1),
+            Token::Word(word)
+                if depth == 0
+                    && word.quote_style.is_none()
+                    && word.value.eq_ignore_ascii_case(RESAMPLE)
+                    && significant
+                        .get(pos + 1)
+                        .is_some_and(|&next| is_keyword(&tokens[next], Keyword::BY)) =>
+            {
+                clause_start = Some(pos);
+                break;
+            }
+            _ => {}
+        }
+    }
+    let Some(clause_start) = clause_start else {
+        return Ok(None);
+    };
+
+    let expr_start = significant
+        .get(clause_start + 2)
+        .copied()
+        .unwrap_or(tokens.len());
+    let expr_end = tokens[expr_start..]
+        .iter()
+        .position(|token| token.token == Token::SemiColon)
+        .map_or(tokens.len(), |pos| expr_start + pos);
+    let interval = parse_expr_tokens(tokens[expr_start..expr_end].to_vec())?;
+    tokens.drain(significant[clause_start]..expr_end);
+
+    let mut statements = parse_statement_tokens(tokens)?;
+    let (Some(Statement::Query(query)), None) = (statements.pop(), statements.pop()) else {
+        return Err(Error::SqlToAstConversion(
+            "RESAMPLE BY is supported only in a single SELECT statement".to_string(),
+        ));
+    };
+
+    Ok(Some((query, interval)))
+}
+
+/// `SYSTEM` statement, see `parse_system_command`.
+#[derive(Debug, PartialEq)]
+pub enum SystemCommand {
+    /// `SYSTEM DISK USAGE [TABLE db.table]`.
+    DiskUsage { table: Option<ObjectName> },
+    /// `SYSTEM PART INFO db.table 'part_name'`.
+    PartInfo {
+        table: ObjectName,
+        part_name: String,
+    },
+}
+
+/// `sqlparser` does not support `SYSTEM DISK USAGE [TABLE db.table]` and
+/// `SYSTEM PART INFO db.table 'part_name'`, they are parsed here.
+///
+/// Returns:
+///   * Ok: parsed command, or `None` when `sql` is not a `SYSTEM` statement.
+///   * Error: `SqlToAstConversion` when statement could not be tokenized or is malformed.
+pub fn parse_system_command(sql: &str) -> Result<Option<SystemCommand>> {
+    const SYSTEM: &str = "SYSTEM";
+
+    // avoid tokenizing other statements twice
+    if !starts_with_word(sql, SYSTEM) {
+        return Ok(None);
+    }
+
+    let dialect = ClickHouseDialect {};
+    let parse = || -> std::result::Result<_, ParserError> {
+        let mut parser = Parser::new(&dialect).try_with_sql(sql)?;
+        expect_words(&mut parser, &[SYSTEM])?;
+        let command = if parser.peek_token().to_string().eq_ignore_ascii_case("PART") {
+            expect_words(&mut parser, &["PART", "INFO"])?;
+            let table = parser.parse_object_name(false)?;
+            let part_name = parser.parse_literal_string()?;
+            SystemCommand::PartInfo { table, part_name }
+        } else {
+            expect_words(&mut parser, &["DISK", "USAGE"])?;
+            let table = if parser.parse_keyword(Keyword::TABLE) {
+                Some(parser.parse_object_name(false)?)
+            } else {
+                None
+            };
+            SystemCommand::DiskUsage { table }
+        };
+        while parser.consume_token(&Token::SemiColon) {}
+        parser.expect_token(&Token::EOF)?;
+        Ok(command)
+    };
+    parse()
+        .map(Some)
+        .map_err(|error| Error::SqlToAstConversion(error.to_string()))
+}
+
+/// Format of the file of `IMPORT INTO`, see `parse_import`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    /// Fields may be quoted with `"`, so they contain delimiters and line breaks, and `""` inside
+    /// quotes is a quote.
+    Csv,
+    /// Fields are not quoted, tab, line break and backslash are escaped as `\t`, `\n` and `\\`,
+    /// and `\N` is `NULL`.
+    Tsv,
+}
+
+/// `IMPORT INTO db.t (col1, ...) FROM 'path' FORMAT CSV|TSV [WITH HEADER] [DELIMITER 'c']
+/// [SETTINGS name = value, ...]`, see `parse_import`.
+#[derive(Debug, PartialEq)]
+pub struct ImportStatement {
+    pub table: ObjectName,
+    pub columns: Vec<Ident>,
+    pub path: String,
+    pub format: ImportFormat,
+    /// First line of the file names columns and is skipped.
+    pub with_header: bool,
+    pub delimiter: Option<String>,
+    pub settings: Vec<Setting>,
+}
+
+/// `sqlparser` does not support `IMPORT INTO`, it is parsed here. Values of the statement are
+/// checked by `LogicalPlan::from_import`.
+///
+/// Returns:
+///   * Ok: parsed statement, or `None` when `sql` is not an `IMPORT` statement.
+///   * Error: `SqlToAstConversion` when statement could not be tokenized or is malformed, e.g.,
+///     has unknown format.
+pub fn parse_import(sql: &str) -> Result<Option<ImportStatement>> {
+    const IMPORT: &str = "IMPORT";
+
+    // avoid tokenizing other statements twice
+    if !starts_with_word(sql, IMPORT) {
+        return Ok(None);
+    }
+
+    let dialect = ClickHouseDialect {};
+    let parse = || -> std::result::Result<_, ParserError> {
+        let mut parser = Parser::new(&dialect).try_with_sql(sql)?;
+        parser.expect_keyword_is(Keyword::IMPORT)?;
+        parser.expect_keyword_is(Keyword::INTO)?;
+        let table = parser.parse_object_name(false)?;
+        let columns = parser.parse_parenthesized_column_list(IsOptional::Mandatory, false)?;
+        parser.expect_keyword_is(Keyword::FROM)?;
+        let path = parser.parse_literal_string()?;
+        parser.expect_keyword_is(Keyword::FORMAT)?;
+        let format = parser.parse_identifier()?;
+        let format = match format.value.to_ascii_uppercase().as_str() {
+            "CSV" => ImportFormat::Csv,
+            "TSV" | "TABSEPARATED" => ImportFormat::Tsv,
+            _ => {
+                return Err(ParserError::ParserError(format!(
+                    "Expected CSV or TSV, found: {format}"
+                )));
+            }
+        };
+        let with_header = parser.parse_keywords(&[Keyword::WITH, Keyword::HEADER]);
+        let delimiter = if parser.parse_keyword(Keyword::DELIMITER) {
+            Some(parser.parse_literal_string()?)
+        } else {
+            None
+        };
+        let settings = if parser.parse_keyword(Keyword::SETTINGS) {
+            parser.parse_comma_separated(|parser| {
+                let key = parser.parse_identifier()?;
+                parser.expect_token(&Token::Eq)?;
+                let value = parser.parse_expr()?;
+                Ok(Setting { key, value })
+            })?
+        } else {
+            Vec::new()
+        };
+        while parser.consume_token(&Token::SemiColon) {}
+        parser.expect_token(&Token::EOF)?;
+        Ok(ImportStatement {
+            table,
+            columns,
+            path,
+            format,
+            with_header,
+            delimiter,
+            settings,
+        })
+    };
+    parse()
+        .map(Some)
+        .map_err(|error| Error::SqlToAstConversion(error.to_string()))
+}
+
+/// Whether `sql` starts with `word` (case-insensitively), followed by whitespace.
+fn starts_with_word(sql: &str, word: &str) -> bool {
+    sql.trim_start()
+        .split_at_checked(word.len())
+        .is_some_and(|(prefix, rest)| {
+            prefix.eq_ignore_ascii_case(word) && rest.starts_with(char::is_whitespace)
+        })
+}
+
+/// Consumes `words`, which `sqlparser` does not know as keywords, case-insensitively.
+fn expect_words(parser: &mut Parser, words: &[&str]) -> std::result::Result<(), ParserError> {
+    for word in words {
+        let ident = parser.parse_identifier()?;
+        if !ident.value.eq_ignore_ascii_case(word) {
+            return Err(ParserError::ParserError(format!(
+                "Expected {word}, found: {ident}"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Parses SQL into statements using `ClickHouseDialect`.
+///
+/// Returns:
+///   * Ok: parsed statements.
+///   * Error: `SqlToAstConversion` when SQL could not be tokenized or parsed,
+///     or `PARTITION BY` (`SAMPLE BY`, `SETTINGS`) is used outside of a single `CREATE TABLE`
+///     statement.
+pub fn parse_statements(sql: &str) -> Result<Vec<Statement>> {
+    let tokens = Tokenizer::new(&ClickHouseDialect {}, sql)
+        .tokenize_with_location()
+        .map_err(|error| Error::SqlToAstConversion(error.to_string()))?;
+    parse_statement_tokens(tokens)
+}
+
+/// Parses tokens into statements, see `parse_statements`.
+fn parse_statement_tokens(tokens: Vec<TokenWithSpan>) -> Result<Vec<Statement>> {
+    let dialect = ClickHouseDialect {};
+    let tokens = rewrite_modify_setting(rewrite_drop_partition(rewrite_tuple_access(tokens)));
+    let (tokens, settings) = extract_settings(tokens)?;
+    let (tokens, partition_by) = extract_create_table_clause(tokens, Keyword::PARTITION)?;
+    let (tokens, sample_by) = extract_create_table_clause(tokens, Keyword::SAMPLE)?;
+
+    let mut statements = Parser::new(&dialect)
+        .with_tokens_with_locations(tokens)
+        .parse_statements()
+        .map_err(|error| Error::SqlToAstConversion(error.to_string()))?;
+
+    if let Some(partition_by) = partition_by {
+        let [Statement::CreateTable(create_table)] = statements.as_mut_slice() else {
+            return Err(Error::SqlToAstConversion(
+                "PARTITION BY is supported only in a single CREATE TABLE statement".to_string(),
+            ));
+        };
+        create_table.partition_by = Some(Box::new(partition_by));
+    }
+
+    if let Some(sample_by) = sample_by {
+        let option = SqlOption::KeyValue {
+            key: Ident::new(SAMPLE_BY_OPTION),
+            value: sample_by,
+        };
+        add_table_options(&mut statements, "SAMPLE BY", vec![option])?;
+    }
+    if !settings.is_empty() {
+        add_table_options(&mut statements, "SETTINGS", settings)?;
+    }
+
+    Ok(statements)
+}
+
+/// Key of `SqlOption::KeyValue`, which carries `SAMPLE BY` expression of `CREATE TABLE`, since
+/// `CreateTable` has no field for it.
+pub(crate) const SAMPLE_BY_OPTION: &str = "SAMPLE BY";
+
+/// Appends `options`, extracted from `clause` of `CREATE TABLE`, to its table options.
+///
+/// Returns:
+///   * Ok: options are added.
+///   * Error: `SqlToAstConversion` when `statements` are not a single `CREATE TABLE`, or its
+///     options are not plain ones.
+fn add_table_options(
+    statements: &mut [Statement],
+    clause: &str,
+    options: Vec<SqlOption>,
+) -> Result<()> {
+    let [Statement::CreateTable(create_table)] = statements else {
+        return Err(Error::SqlToAstConversion(format!(
+            "{clause} is supported only in a single CREATE TABLE statement"
+        )));
+    };
+    match &mut create_table.table_options {
+        CreateTableOptions::None => {
+            create_table.table_options = CreateTableOptions::Plain(options);
+        }
+        CreateTableOptions::Plain(table_options) => table_options.extend(options),
+        _ => {
+            return Err(Error::SqlToAstConversion(format!(
+                "{clause} could not be combined with table options"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// `ClickHouseDialect` does not parse `SETTINGS` clause of `CREATE TABLE`.
+/// Cuts the clause (it is the last one) out of tokens and parses it as `key = value` options.
+///
+/// Returns:
+///   * Ok: tokens without the clause and its options (`SqlOption::KeyValue`), if any.
+///   * Error: `SqlToAstConversion` when options could not be parsed.
+fn extract_settings(
+    mut tokens: Vec<TokenWithSpan>,
+) -> Result<(Vec<TokenWithSpan>, Vec<SqlOption>)> {
+    let significant = significant_tokens(&tokens);
+    if !significant
+        .first()
+        .is_some_and(|&idx| is_keyword(&tokens[idx], Keyword::CREATE))
+    {
+        return Ok((tokens, Vec::new()));
+    }
+
+    let mut depth = 0_usize;
+    let mut clause = None;
+    for &idx in &significant {
+        match &tokens[idx].token {
+            Token::LParen => depth += 1,
+            Token::RParen => depth = depth.saturating_sub(1),
+            // `SETTINGS` of the `AS SELECT` query belongs to the query
+            Token::Word(word) if depth == 0 && word.keyword == Keyword::AS => break,
+            Token::Word(word) if depth == 0 && word.keyword == Keyword::SETTINGS => {
+                clause = Some(idx);
+                break;
+            }
+            _ => {}
+        }
+    }
+    let Some(clause_start) = clause else {
+        return Ok((tokens, Vec::new()));
+    };
+    let clause_end = tokens[clause_start..]
+        .iter()
+        .position(|token| token.token == Token::SemiColon)
+        .map_or(tokens.len(), |pos| clause_start + pos);
+
+    let clause: Vec<_> = tokens.drain(clause_start..clause_end).skip(1).collect();
+    let mut parser = Parser::new(&ClickHouseDialect {}).with_tokens_with_locations(clause);
+    let settings = parser
+        .parse_comma_separated(Parser::parse_sql_option)
+        .and_then(|settings| parser.expect_token(&Token::EOF).map(|_| settings))
+        .map_err(|error| Error::SqlToAstConversion(error.to_string()))?;
+
+    Ok((tokens, settings))
+}
+
+/// Parses a single SQL expression, e.g., stored `PARTITION BY` expression.
+///
+/// Returns:
+///   * Ok: parsed `Expr`.
+///   * Error: `SqlToAstConversion` when SQL is not a single expression.
+pub fn parse_expr(sql: &str) -> Result<Expr> {
+    let tokens = Tokenizer::new(&ClickHouseDialect {}, sql)
+        .tokenize_with_location()
+        .map_err(|error| Error::SqlToAstConversion(error.to_string()))?;
+
+    parse_expr_tokens(rewrite_tuple_access(tokens))
+}
+
+fn parse_expr_tokens(tokens: Vec<TokenWithSpan>) -> Result<Expr> {
+    let mut parser = Parser::new(&ClickHouseDialect {}).with_tokens_with_locations(tokens);
+    let expr = parser
+        .parse_expr()
+        .map_err(|error| Error::SqlToAstConversion(error.to_string()))?;
+    parser
+        .expect_token(&Token::EOF)
+        .map_err(|error| Error::SqlToAstConversion(error.to_string()))?;
+
+    Ok(expr)
+}
+
+/// Returns indexes of tokens, which are not whitespace.
+fn significant_tokens(tokens: &[TokenWithSpan]) -> Vec<usize> {
+    tokens
+        .iter()
+        .enumerate()
+        .filter(|(_, token)| !matches!(token.token, Token::Whitespace(_)))
+        .map(|(idx, _)| idx)
+        .collect()
+}
+
+fn is_keyword(token: &TokenWithSpan, keyword: Keyword) -> bool {
+    matches!(&token.token, Token::Word(word) if word.keyword == keyword)
+}
+
+/// `ClickHouseDialect` does not parse `PARTITION BY` and `SAMPLE BY` clauses of `CREATE TABLE`.
+/// Cuts `<keyword> BY` clause out of tokens and parses its expression separately.
+///
+/// Returns:
+///   * Ok: tokens without the clause and its expression, if any.
+///   * Error: `SqlToAstConversion` when expression could not be parsed.
+fn extract_create_table_clause(
+    mut tokens: Vec<TokenWithSpan>,
+    keyword: Keyword,
+) -> Result<(Vec<TokenWithSpan>, Option<Expr>)> {
+    let significant = significant_tokens(&tokens);
+    if !significant
+        .first()
+        .is_some_and(|&idx| is_keyword(&tokens[idx], Keyword::CREATE))
+    {
+        return Ok((tokens, None));
+    }
+
+    let mut depth = 0_usize;
+    let mut clause_start = None;
+    for (pos, &idx) in significant.iter().enumerate() {
+        match &tokens[idx].token {
+            Token::LParen => depth += 1,
+            Token::RParen => depth = depth.saturating_sub(1),
+            _ if depth == 0
+                && is_keyword(&tokens[idx], keyword)
+                && significant
+                    .get(pos + 1)
+                    .is_some_and(|&next| is_keyword(&tokens[next], Keyword::BY)) =>
+            {
+                clause_start = Some(pos);
+                break;
+            }
+            _ => {}
+        }
+    }
+    let Some(clause_start) = clause_start else {
+        return Ok((tokens, None));
+    };
+
+    // expression lasts until the next clause of `CREATE TABLE`
+    let expr_start = significant
+        .get(clause_start + 2)
+        .copied()
+        .unwrap_or(tokens.len());
+    let mut expr_end = tokens.len();
+    for &idx in &significant[(clause_start + 2).min(significant.len())..] {
+        match &tokens[idx].token {
+            Token::LParen => depth += 1,
+            Token::RParen => depth = depth.saturating_sub(1),
+            Token::SemiColon if depth == 0 => {
+                expr_end = idx;
+                break;
+            }
+            Token::Word(word)
+                if depth == 0
+                    && matches!(
+                        word.keyword,
+                        Keyword::ORDER
+                            | Keyword::PRIMARY
+                            | Keyword::PARTITION
+                            | Keyword::SETTINGS
+                            | Keyword::SAMPLE
+                            | Keyword::AS
+                    ) =>
+            {
+                expr_end = idx;
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    let clause: Vec<_> = tokens.drain(significant[clause_start]..expr_end).collect();
+    let expr = parse_expr_tokens(clause[(expr_start - significant[clause_start])..].to_vec())?;
+
+    Ok((tokens, Some(expr)))
+}
+
+/// `ClickHouseDialect` expects `DROP PARTITION (expr)`, while ClickHouse uses `DROP PARTITION expr`.
+/// Wraps partition expression into parentheses, when they are missing.
+fn rewrite_drop_partition(mut tokens: Vec<TokenWithSpan>) -> Vec<TokenWithSpan> {
+    let significant = significant_tokens(&tokens);
+
+    let Some(pos) = significant.iter().position(|&idx| {
+        is_keyword(&tokens[idx], Keyword::PARTITION)
+            && significant
+                .iter()
+                .take_while(|&&prev| prev < idx)
+                .last()
+                .is_some_and(|&prev| {
+                    is_keyword(&tokens[prev], Keyword::DROP)
+                        || is_keyword(&tokens[prev], Keyword::EXISTS)
+                })
+    }) else {
+        return tokens;
+    };
+    let Some(&expr_start) = significant.get(pos + 1) else {
+        return tokens;
+    };
+    if tokens[expr_start].token == Token::LParen {
+        return tokens;
+    }
+
+    let mut depth = 0_usize;
+    let mut expr_end = tokens.len();
+    for &idx in &significant[(pos + 1)..] {
+        match &tokens[idx].token {
+            Token::LParen => depth += 1,
+            Token::RParen => depth = depth.saturating_sub(1),
+            Token::SemiColon | Token::Comma if depth == 0 => {
+                expr_end = idx;
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    let end_span = tokens[expr_end - 1].span;
+    tokens.insert(expr_end, TokenWithSpan::new(Token::RParen, end_span));
+    let start_span = tokens[expr_start].span;
+    tokens.insert(expr_start, TokenWithSpan::new(Token::LParen, start_span));
+    tokens
+}
+
+/// `sqlparser` does not parse ClickHouse `ALTER TABLE ... MODIFY SETTING key = value, ...`.
+/// Rewrites it into `SET (key = value, ...)`, parsed as `AlterTableOperation::SetOptionsParens`.
+fn rewrite_modify_setting(mut tokens: Vec<TokenWithSpan>) -> Vec<TokenWithSpan> {
+    let significant = significant_tokens(&tokens);
+
+    let Some(pos) = significant.windows(2).position(|pair| {
+        is_keyword(&tokens[pair[0]], Keyword::MODIFY)
+            && matches!(&tokens[pair[1]].token, Token::Word(word) if word.value.eq_ignore_ascii_case("SETTING"))
+    }) else {
+        return tokens;
+    };
+    let (modify, setting) = (significant[pos], significant[pos + 1]);
+
+    let mut depth = 0_usize;
+    let mut options_end = tokens.len();
+    for &idx in &significant[(pos + 2)..] {
+        match &tokens[idx].token {
+            Token::LParen => depth += 1,
+            Token::RParen => depth = depth.saturating_sub(1),
+            Token::SemiColon if depth == 0 => {
+                options_end = idx;
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    let end_span = tokens[options_end - 1].span;
+    tokens.insert(options_end, TokenWithSpan::new(Token::RParen, end_span));
+    tokens[setting].token = Token::LParen;
+    tokens[modify].token = Token::make_keyword("SET");
+    tokens
+}
+
+/// Tokenizer reads tuple element access (`tuple.1`) as an identifier followed by number `.1`.
+/// Rewrites such numbers into `.` and identifier `1`, so the parser produces `Expr::CompoundIdentifier`.
+fn rewrite_tuple_access(tokens: Vec<TokenWithSpan>) -> Vec<TokenWithSpan> {
+    let mut result = Vec::with_capacity(tokens.len());
+
+    for token in tokens {
+        let after_word = matches!(
+            result.last(),
+            Some(TokenWithSpan {
+                token: Token::Word(_),
+                ..
+            })
+        );
+        if after_word
+            && let Token::Number(number, _) = &token.token
+            && let Some(field) = number.strip_prefix('.')
+            && !field.is_empty()
+            && field.bytes().all(|byte| byte.is_ascii_digit())
+        {
+            let field = Token::Word(Word {
+                value: field.to_string(),
+                quote_style: None,
+                keyword: Keyword::NoKeyword,
+            });
+            result.push(TokenWithSpan::new(Token::Period, token.span));
+            result.push(TokenWithSpan::new(field, token.span));
+        } else {
+            result.push(token);
+        }
+    }
+
+    result
+}
+
 /// Lower level representation of the Logical Plan.
 #[derive(Debug)]
 pub enum PhysicalPlan {
@@ -134,12 +1034,31 @@ pub enum PhysicalPlan {
         settings: TableSettings,
         order_by: Vec<ColumnDef>,
         primary_key: Vec<ColumnDef>,
+        comment: Option<String>,
+    },
+
+    /// Return `CREATE TABLE` statement of the existing table.
+    ShowCreateTable {
+        name: TableDef,
     },
 
+    /// List supported table engines.
+    ShowEngines,
+
     /// Insert values.
     Insert {
         table_def: TableDef,
         columns: Vec<Column>,
+        /// Column and expression of each assignment of `ON DUPLICATE KEY UPDATE`, empty without
+        /// it. Expressions use columns of the table.
+        on_duplicate: Vec<(ColumnDef, CompiledExpr)>,
+    },
+
+    /// Insert rows of the file in blocks.
+    Import {
+        table_def: TableDef,
+        columns: Vec<ColumnDef>,
+        source: ImportSource,
     },
 
     DropDatabase {
@@ -152,14 +1071,66 @@ pub enum PhysicalPlan {
         if_exists: bool,
     },
 
+    /// Delete all parts of the partition.
+    DropPartition {
+        name: TableDef,
+        /// Partition directory name.
+        partition: String,
+    },
+
+    /// Atomically swap two tables.
+    ExchangeTables {
+        first: TableDef,
+        second: TableDef,
+    },
+
+    /// Set and clear flags of the table.
+    ModifyTableFlags {
+        name: TableDef,
+        set: u32,
+        clear: u32,
+    },
+
+    /// Merge all parts of the table.
+    OptimizeTable {
+        name: TableDef,
+    },
+
+    /// Report size of parts of the table, or of all tables by database.
+    DiskUsage {
+        table: Option<TableDef>,
+    },
+
+    /// Count rows of the table from part infos.
+    CountStar {
+        table_def: TableDef,
+    },
+
+    /// Report marks of the part.
+    SystemPartInfo {
+        table_def: TableDef,
+        part_name: String,
+    },
+
     /// Select columns from table.
     Select {
         scan_source: ScanSource,
         columns: Vec<ColumnDef>,
+        computed: Vec<ComputedColumn>,
         filter: Option<Box<Expr>>,
         sort_by: Option<Vec<Vec<ColumnDef>>>,
+        row_number: Option<RowNumber>,
         limit: Option<u64>,
         offset: u64,
+        settings: QuerySettings,
+    },
+
+    /// Group rows of the query into time buckets, see `LogicalPlan::Resample`.
+    Resample {
+        key: ColumnDef,
+        interval: u64,
+        columns: Vec<ResampleColumn>,
+        plan: Box<LogicalPlan>,
     },
 }
 
@@ -174,40 +1145,124 @@ impl From<LogicalPlan> for PhysicalPlan {
                 settings,
                 order_by,
                 primary_key,
+                comment,
             } => Self::CreateTable {
                 name,
                 columns,
                 settings,
                 order_by,
                 primary_key,
+                comment,
+            },
+            LogicalPlan::ShowCreateTable { name } => Self::ShowCreateTable { name },
+            LogicalPlan::ShowEngines => Self::ShowEngines,
+            LogicalPlan::Insert {
+                table_def,
+                columns,
+                on_duplicate,
+            } => Self::Insert {
+                table_def,
+                columns,
+                on_duplicate,
+            },
+            LogicalPlan::Import {
+                table_def,
+                columns,
+                source,
+            } => Self::Import {
+                table_def,
+                columns,
+                source,
             },
-            LogicalPlan::Insert { table_def, columns } => Self::Insert { table_def, columns },
             LogicalPlan::DropDatabase { name, if_exists } => Self::DropDatabase { name, if_exists },
             LogicalPlan::DropTable { name, if_exists } => Self::DropTable { name, if_exists },
+            LogicalPlan::DropPartition { name, partition } => {
+                Self::DropPartition { name, partition }
+            }
+            LogicalPlan::ExchangeTables { first, second } => Self::ExchangeTables { first, second },
+            LogicalPlan::ModifyTableFlags { name, set, clear } => {
+                Self::ModifyTableFlags { name, set, clear }
+            }
+            LogicalPlan::OptimizeTable { name } => Self::OptimizeTable { name },
+            LogicalPlan::DiskUsage { table } => Self::DiskUsage { table },
+            LogicalPlan::CountStar { table_def } => Self::CountStar { table_def },
+            LogicalPlan::SystemPartInfo {
+                table_def,
+                part_name,
+            } => Self::SystemPartInfo {
+                table_def,
+                part_name,
+            },
+            LogicalPlan::Pivot { .. } => {
+                unreachable!("PIVOT is always the source of a query, see `LogicalPlan::Pivot`")
+            }
+            LogicalPlan::Flatten { .. } => {
+                unreachable!("FLATTEN is always the source of a query, see `LogicalPlan::Flatten`")
+            }
+            LogicalPlan::Resample {
+                key,
+                interval,
+                columns,
+                plan,
+            } => Self::Resample {
+                key,
+                interval,
+                columns,
+                plan,
+            },
 
             LogicalPlan::Scan { source } => {
                 Self::Select {
                     scan_source: source,
                     columns: Vec::new(), // to be filled,
+                    computed: Vec::new(),
                     filter: None,
                     sort_by: None,
+                    row_number: None,
                     limit: None,
                     offset: 0,
+                    settings: QuerySettings::default(),
                 }
             }
             plan @ (LogicalPlan::Projection { .. }
             | LogicalPlan::Filter { .. }
             | LogicalPlan::OrderBy { .. }
-            | LogicalPlan::Limit { .. }) => {
+            | LogicalPlan::Limit { .. }
+            | LogicalPlan::RowNumber { .. }
+            | LogicalPlan::Settings { .. }) => {
                 let mut current = plan;
                 let mut columns = None;
+                let mut computed = Vec::new();
                 let mut filter = None;
                 let mut sort_by = None;
+                let mut sort_computed = Vec::new();
+                let mut row_number = None;
                 let mut limit = None;
                 let mut offset = 0;
+                let mut settings = QuerySettings::default();
 
                 loop {
                     match current {
+                        LogicalPlan::Settings {
+                            settings: query_settings,
+                            plan: inner,
+                        } => {
+                            settings = query_settings;
+                            current = *inner;
+                        }
+                        LogicalPlan::RowNumber {
+                            alias,
+                            order_by,
+                            position,
+                            plan: inner,
+                        } => {
+                            row_number = Some(RowNumber {
+                                alias,
+                                order_by,
+                                position,
+                            });
+                            current = *inner;
+                        }
                         LogicalPlan::Limit {
                             limit: limit_val,
                             offset: offset_val,
@@ -219,16 +1274,20 @@ impl From<LogicalPlan> for PhysicalPlan {
                         }
                         LogicalPlan::OrderBy {
                             column_defs,
+                            computed: computed_keys,
                             plan: inner,
                         } => {
                             sort_by = Some(column_defs);
+                            sort_computed = computed_keys;
                             current = *inner;
                         }
                         LogicalPlan::Projection {
                             columns: cols,
+                            computed: computed_cols,
                             plan: inner,
                         } => {
                             columns = Some(cols);
+                            computed = computed_cols;
                             current = *inner;
                         }
                         LogicalPlan::Filter { expr, plan: inner } => {
@@ -243,13 +1302,25 @@ impl From<LogicalPlan> for PhysicalPlan {
                             current = *inner;
                         }
                         LogicalPlan::Scan { source } => {
+                            // sort keys are computed together with the projection, but are not projected
+                            for computed_key in sort_computed {
+                                if !computed
+                                    .iter()
+                                    .any(|col| col.column_def == computed_key.column_def)
+                                {
+                                    computed.push(computed_key);
+                                }
+                            }
                             return Self::Select {
                                 scan_source: source,
                                 columns: columns.unwrap_or_default(),
+                                computed,
                                 filter,
                                 sort_by,
+                                row_number,
                                 limit,
                                 offset,
+                                settings,
                             };
                         }
                         unexpected => unreachable!("Unexpected plan node in query: {unexpected:?}"),
@@ -261,15 +1332,101 @@ impl From<LogicalPlan> for PhysicalPlan {
 }
 
 impl PhysicalPlan {
+    /// Returns name of the command, when it modifies the storage directory, e.g., `INSERT`.
+    pub const fn write_command(&self) -> Option<&'static str> {
+        match self {
+            PhysicalPlan::CreateDatabase { .. } => Some("CREATE DATABASE"),
+            PhysicalPlan::CreateTable { .. } => Some("CREATE TABLE"),
+            PhysicalPlan::Insert { .. } => Some("INSERT"),
+            PhysicalPlan::Import { .. } => Some("IMPORT"),
+            PhysicalPlan::DropDatabase { .. } => Some("DROP DATABASE"),
+            PhysicalPlan::DropTable { .. } => Some("DROP TABLE"),
+            PhysicalPlan::DropPartition { .. } => Some("ALTER TABLE"),
+            PhysicalPlan::ExchangeTables { .. } => Some("EXCHANGE TABLES"),
+            PhysicalPlan::ModifyTableFlags { .. } => Some("ALTER TABLE"),
+            PhysicalPlan::OptimizeTable { .. } => Some("OPTIMIZE TABLE"),
+            PhysicalPlan::Skip
+            | PhysicalPlan::ShowCreateTable { .. }
+            | PhysicalPlan::ShowEngines
+            | PhysicalPlan::DiskUsage { .. }
+            | PhysicalPlan::CountStar { .. }
+            | PhysicalPlan::SystemPartInfo { .. }
+            | PhysicalPlan::Select { .. }
+            | PhysicalPlan::Resample { .. } => None,
+        }
+    }
+
+    /// Returns estimated cost of executing the plan, which is added to the database load while
+    /// it runs and limited by `max_query_complexity`.
+    ///
+    /// `SELECT` costs `SELECT_COMPLEXITY` plus the cost of its source: subqueries are added up,
+    /// and a table, scanned without `LIMIT`, adds a point per `UNLIMITED_SCAN_ROWS_PER_POINT`
+    /// rows in its active parts. `RESAMPLE BY` costs `SELECT_COMPLEXITY` plus its query.
     pub fn get_complexity(&self) -> u32 {
         match self {
             PhysicalPlan::Skip => 0,
             PhysicalPlan::CreateDatabase { .. }
             | PhysicalPlan::CreateTable { .. }
             | PhysicalPlan::DropDatabase { .. }
-            | PhysicalPlan::DropTable { .. } => 1,
-            PhysicalPlan::Insert { .. } => 2,
-            PhysicalPlan::Select { .. } => 4,
+            | PhysicalPlan::DropTable { .. }
+            | PhysicalPlan::DropPartition { .. }
+            | PhysicalPlan::ExchangeTables { .. }
+            | PhysicalPlan::ModifyTableFlags { .. }
+            | PhysicalPlan::OptimizeTable { .. }
+            | PhysicalPlan::ShowCreateTable { .. }
+            | PhysicalPlan::ShowEngines
+            | PhysicalPlan::DiskUsage { .. }
+            | PhysicalPlan::CountStar { .. }
+            | PhysicalPlan::SystemPartInfo { .. } => 1,
+            PhysicalPlan::Insert { .. } | PhysicalPlan::Import { .. } => 2,
+            PhysicalPlan::Select {
+                scan_source, limit, ..
+            } => SELECT_COMPLEXITY.saturating_add(source_complexity(scan_source, limit.is_some())),
+            // buckets are built from all rows of the query
+            PhysicalPlan::Resample { plan, .. } => {
+                SELECT_COMPLEXITY.saturating_add(subquery_complexity(plan, false))
+            }
+        }
+    }
+}
+
+/// Complexity of a single `SELECT` level, without its source.
+const SELECT_COMPLEXITY: u32 = 4;
+/// Rows of a table, which add a point of complexity, when it is scanned without `LIMIT`.
+const UNLIMITED_SCAN_ROWS_PER_POINT: u64 = 10_000_000;
+
+/// Returns complexity of reading `source`. `limited` is whether the reading `SELECT` has `LIMIT`.
+fn source_complexity(source: &ScanSource, limited: bool) -> u32 {
+    match source {
+        ScanSource::Table(_) if limited => 0,
+        ScanSource::Table(table_def) => {
+            let rows = TABLE_DATA
+                .get(table_def)
+                .map_or(0, |table| table.total_rows());
+            u32::try_from(rows / UNLIMITED_SCAN_ROWS_PER_POINT).unwrap_or(u32::MAX)
+        }
+        ScanSource::Subquery(plan) => subquery_complexity(plan, false),
+    }
+}
+
+/// Returns complexity of a subquery, see `PhysicalPlan::get_complexity`.
+fn subquery_complexity(plan: &LogicalPlan, limited: bool) -> u32 {
+    match plan {
+        LogicalPlan::Scan { source } => {
+            SELECT_COMPLEXITY.saturating_add(source_complexity(source, limited))
+        }
+        LogicalPlan::Limit { limit, plan, .. } => {
+            subquery_complexity(plan, limited || limit.is_some())
+        }
+        // pivot groups all rows of its source, and flatten reads all of them
+        LogicalPlan::Pivot { plan, .. } | LogicalPlan::Flatten { plan, .. } => {
+            subquery_complexity(plan, false)
         }
+        LogicalPlan::Projection { plan, .. }
+        | LogicalPlan::Filter { plan, .. }
+        | LogicalPlan::OrderBy { plan, .. }
+        | LogicalPlan::RowNumber { plan, .. }
+        | LogicalPlan::Settings { plan, .. } => subquery_complexity(plan, limited),
+        _ => unreachable!("Subquery is always a SELECT"),
     }
 }
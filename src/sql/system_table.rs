@@ -0,0 +1,431 @@
+use crate::background_merge::BackgroundMerge;
+use crate::error::{Error, Result};
+use crate::runtime_config::TABLE_DATA;
+use crate::storage::{Column, ColumnDef, Constraints, TableDef, Value, ValueType};
+
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Reserved database of virtual tables, which describe the server (e.g., `system.parts`).
+/// User database with this name can not be created.
+pub const SYSTEM_DATABASE: &str = "system";
+
+/// Virtual read-only table of the `system` database. Its rows are built from runtime state on
+/// every query, nothing is stored on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SystemTable {
+    /// Active part of every table: row count, granules and size of column files.
+    Parts,
+    /// Every table: engine, row and part count.
+    Tables,
+    /// Column of every table: type, constraints and whether it is part of the keys.
+    Columns,
+    /// Running merge: merged parts, rows and its phase.
+    Merges,
+}
+
+impl SystemTable {
+    /// Finds system table by name.
+    ///
+    /// Returns:
+    ///   * Ok: `Some` for table of the `system` database, `None` for any other database.
+    ///   * Error: `TableNotFound` when `system` database has no such table.
+    pub fn from_table_def(table_def: &TableDef) -> Result<Option<Self>> {
+        if table_def.database != SYSTEM_DATABASE {
+            return Ok(None);
+        }
+
+        match table_def.table.as_str() {
+            "parts" => Ok(Some(Self::Parts)),
+            "tables" => Ok(Some(Self::Tables)),
+            "columns" => Ok(Some(Self::Columns)),
+            "merges" => Ok(Some(Self::Merges)),
+            _ => Err(Error::TableNotFound),
+        }
+    }
+
+    /// Returns columns of the table.
+    pub fn column_defs(self) -> Vec<ColumnDef> {
+        match self {
+            Self::Parts => vec![
+                column_def(
+                    "database",
+                    ValueType::String,
+                    false,
+                    "Database of the table",
+                ),
+                column_def("table", ValueType::String, false, "Table of the part"),
+                column_def(
+                    "partition",
+                    ValueType::String,
+                    true,
+                    "Partition directory, NULL when table has no PARTITION BY",
+                ),
+                column_def(
+                    "name",
+                    ValueType::String,
+                    false,
+                    "Directory name of the part",
+                ),
+                column_def("rows", ValueType::UInt64, false, "Number of rows"),
+                column_def("marks", ValueType::UInt64, false, "Number of granules"),
+                column_def(
+                    "bytes_on_disk",
+                    ValueType::UInt64,
+                    false,
+                    "Size of all files of the part",
+                ),
+                column_def(
+                    "column_bytes",
+                    ValueType::Map(Box::new(ValueType::String), Box::new(ValueType::UInt64)),
+                    false,
+                    "Size of the compressed file of each column",
+                ),
+                column_def(
+                    "column_compression",
+                    ValueType::Map(Box::new(ValueType::String), Box::new(ValueType::String)),
+                    false,
+                    "Compression type of each column",
+                ),
+            ],
+            Self::Tables => vec![
+                column_def(
+                    "database",
+                    ValueType::String,
+                    false,
+                    "Database of the table",
+                ),
+                column_def("table", ValueType::String, false, "Name of the table"),
+                column_def("engine", ValueType::String, false, "Table engine"),
+                column_def(
+                    "total_rows",
+                    ValueType::UInt64,
+                    false,
+                    "Number of rows in all active parts",
+                ),
+                column_def(
+                    "part_count",
+                    ValueType::UInt64,
+                    false,
+                    "Number of active parts",
+                ),
+            ],
+            Self::Columns => vec![
+                column_def(
+                    "database",
+                    ValueType::String,
+                    false,
+                    "Database of the table",
+                ),
+                column_def("table", ValueType::String, false, "Table of the column"),
+                column_def("column", ValueType::String, false, "Name of the column"),
+                column_def("type", ValueType::String, false, "Type of the column"),
+                column_def(
+                    "nullable",
+                    ValueType::Bool,
+                    false,
+                    "Whether column accepts NULL",
+                ),
+                column_def(
+                    "default",
+                    ValueType::String,
+                    true,
+                    "DEFAULT expression, NULL when column has none",
+                ),
+                column_def(
+                    "compression",
+                    ValueType::String,
+                    false,
+                    "Compression type of the column",
+                ),
+                column_def(
+                    "in_primary_key",
+                    ValueType::Bool,
+                    false,
+                    "Whether column is in PRIMARY KEY",
+                ),
+                column_def(
+                    "in_order_by",
+                    ValueType::Bool,
+                    false,
+                    "Whether column is in ORDER BY",
+                ),
+            ],
+            Self::Merges => vec![
+                column_def(
+                    "database",
+                    ValueType::String,
+                    false,
+                    "Database of the table",
+                ),
+                column_def("table", ValueType::String, false, "Table of the merge"),
+                column_def(
+                    "partition",
+                    ValueType::String,
+                    true,
+                    "Partition directory, NULL when table has no PARTITION BY",
+                ),
+                column_def(
+                    "source_parts",
+                    ValueType::Array(Box::new(ValueType::String)),
+                    false,
+                    "Merged parts, oldest first",
+                ),
+                column_def(
+                    "rows",
+                    ValueType::UInt64,
+                    false,
+                    "Number of rows of merged parts",
+                ),
+                column_def(
+                    "started_at",
+                    ValueType::UInt64,
+                    false,
+                    "Start of the merge, milliseconds since Unix epoch",
+                ),
+                column_def(
+                    "elapsed_ms",
+                    ValueType::UInt64,
+                    false,
+                    "Milliseconds since start of the merge",
+                ),
+                column_def(
+                    "phase",
+                    ValueType::String,
+                    false,
+                    "One of loading, merging, writing and moving",
+                ),
+                column_def(
+                    "is_manual",
+                    ValueType::Bool,
+                    false,
+                    "Whether merge is requested by OPTIMIZE TABLE",
+                ),
+            ],
+        }
+    }
+
+    /// Builds all rows of the table.
+    ///
+    /// Returns:
+    ///   * Ok: column per `column_defs`, in the same order.
+    ///   * Error: `CouldNotReadData` when file of a part could not be read (`system.parts`).
+    pub fn read(self) -> Result<Vec<Column>> {
+        let rows = match self {
+            Self::Parts => read_parts()?,
+            Self::Tables => read_tables(),
+            Self::Columns => read_columns(),
+            Self::Merges => read_merges(),
+        };
+
+        let mut columns: Vec<Column> = self
+            .column_defs()
+            .into_iter()
+            .map(|column_def| Column {
+                column_def,
+                data: Vec::with_capacity(rows.len()).into(),
+            })
+            .collect();
+        for row in rows {
+            for (column, value) in columns.iter_mut().zip(row) {
+                column.data.push(value);
+            }
+        }
+
+        Ok(columns)
+    }
+}
+
+fn column_def(name: &str, field_type: ValueType, nullable: bool, comment: &str) -> ColumnDef {
+    ColumnDef {
+        name: name.to_string(),
+        constraints: Constraints {
+            nullable,
+            compression_type: field_type.get_optimal_compression(),
+            comment: Some(comment.to_string()),
+            ..Constraints::default()
+        },
+        field_type,
+    }
+}
+
+/// Returns rows of `system.tables`, ordered by database and table.
+fn read_tables() -> Vec<Vec<Value>> {
+    let mut tables: Vec<_> = TABLE_DATA
+        .iter()
+        .map(|entry| {
+            let table_config = entry.value();
+            (
+                entry.key().clone(),
+                table_config.metadata.settings.engine.to_string(),
+                table_config.total_rows(),
+                table_config.infos.len() as u64,
+            )
+        })
+        .collect();
+    tables.sort_by(|(a, ..), (b, ..)| (&a.database, &a.table).cmp(&(&b.database, &b.table)));
+
+    tables
+        .into_iter()
+        .map(|(table_def, engine, total_rows, part_count)| {
+            vec![
+                Value::String(table_def.database),
+                Value::String(table_def.table),
+                Value::String(engine),
+                Value::UInt64(total_rows),
+                Value::UInt64(part_count),
+            ]
+        })
+        .collect()
+}
+
+/// Returns rows of `system.columns`, ordered by database and table, columns are in table order.
+fn read_columns() -> Vec<Vec<Value>> {
+    let mut tables: Vec<_> = TABLE_DATA
+        .iter()
+        .map(|entry| (entry.key().clone(), entry.value().metadata.schema.clone()))
+        .collect();
+    tables.sort_by(|(a, _), (b, _)| (&a.database, &a.table).cmp(&(&b.database, &b.table)));
+
+    let mut rows = Vec::new();
+    for (table_def, schema) in tables {
+        for column_def in &schema.columns {
+            let constraints = &column_def.constraints;
+            rows.push(vec![
+                Value::String(table_def.database.clone()),
+                Value::String(table_def.table.clone()),
+                Value::String(column_def.name.clone()),
+                Value::String(column_def.field_type.to_string()),
+                Value::Bool(constraints.nullable),
+                constraints
+                    .default
+                    .as_ref()
+                    .map_or(Value::Null, |default| Value::String(default.to_sql())),
+                Value::String(format!("{:?}", constraints.compression_type)),
+                Value::Bool(schema.primary_key.contains(column_def)),
+                Value::Bool(schema.order_by.contains(column_def)),
+            ]);
+        }
+    }
+    rows
+}
+
+/// Returns rows of `system.merges`, ordered by start of the merge.
+fn read_merges() -> Vec<Vec<Value>> {
+    let now = SystemTime::now();
+    BackgroundMerge::running_merges()
+        .into_iter()
+        .map(|merge| {
+            let millis = |duration: std::time::Duration| duration.as_millis() as u64;
+            vec![
+                Value::String(merge.table_def.database),
+                Value::String(merge.table_def.table),
+                merge.partition.map_or(Value::Null, Value::String),
+                Value::Array(merge.source_parts.into_iter().map(Value::String).collect()),
+                Value::UInt64(merge.rows),
+                Value::UInt64(
+                    merge
+                        .started_at
+                        .duration_since(UNIX_EPOCH)
+                        .map_or(0, millis),
+                ),
+                Value::UInt64(now.duration_since(merge.started_at).map_or(0, millis)),
+                Value::String(merge.phase.to_string()),
+                Value::Bool(merge.manual),
+            ]
+        })
+        .collect()
+}
+
+/// Part of `system.parts`, copied from `TABLE_DATA`.
+struct PartEntry {
+    table_def: TableDef,
+    sequence: u64,
+    path: PathBuf,
+    partition: Option<String>,
+    name: String,
+    row_count: u64,
+    marks: u64,
+    column_defs: Vec<ColumnDef>,
+}
+
+/// Returns rows of `system.parts`, ordered by database, table and part creation.
+fn read_parts() -> Result<Vec<Vec<Value>>> {
+    // files are read after `TABLE_DATA` is unlocked, so that queries and merges are not blocked
+    let mut parts = Vec::new();
+    for entry in TABLE_DATA.iter() {
+        for info in &entry.value().infos {
+            parts.push(PartEntry {
+                table_def: entry.key().clone(),
+                sequence: info.sequence,
+                path: info.get_path(entry.key()),
+                partition: info.partition.clone(),
+                name: info.name.clone(),
+                row_count: info.row_count,
+                marks: info.marks.len() as u64,
+                column_defs: info.column_defs.clone(),
+            });
+        }
+    }
+    parts.sort_by(|a, b| {
+        (&a.table_def.database, &a.table_def.table, a.sequence).cmp(&(
+            &b.table_def.database,
+            &b.table_def.table,
+            b.sequence,
+        ))
+    });
+
+    let mut rows = Vec::with_capacity(parts.len());
+    for part in parts {
+        let (bytes_on_disk, column_bytes) = match part_sizes(&part) {
+            Ok(sizes) => sizes,
+            // merged away since `TABLE_DATA` was read
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(error) => return Err(read_error(&part.path, &error)),
+        };
+        let column_compression = part
+            .column_defs
+            .iter()
+            .map(|column_def| {
+                (
+                    Value::String(column_def.name.clone()),
+                    Value::String(format!("{:?}", column_def.constraints.compression_type)),
+                )
+            })
+            .collect();
+
+        rows.push(vec![
+            Value::String(part.table_def.database),
+            Value::String(part.table_def.table),
+            part.partition.map_or(Value::Null, Value::String),
+            Value::String(part.name),
+            Value::UInt64(part.row_count),
+            Value::UInt64(part.marks),
+            Value::UInt64(bytes_on_disk),
+            Value::new_map(column_bytes)?,
+            Value::new_map(column_compression)?,
+        ]);
+    }
+
+    Ok(rows)
+}
+
+/// Returns size of all files of the part and size of the file of each column.
+fn part_sizes(part: &PartEntry) -> std::io::Result<(u64, Vec<(Value, Value)>)> {
+    let mut bytes_on_disk = 0;
+    for entry in std::fs::read_dir(&part.path)? {
+        bytes_on_disk += entry?.metadata()?.len();
+    }
+
+    let mut column_bytes = Vec::with_capacity(part.column_defs.len());
+    for column_def in &part.column_defs {
+        let bytes = std::fs::metadata(part.path.join(format!("{}.bin", column_def.name)))?.len();
+        column_bytes.push((Value::String(column_def.name.clone()), Value::UInt64(bytes)));
+    }
+
+    Ok((bytes_on_disk, column_bytes))
+}
+
+fn read_error(path: &Path, error: &std::io::Error) -> Error {
+    Error::CouldNotReadData(format!("Could not read {}: {error}", path.display()))
+}
@@ -0,0 +1,319 @@
+use crate::storage::Value;
+
+use serde::{Serialize, Serializer};
+use std::borrow::Cow;
+use std::ops::{Index, IndexMut, Range};
+
+/// Values of a `Column`.
+///
+/// Scan of parts, which lack selected columns, produces runs of `NULL` as long as the part.
+/// Such runs are kept as a single `Constant` value with a length, instead of a value per row,
+/// and are materialized only when a consumer needs a slice of values. Both representations are
+/// serialized the same way, so clients always receive a plain array.
+#[derive(Debug, Clone)]
+pub enum ColumnData {
+    Dense(Vec<Value>),
+    /// `len` copies of `value`.
+    Constant {
+        value: Value,
+        len: usize,
+    },
+}
+
+impl Default for ColumnData {
+    fn default() -> Self {
+        Self::Dense(Vec::new())
+    }
+}
+
+impl ColumnData {
+    /// Creates data with `len` copies of `value`.
+    pub const fn constant(value: Value, len: usize) -> Self {
+        Self::Constant { value, len }
+    }
+
+    /// Returns number of rows.
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Dense(values) => values.len(),
+            Self::Constant { len, .. } => *len,
+        }
+    }
+
+    /// Returns `true` when there are no rows.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns value of the row, or `None` when `idx` is out of bounds.
+    pub fn get(&self, idx: usize) -> Option<&Value> {
+        match self {
+            Self::Dense(values) => values.get(idx),
+            Self::Constant { value, len } => (idx < *len).then_some(value),
+        }
+    }
+
+    /// Returns iterator over values of all rows.
+    pub fn iter(&self) -> Iter<'_> {
+        match self {
+            Self::Dense(values) => Iter::Dense(values.iter()),
+            Self::Constant { value, len } => Iter::Constant(std::iter::repeat_n(value, *len)),
+        }
+    }
+
+    /// Returns values of `range` rows.
+    ///
+    /// Panics when `range` is out of bounds.
+    pub fn to_vec(&self, range: Range<usize>) -> Vec<Value> {
+        match self {
+            Self::Dense(values) => values[range].to_vec(),
+            Self::Constant { value, len } => {
+                assert!(range.end <= *len, "range {range:?} is out of {len} rows");
+                vec![value.clone(); range.len()]
+            }
+        }
+    }
+
+    /// Returns values of all rows as a slice, which is allocated only for `Constant` data.
+    pub fn values(&self) -> Cow<'_, [Value]> {
+        match self {
+            Self::Dense(values) => Cow::Borrowed(values),
+            Self::Constant { value, len } => Cow::Owned(vec![value.clone(); *len]),
+        }
+    }
+
+    /// Converts `Constant` data into a value per row.
+    ///
+    /// Returns: values of all rows, which may be changed in place.
+    pub fn make_dense(&mut self) -> &mut Vec<Value> {
+        if let Self::Constant { value, len } = self {
+            *self = Self::Dense(vec![std::mem::take(value); *len]);
+        }
+        match self {
+            Self::Dense(values) => values,
+            Self::Constant { .. } => unreachable!("constant data was converted above"),
+        }
+    }
+
+    /// Returns values of all rows.
+    pub fn into_values(self) -> Vec<Value> {
+        match self {
+            Self::Dense(values) => values,
+            Self::Constant { value, len } => vec![value; len],
+        }
+    }
+
+    /// Appends a row. Row equal to the value of `Constant` data keeps it constant.
+    pub fn push(&mut self, value: Value) {
+        match self {
+            Self::Constant {
+                value: constant,
+                len,
+            } if *constant == value => *len += 1,
+            _ => self.make_dense().push(value),
+        }
+    }
+
+    /// Appends `count` copies of `value`, without allocating a value per row, when data is
+    /// empty or holds the same constant.
+    pub fn push_constant(&mut self, value: Value, count: usize) {
+        match self {
+            Self::Dense(values) if values.is_empty() => *self = Self::constant(value, count),
+            Self::Constant {
+                value: constant,
+                len,
+            } if *constant == value => *len += count,
+            _ => self.make_dense().extend(std::iter::repeat_n(value, count)),
+        }
+    }
+
+    /// Appends all rows of `other`, keeping data constant when possible.
+    pub fn append(&mut self, other: Self) {
+        match other {
+            Self::Constant { value, len } => self.push_constant(value, len),
+            Self::Dense(values) if self.is_empty() => *self = Self::Dense(values),
+            Self::Dense(values) if values.is_empty() => {}
+            Self::Dense(values) => self.make_dense().extend(values),
+        }
+    }
+
+    /// Keeps only rows, whose value of `mask` is `true`. Rows past the end of `mask` are
+    /// removed.
+    pub fn retain_mask(&mut self, mask: &[bool]) {
+        match self {
+            Self::Dense(values) => {
+                let mut keep = mask.iter();
+                values.retain(|_| *keep.next().unwrap_or(&false));
+            }
+            Self::Constant { len, .. } => {
+                *len = mask[..(*len).min(mask.len())]
+                    .iter()
+                    .filter(|keep| **keep)
+                    .count();
+            }
+        }
+    }
+
+    /// Removes first `count` rows (all rows, when there are fewer).
+    pub fn remove_first(&mut self, count: usize) {
+        match self {
+            Self::Dense(values) => {
+                values.drain(..count.min(values.len()));
+            }
+            Self::Constant { len, .. } => *len = len.saturating_sub(count),
+        }
+    }
+
+    /// Keeps first `len` rows.
+    pub fn truncate(&mut self, len: usize) {
+        match self {
+            Self::Dense(values) => values.truncate(len),
+            Self::Constant {
+                len: constant_len, ..
+            } => *constant_len = (*constant_len).min(len),
+        }
+    }
+}
+
+impl Index<usize> for ColumnData {
+    type Output = Value;
+
+    fn index(&self, idx: usize) -> &Value {
+        self.get(idx)
+            .unwrap_or_else(|| panic!("row {idx} is out of {} rows", self.len()))
+    }
+}
+
+impl IndexMut<usize> for ColumnData {
+    /// Converts `Constant` data into a value per row, see `make_dense`.
+    fn index_mut(&mut self, idx: usize) -> &mut Value {
+        &mut self.make_dense()[idx]
+    }
+}
+
+impl PartialEq for ColumnData {
+    /// Data is equal, when values of all rows are equal, regardless of representation.
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl From<Vec<Value>> for ColumnData {
+    fn from(values: Vec<Value>) -> Self {
+        Self::Dense(values)
+    }
+}
+
+impl FromIterator<Value> for ColumnData {
+    fn from_iter<I: IntoIterator<Item = Value>>(iter: I) -> Self {
+        Self::Dense(iter.into_iter().collect())
+    }
+}
+
+impl Serialize for ColumnData {
+    /// Serialized as a sequence of values of all rows, the same as `Vec<Value>`.
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+/// Iterator over values of `ColumnData`, see `ColumnData::iter`.
+pub enum Iter<'a> {
+    Dense(std::slice::Iter<'a, Value>),
+    Constant(std::iter::RepeatN<&'a Value>),
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a Value;
+
+    fn next(&mut self) -> Option<&'a Value> {
+        match self {
+            Self::Dense(iter) => iter.next(),
+            Self::Constant(iter) => iter.next(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            Self::Dense(iter) => iter.size_hint(),
+            Self::Constant(iter) => iter.size_hint(),
+        }
+    }
+}
+
+impl ExactSizeIterator for Iter<'_> {}
+
+impl<'a> IntoIterator for &'a ColumnData {
+    type Item = &'a Value;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dense(values: &[u64]) -> ColumnData {
+        values.iter().copied().map(Value::UInt64).collect()
+    }
+
+    #[test]
+    fn test_constant_data_equals_dense_data() {
+        let constant = ColumnData::constant(Value::Null, 3);
+
+        assert_eq!(constant, ColumnData::from(vec![Value::Null; 3]));
+        assert_ne!(constant, ColumnData::from(vec![Value::Null; 2]));
+        assert_eq!(constant.len(), 3);
+        assert_eq!(constant[2], Value::Null);
+        assert_eq!(constant.get(3), None);
+        assert_eq!(
+            rmp_serde::to_vec(&constant).unwrap(),
+            rmp_serde::to_vec(&vec![Value::Null; 3]).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_appending_keeps_data_constant() {
+        let mut data = ColumnData::default();
+        data.push_constant(Value::Null, 2);
+        data.append(ColumnData::constant(Value::Null, 3));
+        data.push(Value::Null);
+        data.append(ColumnData::default());
+        assert!(matches!(data, ColumnData::Constant { len: 6, .. }));
+
+        // other value materializes the data
+        data.push(Value::UInt64(1));
+        assert!(matches!(data, ColumnData::Dense(_)));
+        let mut expected = vec![Value::Null; 6];
+        expected.push(Value::UInt64(1));
+        assert_eq!(data, ColumnData::from(expected));
+
+        let mut data = dense(&[1, 2]);
+        data.append(ColumnData::constant(Value::UInt64(3), 2));
+        assert_eq!(data, dense(&[1, 2, 3, 3]));
+    }
+
+    #[test]
+    fn test_row_removal_of_constant_data() {
+        let mut data = ColumnData::constant(Value::UInt64(7), 10);
+        data.retain_mask(&[
+            true, false, true, true, false, true, true, true, false, true,
+        ]);
+        assert_eq!(data.len(), 7);
+        data.remove_first(2);
+        assert_eq!(data.len(), 5);
+        data.truncate(3);
+        assert_eq!(data, dense(&[7, 7, 7]));
+        assert_eq!(data.to_vec(1..3), vec![Value::UInt64(7); 2]);
+        assert!(matches!(data, ColumnData::Constant { .. }));
+
+        let mut data = dense(&[1, 2, 3, 4]);
+        data.retain_mask(&[true, false, true]);
+        assert_eq!(data, dense(&[1, 3]));
+        data.remove_first(5);
+        assert!(data.is_empty());
+    }
+}
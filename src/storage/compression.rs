@@ -1,13 +1,22 @@
 use crate::error::{Error, Result};
-use crate::storage::ValueType;
+use crate::storage::{Value, ValueType};
+use rkyv::util::AlignedVec;
 use rkyv::{Archive as RkyvArchive, Deserialize as RkyvDeserialize, Serialize as RkyvSerialize};
 use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::io::{Read as _, Write as _};
 
 #[derive(Debug, Clone, PartialEq, Serialize, RkyvArchive, RkyvSerialize, RkyvDeserialize)]
 pub enum CompressionType {
     None,
     LZ4(u8),
+    /// Distinct values of the column are stored once per part, granules store codes (indexes
+    /// into the dictionary) of the smallest fitting width. Both are compressed with LZ4 of the
+    /// given level. Only for `String` columns (`LowCardinality(String)`).
+    /// Column of a part with over `MAX_DICTIONARY_SIZE` distinct values stores an empty
+    /// dictionary and plain granules, like `LZ4`.
+    Dictionary(u8),
 }
 
 impl Default for CompressionType {
@@ -29,7 +38,7 @@ impl ValueType {
 ///   * Error: `CouldNotInsertData` on compression failure.
 pub fn compress_bytes(bytes: &[u8], compression_type: &CompressionType) -> Result<Vec<u8>> {
     match *compression_type {
-        CompressionType::LZ4(level) => {
+        CompressionType::LZ4(level) | CompressionType::Dictionary(level) => {
             let output = Vec::with_capacity(bytes.len() / 2); // on average compresses 2x
             let mut encoder = lz4::EncoderBuilder::new()
                 .level(u32::from(level))
@@ -55,7 +64,7 @@ pub fn decompress_bytes(
     compression_type: &CompressionType,
 ) -> Result<Vec<u8>> {
     match compression_type {
-        CompressionType::LZ4(_) => {
+        CompressionType::LZ4(_) | CompressionType::Dictionary(_) => {
             let mut decoder = lz4::Decoder::new(compressed_bytes).map_err(|error| {
                 Error::CouldNotReadData(format!("Failed to create LZ4 decoder: {error}"))
             })?;
@@ -68,3 +77,366 @@ pub fn decompress_bytes(
         CompressionType::None => Ok(compressed_bytes.to_vec()),
     }
 }
+
+/// Dictionary with more distinct values makes column of the part plain-encoded, as codes no longer
+/// save space (see `TablePart::write_column_with_marks`).
+pub const MAX_DICTIONARY_SIZE: usize = 100_000;
+
+/// Builds dictionary of distinct values (in order of first occurrence) and code of each value.
+///
+/// Returns:
+///   * Ok: dictionary and codes of `values`.
+///   * Error: `CouldNotInsertData` when value is neither `String` nor `NULL`.
+pub fn build_dictionary(values: &[Value]) -> Result<(Vec<Value>, Vec<u32>)> {
+    let mut dictionary = Vec::new();
+    let mut codes_by_value: HashMap<Option<&str>, u32> = HashMap::new();
+    let mut codes = Vec::with_capacity(values.len());
+
+    for value in values {
+        let code = *codes_by_value
+            .entry(dictionary_key(value)?)
+            .or_insert_with(|| {
+                dictionary.push(value.clone());
+                (dictionary.len() - 1) as u32
+            });
+        codes.push(code);
+    }
+
+    Ok((dictionary, codes))
+}
+
+/// Returns key of the value in a dictionary, `None` for `NULL`.
+///
+/// Returns: Ok or `CouldNotInsertData` when value is neither `String` nor `NULL`.
+fn dictionary_key(value: &Value) -> Result<Option<&str>> {
+    match value {
+        Value::String(string) => Ok(Some(string.as_str())),
+        Value::Null => Ok(None),
+        value => Err(Error::CouldNotInsertData(format!(
+            "Dictionary encoding supports only String values, received: {value:?}"
+        ))),
+    }
+}
+
+/// Builds dictionary like `build_dictionary`, but from values added granule by granule, so
+/// codes are assigned, once all values are known. Dictionary is dropped, once it grows over
+/// `MAX_DICTIONARY_SIZE`, as column is plain-encoded then.
+#[derive(Debug, Default)]
+pub struct DictionaryBuilder {
+    dictionary: Vec<Value>,
+    codes_by_value: HashMap<String, u32>,
+    null_code: Option<u32>,
+    overflowed: bool,
+}
+
+impl DictionaryBuilder {
+    /// Adds distinct values of `values` to the dictionary.
+    ///
+    /// Returns: Ok or `CouldNotInsertData` when value is neither `String` nor `NULL`.
+    pub fn add(&mut self, values: &[Value]) -> Result<()> {
+        for value in values {
+            let key = dictionary_key(value)?;
+            if self.overflowed {
+                continue;
+            }
+            let code = self.dictionary.len() as u32;
+            let added = match key {
+                Some(string) if !self.codes_by_value.contains_key(string) => {
+                    self.codes_by_value.insert(string.to_string(), code);
+                    true
+                }
+                None if self.null_code.is_none() => {
+                    self.null_code = Some(code);
+                    true
+                }
+                _ => false,
+            };
+            if added {
+                self.dictionary.push(value.clone());
+            }
+            if self.dictionary.len() > MAX_DICTIONARY_SIZE {
+                *self = Self {
+                    overflowed: true,
+                    ..Self::default()
+                };
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns dictionary of all added values, or `None` when it has over `MAX_DICTIONARY_SIZE`
+    /// values.
+    pub fn dictionary(&self) -> Option<&Vec<Value>> {
+        (!self.overflowed).then_some(&self.dictionary)
+    }
+
+    /// Returns code of each value, see `build_dictionary`.
+    ///
+    /// Returns: codes, or `CouldNotInsertData` when value was not added or dictionary overflowed.
+    pub fn codes(&self, values: &[Value]) -> Result<Vec<u32>> {
+        values
+            .iter()
+            .map(|value| {
+                let code = match dictionary_key(value)? {
+                    Some(string) => self.codes_by_value.get(string).copied(),
+                    None => self.null_code,
+                };
+                code.ok_or_else(|| {
+                    Error::CouldNotInsertData(format!(
+                        "Value {value:?} is missing in the dictionary"
+                    ))
+                })
+            })
+            .collect()
+    }
+}
+
+/// Returns width in bytes of a single code for dictionary of `dictionary_len` values.
+const fn code_width(dictionary_len: usize) -> usize {
+    if dictionary_len <= 1 << 8 {
+        1
+    } else if dictionary_len <= 1 << 16 {
+        2
+    } else {
+        4
+    }
+}
+
+/// Encodes codes as little-endian integers of the smallest width fitting the dictionary.
+pub fn encode_codes(codes: &[u32], dictionary_len: usize) -> Vec<u8> {
+    let width = code_width(dictionary_len);
+    let mut bytes = Vec::with_capacity(codes.len() * width);
+    for code in codes {
+        bytes.extend_from_slice(&code.to_le_bytes()[..width]);
+    }
+    bytes
+}
+
+/// Decodes codes produced by `encode_codes` into values of the dictionary.
+///
+/// Returns:
+///   * Ok: values of the granule.
+///   * Error: `CouldNotReadData` when bytes are truncated or code is out of dictionary bounds.
+pub fn decode_codes(bytes: &[u8], dictionary: &[Value]) -> Result<Vec<Value>> {
+    let width = code_width(dictionary.len());
+    if !bytes.len().is_multiple_of(width) {
+        return Err(Error::CouldNotReadData(format!(
+            "Dictionary codes size ({}) is not a multiple of code width ({width})",
+            bytes.len()
+        )));
+    }
+
+    bytes
+        .chunks_exact(width)
+        .map(|chunk| {
+            let mut code = [0; 4];
+            code[..width].copy_from_slice(chunk);
+            let code = u32::from_le_bytes(code) as usize;
+            dictionary.get(code).cloned().ok_or_else(|| {
+                Error::CouldNotReadData(format!(
+                    "Dictionary code ({code}) is out of bounds ({})",
+                    dictionary.len()
+                ))
+            })
+        })
+        .collect()
+}
+
+/// Compression of `part.inf` and `.metadata` files, when `compress_info_files` is enabled.
+const INFO_FILE_COMPRESSION: CompressionType = CompressionType::LZ4(3);
+
+/// Builds file of a single serialized struct (`part.inf` or `.metadata`): magic bytes, data and
+/// CRC32 of the stored data. Data is compressed, when `compress` is set, and such file starts
+/// with `compressed_magic` instead of `magic`, so files, written before compression was added,
+/// are read as they are (see `decode_info_file`).
+///
+/// Returns:
+///   * Ok: bytes of the file.
+///   * Error: `CouldNotInsertData` on compression failure.
+pub fn encode_info_file(
+    data: &[u8],
+    magic: &[u8],
+    compressed_magic: &[u8],
+    compress: bool,
+) -> Result<Vec<u8>> {
+    let (magic, data) = if compress {
+        let compressed = compress_bytes(data, &INFO_FILE_COMPRESSION)?;
+        (compressed_magic, Cow::Owned(compressed))
+    } else {
+        (magic, Cow::Borrowed(data))
+    };
+    let mut bytes = Vec::with_capacity(magic.len() + data.len() + 4);
+    bytes.extend_from_slice(magic);
+    bytes.extend_from_slice(&data);
+    bytes.extend(crc32fast::hash(&data).to_le_bytes());
+    Ok(bytes)
+}
+
+/// Reads data of a file, built by `encode_info_file`, verifying its magic bytes and CRC32
+/// checksum, and decompressing it, when file starts with `compressed_magic`. `name` of the file
+/// is used in error messages.
+///
+/// Returns:
+///   * Ok: data, aligned for deserialization.
+///   * Error: `CouldNotReadData` when file is too small, has unknown magic bytes, CRC mismatch,
+///     or data could not be decompressed.
+pub fn decode_info_file(
+    file_bytes: &[u8],
+    magic: &[u8],
+    compressed_magic: &[u8],
+    name: &str,
+) -> Result<AlignedVec<16>> {
+    if file_bytes.len() <= magic.len() + 4 {
+        return Err(Error::CouldNotReadData(format!("Too few bytes in {name}")));
+    }
+
+    let (file_magic, rest) = file_bytes.split_at(magic.len());
+    let compressed = if file_magic == magic {
+        false
+    } else if file_magic == compressed_magic {
+        true
+    } else {
+        return Err(Error::CouldNotReadData(format!(
+            "Invalid magic bytes in {name}"
+        )));
+    };
+
+    let (data, crc) = rest.split_at(rest.len() - 4);
+    let expected_crc = u32::from_le_bytes([crc[0], crc[1], crc[2], crc[3]]);
+    if crc32fast::hash(data) != expected_crc {
+        return Err(Error::CouldNotReadData(format!("CRC mismatch in {name}")));
+    }
+
+    let data = if compressed {
+        Cow::Owned(
+            decompress_bytes(data, &INFO_FILE_COMPRESSION)
+                .map_err(|error| error.with_read_context(name))?,
+        )
+    } else {
+        Cow::Borrowed(data)
+    };
+    // data is not aligned correctly, because of magic bytes
+    let mut aligned_data = AlignedVec::<16>::with_capacity(data.len());
+    aligned_data.extend_from_slice(&data);
+    Ok(aligned_data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dictionary_roundtrip() {
+        let values = vec![
+            Value::String("US".to_string()),
+            Value::Null,
+            Value::String("DE".to_string()),
+            Value::String("US".to_string()),
+            Value::Null,
+        ];
+        let (dictionary, codes) = build_dictionary(&values).unwrap();
+        assert_eq!(
+            dictionary,
+            [
+                Value::String("US".to_string()),
+                Value::Null,
+                Value::String("DE".to_string())
+            ]
+        );
+        assert_eq!(codes, [0, 1, 2, 0, 1]);
+
+        let bytes = encode_codes(&codes, dictionary.len());
+        assert_eq!(bytes.len(), values.len());
+        assert_eq!(decode_codes(&bytes, &dictionary).unwrap(), values);
+
+        assert!(matches!(
+            decode_codes(&[3], &dictionary),
+            Err(Error::CouldNotReadData(_))
+        ));
+        assert!(matches!(
+            build_dictionary(&[Value::UInt8(1)]),
+            Err(Error::CouldNotInsertData(_))
+        ));
+    }
+
+    #[test]
+    fn test_dictionary_builder_matches_build_dictionary() {
+        let values: Vec<Value> = [Some("US"), None, Some("DE"), Some("US"), None, Some("FR")]
+            .into_iter()
+            .map(|value| value.map_or(Value::Null, |value| Value::String(value.to_string())))
+            .collect();
+        let (dictionary, codes) = build_dictionary(&values).unwrap();
+
+        let mut builder = DictionaryBuilder::default();
+        for granule in values.chunks(4) {
+            builder.add(granule).unwrap();
+        }
+        assert_eq!(builder.dictionary(), Some(&dictionary));
+        assert_eq!(builder.codes(&values).unwrap(), codes);
+        assert!(matches!(
+            builder.add(&[Value::UInt8(1)]),
+            Err(Error::CouldNotInsertData(_))
+        ));
+
+        let distinct: Vec<Value> = (0..=MAX_DICTIONARY_SIZE)
+            .map(|idx| Value::String(idx.to_string()))
+            .collect();
+        builder.add(&distinct).unwrap();
+        assert_eq!(builder.dictionary(), None);
+        assert!(builder.codes(&values[..1]).is_err());
+    }
+
+    #[test]
+    fn test_code_width_grows_with_dictionary() {
+        let dictionary: Vec<Value> = (0..300).map(|idx| Value::String(idx.to_string())).collect();
+        let codes = [0, 299, 256];
+
+        let bytes = encode_codes(&codes, dictionary.len());
+        assert_eq!(bytes.len(), codes.len() * 2);
+        assert_eq!(
+            decode_codes(&bytes, &dictionary).unwrap(),
+            [
+                dictionary[0].clone(),
+                dictionary[299].clone(),
+                dictionary[256].clone()
+            ]
+        );
+        assert_eq!(code_width(1 << 16), 2);
+        assert_eq!(code_width((1 << 16) + 1), 4);
+    }
+
+    #[test]
+    fn test_info_file_round_trip() {
+        let data: Vec<u8> = (0..1000).map(|idx| (idx % 7) as u8).collect();
+        let decode = |bytes: &[u8]| decode_info_file(bytes, b"MAGIC", b"MAGIZ", "test file");
+
+        let plain = encode_info_file(&data, b"MAGIC", b"MAGIZ", false).unwrap();
+        assert_eq!(&plain[..5], b"MAGIC");
+        assert_eq!(plain.len(), 5 + data.len() + 4);
+        assert_eq!(decode(&plain).unwrap().as_slice(), data);
+
+        let compressed = encode_info_file(&data, b"MAGIC", b"MAGIZ", true).unwrap();
+        assert_eq!(&compressed[..5], b"MAGIZ");
+        assert!(compressed.len() < plain.len());
+        assert_eq!(decode(&compressed).unwrap().as_slice(), data);
+
+        for mut bytes in [plain, compressed] {
+            let last = bytes.len() - 5;
+            bytes[last] ^= 1;
+            let Err(Error::CouldNotReadData(message)) = decode(&bytes) else {
+                panic!("Expected CRC mismatch");
+            };
+            assert!(message.contains("CRC mismatch in test file"), "{message}");
+        }
+
+        // compressed data with valid CRC, which is not LZ4
+        let mut bytes = Vec::from(b"MAGIZ".as_slice());
+        bytes.extend_from_slice(&data);
+        bytes.extend(crc32fast::hash(&data).to_le_bytes());
+        assert!(matches!(decode(&bytes), Err(Error::CouldNotReadData(_))));
+
+        for bytes in [b"OTHER1234".as_slice(), b"MAGIC1234".as_slice()] {
+            assert!(matches!(decode(bytes), Err(Error::CouldNotReadData(_))));
+        }
+    }
+}
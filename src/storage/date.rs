@@ -0,0 +1,160 @@
+//! Calendar of `Date` and `DateTime` values: days and seconds since `1970-01-01 00:00:00` UTC,
+//! in the proleptic Gregorian calendar.
+
+/// Seconds in a day, there are no leap seconds in unix time.
+pub const SECONDS_PER_DAY: i64 = 86_400;
+
+/// Returns `(year, month, day)` of the day, which is `days` after `1970-01-01`.
+///
+/// Uses the algorithm of Howard Hinnant (`civil_from_days`), which counts in 400-year eras
+/// starting on March 1, so leap days are at the end of an era year.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // month and day are small
+pub const fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468; // days since 0000-03-01
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_from_march + 2) / 5 + 1) as u32;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Returns days since `1970-01-01` of the date, inverse of `civil_from_days`. Date is expected
+/// to be valid, see `days_in_month`.
+pub const fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month_from_march = (month as i64 + 9) % 12;
+    let day_of_year = (153 * month_from_march + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Returns number of days in the month of the year, 0 for invalid month.
+pub const fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        _ => 0,
+    }
+}
+
+/// Parses `YYYY-MM-DD` into days since `1970-01-01`.
+///
+/// Returns:
+///   * Some: days, which may be negative.
+///   * None: text is not a valid date in this format.
+pub fn parse_days(text: &str) -> Option<i64> {
+    let [year, month, day] = split_numbers(text, '-', [4, 2, 2])?;
+    let (month, day) = (u32::try_from(month).ok()?, u32::try_from(day).ok()?);
+    if day == 0 || day > days_in_month(year, month) {
+        return None;
+    }
+    Some(days_from_civil(year, month, day))
+}
+
+/// Parses `YYYY-MM-DD hh:mm:ss` (also with `T` separator) or `YYYY-MM-DD` (midnight) into
+/// seconds since `1970-01-01 00:00:00` UTC.
+///
+/// Returns:
+///   * Some: seconds, which may be negative.
+///   * None: text is not a valid date and time in these formats.
+pub fn parse_seconds(text: &str) -> Option<i64> {
+    let Some((date, time)) = text.split_once([' ', 'T']) else {
+        return Some(parse_days(text)? * SECONDS_PER_DAY);
+    };
+    let [hour, minute, second] = split_numbers(time, ':', [2, 2, 2])?;
+    if hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+    Some(parse_days(date)? * SECONDS_PER_DAY + hour * 3600 + minute * 60 + second)
+}
+
+/// Formats days since `1970-01-01` as `YYYY-MM-DD`.
+pub fn format_days(days: i64) -> String {
+    let (year, month, day) = civil_from_days(days);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// Formats seconds since `1970-01-01 00:00:00` UTC as `YYYY-MM-DD hh:mm:ss`.
+pub fn format_seconds(seconds: i64) -> String {
+    let time = seconds.rem_euclid(SECONDS_PER_DAY);
+    format!(
+        "{} {:02}:{:02}:{:02}",
+        format_days(seconds.div_euclid(SECONDS_PER_DAY)),
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+/// Splits text into three decimal numbers of exactly `widths` digits each.
+fn split_numbers(text: &str, separator: char, widths: [usize; 3]) -> Option<[i64; 3]> {
+    let mut parts = text.split(separator);
+    let mut numbers = [0; 3];
+    for (number, width) in numbers.iter_mut().zip(widths) {
+        let part = parts.next()?;
+        if part.len() != width || !part.bytes().all(|byte| byte.is_ascii_digit()) {
+            return None;
+        }
+        *number = part.parse().ok()?;
+    }
+    parts.next().is_none().then_some(numbers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calendar_round_trip() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(civil_from_days(19_723), (2024, 1, 1));
+        assert_eq!(civil_from_days(19_782), (2024, 2, 29));
+        assert_eq!(civil_from_days(u16::MAX.into()), (2149, 6, 6));
+        for days in -800_000..800_000 {
+            let (year, month, day) = civil_from_days(days);
+            assert!(day >= 1 && day <= days_in_month(year, month));
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+    }
+
+    #[test]
+    fn test_parse_and_format() {
+        assert_eq!(parse_days("2024-02-29"), Some(19_782));
+        assert_eq!(parse_seconds("2024-01-01 12:30:05"), Some(1_704_112_205));
+        assert_eq!(parse_seconds("2024-01-01T12:30:05"), Some(1_704_112_205));
+        assert_eq!(parse_seconds("2024-01-01"), Some(1_704_067_200));
+        assert_eq!(format_days(19_782), "2024-02-29");
+        assert_eq!(format_seconds(1_704_112_205), "2024-01-01 12:30:05");
+        assert_eq!(format_seconds(-1), "1969-12-31 23:59:59");
+
+        for text in [
+            "2023-02-29",
+            "2024-13-01",
+            "2024-00-10",
+            "2024-1-01",
+            "24-01-01",
+            "2024-01-01 24:00:00",
+            "2024-01-01 12:60:00",
+            "2024-01-01 12:00",
+            "2024-01-01-01",
+            "+024-01-01",
+            "",
+        ] {
+            assert_eq!(parse_seconds(text), None, "{text}");
+        }
+    }
+}
@@ -0,0 +1,216 @@
+use crate::CONFIG;
+use crate::error::{Error, Result};
+use crate::runtime_config::TABLE_DATA;
+use crate::storage::{Column, TableDef, TablePartInfo};
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, PoisonError, RwLock};
+
+/// Source of free space of a file system. Replaced in tests (see `set_space_checker`) to
+/// simulate a full disk.
+pub trait SpaceChecker: Send + Sync {
+    /// Returns bytes available to unprivileged writers on the file system of `path`.
+    fn available_bytes(&self, path: &Path) -> std::io::Result<u64>;
+}
+
+/// Reads free space with `statvfs`.
+struct StatvfsChecker;
+
+impl SpaceChecker for StatvfsChecker {
+    #[cfg(unix)]
+    fn available_bytes(&self, path: &Path) -> std::io::Result<u64> {
+        use std::os::unix::ffi::OsStrExt;
+
+        let path =
+            std::ffi::CString::new(path.as_os_str().as_bytes()).map_err(std::io::Error::other)?;
+        // SAFETY: `path` is a valid C string, and `stats` is written by `statvfs` on success.
+        let stats = unsafe {
+            let mut stats = std::mem::zeroed::<libc::statvfs>();
+            if libc::statvfs(path.as_ptr(), &raw mut stats) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            stats
+        };
+        #[allow(clippy::unnecessary_cast)] // field types differ between platforms
+        Ok((stats.f_bavail as u64).saturating_mul(stats.f_frsize as u64))
+    }
+
+    /// Free space is not checked on other platforms.
+    #[cfg(not(unix))]
+    fn available_bytes(&self, _path: &Path) -> std::io::Result<u64> {
+        Ok(u64::MAX)
+    }
+}
+
+static SPACE_CHECKER: LazyLock<RwLock<Arc<dyn SpaceChecker>>> =
+    LazyLock::new(|| RwLock::new(Arc::new(StatvfsChecker)));
+
+/// Replaces the source of free space for the whole process, e.g., with a fake one in tests.
+pub fn set_space_checker(checker: Arc<dyn SpaceChecker>) {
+    *SPACE_CHECKER
+        .write()
+        .unwrap_or_else(PoisonError::into_inner) = checker;
+}
+
+/// Checks, that writing `bytes` into the storage directory leaves at least `min_free_bytes`.
+///
+/// Returns:
+///   * Ok: there is enough space.
+///   * Error: `DiskFull` when there is not, or `CouldNotInsertData` when free space could not be
+///     read.
+pub(crate) fn ensure_free_space(bytes: u64) -> Result<()> {
+    let checker = Arc::clone(&SPACE_CHECKER.read().unwrap_or_else(PoisonError::into_inner));
+    check_free_space(
+        checker.as_ref(),
+        CONFIG.get_db_dir(),
+        bytes,
+        CONFIG.get_min_free_bytes(),
+    )
+}
+
+fn check_free_space(
+    checker: &dyn SpaceChecker,
+    path: &Path,
+    bytes: u64,
+    min_free_bytes: u64,
+) -> Result<()> {
+    let available = checker.available_bytes(path).map_err(|error| {
+        Error::CouldNotInsertData(format!(
+            "Could not read free space of {}: {error}",
+            path.display()
+        ))
+    })?;
+    let required = min_free_bytes.saturating_add(bytes);
+    if available < required {
+        return Err(Error::DiskFull(format!(
+            "{available} bytes are available, writing {bytes} bytes requires {required} \
+             (min_free_bytes is {min_free_bytes})"
+        )));
+    }
+    Ok(())
+}
+
+/// Checks, that `database` stays within its quota (`[quotas]` of the config) after writing
+/// `bytes`. Usage is the size of files of active parts of its tables.
+///
+/// Returns:
+///   * Ok: database has no quota, or stays within it.
+///   * Error: `QuotaExceeded` when quota would be exceeded, or `CouldNotReadData` when part
+///     files could not be read.
+pub(crate) fn ensure_quota(database: &str, bytes: u64) -> Result<()> {
+    let Some(quota) = CONFIG.get_database_quota(database) else {
+        return Ok(());
+    };
+    let used = database_bytes(database)?;
+    if used.saturating_add(bytes) > quota {
+        return Err(Error::QuotaExceeded(database.to_string(), used, quota));
+    }
+    Ok(())
+}
+
+/// Returns size of files of active parts of all tables of `database`.
+fn database_bytes(database: &str) -> Result<u64> {
+    // files are read after `TABLE_DATA` is unlocked, so that queries and merges are not blocked
+    let part_dirs: Vec<PathBuf> = TABLE_DATA
+        .iter()
+        .filter(|entry| entry.key().database == database)
+        .flat_map(|entry| {
+            entry
+                .value()
+                .infos
+                .iter()
+                .map(|info| info.get_path(entry.key()))
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    let mut bytes = 0_u64;
+    for part_dir in part_dirs {
+        match dir_bytes(&part_dir) {
+            Ok(part_bytes) => bytes = bytes.saturating_add(part_bytes),
+            // merged away since `TABLE_DATA` was read
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+            Err(error) => {
+                return Err(Error::CouldNotReadData(format!(
+                    "Could not read {}: {error}",
+                    part_dir.display()
+                )));
+            }
+        }
+    }
+    Ok(bytes)
+}
+
+/// Returns size of files in the part directory.
+fn dir_bytes(dir: &Path) -> std::io::Result<u64> {
+    let mut bytes = 0;
+    for entry in std::fs::read_dir(dir)? {
+        bytes += entry?.metadata()?.len();
+    }
+    Ok(bytes)
+}
+
+/// Returns estimated size of a part with `columns`: uncompressed size of their values (see
+/// `Value::archived_size`), which compressed files do not exceed in practice.
+pub(crate) fn estimate_part_bytes(columns: &[Column]) -> u64 {
+    columns
+        .iter()
+        .flat_map(|column| column.data.iter())
+        .map(|value| value.archived_size() as u64)
+        .sum()
+}
+
+/// Returns size of files of the given parts of the table, which merge writes again.
+pub(crate) fn parts_bytes<'a>(
+    table_def: &TableDef,
+    parts: impl IntoIterator<Item = &'a TablePartInfo>,
+) -> u64 {
+    parts
+        .into_iter()
+        .map(|info| dir_bytes(&info.get_path(table_def)).unwrap_or(0))
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reports given free space, or fails without it.
+    struct FakeChecker(Option<u64>);
+
+    impl SpaceChecker for FakeChecker {
+        fn available_bytes(&self, _path: &Path) -> std::io::Result<u64> {
+            self.0.ok_or_else(|| std::io::ErrorKind::NotFound.into())
+        }
+    }
+
+    #[test]
+    fn test_free_space_covers_write_and_min_free_bytes() {
+        let path = Path::new("db_files");
+        let check =
+            |available, bytes| check_free_space(&FakeChecker(Some(available)), path, bytes, 100);
+
+        assert!(check(150, 50).is_ok());
+        assert!(matches!(check(149, 50), Err(Error::DiskFull(_))));
+        assert!(matches!(check(99, 0), Err(Error::DiskFull(_))));
+
+        assert!(matches!(
+            check_free_space(&FakeChecker(None), path, 0, 0),
+            Err(Error::CouldNotInsertData(_))
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_statvfs_reports_free_space() {
+        let available = StatvfsChecker
+            .available_bytes(&std::env::temp_dir())
+            .unwrap();
+        assert!(available > 0);
+        assert!(
+            StatvfsChecker
+                .available_bytes(Path::new("/missing/directory"))
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use crate::error::{Error, Result};
+use crate::storage::TableDef;
+
+use log::{info, warn};
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+
+/// Intent of the running exchange: names of both tables, one per line. Present only while
+/// directories are being renamed, so startup can finish or undo an interrupted exchange.
+const EXCHANGE_JOURNAL_FILENAME: &str = ".exchange.journal";
+/// Temporary name of the first table directory during exchange.
+const EXCHANGE_TMP_DIRNAME: &str = ".exchange";
+
+/// Swaps directories of two tables in `db_dir` with three renames: `first` to a temporary name,
+/// `second` to `first` and the temporary name to `second`. Intent is written to the journal
+/// beforehand and removed afterwards, see `recover_exchange`.
+///
+/// On failure, already renamed directories are moved back. When that fails too, the journal
+/// is kept, so the next startup brings directories to a consistent state.
+///
+/// Returns:
+///   * Ok: directories are swapped.
+///   * Error: `Internal` when journal could not be written, or directory could not be renamed.
+pub fn exchange_table_dirs(db_dir: &Path, first: &TableDef, second: &TableDef) -> Result<()> {
+    let first_path = table_path(db_dir, first);
+    let second_path = table_path(db_dir, second);
+    let tmp_path = db_dir.join(EXCHANGE_TMP_DIRNAME);
+
+    write_journal(db_dir, first, second)?;
+
+    if let Err(error) = std::fs::rename(&first_path, &tmp_path) {
+        remove_journal(db_dir);
+        return Err(Error::Internal(format!(
+            "Could not move table {first} for exchange: {error}"
+        )));
+    }
+
+    if let Err(error) = std::fs::rename(&second_path, &first_path) {
+        if std::fs::rename(&tmp_path, &first_path).is_ok() {
+            remove_journal(db_dir);
+        }
+        return Err(Error::Internal(format!(
+            "Could not move table {second} for exchange: {error}"
+        )));
+    }
+
+    if let Err(error) = std::fs::rename(&tmp_path, &second_path) {
+        if std::fs::rename(&first_path, &second_path).is_ok()
+            && std::fs::rename(&tmp_path, &first_path).is_ok()
+        {
+            remove_journal(db_dir);
+        }
+        return Err(Error::Internal(format!(
+            "Could not move table {first} for exchange: {error}"
+        )));
+    }
+
+    remove_journal(db_dir);
+    Ok(())
+}
+
+/// Finishes or undoes exchange, which was interrupted (e.g., by a crash), using the journal.
+///
+/// When the temporary directory exists, it holds the first table. It is moved back, when the
+/// first table directory is missing (exchange is undone), or to the second table directory,
+/// when only that one is missing (exchange is finished). Otherwise, either no directory was
+/// renamed yet, or all were, and both states are consistent.
+///
+/// Returns:
+///   * Ok: directories are consistent and the journal is removed (or there was none).
+///   * Error:
+///     1. `CouldNotReadData` when journal could not be read or is malformed.
+///     2. `Internal` when directory could not be renamed, or all three directories exist.
+pub fn recover_exchange(db_dir: &Path) -> Result<()> {
+    let Some((first, second)) = read_journal(db_dir)? else {
+        return Ok(());
+    };
+    let first_path = table_path(db_dir, &first);
+    let second_path = table_path(db_dir, &second);
+    let tmp_path = db_dir.join(EXCHANGE_TMP_DIRNAME);
+
+    if tmp_path.exists() {
+        let target = if !first_path.exists() {
+            info!("Undoing interrupted exchange of tables {first} and {second}");
+            &first_path
+        } else if !second_path.exists() {
+            info!("Finishing interrupted exchange of tables {first} and {second}");
+            &second_path
+        } else {
+            return Err(Error::Internal(format!(
+                "Interrupted exchange of tables {first} and {second} left {}, but both tables exist. Move it manually and remove {}.",
+                tmp_path.display(),
+                db_dir.join(EXCHANGE_JOURNAL_FILENAME).display()
+            )));
+        };
+        std::fs::rename(&tmp_path, target).map_err(|error| {
+            Error::Internal(format!(
+                "Could not recover interrupted exchange of tables {first} and {second}: {error}"
+            ))
+        })?;
+    }
+
+    remove_journal(db_dir);
+    Ok(())
+}
+
+fn table_path(db_dir: &Path, table_def: &TableDef) -> PathBuf {
+    db_dir.join(&table_def.database).join(&table_def.table)
+}
+
+/// Writes journal atomically: to a temporary file first, which is then renamed.
+fn write_journal(db_dir: &Path, first: &TableDef, second: &TableDef) -> Result<()> {
+    let journal_path = db_dir.join(EXCHANGE_JOURNAL_FILENAME);
+    let tmp_journal_path = journal_path.with_extension("journal.tmp");
+    let contents = format!(
+        "{}\n{}\n{}\n{}\n",
+        first.database, first.table, second.database, second.table
+    );
+
+    let write = || -> std::io::Result<()> {
+        let mut file = std::fs::File::create(&tmp_journal_path)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(&tmp_journal_path, &journal_path)
+    };
+    write().map_err(|error| Error::Internal(format!("Could not write exchange journal: {error}")))
+}
+
+/// Returns: both tables of the journal, `None` when there is no journal.
+fn read_journal(db_dir: &Path) -> Result<Option<(TableDef, TableDef)>> {
+    let journal_path = db_dir.join(EXCHANGE_JOURNAL_FILENAME);
+    let contents = match std::fs::read_to_string(&journal_path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => {
+            return Err(Error::CouldNotReadData(format!(
+                "Could not read exchange journal: {error}"
+            )));
+        }
+    };
+
+    let [database_0, table_0, database_1, table_1] = contents.lines().collect::<Vec<_>>()[..]
+    else {
+        return Err(Error::CouldNotReadData(format!(
+            "Exchange journal ({}) is malformed",
+            journal_path.display()
+        )));
+    };
+    let table_def = |database: &str, table: &str| TableDef {
+        database: database.to_string(),
+        table: table.to_string(),
+    };
+    Ok(Some((
+        table_def(database_0, table_0),
+        table_def(database_1, table_1),
+    )))
+}
+
+fn remove_journal(db_dir: &Path) {
+    if let Err(error) = std::fs::remove_file(db_dir.join(EXCHANGE_JOURNAL_FILENAME)) {
+        warn!("Could not remove exchange journal: {error}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table_def(database: &str, table: &str) -> TableDef {
+        TableDef {
+            database: database.to_string(),
+            table: table.to_string(),
+        }
+    }
+
+    /// Creates `db_dir` with tables `db_a.live` and `db_b.staging`, each with a marker file.
+    fn create_tables(name: &str) -> (PathBuf, TableDef, TableDef) {
+        let db_dir =
+            std::env::temp_dir().join(format!("touchhouse-exchange-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&db_dir);
+        let live = table_def("db_a", "live");
+        let staging = table_def("db_b", "staging");
+        for table in [&live, &staging] {
+            let path = table_path(&db_dir, table);
+            std::fs::create_dir_all(&path).unwrap();
+            std::fs::write(path.join("marker"), &table.table).unwrap();
+        }
+        (db_dir, live, staging)
+    }
+
+    fn marker(db_dir: &Path, table: &TableDef) -> String {
+        std::fs::read_to_string(table_path(db_dir, table).join("marker")).unwrap()
+    }
+
+    #[test]
+    fn test_exchange_table_dirs() {
+        let (db_dir, live, staging) = create_tables("swap");
+
+        exchange_table_dirs(&db_dir, &live, &staging).unwrap();
+        assert_eq!(marker(&db_dir, &live), "staging");
+        assert_eq!(marker(&db_dir, &staging), "live");
+        assert!(!db_dir.join(EXCHANGE_JOURNAL_FILENAME).exists());
+        assert!(!db_dir.join(EXCHANGE_TMP_DIRNAME).exists());
+
+        // missing table: nothing is renamed, journal is removed
+        let missing = table_def("db_a", "missing");
+        assert!(exchange_table_dirs(&db_dir, &missing, &live).is_err());
+        assert_eq!(marker(&db_dir, &live), "staging");
+        assert!(!db_dir.join(EXCHANGE_JOURNAL_FILENAME).exists());
+
+        std::fs::remove_dir_all(db_dir).unwrap();
+    }
+
+    #[test]
+    fn test_recover_exchange_after_crash() {
+        let (db_dir, live, staging) = create_tables("recover");
+        let tmp_path = db_dir.join(EXCHANGE_TMP_DIRNAME);
+
+        // crash after the first rename: exchange is undone
+        write_journal(&db_dir, &live, &staging).unwrap();
+        std::fs::rename(table_path(&db_dir, &live), &tmp_path).unwrap();
+        recover_exchange(&db_dir).unwrap();
+        assert_eq!(marker(&db_dir, &live), "live");
+        assert_eq!(marker(&db_dir, &staging), "staging");
+        assert!(!db_dir.join(EXCHANGE_JOURNAL_FILENAME).exists());
+
+        // crash after the second rename: exchange is finished
+        write_journal(&db_dir, &live, &staging).unwrap();
+        std::fs::rename(table_path(&db_dir, &live), &tmp_path).unwrap();
+        std::fs::rename(table_path(&db_dir, &staging), table_path(&db_dir, &live)).unwrap();
+        recover_exchange(&db_dir).unwrap();
+        assert_eq!(marker(&db_dir, &live), "staging");
+        assert_eq!(marker(&db_dir, &staging), "live");
+        assert!(!tmp_path.exists());
+
+        // crash before the first or after the last rename: nothing to do
+        write_journal(&db_dir, &live, &staging).unwrap();
+        recover_exchange(&db_dir).unwrap();
+        assert_eq!(marker(&db_dir, &live), "staging");
+        assert!(!db_dir.join(EXCHANGE_JOURNAL_FILENAME).exists());
+        recover_exchange(&db_dir).unwrap();
+
+        std::fs::write(db_dir.join(EXCHANGE_JOURNAL_FILENAME), "db_a\nlive\n").unwrap();
+        assert!(matches!(
+            recover_exchange(&db_dir),
+            Err(Error::CouldNotReadData(_))
+        ));
+
+        std::fs::remove_dir_all(db_dir).unwrap();
+    }
+}
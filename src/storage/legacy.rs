@@ -0,0 +1,431 @@
+//! Layouts of `.metadata` and `part.inf` written before column comments were added
+//! (`TableMetadata` version 1), before adaptive granularity settings were added (version 2) and
+//! before `SAMPLE BY` was added (version 3), and `part.inf` written before parts had a sequence
+//! or before primary key bounds were stored.
+//! Version 1 also covers layouts written before `PARTITION BY` was added, before `DEFAULT` took
+//! expressions and before column types had parameters, which are told apart by trying each of
+//! them. Used as a fallback on read and converted to current types.
+
+use crate::engines::EngineName;
+use crate::storage::table_metadata::DEFAULT_INDEX_GRANULARITY_BYTES;
+use crate::storage::{
+    ColumnDef, CompressionType, Constraints, DefaultExpr, Mark, TableMetadata, TablePartInfo,
+    TableSchema, TableSettings, Value, ValueType,
+};
+
+use rkyv::rancor::Error;
+use rkyv::{Archive as RkyvArchive, Deserialize as RkyvDeserialize, Serialize as RkyvSerialize};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+use uuid::Uuid;
+
+/// Column types written before types with parameters (`Tuple`, `Array`, `Map`, `Enum`) were
+/// added, which changed the layout of `ValueType`. Must not change.
+#[derive(Debug, Clone, RkyvSerialize, RkyvArchive, RkyvDeserialize)]
+pub enum ValueTypeV0 {
+    Null,
+    String,
+    Uuid,
+    Bool,
+
+    Int8,
+    Int16,
+    Int32,
+    Int64,
+
+    UInt8,
+    UInt16,
+    UInt32,
+    UInt64,
+}
+
+/// Constraints written before `DEFAULT` took expressions, default is a literal value.
+#[derive(Debug, Clone, RkyvSerialize, RkyvArchive, RkyvDeserialize)]
+pub struct ConstraintsV0 {
+    pub nullable: bool,
+    pub default: Option<Value>,
+    pub compression_type: CompressionType,
+}
+
+#[derive(Debug, Clone, RkyvSerialize, RkyvArchive, RkyvDeserialize)]
+pub struct ColumnDefV0<T> {
+    pub name: String,
+    pub field_type: T,
+    pub constraints: ConstraintsV0,
+}
+
+#[derive(Debug, Clone, RkyvSerialize, RkyvArchive, RkyvDeserialize)]
+pub struct ConstraintsV1 {
+    pub nullable: bool,
+    pub default: Option<DefaultExpr>,
+    pub compression_type: CompressionType,
+}
+
+#[derive(Debug, Clone, RkyvSerialize, RkyvArchive, RkyvDeserialize)]
+pub struct ColumnDefV1 {
+    pub name: String,
+    pub field_type: ValueType,
+    pub constraints: ConstraintsV1,
+}
+
+#[derive(Debug, Clone, RkyvSerialize, RkyvArchive, RkyvDeserialize)]
+pub struct TableSchemaV1<C> {
+    pub columns: Vec<C>,
+    pub order_by: Vec<C>,
+    pub primary_key: Vec<C>,
+}
+
+#[derive(Debug, Clone, RkyvSerialize, RkyvArchive, RkyvDeserialize)]
+pub struct TableSettingsV0 {
+    pub index_granularity: u32,
+    pub engine: EngineName,
+}
+
+/// Metadata written before `PARTITION BY` was added. With `ColumnDefV0<ValueTypeV0>` columns,
+/// it is the first layout of all.
+#[derive(Debug, Clone, RkyvSerialize, RkyvArchive, RkyvDeserialize)]
+pub struct TableMetadataV0<C> {
+    pub version: u16,
+    pub flags: u32,
+    pub created_at: u64,
+    pub settings: TableSettingsV0,
+    pub schema: TableSchemaV1<C>,
+}
+
+#[derive(Debug, Clone, RkyvSerialize, RkyvArchive, RkyvDeserialize)]
+pub struct TableSettingsV1 {
+    pub index_granularity: u32,
+    pub engine: EngineName,
+    pub partition_by: Option<String>,
+}
+
+#[derive(Debug, Clone, RkyvSerialize, RkyvArchive, RkyvDeserialize)]
+pub struct TableMetadataV1<C> {
+    pub version: u16,
+    pub flags: u32,
+    pub created_at: u64,
+    pub settings: TableSettingsV1,
+    pub schema: TableSchemaV1<C>,
+}
+
+#[derive(Debug, Clone, RkyvSerialize, RkyvArchive, RkyvDeserialize)]
+pub struct TableMetadataV2 {
+    pub version: u16,
+    pub flags: u32,
+    pub created_at: u64,
+    pub settings: TableSettingsV1,
+    pub schema: TableSchema,
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, Clone, RkyvSerialize, RkyvArchive, RkyvDeserialize)]
+pub struct TableSettingsV3 {
+    pub index_granularity: u32,
+    pub adaptive_index_granularity: bool,
+    pub index_granularity_bytes: u32,
+    pub engine: EngineName,
+    pub partition_by: Option<String>,
+}
+
+#[derive(Debug, Clone, RkyvSerialize, RkyvArchive, RkyvDeserialize)]
+pub struct TableMetadataV3 {
+    pub version: u16,
+    pub flags: u32,
+    pub created_at: u64,
+    pub settings: TableSettingsV3,
+    pub schema: TableSchema,
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, Clone, RkyvSerialize, RkyvArchive, RkyvDeserialize)]
+pub struct TablePartInfoV1 {
+    pub name: String,
+    pub sequence: u64,
+    pub row_count: u64,
+    pub marks: Vec<Mark>,
+    pub pk_bounds: Vec<(Value, Value)>,
+    pub column_defs: Vec<ColumnDefV1>,
+    pub partition: Option<String>,
+}
+
+/// `part.inf` written before primary key bounds were stored. Converted with empty `pk_bounds`,
+/// so the part is never skipped by them.
+#[derive(Debug, Clone, RkyvSerialize, RkyvArchive, RkyvDeserialize)]
+pub struct TablePartInfoV0<C> {
+    pub name: String,
+    pub row_count: u64,
+    pub marks: Vec<Mark>,
+    pub column_defs: Vec<C>,
+}
+
+/// `part.inf` written before `PARTITION BY` was added.
+#[derive(Debug, Clone, RkyvSerialize, RkyvArchive, RkyvDeserialize)]
+pub struct TablePartInfoV0Bounds<C> {
+    pub name: String,
+    pub row_count: u64,
+    pub marks: Vec<Mark>,
+    pub pk_bounds: Vec<(Value, Value)>,
+    pub column_defs: Vec<C>,
+}
+
+/// `part.inf` written before parts had a sequence.
+#[derive(Debug, Clone, RkyvSerialize, RkyvArchive, RkyvDeserialize)]
+pub struct TablePartInfoV0Partition<C> {
+    pub name: String,
+    pub row_count: u64,
+    pub marks: Vec<Mark>,
+    pub pk_bounds: Vec<(Value, Value)>,
+    pub column_defs: Vec<C>,
+    pub partition: Option<String>,
+}
+
+impl<C: Into<ColumnDefV1>> TablePartInfoV0Partition<C> {
+    /// Converts part at `part_path`, which gets sequence from `legacy_sequence`.
+    fn with_legacy_sequence(self, part_path: &Path) -> TablePartInfoV1 {
+        TablePartInfoV1 {
+            sequence: legacy_sequence(&self.name, part_path),
+            name: self.name,
+            row_count: self.row_count,
+            marks: self.marks,
+            pk_bounds: self.pk_bounds,
+            column_defs: self.column_defs.into_iter().map(Into::into).collect(),
+            partition: self.partition,
+        }
+    }
+}
+
+/// Deserializes `part.inf` of the part at `part_path`, written in one of the older layouts.
+/// Layouts are tried from the newest one.
+///
+/// Returns: `TablePartInfo` or None, when no layout matches.
+pub fn deserialize_part_info(data: &[u8], part_path: &Path) -> Option<TablePartInfo> {
+    if let Ok(info) = rkyv::from_bytes::<TablePartInfoV1, Error>(data) {
+        return Some(info.into());
+    }
+    rkyv::from_bytes::<TablePartInfoV0Partition<ColumnDefV1>, Error>(data)
+        .map(|info| info.with_legacy_sequence(part_path))
+        .or_else(|_| {
+            rkyv::from_bytes::<TablePartInfoV0Partition<ColumnDefV0<ValueType>>, Error>(data)
+                .map(|info| info.with_legacy_sequence(part_path))
+        })
+        .or_else(|_| {
+            rkyv::from_bytes::<TablePartInfoV0Bounds<ColumnDefV0<ValueType>>, Error>(data)
+                .map(|info| TablePartInfoV0Partition::from(info).with_legacy_sequence(part_path))
+        })
+        .or_else(|_| {
+            rkyv::from_bytes::<TablePartInfoV0<ColumnDefV0<ValueType>>, Error>(data)
+                .map(|info| TablePartInfoV0Partition::from(info).with_legacy_sequence(part_path))
+        })
+        .or_else(|_| {
+            rkyv::from_bytes::<TablePartInfoV0<ColumnDefV0<ValueTypeV0>>, Error>(data)
+                .map(|info| TablePartInfoV0Partition::from(info).with_legacy_sequence(part_path))
+        })
+        .ok()
+        .map(TablePartInfo::from)
+}
+
+/// Returns sequence of a part written before parts had one. Such parts are named by UUIDv7 and
+/// merged part kept the name of the newer part, so the name orders them as they were created:
+/// sequence is the creation time in milliseconds followed by the low 16 bits of the counter,
+/// which is incremented for parts created in the same millisecond. Part with another name gets
+/// the modification time of its directory in the same format.
+fn legacy_sequence(name: &str, part_path: &Path) -> u64 {
+    match Uuid::parse_str(name) {
+        Ok(uuid) if uuid.get_version_num() == 7 => {
+            let uuid = uuid.as_u128();
+            let created_ms = (uuid >> 80) as u64;
+            // the 42-bit counter ends 32 bits from the end, before the random bits
+            let counter = (uuid >> 32) as u64 & 0xffff;
+            created_ms << 16 | counter
+        }
+        _ => {
+            let modified_ms = std::fs::metadata(part_path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |modified| modified.as_millis() as u64);
+            modified_ms << 16
+        }
+    }
+}
+
+impl<C> From<TablePartInfoV0<C>> for TablePartInfoV0Partition<C> {
+    fn from(info: TablePartInfoV0<C>) -> Self {
+        Self {
+            name: info.name,
+            row_count: info.row_count,
+            marks: info.marks,
+            pk_bounds: Vec::new(),
+            column_defs: info.column_defs,
+            partition: None,
+        }
+    }
+}
+
+impl<C> From<TablePartInfoV0Bounds<C>> for TablePartInfoV0Partition<C> {
+    fn from(info: TablePartInfoV0Bounds<C>) -> Self {
+        Self {
+            name: info.name,
+            row_count: info.row_count,
+            marks: info.marks,
+            pk_bounds: info.pk_bounds,
+            column_defs: info.column_defs,
+            partition: None,
+        }
+    }
+}
+
+impl From<ValueTypeV0> for ValueType {
+    fn from(value_type: ValueTypeV0) -> Self {
+        match value_type {
+            ValueTypeV0::Null => Self::Null,
+            ValueTypeV0::String => Self::String,
+            ValueTypeV0::Uuid => Self::Uuid,
+            ValueTypeV0::Bool => Self::Bool,
+            ValueTypeV0::Int8 => Self::Int8,
+            ValueTypeV0::Int16 => Self::Int16,
+            ValueTypeV0::Int32 => Self::Int32,
+            ValueTypeV0::Int64 => Self::Int64,
+            ValueTypeV0::UInt8 => Self::UInt8,
+            ValueTypeV0::UInt16 => Self::UInt16,
+            ValueTypeV0::UInt32 => Self::UInt32,
+            ValueTypeV0::UInt64 => Self::UInt64,
+        }
+    }
+}
+
+impl<T: Into<ValueType>> From<ColumnDefV0<T>> for ColumnDefV1 {
+    fn from(column_def: ColumnDefV0<T>) -> Self {
+        Self {
+            name: column_def.name,
+            field_type: column_def.field_type.into(),
+            constraints: ConstraintsV1 {
+                nullable: column_def.constraints.nullable,
+                default: column_def.constraints.default.map(DefaultExpr::Literal),
+                compression_type: column_def.constraints.compression_type,
+            },
+        }
+    }
+}
+
+impl From<ColumnDefV1> for ColumnDef {
+    fn from(column_def: ColumnDefV1) -> Self {
+        Self {
+            name: column_def.name,
+            field_type: column_def.field_type,
+            constraints: Constraints {
+                nullable: column_def.constraints.nullable,
+                default: column_def.constraints.default,
+                compression_type: column_def.constraints.compression_type,
+                comment: None,
+            },
+        }
+    }
+}
+
+fn convert_columns<C: Into<ColumnDefV1>>(columns: Vec<C>) -> Vec<ColumnDef> {
+    columns
+        .into_iter()
+        .map(|column_def| ColumnDef::from(column_def.into()))
+        .collect()
+}
+
+impl<C> From<TableMetadataV0<C>> for TableMetadataV1<C> {
+    fn from(metadata: TableMetadataV0<C>) -> Self {
+        Self {
+            version: metadata.version,
+            flags: metadata.flags,
+            created_at: metadata.created_at,
+            settings: TableSettingsV1 {
+                index_granularity: metadata.settings.index_granularity,
+                engine: metadata.settings.engine,
+                partition_by: None,
+            },
+            schema: metadata.schema,
+        }
+    }
+}
+
+impl From<TableSettingsV1> for TableSettings {
+    fn from(settings: TableSettingsV1) -> Self {
+        Self {
+            index_granularity: settings.index_granularity,
+            adaptive_index_granularity: false,
+            index_granularity_bytes: DEFAULT_INDEX_GRANULARITY_BYTES,
+            engine: settings.engine,
+            partition_by: settings.partition_by,
+            sample_by: None,
+        }
+    }
+}
+
+impl From<TableSettingsV3> for TableSettings {
+    fn from(settings: TableSettingsV3) -> Self {
+        Self {
+            index_granularity: settings.index_granularity,
+            adaptive_index_granularity: settings.adaptive_index_granularity,
+            index_granularity_bytes: settings.index_granularity_bytes,
+            engine: settings.engine,
+            partition_by: settings.partition_by,
+            sample_by: None,
+        }
+    }
+}
+
+impl From<TableMetadataV3> for TableMetadata {
+    fn from(metadata: TableMetadataV3) -> Self {
+        Self {
+            version: metadata.version,
+            flags: metadata.flags,
+            created_at: metadata.created_at,
+            settings: metadata.settings.into(),
+            schema: metadata.schema,
+            comment: metadata.comment,
+        }
+    }
+}
+
+impl From<TableMetadataV2> for TableMetadata {
+    fn from(metadata: TableMetadataV2) -> Self {
+        Self {
+            version: metadata.version,
+            flags: metadata.flags,
+            created_at: metadata.created_at,
+            settings: metadata.settings.into(),
+            schema: metadata.schema,
+            comment: metadata.comment,
+        }
+    }
+}
+
+impl<C: Into<ColumnDefV1>> From<TableMetadataV1<C>> for TableMetadata {
+    fn from(metadata: TableMetadataV1<C>) -> Self {
+        Self {
+            version: metadata.version,
+            flags: metadata.flags,
+            created_at: metadata.created_at,
+            settings: metadata.settings.into(),
+            schema: TableSchema {
+                columns: convert_columns(metadata.schema.columns),
+                order_by: convert_columns(metadata.schema.order_by),
+                primary_key: convert_columns(metadata.schema.primary_key),
+            },
+            comment: None,
+        }
+    }
+}
+
+impl From<TablePartInfoV1> for TablePartInfo {
+    fn from(info: TablePartInfoV1) -> Self {
+        Self {
+            name: info.name,
+            sequence: info.sequence,
+            row_count: info.row_count,
+            marks: info.marks,
+            pk_bounds: info.pk_bounds,
+            column_defs: convert_columns(info.column_defs),
+            partition: info.partition,
+            suspect: None,
+        }
+    }
+}
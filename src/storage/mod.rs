@@ -1,16 +1,36 @@
+mod column_data;
 mod compression;
+pub mod date;
+mod disk_space;
+mod exchange;
+mod legacy;
+mod part_writer;
 pub mod table_metadata;
 mod table_part;
 pub mod value;
+mod write_throttle;
 
 use crate::CONFIG;
 use crate::error::{Error, Result};
+use crate::sql::validate_name;
+pub use crate::storage::column_data::ColumnData;
 pub use crate::storage::compression::CompressionType;
+pub use crate::storage::disk_space::{SpaceChecker, set_space_checker};
+pub(crate) use crate::storage::disk_space::{
+    ensure_free_space, ensure_quota, estimate_part_bytes, parts_bytes,
+};
+pub use crate::storage::exchange::{exchange_table_dirs, recover_exchange};
+pub(crate) use crate::storage::part_writer::PartWriter;
 use crate::storage::table_metadata::TABLE_METADATA_FILENAME;
 pub use crate::storage::table_metadata::{TableMetadata, TableSchema, TableSettings};
 use crate::storage::table_part::MAGIC_BYTES_COLUMN;
-pub use crate::storage::table_part::{Mark, TablePart, TablePartInfo, load_all_parts_on_startup};
+pub use crate::storage::table_part::{
+    Mark, MarkInfo, PART_INFO_FILENAME, TablePart, TablePartInfo, load_all_parts_on_startup,
+    rescan_parts,
+};
 pub use crate::storage::value::{Value, ValueType};
+pub use crate::storage::write_throttle::WriteOrigin;
+pub(crate) use crate::storage::write_throttle::stop_throttling_on;
 
 use memmap2::{Advice, Mmap};
 use rkyv::{Archive as RkyvArchive, Deserialize as RkyvDeserialize, Serialize as RkyvSerialize};
@@ -20,12 +40,43 @@ use std::fmt;
 use std::fs::File;
 use std::path::{Path, PathBuf};
 use std::time::{Duration, SystemTime};
+use uuid::Uuid;
+
+/// Max number of characters of a cell in `OutputTable::to_pretty_string`. Longer cells are cut
+/// and end with `…`.
+const PRETTY_MAX_CELL_CHARS: usize = 64;
+/// Shown in place of `NULL` by `OutputTable::to_pretty_string`.
+const PRETTY_NULL: &str = "␀";
+
+/// Default value of the column.
+#[derive(Debug, Clone, PartialEq, Serialize, RkyvSerialize, RkyvArchive, RkyvDeserialize)]
+pub enum DefaultExpr {
+    /// Constant value, e.g., `DEFAULT 5` or `DEFAULT 2 + 3`.
+    Literal(Value),
+    /// SQL expression with function calls, e.g., `DEFAULT now()`. Evaluated for each inserted row.
+    Function(String),
+}
+
+impl DefaultExpr {
+    /// Returns expression as in `CREATE TABLE`, e.g., `5`, `'text'` or `now()`.
+    pub fn to_sql(&self) -> String {
+        match self {
+            Self::Literal(value) => value.to_sql_literal(),
+            Self::Function(expr) => expr.clone(),
+        }
+    }
+}
 
 #[derive(Debug, Clone, PartialEq, Serialize, RkyvSerialize, RkyvArchive, RkyvDeserialize)]
 pub struct Constraints {
     pub nullable: bool,
-    pub default: Option<Value>,
+    /// `None` when no `DEFAULT` is given: missing values of nullable columns are `NULL`,
+    /// and NOT NULL columns must be specified on insert. `Some(DefaultExpr::Literal(Value::Null))`
+    /// is explicit `DEFAULT NULL`, allowed only for nullable columns.
+    pub default: Option<DefaultExpr>,
     pub compression_type: CompressionType,
+    /// Set by `COMMENT 'text'`.
+    pub comment: Option<String>,
 }
 
 impl Default for Constraints {
@@ -34,6 +85,7 @@ impl Default for Constraints {
             nullable: true,
             default: None,
             compression_type: CompressionType::default(),
+            comment: None,
         }
     }
 }
@@ -48,7 +100,7 @@ pub struct ColumnDef {
 #[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct Column {
     pub column_def: ColumnDef,
-    pub data: Vec<Value>,
+    pub data: ColumnData,
 }
 
 /// Tiny wrapper for implementing `std::io::Write` for `crc32fast::Hasher`.
@@ -74,6 +126,12 @@ impl std::io::Write for Crc32Writer {
 }
 
 impl Column {
+    /// Max size of column file in bytes, which can be mapped into memory: `usize::MAX`, i.e.,
+    /// 4 GiB on 32-bit platforms, while `MarkInfo` offsets are `u64`.
+    pub const fn max_file_size() -> u64 {
+        usize::MAX as u64
+    }
+
     pub fn open_as_mmap(file_path: &Path) -> Result<Mmap> {
         let file = File::open(file_path).map_err(|error| {
             Error::CouldNotReadData(format!(
@@ -149,6 +207,16 @@ pub struct OutputTable {
     pub columns: Vec<Column>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub execution_time: Option<Duration>,
+    /// Time, which command waited for a free query thread before execution.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub queue_time: Option<Duration>,
+    /// Id of the query, which is also a field of its log lines.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query_id: Option<Uuid>,
+    /// Number of parts, which `SELECT` skipped, because they could not be read. Set only with
+    /// `SETTINGS skip_unreadable_parts = 1`, so rows may be missing when it is not zero.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skipped_parts: Option<u64>,
 }
 
 impl OutputTable {
@@ -157,6 +225,9 @@ impl OutputTable {
         Self {
             columns,
             execution_time: None,
+            queue_time: None,
+            query_id: None,
+            skipped_parts: None,
         }
     }
 
@@ -166,19 +237,170 @@ impl OutputTable {
         self
     }
 
+    /// Sets the time, which command waited before execution.
+    pub fn with_queue_time(mut self, duration: Duration) -> Self {
+        self.queue_time = Some(duration);
+        self
+    }
+
+    /// Sets id of the query, which produced this output table.
+    pub fn with_query_id(mut self, query_id: Uuid) -> Self {
+        self.query_id = Some(query_id);
+        self
+    }
+
+    /// Sets number of parts, which were skipped, because they could not be read.
+    pub fn with_skipped_parts(mut self, skipped_parts: u64) -> Self {
+        self.skipped_parts = Some(skipped_parts);
+        self
+    }
+
+    /// Returns number of rows.
+    pub fn row_count(&self) -> usize {
+        self.columns.first().map_or(0, |col| col.data.len())
+    }
+
+    /// Renders table as an ASCII box with header row, for interactive clients.
+    ///
+    /// Strings are shown without quotes and `NULL` as `␀`, other values as SQL literals. Columns
+    /// of integers are aligned right, other columns left. Width is counted in characters, so
+    /// multi-byte strings stay aligned, and cells longer than `PRETTY_MAX_CELL_CHARS` are cut.
+    ///
+    /// Returns: lines of the table, each ending with newline. Empty string, when table has no
+    /// columns.
+    pub fn to_pretty_string(&self) -> String {
+        if self.columns.is_empty() {
+            return String::new();
+        }
+
+        let header: Vec<_> = self
+            .columns
+            .iter()
+            .map(|column| pretty_cell(&column.column_def.name))
+            .collect();
+        let cells: Vec<Vec<_>> = self
+            .columns
+            .iter()
+            .map(|column| {
+                column
+                    .data
+                    .iter()
+                    .map(|value| match value {
+                        Value::Null => PRETTY_NULL.to_string(),
+                        Value::String(value) => pretty_cell(value),
+                        value => pretty_cell(&value.to_sql_literal()),
+                    })
+                    .collect()
+            })
+            .collect();
+        let widths: Vec<_> = header
+            .iter()
+            .zip(&cells)
+            .map(|(name, cells)| {
+                cells
+                    .iter()
+                    .chain(std::iter::once(name))
+                    .map(|cell| cell.chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        let mut border = String::from("+");
+        for width in &widths {
+            border.push_str(&"-".repeat(width + 2));
+            border.push('+');
+        }
+        border.push('\n');
+
+        let write_row = |pretty: &mut String, row: &mut dyn Iterator<Item = (&String, bool)>| {
+            pretty.push('|');
+            for ((cell, align_right), width) in row.zip(&widths) {
+                let padding = " ".repeat(width - cell.chars().count());
+                if align_right {
+                    pretty.push_str(&format!(" {padding}{cell} |"));
+                } else {
+                    pretty.push_str(&format!(" {cell}{padding} |"));
+                }
+            }
+            pretty.push('\n');
+        };
+
+        let mut pretty = border.clone();
+        write_row(&mut pretty, &mut header.iter().map(|name| (name, false)));
+        pretty.push_str(&border);
+        for row in 0..self.row_count() {
+            write_row(
+                &mut pretty,
+                &mut self.columns.iter().zip(&cells).map(|(column, cells)| {
+                    (&cells[row], column.column_def.field_type.is_integer())
+                }),
+            );
+        }
+        if self.row_count() > 0 {
+            pretty.push_str(&border);
+        }
+        pretty
+    }
+
     /// Builds a simple OK response table.
     pub fn build_ok() -> Self {
-        Self {
-            columns: vec![Column {
-                column_def: ColumnDef {
-                    name: "OK".to_string(),
-                    field_type: ValueType::String,
-                    constraints: Constraints::default(),
-                },
-                data: vec![Value::String("OK".to_string())],
-            }],
-            execution_time: None,
-        }
+        Self::new(vec![single_value_column(
+            "OK",
+            ValueType::String,
+            Value::String("OK".to_string()),
+        )])
+    }
+
+    /// Builds a response table of a write: `status` ("OK") and `rows_written` columns.
+    pub fn build_rows_written(rows_written: u64) -> Self {
+        Self::new(vec![
+            single_value_column("status", ValueType::String, Value::String("OK".to_string())),
+            single_value_column(
+                "rows_written",
+                ValueType::UInt64,
+                Value::UInt64(rows_written),
+            ),
+        ])
+    }
+
+    /// Builds a response table of `IMPORT INTO`: `status` ("OK"), `rows_imported` and
+    /// `rows_skipped` (lines, which could not be parsed) columns.
+    pub fn build_rows_imported(rows_imported: u64, rows_skipped: u64) -> Self {
+        Self::new(vec![
+            single_value_column("status", ValueType::String, Value::String("OK".to_string())),
+            single_value_column(
+                "rows_imported",
+                ValueType::UInt64,
+                Value::UInt64(rows_imported),
+            ),
+            single_value_column(
+                "rows_skipped",
+                ValueType::UInt64,
+                Value::UInt64(rows_skipped),
+            ),
+        ])
+    }
+
+    /// Builds a response table of `CREATE` or `DROP`: `status` ("OK") and `object` (name of the
+    /// created or dropped database, table or partition) columns.
+    pub fn build_object_ok(object: String) -> Self {
+        Self::new(vec![
+            single_value_column("status", ValueType::String, Value::String("OK".to_string())),
+            single_value_column("object", ValueType::String, Value::String(object)),
+        ])
+    }
+}
+
+/// Returns column `name` with a single `value`.
+fn single_value_column(name: &str, field_type: ValueType, value: Value) -> Column {
+    Column {
+        column_def: ColumnDef {
+            name: name.to_string(),
+            field_type,
+            constraints: Constraints::default(),
+        },
+        data: vec![value].into(),
     }
 }
 
@@ -195,6 +417,11 @@ impl fmt::Display for TableDef {
 }
 
 impl TableDef {
+    /// Returns `database.table`, as written in SQL.
+    pub fn qualified_name(&self) -> String {
+        format!("{}.{}", self.database, self.table)
+    }
+
     /// Returns filesystem path for this table.
     pub fn get_path(&self) -> PathBuf {
         CONFIG.get_db_dir().join(&self.database).join(&self.table)
@@ -222,31 +449,44 @@ impl TableDef {
 
 impl TryFrom<&ObjectName> for TableDef {
     type Error = Error;
-    fn try_from(object_name: &ObjectName) -> Result<Self> {
-        let names = &object_name.0;
-        if names.len() != 2 {
-            return Err(Error::UnsupportedCommand(
-                "You should provide table name in form `database_name.table_name`".to_string(),
-            ));
-        }
 
-        let ObjectNamePart::Identifier(ref database) = names[0] else {
-            return Err(Error::UnsupportedCommand(
-                "Currently unimplemented.".to_string(),
-            ));
+    /// Parses `database_name.table_name`. Quoted parts (`"My DB".`My Table``) are unquoted and,
+    /// as any identifier, kept exactly as written.
+    ///
+    /// Returns:
+    ///   * Ok: `TableDef`.
+    ///   * Error: `InvalidTableName`, naming the received name, when:
+    ///     1. Database is not specified (`table_name`), as connections have no current database.
+    ///     2. Name has more than two parts (e.g., `catalog.database_name.table_name`).
+    ///     3. Part is not an identifier (e.g., function call).
+    ///     4. Part is not a valid name, see `validate_name`.
+    fn try_from(object_name: &ObjectName) -> Result<Self> {
+        let [database, table] = &object_name.0[..] else {
+            let reason = match object_name.0.len() {
+                1 => "no database specified, use `database_name.table_name`".to_string(),
+                parts => format!("expected `database_name.table_name`, got {parts} parts"),
+            };
+            return Err(Error::InvalidTableName(format!("{object_name}: {reason}")));
         };
-        let database = database.value.clone();
 
-        let ObjectNamePart::Identifier(ref table) = names[1] else {
-            return Err(Error::UnsupportedCommand(
-                "Currently unimplemented.".to_string(),
-            ));
+        let name_part = |part: &ObjectNamePart, kind: &str| {
+            let ObjectNamePart::Identifier(ident) = part else {
+                return Err(Error::InvalidTableName(format!(
+                    "{object_name}: {kind} name ({part}) is not an identifier"
+                )));
+            };
+            if !validate_name(&ident.value) {
+                return Err(Error::InvalidTableName(format!(
+                    "{object_name}: invalid {kind} name ({ident})"
+                )));
+            }
+            Ok(ident.value.clone())
         };
-        let table = table.value.clone();
 
-        let table_def = Self { table, database };
-
-        Ok(table_def)
+        Ok(Self {
+            database: name_part(database, "database")?,
+            table: name_part(table, "table")?,
+        })
     }
 }
 
@@ -262,3 +502,171 @@ pub fn get_unix_time() -> Result<u64> {
     )
     .map_err(|_| Error::SystemTimeWentBackword)
 }
+
+/// Returns cell text with control characters replaced by spaces (so that cell stays on its line),
+/// cut to `PRETTY_MAX_CELL_CHARS`.
+fn pretty_cell(text: &str) -> String {
+    let mut cell: String = text
+        .chars()
+        .map(|ch| if ch.is_control() { ' ' } else { ch })
+        .collect();
+    if let Some((end, _)) = cell.char_indices().nth(PRETTY_MAX_CELL_CHARS) {
+        // the last kept character is replaced, so that cell has exactly the max width
+        let end = cell[..end].char_indices().last().map_or(0, |(idx, _)| idx);
+        cell.truncate(end);
+        cell.push('…');
+    }
+    cell
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlparser::dialect::ClickHouseDialect;
+    use sqlparser::parser::Parser;
+
+    fn table_def(name: &str) -> Result<TableDef> {
+        let object_name = Parser::new(&ClickHouseDialect {})
+            .try_with_sql(name)
+            .and_then(|mut parser| parser.parse_object_name(false))
+            .unwrap();
+        TableDef::try_from(&object_name)
+    }
+
+    #[test]
+    fn test_table_def_from_object_name() {
+        for (name, database, table) in [
+            ("db.t", "db", "t"),
+            ("\"db\".\"t\"", "db", "t"),
+            ("`My DB`.`My Table`", "My DB", "My Table"),
+            ("DB.\"T-1\"", "DB", "T-1"),
+            ("system.parts", "system", "parts"),
+        ] {
+            assert_eq!(
+                table_def(name),
+                Ok(TableDef {
+                    database: database.to_string(),
+                    table: table.to_string(),
+                }),
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_table_def_from_object_name_errors() {
+        for (name, message) in [
+            (
+                "t",
+                "Invalid table name: t: no database specified, use `database_name.table_name`",
+            ),
+            (
+                "\"My Table\"",
+                "Invalid table name: \"My Table\": no database specified, use `database_name.table_name`",
+            ),
+            (
+                "catalog.db.t",
+                "Invalid table name: catalog.db.t: expected `database_name.table_name`, got 3 parts",
+            ),
+            (
+                "a.`b`.\"c\".d",
+                "Invalid table name: a.`b`.\"c\".d: expected `database_name.table_name`, got 4 parts",
+            ),
+            (
+                "db.\"a.b\"",
+                "Invalid table name: db.\"a.b\": invalid table name (\"a.b\")",
+            ),
+            (
+                "\"..\".t",
+                "Invalid table name: \"..\".t: invalid database name (\"..\")",
+            ),
+            (
+                "db.\" t\"",
+                "Invalid table name: db.\" t\": invalid table name (\" t\")",
+            ),
+        ] {
+            assert_eq!(table_def(name).unwrap_err().to_string(), message, "{name}");
+        }
+    }
+
+    fn pretty_column(name: &str, field_type: ValueType, data: Vec<Value>) -> Column {
+        Column {
+            column_def: ColumnDef {
+                name: name.to_string(),
+                field_type,
+                constraints: Constraints::default(),
+            },
+            data: data.into(),
+        }
+    }
+
+    #[test]
+    fn test_pretty_string_alignment_and_null() {
+        let table = OutputTable::new(vec![
+            pretty_column(
+                "id",
+                ValueType::UInt64,
+                vec![Value::UInt64(7), Value::UInt64(1234), Value::Null],
+            ),
+            pretty_column(
+                "name",
+                ValueType::String,
+                vec![
+                    Value::String("äöü".to_string()),
+                    Value::Null,
+                    Value::String("line\nbreak".to_string()),
+                ],
+            ),
+            pretty_column(
+                "tags",
+                ValueType::Array(Box::new(ValueType::String)),
+                vec![
+                    Value::Array(vec![Value::String("a".to_string())]),
+                    Value::Array(Vec::new()),
+                    Value::Null,
+                ],
+            ),
+        ]);
+
+        assert_eq!(
+            table.to_pretty_string(),
+            "\
++------+------------+-------+
+| id   | name       | tags  |
++------+------------+-------+
+|    7 | äöü        | ['a'] |
+| 1234 | ␀          | []    |
+|    ␀ | line break | ␀     |
++------+------------+-------+
+"
+        );
+    }
+
+    #[test]
+    fn test_pretty_string_empty_and_wide_cells() {
+        assert_eq!(OutputTable::new(Vec::new()).to_pretty_string(), "");
+        assert_eq!(
+            OutputTable::new(vec![pretty_column("id", ValueType::UInt64, Vec::new())])
+                .to_pretty_string(),
+            "+----+\n| id |\n+----+\n"
+        );
+
+        let wide = "ж".repeat(PRETTY_MAX_CELL_CHARS + 10);
+        let table = OutputTable::new(vec![pretty_column(
+            "text",
+            ValueType::String,
+            vec![Value::String(wide)],
+        )]);
+        let pretty = table.to_pretty_string();
+        let row = pretty.lines().nth(3).unwrap();
+        assert_eq!(
+            row,
+            format!("| {}… |", "ж".repeat(PRETTY_MAX_CELL_CHARS - 1))
+        );
+        assert!(
+            pretty
+                .lines()
+                .all(|line| line.chars().count() == PRETTY_MAX_CELL_CHARS + 4)
+        );
+    }
+}
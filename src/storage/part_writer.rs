@@ -0,0 +1,409 @@
+use crate::error::{Error, Result};
+use crate::runtime_config::TABLE_DATA;
+use crate::storage::compression::{
+    DictionaryBuilder, MAX_DICTIONARY_SIZE, compress_bytes, decompress_bytes, encode_codes,
+};
+use crate::storage::table_part::{
+    MAGIC_BYTES_COLUMN, encode_dictionary, encode_granule, encode_marks, raw_dir,
+};
+use crate::storage::write_throttle::{WriteOrigin, throttle_write};
+use crate::storage::{
+    ColumnDef, CompressionType, Mark, MarkInfo, TableDef, TablePart, TablePartInfo, Value,
+};
+
+use log::warn;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read as _, Write as _};
+use std::path::{Path, PathBuf};
+
+/// Writes a part into the raw directory row by row, keeping only the current granule of each
+/// column in memory. Written files are the same as of `TablePart::save_raw` for these rows:
+/// granules start at the same rows, and part has the same marks and `pk_bounds`.
+///
+/// Granules of a dictionary-encoded column are kept plain-encoded in a temporary file, until
+/// all values, and so the dictionary, are known. Raw directory is removed, when writer is
+/// dropped before `finish`.
+pub(crate) struct PartWriter {
+    table_def: TableDef,
+    /// Info of the written part, `row_count`, `marks` and `pk_bounds` grow with written rows.
+    info: TablePartInfo,
+    columns: Vec<ColumnWriter>,
+    /// Position of each primary key column in `column_defs`, in primary key order.
+    primary_key: Vec<Option<usize>>,
+    /// Positions of columns, which are in primary key, in column order (see `Mark::index`).
+    mark_columns: Vec<usize>,
+    /// Max rows and, with `adaptive_index_granularity`, max bytes of a granule.
+    max_rows: usize,
+    max_bytes: Option<usize>,
+    /// Rows of the current granule by column, and their size (see `Value::archived_size`).
+    granule: Vec<Vec<Value>>,
+    granule_bytes: usize,
+    origin: WriteOrigin,
+    finished: bool,
+}
+
+impl PartWriter {
+    /// Creates raw directory of the part with a file for each of `column_defs`. Primary key and
+    /// granularity are taken from the table.
+    ///
+    /// Returns:
+    ///   * Ok: writer of the part.
+    ///   * Error: `InvalidSource` without columns, `TableNotFound`, or `CouldNotInsertData` when
+    ///     files could not be created.
+    pub fn try_new(
+        table_def: &TableDef,
+        column_defs: Vec<ColumnDef>,
+        name: String,
+        sequence: u64,
+        partition: Option<String>,
+        origin: WriteOrigin,
+    ) -> Result<Self> {
+        if column_defs.is_empty() {
+            return Err(Error::InvalidSource("No columns provided".to_string()));
+        }
+        let (primary_key, mark_columns, max_rows, max_bytes) = {
+            let Some(table_config) = TABLE_DATA.get(table_def) else {
+                return Err(Error::TableNotFound);
+            };
+            let schema = &table_config.metadata.schema;
+            let settings = &table_config.metadata.settings;
+            (
+                schema
+                    .primary_key
+                    .iter()
+                    .map(|pk_col_def| column_defs.iter().position(|col| col == pk_col_def))
+                    .collect::<Vec<_>>(),
+                (0..column_defs.len())
+                    .filter(|&idx| schema.primary_key.contains(&column_defs[idx]))
+                    .collect(),
+                (settings.index_granularity as usize).max(1),
+                settings
+                    .adaptive_index_granularity
+                    .then_some(settings.index_granularity_bytes as usize),
+            )
+        };
+
+        let mut writer = Self {
+            table_def: table_def.clone(),
+            info: TablePartInfo {
+                name,
+                sequence,
+                row_count: 0,
+                marks: Vec::new(),
+                pk_bounds: vec![(Value::Null, Value::Null); primary_key.len()],
+                column_defs,
+                partition,
+                suspect: None,
+            },
+            columns: Vec::new(),
+            primary_key,
+            mark_columns,
+            max_rows,
+            max_bytes,
+            granule: Vec::new(),
+            granule_bytes: 0,
+            origin,
+            finished: false,
+        };
+
+        let dir = raw_dir(table_def, &writer.info.name);
+        std::fs::create_dir_all(&dir)
+            .map_err(|_| Error::CouldNotInsertData("Failed to create raw directory".to_string()))?;
+        for column_def in &writer.info.column_defs {
+            writer.columns.push(ColumnWriter::create(&dir, column_def)?);
+            writer.granule.push(Vec::with_capacity(max_rows));
+        }
+        Ok(writer)
+    }
+
+    /// Appends a row with a value for each column. Rows must come in the order of the part.
+    ///
+    /// Returns: Ok or `CouldNotInsertData` when row has another number of values, or granule
+    /// could not be written.
+    pub fn push_row(&mut self, row: Vec<Value>) -> Result<()> {
+        if row.len() != self.columns.len() {
+            return Err(Error::CouldNotInsertData(format!(
+                "Row has {} values, but part has {} columns",
+                row.len(),
+                self.columns.len()
+            )));
+        }
+        if self.granule[0].is_empty() {
+            self.info.marks.push(Mark {
+                index: self
+                    .mark_columns
+                    .iter()
+                    .map(|&idx| row[idx].clone())
+                    .collect(),
+                info: Vec::new(),
+            });
+        }
+        if self.max_bytes.is_some() {
+            self.granule_bytes += row.iter().map(Value::archived_size).sum::<usize>();
+        }
+        for (values, value) in self.granule.iter_mut().zip(row) {
+            values.push(value);
+        }
+        self.info.row_count += 1;
+
+        if self.granule[0].len() == self.max_rows
+            || self
+                .max_bytes
+                .is_some_and(|max_bytes| self.granule_bytes >= max_bytes)
+        {
+            self.flush_granule()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the last granule, mark files and part info.
+    ///
+    /// Returns:
+    ///   * Ok: part, which is ready to be moved out of the raw directory.
+    ///   * Error: `InvalidSource` when no rows were written, or `CouldNotInsertData` on failure.
+    pub fn finish(mut self) -> Result<TablePart> {
+        self.flush_granule()?;
+        if self.info.row_count == 0 {
+            return Err(Error::InvalidSource("No data provided".to_string()));
+        }
+        for column in std::mem::take(&mut self.columns) {
+            let bytes = column.finish(&self.info.name)?;
+            throttle_write(self.origin, bytes);
+        }
+        self.info.write_to(&self.table_def, true)?;
+
+        self.finished = true;
+        Ok(TablePart::written(self.info.clone()))
+    }
+
+    /// Writes the current granule of every column and updates `pk_bounds` with its values.
+    /// Merged parts are throttled after every granule, see `max_merge_bytes_per_sec`.
+    fn flush_granule(&mut self) -> Result<()> {
+        if self.granule[0].is_empty() {
+            return Ok(());
+        }
+
+        for (bounds, position) in self.info.pk_bounds.iter_mut().zip(&self.primary_key) {
+            let Some(position) = position else {
+                continue;
+            };
+            let mut values = self.granule[*position]
+                .iter()
+                .filter(|value| **value != Value::Null);
+            let current = (bounds.0 != Value::Null).then_some((&bounds.0, &bounds.1));
+            let Some(first) = current.or_else(|| values.next().map(|value| (value, value))) else {
+                continue;
+            };
+            let (min, max) = values.fold(first, |(min, max), value| {
+                (
+                    if value < min { value } else { min },
+                    if value > max { value } else { max },
+                )
+            });
+            *bounds = (min.clone(), max.clone());
+        }
+
+        let mut bytes = 0;
+        for (column, values) in self.columns.iter_mut().zip(&mut self.granule) {
+            bytes += column.write_granule(values)?;
+            values.clear();
+        }
+        self.granule_bytes = 0;
+        throttle_write(self.origin, bytes);
+        Ok(())
+    }
+}
+
+impl Drop for PartWriter {
+    fn drop(&mut self) {
+        if !self.finished {
+            // files are closed, before their directory is removed
+            self.columns.clear();
+            let _ = std::fs::remove_dir_all(raw_dir(&self.table_def, &self.info.name));
+        }
+    }
+}
+
+/// Column file of `PartWriter`.
+struct ColumnWriter {
+    column_def: ColumnDef,
+    /// Path of the column file.
+    path: PathBuf,
+    /// Column file, or temporary file of plain-encoded granules, when column is
+    /// dictionary-encoded.
+    file: GranuleFile,
+    dictionary: Option<DictionaryBuilder>,
+}
+
+impl ColumnWriter {
+    fn create(dir: &Path, column_def: &ColumnDef) -> Result<Self> {
+        let path = dir.join(format!("{}.bin", column_def.name));
+        let (file, dictionary) = match column_def.constraints.compression_type {
+            CompressionType::Dictionary(_) => (
+                GranuleFile::create(path.with_extension("bin.tmp"), &[])?,
+                Some(DictionaryBuilder::default()),
+            ),
+            _ => (GranuleFile::create(path.clone(), MAGIC_BYTES_COLUMN)?, None),
+        };
+        Ok(Self {
+            column_def: column_def.clone(),
+            path,
+            file,
+            dictionary,
+        })
+    }
+
+    /// Writes a granule plain-encoded.
+    ///
+    /// Returns: number of written bytes, or `CouldNotInsertData` on failure.
+    #[allow(clippy::ptr_arg)] // see `encode_granule`
+    fn write_granule(&mut self, values: &Vec<Value>) -> Result<u64> {
+        if let Some(dictionary) = &mut self.dictionary {
+            dictionary.add(values)?;
+        }
+        let bytes = encode_granule(values, &self.column_def.constraints.compression_type)?;
+        self.file.write_granule(&bytes)?;
+        Ok(bytes.len() as u64)
+    }
+
+    /// Finishes the column file. Column file of a dictionary-encoded column is written here
+    /// from the temporary file, like `TablePart::write_column_with_marks` does: with codes of
+    /// the dictionary, or plain-encoded, when dictionary has over `MAX_DICTIONARY_SIZE` values.
+    ///
+    /// Returns: number of written bytes, or `CouldNotInsertData` on failure.
+    fn finish(self, part_name: &str) -> Result<u64> {
+        let Some(dictionary) = self.dictionary else {
+            return self.file.finish();
+        };
+        let compression_type = &self.column_def.constraints.compression_type;
+        let temporary_path = self.file.path.clone();
+        let granules = self.file.finish_temporary()?;
+        let mut temporary = BufReader::new(File::open(&temporary_path).map_err(|error| {
+            Error::CouldNotInsertData(format!("Failed to open temporary column file: {error}"))
+        })?);
+
+        let mut file = GranuleFile::create(self.path, MAGIC_BYTES_COLUMN)?;
+        if let Some(values) = dictionary.dictionary() {
+            let dictionary_bytes = encode_dictionary(values, compression_type)?;
+            file.write(&(dictionary_bytes.len() as u64).to_le_bytes())?;
+            file.write(&dictionary_bytes)?;
+        } else {
+            warn!(
+                part = part_name, column = self.column_def.name.as_str();
+                "Column {} has over {MAX_DICTIONARY_SIZE} distinct values, \
+                 part {part_name} stores it plain-encoded",
+                self.column_def.name
+            );
+            file.write(&0_u64.to_le_bytes())?;
+        }
+
+        let mut granule = Vec::new();
+        for mark in granules {
+            granule.resize((mark.end - mark.start) as usize, 0);
+            temporary.read_exact(&mut granule).map_err(|error| {
+                Error::CouldNotInsertData(format!("Failed to read temporary column file: {error}"))
+            })?;
+            let Some(dictionary_values) = dictionary.dictionary() else {
+                file.write_granule(&granule)?;
+                continue;
+            };
+            let bytes = decompress_bytes(&granule, compression_type)?;
+            let values =
+                rkyv::from_bytes::<Vec<Value>, rkyv::rancor::Error>(&bytes).map_err(|error| {
+                    Error::CouldNotInsertData(format!(
+                        "Could not read temporary column file: {error}"
+                    ))
+                })?;
+            let codes = encode_codes(&dictionary.codes(&values)?, dictionary_values.len());
+            file.write_granule(&compress_bytes(&codes, compression_type)?)?;
+        }
+        drop(temporary);
+        std::fs::remove_file(&temporary_path).map_err(|error| {
+            Error::CouldNotInsertData(format!("Failed to remove temporary column file: {error}"))
+        })?;
+
+        let written = file.position;
+        Ok(written + file.finish()?)
+    }
+}
+
+/// File of granules with their `MarkInfo`, and CRC32 of bytes after magic bytes.
+struct GranuleFile {
+    path: PathBuf,
+    file: BufWriter<File>,
+    crc: crc32fast::Hasher,
+    /// Size of the file so far.
+    position: u64,
+    marks: Vec<MarkInfo>,
+}
+
+impl GranuleFile {
+    fn create(path: PathBuf, magic: &[u8]) -> Result<Self> {
+        let file = File::create(&path).map_err(|error| {
+            Error::CouldNotInsertData(format!(
+                "Failed to create column file ({}): {error}",
+                path.display()
+            ))
+        })?;
+        let mut file = Self {
+            path,
+            file: BufWriter::new(file),
+            crc: crc32fast::Hasher::new(),
+            position: 0,
+            marks: Vec::new(),
+        };
+        file.write_unchecked(magic)?;
+        Ok(file)
+    }
+
+    /// Writes bytes, which are covered by CRC32.
+    fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        self.crc.update(bytes);
+        self.write_unchecked(bytes)
+    }
+
+    fn write_unchecked(&mut self, bytes: &[u8]) -> Result<()> {
+        self.file.write_all(bytes).map_err(|error| {
+            Error::CouldNotInsertData(format!("Failed to write column file: {error}"))
+        })?;
+        self.position += bytes.len() as u64;
+        Ok(())
+    }
+
+    fn write_granule(&mut self, bytes: &[u8]) -> Result<()> {
+        let start = self.position;
+        self.write(bytes)?;
+        self.marks.push(MarkInfo {
+            start,
+            end: self.position,
+        });
+        Ok(())
+    }
+
+    /// Writes CRC32 and mark file next to the file (see `encode_marks`).
+    ///
+    /// Returns: number of written bytes, or `CouldNotInsertData` on failure.
+    fn finish(mut self) -> Result<u64> {
+        let crc = self.crc.clone().finalize();
+        self.write_unchecked(&crc.to_le_bytes())?;
+        self.file.flush().map_err(|error| {
+            Error::CouldNotInsertData(format!("Failed to write column file: {error}"))
+        })?;
+
+        let marks_bytes = encode_marks(&self.marks);
+        std::fs::write(self.path.with_extension("mrk"), &marks_bytes).map_err(|error| {
+            Error::CouldNotInsertData(format!("Failed to write mark file: {error}"))
+        })?;
+        Ok((size_of::<u32>() + marks_bytes.len()) as u64)
+    }
+
+    /// Flushes temporary file.
+    ///
+    /// Returns: `MarkInfo` of written granules, or `CouldNotInsertData` on failure.
+    fn finish_temporary(mut self) -> Result<Vec<MarkInfo>> {
+        self.file.flush().map_err(|error| {
+            Error::CouldNotInsertData(format!("Failed to write temporary column file: {error}"))
+        })?;
+        Ok(self.marks)
+    }
+}
@@ -1,16 +1,46 @@
+use crate::config::CONFIG;
 use crate::engines::EngineName;
 use crate::error::{Error, Result};
-use crate::storage::{ColumnDef, TableDef, get_unix_time};
+use crate::storage::compression::{decode_info_file, encode_info_file};
+use crate::storage::legacy::{
+    ColumnDefV0, ColumnDefV1, TableMetadataV0, TableMetadataV1, TableMetadataV2, TableMetadataV3,
+    ValueTypeV0,
+};
+use crate::storage::value::{quote_ident, quote_string};
+use crate::storage::{ColumnDef, CompressionType, TableDef, ValueType, get_unix_time};
 
 use rkyv::{Archive as RkyvArchive, Deserialize as RkyvDeserialize, Serialize as RkyvSerialize};
+use std::fmt::Write as _;
 
 pub const TABLE_METADATA_MAGIC_BYTES: &[u8] = b"THMETA".as_slice();
+/// Magic bytes of metadata file with LZ4-compressed data, see `compress_info_files` config.
+pub const TABLE_METADATA_MAGIC_BYTES_LZ4: &[u8] = b"THMETZ".as_slice();
 pub const TABLE_METADATA_FILENAME: &str = ".metadata";
 
-const VERSION: u16 = 1;
+/// 1: the first layout. Column types with parameters, `TableSettings::partition_by` and
+///    `DefaultExpr` defaults were added later without a version change, so these layouts are
+///    told apart by trying each of them.
+/// 2: added `TableMetadata::comment` and `Constraints::comment`.
+/// 3: added `TableSettings::adaptive_index_granularity` and `index_granularity_bytes`.
+/// 4: added `TableSettings::sample_by`.
+const VERSION: u16 = 4;
 
+/// Default `TableSettings::index_granularity`, as in ClickHouse.
+pub const DEFAULT_INDEX_GRANULARITY: u32 = 8192;
+/// Default `TableSettings::index_granularity_bytes`, 10 MiB as in ClickHouse.
+pub const DEFAULT_INDEX_GRANULARITY_BYTES: u32 = 10_485_760;
+
+/// Flags of `TableMetadata`, set by `ALTER TABLE ... MODIFY SETTING`.
 pub mod flags {
     pub const NONE: u32 = 0x0000_0000;
+    /// Content of the table can not change: `INSERT`, `DROP PARTITION` and `EXCHANGE TABLES`
+    /// fail with `TableReadOnly`, as well as `DROP TABLE` and `DROP DATABASE`, unless
+    /// `READ_ONLY_ALLOW_DROP` is set. Background merges continue.
+    pub const READ_ONLY: u32 = 0x0000_0001;
+    /// Background merges of the table are not started, e.g., during backups of its directory.
+    pub const NO_MERGES: u32 = 0x0000_0002;
+    /// Table with `READ_ONLY` can be dropped.
+    pub const READ_ONLY_ALLOW_DROP: u32 = 0x0000_0004;
 }
 
 #[derive(Debug, PartialEq, Clone, RkyvSerialize, RkyvArchive, RkyvDeserialize)]
@@ -23,15 +53,29 @@ pub struct TableSchema {
 /// Table settings parsed from options received in CREATE command.
 #[derive(Debug, PartialEq, Clone, RkyvSerialize, RkyvArchive, RkyvDeserialize)]
 pub struct TableSettings {
+    /// Max number of rows in a granule.
     pub index_granularity: u32,
+    /// Granule also ends, when uncompressed size of its rows in all columns reaches
+    /// `index_granularity_bytes`, so wide rows do not produce oversized granules.
+    pub adaptive_index_granularity: bool,
+    pub index_granularity_bytes: u32,
     pub engine: EngineName,
+    /// `PARTITION BY` expression as SQL. Parts are stored in a directory per its value.
+    pub partition_by: Option<String>,
+    /// `SAMPLE BY` expression as SQL: integer, `Date` or `DateTime` time column, which
+    /// `RESAMPLE BY INTERVAL` groups rows of the table by.
+    pub sample_by: Option<String>,
 }
 
 impl Default for TableSettings {
     fn default() -> Self {
         TableSettings {
-            index_granularity: 8192,
+            index_granularity: DEFAULT_INDEX_GRANULARITY,
+            adaptive_index_granularity: false,
+            index_granularity_bytes: DEFAULT_INDEX_GRANULARITY_BYTES,
             engine: EngineName::MergeTree,
+            partition_by: None,
+            sample_by: None,
         }
     }
 }
@@ -45,37 +89,45 @@ pub struct TableMetadata {
     pub created_at: u64,
     pub settings: TableSettings,
     pub schema: TableSchema,
+    /// Set by table-level `COMMENT 'text'`.
+    pub comment: Option<String>,
 }
 
 impl TableMetadata {
     /// Creates new table metadata with current timestamp and default flags.
     ///
     /// Returns: `TableMetadata` or error from `get_unix_time()`
-    pub fn try_new(schema: TableSchema, settings: TableSettings) -> Result<Self> {
+    pub fn try_new(
+        schema: TableSchema,
+        settings: TableSettings,
+        comment: Option<String>,
+    ) -> Result<Self> {
         Ok(Self {
             version: VERSION,
             flags: flags::NONE,
             created_at: get_unix_time()?,
             settings,
             schema,
+            comment,
         })
     }
 
-    /// Writes table metadata to disk with magic bytes and CRC32 checksum.
+    /// Writes table metadata to disk with magic bytes and CRC32 checksum. Data is compressed,
+    /// when `compress_info_files` is enabled, see `encode_info_file`.
     ///
     /// Returns:
     ///   * Ok: on successful write.
-    ///   * Error: `CouldNotInsertData` on serialization or I/O failure.
+    ///   * Error: `CouldNotInsertData` on serialization, compression or I/O failure.
     pub fn write_to(&self, table_def: &TableDef) -> Result<()> {
-        let mut bytes = Vec::from(TABLE_METADATA_MAGIC_BYTES);
-
         let data_bytes = rkyv::to_bytes::<rkyv::rancor::Error>(self).map_err(|error| {
             Error::CouldNotInsertData(format!("Failed to serialize table metadata: {error}"))
         })?;
-        let crc = crc32fast::hash(&data_bytes);
-
-        bytes.extend(&data_bytes[..]);
-        bytes.extend(crc.to_le_bytes());
+        let bytes = encode_info_file(
+            &data_bytes,
+            TABLE_METADATA_MAGIC_BYTES,
+            TABLE_METADATA_MAGIC_BYTES_LZ4,
+            CONFIG.is_compress_info_files(),
+        )?;
 
         let metadata_path = table_def.get_path().join(TABLE_METADATA_FILENAME);
         let temp_path = metadata_path.with_extension("tmp");
@@ -89,50 +141,431 @@ impl TableMetadata {
         })
     }
 
-    /// Reads table metadata from disk, verifying magic bytes and CRC32 checksum.
+    /// Reads table metadata from disk, verifying magic bytes and CRC32 checksum. Both compressed
+    /// and uncompressed files are read, regardless of `compress_info_files`.
     ///
     /// Returns:
     ///   * Ok: `TableMetadata` on successful read and validation.
-    ///   * Error: `CouldNotReadData` on I/O failure, invalid magic bytes, or CRC mismatch.
+    ///   * Error: `CouldNotReadData` on I/O failure, invalid magic bytes, CRC mismatch, or
+    ///     decompression failure.
     pub fn read_from(table_def: &TableDef) -> Result<Self> {
         let file_bytes = std::fs::read(table_def.get_path().join(TABLE_METADATA_FILENAME))
             .map_err(|error| {
                 Error::CouldNotReadData(format!("Failed to read table metadata: {error}"))
             })?;
 
-        if file_bytes.len() <= TABLE_METADATA_MAGIC_BYTES.len() + 4 {
-            return Err(Error::CouldNotReadData(
-                "Table metadata file too small".to_string(),
-            ));
+        let aligned_data = decode_info_file(
+            &file_bytes,
+            TABLE_METADATA_MAGIC_BYTES,
+            TABLE_METADATA_MAGIC_BYTES_LZ4,
+            "table metadata",
+        )?;
+        Self::deserialize(&aligned_data)
+    }
+
+    /// Deserializes metadata of the current version, or of versions 1 to 3 (in any of the
+    /// layouts of version 1), which is then migrated.
+    ///
+    /// Returns:
+    ///   * Ok: `TableMetadata` of the current version.
+    ///   * Error: `CouldNotReadData` when data matches no known layout.
+    fn deserialize(data: &[u8]) -> Result<Self> {
+        rkyv::from_bytes::<TableMetadata, rkyv::rancor::Error>(data)
+            .or_else(|error| {
+                rkyv::from_bytes::<TableMetadataV3, rkyv::rancor::Error>(data)
+                    .map(Self::from)
+                    .or_else(|_| {
+                        rkyv::from_bytes::<TableMetadataV2, rkyv::rancor::Error>(data)
+                            .map(Self::from)
+                    })
+                    .or_else(|_| {
+                        rkyv::from_bytes::<TableMetadataV1<ColumnDefV1>, rkyv::rancor::Error>(data)
+                            .map(Self::from)
+                    })
+                    .or_else(|_| {
+                        rkyv::from_bytes::<
+                            TableMetadataV1<ColumnDefV0<ValueType>>,
+                            rkyv::rancor::Error,
+                        >(data)
+                        .map(Self::from)
+                    })
+                    .or_else(|_| {
+                        rkyv::from_bytes::<
+                            TableMetadataV0<ColumnDefV0<ValueType>>,
+                            rkyv::rancor::Error,
+                        >(data)
+                        .map(|metadata| Self::from(TableMetadataV1::from(metadata)))
+                    })
+                    .or_else(|_| {
+                        rkyv::from_bytes::<
+                            TableMetadataV0<ColumnDefV0<ValueTypeV0>>,
+                            rkyv::rancor::Error,
+                        >(data)
+                        .map(|metadata| Self::from(TableMetadataV1::from(metadata)))
+                    })
+                    .map(|metadata| Self {
+                        version: VERSION,
+                        ..metadata
+                    })
+                    .map_err(|_| error)
+            })
+            .map_err(|error| {
+                Error::CouldNotReadData(format!("Failed to deserialize table metadata: {error}"))
+            })
+    }
+
+    /// Whether `flag` (see `flags`) is set.
+    pub const fn has_flag(&self, flag: u32) -> bool {
+        self.flags & flag != 0
+    }
+
+    /// Checks that content of the table can change.
+    ///
+    /// Returns:
+    ///   * Ok: when `flags::READ_ONLY` is not set.
+    ///   * Error: `TableReadOnly` otherwise.
+    pub fn check_writable(&self, table_def: &TableDef) -> Result<()> {
+        if self.has_flag(flags::READ_ONLY) {
+            return Err(Error::TableReadOnly(table_def.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Checks that parts of the table can be merged, e.g., by `OPTIMIZE TABLE`.
+    ///
+    /// Returns:
+    ///   * Ok: when `flags::NO_MERGES` is not set.
+    ///   * Error: `MergesStopped` otherwise.
+    pub fn check_mergeable(&self, table_def: &TableDef) -> Result<()> {
+        if self.has_flag(flags::NO_MERGES) {
+            return Err(Error::MergesStopped(table_def.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Checks that the table can be dropped.
+    ///
+    /// Returns:
+    ///   * Ok: when `flags::READ_ONLY` is not set, or `flags::READ_ONLY_ALLOW_DROP` is set.
+    ///   * Error: `TableReadOnly` otherwise.
+    pub fn check_droppable(&self, table_def: &TableDef) -> Result<()> {
+        if self.has_flag(flags::READ_ONLY_ALLOW_DROP) {
+            return Ok(());
         }
+        self.check_writable(table_def)
+    }
 
-        let file_magic_bytes = &file_bytes[0..TABLE_METADATA_MAGIC_BYTES.len()];
-        if file_magic_bytes != TABLE_METADATA_MAGIC_BYTES {
-            return Err(Error::CouldNotReadData(
-                "Invalid magic bytes in table metadata".to_string(),
+    /// Reconstructs `CREATE TABLE` statement, which creates table with the same schema,
+    /// settings and comments.
+    pub fn to_create_sql(&self, table_def: &TableDef) -> String {
+        let columns: Vec<_> = self.schema.columns.iter().map(column_to_sql).collect();
+        let mut sql = format!(
+            "CREATE TABLE {}.{} ({}) ENGINE = {}",
+            quote_ident(&table_def.database),
+            quote_ident(&table_def.table),
+            columns.join(", "),
+            self.settings.engine
+        );
+        if let Some(comment) = &self.comment {
+            let _ = write!(sql, " COMMENT {}", quote_string(comment));
+        }
+        if let Some(partition_by) = &self.settings.partition_by {
+            let _ = write!(sql, " PARTITION BY {partition_by}");
+        }
+        let names = |columns: &[ColumnDef]| {
+            let names: Vec<_> = columns.iter().map(|col| quote_ident(&col.name)).collect();
+            names.join(", ")
+        };
+        let _ = write!(
+            sql,
+            " PRIMARY KEY ({}) ORDER BY ({})",
+            names(&self.schema.primary_key),
+            names(&self.schema.order_by)
+        );
+        if let Some(sample_by) = &self.settings.sample_by {
+            let _ = write!(sql, " SAMPLE BY {sample_by}");
+        }
+        let mut settings = Vec::new();
+        if self.settings.index_granularity != DEFAULT_INDEX_GRANULARITY {
+            settings.push(format!(
+                "index_granularity = {}",
+                self.settings.index_granularity
+            ));
+        }
+        if self.settings.adaptive_index_granularity {
+            settings.push(format!(
+                "adaptive_index_granularity = 1, index_granularity_bytes = {}",
+                self.settings.index_granularity_bytes
             ));
         }
+        // not stored, key columns are NOT NULL without it
+        if self
+            .schema
+            .order_by
+            .iter()
+            .any(|col| col.constraints.nullable)
+        {
+            settings.push("allow_nullable_key = 1".to_string());
+        }
+        if !settings.is_empty() {
+            let _ = write!(sql, " SETTINGS {}", settings.join(", "));
+        }
+        sql
+    }
+}
 
-        let data_bytes = &file_bytes[TABLE_METADATA_MAGIC_BYTES.len()..(file_bytes.len() - 4)];
+/// Returns column definition as in `CREATE TABLE`, e.g., `id UInt64 NOT NULL DEFAULT 1`.
+fn column_to_sql(column_def: &ColumnDef) -> String {
+    let constraints = &column_def.constraints;
+    let mut sql = match constraints.compression_type {
+        CompressionType::Dictionary(_) => {
+            format!(
+                "{} LowCardinality({})",
+                quote_ident(&column_def.name),
+                column_def.field_type
+            )
+        }
+        _ => format!(
+            "{} {}",
+            quote_ident(&column_def.name),
+            column_def.field_type
+        ),
+    };
+    if !constraints.nullable {
+        sql.push_str(" NOT NULL");
+    }
+    if let Some(default) = &constraints.default {
+        let _ = write!(sql, " DEFAULT {}", default.to_sql());
+    }
+    if let Some(comment) = &constraints.comment {
+        let _ = write!(sql, " COMMENT {}", quote_string(comment));
+    }
+    sql
+}
 
-        let expected_crc = u32::from_le_bytes([
-            file_bytes[file_bytes.len() - 4],
-            file_bytes[file_bytes.len() - 3],
-            file_bytes[file_bytes.len() - 2],
-            file_bytes[file_bytes.len() - 1],
-        ]);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::legacy::{
+        ColumnDefV1, ConstraintsV1, TableSchemaV1, TableSettingsV1, TableSettingsV3,
+    };
+    use crate::storage::{DefaultExpr, TablePartInfo, Value, ValueType};
+    use std::path::Path;
 
-        let actual_crc = crc32fast::hash(data_bytes);
-        if expected_crc != actual_crc {
-            return Err(Error::CouldNotReadData(
-                "CRC mismatch in table metadata".to_string(),
-            ));
+    fn settings_v1() -> TableSettingsV1 {
+        TableSettingsV1 {
+            index_granularity: 4096,
+            engine: EngineName::ReplacingMergeTree,
+            partition_by: Some("id".to_string()),
         }
-        // data is not aligned correctly, because of magic bytes
-        let mut aligned_data = rkyv::util::AlignedVec::<16>::with_capacity(data_bytes.len());
-        aligned_data.extend_from_slice(data_bytes);
-        rkyv::from_bytes::<TableMetadata, rkyv::rancor::Error>(&aligned_data).map_err(|error| {
-            Error::CouldNotReadData(format!("Failed to deserialize table metadata: {error}"))
+    }
+
+    #[test]
+    fn test_deserialize_migrates_v1() {
+        let column = ColumnDefV1 {
+            name: "id".to_string(),
+            field_type: ValueType::UInt64,
+            constraints: ConstraintsV1 {
+                nullable: false,
+                default: Some(DefaultExpr::Literal(Value::UInt64(1))),
+                compression_type: CompressionType::LZ4(3),
+            },
+        };
+        let metadata = TableMetadataV1 {
+            version: 1,
+            flags: flags::NONE,
+            created_at: 7,
+            settings: settings_v1(),
+            schema: TableSchemaV1 {
+                columns: vec![column.clone()],
+                order_by: vec![column.clone()],
+                primary_key: vec![column],
+            },
+        };
+        let bytes = rkyv::to_bytes::<rkyv::rancor::Error>(&metadata).unwrap();
+
+        let migrated = TableMetadata::deserialize(&bytes).unwrap();
+        assert_eq!(migrated.version, VERSION);
+        assert_eq!(migrated.created_at, 7);
+        assert_eq!(migrated.comment, None);
+        assert_eq!(migrated.schema.columns[0].constraints.comment, None);
+        assert_eq!(
+            migrated.schema.columns[0].constraints.default,
+            Some(DefaultExpr::Literal(Value::UInt64(1)))
+        );
+
+        let current = rkyv::to_bytes::<rkyv::rancor::Error>(&TableMetadata {
+            comment: Some("c".to_string()),
+            ..migrated.clone()
         })
+        .unwrap();
+        assert_eq!(
+            TableMetadata::deserialize(&current)
+                .unwrap()
+                .comment
+                .as_deref(),
+            Some("c")
+        );
+    }
+
+    #[test]
+    fn test_deserialize_migrates_v2() {
+        let metadata = TableMetadataV2 {
+            version: 2,
+            flags: flags::NONE,
+            created_at: 7,
+            settings: settings_v1(),
+            schema: TableSchema {
+                columns: Vec::new(),
+                order_by: Vec::new(),
+                primary_key: Vec::new(),
+            },
+            comment: Some("c".to_string()),
+        };
+        let bytes = rkyv::to_bytes::<rkyv::rancor::Error>(&metadata).unwrap();
+
+        let migrated = TableMetadata::deserialize(&bytes).unwrap();
+        assert_eq!(migrated.version, VERSION);
+        assert_eq!(migrated.comment.as_deref(), Some("c"));
+        assert_eq!(
+            migrated.settings,
+            TableSettings {
+                index_granularity: 4096,
+                adaptive_index_granularity: false,
+                index_granularity_bytes: DEFAULT_INDEX_GRANULARITY_BYTES,
+                engine: EngineName::ReplacingMergeTree,
+                partition_by: Some("id".to_string()),
+                sample_by: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_deserialize_migrates_v3() {
+        let metadata = TableMetadataV3 {
+            version: 3,
+            flags: flags::NO_MERGES,
+            created_at: 7,
+            settings: TableSettingsV3 {
+                index_granularity: 4096,
+                adaptive_index_granularity: true,
+                index_granularity_bytes: 1024,
+                engine: EngineName::MergeTree,
+                partition_by: None,
+            },
+            schema: TableSchema {
+                columns: Vec::new(),
+                order_by: Vec::new(),
+                primary_key: Vec::new(),
+            },
+            comment: None,
+        };
+        let bytes = rkyv::to_bytes::<rkyv::rancor::Error>(&metadata).unwrap();
+
+        let migrated = TableMetadata::deserialize(&bytes).unwrap();
+        assert_eq!(migrated.version, VERSION);
+        assert_eq!(migrated.flags, flags::NO_MERGES);
+        assert_eq!(
+            migrated.settings,
+            TableSettings {
+                index_granularity: 4096,
+                adaptive_index_granularity: true,
+                index_granularity_bytes: 1024,
+                engine: EngineName::MergeTree,
+                partition_by: None,
+                sample_by: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_baseline_files_are_readable() {
+        // written by the first release: `score Int32 DEFAULT 7`, two rows in the part
+        let table_path =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/legacy/baseline/legacy/t");
+        let bytes = std::fs::read(table_path.join(TABLE_METADATA_FILENAME)).unwrap();
+        let data = decode_info_file(
+            &bytes,
+            TABLE_METADATA_MAGIC_BYTES,
+            TABLE_METADATA_MAGIC_BYTES_LZ4,
+            "table metadata",
+        )
+        .unwrap();
+
+        let metadata = TableMetadata::deserialize(&data).unwrap();
+        assert_eq!(metadata.version, VERSION);
+        assert_eq!(metadata.settings, TableSettings::default());
+        let columns: Vec<_> = metadata
+            .schema
+            .columns
+            .iter()
+            .map(|column_def| (column_def.name.as_str(), &column_def.field_type))
+            .collect();
+        assert_eq!(
+            columns,
+            [
+                ("id", &ValueType::UInt64),
+                ("name", &ValueType::String),
+                ("score", &ValueType::Int32)
+            ]
+        );
+        assert_eq!(
+            metadata.schema.columns[2].constraints.default,
+            Some(DefaultExpr::Literal(Value::Int32(7)))
+        );
+
+        let info = TablePartInfo::read_from(
+            &table_path.join("01a1476f-905c-70d5-b224-16b2bc9017b1"),
+            &metadata.settings,
+        )
+        .unwrap();
+        assert_eq!(info.row_count, 2);
+        assert_eq!(info.column_defs, metadata.schema.columns);
+        assert!(info.pk_bounds.is_empty());
+        assert_eq!(info.partition, None);
+        assert_eq!(info.suspect, None);
+    }
+
+    #[test]
+    fn test_metadata_file_round_trip() {
+        let metadata = TableMetadata {
+            version: VERSION,
+            flags: flags::NONE,
+            created_at: 7,
+            settings: TableSettings::default(),
+            schema: TableSchema {
+                columns: Vec::new(),
+                order_by: Vec::new(),
+                primary_key: Vec::new(),
+            },
+            comment: Some("c".repeat(100)),
+        };
+        let data = rkyv::to_bytes::<rkyv::rancor::Error>(&metadata).unwrap();
+
+        // uncompressed file has the layout of files written before compression was added
+        for (compress, magic) in [
+            (false, TABLE_METADATA_MAGIC_BYTES),
+            (true, TABLE_METADATA_MAGIC_BYTES_LZ4),
+        ] {
+            let bytes = encode_info_file(
+                &data,
+                TABLE_METADATA_MAGIC_BYTES,
+                TABLE_METADATA_MAGIC_BYTES_LZ4,
+                compress,
+            )
+            .unwrap();
+            assert!(bytes.starts_with(magic));
+            let decoded = decode_info_file(
+                &bytes,
+                TABLE_METADATA_MAGIC_BYTES,
+                TABLE_METADATA_MAGIC_BYTES_LZ4,
+                "table metadata",
+            )
+            .unwrap();
+            let read = TableMetadata::deserialize(&decoded).unwrap();
+            assert_eq!(read.created_at, 7);
+            assert_eq!(read.settings, metadata.settings);
+            assert_eq!(read.comment, metadata.comment);
+        }
     }
 }
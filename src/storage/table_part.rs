@@ -1,19 +1,40 @@
+use crate::config::{CONFIG, GranuleValidation};
 use crate::engines::EngineConfig;
 use crate::error::{Error, Result};
-use crate::runtime_config::{TABLE_DATA, TableConfig};
-use crate::storage::compression::{compress_bytes, decompress_bytes};
-use crate::storage::table_metadata::TableMetadata;
-use crate::storage::{Column, ColumnDef, CompressionType, TableDef, Value};
-
+use crate::runtime_config::{NEXT_PART_SEQUENCE, TABLE_DATA, TableConfig};
+use crate::storage::compression::{
+    MAX_DICTIONARY_SIZE, build_dictionary, compress_bytes, decode_codes, decode_info_file,
+    decompress_bytes, encode_codes, encode_info_file,
+};
+use crate::storage::legacy;
+use crate::storage::table_metadata::{TABLE_METADATA_FILENAME, TableMetadata, TableSettings};
+use crate::storage::value::ArchivedValue;
+use crate::storage::write_throttle::{WriteOrigin, throttle_write};
+use crate::storage::{Column, ColumnDef, CompressionType, TableDef, Value, recover_exchange};
+
+use dashmap::DashMap;
 use log::{info, warn};
+use rkyv::vec::ArchivedVec;
 use rkyv::{Archive as RkyvArchive, Deserialize as RkyvDeserialize, Serialize as RkyvSerialize};
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use std::sync::atomic::Ordering;
+use std::time::{Instant, SystemTime};
 use uuid::Uuid;
 
 pub const MAGIC_BYTES_COLUMN: &[u8] = b"THDATA".as_slice();
 pub const MAGIC_BYTES_INFO: &[u8] = b"THINDX".as_slice();
+/// Magic bytes of part info file with LZ4-compressed data, see `compress_info_files` config.
+pub const MAGIC_BYTES_INFO_LZ4: &[u8] = b"THINDZ".as_slice();
+pub const MAGIC_BYTES_MARKS: &[u8] = b"THMARK".as_slice();
 pub const PART_INFO_FILENAME: &str = "part.inf";
 
+/// Stamps of `.metadata` and `part.inf` files, which were loaded by a read-only server, by
+/// their paths. Merged part keeps the name of the newer part, and re-created table keeps its
+/// directory, so `rescan_parts` tells replaced files by their stamps, not by names.
+static LOADED_FILES: LazyLock<DashMap<PathBuf, FileStamp>> = LazyLock::new(DashMap::default);
+
 /// Represents a start byte position and end byte position of the
 /// compressed granule.
 #[derive(Debug, Clone, RkyvSerialize, RkyvArchive, RkyvDeserialize)]
@@ -26,49 +47,125 @@ pub struct MarkInfo {
 #[derive(Debug, Clone, RkyvSerialize, RkyvArchive, RkyvDeserialize)]
 pub struct Mark {
     pub index: Vec<Value>,
-    pub info: Vec<MarkInfo>, // compression
+    /// Position of the granule in each column file. Empty for parts, which keep positions in
+    /// `.mrk` files (see `TablePartInfo::read_column_marks`), only older parts have them here.
+    pub info: Vec<MarkInfo>,
 }
 
 #[derive(Debug, Clone, RkyvSerialize, RkyvArchive, RkyvDeserialize)]
 pub struct TablePartInfo {
     pub name: String,
+    /// Increases with every created part, merged part keeps sequence of the newer part.
+    /// Orders parts for merging, regardless of the name format.
+    pub sequence: u64,
     pub row_count: u64, // max rows per tablepart = 18_446_744_073_709_551_615
     pub marks: Vec<Mark>,
+    /// Min and max values of each primary key column (in primary key order) across all rows.
+    /// Both are `NULL`, when column has only `NULL` values. Used to skip parts during scan.
+    /// Empty for parts written before bounds were stored, which are then never skipped.
+    pub pk_bounds: Vec<(Value, Value)>,
     pub column_defs: Vec<ColumnDef>,
+    /// Partition directory (see `Value::to_partition_id`), when table has `PARTITION BY`.
+    pub partition: Option<String>,
+    /// Why `row_count` can not be trusted, set by `read_from` when it does not agree with
+    /// `marks` (see `check_row_count`). Not stored in `part.inf`.
+    #[rkyv(with = rkyv::with::Skip)]
+    pub suspect: Option<String>,
 }
 
 impl TablePartInfo {
+    /// Returns the filesystem path of this part: `table/part` or `table/partition/part`.
+    pub fn get_path(&self, table_def: &TableDef) -> PathBuf {
+        let mut path = table_def.get_path();
+        if let Some(partition) = &self.partition {
+            path.push(partition);
+        }
+        path.join(&self.name)
+    }
+
     /// Returns the filesystem path for a column's data file within this part.
     pub fn get_column_path(&self, table_def: &TableDef, column_def: &ColumnDef) -> PathBuf {
-        table_def
-            .get_path()
-            .join(&self.name)
+        self.get_path(table_def)
             .join(format!("{}.bin", column_def.name))
     }
 
-    /// Reads and decompresses a granule from disk.
+    /// Returns the filesystem path for a column's mark file within this part.
+    pub fn get_marks_path(&self, table_def: &TableDef, column_def: &ColumnDef) -> PathBuf {
+        self.get_path(table_def)
+            .join(format!("{}.mrk", column_def.name))
+    }
+
+    /// Reads `MarkInfo` of every granule of the column at `file_idx` of `column_defs`. Parts
+    /// written before `.mrk` files were added keep them in `part.inf` (see `Mark::info`), so
+    /// that file is not read for them.
+    ///
+    /// Returns:
+    ///   * Ok: `MarkInfo` per granule of the part.
+    ///   * Error: `CouldNotReadData` when mark file could not be read, is corrupted, or has
+    ///     another number of granules.
+    pub fn read_column_marks(
+        &self,
+        table_def: &TableDef,
+        file_idx: usize,
+    ) -> Result<Vec<MarkInfo>> {
+        if self.marks.first().is_some_and(|mark| !mark.info.is_empty()) {
+            return self
+                .marks
+                .iter()
+                .map(|mark| {
+                    mark.info.get(file_idx).cloned().ok_or_else(|| {
+                        Error::CouldNotReadData(format!("Part has no marks of file {file_idx}"))
+                    })
+                })
+                .collect();
+        }
+
+        let path = self.get_marks_path(table_def, &self.column_defs[file_idx]);
+        let bytes = std::fs::read(&path).map_err(|error| {
+            Error::CouldNotReadData(format!(
+                "Could not read mark file ({}): {error}",
+                path.display()
+            ))
+        })?;
+        decode_marks(&bytes, self.marks.len())
+    }
+
+    /// Reads and decompresses a granule from disk. Dictionary codes are decoded into values,
+    /// so result is always serialized `Vec<Value>`.
     ///
     /// Args:
     ///   * `file`: Column file.
     ///   * `mark_info`: `MarkInfo` of granule
     ///   * `compression_type`: Compression type for the granule
+    ///   * `dictionary`: Dictionary of the column (see `read_dictionary`), when dictionary-encoded
+    ///   * `granule_idx`: Index of granule in part, used in error messages
     ///
     /// Returns: Vec with data from specified granule or `CouldNotReadData` on failure
     pub fn get_granule_bytes_decompressed(
         file: &[u8],
         mark_info: &MarkInfo,
         compression_type: &CompressionType,
+        dictionary: Option<&[Value]>,
+        granule_idx: usize,
     ) -> Result<Vec<u8>> {
+        // offsets are `u64`, so `as usize` below would truncate them
+        #[cfg(target_pointer_width = "32")]
+        if mark_info.end > Column::max_file_size() {
+            return Err(Error::CouldNotReadData(
+                "Column file too large for 32-bit platform".to_string(),
+            ));
+        }
+
         if mark_info.end < mark_info.start {
             return Err(Error::CouldNotReadData(format!(
-                "Invalid mark bounds: end ({}) < start ({})",
+                "granule={granule_idx}: Invalid mark bounds: end ({}) < start ({})",
                 mark_info.end, mark_info.start
             )));
         }
 
         if mark_info.end > file.len() as u64 {
             return Err(Error::CouldNotReadData(format!(
-                "Mark end ({}) exceeds file size ({})",
+                "granule={granule_idx}: Mark end ({}) exceeds file size ({})",
                 mark_info.end,
                 file.len()
             )));
@@ -76,10 +173,114 @@ impl TablePartInfo {
 
         let compressed = &file[(mark_info.start as usize)..(mark_info.end as usize)];
 
-        decompress_bytes(compressed, compression_type)
+        let bytes = decompress_bytes(compressed, compression_type)
+            .map_err(|error| error.with_read_context(format!("granule={granule_idx}")))?;
+
+        // column without dictionary is plain-encoded, see `TablePartInfo::read_dictionary`
+        let (CompressionType::Dictionary(_), Some(dictionary)) = (compression_type, dictionary)
+        else {
+            return Ok(bytes);
+        };
+        let values = decode_codes(&bytes, dictionary)
+            .map_err(|error| error.with_read_context(format!("granule={granule_idx}")))?;
+        rkyv::to_bytes::<rkyv::rancor::Error>(&values)
+            .map(|bytes| bytes.to_vec())
+            .map_err(|error| {
+                Error::CouldNotReadData(format!(
+                    "granule={granule_idx}: Could not serialize decoded values: {error}"
+                ))
+            })
+    }
+
+    /// Gives access to values of decompressed granule (see `get_granule_bytes_decompressed`)
+    /// without deserialization, validating it according to `validate_granules` config.
+    ///
+    /// Returns:
+    ///   * Ok: archived values of the granule.
+    ///   * Error: `CouldNotReadData` when bytes are not a valid archived `Vec<Value>`.
+    pub fn access_granule(bytes: &[u8]) -> Result<&ArchivedVec<ArchivedValue>> {
+        Self::access_granule_with(bytes, CONFIG.get_validate_granules())
+    }
+
+    /// Gives access to values of decompressed granule with given validation mode.
+    ///
+    /// Full validation walks every value once. Measured overhead (release build) per granule of
+    /// 8192 rows is ~20μs for `UInt64` values and ~45μs for short strings, compared to
+    /// constant time of `OnError` and `Never`.
+    ///
+    /// Returns:
+    ///   * Ok: archived values of the granule.
+    ///   * Error: `CouldNotReadData` when validation fails.
+    pub fn access_granule_with(
+        bytes: &[u8],
+        validation: GranuleValidation,
+    ) -> Result<&ArchivedVec<ArchivedValue>> {
+        match validation {
+            GranuleValidation::OnError if granule_top_level_is_valid(bytes) => {
+                // SAFETY: values array lies within `bytes` and is aligned. Values themselves are
+                // trusted, which is the documented trade-off of `OnError`.
+                Ok(unsafe { rkyv::access_unchecked::<ArchivedVec<ArchivedValue>>(bytes) })
+            }
+            GranuleValidation::Never => {
+                // SAFETY: none, corrupted granule is undefined behaviour, as documented for
+                // `Never`.
+                Ok(unsafe { rkyv::access_unchecked::<ArchivedVec<ArchivedValue>>(bytes) })
+            }
+            GranuleValidation::Always | GranuleValidation::OnError => {
+                rkyv::access::<ArchivedVec<ArchivedValue>, rkyv::rancor::Error>(bytes).map_err(
+                    |error| Error::CouldNotReadData(format!("Invalid granule data: {error}")),
+                )
+            }
+        }
+    }
+
+    /// Reads dictionary of the dictionary-encoded column. It is stored right after magic bytes
+    /// as length (u64, little-endian) and compressed serialized `Vec<Value>`. Zero length marks
+    /// column, which fell back to plain encoding (see `MAX_DICTIONARY_SIZE`).
+    ///
+    /// Returns:
+    ///   * Ok: dictionary, or `None` when column is not dictionary-encoded in this part.
+    ///   * Error: `CouldNotReadData` when dictionary is truncated or could not be decoded.
+    pub fn read_dictionary(
+        file: &[u8],
+        compression_type: &CompressionType,
+    ) -> Result<Option<Vec<Value>>> {
+        let CompressionType::Dictionary(_) = compression_type else {
+            return Ok(None);
+        };
+
+        let len_start = MAGIC_BYTES_COLUMN.len();
+        let Some(len_bytes) = file.get(len_start..len_start + size_of::<u64>()) else {
+            return Err(Error::CouldNotReadData(
+                "Column file is too small for dictionary".to_string(),
+            ));
+        };
+        let dictionary_len =
+            u64::from_le_bytes(len_bytes.try_into().expect("slice has size of u64")) as usize;
+        if dictionary_len == 0 {
+            return Ok(None);
+        }
+        let dictionary_start = len_start + size_of::<u64>();
+        let Some(compressed) = dictionary_start
+            .checked_add(dictionary_len)
+            .and_then(|dictionary_end| file.get(dictionary_start..dictionary_end))
+        else {
+            return Err(Error::CouldNotReadData(format!(
+                "Dictionary size ({dictionary_len}) exceeds file size ({})",
+                file.len()
+            )));
+        };
+
+        let bytes = decompress_bytes(compressed, compression_type)?;
+        rkyv::from_bytes::<Vec<Value>, rkyv::rancor::Error>(&bytes)
+            .map(Some)
+            .map_err(|error| {
+                Error::CouldNotReadData(format!("Could not deserialize dictionary: {error}"))
+            })
     }
 
-    /// Writes part info to disk with magic bytes and CRC32 checksum.
+    /// Writes part info to disk with magic bytes and CRC32 checksum. Data is compressed, when
+    /// `compress_info_files` is enabled, see `encode_info_file`.
     ///
     /// Args:
     ///   * `table_def`: Table definition for path resolution.
@@ -87,23 +288,23 @@ impl TablePartInfo {
     ///
     /// Returns:
     ///   * Ok: on successful write.
-    ///   * Error: `CouldNotInsertData` on serialization or I/O failure.
+    ///   * Error: `CouldNotInsertData` on serialization, compression or I/O failure.
     pub fn write_to(&self, table_def: &TableDef, raw: bool) -> Result<()> {
-        let mut bytes = Vec::from(MAGIC_BYTES_INFO);
-
         let data_bytes = rkyv::to_bytes::<rkyv::rancor::Error>(self).map_err(|error| {
             Error::CouldNotInsertData(format!("Failed to serialize part info: {error}"))
         })?;
-        let crc = crc32fast::hash(&data_bytes);
-
-        bytes.extend(&data_bytes[..]);
-        bytes.extend(crc.to_le_bytes());
+        let bytes = encode_info_file(
+            &data_bytes,
+            MAGIC_BYTES_INFO,
+            MAGIC_BYTES_INFO_LZ4,
+            CONFIG.is_compress_info_files(),
+        )?;
 
-        let mut path = table_def.get_path();
-        if raw {
-            path.push("raw");
-        }
-        path.push(&self.name);
+        let mut path = if raw {
+            raw_dir(table_def, &self.name)
+        } else {
+            self.get_path(table_def)
+        };
         path.push(PART_INFO_FILENAME);
 
         if let Some(parent) = path.parent() {
@@ -116,56 +317,62 @@ impl TablePartInfo {
             .map_err(|error| Error::CouldNotInsertData(format!("Failed to write file: {error}")))
     }
 
-    /// Reads part info from disk, verifying magic bytes and CRC32 checksum.
+    /// Checks that number of marks agrees with `row_count` for granularity of `settings` of the
+    /// table: every granule has `index_granularity` rows, except the last one. With
+    /// `adaptive_index_granularity` granules may end earlier, but have at least one row.
+    ///
+    /// Returns:
+    ///   * Ok: when `row_count` and `marks` agree.
+    ///   * Error: `CouldNotReadData` describing the mismatch.
+    pub fn check_row_count(&self, settings: &TableSettings) -> Result<()> {
+        let granularity = u64::from(settings.index_granularity.max(1));
+        let min_marks = self.row_count.div_ceil(granularity);
+        let max_marks = if settings.adaptive_index_granularity {
+            self.row_count
+        } else {
+            min_marks
+        };
+        let marks = self.marks.len() as u64;
+        if (min_marks..=max_marks).contains(&marks) {
+            return Ok(());
+        }
+        Err(Error::CouldNotReadData(format!(
+            "Part {} has {marks} marks, which do not match {} rows with index_granularity {granularity}",
+            self.name, self.row_count
+        )))
+    }
+
+    /// Reads part info from disk, verifying magic bytes and CRC32 checksum. `row_count` is
+    /// checked against marks with `settings` of the table (see `check_row_count`): part, which
+    /// fails the check, is still returned, but has `suspect` set. Both compressed and
+    /// uncompressed files are read, regardless of `compress_info_files`.
     ///
     /// Returns:
     ///   * Ok: `TablePartInfo` on successful read and validation.
-    ///   * Error: `CouldNotReadData` on I/O failure, invalid magic bytes, or CRC mismatch.
-    pub fn read_from(table_def: &TableDef, part_name: &str) -> Result<Self> {
-        let file_bytes = std::fs::read(
-            table_def
-                .get_path()
-                .join(part_name)
-                .join(PART_INFO_FILENAME),
-        )
-        .map_err(|error| {
+    ///   * Error: `CouldNotReadData` on I/O failure, invalid magic bytes, CRC mismatch, or
+    ///     decompression failure.
+    pub fn read_from(part_path: &Path, settings: &TableSettings) -> Result<Self> {
+        let file_bytes = std::fs::read(part_path.join(PART_INFO_FILENAME)).map_err(|error| {
             Error::CouldNotReadData(format!("Failed to read part info file: {error}"))
         })?;
 
-        if file_bytes.len() <= MAGIC_BYTES_INFO.len() + 4 {
-            return Err(Error::CouldNotReadData(
-                "Part info file is too small".to_string(),
-            ));
-        }
-
-        let file_magic_bytes = &file_bytes[0..MAGIC_BYTES_INFO.len()];
-        if file_magic_bytes != MAGIC_BYTES_INFO {
-            return Err(Error::CouldNotReadData(
-                "Invalid magic bytes in part info file".to_string(),
-            ));
-        }
-
-        let data_bytes = &file_bytes[MAGIC_BYTES_INFO.len()..(file_bytes.len() - 4)];
-
-        let expected_crc = u32::from_le_bytes([
-            file_bytes[file_bytes.len() - 4],
-            file_bytes[file_bytes.len() - 3],
-            file_bytes[file_bytes.len() - 2],
-            file_bytes[file_bytes.len() - 1],
-        ]);
-
-        let actual_crc = crc32fast::hash(data_bytes);
-        if expected_crc != actual_crc {
-            return Err(Error::CouldNotReadData(
-                "CRC mismatch in part info file".to_string(),
-            ));
-        }
-        // data is not aligned correctly, because of magic bytes
-        let mut aligned_data = rkyv::util::AlignedVec::<16>::with_capacity(data_bytes.len());
-        aligned_data.extend_from_slice(data_bytes);
-        rkyv::from_bytes::<TablePartInfo, rkyv::rancor::Error>(&aligned_data).map_err(|error| {
-            Error::CouldNotReadData(format!("Failed to deserialize part info: {error}"))
-        })
+        let aligned_data = decode_info_file(
+            &file_bytes,
+            MAGIC_BYTES_INFO,
+            MAGIC_BYTES_INFO_LZ4,
+            "part info file",
+        )?;
+        // parts written by older versions have other layouts
+        let mut info = rkyv::from_bytes::<TablePartInfo, rkyv::rancor::Error>(&aligned_data)
+            .or_else(|error| legacy::deserialize_part_info(&aligned_data, part_path).ok_or(error))
+            .map_err(|error| {
+                Error::CouldNotReadData(format!("Failed to deserialize part info: {error}"))
+            })?;
+        info.suspect = info
+            .check_row_count(settings)
+            .err()
+            .map(|error| error.to_string());
+        Ok(info)
     }
 }
 
@@ -174,19 +381,22 @@ impl TablePartInfo {
 pub struct TablePart {
     pub info: TablePartInfo,
     pub data: Vec<Column>,
+    /// First row of every granule, see `granule_starts`.
+    granule_starts: Vec<usize>,
 }
 
 impl TablePart {
     /// Creates a new table part with generated UUID name and indexes.
     ///
     /// Orders columns according to engine requirements and generates primary indexes
-    /// for ORDER BY columns.
+    /// for ORDER BY columns. All rows must belong to `partition`. Part gets the next sequence number.
     ///
     /// Returns: Self or engine error
     pub fn try_new(
         table_def: &TableDef,
         columns: Vec<Column>,
         name: Option<String>,
+        partition: Option<String>,
     ) -> Result<Self> {
         if columns.is_empty() {
             return Err(Error::InvalidSource("No columns provided".to_string()));
@@ -194,11 +404,19 @@ impl TablePart {
         if columns[0].data.is_empty() {
             return Err(Error::InvalidSource("No data provided".to_string()));
         }
-        let name = name.unwrap_or(Uuid::now_v7().to_string());
-
         let Some(table_config) = TABLE_DATA.get(table_def) else {
             return Err(Error::TableNotFound);
         };
+        // generated name repeats a name of an active part only after the clock went back, while
+        // given name (merged part takes the name of the newer part) is checked by `move_to_normal`
+        let name = name.unwrap_or_else(|| {
+            loop {
+                let name = Uuid::now_v7().to_string();
+                if !table_config.infos.iter().any(|info| info.name == name) {
+                    break name;
+                }
+            }
+        });
 
         let engine = table_config
             .metadata
@@ -211,44 +429,61 @@ impl TablePart {
             &table_config.metadata.schema.primary_key,
         )?;
 
+        let granule_starts = granule_starts(&data, &table_config.metadata.settings);
         let marks = generate_indexes(
             &data,
             &table_config.metadata.schema.primary_key,
-            table_config.metadata.settings.index_granularity,
+            &granule_starts,
         );
         let row_count = data[0].data.len() as u64;
+        let pk_bounds = compute_pk_bounds(&data, &table_config.metadata.schema.primary_key);
 
         let info = TablePartInfo {
             name,
+            sequence: NEXT_PART_SEQUENCE.fetch_add(1, Ordering::Relaxed),
             marks,
             row_count,
+            pk_bounds,
             column_defs: data.iter().map(|col| col.column_def.clone()).collect(),
+            partition,
+            suspect: None,
         };
 
-        Ok(Self { info, data })
+        Ok(Self {
+            info,
+            data,
+            granule_starts,
+        })
+    }
+
+    /// Creates part, which is already written into the raw directory by `PartWriter`, so its
+    /// data is not kept in memory.
+    pub(super) const fn written(info: TablePartInfo) -> Self {
+        Self {
+            info,
+            data: Vec::new(),
+            granule_starts: Vec::new(),
+        }
     }
 
     /// Saves part data and indexes to raw directory.
     ///
-    /// Writes each column to separate .bin file and info to `PART_INFO_FILENAME`.
-    /// All files include magic bytes and CRC32 checksums.
+    /// Writes each column to separate .bin file with its marks in .mrk file, and info to
+    /// `PART_INFO_FILENAME`. All files include magic bytes and CRC32 checksums.
+    ///
+    /// Writes of merged parts (`WriteOrigin::Merge`) are throttled after every column file by
+    /// `max_merge_bytes_per_sec`.
     ///
     /// Returns: Ok or `CouldNotInsertData` on I/O failure
-    pub fn save_raw(&mut self, table_def: &TableDef) -> Result<()> {
+    pub fn save_raw(&mut self, table_def: &TableDef, origin: WriteOrigin) -> Result<()> {
         let raw_dir = self.get_raw_dir(table_def);
         std::fs::create_dir_all(&raw_dir)
             .map_err(|_| Error::CouldNotInsertData("Failed to create raw directory".to_string()))?;
 
-        let granularity = {
-            let Some(config) = TABLE_DATA.get(table_def) else {
-                return Err(Error::TableNotFound);
-            };
-            Ok(config.metadata.settings.index_granularity)
-        }?;
-
         for col_idx in 0..self.data.len() {
             let column_file = raw_dir.join(format!("{}.bin", self.data[col_idx].column_def.name));
-            self.write_column_with_marks(col_idx, &column_file, granularity)?;
+            let bytes = self.write_column_with_marks(col_idx, &column_file)?;
+            throttle_write(origin, bytes);
         }
 
         self.info.write_to(table_def, true)?;
@@ -256,33 +491,60 @@ impl TablePart {
         Ok(())
     }
 
-    /// Writes a single column file with granule-by-granule serialization and populates `MarkInfo`.
-    fn write_column_with_marks(
-        &mut self,
-        col_idx: usize,
-        path: &PathBuf,
-        index_granularity: u32,
-    ) -> Result<()> {
+    /// Writes a single column file with granule-by-granule serialization, and its `MarkInfo`
+    /// into the mark file next to it (see `encode_marks`). Granules of all columns start at the
+    /// same rows (`granule_starts`).
+    ///
+    /// Returns: size of the written files, or `CouldNotInsertData` on failure.
+    fn write_column_with_marks(&self, col_idx: usize, path: &Path) -> Result<u64> {
         let mut file_bytes = Vec::from(MAGIC_BYTES_COLUMN);
-        let granule_size = index_granularity as usize;
         let total_rows = self.data[col_idx].data.len();
+        let compression_type = self.data[col_idx]
+            .column_def
+            .constraints
+            .compression_type
+            .clone();
+
+        // dictionary is shared by all granules, see `TablePartInfo::read_dictionary`
+        let dictionary_codes = if let CompressionType::Dictionary(_) = compression_type {
+            let (dictionary, codes) = build_dictionary(&self.data[col_idx].data.values())?;
+            if dictionary.len() > MAX_DICTIONARY_SIZE {
+                warn!(
+                    part = self.info.name.as_str(), column = self.data[col_idx].column_def.name.as_str();
+                    "Column {} has {} distinct values (over {MAX_DICTIONARY_SIZE}), \
+                     part {} stores it plain-encoded",
+                    self.data[col_idx].column_def.name,
+                    dictionary.len(),
+                    self.info.name
+                );
+                file_bytes.extend(0_u64.to_le_bytes());
+                None
+            } else {
+                let dictionary_bytes = encode_dictionary(&dictionary, &compression_type)?;
+                file_bytes.extend((dictionary_bytes.len() as u64).to_le_bytes());
+                file_bytes.extend(&dictionary_bytes);
+                Some((dictionary.len(), codes))
+            }
+        } else {
+            None
+        };
 
-        for (granule_idx, chunk_start) in (0..total_rows).step_by(granule_size).enumerate() {
-            let chunk_end = (chunk_start + granule_size).min(total_rows);
-            let granule_data = self.data[col_idx].data[chunk_start..chunk_end]
-                .as_ref()
-                .to_vec();
+        let mut marks = Vec::with_capacity(self.granule_starts.len());
+        for (granule_idx, &chunk_start) in self.granule_starts.iter().enumerate() {
+            let chunk_end = self
+                .granule_starts
+                .get(granule_idx + 1)
+                .map_or(total_rows, |&next_start| next_start.min(total_rows));
 
             let start_pos = file_bytes.len() as u64;
 
-            let granule_bytes =
-                rkyv::to_bytes(&granule_data).map_err(|error: rkyv::rancor::Error| {
-                    Error::CouldNotInsertData(format!("Could not serialize data: {error}"))
-                })?;
-            let granule_bytes = compress_bytes(
-                &granule_bytes,
-                &granule_data[0].get_type().get_optimal_compression(),
-            )?;
+            let granule_bytes = if let Some((dictionary_len, codes)) = &dictionary_codes {
+                let codes = encode_codes(&codes[chunk_start..chunk_end], *dictionary_len);
+                compress_bytes(&codes, &compression_type)?
+            } else {
+                let granule_data = self.data[col_idx].data.to_vec(chunk_start..chunk_end);
+                encode_granule(&granule_data, &compression_type)?
+            };
             file_bytes.extend(&granule_bytes);
 
             let end_pos = file_bytes.len() as u64;
@@ -293,7 +555,7 @@ impl TablePart {
                 ));
             }
 
-            self.info.marks[granule_idx].info.push(MarkInfo {
+            marks.push(MarkInfo {
                 start: start_pos,
                 end: end_pos,
             });
@@ -303,25 +565,51 @@ impl TablePart {
         let crc = crc32fast::hash(data_bytes);
         file_bytes.extend(crc.to_le_bytes());
 
-        std::fs::write(path, file_bytes).map_err(|error| {
+        std::fs::write(path, &file_bytes).map_err(|error| {
             Error::CouldNotInsertData(format!("Failed to write column file: {error}"))
-        })
+        })?;
+
+        let marks_bytes = encode_marks(&marks);
+        std::fs::write(path.with_extension("mrk"), &marks_bytes).map_err(|error| {
+            Error::CouldNotInsertData(format!("Failed to write mark file: {error}"))
+        })?;
+        Ok((file_bytes.len() + marks_bytes.len()) as u64)
     }
 
     /// Atomically moves part from raw to normal directory and updates in-memory index.
     ///
     /// Updates memory first (under exclusive lock), then renames directory.
-    /// Rolls back memory change on filesystem failure.
+    /// Rolls back memory change on filesystem failure. Part never replaces another one: name
+    /// must not belong to an active part of the table, and its directory must not exist (e.g.,
+    /// copied by hand).
     ///
-    /// Returns: Ok or `CouldNotInsertData` with rollback on failure
+    /// Returns: Ok or `CouldNotInsertData` on name collision, or with rollback on failure
     pub fn move_to_normal(self, table_def: &TableDef) -> Result<()> {
         let raw_dir = self.get_raw_dir(table_def);
-        let normal_dir = table_def.get_path().join(&self.info.name);
+        let normal_dir = self.info.get_path(table_def);
+
+        if let Some(partition_dir) = normal_dir.parent() {
+            std::fs::create_dir_all(partition_dir).map_err(|error| {
+                Error::CouldNotInsertData(format!("Failed to create partition directory: {error}"))
+            })?;
+        }
 
         let Some(mut result) = TABLE_DATA.get_mut(table_def) else {
             return Err(Error::TableNotFound);
         };
         let part_name = self.info.name.clone();
+        if result.infos.iter().any(|info| info.name == part_name) {
+            return Err(Error::CouldNotInsertData(format!(
+                "Part {part_name} already exists in table {table_def}"
+            )));
+        }
+        // rename replaces an empty directory, and some platforms replace any
+        if normal_dir.try_exists().unwrap_or(true) {
+            return Err(Error::CouldNotInsertData(format!(
+                "Directory of part {part_name} already exists: {}",
+                normal_dir.display()
+            )));
+        }
         result.infos.push(self.info);
 
         if let Err(e) = std::fs::rename(&raw_dir, &normal_dir) {
@@ -335,25 +623,85 @@ impl TablePart {
     }
 
     fn get_raw_dir(&self, table_def: &TableDef) -> PathBuf {
-        table_def.get_path().join("raw").join(&self.info.name)
+        raw_dir(table_def, &self.info.name)
     }
 }
 
+/// Returns directory, where part is written before it is moved into the table directory.
+pub(super) fn raw_dir(table_def: &TableDef, name: &str) -> PathBuf {
+    table_def.get_path().join("raw").join(name)
+}
+
+/// Serializes and compresses values of a granule of a plain-encoded column.
+///
+/// Returns: Ok or `CouldNotInsertData` on serialization or compression failure.
+#[allow(clippy::ptr_arg)] // granules are read back as `Vec<Value>`
+pub(super) fn encode_granule(
+    values: &Vec<Value>,
+    compression_type: &CompressionType,
+) -> Result<Vec<u8>> {
+    let bytes = rkyv::to_bytes(values).map_err(|error: rkyv::rancor::Error| {
+        Error::CouldNotInsertData(format!("Could not serialize data: {error}"))
+    })?;
+    compress_bytes(&bytes, compression_type)
+}
+
+/// Serializes and compresses dictionary of a column, see `TablePartInfo::read_dictionary`.
+///
+/// Returns: Ok or `CouldNotInsertData` on serialization or compression failure.
+#[allow(clippy::ptr_arg)] // dictionary is read back as `Vec<Value>`
+pub(super) fn encode_dictionary(
+    dictionary: &Vec<Value>,
+    compression_type: &CompressionType,
+) -> Result<Vec<u8>> {
+    let bytes = rkyv::to_bytes(dictionary).map_err(|error: rkyv::rancor::Error| {
+        Error::CouldNotInsertData(format!("Could not serialize dictionary: {error}"))
+    })?;
+    compress_bytes(&bytes, compression_type)
+}
+
+/// Returns first row of every granule. Granule has `index_granularity` rows. With
+/// `adaptive_index_granularity` it ends earlier, when uncompressed size of its rows in all
+/// columns (see `Value::archived_size`) reaches `index_granularity_bytes`. Every granule has at
+/// least one row.
+fn granule_starts(columns: &[Column], settings: &TableSettings) -> Vec<usize> {
+    let total_rows = columns.first().map_or(0, |col| col.data.len());
+    let max_rows = (settings.index_granularity as usize).max(1);
+    if !settings.adaptive_index_granularity {
+        return (0..total_rows).step_by(max_rows).collect();
+    }
+
+    let mut starts = Vec::new();
+    let (mut granule_rows, mut granule_bytes) = (0, 0);
+    for row_idx in 0..total_rows {
+        if granule_rows == 0 {
+            starts.push(row_idx);
+        }
+        granule_rows += 1;
+        granule_bytes += columns
+            .iter()
+            .map(|col| col.data[row_idx].archived_size())
+            .sum::<usize>();
+        if granule_rows == max_rows || granule_bytes >= settings.index_granularity_bytes as usize {
+            (granule_rows, granule_bytes) = (0, 0);
+        }
+    }
+    starts
+}
+
 fn generate_indexes(
     columns: &[Column],
     order_by: &[ColumnDef],
-    index_granularity: u32,
+    granule_starts: &[usize],
 ) -> Vec<Mark> {
     let columns_in_order_by: Vec<&Column> = columns
         .iter()
         .filter(|x| order_by.contains(&x.column_def))
         .collect();
 
-    let total_rows = columns_in_order_by.first().map_or(0, |x| x.data.len());
-    let num_granules = total_rows.div_ceil(index_granularity as usize);
-    let mut marks = Vec::with_capacity(num_granules);
+    let mut marks = Vec::with_capacity(granule_starts.len());
 
-    for row_idx in (0..total_rows).step_by(index_granularity as usize) {
+    for &row_idx in granule_starts {
         let row_values: Vec<Value> = columns_in_order_by
             .iter()
             .map(|x| x.data[row_idx].clone())
@@ -366,13 +714,179 @@ fn generate_indexes(
     marks
 }
 
+/// Computes min and max non-`NULL` value of each primary key column.
+///
+/// Returns: `(min, max)` for each column of `primary_key`. `(NULL, NULL)` when column has no values.
+fn compute_pk_bounds(columns: &[Column], primary_key: &[ColumnDef]) -> Vec<(Value, Value)> {
+    primary_key
+        .iter()
+        .map(|pk_col_def| {
+            let Some(column) = columns.iter().find(|col| col.column_def == *pk_col_def) else {
+                return (Value::Null, Value::Null);
+            };
+
+            let mut values = column.data.iter().filter(|value| **value != Value::Null);
+            let Some(first) = values.next() else {
+                return (Value::Null, Value::Null);
+            };
+            let (min, max) = values.fold((first, first), |(min, max), value| {
+                (
+                    if value < min { value } else { min },
+                    if value > max { value } else { max },
+                )
+            });
+            (min.clone(), max.clone())
+        })
+        .collect()
+}
+
+/// Serializes marks of a column file: magic bytes, `start` and `end` of each granule as
+/// little-endian `u64`, and CRC32 checksum of them.
+pub(super) fn encode_marks(marks: &[MarkInfo]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(MAGIC_BYTES_MARKS.len() + marks.len() * 16 + 4);
+    bytes.extend(MAGIC_BYTES_MARKS);
+    for mark in marks {
+        bytes.extend(mark.start.to_le_bytes());
+        bytes.extend(mark.end.to_le_bytes());
+    }
+    let crc = crc32fast::hash(&bytes[MAGIC_BYTES_MARKS.len()..]);
+    bytes.extend(crc.to_le_bytes());
+    bytes
+}
+
+/// Reads marks written by `encode_marks`.
+///
+/// Returns:
+///   * Ok: `MarkInfo` of each granule.
+///   * Error: `CouldNotReadData` on invalid magic bytes, CRC mismatch, or when file has not
+///     exactly `granules` marks.
+fn decode_marks(bytes: &[u8], granules: usize) -> Result<Vec<MarkInfo>> {
+    let expected_len = MAGIC_BYTES_MARKS.len() + granules * 16 + 4;
+    if bytes.len() != expected_len {
+        return Err(Error::CouldNotReadData(format!(
+            "Mark file has {} bytes, expected {expected_len} for {granules} granules",
+            bytes.len()
+        )));
+    }
+    if &bytes[..MAGIC_BYTES_MARKS.len()] != MAGIC_BYTES_MARKS {
+        return Err(Error::CouldNotReadData(
+            "Invalid magic bytes in mark file".to_string(),
+        ));
+    }
+
+    let (data, crc) = bytes[MAGIC_BYTES_MARKS.len()..].split_at(granules * 16);
+    if crc32fast::hash(data).to_le_bytes() != crc {
+        return Err(Error::CouldNotReadData(
+            "CRC mismatch in mark file".to_string(),
+        ));
+    }
+
+    let read_u64 = |bytes: &[u8]| u64::from_le_bytes(bytes.try_into().expect("8 bytes"));
+    Ok(data
+        .chunks_exact(16)
+        .map(|mark| MarkInfo {
+            start: read_u64(&mark[..8]),
+            end: read_u64(&mark[8..]),
+        })
+        .collect())
+}
+
+// Root of a granule is a relative pointer (i32) and a length (u32).
+const _: () = assert!(size_of::<ArchivedVec<ArchivedValue>>() == 8);
+
+/// Checks, in constant time, that root of the granule and its values array lie within `bytes`
+/// and are aligned. Values themselves are not checked.
+fn granule_top_level_is_valid(bytes: &[u8]) -> bool {
+    let root_size = size_of::<ArchivedVec<ArchivedValue>>();
+    if bytes.len() < root_size {
+        return false;
+    }
+    let root_pos = rkyv::api::root_position::<ArchivedVec<ArchivedValue>>(bytes.len());
+    let base = bytes.as_ptr() as usize;
+    if !(base + root_pos).is_multiple_of(align_of::<ArchivedVec<ArchivedValue>>()) {
+        return false;
+    }
+
+    let offset = i32::from_le_bytes(bytes[root_pos..root_pos + 4].try_into().expect("4 bytes"));
+    let len = u32::from_le_bytes(
+        bytes[root_pos + 4..root_pos + 8]
+            .try_into()
+            .expect("4 bytes"),
+    );
+    let Some(start) = root_pos.checked_add_signed(offset as isize) else {
+        return false;
+    };
+    let Some(end) = (len as usize)
+        .checked_mul(size_of::<ArchivedValue>())
+        .and_then(|size| start.checked_add(size))
+    else {
+        return false;
+    };
+    end <= bytes.len() && (base + start).is_multiple_of(align_of::<ArchivedValue>())
+}
+
+/// Identity of a file, which changes, when the file is replaced or rewritten.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileStamp {
+    len: u64,
+    modified: Option<SystemTime>,
+    /// Inode on Unix, so a file, replaced in the same instant with the same size, differs too.
+    id: u64,
+}
+
+impl FileStamp {
+    /// Returns: stamp of the file at `path`, or None, when it could not be read.
+    fn read(path: &Path) -> Option<Self> {
+        let metadata = std::fs::metadata(path).ok()?;
+        Some(Self {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            id: file_id(&metadata),
+        })
+    }
+}
+
+#[cfg(unix)]
+fn file_id(metadata: &std::fs::Metadata) -> u64 {
+    std::os::unix::fs::MetadataExt::ino(metadata)
+}
+
+#[cfg(not(unix))]
+const fn file_id(_metadata: &std::fs::Metadata) -> u64 {
+    0
+}
+
+/// Remembers stamp of the file at `path` for `rescan_parts`, when server is read-only. Stamp is
+/// taken before the file is read, so a file, replaced meanwhile, is read again by the next scan.
+fn remember_stamp(path: PathBuf) {
+    if !CONFIG.is_read_only() {
+        return;
+    }
+    if let Some(stamp) = FileStamp::read(&path) {
+        LOADED_FILES.insert(path, stamp);
+    } else {
+        LOADED_FILES.remove(&path);
+    }
+}
+
+/// Whether file at `path` is the one, which was loaded before (see `remember_stamp`).
+fn is_loaded_file(path: &Path) -> bool {
+    LOADED_FILES
+        .get(path)
+        .is_some_and(|stamp| FileStamp::read(path) == Some(*stamp))
+}
+
 /// Loads all table parts from filesystem into memory on startup.
 ///
 /// Scans all databases and tables, loads part indexes, and populates `TABLE_DATA`.
-/// Cleans up any leftover raw directories from crashes.
+/// Cleans up any leftover raw directories from crashes and recovers interrupted
+/// `EXCHANGE TABLES` (see `recover_exchange`), unless server is read-only. Logs number of
+/// loaded tables and parts, and time of loading.
 ///
 /// Returns: Ok or `CouldNotInsertData` on critical failure
 pub fn load_all_parts_on_startup(db_dir: &Path) -> Result<()> {
+    let started = Instant::now();
+    let (mut tables, mut parts) = (0, 0);
     info!(
         "Loading parts from database directory: {}",
         db_dir.display()
@@ -383,110 +897,598 @@ pub fn load_all_parts_on_startup(db_dir: &Path) -> Result<()> {
         return Ok(());
     }
 
+    if !CONFIG.is_read_only() {
+        recover_exchange(db_dir)?;
+    }
+
+    for (table_def, table_path) in table_dirs(db_dir)? {
+        remember_stamp(table_path.join(TABLE_METADATA_FILENAME));
+        let table_metadata = TableMetadata::read_from(&table_def)?;
+        tables += 1;
+        let partitioned = table_metadata.settings.partition_by.is_some();
+        let settings = table_metadata.settings.clone();
+
+        TABLE_DATA.insert(
+            table_def.clone(),
+            TableConfig {
+                metadata: table_metadata,
+                infos: Vec::new(),
+            },
+        );
+
+        // raw directory of read-only server belongs to the server, which writes to the storage
+        let raw_path = table_path.join("raw");
+        if raw_path.is_dir() && !CONFIG.is_read_only() {
+            match std::fs::remove_dir_all(&raw_path) {
+                Ok(()) => {
+                    info!(table:% = table_def; "Removed raw directory for table {table_def}");
+                }
+                Err(e) => {
+                    warn!(
+                        table:% = table_def;
+                        "Failed to remove raw directory for table {table_def}: {e}"
+                    );
+                }
+            }
+        }
+
+        for part_path in part_dirs(&table_path, partitioned)? {
+            let part_name = part_name(&part_path);
+            if is_old_part(&part_path) {
+                warn!(
+                    "Found old part: {part_name}. Consult the logs to make the decision about removal."
+                );
+                continue;
+            }
+
+            remember_stamp(part_path.join(PART_INFO_FILENAME));
+            match TablePartInfo::read_from(&part_path, &settings) {
+                Ok(info) => {
+                    if let Some(reason) = &info.suspect {
+                        warn!(
+                            table:% = table_def, part = part_name.as_str();
+                            "Part {part_name} of table {table_def} is suspect, its row count can not be trusted: {reason}"
+                        );
+                    }
+                    let Some(mut result) = TABLE_DATA.get_mut(&table_def) else {
+                        continue;
+                    };
+                    NEXT_PART_SEQUENCE.fetch_max(info.sequence + 1, Ordering::Relaxed);
+                    result.infos.push(info);
+                    parts += 1;
+                    info!(
+                        table:% = table_def, part = part_name.as_str();
+                        "Loaded part {part_name} for table {table_def}"
+                    );
+                }
+                Err(e) => {
+                    warn!(
+                        table:% = table_def, part = part_name.as_str();
+                        "Failed to load part {part_name} for table {table_def}: {e:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    let elapsed_ms = started.elapsed().as_millis() as u64;
+    info!(
+        tables = tables, parts = parts, elapsed_ms = elapsed_ms;
+        "Finished loading {parts} parts of {tables} tables in {elapsed_ms} ms"
+    );
+    Ok(())
+}
+
+/// Brings `TABLE_DATA` in line with the storage directory, which is written by another server.
+///
+/// Loads tables and parts, which appeared since the last scan, and forgets those, which were
+/// removed (e.g., dropped, or merged into a new part). Parts and tables, whose files were
+/// replaced since they were loaded (e.g., merged part keeps the name of the newer part, table
+/// was re-created or exchanged), are loaded again, with all parts of such table. Unlike
+/// `load_all_parts_on_startup`, never modifies the storage directory. Parts appear atomically
+/// (they are moved from `raw` directory when complete), so only complete parts are loaded.
+/// Tables, whose metadata could not be read yet, are retried on the next scan.
+///
+/// Returns: Ok or `CouldNotInsertData`, when storage directory could not be read.
+pub fn rescan_parts(db_dir: &Path) -> Result<()> {
+    let tables = table_dirs(db_dir)?;
+
+    TABLE_DATA.retain(|table_def, _| {
+        let exists = tables.iter().any(|(found, _)| found == table_def);
+        if !exists {
+            info!(table:% = table_def; "Table {table_def} was removed from storage");
+        }
+        exists
+    });
+
+    let mut present_files = HashSet::new();
+    for (table_def, table_path) in tables {
+        let metadata_path = table_path.join(TABLE_METADATA_FILENAME);
+        if TABLE_DATA.contains_key(&table_def) && !is_loaded_file(&metadata_path) {
+            info!(table:% = table_def; "Table {table_def} was replaced in storage");
+            TABLE_DATA.remove(&table_def);
+        }
+        if !TABLE_DATA.contains_key(&table_def) {
+            remember_stamp(metadata_path.clone());
+            match TableMetadata::read_from(&table_def) {
+                Ok(metadata) => {
+                    TABLE_DATA.insert(
+                        table_def.clone(),
+                        TableConfig {
+                            metadata,
+                            infos: Vec::new(),
+                        },
+                    );
+                    info!(table:% = table_def; "Loaded new table {table_def}");
+                }
+                Err(e) => {
+                    warn!(
+                        table:% = table_def;
+                        "Failed to load metadata of table {table_def}: {e:?}"
+                    );
+                    continue;
+                }
+            }
+        }
+        present_files.insert(metadata_path);
+
+        let (settings, known_parts) = {
+            let Some(table_config) = TABLE_DATA.get(&table_def) else {
+                continue;
+            };
+            let known_parts: HashSet<String> = table_config
+                .infos
+                .iter()
+                .map(|info| info.name.clone())
+                .collect();
+            (table_config.metadata.settings.clone(), known_parts)
+        };
+        let partitioned = settings.partition_by.is_some();
+
+        // part infos are read without holding the table lock
+        let mut present_parts = HashSet::new();
+        let mut new_infos = Vec::new();
+        for part_path in part_dirs(&table_path, partitioned)? {
+            if is_old_part(&part_path) {
+                continue;
+            }
+            let part_name = part_name(&part_path);
+            let info_path = part_path.join(PART_INFO_FILENAME);
+            if !known_parts.contains(&part_name) || !is_loaded_file(&info_path) {
+                remember_stamp(info_path.clone());
+                match TablePartInfo::read_from(&part_path, &settings) {
+                    Ok(info) => {
+                        if let Some(reason) = &info.suspect {
+                            warn!(
+                                table:% = table_def, part = part_name.as_str();
+                                "Part {part_name} of table {table_def} is suspect, its row count can not be trusted: {reason}"
+                            );
+                        }
+                        new_infos.push(info);
+                    }
+                    Err(e) => {
+                        warn!(
+                            table:% = table_def, part = part_name.as_str();
+                            "Failed to load part {part_name} for table {table_def}: {e:?}"
+                        );
+                        continue;
+                    }
+                }
+            }
+            present_files.insert(info_path);
+            present_parts.insert(part_name);
+        }
+
+        let Some(mut table_config) = TABLE_DATA.get_mut(&table_def) else {
+            continue;
+        };
+        // parts, which were read again, replace their previous infos
+        table_config.infos.retain(|info| {
+            present_parts.contains(&info.name)
+                && !new_infos.iter().any(|new_info| new_info.name == info.name)
+        });
+        for info in new_infos {
+            NEXT_PART_SEQUENCE.fetch_max(info.sequence + 1, Ordering::Relaxed);
+            info!(
+                table:% = table_def, part = info.name.as_str();
+                "Loaded part {} for table {table_def}",
+                info.name
+            );
+            table_config.infos.push(info);
+        }
+    }
+    LOADED_FILES.retain(|path, _| !path.starts_with(db_dir) || present_files.contains(path));
+    Ok(())
+}
+
+/// Returns definitions and directories of all tables in all databases of `db_dir`.
+fn table_dirs(db_dir: &Path) -> Result<Vec<(TableDef, PathBuf)>> {
     let databases = std::fs::read_dir(db_dir).map_err(|error| {
         Error::CouldNotInsertData(format!("Failed to read database directory: {error}"))
     })?;
 
+    let mut tables = Vec::new();
     for database_entry in databases {
         let database_entry = database_entry.map_err(|error| {
             Error::CouldNotInsertData(format!("Failed to read database entry: {error}"))
         })?;
 
         let database_path = database_entry.path();
-        if !database_path.is_dir() {
+        // hidden entries are not databases, e.g., table directory during `EXCHANGE TABLES`
+        if !database_path.is_dir() || is_hidden(&database_path) {
             continue;
         }
 
         let database_name = database_entry.file_name().to_string_lossy().to_string();
 
-        let tables = std::fs::read_dir(&database_path).map_err(|error| {
+        let table_entries = std::fs::read_dir(&database_path).map_err(|error| {
             Error::CouldNotInsertData(format!(
                 "Failed to read tables in database {database_name}: {error}"
             ))
         })?;
 
-        for table_entry in tables {
+        for table_entry in table_entries {
             let table_entry = table_entry.map_err(|error| {
                 Error::CouldNotInsertData(format!("Failed to read table entry: {error}"))
             })?;
 
             let table_path = table_entry.path();
-            if !table_path.is_dir() {
+            if !table_path.is_dir() || is_hidden(&table_path) {
                 continue;
             }
 
-            let table_name = table_entry.file_name().to_string_lossy().to_string();
             let table_def = TableDef {
                 database: database_name.clone(),
-                table: table_name.clone(),
+                table: table_entry.file_name().to_string_lossy().to_string(),
             };
+            tables.push((table_def, table_path));
+        }
+    }
+    Ok(tables)
+}
 
-            let table_metadata = TableMetadata::read_from(&table_def)?;
+/// Returns subdirectories of `dir`, skipping hidden ones and `raw`.
+fn read_dirs(dir: &Path) -> Result<Vec<PathBuf>> {
+    let entries = std::fs::read_dir(dir).map_err(|error| {
+        Error::CouldNotInsertData(format!(
+            "Failed to read directory {}: {error}",
+            dir.display()
+        ))
+    })?;
 
-            TABLE_DATA.insert(
-                table_def.clone(),
-                TableConfig {
-                    metadata: table_metadata,
-                    infos: Vec::new(),
-                },
-            );
+    let mut dirs = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|error| {
+            Error::CouldNotInsertData(format!("Failed to read directory entry: {error}"))
+        })?;
 
-            let parts = std::fs::read_dir(&table_path).map_err(|error| {
-                Error::CouldNotInsertData(format!(
-                    "Failed to read parts in table {table_def}: {error}"
-                ))
-            })?;
+        let path = entry.path();
+        if path.is_dir() && !is_hidden(&path) && entry.file_name() != "raw" {
+            dirs.push(path);
+        }
+    }
+    Ok(dirs)
+}
 
-            for part_entry in parts {
-                let part_entry = part_entry.map_err(|error| {
-                    Error::CouldNotInsertData(format!("Failed to read part entry: {error}"))
-                })?;
+/// Whether name of the entry starts with `.`. Such names are never valid database, table or part names.
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+}
 
-                let part_path = part_entry.path();
-                let part_name = part_entry.file_name().to_string_lossy().to_string();
+/// Returns directories of all parts of the table, including those of every partition.
+fn part_dirs(table_path: &Path, partitioned: bool) -> Result<Vec<PathBuf>> {
+    if !partitioned {
+        return read_dirs(table_path);
+    }
 
-                if !part_path.is_dir() || part_name.starts_with('.') {
-                    continue;
-                }
+    let mut dirs = Vec::new();
+    for partition_path in read_dirs(table_path)? {
+        dirs.extend(read_dirs(&partition_path)?);
+    }
+    Ok(dirs)
+}
 
-                if part_name == "raw" {
-                    match std::fs::remove_dir_all(&part_path) {
-                        Ok(()) => {
-                            info!("Removed raw directory for table {table_def}");
-                        }
-                        Err(e) => {
-                            warn!("Failed to remove raw directory for table {table_def}: {e}");
-                        }
-                    }
-                    continue;
-                }
+fn part_name(part_path: &Path) -> String {
+    part_path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
 
-                if Path::new(&part_path)
-                    .extension()
-                    .is_some_and(|ext| ext.eq_ignore_ascii_case("old"))
-                {
-                    warn!(
-                        "Found old part: {part_name}. Consult the logs to make the decision about removal."
-                    );
-                    continue;
-                }
+/// Whether part was replaced by merge and is kept for manual removal.
+fn is_old_part(part_path: &Path) -> bool {
+    part_path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("old"))
+}
 
-                match TablePartInfo::read_from(&table_def, &part_name) {
-                    Ok(info) => {
-                        let Some(mut result) = TABLE_DATA.get_mut(&table_def) else {
-                            continue;
-                        };
-                        result.infos.push(info);
-                        info!("Loaded part {part_name} for table {table_def}");
-                    }
-                    Err(e) => {
-                        warn!("Failed to load part {part_name} for table {table_def}: {e:?}");
-                    }
-                }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::ValueType;
+
+    fn column(name: &str, data: Vec<Value>) -> Column {
+        Column {
+            column_def: ColumnDef {
+                name: name.to_string(),
+                field_type: ValueType::Int32,
+                constraints: Default::default(),
+            },
+            data: data.into(),
+        }
+    }
+
+    #[test]
+    fn test_granule_starts() {
+        let ids = column("id", (0..10).map(Value::Int32).collect());
+        let mut payload = column("payload", vec![Value::String(String::new()); 10]);
+        payload.data[3] = Value::String("x".repeat(1000));
+        payload.data[4] = Value::String("x".repeat(100));
+        let columns = [ids, payload];
+        let row_size = 2 * size_of::<ArchivedValue>();
+
+        let mut settings = TableSettings {
+            index_granularity: 4,
+            ..Default::default()
+        };
+        assert_eq!(granule_starts(&columns, &settings), [0, 4, 8]);
+
+        // row, reaching the byte target, is the last one of its granule; rows limit still applies
+        settings.adaptive_index_granularity = true;
+        settings.index_granularity_bytes = (3 * row_size + 50) as u32;
+        assert_eq!(granule_starts(&columns, &settings), [0, 4, 6]);
+
+        // row larger than the target gets its own granule
+        settings.index_granularity_bytes = 1;
+        assert_eq!(
+            granule_starts(&columns, &settings),
+            (0..10).collect::<Vec<_>>()
+        );
+
+        assert!(granule_starts(&[column("id", Vec::new())], &settings).is_empty());
+    }
+
+    #[test]
+    fn test_check_row_count() {
+        let info = |row_count: u64, marks: usize| TablePartInfo {
+            name: "part".to_string(),
+            sequence: 0,
+            row_count,
+            marks: vec![
+                Mark {
+                    index: Vec::new(),
+                    info: Vec::new(),
+                };
+                marks
+            ],
+            pk_bounds: Vec::new(),
+            column_defs: Vec::new(),
+            partition: None,
+            suspect: None,
+        };
+        let mut settings = TableSettings {
+            index_granularity: 4,
+            ..Default::default()
+        };
+        for (row_count, marks) in [(1, 1), (4, 1), (5, 2), (12, 3)] {
+            assert!(info(row_count, marks).check_row_count(&settings).is_ok());
+        }
+        for (row_count, marks) in [(4, 2), (5, 1), (12, 4), (0, 1)] {
+            assert!(matches!(
+                info(row_count, marks).check_row_count(&settings),
+                Err(Error::CouldNotReadData(_))
+            ));
+        }
+
+        // adaptive granules have from one to `index_granularity` rows
+        settings.adaptive_index_granularity = true;
+        for (row_count, marks) in [(5, 2), (5, 3), (5, 5)] {
+            assert!(info(row_count, marks).check_row_count(&settings).is_ok());
+        }
+        for (row_count, marks) in [(5, 1), (5, 6)] {
+            assert!(info(row_count, marks).check_row_count(&settings).is_err());
+        }
+    }
+
+    #[test]
+    fn test_compute_pk_bounds() {
+        let columns = vec![
+            column(
+                "id",
+                vec![Value::Int32(3), Value::Int32(-1), Value::Int32(7)],
+            ),
+            column("score", vec![Value::Null, Value::Int32(5), Value::Null]),
+            column("empty", vec![Value::Null, Value::Null, Value::Null]),
+        ];
+        let primary_key = vec![
+            columns[1].column_def.clone(),
+            columns[0].column_def.clone(),
+            columns[2].column_def.clone(),
+        ];
+
+        assert_eq!(
+            compute_pk_bounds(&columns, &primary_key),
+            vec![
+                (Value::Int32(5), Value::Int32(5)),
+                (Value::Int32(-1), Value::Int32(7)),
+                (Value::Null, Value::Null),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_marks_round_trip() {
+        let marks = vec![
+            MarkInfo { start: 14, end: 90 },
+            MarkInfo {
+                start: 90,
+                end: u64::MAX,
+            },
+        ];
+        let bytes = encode_marks(&marks);
+        let decoded = decode_marks(&bytes, 2).unwrap();
+        assert_eq!(
+            decoded
+                .iter()
+                .map(|mark| (mark.start, mark.end))
+                .collect::<Vec<_>>(),
+            vec![(14, 90), (90, u64::MAX)]
+        );
+
+        assert!(decode_marks(&bytes, 3).is_err());
+        let mut corrupted = bytes.clone();
+        corrupted[MAGIC_BYTES_MARKS.len()] ^= 1;
+        assert_eq!(
+            decode_marks(&corrupted, 2).unwrap_err(),
+            Error::CouldNotReadData("CRC mismatch in mark file".to_string())
+        );
+        assert!(decode_marks(&encode_marks(&[]), 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_granule_read_errors_name_granule() {
+        let file = [0; 8];
+        let error = TablePartInfo::get_granule_bytes_decompressed(
+            &file,
+            &MarkInfo { start: 4, end: 16 },
+            &CompressionType::None,
+            None,
+            3,
+        )
+        .unwrap_err()
+        .with_read_context("table=(db.t), part=p, column=id");
+
+        assert_eq!(
+            error,
+            Error::CouldNotReadData(
+                "table=(db.t), part=p, column=id: granule=3: Mark end (16) exceeds file size (8)"
+                    .to_string()
+            )
+        );
+
+        let error = TablePartInfo::get_granule_bytes_decompressed(
+            &file,
+            &MarkInfo { start: 0, end: 8 },
+            &CompressionType::LZ4(1),
+            None,
+            5,
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("granule=5: "));
+    }
+
+    /// Decompresses and accesses granule, deserializing every value on success.
+    fn read_granule(file: &[u8], compression_type: &CompressionType) -> Result<Vec<Value>> {
+        let mark_info = MarkInfo {
+            start: 0,
+            end: file.len() as u64,
+        };
+        let bytes = TablePartInfo::get_granule_bytes_decompressed(
+            file,
+            &mark_info,
+            compression_type,
+            None,
+            0,
+        )?;
+        TablePartInfo::access_granule(&bytes)?
+            .iter()
+            .map(|value| {
+                rkyv::deserialize::<Value, rkyv::rancor::Error>(value)
+                    .map_err(|error| Error::CouldNotReadData(error.to_string()))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_access_granule_fuzz() {
+        let values = vec![
+            Value::String("text".to_string()),
+            Value::Null,
+            Value::Array(vec![Value::Int64(-1), Value::Int64(2)]),
+            Value::Map(vec![(
+                Value::UInt8(1),
+                Value::Tuple(vec![Value::Bool(true)]),
+            )]),
+        ];
+        let valid = rkyv::to_bytes::<rkyv::rancor::Error>(&values)
+            .unwrap()
+            .to_vec();
+        assert_eq!(
+            read_granule(&valid, &CompressionType::None).unwrap(),
+            values
+        );
+
+        // xorshift, so failures are reproducible
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        for _ in 0..5000 {
+            let random: Vec<u8> = (0..next() % 256).map(|_| next() as u8).collect();
+            let mut corrupted = valid.clone();
+            for _ in 0..=next() % 4 {
+                let idx = (next() % corrupted.len() as u64) as usize;
+                corrupted[idx] = next() as u8;
+            }
+            let truncated = &valid[..(next() % valid.len() as u64) as usize];
+
+            for file in [random.as_slice(), &corrupted, truncated] {
+                // any result is fine, as long as there is no panic or undefined behaviour
+                let _ = read_granule(file, &CompressionType::None);
+                let _ = read_granule(file, &CompressionType::LZ4(1));
             }
         }
     }
 
-    info!("Finished loading parts");
-    Ok(())
+    #[test]
+    fn test_access_truncated_granule() {
+        let values: Vec<Value> = (0..100)
+            .map(|idx| Value::String(format!("value {idx}")))
+            .collect();
+        let valid = rkyv::to_bytes::<rkyv::rancor::Error>(&values)
+            .unwrap()
+            .to_vec();
+
+        for len in [0, 4, valid.len() / 2, valid.len() - 1] {
+            for validation in [GranuleValidation::Always, GranuleValidation::OnError] {
+                let error =
+                    TablePartInfo::access_granule_with(&valid[..len], validation).unwrap_err();
+                assert!(
+                    matches!(&error, Error::CouldNotReadData(message) if message.starts_with("Invalid granule data: ")),
+                    "{validation:?}, len={len}: {error}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_granule_top_level_is_valid() {
+        for values in [
+            Vec::new(),
+            vec![Value::Null],
+            (0..1000).map(Value::UInt64).collect(),
+        ] {
+            let bytes = rkyv::to_bytes::<rkyv::rancor::Error>(&values).unwrap();
+            assert!(granule_top_level_is_valid(&bytes));
+            for validation in [GranuleValidation::OnError, GranuleValidation::Never] {
+                let archived = TablePartInfo::access_granule_with(&bytes, validation).unwrap();
+                let checked =
+                    TablePartInfo::access_granule_with(&bytes, GranuleValidation::Always).unwrap();
+                assert_eq!(archived.as_ptr(), checked.as_ptr());
+                assert_eq!(archived.len(), values.len());
+            }
+        }
+
+        assert!(!granule_top_level_is_valid(&[]));
+        // length points far outside of the buffer
+        let mut bytes = rkyv::to_bytes::<rkyv::rancor::Error>(&vec![Value::Null]).unwrap();
+        let len = bytes.len();
+        bytes[len - 4..].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(!granule_top_level_is_valid(&bytes));
+    }
 }
@@ -1,9 +1,18 @@
 use crate::error::{Error, Result};
+use crate::sql::parse_value;
+use crate::storage::date;
 
 use rkyv::{Archive as RkyvArchive, Deserialize as RkyvDeserialize, Serialize as RkyvSerialize};
 use serde::Serialize;
-use sqlparser::ast::{DataType as SQLDatatype, Value as SQLValue};
+use sqlparser::ast::{ArrayElemTypeDef, DataType as SQLDatatype, EnumMember, Value as SQLValue};
+use sqlparser::keywords::{
+    ALL_KEYWORDS, ALL_KEYWORDS_INDEX, Keyword, RESERVED_FOR_COLUMN_ALIAS, RESERVED_FOR_IDENTIFIER,
+    RESERVED_FOR_TABLE_ALIAS,
+};
 use std::cmp::Ordering;
+use std::fmt;
+use std::fmt::Write as _;
+use std::net::{Ipv4Addr, Ipv6Addr};
 use uuid::Uuid;
 
 /// Represents a parsed value in our custom protocol
@@ -11,6 +20,12 @@ use uuid::Uuid;
     Clone, Debug, PartialEq, Default, Serialize, RkyvSerialize, RkyvArchive, RkyvDeserialize,
 )]
 #[rkyv(derive(Debug), compare(PartialEq))]
+#[rkyv(serialize_bounds(
+    __S: rkyv::ser::Writer + rkyv::ser::Allocator,
+    __S::Error: rkyv::rancor::Source,
+))]
+#[rkyv(deserialize_bounds(__D::Error: rkyv::rancor::Source))]
+#[rkyv(bytecheck(bounds(__C: rkyv::validation::ArchiveContext)))]
 pub enum Value {
     #[default]
     Null,
@@ -27,6 +42,22 @@ pub enum Value {
     UInt16(u16),
     UInt32(u32),
     UInt64(u64),
+
+    Tuple(#[rkyv(omit_bounds)] Vec<Value>),
+    Array(#[rkyv(omit_bounds)] Vec<Value>),
+    /// Sorted by key, without duplicate keys. Use `Value::new_map` to build.
+    Map(#[rkyv(omit_bounds)] Vec<(Value, Value)>),
+
+    /// Address as a number, so addresses are ordered numerically, e.g., for subnet ranges.
+    IPv4(u32),
+    /// Address bytes in network order, which are ordered as the 128-bit number. Bytes (unlike
+    /// `u128`) keep size and alignment of `ArchivedValue`, so existing granules are readable.
+    IPv6([u8; 16]),
+
+    /// Days since `1970-01-01`, up to `2149-06-06`.
+    Date(u16),
+    /// Seconds since `1970-01-01 00:00:00` UTC, up to `2106-02-07 06:28:15`.
+    DateTime(u32),
 }
 
 impl TryFrom<(SQLValue, &ValueType)> for Value {
@@ -46,6 +77,44 @@ impl TryFrom<(SQLValue, &ValueType)> for Value {
                         Error::InvalidSource(format!("Could not parse uuid: {error}"))
                     })?;
                     Ok(Self::Uuid(uuid))
+                } else if value_type == &ValueType::IPv4 {
+                    let address: Ipv4Addr = string.parse().map_err(|error| {
+                        Error::InvalidSource(format!("Could not parse IPv4 '{string}': {error}"))
+                    })?;
+                    Ok(Self::IPv4(address.into()))
+                } else if value_type == &ValueType::IPv6 {
+                    // IPv4 is stored as IPv4-mapped address, e.g., `::ffff:1.2.3.4`
+                    let address = match string.parse::<Ipv4Addr>() {
+                        Ok(address) => address.to_ipv6_mapped(),
+                        Err(_) => string.parse::<Ipv6Addr>().map_err(|error| {
+                            Error::InvalidSource(format!(
+                                "Could not parse IPv6 '{string}': {error}"
+                            ))
+                        })?,
+                    };
+                    Ok(Self::IPv6(address.octets()))
+                } else if value_type == &ValueType::Date {
+                    date::parse_days(&string)
+                        .and_then(Self::date_from_days)
+                        .ok_or_else(|| {
+                            Error::InvalidSource(format!(
+                                "Could not parse Date '{string}', expected YYYY-MM-DD between \
+                                 1970-01-01 and 2149-06-06"
+                            ))
+                        })
+                } else if value_type == &ValueType::DateTime {
+                    date::parse_seconds(&string)
+                        .and_then(Self::date_time_from_seconds)
+                        .ok_or_else(|| {
+                            Error::InvalidSource(format!(
+                                "Could not parse DateTime '{string}', expected YYYY-MM-DD \
+                                 hh:mm:ss between 1970-01-01 00:00:00 and 2106-02-07 06:28:15"
+                            ))
+                        })
+                } else if value_type.is_enum() {
+                    value_type.enum_code(&string).ok_or_else(|| {
+                        Error::InvalidSource(format!("Unknown label '{string}' of {value_type}"))
+                    })
                 } else {
                     Err(Error::InvalidSource(format!(
                         "Could not convert {string} to {value_type:?}",
@@ -63,6 +132,27 @@ impl TryFrom<(SQLValue, &ValueType)> for Value {
                     ValueType::UInt16 => Ok(Self::UInt16(number.parse().map_err(parse_err)?)),
                     ValueType::UInt32 => Ok(Self::UInt32(number.parse().map_err(parse_err)?)),
                     ValueType::UInt64 => Ok(Self::UInt64(number.parse().map_err(parse_err)?)),
+                    // days and unix seconds, as stored
+                    ValueType::Date => Ok(Self::Date(number.parse().map_err(parse_err)?)),
+                    ValueType::DateTime => Ok(Self::DateTime(number.parse().map_err(parse_err)?)),
+                    ValueType::Enum8(labels) => {
+                        let code = number.parse().map_err(parse_err)?;
+                        if !labels.iter().any(|(_, label_code)| *label_code == code) {
+                            return Err(Error::InvalidSource(format!(
+                                "Unknown code {code} of {value_type}"
+                            )));
+                        }
+                        Ok(Self::Int8(code))
+                    }
+                    ValueType::Enum16(labels) => {
+                        let code = number.parse().map_err(parse_err)?;
+                        if !labels.iter().any(|(_, label_code)| *label_code == code) {
+                            return Err(Error::InvalidSource(format!(
+                                "Unknown code {code} of {value_type}"
+                            )));
+                        }
+                        Ok(Self::Int16(code))
+                    }
                     _ => Err(Error::UnsupportedColumnType(format!(
                         "Cannot convert number to {value_type:?}",
                     ))),
@@ -87,6 +177,12 @@ impl TryFrom<(SQLValue, &ValueType)> for Value {
 #[derive(
     Debug, Clone, Hash, PartialEq, Eq, Serialize, RkyvSerialize, RkyvArchive, RkyvDeserialize,
 )]
+#[rkyv(serialize_bounds(
+    __S: rkyv::ser::Writer + rkyv::ser::Allocator,
+    __S::Error: rkyv::rancor::Source,
+))]
+#[rkyv(deserialize_bounds(__D::Error: rkyv::rancor::Source))]
+#[rkyv(bytecheck(bounds(__C: rkyv::validation::ArchiveContext)))]
 pub enum ValueType {
     Null,
     String,
@@ -102,6 +198,30 @@ pub enum ValueType {
     UInt16,
     UInt32,
     UInt64,
+
+    Tuple(#[rkyv(omit_bounds)] Vec<ValueType>),
+    Array(#[rkyv(omit_bounds)] Box<ValueType>),
+    Map(
+        #[rkyv(omit_bounds)] Box<ValueType>,
+        #[rkyv(omit_bounds)] Box<ValueType>,
+    ),
+
+    /// Labels with their codes, in order of definition. Values are stored as `Value::Int8`
+    /// codes, while INSERT and SELECT use labels.
+    Enum8(Vec<(String, i8)>),
+    /// Same as `Enum8`, with `Value::Int16` codes.
+    Enum16(Vec<(String, i16)>),
+
+    /// Stored as `Value::IPv4`, inserted and selected as strings, e.g., `'10.0.0.1'`.
+    IPv4,
+    /// Stored as `Value::IPv6`, inserted and selected as strings, e.g., `'2001:db8::1'`.
+    IPv6,
+
+    /// Stored as `Value::Date`, inserted and selected as strings, e.g., `'2024-01-31'`.
+    Date,
+    /// Stored as `Value::DateTime`, inserted and selected as strings in UTC, e.g.,
+    /// `'2024-01-31 23:59:59'`.
+    DateTime,
 }
 
 impl TryFrom<&SQLDatatype> for ValueType {
@@ -120,12 +240,78 @@ impl TryFrom<&SQLDatatype> for ValueType {
             SQLDatatype::UInt16 => Ok(Self::UInt16),
             SQLDatatype::UInt32 => Ok(Self::UInt32),
             SQLDatatype::UInt64 => Ok(Self::UInt64),
+            SQLDatatype::Tuple(fields) => {
+                if fields.is_empty() {
+                    return Err(Error::UnsupportedColumnType(
+                        "Tuple must have at least one element".to_string(),
+                    ));
+                }
+                fields
+                    .iter()
+                    .map(|field| Self::try_from(&field.field_type))
+                    .collect::<Result<Vec<_>>>()
+                    .map(Self::Tuple)
+            }
+            SQLDatatype::Array(
+                ArrayElemTypeDef::Parenthesis(elem_type)
+                | ArrayElemTypeDef::AngleBracket(elem_type)
+                | ArrayElemTypeDef::SquareBracket(elem_type, _),
+            ) => Ok(Self::Array(Box::new(Self::try_from(elem_type.as_ref())?))),
+            SQLDatatype::Map(key_type, value_type) => {
+                let key_type = Self::try_from(key_type.as_ref())?;
+                if matches!(key_type, Self::Array(_) | Self::Map(..)) {
+                    return Err(Error::UnsupportedColumnType(format!(
+                        "Map key cannot be {key_type:?}"
+                    )));
+                }
+                Ok(Self::Map(
+                    Box::new(key_type),
+                    Box::new(Self::try_from(value_type.as_ref())?),
+                ))
+            }
+            // nullability of a column is kept in `Constraints`, nested values may always be `NULL`
+            SQLDatatype::Nullable(inner_type) => {
+                if matches!(inner_type.as_ref(), SQLDatatype::Nullable(_)) {
+                    return Err(Error::UnsupportedColumnType(format!(
+                        "Nested Nullable is not supported: {value}"
+                    )));
+                }
+                Self::try_from(inner_type.as_ref())
+            }
+            // storage encoding is kept in `Constraints`, see `CompressionType::Dictionary`
+            SQLDatatype::LowCardinality(inner_type) => Self::try_from(inner_type.as_ref()),
+            SQLDatatype::Enum(members, bits) => Self::from_enum_members(members, *bits),
+            SQLDatatype::Date => Ok(Self::Date),
+            SQLDatatype::Datetime(None) => Ok(Self::DateTime),
+            SQLDatatype::Custom(name, modifiers) if modifiers.is_empty() => {
+                match name.to_string().as_str() {
+                    "IPv4" => Ok(Self::IPv4),
+                    "IPv6" => Ok(Self::IPv6),
+                    _ => Err(Error::UnsupportedColumnType(value.to_string())),
+                }
+            }
             column_type => Err(Error::UnsupportedColumnType(column_type.to_string())),
         }
     }
 }
 
 impl Value {
+    /// Returns value of integer of any width, `None` for `NULL` and other values. `i128` holds
+    /// every integer type, so integers of different types can be compared and summed.
+    pub const fn as_i128(&self) -> Option<i128> {
+        match self {
+            Value::Int8(value) => Some(*value as i128),
+            Value::Int16(value) => Some(*value as i128),
+            Value::Int32(value) => Some(*value as i128),
+            Value::Int64(value) => Some(*value as i128),
+            Value::UInt8(value) => Some(*value as i128),
+            Value::UInt16(value) => Some(*value as i128),
+            Value::UInt32(value) => Some(*value as i128),
+            Value::UInt64(value) => Some(*value as i128),
+            _ => None,
+        }
+    }
+
     /// Returns the `ValueType` corresponding to this value.
     pub fn get_type(&self) -> ValueType {
         match &self {
@@ -141,8 +327,501 @@ impl Value {
             Value::UInt16(_) => ValueType::UInt16,
             Value::UInt32(_) => ValueType::UInt32,
             Value::UInt64(_) => ValueType::UInt64,
+            Value::Tuple(values) => ValueType::Tuple(values.iter().map(Value::get_type).collect()),
+            Value::Array(values) => ValueType::Array(Box::new(
+                values.first().map_or(ValueType::Null, Value::get_type),
+            )),
+            Value::Map(entries) => {
+                let (key_type, value_type) = entries
+                    .first()
+                    .map_or((ValueType::Null, ValueType::Null), |(key, value)| {
+                        (key.get_type(), value.get_type())
+                    });
+                ValueType::Map(Box::new(key_type), Box::new(value_type))
+            }
+            Value::IPv4(_) => ValueType::IPv4,
+            Value::IPv6(_) => ValueType::IPv6,
+            Value::Date(_) => ValueType::Date,
+            Value::DateTime(_) => ValueType::DateTime,
+        }
+    }
+
+    /// Returns `Value::Date` of days since `1970-01-01`, `None` when it is out of range.
+    pub fn date_from_days(days: i64) -> Option<Self> {
+        u16::try_from(days).ok().map(Self::Date)
+    }
+
+    /// Returns `Value::DateTime` of unix seconds, `None` when it is out of range.
+    pub fn date_time_from_seconds(seconds: i64) -> Option<Self> {
+        u32::try_from(seconds).ok().map(Self::DateTime)
+    }
+
+    /// Returns approximate size of the value in uncompressed granule: size of `ArchivedValue`
+    /// and bytes of strings and nested values.
+    pub fn archived_size(&self) -> usize {
+        let nested = match self {
+            Value::String(string) => string.len(),
+            Value::Tuple(values) | Value::Array(values) => {
+                values.iter().map(Value::archived_size).sum()
+            }
+            Value::Map(entries) => entries
+                .iter()
+                .map(|(key, value)| key.archived_size() + value.archived_size())
+                .sum(),
+            _ => 0,
+        };
+        size_of::<ArchivedValue>() + nested
+    }
+
+    /// Builds `Value::Map`, sorting entries by key. When key is repeated, the last value is kept.
+    ///
+    /// Returns:
+    ///   * Ok: `Value::Map`.
+    ///   * Error: `InvalidSource` when keys cannot be compared (e.g., different types).
+    pub fn new_map(mut entries: Vec<(Value, Value)>) -> Result<Self> {
+        for (key, _) in &entries {
+            if key.partial_cmp(key).is_none() {
+                return Err(Error::InvalidSource(format!(
+                    "Map key {key:?} is not comparable"
+                )));
+            }
+        }
+        if entries
+            .windows(2)
+            .any(|pair| pair[0].0.partial_cmp(&pair[1].0).is_none())
+        {
+            return Err(Error::InvalidSource(
+                "Map keys must have the same type".to_string(),
+            ));
+        }
+
+        // stable sort keeps insertion order of equal keys, so the last one wins after dedup
+        entries.sort_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+        let mut deduped: Vec<(Value, Value)> = Vec::with_capacity(entries.len());
+        for entry in entries {
+            match deduped.last_mut() {
+                Some(last) if last.0 == entry.0 => *last = entry,
+                _ => deduped.push(entry),
+            }
+        }
+        Ok(Self::Map(deduped))
+    }
+
+    /// Looks up value by key in `Value::Map` using binary search.
+    ///
+    /// Returns:
+    ///   * Some: value for the key.
+    ///   * None: key is absent, or `self` is not a map.
+    pub fn map_get(&self, key: &Value) -> Option<&Value> {
+        let Self::Map(entries) = self else {
+            return None;
+        };
+        entries
+            .binary_search_by(|(entry_key, _)| entry_key.partial_cmp(key).unwrap_or(Ordering::Less))
+            .ok()
+            .map(|idx| &entries[idx].1)
+    }
+
+    /// Returns name of the partition directory for the value of `PARTITION BY` expression.
+    ///
+    /// Bytes other than ASCII alphanumerics, `-` and `_` are percent-encoded, so any value is
+    /// a valid directory name. Empty name becomes `%` and `raw` becomes `%72aw`, as `raw`
+    /// directory holds parts, which are being written.
+    pub fn to_partition_id(&self) -> String {
+        let mut text = String::new();
+        self.write_partition_text(&mut text);
+
+        match text.as_str() {
+            "" => return "%".to_string(),
+            "raw" => return "%72aw".to_string(),
+            _ => {}
+        }
+
+        let mut id = String::with_capacity(text.len());
+        for byte in text.bytes() {
+            if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' {
+                id.push(char::from(byte));
+            } else {
+                let _ = write!(id, "%{byte:02X}");
+            }
+        }
+        id
+    }
+
+    fn write_partition_text(&self, text: &mut String) {
+        fn write_list<'a>(
+            text: &mut String,
+            open: char,
+            values: impl Iterator<Item = &'a Value>,
+            close: char,
+        ) {
+            text.push(open);
+            for (idx, value) in values.enumerate() {
+                if idx > 0 {
+                    text.push(',');
+                }
+                value.write_partition_text(text);
+            }
+            text.push(close);
+        }
+
+        match self {
+            Value::Null => text.push_str("NULL"),
+            Value::String(value) => text.push_str(value),
+            Value::Uuid(value) => text.push_str(&value.to_string()),
+            Value::Bool(value) => text.push_str(&value.to_string()),
+            Value::Int8(value) => text.push_str(&value.to_string()),
+            Value::Int16(value) => text.push_str(&value.to_string()),
+            Value::Int32(value) => text.push_str(&value.to_string()),
+            Value::Int64(value) => text.push_str(&value.to_string()),
+            Value::UInt8(value) => text.push_str(&value.to_string()),
+            Value::UInt16(value) => text.push_str(&value.to_string()),
+            Value::UInt32(value) => text.push_str(&value.to_string()),
+            Value::UInt64(value) => text.push_str(&value.to_string()),
+            Value::Tuple(values) => write_list(text, '(', values.iter(), ')'),
+            Value::Array(values) => write_list(text, '[', values.iter(), ']'),
+            Value::Map(entries) => write_list(
+                text,
+                '{',
+                entries.iter().flat_map(|(key, value)| [key, value]),
+                '}',
+            ),
+            Value::IPv4(_) | Value::IPv6(_) => text.push_str(&self.ip_to_string()),
+            Value::Date(_) | Value::DateTime(_) => text.push_str(&self.date_to_string()),
+        }
+    }
+
+    /// Returns value as SQL literal, e.g., `'it''s'` or `[1, 2]`.
+    pub fn to_sql_literal(&self) -> String {
+        fn list<'a>(values: impl Iterator<Item = &'a Value>) -> String {
+            let values: Vec<_> = values.map(Value::to_sql_literal).collect();
+            values.join(", ")
+        }
+
+        match self {
+            Value::Null => "NULL".to_string(),
+            Value::String(value) => quote_string(value),
+            Value::Uuid(value) => quote_string(&value.to_string()),
+            Value::Bool(value) => value.to_string(),
+            Value::Int8(value) => value.to_string(),
+            Value::Int16(value) => value.to_string(),
+            Value::Int32(value) => value.to_string(),
+            Value::Int64(value) => value.to_string(),
+            Value::UInt8(value) => value.to_string(),
+            Value::UInt16(value) => value.to_string(),
+            Value::UInt32(value) => value.to_string(),
+            Value::UInt64(value) => value.to_string(),
+            Value::Tuple(values) => format!("({})", list(values.iter())),
+            Value::Array(values) => format!("[{}]", list(values.iter())),
+            Value::Map(entries) => format!(
+                "map({})",
+                list(entries.iter().flat_map(|(key, value)| [key, value]))
+            ),
+            Value::IPv4(_) | Value::IPv6(_) => quote_string(&self.ip_to_string()),
+            Value::Date(_) | Value::DateTime(_) => quote_string(&self.date_to_string()),
+        }
+    }
+
+    /// Returns canonical text of `IPv4`/`IPv6` value, e.g., `10.0.0.1` or `2001:db8::1`, and
+    /// an empty string for other values.
+    pub fn ip_to_string(&self) -> String {
+        match self {
+            Value::IPv4(address) => Ipv4Addr::from(*address).to_string(),
+            Value::IPv6(address) => Ipv6Addr::from(*address).to_string(),
+            _ => String::new(),
+        }
+    }
+
+    /// Returns text of `Date`/`DateTime` value, e.g., `2024-01-31` or `2024-01-31 23:59:59`,
+    /// and an empty string for other values.
+    pub fn date_to_string(&self) -> String {
+        match self {
+            Value::Date(days) => date::format_days(i64::from(*days)),
+            Value::DateTime(seconds) => date::format_seconds(i64::from(*seconds)),
+            _ => String::new(),
+        }
+    }
+}
+
+/// Returns text as single-quoted SQL string, e.g., `it's` as `'it''s'`.
+pub fn quote_string(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+/// Keywords, which are parsed as something else than a column in `CREATE TABLE` or `ORDER BY`,
+/// besides keywords reserved by the parser (e.g., `null` is a literal, `primary` starts a
+/// constraint).
+const RESERVED_KEYWORDS: &[Keyword] = &[
+    Keyword::CHECK,
+    Keyword::CONSTRAINT,
+    Keyword::CURRENT_DATE,
+    Keyword::CURRENT_TIME,
+    Keyword::CURRENT_TIMESTAMP,
+    Keyword::FALSE,
+    Keyword::FOREIGN,
+    Keyword::LOCALTIME,
+    Keyword::LOCALTIMESTAMP,
+    Keyword::NULL,
+    Keyword::PRIMARY,
+    Keyword::TRUE,
+    Keyword::UNIQUE,
+];
+
+/// Whether name is a keyword, which is not always parsed as identifier (e.g., `order` or
+/// `select`), see `RESERVED_KEYWORDS`.
+fn is_reserved_keyword(name: &str) -> bool {
+    let Ok(idx) = ALL_KEYWORDS.binary_search(&name.to_ascii_uppercase().as_str()) else {
+        return false;
+    };
+    let keyword = ALL_KEYWORDS_INDEX[idx];
+    [
+        RESERVED_KEYWORDS,
+        RESERVED_FOR_TABLE_ALIAS,
+        RESERVED_FOR_COLUMN_ALIAS,
+        RESERVED_FOR_IDENTIFIER,
+    ]
+    .iter()
+    .any(|keywords| keywords.contains(&keyword))
+}
+
+/// Returns name as SQL identifier, quoting it with `"`, unless it is a plain identifier.
+/// Reserved keywords are quoted too, see `is_reserved_keyword`.
+pub fn quote_ident(name: &str) -> String {
+    let is_plain = name
+        .chars()
+        .next()
+        .is_some_and(|ch| ch.is_ascii_alphabetic() || ch == '_')
+        && name
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+        && !is_reserved_keyword(name);
+    if is_plain {
+        name.to_string()
+    } else {
+        format!("\"{}\"", name.replace('"', "\"\""))
+    }
+}
+
+impl ValueType {
+    /// Builds `Enum8` or `Enum16` from definition, e.g., `Enum8('a' = 1, 'b' = 2)`. Label
+    /// without code gets the code of the previous label plus one (1 for the first label).
+    /// `Enum` without size is `Enum8`, when all codes fit into `Int8`.
+    ///
+    /// Returns:
+    ///   * Ok: `Enum8` or `Enum16`.
+    ///   * Error: `UnsupportedColumnType` when enum has no labels, label or code is repeated,
+    ///     or code does not fit into the type.
+    fn from_enum_members(members: &[EnumMember], bits: Option<u8>) -> Result<Self> {
+        let mut labels: Vec<(String, i16)> = Vec::with_capacity(members.len());
+        for member in members {
+            let (label, code) = match member {
+                EnumMember::Name(label) => {
+                    let code = match labels.last() {
+                        Some((_, code)) => code.checked_add(1).ok_or_else(|| {
+                            Error::UnsupportedColumnType(format!(
+                                "Enum code of '{label}' does not fit into Int16"
+                            ))
+                        })?,
+                        None => 1,
+                    };
+                    (label, code)
+                }
+                EnumMember::NamedValue(label, code) => {
+                    let Ok(Value::Int16(code)) = parse_value(code, &Self::Int16) else {
+                        return Err(Error::UnsupportedColumnType(format!(
+                            "Enum code of '{label}' must be an Int16 literal, received: {code}"
+                        )));
+                    };
+                    (label, code)
+                }
+            };
+            if labels.iter().any(|(existing, _)| existing == label) {
+                return Err(Error::UnsupportedColumnType(format!(
+                    "Enum label '{label}' is repeated"
+                )));
+            }
+            if labels.iter().any(|(_, existing)| *existing == code) {
+                return Err(Error::UnsupportedColumnType(format!(
+                    "Enum code {code} is repeated"
+                )));
+            }
+            labels.push((label.clone(), code));
+        }
+        if labels.is_empty() {
+            return Err(Error::UnsupportedColumnType(
+                "Enum must have at least one label".to_string(),
+            ));
+        }
+
+        let labels_int8: Option<Vec<_>> = labels
+            .iter()
+            .map(|(label, code)| Some((label.clone(), i8::try_from(*code).ok()?)))
+            .collect();
+        match (bits, labels_int8) {
+            (Some(8) | None, Some(labels)) => Ok(Self::Enum8(labels)),
+            (Some(16) | None, _) => Ok(Self::Enum16(labels)),
+            (Some(8), None) => Err(Error::UnsupportedColumnType(
+                "Enum8 codes must fit into Int8 (-128..=127)".to_string(),
+            )),
+            (Some(bits), _) => Err(Error::UnsupportedColumnType(format!("Enum{bits}"))),
+        }
+    }
+
+    /// Whether the type is `Enum8` or `Enum16`.
+    pub const fn is_enum(&self) -> bool {
+        matches!(self, Self::Enum8(_) | Self::Enum16(_))
+    }
+
+    /// Returns stored value of the label of `Enum8`/`Enum16` type, e.g., `Int8(1)`.
+    ///
+    /// Returns:
+    ///   * Some: code of the label.
+    ///   * None: label is unknown, or type is not an enum.
+    pub fn enum_code(&self, label: &str) -> Option<Value> {
+        match self {
+            Self::Enum8(labels) => labels
+                .iter()
+                .find(|(name, _)| name == label)
+                .map(|(_, code)| Value::Int8(*code)),
+            Self::Enum16(labels) => labels
+                .iter()
+                .find(|(name, _)| name == label)
+                .map(|(_, code)| Value::Int16(*code)),
+            _ => None,
+        }
+    }
+
+    /// Converts stored value into the value, returned to clients: codes of `Enum8`/`Enum16`
+    /// values are replaced with their labels, `IPv4`/`IPv6` addresses with their canonical
+    /// text and `Date`/`DateTime` values with their text, including values nested in tuples,
+    /// arrays and maps. `NULL`, unknown codes and values of other types are kept.
+    pub fn to_output_value(&self, value: Value) -> Value {
+        match (self, value) {
+            (Self::IPv4, value @ Value::IPv4(_)) | (Self::IPv6, value @ Value::IPv6(_)) => {
+                Value::String(value.ip_to_string())
+            }
+            (Self::Date, value @ Value::Date(_)) | (Self::DateTime, value @ Value::DateTime(_)) => {
+                Value::String(value.date_to_string())
+            }
+            (Self::Enum8(labels), Value::Int8(code)) => {
+                match labels.iter().find(|(_, label_code)| *label_code == code) {
+                    Some((label, _)) => Value::String(label.clone()),
+                    None => Value::Int8(code),
+                }
+            }
+            (Self::Enum16(labels), Value::Int16(code)) => {
+                match labels.iter().find(|(_, label_code)| *label_code == code) {
+                    Some((label, _)) => Value::String(label.clone()),
+                    None => Value::Int16(code),
+                }
+            }
+            (Self::Tuple(field_types), Value::Tuple(values)) => Value::Tuple(
+                values
+                    .into_iter()
+                    .zip(field_types)
+                    .map(|(value, field_type)| field_type.to_output_value(value))
+                    .collect(),
+            ),
+            (Self::Array(elem_type), Value::Array(values)) => Value::Array(
+                values
+                    .into_iter()
+                    .map(|value| elem_type.to_output_value(value))
+                    .collect(),
+            ),
+            (Self::Map(key_type, value_type), Value::Map(entries)) => Value::Map(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| {
+                        (
+                            key_type.to_output_value(key),
+                            value_type.to_output_value(value),
+                        )
+                    })
+                    .collect(),
+            ),
+            (_, value) => value,
         }
     }
+
+    /// Whether values of the type are converted by `to_output_value`, i.e. the type contains
+    /// `Enum8`, `Enum16`, `IPv4`, `IPv6`, `Date` or `DateTime`, including nested types.
+    pub fn has_output_conversion(&self) -> bool {
+        match self {
+            Self::Enum8(_)
+            | Self::Enum16(_)
+            | Self::IPv4
+            | Self::IPv6
+            | Self::Date
+            | Self::DateTime => true,
+            Self::Tuple(field_types) => field_types.iter().any(Self::has_output_conversion),
+            Self::Array(elem_type) => elem_type.has_output_conversion(),
+            Self::Map(key_type, value_type) => {
+                key_type.has_output_conversion() || value_type.has_output_conversion()
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the type is one of signed integers.
+    pub const fn is_signed_integer(&self) -> bool {
+        matches!(self, Self::Int8 | Self::Int16 | Self::Int32 | Self::Int64)
+    }
+
+    /// Whether the type is one of signed or unsigned integers.
+    pub const fn is_integer(&self) -> bool {
+        matches!(
+            self,
+            Self::Int8
+                | Self::Int16
+                | Self::Int32
+                | Self::Int64
+                | Self::UInt8
+                | Self::UInt16
+                | Self::UInt32
+                | Self::UInt64
+        )
+    }
+}
+
+/// Formats type as in `CREATE TABLE`, e.g., `Map(String, UInt64)`.
+impl fmt::Display for ValueType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValueType::Null => write!(f, "Nothing"),
+            ValueType::String => write!(f, "String"),
+            ValueType::Uuid => write!(f, "UUID"),
+            ValueType::Bool => write!(f, "Bool"),
+            ValueType::Int8 => write!(f, "Int8"),
+            ValueType::Int16 => write!(f, "Int16"),
+            ValueType::Int32 => write!(f, "Int32"),
+            ValueType::Int64 => write!(f, "Int64"),
+            ValueType::UInt8 => write!(f, "UInt8"),
+            ValueType::UInt16 => write!(f, "UInt16"),
+            ValueType::UInt32 => write!(f, "UInt32"),
+            ValueType::UInt64 => write!(f, "UInt64"),
+            ValueType::Tuple(types) => {
+                let types: Vec<_> = types.iter().map(ToString::to_string).collect();
+                write!(f, "Tuple({})", types.join(", "))
+            }
+            ValueType::Array(elem_type) => write!(f, "Array({elem_type})"),
+            ValueType::Map(key_type, value_type) => write!(f, "Map({key_type}, {value_type})"),
+            ValueType::Enum8(labels) => write!(f, "Enum8({})", enum_labels(labels)),
+            ValueType::Enum16(labels) => write!(f, "Enum16({})", enum_labels(labels)),
+            ValueType::IPv4 => write!(f, "IPv4"),
+            ValueType::IPv6 => write!(f, "IPv6"),
+            ValueType::Date => write!(f, "Date"),
+            ValueType::DateTime => write!(f, "DateTime"),
+        }
+    }
+}
+
+/// Formats labels of enum as in `CREATE TABLE`, e.g., `'a' = 1, 'b' = 2`.
+fn enum_labels(labels: &[(String, impl fmt::Display)]) -> String {
+    let labels: Vec<_> = labels
+        .iter()
+        .map(|(label, code)| format!("{} = {code}", quote_string(label)))
+        .collect();
+    labels.join(", ")
 }
 
 impl PartialOrd for Value {
@@ -160,7 +839,15 @@ impl PartialOrd for Value {
             (Value::UInt16(l), Value::UInt16(r)) => Some(l.cmp(r)),
             (Value::UInt32(l), Value::UInt32(r)) => Some(l.cmp(r)),
             (Value::UInt64(l), Value::UInt64(r)) => Some(l.cmp(r)),
-            _ => None,
+            (Value::Tuple(l), Value::Tuple(r)) | (Value::Array(l), Value::Array(r)) => {
+                l.partial_cmp(r)
+            }
+            (Value::Map(l), Value::Map(r)) => l.partial_cmp(r),
+            (Value::IPv4(l), Value::IPv4(r)) => Some(l.cmp(r)),
+            (Value::IPv6(l), Value::IPv6(r)) => Some(l.cmp(r)),
+            (Value::Date(l), Value::Date(r)) => Some(l.cmp(r)),
+            (Value::DateTime(l), Value::DateTime(r)) => Some(l.cmp(r)),
+            _ => cmp_integers(self.as_i128(), other.as_i128()),
         }
     }
 }
@@ -180,7 +867,17 @@ impl PartialOrd<ArchivedValue> for Value {
             (Self::UInt16(l), ArchivedValue::UInt16(r)) => l.partial_cmp(&r.to_native()),
             (Self::UInt32(l), ArchivedValue::UInt32(r)) => l.partial_cmp(&r.to_native()),
             (Self::UInt64(l), ArchivedValue::UInt64(r)) => l.partial_cmp(&r.to_native()),
-            _ => None,
+            (Self::Tuple(l), ArchivedValue::Tuple(r))
+            | (Self::Array(l), ArchivedValue::Array(r)) => partial_cmp_iters(l.iter(), r.iter()),
+            (Self::Map(l), ArchivedValue::Map(r)) => partial_cmp_iters(
+                l.iter().flat_map(|(key, value)| [key, value]),
+                r.iter().flat_map(|entry| [&entry.0, &entry.1]),
+            ),
+            (Self::IPv4(l), ArchivedValue::IPv4(r)) => l.partial_cmp(&r.to_native()),
+            (Self::IPv6(l), ArchivedValue::IPv6(r)) => l.partial_cmp(r),
+            (Self::Date(l), ArchivedValue::Date(r)) => l.partial_cmp(&r.to_native()),
+            (Self::DateTime(l), ArchivedValue::DateTime(r)) => l.partial_cmp(&r.to_native()),
+            _ => cmp_integers(self.as_i128(), rhs.as_i128()),
         }
     }
 }
@@ -200,7 +897,18 @@ impl PartialOrd<Value> for ArchivedValue {
             (Self::UInt16(l), Value::UInt16(r)) => l.to_native().partial_cmp(r),
             (Self::UInt32(l), Value::UInt32(r)) => l.to_native().partial_cmp(r),
             (Self::UInt64(l), Value::UInt64(r)) => l.to_native().partial_cmp(r),
-            _ => None,
+            (Self::Tuple(l), Value::Tuple(r)) | (Self::Array(l), Value::Array(r)) => {
+                partial_cmp_iters(l.iter(), r.iter())
+            }
+            (Self::Map(l), Value::Map(r)) => partial_cmp_iters(
+                l.iter().flat_map(|entry| [&entry.0, &entry.1]),
+                r.iter().flat_map(|(key, value)| [key, value]),
+            ),
+            (Self::IPv4(l), Value::IPv4(r)) => l.to_native().partial_cmp(r),
+            (Self::IPv6(l), Value::IPv6(r)) => l.partial_cmp(r),
+            (Self::Date(l), Value::Date(r)) => l.to_native().partial_cmp(r),
+            (Self::DateTime(l), Value::DateTime(r)) => l.to_native().partial_cmp(r),
+            _ => cmp_integers(self.as_i128(), rhs.as_i128()),
         }
     }
 }
@@ -220,6 +928,18 @@ impl PartialEq<ArchivedValue> for ArchivedValue {
             (Self::UInt16(l), ArchivedValue::UInt16(r)) => l == r,
             (Self::UInt32(l), ArchivedValue::UInt32(r)) => l == r,
             (Self::UInt64(l), ArchivedValue::UInt64(r)) => l == r,
+            (Self::Tuple(l), ArchivedValue::Tuple(r))
+            | (Self::Array(l), ArchivedValue::Array(r)) => l.as_slice() == r.as_slice(),
+            (Self::Map(l), ArchivedValue::Map(r)) => {
+                l.len() == r.len()
+                    && l.iter()
+                        .zip(r.iter())
+                        .all(|(l, r)| l.0 == r.0 && l.1 == r.1)
+            }
+            (Self::IPv4(l), ArchivedValue::IPv4(r)) => l == r,
+            (Self::IPv6(l), ArchivedValue::IPv6(r)) => l == r,
+            (Self::Date(l), ArchivedValue::Date(r)) => l == r,
+            (Self::DateTime(l), ArchivedValue::DateTime(r)) => l == r,
             _ => false,
         }
     }
@@ -240,7 +960,374 @@ impl PartialOrd<ArchivedValue> for ArchivedValue {
             (Self::UInt16(l), ArchivedValue::UInt16(r)) => l.partial_cmp(&r.to_native()),
             (Self::UInt32(l), ArchivedValue::UInt32(r)) => l.partial_cmp(&r.to_native()),
             (Self::UInt64(l), ArchivedValue::UInt64(r)) => l.partial_cmp(&r.to_native()),
+            (Self::Tuple(l), ArchivedValue::Tuple(r))
+            | (Self::Array(l), ArchivedValue::Array(r)) => partial_cmp_iters(l.iter(), r.iter()),
+            (Self::Map(l), ArchivedValue::Map(r)) => partial_cmp_iters(
+                l.iter().flat_map(|entry| [&entry.0, &entry.1]),
+                r.iter().flat_map(|entry| [&entry.0, &entry.1]),
+            ),
+            (Self::IPv4(l), ArchivedValue::IPv4(r)) => l.partial_cmp(&r.to_native()),
+            (Self::IPv6(l), ArchivedValue::IPv6(r)) => l.partial_cmp(r),
+            (Self::Date(l), ArchivedValue::Date(r)) => l.partial_cmp(&r.to_native()),
+            (Self::DateTime(l), ArchivedValue::DateTime(r)) => l.partial_cmp(&r.to_native()),
+            _ => cmp_integers(self.as_i128(), rhs.as_i128()),
+        }
+    }
+}
+
+impl ArchivedValue {
+    /// Returns integer as `i128`, see `Value::as_i128`.
+    fn as_i128(&self) -> Option<i128> {
+        match self {
+            Self::Int8(value) => Some(i128::from(*value)),
+            Self::Int16(value) => Some(i128::from(value.to_native())),
+            Self::Int32(value) => Some(i128::from(value.to_native())),
+            Self::Int64(value) => Some(i128::from(value.to_native())),
+            Self::UInt8(value) => Some(i128::from(*value)),
+            Self::UInt16(value) => Some(i128::from(value.to_native())),
+            Self::UInt32(value) => Some(i128::from(value.to_native())),
+            Self::UInt64(value) => Some(i128::from(value.to_native())),
             _ => None,
         }
     }
 }
+
+/// Compares integers of different types by value, e.g., `Int32` column with `Int64` column
+/// or `UInt8` with `Int64`. Other values of different types are not comparable.
+fn cmp_integers(left: Option<i128>, right: Option<i128>) -> Option<Ordering> {
+    Some(left?.cmp(&right?))
+}
+
+/// Compares sequences lexicographically, the same way as `PartialOrd` for slices does,
+/// but allows elements of different types (e.g., `Value` and `ArchivedValue`).
+fn partial_cmp_iters<'l, 'r, T, K>(
+    mut left: impl Iterator<Item = &'l T>,
+    mut right: impl Iterator<Item = &'r K>,
+) -> Option<Ordering>
+where
+    T: PartialOrd<K> + 'l,
+    K: 'r,
+{
+    loop {
+        match (left.next(), right.next()) {
+            (Some(l), Some(r)) => match l.partial_cmp(r)? {
+                Ordering::Equal => {}
+                ordering => return Some(ordering),
+            },
+            (None, None) => return Some(Ordering::Equal),
+            (None, Some(_)) => return Some(Ordering::Less),
+            (Some(_), None) => return Some(Ordering::Greater),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlparser::dialect::ClickHouseDialect;
+    use sqlparser::parser::Parser;
+
+    fn parse_type(sql: &str) -> Result<ValueType> {
+        let data_type = Parser::new(&ClickHouseDialect {})
+            .try_with_sql(sql)
+            .and_then(|mut parser| parser.parse_data_type())
+            .unwrap();
+        ValueType::try_from(&data_type)
+    }
+
+    #[test]
+    fn test_parse_enum_type() {
+        let labels = |labels: &[(&str, i16)]| {
+            labels
+                .iter()
+                .map(|(label, code)| (label.to_string(), *code))
+                .collect::<Vec<_>>()
+        };
+        let status = parse_type("Enum8('active' = 1, 'it''s' = -2, 'done')").unwrap();
+        assert_eq!(
+            status,
+            ValueType::Enum8(vec![
+                ("active".to_string(), 1),
+                ("it's".to_string(), -2),
+                ("done".to_string(), -1),
+            ])
+        );
+        // formatted type is parsed back into the same type
+        assert_eq!(
+            status.to_string(),
+            "Enum8('active' = 1, 'it''s' = -2, 'done' = -1)"
+        );
+        assert_eq!(parse_type(&status.to_string()).unwrap(), status);
+
+        assert_eq!(
+            parse_type("Enum16('a', 'b' = 1000)").unwrap(),
+            ValueType::Enum16(labels(&[("a", 1), ("b", 1000)]))
+        );
+        // size is chosen by codes
+        assert!(matches!(
+            parse_type("Enum('a', 'b')"),
+            Ok(ValueType::Enum8(_))
+        ));
+        assert_eq!(
+            parse_type("Enum('a' = 1000)").unwrap(),
+            ValueType::Enum16(labels(&[("a", 1000)]))
+        );
+        assert_eq!(
+            parse_type("Array(Enum8('a' = 1))").unwrap().to_string(),
+            "Array(Enum8('a' = 1))"
+        );
+
+        for sql in [
+            "Enum8('a' = 1, 'a' = 2)",
+            "Enum8('a' = 1, 'b' = 1)",
+            "Enum8('a' = 0, 'b', 'c' = 1)",
+            "Enum8('a' = 128)",
+            "Enum16('a' = 32768)",
+        ] {
+            assert!(
+                matches!(parse_type(sql), Err(Error::UnsupportedColumnType(_))),
+                "{sql}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_enum_values_are_codes() {
+        let status = parse_type("Enum8('active' = 1, 'done' = 2)").unwrap();
+        let string = |text: &str| SQLValue::SingleQuotedString(text.to_string());
+
+        assert_eq!(
+            Value::try_from((string("done"), &status)).unwrap(),
+            Value::Int8(2)
+        );
+        assert_eq!(
+            Value::try_from((SQLValue::Number("1".to_string(), false), &status)).unwrap(),
+            Value::Int8(1)
+        );
+        assert!(Value::try_from((string("missing"), &status)).is_err());
+        assert!(Value::try_from((SQLValue::Number("3".to_string(), false), &status)).is_err());
+        assert_eq!(status.enum_code("active"), Some(Value::Int8(1)));
+        assert_eq!(ValueType::Int8.enum_code("active"), None);
+
+        assert_eq!(
+            status.to_output_value(Value::Int8(2)),
+            Value::String("done".to_string())
+        );
+        assert_eq!(status.to_output_value(Value::Null), Value::Null);
+        assert_eq!(status.to_output_value(Value::Int8(7)), Value::Int8(7));
+        let nested = ValueType::Array(Box::new(status));
+        assert!(nested.has_output_conversion());
+        assert_eq!(
+            nested.to_output_value(Value::Array(vec![Value::Int8(1), Value::Int8(2)])),
+            Value::Array(vec![
+                Value::String("active".to_string()),
+                Value::String("done".to_string())
+            ])
+        );
+    }
+
+    #[test]
+    fn test_ip_values() {
+        let string = |text: &str| SQLValue::SingleQuotedString(text.to_string());
+        let ipv4 = |text: &str| Value::try_from((string(text), &ValueType::IPv4)).unwrap();
+        let ipv6 = |text: &str| Value::try_from((string(text), &ValueType::IPv6)).unwrap();
+
+        assert_eq!(parse_type("IPv4").unwrap(), ValueType::IPv4);
+        assert_eq!(
+            parse_type("Array(IPv6)").unwrap().to_string(),
+            "Array(IPv6)"
+        );
+        assert!(parse_type("IPv5").is_err());
+
+        assert_eq!(ipv4("10.0.0.1"), Value::IPv4(0x0A00_0001));
+        assert_eq!(ipv4("10.0.0.1").to_sql_literal(), "'10.0.0.1'");
+        // text is canonical, IPv4 is mapped into IPv6
+        assert_eq!(ipv6("2001:0DB8:0000::0001").ip_to_string(), "2001:db8::1");
+        assert_eq!(ipv6("1.2.3.4").ip_to_string(), "::ffff:1.2.3.4");
+        assert_eq!(
+            ValueType::IPv6.to_output_value(ipv6("::1")),
+            Value::String("::1".to_string())
+        );
+        assert_eq!(
+            ValueType::Array(Box::new(ValueType::IPv4))
+                .to_output_value(Value::Array(vec![ipv4("127.0.0.1"), Value::Null])),
+            Value::Array(vec![Value::String("127.0.0.1".to_string()), Value::Null])
+        );
+        for (text, value_type) in [
+            ("10.0.0.256", ValueType::IPv4),
+            ("::1", ValueType::IPv4),
+            ("2001:db8::g", ValueType::IPv6),
+            ("", ValueType::IPv6),
+        ] {
+            assert!(
+                Value::try_from((string(text), &value_type)).is_err(),
+                "{text}"
+            );
+        }
+
+        // addresses are ordered as numbers, not as text
+        for (smaller, larger) in [
+            (ipv4("9.255.255.255"), ipv4("10.0.0.0")),
+            (ipv4("10.0.0.9"), ipv4("10.0.0.10")),
+            (ipv6("::ffff"), ipv6("::1:0")),
+            (ipv6("fe80::1"), ipv6("ff00::")),
+        ] {
+            assert!(smaller < larger, "{smaller:?} < {larger:?}");
+            let bytes = rkyv::to_bytes::<rkyv::rancor::Error>(&larger).unwrap();
+            let archived = rkyv::access::<ArchivedValue, rkyv::rancor::Error>(&bytes).unwrap();
+            assert_eq!(smaller.partial_cmp(archived), Some(Ordering::Less));
+            assert_eq!(archived.partial_cmp(&smaller), Some(Ordering::Greater));
+        }
+    }
+
+    #[test]
+    fn test_date_values() {
+        let string = |text: &str| SQLValue::SingleQuotedString(text.to_string());
+        let date = |text: &str| Value::try_from((string(text), &ValueType::Date));
+        let date_time = |text: &str| Value::try_from((string(text), &ValueType::DateTime));
+
+        assert_eq!(parse_type("Date").unwrap(), ValueType::Date);
+        assert_eq!(parse_type("DateTime").unwrap(), ValueType::DateTime);
+        assert_eq!(
+            parse_type("Array(DateTime)").unwrap().to_string(),
+            "Array(DateTime)"
+        );
+        assert!(parse_type("DateTime64(3)").is_err());
+
+        assert_eq!(date("1970-01-02").unwrap(), Value::Date(1));
+        assert_eq!(date("2149-06-06").unwrap(), Value::Date(u16::MAX));
+        assert_eq!(
+            date_time("2024-01-01 00:00:01").unwrap(),
+            Value::DateTime(1_704_067_201)
+        );
+        // date is midnight
+        assert_eq!(
+            date_time("2024-01-01").unwrap(),
+            Value::DateTime(1_704_067_200)
+        );
+        assert_eq!(
+            date_time("2024-01-01 00:00:01").unwrap().to_sql_literal(),
+            "'2024-01-01 00:00:01'"
+        );
+        assert_eq!(
+            ValueType::Date.to_output_value(Value::Date(19_782)),
+            Value::String("2024-02-29".to_string())
+        );
+        assert_eq!(
+            ValueType::DateTime.to_output_value(Value::Null),
+            Value::Null
+        );
+        for value in [
+            date("1969-12-31"),
+            date("2149-06-07"),
+            date("2024-01-01 00:00:00"),
+            date_time("2106-02-07 06:28:16"),
+            date_time("2024-02-30 00:00:00"),
+            date_time("yesterday"),
+        ] {
+            assert!(value.is_err(), "{value:?}");
+        }
+
+        // dates are ordered, but are not compared with integers
+        let bytes = rkyv::to_bytes::<rkyv::rancor::Error>(&Value::Date(2)).unwrap();
+        let archived = rkyv::access::<ArchivedValue, rkyv::rancor::Error>(&bytes).unwrap();
+        assert_eq!(Value::Date(1).partial_cmp(archived), Some(Ordering::Less));
+        assert_eq!(archived.partial_cmp(&Value::Date(3)), Some(Ordering::Less));
+        assert_eq!(Value::Date(2).partial_cmp(&Value::UInt16(2)), None);
+        assert_eq!(Value::DateTime(2).partial_cmp(&Value::Date(2)), None);
+    }
+
+    #[test]
+    fn test_integers_of_different_types_are_compared_by_value() {
+        let archive = |value: &Value| rkyv::to_bytes::<rkyv::rancor::Error>(value).unwrap();
+        for (left, right, expected) in [
+            (Value::Int8(-1), Value::UInt64(0), Ordering::Less),
+            (Value::Int32(7), Value::Int64(7), Ordering::Equal),
+            (
+                Value::UInt64(u64::MAX),
+                Value::Int64(i64::MAX),
+                Ordering::Greater,
+            ),
+        ] {
+            let (left_bytes, right_bytes) = (archive(&left), archive(&right));
+            let archived_left =
+                rkyv::access::<ArchivedValue, rkyv::rancor::Error>(&left_bytes).unwrap();
+            let archived_right =
+                rkyv::access::<ArchivedValue, rkyv::rancor::Error>(&right_bytes).unwrap();
+
+            assert_eq!(
+                left.partial_cmp(&right),
+                Some(expected),
+                "{left:?} {right:?}"
+            );
+            assert_eq!(left.partial_cmp(archived_right), Some(expected));
+            assert_eq!(archived_left.partial_cmp(&right), Some(expected));
+            assert_eq!(archived_left.partial_cmp(archived_right), Some(expected));
+        }
+        // other types stay incomparable
+        assert_eq!(Value::Int8(1).partial_cmp(&Value::Null), None);
+        assert_eq!(
+            Value::Int8(1).partial_cmp(&Value::String("1".to_string())),
+            None
+        );
+    }
+
+    #[test]
+    fn test_archived_value_type_layout_is_unchanged() {
+        // enum and IP types are appended after existing ones without growing the archived
+        // types, so `.metadata` and `part.inf` files and granules written before them are read
+        // as they are
+        assert_eq!(size_of::<ArchivedValueType>(), 12);
+        assert_eq!(size_of::<ArchivedValue>(), 24);
+        let bytes = rkyv::to_bytes::<rkyv::rancor::Error>(&ValueType::Map(
+            Box::new(ValueType::String),
+            Box::new(ValueType::UInt64),
+        ))
+        .unwrap();
+        assert_eq!(
+            rkyv::from_bytes::<ValueType, rkyv::rancor::Error>(&bytes).unwrap(),
+            ValueType::Map(Box::new(ValueType::String), Box::new(ValueType::UInt64))
+        );
+    }
+
+    #[test]
+    fn test_partition_id_is_directory_name() {
+        assert_eq!(
+            Value::String("2024-01".to_string()).to_partition_id(),
+            "2024-01"
+        );
+        assert_eq!(Value::Int32(-7).to_partition_id(), "-7");
+        assert_eq!(
+            Value::String("a/b.c".to_string()).to_partition_id(),
+            "a%2Fb%2Ec"
+        );
+        assert_eq!(Value::String("..".to_string()).to_partition_id(), "%2E%2E");
+        assert_eq!(
+            Value::Tuple(vec![Value::UInt8(1), Value::Null]).to_partition_id(),
+            "%281%2CNULL%29"
+        );
+
+        // reserved names do not collide with values, which are encoded
+        assert_eq!(Value::String(String::new()).to_partition_id(), "%");
+        assert_eq!(Value::String("raw".to_string()).to_partition_id(), "%72aw");
+        assert_eq!(
+            Value::String("%72aw".to_string()).to_partition_id(),
+            "%2572aw"
+        );
+    }
+
+    #[test]
+    fn test_quote_ident() {
+        assert_eq!(quote_ident("John_Data"), "John_Data");
+        assert_eq!(quote_ident("_id2"), "_id2");
+        assert_eq!(quote_ident("My Column"), "\"My Column\"");
+        assert_eq!(quote_ident("2nd"), "\"2nd\"");
+        assert_eq!(quote_ident("a\"b"), "\"a\"\"b\"");
+        assert_eq!(quote_ident("order"), "\"order\"");
+        assert_eq!(quote_ident("SELECT"), "\"SELECT\"");
+        assert_eq!(quote_ident("null"), "\"null\"");
+        assert_eq!(quote_ident("primary"), "\"primary\"");
+        // keywords, which are parsed as identifiers, are left plain
+        assert_eq!(quote_ident("name"), "name");
+    }
+}
@@ -0,0 +1,130 @@
+use crate::CONFIG;
+
+use std::sync::{LazyLock, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// How often throttled merge checks for shutdown.
+const SHUTDOWN_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Origin of part files, which decides whether their writes are throttled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteOrigin {
+    /// Part of an `INSERT`. Never throttled, so clients are not slowed down.
+    Insert,
+    /// Part, produced by a background merge. Throttled by `max_merge_bytes_per_sec`.
+    Merge,
+}
+
+/// Limiter of merge writes, shared by all running merges, so their total rate stays under the
+/// limit.
+static MERGE_LIMITER: LazyLock<Mutex<RateLimiter>> = LazyLock::new(|| {
+    Mutex::new(RateLimiter::new(
+        CONFIG.get_max_merge_bytes_per_sec(),
+        Instant::now(),
+    ))
+});
+
+/// Shutdown signals of merge workers, see `stop_throttling_on`.
+static SHUTDOWN: Mutex<Vec<watch::Receiver<bool>>> = Mutex::new(Vec::new());
+
+/// Stops throttling of merges, once `true` is sent to `shutdown`, so that running merges
+/// finish their parts without waiting for the limit.
+pub(crate) fn stop_throttling_on(shutdown: watch::Receiver<bool>) {
+    SHUTDOWN
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .push(shutdown);
+}
+
+fn is_shutting_down() -> bool {
+    SHUTDOWN
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .any(|shutdown| *shutdown.borrow())
+}
+
+/// Sleeps after writing `bytes` of a part of `origin`, until writes of merges are back under
+/// `max_merge_bytes_per_sec`, or shutdown (see `stop_throttling_on`). Returns immediately for
+/// inserts and without a limit.
+pub(crate) fn throttle_write(origin: WriteOrigin, bytes: u64) {
+    if origin != WriteOrigin::Merge {
+        return;
+    }
+    // lock is released before sleeping, so other merges reserve their writes meanwhile
+    let delay = MERGE_LIMITER
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .reserve(bytes, Instant::now());
+    let deadline = Instant::now() + delay;
+    // sleeps in slices, so shutdown does not wait for the whole delay
+    loop {
+        let now = Instant::now();
+        if now >= deadline || is_shutting_down() {
+            return;
+        }
+        std::thread::sleep(SHUTDOWN_CHECK_INTERVAL.min(deadline - now));
+    }
+}
+
+/// Keeps average rate of writes under `bytes_per_sec`. Every write reserves time, which it
+/// takes at the limit, after the end of previous reservations, and the writer waits until its
+/// reservation ends. Idle time is not saved up, so writes after a pause are not bursted.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    /// `None` means no limit.
+    bytes_per_sec: Option<u64>,
+    /// End of the last reservation.
+    next_free: Instant,
+}
+
+impl RateLimiter {
+    pub(crate) const fn new(bytes_per_sec: Option<u64>, now: Instant) -> Self {
+        Self {
+            bytes_per_sec,
+            next_free: now,
+        }
+    }
+
+    /// Reserves time of writing `bytes` at the limit.
+    ///
+    /// Returns: duration the writer should sleep after writing `bytes` at `now`. Zero without
+    /// a limit.
+    pub(crate) fn reserve(&mut self, bytes: u64, now: Instant) -> Duration {
+        let Some(bytes_per_sec) = self.bytes_per_sec else {
+            return Duration::ZERO;
+        };
+        let nanos = u128::from(bytes) * 1_000_000_000 / u128::from(bytes_per_sec);
+        let write_time = Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX));
+        self.next_free = self.next_free.max(now) + write_time;
+        self.next_free - now
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter_keeps_writes_under_limit() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(Some(1000), start);
+
+        // writes at the same moment wait for each other
+        assert_eq!(limiter.reserve(500, start), Duration::from_millis(500));
+        assert_eq!(limiter.reserve(1000, start), Duration::from_millis(1500));
+        // writer, which slept its delay, waits only for its own bytes
+        let after_sleep = start + Duration::from_millis(1500);
+        assert_eq!(
+            limiter.reserve(100, after_sleep),
+            Duration::from_millis(100)
+        );
+        // idle time is not saved up
+        let after_pause = start + Duration::from_secs(10);
+        assert_eq!(limiter.reserve(2000, after_pause), Duration::from_secs(2));
+
+        let mut unlimited = RateLimiter::new(None, start);
+        assert_eq!(unlimited.reserve(u64::MAX, start), Duration::ZERO);
+    }
+}
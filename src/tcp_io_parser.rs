@@ -31,6 +31,11 @@ pub enum ProtocolError {
 
     #[display("Conversion error. {_0}")]
     Conversion(String),
+
+    #[display("Frame too large: {_0} bytes (max {_1} bytes)")]
+    FrameTooLarge(usize, usize),
+    #[display("Empty frame: SQL command is required")]
+    EmptyFrame,
 }
 
 // Required by `Encoder` and `Decoder` traits.
@@ -57,7 +62,19 @@ impl From<RMPError> for ProtocolError {
 /// Protocol format:
 /// - Header: 8-byte little-endian u64 containing body size
 /// - Body: UTF-8 encoded SQL command (for decoding) or `MessagePack` response (for encoding)
-pub struct Parser;
+///
+/// Incoming frames larger than `max_frame_size` are rejected before any buffer is reserved, and
+/// frames without body are rejected.
+pub struct Parser {
+    max_frame_size: usize,
+}
+
+impl Parser {
+    /// Creates a parser, which accepts SQL commands up to `max_frame_size` bytes.
+    pub const fn new(max_frame_size: usize) -> Self {
+        Self { max_frame_size }
+    }
+}
 
 impl Decoder for Parser {
     type Item = String;
@@ -76,6 +93,14 @@ impl Decoder for Parser {
 
         let body_size = usize::try_from(HeaderType::from_le_bytes(header_bytes))
             .map_err(|_| ProtocolError::Conversion("Header type too large".to_string()))?;
+        if body_size > self.max_frame_size {
+            return Err(ProtocolError::FrameTooLarge(body_size, self.max_frame_size));
+        }
+        if body_size == 0 {
+            // the frame is complete, so it is consumed as any other malformed frame
+            buf.advance(HEADER_SIZE);
+            return Err(ProtocolError::EmptyFrame);
+        }
         let total_message_size = HEADER_SIZE + body_size;
 
         if buf.len() < total_message_size {
@@ -86,7 +111,9 @@ impl Decoder for Parser {
         // Now consume the header and the data
         buf.advance(HEADER_SIZE);
         let data = buf.split_to(body_size);
-        let decoded = String::from_utf8_lossy(&data).into_owned();
+        let decoded = String::from_utf8(data.to_vec()).map_err(|error| {
+            ProtocolError::Conversion(format!("SQL command is not valid UTF-8: {error}"))
+        })?;
 
         Ok(Some(decoded))
     }
@@ -113,3 +140,81 @@ where
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(body: &[u8]) -> BytesMut {
+        let mut buf = BytesMut::new();
+        buf.put((body.len() as HeaderType).to_le_bytes().as_slice());
+        buf.put(body);
+        buf
+    }
+
+    const MAX_FRAME_SIZE: usize = 1024;
+
+    #[test]
+    fn test_decode_valid_utf8() {
+        let mut buf = frame("SELECT 'привет' FROM db.t".as_bytes());
+        let decoded = Parser::new(MAX_FRAME_SIZE).decode(&mut buf).unwrap();
+        assert_eq!(decoded, Some("SELECT 'привет' FROM db.t".to_string()));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_decode_invalid_utf8() {
+        let mut buf = frame(b"SELECT '\xff\xfe' FROM db.t");
+        buf.put(frame(b"SELECT 1"));
+
+        let mut parser = Parser::new(MAX_FRAME_SIZE);
+        let result = parser.decode(&mut buf);
+        assert!(matches!(result, Err(ProtocolError::Conversion(_))));
+        // the malformed frame is consumed, so the next one can still be decoded
+        assert_eq!(
+            parser.decode(&mut buf).unwrap(),
+            Some("SELECT 1".to_string())
+        );
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_decode_oversized_header() {
+        let mut buf = BytesMut::new();
+        buf.put(HeaderType::MAX.to_le_bytes().as_slice());
+        buf.put(b"SELECT".as_slice());
+
+        let result = Parser::new(MAX_FRAME_SIZE).decode(&mut buf);
+        assert!(matches!(
+            result,
+            Err(ProtocolError::FrameTooLarge(_, MAX_FRAME_SIZE))
+        ));
+        // nothing should be reserved for the claimed body
+        assert!(buf.capacity() < MAX_FRAME_SIZE);
+    }
+
+    #[test]
+    fn test_decode_empty_frame() {
+        let mut buf = frame(b"");
+        buf.put(frame(b"SELECT 1"));
+
+        let mut parser = Parser::new(MAX_FRAME_SIZE);
+        assert!(matches!(
+            parser.decode(&mut buf),
+            Err(ProtocolError::EmptyFrame)
+        ));
+        assert_eq!(
+            parser.decode(&mut buf).unwrap(),
+            Some("SELECT 1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_decode_frame_at_limit() {
+        let body = vec![b'a'; MAX_FRAME_SIZE];
+        let mut buf = frame(&body);
+
+        let decoded = Parser::new(MAX_FRAME_SIZE).decode(&mut buf).unwrap();
+        assert_eq!(decoded.map(|sql| sql.len()), Some(MAX_FRAME_SIZE));
+    }
+}
@@ -0,0 +1,4886 @@
+//! Runs queries against the server binary through `db-rs-client`.
+
+use db_rs_client::{Client, Connection, Error, OutputTable, Pool, PoolConfig, Value, ValueType};
+use futures::{StreamExt, TryStreamExt};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// Server process with its own storage directory and port. Killed on drop.
+struct TestServer {
+    process: Child,
+    dir: PathBuf,
+    addr: String,
+}
+
+impl TestServer {
+    async fn start(name: &str, idle_timeout_secs: u64) -> Self {
+        Self::start_with_config(name, idle_timeout_secs, "").await
+    }
+
+    /// Starts server with `extra_config` lines appended to the config file.
+    async fn start_with_config(name: &str, idle_timeout_secs: u64, extra_config: &str) -> Self {
+        let dir = Self::create_dir(name);
+        let storage_dir = dir.join("db");
+        Self::start_on_storage(dir, &storage_dir, idle_timeout_secs, extra_config).await
+    }
+
+    /// Starts server, which merges parts only by `OPTIMIZE TABLE`, with `extra_config` lines
+    /// appended to the config file.
+    async fn start_without_merges(name: &str, extra_config: &str) -> Self {
+        Self::start_with_config(
+            name,
+            0,
+            &format!("background_merge_available_under = 0\n{extra_config}"),
+        )
+        .await
+    }
+
+    /// Starts read-only server on the storage directory of `primary`.
+    async fn start_replica(name: &str, primary: &TestServer, extra_config: &str) -> Self {
+        Self::start_on_storage(
+            Self::create_dir(name),
+            &primary.dir.join("db"),
+            0,
+            &format!("read_only = true\n{extra_config}"),
+        )
+        .await
+    }
+
+    fn create_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("touchhouse-client-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    async fn start_on_storage(
+        dir: PathBuf,
+        storage_dir: &Path,
+        idle_timeout_secs: u64,
+        extra_config: &str,
+    ) -> Self {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let addr = format!("127.0.0.1:{port}");
+
+        // `extra_config` may stop automatic merges with its own value, see `start_without_merges`,
+        // or log more
+        let merges = if extra_config.contains("background_merge_available_under") {
+            ""
+        } else {
+            "background_merge_available_under = 5\n"
+        };
+        let log_level = if extra_config.contains("log_level") {
+            ""
+        } else {
+            "log_level = 2\n"
+        };
+        let config_path = dir.join("touch_config.toml");
+        std::fs::write(
+            &config_path,
+            format!(
+                "storage_directory = \"{}\"\n\
+                 tcp_socket = \"{addr}\"\n\
+                 max_connections = 10\n\
+                 {log_level}\
+                 {merges}\
+                 idle_timeout_secs = {idle_timeout_secs}\n\
+                 {extra_config}",
+                storage_dir.display()
+            ),
+        )
+        .unwrap();
+
+        let process = Self::spawn(&config_path);
+        let server = Self { process, dir, addr };
+        server.wait_ready().await;
+        server
+    }
+
+    /// Kills server and starts it again on the same storage directory.
+    async fn restart(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+
+        self.process = Self::spawn(&self.dir.join("touch_config.toml"));
+        self.wait_ready().await;
+    }
+
+    /// Spawns server, appending its log to `server.log` next to the config.
+    fn spawn(config_path: &PathBuf) -> Child {
+        let log = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(config_path.with_file_name("server.log"))
+            .unwrap();
+
+        Command::new(env!("CARGO_BIN_EXE_touchhouse"))
+            .env("CONFIG_PATH", config_path)
+            .stdout(Stdio::null())
+            .stderr(log)
+            .spawn()
+            .unwrap()
+    }
+
+    fn log(&self) -> String {
+        std::fs::read_to_string(self.dir.join("server.log")).unwrap()
+    }
+
+    async fn wait_ready(&self) {
+        for _ in 0..100 {
+            if let Ok(client) = Client::connect(&self.addr).await {
+                client.close().await.unwrap();
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("Server did not start on {}", self.addr);
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[tokio::test]
+async fn test_query_matrix() {
+    let server = TestServer::start("matrix", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    for sql in [
+        "CREATE DATABASE client_db",
+        "CREATE TABLE client_db.users (id UInt64, name String, age UInt8, score Int32, active Bool, \
+         uid UUID, point Tuple(Int32, String), tags Array(String), attrs Map(String, UInt64)) \
+         ORDER BY id",
+        "INSERT INTO client_db.users (id, name, age, score, active, uid, point, tags, attrs) VALUES \
+         (1, 'Alice', 30, -5, true, '123e4567-e89b-12d3-a456-426614174000', (1, 'a'), ['x', 'y'], map('k', 1)), \
+         (2, 'Bob', 25, 7, false, '123e4567-e89b-12d3-a456-426614174001', (2, 'b'), [], map('k', 2, 'm', 3)), \
+         (3, NULL, 41, 0, true, '123e4567-e89b-12d3-a456-426614174002', (3, 'c'), ['z'], map())",
+    ] {
+        let table = client.query(sql).await.unwrap();
+        assert_eq!(
+            table.column("status").unwrap().data,
+            [Value::String("OK".to_string())]
+        );
+    }
+
+    let table = client
+        .query("SELECT * FROM client_db.users ORDER BY id")
+        .await
+        .unwrap();
+    assert_eq!(table.row_count(), 3);
+    assert!(table.execution_time.is_some());
+    let rows: Vec<_> = table.rows().collect();
+    assert_eq!(rows[0].get::<u64>("id").unwrap(), 1);
+    assert_eq!(rows[0].get::<i64>("score").unwrap(), -5);
+    assert!(rows[0].get::<bool>("active").unwrap());
+    assert_eq!(
+        rows[0].get::<uuid::Uuid>("uid").unwrap().to_string(),
+        "123e4567-e89b-12d3-a456-426614174000"
+    );
+    assert_eq!(
+        rows[0].get::<Vec<String>>("tags").unwrap(),
+        ["x".to_string(), "y".to_string()]
+    );
+    assert_eq!(
+        rows[0].get::<Value>("point").unwrap(),
+        Value::Tuple(vec![Value::Int32(1), Value::String("a".to_string())])
+    );
+    assert_eq!(rows[2].get::<Option<String>>("name").unwrap(), None);
+    assert!(matches!(
+        rows[0].get::<u8>("score"),
+        Err(Error::TypeMismatch { .. })
+    ));
+    assert!(matches!(
+        rows[0].get::<u8>("missing"),
+        Err(Error::ColumnNotFound(_))
+    ));
+
+    let table = client
+        .query(
+            "SELECT id, attrs['k'], mapContains(attrs, 'm') FROM client_db.users \
+             WHERE age < 26 OR point.2 = 'c' ORDER BY id",
+        )
+        .await
+        .unwrap();
+    let rows: Vec<_> = table.rows().collect();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0].get::<u64>("id").unwrap(), 2);
+    assert_eq!(rows[0].get::<u64>("attrs['k']").unwrap(), 2);
+    assert!(rows[0].get::<bool>("mapContains(attrs, 'm')").unwrap());
+    assert_eq!(rows[1].get::<Option<u64>>("attrs['k']").unwrap(), None);
+
+    let table = client
+        .query("SELECT id, row_number() OVER () FROM client_db.users ORDER BY id LIMIT 2 OFFSET 1")
+        .await
+        .unwrap();
+    let rows: Vec<_> = table
+        .rows()
+        .map(|row| {
+            (
+                row.get::<u64>("id").unwrap(),
+                row.get::<u64>("row_number() OVER ()").unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(rows, [(2, 1), (3, 2)]);
+
+    for limit in ["LIMIT 1, 2", "OFFSET 1 LIMIT 2", "OFFSET 1"] {
+        let table = client
+            .query(&format!(
+                "SELECT id FROM client_db.users ORDER BY id {limit}"
+            ))
+            .await
+            .unwrap();
+        let ids: Vec<u64> = table.rows().map(|row| row.get("id").unwrap()).collect();
+        assert_eq!(ids, [2, 3], "{limit}");
+    }
+
+    // server errors do not break the connection
+    assert!(matches!(
+        client.query("SELECT * FROM client_db.missing").await,
+        Err(Error::Server(_))
+    ));
+    assert!(matches!(
+        client.query("SELEC 1").await,
+        Err(Error::Server(_))
+    ));
+    assert_eq!(
+        client
+            .query("SELECT id FROM client_db.users")
+            .await
+            .unwrap()
+            .row_count(),
+        3
+    );
+
+    client.query("DROP TABLE client_db.users").await.unwrap();
+    client.query("DROP DATABASE client_db").await.unwrap();
+    client.close().await.unwrap();
+}
+
+/// Edits column or mark file of a part (6 magic bytes, data and CRC32 of the data), keeping its
+/// checksum valid. `edit` gets the file without the checksum.
+fn edit_checksummed_file(path: &Path, edit: impl FnOnce(&mut [u8])) {
+    let mut bytes = std::fs::read(path).unwrap();
+    let crc_start = bytes.len() - 4;
+    edit(&mut bytes[..crc_start]);
+    let crc = crc32fast::hash(&bytes[6..crc_start]);
+    bytes[crc_start..].copy_from_slice(&crc.to_le_bytes());
+    std::fs::write(path, bytes).unwrap();
+}
+
+fn column_names(table: &OutputTable) -> Vec<&str> {
+    table
+        .columns
+        .iter()
+        .map(|col| col.column_def.name.as_str())
+        .collect()
+}
+
+#[tokio::test]
+async fn test_column_order_matches_projection() {
+    let server = TestServer::start("order", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    client.query("CREATE DATABASE order_db").await.unwrap();
+    client
+        .query("CREATE TABLE order_db.t (a UInt64, b String, c UInt8, d Bool) ORDER BY a")
+        .await
+        .unwrap();
+    client
+        .query("INSERT INTO order_db.t (a, b, c, d) VALUES (1, 'x', 10, true), (2, 'y', 20, false)")
+        .await
+        .unwrap();
+
+    for (sql, expected) in [
+        ("SELECT * FROM order_db.t", ["a", "b", "c", "d"].as_slice()),
+        (
+            "SELECT * FROM order_db.t WHERE c = 20",
+            &["a", "b", "c", "d"],
+        ),
+        (
+            "SELECT * FROM order_db.t WHERE b = 'y' ORDER BY d",
+            &["a", "b", "c", "d"],
+        ),
+        ("SELECT d, c, b, a FROM order_db.t", &["d", "c", "b", "a"]),
+        (
+            "SELECT d, c, b, a FROM order_db.t WHERE c = 20",
+            &["d", "c", "b", "a"],
+        ),
+        (
+            "SELECT b, a FROM order_db.t WHERE c > 10 ORDER BY d",
+            &["b", "a"],
+        ),
+        (
+            "SELECT c, * FROM order_db.t WHERE b = 'y'",
+            &["c", "a", "b", "d"],
+        ),
+        (
+            "SELECT b, row_number() OVER (), a FROM order_db.t WHERE d = false",
+            &["b", "row_number() OVER ()", "a"],
+        ),
+    ] {
+        let table = client.query(sql).await.unwrap();
+        assert_eq!(column_names(&table), expected, "{sql}");
+    }
+
+    let table = client
+        .query("SELECT c, a FROM order_db.t WHERE c = 20")
+        .await
+        .unwrap();
+    let rows: Vec<_> = table.rows().map(|row| row.into_values()).collect();
+    assert_eq!(rows, [vec![Value::UInt8(20), Value::UInt64(2)]]);
+}
+
+#[tokio::test]
+async fn test_drop_partition() {
+    let server = TestServer::start("partition", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    client.query("CREATE DATABASE part_db").await.unwrap();
+    client
+        .query(
+            "CREATE TABLE part_db.t (month String, id UInt64) ENGINE = MergeTree \
+             PARTITION BY month ORDER BY id",
+        )
+        .await
+        .unwrap();
+    // rows of a single insert are split by partition
+    client
+        .query(
+            "INSERT INTO part_db.t (month, id) VALUES \
+             ('2024-01', 1), ('2024-02', 2), ('2024-01', 3), ('raw', 4)",
+        )
+        .await
+        .unwrap();
+    client
+        .query("INSERT INTO part_db.t (month, id) VALUES ('2024-02', 5)")
+        .await
+        .unwrap();
+
+    let table_path = server.dir.join("db").join("part_db").join("t");
+    assert!(table_path.join("2024-01").is_dir());
+    assert!(table_path.join("%72aw").is_dir());
+
+    let ids = |table: OutputTable| -> Vec<u64> {
+        table
+            .rows()
+            .map(|row| row.get::<u64>("id").unwrap())
+            .collect()
+    };
+
+    client
+        .query("ALTER TABLE part_db.t DROP PARTITION '2024-01'")
+        .await
+        .unwrap();
+    assert!(!table_path.join("2024-01").exists());
+    let table = client
+        .query("SELECT id FROM part_db.t ORDER BY id")
+        .await
+        .unwrap();
+    assert_eq!(ids(table), [2, 4, 5]);
+
+    client
+        .query("ALTER TABLE part_db.t DROP PARTITION ('raw')")
+        .await
+        .unwrap();
+    // dropping missing partition is a no-op
+    client
+        .query("ALTER TABLE part_db.t DROP PARTITION '2024-01'")
+        .await
+        .unwrap();
+    let table = client
+        .query("SELECT id FROM part_db.t ORDER BY id")
+        .await
+        .unwrap();
+    assert_eq!(ids(table), [2, 5]);
+
+    client
+        .query("CREATE TABLE part_db.plain (id UInt64) ORDER BY id")
+        .await
+        .unwrap();
+    assert!(matches!(
+        client
+            .query("ALTER TABLE part_db.plain DROP PARTITION 1")
+            .await,
+        Err(Error::Server(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_low_cardinality_column() {
+    let server = TestServer::start("dictionary", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    client.query("CREATE DATABASE dict_db").await.unwrap();
+    client
+        .query(
+            "CREATE TABLE dict_db.t (id UInt64, country LowCardinality(Nullable(String))) \
+             ORDER BY id",
+        )
+        .await
+        .unwrap();
+    client
+        .query(
+            "INSERT INTO dict_db.t (id, country) VALUES (1, 'US'), (2, 'DE'), (3, NULL), (4, 'US')",
+        )
+        .await
+        .unwrap();
+    client
+        .query("INSERT INTO dict_db.t (id, country) VALUES (5, 'FR')")
+        .await
+        .unwrap();
+
+    let rows = |table: OutputTable| -> Vec<(u64, Option<String>)> {
+        table
+            .rows()
+            .map(|row| (row.get("id").unwrap(), row.get("country").unwrap()))
+            .collect()
+    };
+
+    let table = client
+        .query("SELECT id, country FROM dict_db.t ORDER BY id")
+        .await
+        .unwrap();
+    assert_eq!(
+        rows(table),
+        [
+            (1, Some("US".to_string())),
+            (2, Some("DE".to_string())),
+            (3, None),
+            (4, Some("US".to_string())),
+            (5, Some("FR".to_string())),
+        ]
+    );
+
+    let table = client
+        .query(
+            "SELECT id, country FROM dict_db.t WHERE country = 'US' OR country > 'E' ORDER BY id",
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        rows(table),
+        [
+            (1, Some("US".to_string())),
+            (4, Some("US".to_string())),
+            (5, Some("FR".to_string())),
+        ]
+    );
+
+    let table = client
+        .query("SELECT id FROM dict_db.t WHERE country = 'JP'")
+        .await
+        .unwrap();
+    assert_eq!(table.row_count(), 0);
+}
+
+#[tokio::test]
+async fn test_low_cardinality_setting_falls_back_to_plain() {
+    let server = TestServer::start("dictionary_fallback", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    client.query("CREATE DATABASE fallback_db").await.unwrap();
+    client
+        .query(
+            "CREATE TABLE fallback_db.t (id UInt64, status String, country String) \
+             ORDER BY id SETTINGS low_cardinality = 'status,country'",
+        )
+        .await
+        .unwrap();
+    let table = client
+        .query("SHOW CREATE TABLE fallback_db.t")
+        .await
+        .unwrap();
+    let statement: String = table.rows().next().unwrap().get("statement").unwrap();
+    assert!(
+        statement.contains("country LowCardinality(String)"),
+        "{statement}"
+    );
+
+    // dictionary-encoded part
+    client
+        .query(
+            "INSERT INTO fallback_db.t (id, status, country) VALUES \
+             (0, 'ok', 'US'), (1, 'failed', 'DE'), (2, 'ok', 'US')",
+        )
+        .await
+        .unwrap();
+    // too many distinct countries, part stores them plain-encoded
+    const ROWS: u64 = 100_001;
+    let values: Vec<_> = (3..3 + ROWS)
+        .map(|id| format!("({id}, 'ok', 'c{id}')"))
+        .collect();
+    client
+        .query(&format!(
+            "INSERT INTO fallback_db.t (id, status, country) VALUES {}",
+            values.join(", ")
+        ))
+        .await
+        .unwrap();
+
+    let check = async |client: &mut Client| {
+        let table = client
+            .query("SELECT id FROM fallback_db.t WHERE status = 'ok'")
+            .await
+            .unwrap();
+        assert_eq!(table.row_count(), ROWS as usize + 2);
+
+        let table = client
+            .query("SELECT id, country FROM fallback_db.t WHERE country = 'c7' OR country = 'DE'")
+            .await
+            .unwrap();
+        let mut rows: Vec<(u64, String)> = table
+            .rows()
+            .map(|row| (row.get("id").unwrap(), row.get("country").unwrap()))
+            .collect();
+        rows.sort();
+        assert_eq!(rows, [(1, "DE".to_string()), (7, "c7".to_string())]);
+
+        let table = client
+            .query("SELECT country FROM fallback_db.t ORDER BY country LIMIT 3")
+            .await
+            .unwrap();
+        let countries: Vec<String> = table
+            .rows()
+            .map(|row| row.get("country").unwrap())
+            .collect();
+        assert_eq!(countries, ["DE", "US", "US"]);
+    };
+    check(&mut client).await;
+
+    // parts are merged in the background
+    let table_dir = server.dir.join("db").join("fallback_db").join("t");
+    let part_count = || {
+        std::fs::read_dir(&table_dir)
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_dir() && entry.file_name() != "raw")
+            .count()
+    };
+    for _ in 0..100 {
+        if part_count() == 1 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(part_count(), 1);
+    check(&mut client).await;
+}
+
+#[tokio::test]
+async fn test_quoted_identifiers() {
+    let mut server = TestServer::start("quoted", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    let statement = "CREATE TABLE \"My DB\".\"Events Table\" (id UInt64, \"My Column\" String, \
+                     ID UInt64, \"Straße\" LowCardinality(String)) ENGINE = MergeTree \
+                     PARTITION BY \"Straße\" PRIMARY KEY (id) ORDER BY (id)";
+    client.query("CREATE DATABASE \"My DB\"").await.unwrap();
+    client.query(statement).await.unwrap();
+    client
+        .query(
+            "INSERT INTO \"My DB\".\"Events Table\" (id, \"My Column\", ID, \"Straße\") \
+             VALUES (1, 'a', 10, 'x'), (2, 'b', 20, 'y')",
+        )
+        .await
+        .unwrap();
+
+    for _ in 0..2 {
+        let table = client
+            .query(
+                "SELECT \"My Column\", ID, id FROM \"My DB\".\"Events Table\" \
+                 WHERE \"My Column\" = 'b' AND \"Straße\" = 'y'",
+            )
+            .await
+            .unwrap();
+        let names: Vec<_> = table
+            .columns
+            .iter()
+            .map(|col| col.column_def.name.as_str())
+            .collect();
+        assert_eq!(names, ["My Column", "ID", "id"]);
+        let row = table.rows().next().unwrap();
+        assert_eq!(row.get::<String>("My Column").unwrap(), "b");
+        assert_eq!(row.get::<u64>("ID").unwrap(), 20);
+        assert_eq!(row.get::<u64>("id").unwrap(), 2);
+
+        // names are case-sensitive
+        for sql in [
+            "SELECT \"my column\" FROM \"My DB\".\"Events Table\"",
+            "SELECT Id FROM \"My DB\".\"Events Table\"",
+            "SELECT id FROM \"my db\".\"Events Table\"",
+        ] {
+            assert!(
+                matches!(client.query(sql).await, Err(Error::Server(_))),
+                "{sql}"
+            );
+        }
+
+        let table = client
+            .query("SHOW CREATE TABLE \"My DB\".\"Events Table\"")
+            .await
+            .unwrap();
+        let shown: String = table.rows().next().unwrap().get("statement").unwrap();
+        assert_eq!(
+            shown,
+            "CREATE TABLE \"My DB\".\"Events Table\" (id UInt64 NOT NULL, \"My Column\" String, \
+             ID UInt64, \"Straße\" LowCardinality(String)) ENGINE = MergeTree \
+             PARTITION BY \"Straße\" PRIMARY KEY (id) ORDER BY (id)"
+        );
+
+        server.restart().await;
+        client = Client::connect(&server.addr).await.unwrap();
+    }
+
+    // keywords are quoted, so the shown statement creates the same table again
+    client
+        .query(
+            "CREATE TABLE \"My DB\".keywords (\"order\" UInt64, \"select\" String, \
+             \"null\" UInt64) ORDER BY \"order\"",
+        )
+        .await
+        .unwrap();
+    let table = client
+        .query("SHOW CREATE TABLE \"My DB\".keywords")
+        .await
+        .unwrap();
+    let shown: String = table.rows().next().unwrap().get("statement").unwrap();
+    assert_eq!(
+        shown,
+        "CREATE TABLE \"My DB\".keywords (\"order\" UInt64 NOT NULL, \"select\" String, \
+         \"null\" UInt64) ENGINE = MergeTree PRIMARY KEY (\"order\") ORDER BY (\"order\")"
+    );
+    client.query("DROP TABLE \"My DB\".keywords").await.unwrap();
+    client.query(&shown).await.unwrap();
+    client
+        .query(
+            "INSERT INTO \"My DB\".keywords (\"order\", \"select\", \"null\") \
+             VALUES (2, 'b', 20), (1, 'a', 10)",
+        )
+        .await
+        .unwrap();
+    let table = client
+        .query("SELECT \"order\", \"select\", \"null\" FROM \"My DB\".keywords ORDER BY \"order\"")
+        .await
+        .unwrap();
+    let rows: Vec<(u64, String, u64)> = table
+        .rows()
+        .map(|row| {
+            (
+                row.get("order").unwrap(),
+                row.get("select").unwrap(),
+                row.get("null").unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(rows, [(1, "a".to_string(), 10), (2, "b".to_string(), 20)]);
+
+    for sql in [
+        "CREATE TABLE \"My DB\".t (\"a/b\" UInt64) ORDER BY \"a/b\"",
+        "CREATE TABLE \"My DB\".\"..\" (id UInt64) ORDER BY id",
+        "CREATE DATABASE \" padded\"",
+    ] {
+        assert!(
+            matches!(client.query(sql).await, Err(Error::Server(_))),
+            "{sql}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_pool_reuses_and_reconnects() {
+    let server = TestServer::start("pool", 1).await;
+    let pool = Pool::new(server.addr.clone(), 2);
+
+    {
+        let first = pool.get().await.unwrap();
+        let second = pool.get().await.unwrap();
+        first.query("CREATE DATABASE pool_db").await.unwrap();
+        second.query("RESET").await.unwrap();
+
+        // pool is exhausted until a connection is returned
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), pool.get())
+                .await
+                .is_err()
+        );
+    }
+
+    // server closes idle connections, pool replaces them
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+
+    let client = pool.get().await.unwrap();
+    client
+        .query("CREATE TABLE pool_db.t (id UInt64) ORDER BY id")
+        .await
+        .unwrap();
+    client
+        .query("INSERT INTO pool_db.t (id) VALUES (1), (2)")
+        .await
+        .unwrap();
+
+    // connection closes while checked out, next query reconnects
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    let table = client.query("SELECT id FROM pool_db.t").await.unwrap();
+    assert_eq!(table.row_count(), 2);
+}
+
+#[tokio::test]
+async fn test_pool_opens_min_idle_and_pings() {
+    let mut server = TestServer::start("pool_config", 0).await;
+    let config = PoolConfig {
+        max_size: 3,
+        min_idle: 2,
+        ping_on_checkout: true,
+    };
+    let pool = Pool::connect(server.addr.clone(), config).await.unwrap();
+    assert_eq!(pool.idle_count(), 2);
+
+    {
+        let client = pool.get().await.unwrap();
+        client
+            .execute("CREATE DATABASE pool_config_db")
+            .await
+            .unwrap();
+        assert!(matches!(
+            client.execute("CREATE DATABASE pool_config_db").await,
+            Err(Error::Server(_))
+        ));
+    }
+    assert_eq!(pool.idle_count(), 2);
+
+    // idle connections break with the server, checkout replaces them
+    server.restart().await;
+    let client = pool.get().await.unwrap();
+    client
+        .execute("CREATE TABLE pool_config_db.t (id UInt64) ORDER BY id")
+        .await
+        .unwrap();
+    drop(client);
+    assert_eq!(pool.idle_count(), 1);
+
+    let mut client = Client::connect(&server.addr).await.unwrap();
+    client.ping().await.unwrap();
+    client.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_shared_connection_and_query_stream() {
+    let server = TestServer::start("stream", 0).await;
+    let connection = Connection::connect(server.addr.clone()).await.unwrap();
+    connection
+        .execute("CREATE DATABASE stream_db")
+        .await
+        .unwrap();
+    connection
+        .execute("CREATE TABLE stream_db.t (id UInt64) ORDER BY id")
+        .await
+        .unwrap();
+
+    // commands of concurrent tasks share the connection through `&Connection`
+    let inserts = (0..10).map(|batch| {
+        let connection = &connection;
+        async move {
+            let values: Vec<_> = (0..10)
+                .map(|row| format!("({})", batch * 10 + row))
+                .collect();
+            connection
+                .execute(&format!(
+                    "INSERT INTO stream_db.t (id) VALUES {}",
+                    values.join(", ")
+                ))
+                .await
+        }
+    });
+    for result in futures::future::join_all(inserts).await {
+        result.unwrap();
+    }
+
+    let batches: Vec<OutputTable> = connection
+        .query_stream("SELECT id FROM stream_db.t ORDER BY id", 30)
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(
+        batches
+            .iter()
+            .map(OutputTable::row_count)
+            .collect::<Vec<_>>(),
+        [30, 30, 30, 10]
+    );
+    let ids: Vec<u64> = batches
+        .iter()
+        .flat_map(OutputTable::rows)
+        .map(|row| row.get("id").unwrap())
+        .collect();
+    assert_eq!(ids, (0..100).collect::<Vec<_>>());
+
+    // batch ending exactly at the last row is not followed by an empty one
+    let batches: Vec<OutputTable> = connection
+        .query_stream("SELECT id FROM stream_db.t ORDER BY id", 50)
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(batches.len(), 2);
+
+    // empty result has a single batch with the columns
+    let batches: Vec<OutputTable> = connection
+        .query_stream("SELECT id FROM stream_db.t WHERE id > 1000 ORDER BY id", 30)
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(batches.len(), 1);
+    assert_eq!(batches[0].row_count(), 0);
+    assert_eq!(column_names(&batches[0]), ["id"]);
+
+    let mut stream =
+        std::pin::pin!(connection.query_stream("SELECT id FROM stream_db.missing", 30));
+    assert!(matches!(stream.next().await, Some(Err(Error::Server(_)))));
+    assert!(stream.next().await.is_none());
+}
+
+#[tokio::test]
+async fn test_queries_proceed_during_merges() {
+    let server = TestServer::start_with_config(
+        "merges",
+        0,
+        "max_background_merges = 2\nbackground_merge_threads = 1",
+    )
+    .await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    for sql in [
+        "CREATE DATABASE merge_db",
+        "CREATE TABLE merge_db.big (id UInt64, payload String) ORDER BY id",
+        "CREATE TABLE merge_db.small (id UInt64) ORDER BY id",
+        "INSERT INTO merge_db.small (id) VALUES (1), (2), (3)",
+    ] {
+        client.query(sql).await.unwrap();
+    }
+
+    const BATCHES: u64 = 8;
+    const BATCH_ROWS: u64 = 5000;
+    for batch in 0..BATCHES {
+        let values: Vec<_> = (0..BATCH_ROWS)
+            .map(|row| format!("({}, 'payload-{row}')", batch * BATCH_ROWS + row))
+            .collect();
+        client
+            .query(&format!(
+                "INSERT INTO merge_db.big (id, payload) VALUES {}",
+                values.join(", ")
+            ))
+            .await
+            .unwrap();
+    }
+
+    // parts of `big` are merged in the background meanwhile
+    for _ in 0..20 {
+        let started = Instant::now();
+        let table = client.query("SELECT id FROM merge_db.small").await.unwrap();
+        assert_eq!(table.row_count(), 3);
+        assert!(started.elapsed() < Duration::from_secs(2));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    let table = client.query("SELECT id FROM merge_db.big").await.unwrap();
+    assert_eq!(table.row_count(), (BATCHES * BATCH_ROWS) as usize);
+}
+
+#[tokio::test]
+async fn test_show_create_table_after_restart() {
+    let mut server = TestServer::start("comments", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    let statement = "CREATE TABLE comment_db.t (id UInt64 NOT NULL COMMENT 'it''s \"id\" ✓', \
+                     name LowCardinality(String) NOT NULL DEFAULT 'x') ENGINE = MergeTree \
+                     COMMENT 'таблица ''t''' PRIMARY KEY (id) ORDER BY (id, name)";
+    client.query("CREATE DATABASE comment_db").await.unwrap();
+    client.query(statement).await.unwrap();
+    client
+        .query("INSERT INTO comment_db.t (id, name) VALUES (1, 'a')")
+        .await
+        .unwrap();
+
+    let show = |table: OutputTable| table.rows().next().unwrap().get::<String>("statement");
+    let shown = show(
+        client
+            .query("SHOW CREATE TABLE comment_db.t")
+            .await
+            .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(shown, statement);
+
+    server.restart().await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+    let table = client
+        .query("SHOW CREATE TABLE comment_db.t")
+        .await
+        .unwrap();
+    assert_eq!(show(table).unwrap(), statement);
+
+    let table = client.query("SELECT id FROM comment_db.t").await.unwrap();
+    let column = table.column("id").unwrap();
+    assert_eq!(
+        column.column_def.constraints.comment.as_deref(),
+        Some("it's \"id\" ✓")
+    );
+    assert_eq!(column.data, vec![Value::UInt64(1)]);
+
+    assert!(matches!(
+        client.query("SHOW CREATE TABLE comment_db.missing").await,
+        Err(Error::Server(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_exchange_tables() {
+    let mut server = TestServer::start("exchange", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+    for sql in [
+        "CREATE DATABASE live_db",
+        "CREATE DATABASE staging_db",
+        "CREATE TABLE live_db.live (id UInt64) ORDER BY id",
+        "CREATE TABLE staging_db.staging (id UInt64, version UInt8) ORDER BY id",
+        "INSERT INTO live_db.live (id) VALUES (1), (2)",
+        "INSERT INTO live_db.live (id) VALUES (3)",
+        "INSERT INTO staging_db.staging (id, version) VALUES (10, 2), (20, 2)",
+    ] {
+        client.query(sql).await.unwrap();
+    }
+
+    let ids = async |client: &mut Client, table: &str| {
+        let table = client
+            .query(&format!("SELECT id FROM {table} ORDER BY id"))
+            .await
+            .unwrap();
+        table.column("id").unwrap().data.clone()
+    };
+    let ids_of = |ids: &[u64]| ids.iter().copied().map(Value::UInt64).collect::<Vec<_>>();
+
+    client
+        .query("EXCHANGE TABLES live_db.live AND staging_db.staging")
+        .await
+        .unwrap();
+    assert_eq!(ids(&mut client, "live_db.live").await, ids_of(&[10, 20]));
+    assert_eq!(
+        ids(&mut client, "staging_db.staging").await,
+        ids_of(&[1, 2, 3])
+    );
+    // schemas are exchanged with data
+    client
+        .query("INSERT INTO live_db.live (id, version) VALUES (30, 3)")
+        .await
+        .unwrap();
+    assert!(
+        client
+            .query("SELECT version FROM staging_db.staging")
+            .await
+            .is_err()
+    );
+
+    for sql in [
+        "EXCHANGE TABLES live_db.live AND live_db.live",
+        "EXCHANGE TABLES live_db.live AND live_db.missing",
+        "EXCHANGE TABLES live_db.live",
+        "EXCHANGE TABLES live_db.live AND staging_db.staging AND live_db.live",
+    ] {
+        assert!(
+            matches!(client.query(sql).await, Err(Error::Server(_))),
+            "{sql}"
+        );
+    }
+
+    server.restart().await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+    assert_eq!(
+        ids(&mut client, "live_db.live").await,
+        ids_of(&[10, 20, 30])
+    );
+    assert_eq!(
+        ids(&mut client, "staging_db.staging").await,
+        ids_of(&[1, 2, 3])
+    );
+
+    // server crashes mid-exchange, after `live` is moved to the temporary directory
+    let db_dir = server.dir.join("db");
+    let live = db_dir.join("live_db").join("live");
+    let staging = db_dir.join("staging_db").join("staging");
+    let interrupted = async |server: &mut TestServer, renames: &[(&PathBuf, &PathBuf)]| {
+        let _ = server.process.kill();
+        let _ = server.process.wait();
+        std::fs::write(
+            db_dir.join(".exchange.journal"),
+            "live_db\nlive\nstaging_db\nstaging\n",
+        )
+        .unwrap();
+        for (from, to) in renames {
+            std::fs::rename(from, to).unwrap();
+        }
+        server.restart().await;
+        Client::connect(&server.addr).await.unwrap()
+    };
+    let tmp = db_dir.join(".exchange");
+
+    // only the first rename is done: exchange is undone
+    let mut client = interrupted(&mut server, &[(&live, &tmp)]).await;
+    assert_eq!(
+        ids(&mut client, "live_db.live").await,
+        ids_of(&[10, 20, 30])
+    );
+    assert_eq!(
+        ids(&mut client, "staging_db.staging").await,
+        ids_of(&[1, 2, 3])
+    );
+
+    // two of three renames are done: exchange is finished
+    let mut client = interrupted(&mut server, &[(&live, &tmp), (&staging, &live)]).await;
+    assert_eq!(ids(&mut client, "live_db.live").await, ids_of(&[1, 2, 3]));
+    assert_eq!(
+        ids(&mut client, "staging_db.staging").await,
+        ids_of(&[10, 20, 30])
+    );
+    assert!(!tmp.exists());
+    assert!(!db_dir.join(".exchange.journal").exists());
+}
+
+#[tokio::test]
+async fn test_hash_sampling() {
+    let server = TestServer::start("hash_sampling", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    client.query("CREATE DATABASE sample_db").await.unwrap();
+    client
+        .query("CREATE TABLE sample_db.t (id UInt64) ENGINE = MergeTree ORDER BY id")
+        .await
+        .unwrap();
+    let values: Vec<_> = (0..1000).map(|id| format!("({id})")).collect();
+    client
+        .query(&format!(
+            "INSERT INTO sample_db.t (id) VALUES {}",
+            values.join(", ")
+        ))
+        .await
+        .unwrap();
+
+    let sample = "SELECT id, cityHash64(id) % 10 FROM sample_db.t WHERE cityHash64(id) % 10 = 0";
+    let first = client.query(sample).await.unwrap();
+    assert!(
+        (50..150).contains(&first.row_count()),
+        "{}",
+        first.row_count()
+    );
+    assert!(
+        first
+            .column("cityHash64(id) % 10")
+            .unwrap()
+            .data
+            .iter()
+            .all(|value| *value == Value::UInt64(0))
+    );
+    // hashes are stable, so the same rows are sampled every time
+    assert_eq!(client.query(sample).await.unwrap().columns, first.columns);
+}
+
+#[tokio::test]
+async fn test_read_only_replica() {
+    let primary = TestServer::start("primary", 0).await;
+    let mut writer = Client::connect(&primary.addr).await.unwrap();
+    for sql in [
+        "CREATE DATABASE replica_db",
+        "CREATE TABLE replica_db.t (id UInt64) ORDER BY id",
+        "INSERT INTO replica_db.t (id) VALUES (1), (2)",
+    ] {
+        writer.query(sql).await.unwrap();
+    }
+
+    let replica = TestServer::start_replica("replica", &primary, "rescan_interval_secs = 1").await;
+    let mut reader = Client::connect(&replica.addr).await.unwrap();
+    let count = async |client: &mut Client, sql: &str| {
+        client.query(sql).await.map(|table| table.row_count())
+    };
+    assert_eq!(
+        count(&mut reader, "SELECT id FROM replica_db.t")
+            .await
+            .unwrap(),
+        2
+    );
+
+    for sql in [
+        "INSERT INTO replica_db.t (id) VALUES (3)",
+        "CREATE TABLE replica_db.other (id UInt64) ORDER BY id",
+        "CREATE DATABASE other_db",
+        "DROP TABLE replica_db.t",
+        "DROP DATABASE replica_db",
+    ] {
+        assert!(
+            matches!(reader.query(sql).await, Err(Error::Server(error)) if error.contains("read-only")),
+            "{sql}"
+        );
+    }
+
+    writer
+        .query("INSERT INTO replica_db.t (id) VALUES (3), (4)")
+        .await
+        .unwrap();
+    writer
+        .query("CREATE TABLE replica_db.new (id UInt64) ORDER BY id")
+        .await
+        .unwrap();
+    writer
+        .query("INSERT INTO replica_db.new (id) VALUES (1)")
+        .await
+        .unwrap();
+
+    // primary may merge parts of `t` meanwhile, so reads fail until the replica rescans
+    let started = Instant::now();
+    while count(&mut reader, "SELECT id FROM replica_db.t").await.ok() != Some(4)
+        || count(&mut reader, "SELECT id FROM replica_db.new")
+            .await
+            .ok()
+            != Some(1)
+    {
+        assert!(
+            started.elapsed() < Duration::from_secs(10),
+            "New parts were not detected"
+        );
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    writer.query("DROP TABLE replica_db.new").await.unwrap();
+    while count(&mut reader, "SELECT id FROM replica_db.new")
+        .await
+        .is_ok()
+    {
+        assert!(
+            started.elapsed() < Duration::from_secs(20),
+            "Dropped table was not detected"
+        );
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+#[tokio::test]
+async fn test_validate_does_not_execute() {
+    let server = TestServer::start("validate", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+    client.query("CREATE DATABASE validate_db").await.unwrap();
+
+    let table = client
+        .query(
+            "VALIDATE CREATE TABLE validate_db.t (id UInt64 NOT NULL, name LowCardinality(String)) \
+             ORDER BY (id, name)",
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        table
+            .rows()
+            .next()
+            .unwrap()
+            .get::<String>("statement")
+            .unwrap(),
+        "CREATE TABLE validate_db.t (id UInt64 NOT NULL, name LowCardinality(String) NOT NULL) \
+         ENGINE = MergeTree PRIMARY KEY (id, name) ORDER BY (id, name)"
+    );
+    assert!(!server.dir.join("db").join("validate_db").join("t").exists());
+    assert!(matches!(
+        client.query("SELECT id FROM validate_db.t").await,
+        Err(Error::Server(_))
+    ));
+
+    client
+        .query("CREATE TABLE validate_db.t (id UInt64) ORDER BY id")
+        .await
+        .unwrap();
+    let table = client
+        .query("validate INSERT INTO validate_db.t (id) VALUES (1)")
+        .await
+        .unwrap();
+    assert_eq!(table.column("OK").unwrap().data.len(), 1);
+    let table = client.query("SELECT id FROM validate_db.t").await.unwrap();
+    assert_eq!(table.row_count(), 0);
+
+    for sql in [
+        "VALIDATE CREATE TABLE validate_db.t (id UInt64) ORDER BY id",
+        "VALIDATE CREATE TABLE validate_db.other (id UInt64) ORDER BY missing",
+        "VALIDATE INSERT INTO validate_db.t (id) VALUES ('a')",
+        "VALIDATE SELECT id FROM validate_db.missing",
+    ] {
+        assert!(
+            matches!(client.query(sql).await, Err(Error::Server(_))),
+            "{sql}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_execution_time_and_slow_query_log() {
+    const SLOW_QUERY_MS: u64 = 100;
+    let server = TestServer::start_with_config(
+        "slow_log",
+        0,
+        &format!("slow_query_ms = {SLOW_QUERY_MS}\nslow_query_redact_literals = true"),
+    )
+    .await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+    let threshold = Duration::from_millis(SLOW_QUERY_MS);
+    // responses, slower than the threshold, each of which is logged once
+    let mut slow_count = 0;
+
+    for sql in [
+        "CREATE DATABASE slow_db",
+        "CREATE TABLE slow_db.t (id UInt64, payload String) ORDER BY id",
+    ] {
+        let table = client.query(sql).await.unwrap();
+        slow_count += usize::from(table.execution_time.unwrap() > threshold);
+    }
+
+    const BATCHES: u64 = 20;
+    const BATCH_ROWS: u64 = 5000;
+    for batch in 0..BATCHES {
+        let values: Vec<_> = (0..BATCH_ROWS)
+            .map(|row| format!("({}, 'secret-{row}')", batch * BATCH_ROWS + row))
+            .collect();
+        let table = client
+            .query(&format!(
+                "INSERT INTO slow_db.t (id, payload) VALUES {}",
+                values.join(", ")
+            ))
+            .await
+            .unwrap();
+        assert!(table.queue_time.is_some());
+        slow_count += usize::from(table.execution_time.unwrap() > threshold);
+    }
+
+    let table = client
+        .query("SELECT id FROM slow_db.t WHERE id = 7")
+        .await
+        .unwrap();
+    assert_eq!(table.row_count(), 1);
+    slow_count += usize::from(table.execution_time.unwrap() > threshold);
+
+    let started = Instant::now();
+    let table = client
+        .query("SELECT id, payload FROM slow_db.t WHERE payload <> 'none' ORDER BY payload")
+        .await
+        .unwrap();
+    let execution_time = table.execution_time.unwrap();
+    assert!(execution_time > threshold, "{execution_time:?}");
+    assert!(execution_time <= started.elapsed());
+    assert_eq!(table.row_count(), (BATCHES * BATCH_ROWS) as usize);
+    slow_count += 1;
+
+    let log = server.log();
+    let slow_lines: Vec<_> = log
+        .lines()
+        .filter(|line| line.contains("Slow query"))
+        .collect();
+    assert_eq!(slow_lines.len(), slow_count, "{log}");
+    let select_lines: Vec<_> = slow_lines
+        .iter()
+        .filter(|line| line.contains("ORDER BY payload"))
+        .collect();
+    assert_eq!(select_lines.len(), 1, "{log}");
+    assert!(
+        select_lines[0].contains(
+            "100000 rows): SELECT id, payload FROM slow_db.t WHERE payload <> ? ORDER BY payload"
+        ),
+        "{log}"
+    );
+    assert!(!log.contains("secret"), "{log}");
+}
+
+#[tokio::test]
+async fn test_system_parts() {
+    let server = TestServer::start("system_parts", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    for sql in [
+        "CREATE DATABASE parts_db",
+        "CREATE TABLE parts_db.t (id UInt64, tag LowCardinality(String)) ORDER BY id",
+        "INSERT INTO parts_db.t (id, tag) VALUES (1, 'a'), (2, 'b'), (3, 'a')",
+    ] {
+        client.query(sql).await.unwrap();
+    }
+
+    let table = client
+        .query(
+            "SELECT name, partition, rows, marks, bytes_on_disk, column_bytes, column_compression \
+             FROM system.parts WHERE database = 'parts_db' AND table = 't'",
+        )
+        .await
+        .unwrap();
+    assert_eq!(table.row_count(), 1);
+    let row = table.rows().next().unwrap();
+
+    let name = row.get::<String>("name").unwrap();
+    let part_dir = server.dir.join("db").join("parts_db").join("t").join(&name);
+    assert!(part_dir.is_dir(), "{}", part_dir.display());
+    assert_eq!(row.get::<Option<String>>("partition").unwrap(), None);
+    assert_eq!(row.get::<u64>("rows").unwrap(), 3);
+    assert_eq!(row.get::<u64>("marks").unwrap(), 1);
+
+    let file_size = |file: &str| std::fs::metadata(part_dir.join(file)).unwrap().len();
+    assert_eq!(
+        row.get::<u64>("bytes_on_disk").unwrap(),
+        file_size("id.bin")
+            + file_size("tag.bin")
+            + file_size("id.mrk")
+            + file_size("tag.mrk")
+            + file_size("part.inf")
+    );
+    assert_eq!(
+        table.column("column_bytes").unwrap().data[0],
+        Value::Map(vec![
+            (
+                Value::String("id".to_string()),
+                Value::UInt64(file_size("id.bin"))
+            ),
+            (
+                Value::String("tag".to_string()),
+                Value::UInt64(file_size("tag.bin"))
+            ),
+        ])
+    );
+    assert_eq!(
+        table.column("column_compression").unwrap().data[0],
+        Value::Map(vec![
+            (
+                Value::String("id".to_string()),
+                Value::String("LZ4(3)".to_string())
+            ),
+            (
+                Value::String("tag".to_string()),
+                Value::String("Dictionary(3)".to_string())
+            ),
+        ])
+    );
+
+    for sql in ["SELECT * FROM system.missing", "CREATE DATABASE system"] {
+        assert!(
+            matches!(client.query(sql).await, Err(Error::Server(_))),
+            "{sql}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_negated_filters() {
+    let server = TestServer::start("negated_filters", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    for sql in [
+        "CREATE DATABASE negated_db",
+        "CREATE TABLE negated_db.t (id UInt64, name String) ORDER BY id",
+        "INSERT INTO negated_db.t (id, name) VALUES \
+         (1, 'Alice'), (2, 'Bob'), (3, 'Anna'), (4, 'Carl'), (5, 'Dan'), (6, 'Ada')",
+    ] {
+        client.query(sql).await.unwrap();
+    }
+
+    let mut ids = async |condition: &str| {
+        let sql = format!("SELECT id FROM negated_db.t WHERE {condition} ORDER BY id");
+        client
+            .query(&sql)
+            .await
+            .unwrap()
+            .rows()
+            .map(|row| row.get::<u64>("id").unwrap())
+            .collect::<Vec<_>>()
+    };
+
+    for (positive, negated) in [
+        ("id IN (1, 2, 3)", "id NOT IN (1, 2, 3)"),
+        ("name LIKE 'A%'", "name NOT LIKE 'A%'"),
+        ("id BETWEEN 2 AND 4", "id NOT BETWEEN 2 AND 4"),
+    ] {
+        let matching = ids(positive).await;
+        let complement = ids(negated).await;
+        assert!(!matching.is_empty() && !complement.is_empty(), "{positive}");
+
+        let mut all = [matching, complement].concat();
+        all.sort_unstable();
+        assert_eq!(all, [1, 2, 3, 4, 5, 6], "{positive}");
+    }
+    assert_eq!(ids("id NOT IN (1, 2, 3)").await, [4, 5, 6]);
+    assert_eq!(ids("name NOT LIKE 'A%'").await, [2, 4, 5]);
+}
+
+#[tokio::test]
+async fn test_filters_compare_integers_of_different_types() {
+    let server = TestServer::start("integer_coercion", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    for sql in [
+        "CREATE DATABASE coercion_db",
+        "CREATE TABLE coercion_db.t (id UInt64, a Int32, b Int64, small Int8) ORDER BY id",
+        "INSERT INTO coercion_db.t (id, a, b, small) VALUES \
+         (1, 1, 1, 1), (2, 5, 3000000000, 100), (3, 7, 7, 120), (4, 9, 2, 127)",
+    ] {
+        client.query(sql).await.unwrap();
+    }
+
+    let mut ids = async |condition: &str| {
+        let sql = format!("SELECT id FROM coercion_db.t WHERE {condition} ORDER BY id");
+        client
+            .query(&sql)
+            .await
+            .unwrap()
+            .rows()
+            .map(|row| row.get::<u64>("id").unwrap())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(ids("a = b").await, [1, 3]);
+    assert_eq!(ids("a != b").await, [2, 4]);
+    assert_eq!(ids("a < b").await, [2]);
+    assert_eq!(ids("small > a").await, [2, 3, 4]);
+    assert_eq!(ids("small < 1000").await, [1, 2, 3, 4]);
+    assert_eq!(ids("small > 3000000000").await, Vec::<u64>::new());
+    assert_eq!(ids("b > a AND small >= 100").await, [2]);
+}
+
+#[tokio::test]
+async fn test_common_table_expressions() {
+    let server = TestServer::start("cte", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    for sql in [
+        "CREATE DATABASE cte_db",
+        "CREATE TABLE cte_db.users (id UInt64, active Bool, age UInt8) ORDER BY id",
+        "INSERT INTO cte_db.users (id, active, age) VALUES \
+         (1, true, 20), (2, false, 30), (3, true, 40), (4, true, 50), (5, false, 60)",
+    ] {
+        client.query(sql).await.unwrap();
+    }
+
+    let mut ids = async |sql: &str| {
+        client
+            .query(sql)
+            .await
+            .unwrap()
+            .rows()
+            .map(|row| row.get::<u64>("id").unwrap())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        ids(
+            "WITH active AS (SELECT id FROM cte_db.users WHERE active = true) \
+             SELECT * FROM active ORDER BY id LIMIT 10"
+        )
+        .await,
+        [1, 3, 4]
+    );
+    // CTE reads an earlier one, and both are filtered
+    assert_eq!(
+        ids(
+            "WITH active AS (SELECT id, age FROM cte_db.users WHERE active = true), \
+             adults AS (SELECT id, age FROM active WHERE age >= 30) \
+             SELECT id FROM adults WHERE id != 4 ORDER BY id"
+        )
+        .await,
+        [3]
+    );
+    // CTE name is not a table name, as tables are always `database.table`
+    assert_eq!(
+        ids("WITH users AS (SELECT id FROM cte_db.users WHERE id = 2) SELECT id FROM users").await,
+        [2]
+    );
+
+    assert!(matches!(
+        client
+            .query("WITH RECURSIVE t AS (SELECT id FROM cte_db.users) SELECT id FROM t")
+            .await,
+        Err(Error::Server(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_partial_pk_filter_matches_full_scan() {
+    let server = TestServer::start("partial_pk", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    for sql in [
+        "CREATE DATABASE pk_db",
+        "CREATE TABLE pk_db.events (ts UInt64, status String) ORDER BY ts",
+    ] {
+        client.query(sql).await.unwrap();
+    }
+    // several granules of 8192 rows
+    const ROWS: u64 = 30_000;
+    let status = |ts: u64| if ts.is_multiple_of(7) { "err" } else { "ok" };
+    let values: Vec<_> = (0..ROWS)
+        .map(|ts| format!("({ts}, '{}')", status(ts)))
+        .collect();
+    client
+        .query(&format!(
+            "INSERT INTO pk_db.events (ts, status) VALUES {}",
+            values.join(", ")
+        ))
+        .await
+        .unwrap();
+
+    let mut ids = async |condition: &str| {
+        let sql = format!("SELECT ts FROM pk_db.events WHERE {condition} ORDER BY ts");
+        client
+            .query(&sql)
+            .await
+            .unwrap()
+            .rows()
+            .map(|row| row.get::<u64>("ts").unwrap())
+            .collect::<Vec<_>>()
+    };
+
+    for (pruned, full_scan, ts_range) in [
+        (
+            "ts > 20000 AND status = 'err'",
+            // `OR` with a non-key column disables pruning
+            "(ts > 20000 OR status = 'none') AND status = 'err'",
+            20_001..ROWS,
+        ),
+        (
+            "status = 'err' AND ts >= 8191 AND ts < 16384",
+            "status = 'err' AND (ts >= 8191 OR status = 'none') AND (ts < 16384 OR status = 'none')",
+            8_191..16_384,
+        ),
+    ] {
+        let expected: Vec<_> = ts_range.filter(|&ts| status(ts) == "err").collect();
+        assert_eq!(ids(pruned).await, expected, "{pruned}");
+        assert_eq!(ids(full_scan).await, expected, "{full_scan}");
+    }
+}
+
+#[tokio::test]
+async fn test_system_tables_and_columns() {
+    let server = TestServer::start("system_catalog", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    for sql in [
+        "CREATE DATABASE catalog_db",
+        "CREATE TABLE catalog_db.events (id UInt64 NOT NULL, status LowCardinality(String) \
+         DEFAULT 'ok', ts UInt64 DEFAULT now()) ENGINE = ReplacingMergeTree \
+         PRIMARY KEY id ORDER BY (id, ts)",
+        "CREATE TABLE catalog_db.empty (id UInt64) ORDER BY id",
+        "INSERT INTO catalog_db.events (id) VALUES (1), (2)",
+        "INSERT INTO catalog_db.events (id) VALUES (3)",
+    ] {
+        client.query(sql).await.unwrap();
+    }
+
+    let table = client
+        .query(
+            "SELECT table, engine, total_rows, part_count FROM system.tables \
+             WHERE database = 'catalog_db'",
+        )
+        .await
+        .unwrap();
+    let tables: Vec<_> = table
+        .rows()
+        .map(|row| {
+            (
+                row.get::<String>("table").unwrap(),
+                row.get::<String>("engine").unwrap(),
+                row.get::<u64>("total_rows").unwrap(),
+                row.get::<u64>("part_count").unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        tables,
+        [
+            ("empty".to_string(), "MergeTree".to_string(), 0, 0),
+            ("events".to_string(), "ReplacingMergeTree".to_string(), 3, 2),
+        ]
+    );
+
+    let table = client
+        .query(
+            "SELECT column, type, nullable, default, compression, in_primary_key, in_order_by \
+             FROM system.columns WHERE database = 'catalog_db' AND table = 'events'",
+        )
+        .await
+        .unwrap();
+    let columns: Vec<_> = table
+        .rows()
+        .map(|row| {
+            (
+                row.get::<String>("column").unwrap(),
+                row.get::<String>("type").unwrap(),
+                row.get::<bool>("nullable").unwrap(),
+                row.get::<Option<String>>("default").unwrap(),
+                row.get::<String>("compression").unwrap(),
+                row.get::<bool>("in_primary_key").unwrap(),
+                row.get::<bool>("in_order_by").unwrap(),
+            )
+        })
+        .collect();
+    let columns: Vec<_> = columns
+        .iter()
+        .map(
+            |(name, field_type, nullable, default, compression, in_pk, in_order_by)| {
+                let default = default.as_deref();
+                (
+                    name.as_str(),
+                    field_type.as_str(),
+                    *nullable,
+                    default,
+                    compression.as_str(),
+                    *in_pk,
+                    *in_order_by,
+                )
+            },
+        )
+        .collect();
+    assert_eq!(
+        columns,
+        [
+            ("id", "UInt64", false, None, "LZ4(3)", true, true),
+            (
+                "status",
+                "String",
+                true,
+                Some("'ok'"),
+                "Dictionary(3)",
+                false,
+                false
+            ),
+            ("ts", "UInt64", false, Some("now()"), "LZ4(3)", false, true),
+        ]
+    );
+
+    // dropped tables disappear from the catalog
+    client.query("DROP TABLE catalog_db.empty").await.unwrap();
+    let table = client
+        .query(
+            "SELECT column FROM system.columns WHERE database = 'catalog_db' AND table = 'empty'",
+        )
+        .await
+        .unwrap();
+    assert_eq!(table.row_count(), 0);
+}
+
+#[tokio::test]
+async fn test_show_engines() {
+    let server = TestServer::start("show_engines", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    let table = client.query("SHOW ENGINES").await.unwrap();
+    let engines: Vec<_> = table
+        .rows()
+        .map(|row| row.get::<String>("engine_name").unwrap())
+        .collect();
+    assert_eq!(engines, ["MergeTree", "ReplacingMergeTree"]);
+    assert!(
+        table
+            .rows()
+            .all(|row| !row.get::<String>("description").unwrap().is_empty())
+    );
+
+    // every listed engine can be used to create a table
+    client.query("CREATE DATABASE engines_db").await.unwrap();
+    for engine in engines {
+        client
+            .query(&format!(
+                "CREATE TABLE engines_db.{engine} (id UInt64) ENGINE = {engine} ORDER BY id"
+            ))
+            .await
+            .unwrap();
+    }
+
+    assert!(matches!(
+        client.query("SHOW SETTINGS").await,
+        Err(Error::Server(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_startup_time_is_logged() {
+    let mut server =
+        TestServer::start_without_merges("startup_log", "log_level = 1\nlog_format = \"json\"")
+            .await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+    for sql in [
+        "CREATE DATABASE startup_db",
+        "CREATE TABLE startup_db.t (id UInt64) ORDER BY id",
+        "CREATE TABLE startup_db.empty (id UInt64) ORDER BY id",
+        "INSERT INTO startup_db.t (id) VALUES (1)",
+        "INSERT INTO startup_db.t (id) VALUES (2)",
+    ] {
+        client.query(sql).await.unwrap();
+    }
+    server.restart().await;
+
+    let log = server.log();
+    let finished: Vec<serde_json::Value> = log
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap_or_else(|e| panic!("{e}: {line}")))
+        .filter(|line: &serde_json::Value| {
+            line["message"]
+                .as_str()
+                .is_some_and(|message| message.starts_with("Finished loading"))
+        })
+        .collect();
+    // the first start found an empty storage directory
+    assert_eq!(finished.len(), 2, "{log}");
+    assert_eq!(finished[0]["tables"], 0);
+    assert_eq!(finished[1]["tables"], 2);
+    assert_eq!(finished[1]["parts"], 2);
+    assert!(finished[1]["elapsed_ms"].is_u64(), "{}", finished[1]);
+}
+
+#[tokio::test]
+async fn test_json_log_format() {
+    let server =
+        TestServer::start_with_config("json_log", 0, "slow_query_ms = 1\nlog_format = \"json\"")
+            .await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+    client.query("CREATE DATABASE json_db").await.unwrap();
+    client
+        .query("CREATE TABLE json_db.t (id UInt64, payload String) ORDER BY id")
+        .await
+        .unwrap();
+    let values: Vec<_> = (0..20000)
+        .map(|row| format!("({row}, 'payload-{row}')"))
+        .collect();
+    client
+        .query(&format!(
+            "INSERT INTO json_db.t (id, payload) VALUES {}",
+            values.join(", ")
+        ))
+        .await
+        .unwrap();
+    let table = client
+        .query("SELECT id, payload FROM json_db.t ORDER BY payload")
+        .await
+        .unwrap();
+    assert_eq!(table.row_count(), 20000);
+
+    let log = server.log();
+    let lines: Vec<serde_json::Value> = log
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap_or_else(|e| panic!("{e}: {line}")))
+        .collect();
+    assert!(!lines.is_empty(), "{log}");
+    for line in &lines {
+        for field in ["timestamp", "level", "target", "message"] {
+            assert!(line[field].is_string(), "{line}");
+        }
+    }
+    let select = lines
+        .iter()
+        .find(|line| {
+            line["message"]
+                .as_str()
+                .is_some_and(|message| message.contains("ORDER BY payload"))
+        })
+        .unwrap_or_else(|| panic!("{log}"));
+    assert_eq!(select["level"], "WARN");
+    assert_eq!(select["rows"], 20000);
+    assert!(select["execution_time_ms"].is_u64(), "{select}");
+}
+
+#[tokio::test]
+async fn test_address_access_levels() {
+    let readonly = TestServer::start_with_config(
+        "access_readonly",
+        0,
+        "[access]\nadmin_addrs = [\"10.0.0.0/8\"]\nreadonly_addrs = [\"127.0.0.1/32\"]\ndefault_access = \"deny\"",
+    )
+    .await;
+    let mut client = Client::connect(&readonly.addr).await.unwrap();
+    client.query("SHOW ENGINES").await.unwrap();
+    client
+        .query("SELECT table FROM system.tables")
+        .await
+        .unwrap();
+    for sql in [
+        "CREATE DATABASE access_db",
+        "CREATE TABLE access_db.t (id UInt64) ORDER BY id",
+        "DROP DATABASE access_db",
+    ] {
+        assert!(
+            matches!(
+                client.query(sql).await,
+                Err(Error::Server(error)) if error.contains("readwrite access is required")
+            ),
+            "{sql}"
+        );
+    }
+
+    let denied = TestServer::start_with_config(
+        "access_deny",
+        0,
+        "[access]\nadmin_addrs = [\"::1\"]\ndefault_access = \"deny\"",
+    )
+    .await;
+    let mut client = Client::connect(&denied.addr).await.unwrap();
+    assert!(matches!(
+        client.query("SHOW ENGINES").await,
+        Err(Error::Server(error)) if error.contains("readonly access is required")
+    ));
+
+    let admin = TestServer::start_with_config(
+        "access_admin",
+        0,
+        "[access]\nadmin_addrs = [\"127.0.0.0/8\"]\nreadonly_addrs = [\"0.0.0.0/0\"]",
+    )
+    .await;
+    let mut client = Client::connect(&admin.addr).await.unwrap();
+    client.query("CREATE DATABASE access_db").await.unwrap();
+    client.query("DROP DATABASE access_db").await.unwrap();
+}
+
+#[tokio::test]
+async fn test_query_id_in_output_and_logs() {
+    let server =
+        TestServer::start_with_config("query_id", 0, "slow_query_ms = 1\nlog_format = \"json\"")
+            .await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+    client.query("CREATE DATABASE qid_db").await.unwrap();
+    client
+        .query("CREATE TABLE qid_db.t (id UInt64, payload String) ORDER BY id")
+        .await
+        .unwrap();
+    let values: Vec<_> = (0..20000)
+        .map(|row| format!("({row}, 'payload-{row}')"))
+        .collect();
+    let insert = client
+        .query(&format!(
+            "INSERT INTO qid_db.t (id, payload) VALUES {}",
+            values.join(", ")
+        ))
+        .await
+        .unwrap();
+    let select = client
+        .query("SELECT id, payload FROM qid_db.t ORDER BY payload")
+        .await
+        .unwrap();
+    let query_id = select.query_id.unwrap();
+    assert_ne!(insert.query_id.unwrap(), query_id);
+
+    let log = server.log();
+    let slow_lines: Vec<serde_json::Value> = log
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .filter(|line: &serde_json::Value| line["message"].as_str().unwrap().contains("Slow query"))
+        .collect();
+    let select_lines: Vec<_> = slow_lines
+        .iter()
+        .filter(|line| {
+            line["message"]
+                .as_str()
+                .unwrap()
+                .contains("ORDER BY payload")
+        })
+        .collect();
+    assert_eq!(select_lines.len(), 1, "{log}");
+    assert_eq!(select_lines[0]["query_id"], query_id.to_string(), "{log}");
+    // 20000 rows are at least buffered and sorted
+    let peak_memory = select_lines[0]["peak_memory_bytes"].as_u64().unwrap();
+    assert!(peak_memory > 20000 * 2 * 16, "{log}");
+    // every slow statement is logged with its own id
+    for line in &slow_lines {
+        assert!(line["query_id"].is_string(), "{line}");
+    }
+}
+
+#[tokio::test]
+async fn test_adaptive_index_granularity() {
+    let mut server = TestServer::start("adaptive_granularity", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+    client.query("CREATE DATABASE ag_db").await.unwrap();
+    client
+        .query(
+            "CREATE TABLE ag_db.t (id UInt64, payload String) ORDER BY id \
+             SETTINGS adaptive_index_granularity = 1, index_granularity_bytes = 65536",
+        )
+        .await
+        .unwrap();
+    const ROWS: u64 = 2000;
+    let values: Vec<_> = (0..ROWS)
+        .map(|id| format!("({id}, '{}')", "x".repeat(500)))
+        .collect();
+    client
+        .query(&format!(
+            "INSERT INTO ag_db.t (id, payload) VALUES {}",
+            values.join(", ")
+        ))
+        .await
+        .unwrap();
+
+    let marks = async |client: &mut Client| {
+        let table = client
+            .query("SELECT marks FROM system.parts WHERE database = 'ag_db'")
+            .await
+            .unwrap();
+        table.rows().next().unwrap().get::<u64>("marks").unwrap()
+    };
+    // ~120 rows of ~0.5 KiB fit into 64 KiB, while fixed granularity gives a single granule
+    let granules = marks(&mut client).await;
+    assert!((10..=20).contains(&granules), "{granules}");
+
+    let statement: String = client
+        .query("SHOW CREATE TABLE ag_db.t")
+        .await
+        .unwrap()
+        .rows()
+        .next()
+        .unwrap()
+        .get("statement")
+        .unwrap();
+    assert!(
+        statement
+            .ends_with("SETTINGS adaptive_index_granularity = 1, index_granularity_bytes = 65536"),
+        "{statement}"
+    );
+
+    server.restart().await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+    assert_eq!(marks(&mut client).await, granules);
+    for id in [0, 777, ROWS - 1] {
+        let table = client
+            .query(&format!("SELECT id, payload FROM ag_db.t WHERE id = {id}"))
+            .await
+            .unwrap();
+        assert_eq!(table.row_count(), 1, "{id}");
+        assert_eq!(table.rows().next().unwrap().get::<u64>("id").unwrap(), id);
+    }
+    let table = client
+        .query("SELECT id FROM ag_db.t WHERE id >= 100 AND id < 1100")
+        .await
+        .unwrap();
+    assert_eq!(table.row_count(), 1000);
+
+    // row limit ends granules before the byte target
+    client
+        .query(
+            "CREATE TABLE ag_db.rows (id UInt64, payload String) ORDER BY id \
+             SETTINGS index_granularity = 100, adaptive_index_granularity = 1",
+        )
+        .await
+        .unwrap();
+    client
+        .query(&format!(
+            "INSERT INTO ag_db.rows (id, payload) VALUES {}",
+            values.join(", ")
+        ))
+        .await
+        .unwrap();
+    let table = client
+        .query("SELECT marks FROM system.parts WHERE database = 'ag_db' AND table = 'rows'")
+        .await
+        .unwrap();
+    assert_eq!(
+        table.rows().next().unwrap().get::<u64>("marks").unwrap(),
+        ROWS / 100
+    );
+}
+
+#[tokio::test]
+async fn test_order_by_expressions() {
+    let server = TestServer::start("order_by_expr", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    client.query("CREATE DATABASE obe_db").await.unwrap();
+    client
+        .query("CREATE TABLE obe_db.t (id UInt64, price UInt32, qty UInt32) ORDER BY id")
+        .await
+        .unwrap();
+    client
+        .query(
+            "INSERT INTO obe_db.t (id, price, qty) VALUES \
+             (1, 10, 5), (2, 3, 4), (3, 100, 1), (4, 7, 20), (5, 1, 1), (6, 2, 2)",
+        )
+        .await
+        .unwrap();
+
+    let ids = async |client: &mut Client, sql: &str| -> Vec<u64> {
+        let table = client.query(sql).await.unwrap();
+        table.rows().map(|row| row.get("id").unwrap()).collect()
+    };
+
+    // totals: 50, 12, 100, 140, 1, 4
+    for (sql, expected) in [
+        (
+            "SELECT id FROM obe_db.t ORDER BY price * qty",
+            [5, 6, 2, 1, 3, 4].as_slice(),
+        ),
+        (
+            "SELECT id FROM obe_db.t ORDER BY id % 3, id",
+            &[3, 6, 1, 4, 2, 5],
+        ),
+        (
+            "SELECT id FROM obe_db.t ORDER BY (id % 2, qty, id)",
+            &[6, 2, 4, 3, 5, 1],
+        ),
+        (
+            "SELECT id FROM obe_db.t WHERE id > 1 ORDER BY price * qty LIMIT 2 OFFSET 1",
+            &[6, 2],
+        ),
+        (
+            "SELECT id FROM (SELECT id, price, qty FROM obe_db.t) ORDER BY price * qty LIMIT 3",
+            &[5, 6, 2],
+        ),
+        (
+            "SELECT id FROM (SELECT id, price, qty FROM obe_db.t ORDER BY price * qty)",
+            &[5, 6, 2, 1, 3, 4],
+        ),
+    ] {
+        assert_eq!(ids(&mut client, sql).await, expected, "{sql}");
+    }
+
+    // sort key is returned only when it is projected
+    let table = client
+        .query("SELECT id, price * qty FROM obe_db.t ORDER BY price * qty LIMIT 2")
+        .await
+        .unwrap();
+    assert_eq!(column_names(&table), ["id", "price * qty"]);
+    let rows: Vec<_> = table.rows().map(|row| row.into_values()).collect();
+    assert_eq!(
+        rows,
+        [
+            vec![Value::UInt64(5), Value::UInt32(1)],
+            vec![Value::UInt64(6), Value::UInt32(4)]
+        ]
+    );
+    let table = client
+        .query("SELECT * FROM obe_db.t ORDER BY price * qty")
+        .await
+        .unwrap();
+    assert_eq!(column_names(&table), ["id", "price", "qty"]);
+
+    assert!(
+        client
+            .query("SELECT id FROM obe_db.t ORDER BY price * id")
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn test_enum_columns() {
+    let mut server = TestServer::start("enum", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    client.query("CREATE DATABASE enum_db").await.unwrap();
+    client
+        .query(
+            "CREATE TABLE enum_db.tasks (id UInt64, status Enum8('todo' = 1, 'doing' = 2, 'done' = 3), \
+             priority Enum16('low' = -1000, 'high' = 1000) NULL) ORDER BY id",
+        )
+        .await
+        .unwrap();
+    // labels and codes are both accepted
+    client
+        .query(
+            "INSERT INTO enum_db.tasks (id, status, priority) VALUES \
+             (1, 'todo', 'low'), (2, 3, 'high'), (3, 'doing', NULL), (4, 'done', -1000)",
+        )
+        .await
+        .unwrap();
+
+    let rows = async |client: &mut Client, sql: &str| -> Vec<Vec<Value>> {
+        let table = client.query(sql).await.unwrap();
+        table.rows().map(|row| row.into_values()).collect()
+    };
+    let label = |label: &str| Value::String(label.to_string());
+
+    assert_eq!(
+        rows(
+            &mut client,
+            "SELECT status, priority FROM enum_db.tasks ORDER BY id"
+        )
+        .await,
+        [
+            vec![label("todo"), label("low")],
+            vec![label("done"), label("high")],
+            vec![label("doing"), Value::Null],
+            vec![label("done"), label("low")],
+        ]
+    );
+    for sql in [
+        "SELECT id FROM enum_db.tasks WHERE status = 'done' ORDER BY id",
+        "SELECT id FROM enum_db.tasks WHERE status = 3 ORDER BY id",
+        "SELECT id FROM enum_db.tasks WHERE status IN ('done') ORDER BY id",
+        // codes are ordered as defined
+        "SELECT id FROM enum_db.tasks WHERE status > 'doing' ORDER BY id",
+    ] {
+        assert_eq!(
+            rows(&mut client, sql).await,
+            [vec![Value::UInt64(2)], vec![Value::UInt64(4)]],
+            "{sql}"
+        );
+    }
+
+    for sql in [
+        "INSERT INTO enum_db.tasks (id, status) VALUES (5, 'unknown')",
+        "INSERT INTO enum_db.tasks (id, status) VALUES (5, 7)",
+        "SELECT id FROM enum_db.tasks WHERE status = 'unknown'",
+        "CREATE TABLE enum_db.bad (id UInt64, e Enum8('a' = 1, 'a' = 2)) ORDER BY id",
+        "CREATE TABLE enum_db.bad (id UInt64, e Enum8('a' = 1, 'b' = 1)) ORDER BY id",
+    ] {
+        assert!(client.query(sql).await.is_err(), "{sql}");
+    }
+
+    let show_create = async |client: &mut Client| -> String {
+        let table = client
+            .query("SHOW CREATE TABLE enum_db.tasks")
+            .await
+            .unwrap();
+        table.rows().next().unwrap().get("statement").unwrap()
+    };
+    let statement = show_create(&mut client).await;
+    assert!(
+        statement.contains(
+            "status Enum8('todo' = 1, 'doing' = 2, 'done' = 3), \
+             priority Enum16('low' = -1000, 'high' = 1000)"
+        ),
+        "{statement}"
+    );
+
+    // definition and values survive restart, and `SHOW CREATE TABLE` creates the same table
+    server.restart().await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+    assert_eq!(show_create(&mut client).await, statement);
+    assert_eq!(
+        rows(&mut client, "SELECT status FROM enum_db.tasks WHERE id = 2").await,
+        [vec![label("done")]]
+    );
+    client.query("DROP TABLE enum_db.tasks").await.unwrap();
+    client.query(&statement).await.unwrap();
+    assert_eq!(show_create(&mut client).await, statement);
+}
+
+#[tokio::test]
+async fn test_ctrl_c_stops_merges_gracefully() {
+    let mut server = TestServer::start("shutdown", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    client.query("CREATE DATABASE shutdown_db").await.unwrap();
+    client
+        .query("CREATE TABLE shutdown_db.t (id UInt64, payload String) ORDER BY id")
+        .await
+        .unwrap();
+    for batch in 0..10 {
+        let values = (0..1000)
+            .map(|idx| format!("({}, '{}')", batch * 1000 + idx, "x".repeat(100)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        client
+            .query(&format!(
+                "INSERT INTO shutdown_db.t (id, payload) VALUES {values}"
+            ))
+            .await
+            .unwrap();
+    }
+    client.close().await.unwrap();
+
+    // parts are likely being merged, when the signal arrives
+    let status = Command::new("kill")
+        .args(["-INT", &server.process.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let exit_status = loop {
+        if let Some(exit_status) = server.process.try_wait().unwrap() {
+            break exit_status;
+        }
+        assert!(Instant::now() < deadline, "Server did not stop on Ctrl-C");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    };
+    // killed by the signal otherwise
+    assert!(exit_status.success(), "{exit_status}");
+
+    // no part is left half-moved
+    server.restart().await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+    let table = client.query("SELECT id FROM shutdown_db.t").await.unwrap();
+    assert_eq!(table.row_count(), 10000);
+}
+
+#[tokio::test]
+async fn test_ctrl_c_stops_throttled_merge() {
+    let mut server =
+        TestServer::start_with_config("shutdown_throttled", 0, "max_merge_bytes_per_sec = 1000")
+            .await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    client.query("CREATE DATABASE throttled_db").await.unwrap();
+    client
+        .query("CREATE TABLE throttled_db.t (id UInt64, payload String) ORDER BY id")
+        .await
+        .unwrap();
+    for batch in 0..2 {
+        let values = (0..1000)
+            .map(|idx| format!("({}, '{}')", batch * 1000 + idx, "x".repeat(100)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        client
+            .query(&format!(
+                "INSERT INTO throttled_db.t (id, payload) VALUES {values}"
+            ))
+            .await
+            .unwrap();
+    }
+
+    // merge is throttled for longer than the test waits for shutdown
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while client
+        .query("SELECT phase FROM system.merges")
+        .await
+        .unwrap()
+        .row_count()
+        == 0
+    {
+        assert!(Instant::now() < deadline, "Merge did not start");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    client.close().await.unwrap();
+
+    let status = Command::new("kill")
+        .args(["-INT", &server.process.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let exit_status = loop {
+        if let Some(exit_status) = server.process.try_wait().unwrap() {
+            break exit_status;
+        }
+        assert!(
+            Instant::now() < deadline,
+            "Throttled merge held up shutdown"
+        );
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    };
+    assert!(exit_status.success(), "{exit_status}");
+
+    // merge finished its part at full speed
+    server.restart().await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+    let table = client.query("SELECT id FROM throttled_db.t").await.unwrap();
+    assert_eq!(table.row_count(), 2000);
+    let parts = client
+        .query("SELECT name FROM system.parts WHERE database = 'throttled_db'")
+        .await
+        .unwrap();
+    assert_eq!(parts.row_count(), 1);
+}
+
+#[tokio::test]
+async fn test_pivot() {
+    let server = TestServer::start("pivot", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    client.query("CREATE DATABASE pivot_db").await.unwrap();
+    client
+        .query(
+            "CREATE TABLE pivot_db.sales (id UInt64, region String, quarter String, \
+             amount Int32 NULL) ORDER BY id",
+        )
+        .await
+        .unwrap();
+    // rows of a single table are split among parts
+    for values in [
+        "(1, 'east', 'Q1', 10), (2, 'east', 'Q1', 5), (3, 'east', 'Q2', -3)",
+        "(4, 'west', 'Q2', 7), (5, 'west', 'Q3', 100), (6, 'north', 'Q1', NULL)",
+    ] {
+        client
+            .query(&format!(
+                "INSERT INTO pivot_db.sales (id, region, quarter, amount) VALUES {values}"
+            ))
+            .await
+            .unwrap();
+    }
+
+    let rows = async |client: &mut Client, sql: &str| -> Vec<Vec<Value>> {
+        let table = client.query(sql).await.unwrap();
+        table.rows().map(|row| row.into_values()).collect()
+    };
+    let region = |region: &str| Value::String(region.to_string());
+
+    // `id` is unique, so it is removed by a subquery to group by region only
+    let table = client
+        .query(
+            "SELECT * FROM (SELECT region, quarter, amount FROM pivot_db.sales) \
+             PIVOT (SUM(amount) FOR quarter IN ('Q1', 'Q2')) ORDER BY region",
+        )
+        .await
+        .unwrap();
+    assert_eq!(column_names(&table), ["region", "Q1", "Q2"]);
+    assert_eq!(
+        table
+            .rows()
+            .map(|row| row.into_values())
+            .collect::<Vec<_>>(),
+        [
+            vec![region("east"), Value::Int64(15), Value::Int64(-3)],
+            // only `NULL` was summed
+            vec![region("north"), Value::Null, Value::Null],
+            vec![region("west"), Value::Null, Value::Int64(7)],
+        ]
+    );
+
+    // pivoted columns are filtered, projected and sorted by like table columns
+    assert_eq!(
+        rows(
+            &mut client,
+            "SELECT Q2, region FROM (SELECT region, quarter, amount FROM pivot_db.sales) \
+             PIVOT (SUM(amount) FOR quarter IN ('Q1', 'Q2')) WHERE Q2 > 0",
+        )
+        .await,
+        [vec![Value::Int64(7), region("west")]]
+    );
+
+    // without a subquery, rows are grouped by all other columns
+    assert_eq!(
+        rows(
+            &mut client,
+            "SELECT id, Q3 FROM pivot_db.sales PIVOT (SUM(amount) FOR quarter IN ('Q3')) \
+             ORDER BY id",
+        )
+        .await,
+        [vec![Value::UInt64(5), Value::Int64(100)]]
+    );
+
+    for sql in [
+        "SELECT * FROM pivot_db.sales PIVOT (SUM(region) FOR quarter IN ('Q1'))",
+        "SELECT * FROM pivot_db.sales PIVOT (MAX(amount) FOR quarter IN ('Q1'))",
+        "SELECT quarter FROM pivot_db.sales PIVOT (SUM(amount) FOR quarter IN ('Q1'))",
+    ] {
+        assert!(client.query(sql).await.is_err(), "{sql}");
+    }
+}
+
+#[tokio::test]
+async fn test_scan_chunk_size_does_not_change_results() {
+    let server = TestServer::start("chunks", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    client.query("CREATE DATABASE chunk_db").await.unwrap();
+    // small granules, so a part has many of them
+    client
+        .query(
+            "CREATE TABLE chunk_db.t (id UInt64, value Int64) ORDER BY id \
+             SETTINGS adaptive_index_granularity = 1, index_granularity_bytes = 1000",
+        )
+        .await
+        .unwrap();
+    for part in 0..3 {
+        let values: Vec<_> = (part * 2000..(part + 1) * 2000)
+            .map(|id| format!("({id}, {})", id % 13))
+            .collect();
+        client
+            .query(&format!(
+                "INSERT INTO chunk_db.t (id, value) VALUES {}",
+                values.join(", ")
+            ))
+            .await
+            .unwrap();
+    }
+
+    let parts = client
+        .query("SELECT marks FROM system.parts WHERE database = 'chunk_db'")
+        .await
+        .unwrap();
+    assert!(
+        parts
+            .rows()
+            .all(|row| row.get::<u64>("marks").unwrap() >= 10)
+    );
+
+    let ids = async |client: &mut Client, sql: &str| -> Vec<u64> {
+        let table = client.query(sql).await.unwrap();
+        let mut ids: Vec<u64> = table.rows().map(|row| row.get("id").unwrap()).collect();
+        ids.sort_unstable();
+        ids
+    };
+    for filter in [
+        "value = 3",
+        "id >= 1500 AND id < 2600",
+        "id % 2 = 0 OR value > 10",
+    ] {
+        let expected = ids(
+            &mut client,
+            &format!("SELECT id FROM chunk_db.t WHERE {filter}"),
+        )
+        .await;
+        assert!(!expected.is_empty(), "{filter}");
+        for granules in [1, 3, 1000] {
+            let sql = format!(
+                "SELECT id FROM chunk_db.t WHERE {filter} SETTINGS scan_chunk_granules = {granules}"
+            );
+            assert_eq!(ids(&mut client, &sql).await, expected, "{sql}");
+        }
+    }
+
+    for sql in [
+        "SELECT id FROM chunk_db.t SETTINGS scan_chunk_granules = 0",
+        "SELECT id FROM chunk_db.t SETTINGS scan_chunk_granules = 'many'",
+        "SELECT id FROM chunk_db.t SETTINGS unknown_setting = 1",
+        "SELECT id FROM (SELECT id FROM chunk_db.t SETTINGS scan_chunk_granules = 1)",
+    ] {
+        assert!(client.query(sql).await.is_err(), "{sql}");
+    }
+}
+
+#[tokio::test]
+async fn test_max_query_complexity() {
+    let server =
+        TestServer::start_with_config("max_query_complexity", 0, "max_query_complexity = 6\n")
+            .await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    client.query("CREATE DATABASE complexity_db").await.unwrap();
+    client
+        .query("CREATE TABLE complexity_db.t (id UInt64, kind String, n Int32) ORDER BY id")
+        .await
+        .unwrap();
+    client
+        .query("INSERT INTO complexity_db.t (id, kind, n) VALUES (1, 'a', 1), (2, 'b', 2)")
+        .await
+        .unwrap();
+
+    let table = client
+        .query("SELECT id FROM (SELECT id, kind FROM complexity_db.t) WHERE kind = 'a'")
+        .await
+        .unwrap();
+    assert_eq!(table.rows().count(), 1);
+
+    // pivot scans its source as a subquery: 4 + 4
+    let pivot =
+        "SELECT * FROM (SELECT kind, n FROM complexity_db.t) PIVOT (SUM(n) FOR kind IN ('a'))";
+    for sql in [pivot.to_string(), format!("VALIDATE {pivot}")] {
+        assert!(
+            matches!(
+                client.query(&sql).await,
+                Err(Error::Server(error)) if error.contains("too complex") && error.contains('8')
+            ),
+            "{sql}"
+        );
+    }
+
+    // connection is still usable
+    let table = client
+        .query("SELECT id FROM complexity_db.t")
+        .await
+        .unwrap();
+    assert_eq!(table.rows().count(), 2);
+}
+
+#[tokio::test]
+async fn test_table_read_only_and_no_merges() {
+    let mut server = TestServer::start("table_flags", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    client.query("CREATE DATABASE flags_db").await.unwrap();
+    client
+        .query("CREATE TABLE flags_db.t (id UInt64) ORDER BY id")
+        .await
+        .unwrap();
+    client
+        .query("INSERT INTO flags_db.t (id) VALUES (1)")
+        .await
+        .unwrap();
+
+    let is_read_only = |result: Result<_, Error>| matches!(result, Err(Error::Server(error)) if error.contains("(flags_db.t) is read-only"));
+    let ids = async |client: &mut Client| -> Vec<u64> {
+        let table = client.query("SELECT id FROM flags_db.t").await.unwrap();
+        let mut ids: Vec<u64> = table.rows().map(|row| row.get("id").unwrap()).collect();
+        ids.sort_unstable();
+        ids
+    };
+
+    client
+        .query("ALTER TABLE flags_db.t MODIFY SETTING read_only = 1")
+        .await
+        .unwrap();
+    // flag survives restart
+    server.restart().await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+    for sql in [
+        "INSERT INTO flags_db.t (id) VALUES (2)",
+        "DROP TABLE flags_db.t",
+        "DROP DATABASE flags_db",
+    ] {
+        assert!(is_read_only(client.query(sql).await), "{sql}");
+    }
+    assert_eq!(ids(&mut client).await, [1]);
+
+    client
+        .query("ALTER TABLE flags_db.t MODIFY SETTING read_only = 0")
+        .await
+        .unwrap();
+    client
+        .query("INSERT INTO flags_db.t (id) VALUES (2)")
+        .await
+        .unwrap();
+    assert_eq!(ids(&mut client).await, [1, 2]);
+
+    // parts are not merged, until the flag is cleared
+    client
+        .query("ALTER TABLE flags_db.t MODIFY SETTING no_merges = 1")
+        .await
+        .unwrap();
+    for id in 3..6 {
+        client
+            .query(&format!("INSERT INTO flags_db.t (id) VALUES ({id})"))
+            .await
+            .unwrap();
+    }
+    let part_count = async |client: &mut Client| -> u64 {
+        let table = client
+            .query("SELECT part_count FROM system.tables WHERE database = 'flags_db'")
+            .await
+            .unwrap();
+        table.rows().next().unwrap().get("part_count").unwrap()
+    };
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert!(part_count(&mut client).await >= 4);
+    let result = client.query("OPTIMIZE TABLE flags_db.t").await;
+    assert!(
+        matches!(&result, Err(Error::Server(error)) if error.contains("stopped by no_merges")),
+        "{result:?}"
+    );
+    assert!(part_count(&mut client).await >= 4);
+
+    client
+        .query("ALTER TABLE flags_db.t MODIFY SETTING no_merges = 0")
+        .await
+        .unwrap();
+    for _ in 0..100 {
+        if part_count(&mut client).await == 1 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(part_count(&mut client).await, 1);
+    assert_eq!(ids(&mut client).await, [1, 2, 3, 4, 5]);
+
+    // read-only table is dropped, when allowed
+    client
+        .query("ALTER TABLE flags_db.t MODIFY SETTING read_only = 1, read_only_allow_drop = 1")
+        .await
+        .unwrap();
+    client.query("DROP TABLE flags_db.t").await.unwrap();
+}
+
+#[tokio::test]
+async fn test_ip_columns() {
+    let mut server = TestServer::start("ip", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    client.query("CREATE DATABASE ip_db").await.unwrap();
+    client
+        .query(
+            "CREATE TABLE ip_db.hits (ip IPv4, ip6 IPv6 NULL) ORDER BY ip \
+             SETTINGS adaptive_index_granularity = 1, index_granularity_bytes = 50",
+        )
+        .await
+        .unwrap();
+    // text order of these addresses differs from their numeric order
+    client
+        .query(
+            "INSERT INTO ip_db.hits (ip, ip6) VALUES ('10.0.0.10', '2001:0db8::1'), \
+             ('9.255.255.255', NULL), ('10.0.0.9', '::ffff:10.0.0.9'), \
+             ('10.1.0.0', '10.1.0.0'), ('192.168.1.1', 'fe80::1'), ('10.0.255.255', '::')",
+        )
+        .await
+        .unwrap();
+
+    let rows = async |client: &mut Client, sql: &str| -> Vec<Vec<Value>> {
+        let table = client.query(sql).await.unwrap();
+        table.rows().map(|row| row.into_values()).collect()
+    };
+    let ip = |text: &str| Value::String(text.to_string());
+
+    assert_eq!(
+        rows(&mut client, "SELECT ip, ip6 FROM ip_db.hits ORDER BY ip").await,
+        [
+            vec![ip("9.255.255.255"), Value::Null],
+            vec![ip("10.0.0.9"), ip("::ffff:10.0.0.9")],
+            vec![ip("10.0.0.10"), ip("2001:db8::1")],
+            vec![ip("10.0.255.255"), ip("::")],
+            vec![ip("10.1.0.0"), ip("::ffff:10.1.0.0")],
+            vec![ip("192.168.1.1"), ip("fe80::1")],
+        ]
+    );
+    // subnet 10.0.0.0/16 as a range of the primary key
+    assert_eq!(
+        rows(
+            &mut client,
+            "SELECT ip FROM ip_db.hits WHERE ip >= '10.0.0.0' AND ip < '10.1.0.0' ORDER BY ip"
+        )
+        .await,
+        [
+            vec![ip("10.0.0.9")],
+            vec![ip("10.0.0.10")],
+            vec![ip("10.0.255.255")]
+        ]
+    );
+    assert_eq!(
+        rows(
+            &mut client,
+            "SELECT ip FROM ip_db.hits WHERE ip6 = '2001:db8:0::1'"
+        )
+        .await,
+        [vec![ip("10.0.0.10")]]
+    );
+
+    for sql in [
+        "INSERT INTO ip_db.hits (ip) VALUES ('10.0.0.256')",
+        "INSERT INTO ip_db.hits (ip) VALUES ('::1')",
+        "INSERT INTO ip_db.hits (ip, ip6) VALUES ('1.1.1.1', 'fe80::g')",
+        "SELECT ip FROM ip_db.hits WHERE ip > 'localhost'",
+    ] {
+        assert!(client.query(sql).await.is_err(), "{sql}");
+    }
+
+    let table = client.query("SHOW CREATE TABLE ip_db.hits").await.unwrap();
+    let statement: String = table.rows().next().unwrap().get("statement").unwrap();
+    assert!(
+        statement.contains("(ip IPv4 NOT NULL, ip6 IPv6)"),
+        "{statement}"
+    );
+
+    // values survive restart
+    server.restart().await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+    assert_eq!(
+        rows(
+            &mut client,
+            "SELECT ip6 FROM ip_db.hits WHERE ip = '192.168.1.1'"
+        )
+        .await,
+        [vec![ip("fe80::1")]]
+    );
+}
+
+#[tokio::test]
+async fn test_system_disk_usage() {
+    let server = TestServer::start("disk_usage", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    for sql in [
+        "CREATE DATABASE usage_db",
+        "CREATE TABLE usage_db.t (id UInt64, tag String) ORDER BY id",
+        "CREATE TABLE usage_db.empty (id UInt64) ORDER BY id",
+        "INSERT INTO usage_db.t (id, tag) VALUES (1, 'a'), (2, 'b'), (3, 'a')",
+    ] {
+        client.query(sql).await.unwrap();
+    }
+
+    let table = client
+        .query("SYSTEM DISK USAGE TABLE usage_db.t")
+        .await
+        .unwrap();
+    assert_eq!(table.row_count(), 1);
+    let row = table.rows().next().unwrap();
+
+    let name = row.get::<String>("part_name").unwrap();
+    let part_dir = server.dir.join("db").join("usage_db").join("t").join(&name);
+    let file_size = |file: &str| std::fs::metadata(part_dir.join(file)).unwrap().len();
+    let compressed = file_size("id.bin")
+        + file_size("tag.bin")
+        + file_size("id.mrk")
+        + file_size("tag.mrk")
+        + file_size("part.inf");
+    assert_eq!(row.get::<u64>("compressed_bytes").unwrap(), compressed);
+    // 3 rows of 2 values, 24 bytes each
+    let uncompressed = row.get::<u64>("uncompressed_bytes").unwrap();
+    assert_eq!(uncompressed, 144);
+    let ratio = row.get::<String>("compression_ratio").unwrap();
+    let expected = uncompressed as f64 / compressed as f64;
+    assert!(
+        (ratio.parse::<f64>().unwrap() - expected).abs() <= 0.005,
+        "{ratio} != {expected}"
+    );
+
+    let table = client
+        .query("SYSTEM DISK USAGE TABLE usage_db.empty")
+        .await
+        .unwrap();
+    assert_eq!(table.row_count(), 0);
+
+    let table = client.query("SYSTEM DISK USAGE").await.unwrap();
+    assert_eq!(table.row_count(), 1);
+    let row = table.rows().next().unwrap();
+    assert_eq!(row.get::<String>("database").unwrap(), "usage_db");
+    assert_eq!(row.get::<u64>("tables").unwrap(), 2);
+    assert_eq!(row.get::<u64>("parts").unwrap(), 1);
+    assert_eq!(row.get::<u64>("compressed_bytes").unwrap(), compressed);
+    assert_eq!(row.get::<String>("compression_ratio").unwrap(), ratio);
+
+    assert!(matches!(
+        client
+            .query("SYSTEM DISK USAGE TABLE usage_db.missing")
+            .await,
+        Err(Error::Server(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_system_part_info() {
+    let server = TestServer::start("part_info", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    // row of 2 values is 48 bytes, so granules have 2 rows
+    for sql in [
+        "CREATE DATABASE part_info_db",
+        "CREATE TABLE part_info_db.t (id UInt64, tag String) ORDER BY id \
+         SETTINGS adaptive_index_granularity = 1, index_granularity_bytes = 96",
+        "INSERT INTO part_info_db.t (id, tag) VALUES (5, 'e'), (1, 'a'), (4, 'd'), (2, 'b'), (3, 'c')",
+    ] {
+        client.query(sql).await.unwrap();
+    }
+    let table = client
+        .query("SYSTEM DISK USAGE TABLE part_info_db.t")
+        .await
+        .unwrap();
+    let part_name = table
+        .rows()
+        .next()
+        .unwrap()
+        .get::<String>("part_name")
+        .unwrap();
+
+    let table = client
+        .query(&format!("SYSTEM PART INFO part_info_db.t '{part_name}'"))
+        .await
+        .unwrap();
+    let marks: Vec<_> = table
+        .rows()
+        .map(|row| {
+            (
+                row.get::<u64>("mark_idx").unwrap(),
+                row.get::<String>("pk_col_name").unwrap(),
+                row.get::<String>("pk_min_value").unwrap(),
+                row.get::<u64>("granule_row_count").unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        marks,
+        [
+            (0, "id".to_string(), "1".to_string(), 2),
+            (1, "id".to_string(), "3".to_string(), 2),
+            (2, "id".to_string(), "5".to_string(), 1),
+        ]
+    );
+
+    let mut previous_end = None;
+    for row in table.rows() {
+        let start = row.get::<u64>("start_byte").unwrap();
+        let end = row.get::<u64>("end_byte").unwrap();
+        assert_eq!(row.get::<u64>("compressed_size").unwrap(), end - start);
+        if let Some(previous_end) = previous_end {
+            assert_eq!(start, previous_end);
+        }
+        previous_end = Some(end);
+    }
+    let file_size = std::fs::metadata(
+        server
+            .dir
+            .join("db")
+            .join("part_info_db")
+            .join("t")
+            .join(&part_name)
+            .join("id.bin"),
+    )
+    .unwrap()
+    .len();
+    assert!(previous_end.unwrap() <= file_size);
+
+    for sql in [
+        "SYSTEM PART INFO part_info_db.t 'missing'",
+        "SYSTEM PART INFO part_info_db.missing 'missing'",
+    ] {
+        assert!(
+            matches!(client.query(sql).await, Err(Error::Server(_))),
+            "{sql}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_format_pretty() {
+    let server = TestServer::start("format_pretty", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    for sql in [
+        "CREATE DATABASE pretty_db",
+        "CREATE TABLE pretty_db.t (id UInt64, name String) ORDER BY id",
+        "INSERT INTO pretty_db.t (id, name) VALUES (1, 'one'), (20, NULL)",
+    ] {
+        client.query(sql).await.unwrap();
+    }
+
+    let table = client
+        .query("SELECT id, name FROM pretty_db.t ORDER BY id FORMAT PRETTY;")
+        .await
+        .unwrap();
+    assert_eq!(table.row_count(), 1);
+    let pretty = table
+        .rows()
+        .next()
+        .unwrap()
+        .get::<String>("pretty")
+        .unwrap();
+    assert_eq!(
+        pretty,
+        "\
++----+------+
+| id | name |
++----+------+
+|  1 | one  |
+| 20 | ␀    |
++----+------+
+"
+    );
+
+    // MessagePack table is still the default
+    let table = client.query("SELECT id FROM pretty_db.t").await.unwrap();
+    assert_eq!(table.row_count(), 2);
+
+    assert!(matches!(
+        client.query("SELECT id FROM pretty_db.t FORMAT JSON").await,
+        Err(Error::Server(error)) if error.contains("only FORMAT PRETTY is supported")
+    ));
+}
+
+#[tokio::test]
+async fn test_skip_unreadable_parts() {
+    let server = TestServer::start("skip_unreadable", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    for sql in [
+        "CREATE DATABASE skip_db",
+        "CREATE TABLE skip_db.t (id UInt64) ORDER BY id",
+        "ALTER TABLE skip_db.t MODIFY SETTING no_merges = 1",
+        "INSERT INTO skip_db.t (id) VALUES (1), (2)",
+        "INSERT INTO skip_db.t (id) VALUES (3), (4)",
+        "INSERT INTO skip_db.t (id) VALUES (5), (6)",
+    ] {
+        client.query(sql).await.unwrap();
+    }
+    let table = client.query("SELECT id FROM skip_db.t").await.unwrap();
+    assert_eq!(table.skipped_parts, None);
+
+    // corrupt column file of a single part, which fails its checksum
+    let table_dir = server.dir.join("db").join("skip_db").join("t");
+    let column_file = std::fs::read_dir(&table_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path().join("id.bin"))
+        .find(|path| path.is_file())
+        .unwrap();
+    let mut bytes = std::fs::read(&column_file).unwrap();
+    let middle = bytes.len() / 2;
+    bytes[middle] ^= 0xFF;
+    std::fs::write(&column_file, bytes).unwrap();
+
+    assert!(matches!(
+        client.query("SELECT id FROM skip_db.t").await,
+        Err(Error::Server(error)) if error.contains("id.bin") || error.contains("CRC")
+    ));
+
+    let table = client
+        .query("SELECT id FROM skip_db.t SETTINGS skip_unreadable_parts = 1")
+        .await
+        .unwrap();
+    assert_eq!(table.skipped_parts, Some(1));
+    assert_eq!(table.row_count(), 4);
+
+    // parts are not merged in order, so the unreadable one is skipped as a whole
+    let table = client
+        .query("SELECT id FROM skip_db.t ORDER BY id LIMIT 3 SETTINGS skip_unreadable_parts = true")
+        .await
+        .unwrap();
+    assert_eq!(table.skipped_parts, Some(1));
+    let ids: Vec<u64> = table.rows().map(|row| row.get("id").unwrap()).collect();
+    assert_eq!(ids.len(), 3);
+    assert!(ids.is_sorted(), "{ids:?}");
+
+    assert!(matches!(
+        client
+            .query("SELECT id FROM skip_db.t SETTINGS skip_unreadable_parts = 2")
+            .await,
+        Err(Error::Server(error)) if error.contains("skip_unreadable_parts expects")
+    ));
+}
+
+#[tokio::test]
+async fn test_mark_offsets_beyond_file_are_rejected() {
+    let server = TestServer::start_without_merges("mark_offsets", "").await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+    for sql in [
+        "CREATE DATABASE offsets_db",
+        "CREATE TABLE offsets_db.t (id UInt64, name String) ORDER BY id",
+        "INSERT INTO offsets_db.t (id, name) VALUES (1, 'a'), (2, 'b')",
+    ] {
+        client.query(sql).await.unwrap();
+    }
+
+    // end of the last granule of `name` points beyond what 32-bit platforms can map
+    let table_dir = server.dir.join("db").join("offsets_db").join("t");
+    let part_dir = std::fs::read_dir(&table_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| path.join("name.mrk").is_file())
+        .unwrap();
+    edit_checksummed_file(&part_dir.join("name.mrk"), |marks| {
+        let end = marks.len() - 8;
+        marks[end..].copy_from_slice(&u64::MAX.to_le_bytes());
+    });
+
+    let expected = if cfg!(target_pointer_width = "32") {
+        "Column file too large for 32-bit platform"
+    } else {
+        "exceeds file size"
+    };
+    let result = client.query("SELECT id, name FROM offsets_db.t").await;
+    assert!(
+        matches!(
+            &result,
+            Err(Error::Server(error)) if error.contains(expected) && error.contains("column=name")
+        ),
+        "{result:?}"
+    );
+    // other columns are read as before
+    let table = client.query("SELECT id FROM offsets_db.t").await.unwrap();
+    assert_eq!(table.row_count(), 2);
+}
+
+#[tokio::test]
+async fn test_mark_files_are_read_per_column() {
+    let server = TestServer::start("mark_files", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    for sql in [
+        "CREATE DATABASE marks_db",
+        "CREATE TABLE marks_db.t (id UInt64, name String) ORDER BY id",
+        "INSERT INTO marks_db.t (id, name) VALUES (1, 'a'), (2, 'b'), (3, 'c')",
+    ] {
+        client.query(sql).await.unwrap();
+    }
+
+    let table_dir = server.dir.join("db").join("marks_db").join("t");
+    let part_dir = std::fs::read_dir(&table_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| path.is_dir() && path.join("part.inf").is_file())
+        .unwrap();
+    assert!(part_dir.join("id.mrk").is_file());
+    std::fs::remove_file(part_dir.join("name.mrk")).unwrap();
+
+    // marks of columns, which are not read, are not needed
+    let table = client
+        .query("SELECT id FROM marks_db.t WHERE id >= 2")
+        .await
+        .unwrap();
+    assert_eq!(table.row_count(), 2);
+    assert!(matches!(
+        client.query("SELECT name FROM marks_db.t").await,
+        Err(Error::Server(error)) if error.contains("name.mrk")
+    ));
+}
+
+#[tokio::test]
+async fn test_count_star_reads_no_columns() {
+    let server = TestServer::start("count_star", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    for sql in [
+        "CREATE DATABASE count_db",
+        "CREATE TABLE count_db.t (id UInt64, name String) ORDER BY id",
+        "ALTER TABLE count_db.t MODIFY SETTING no_merges = 1",
+        "INSERT INTO count_db.t (id, name) VALUES (1, 'a'), (2, 'b'), (3, 'c')",
+        "INSERT INTO count_db.t (id, name) VALUES (4, 'd'), (5, 'e')",
+        "CREATE TABLE count_db.empty (id UInt64) ORDER BY id",
+    ] {
+        client.query(sql).await.unwrap();
+    }
+    let mut count = async |sql: &str| -> u64 {
+        let table = client.query(sql).await.unwrap();
+        table.rows().next().unwrap().get("count()").unwrap()
+    };
+    assert_eq!(count("SELECT count() FROM count_db.empty").await, 0);
+    assert_eq!(count("SELECT count(*) FROM count_db.t").await, 5);
+
+    // without column files, rows can not be read, but are still counted
+    let table_dir = server.dir.join("db").join("count_db").join("t");
+    for part in std::fs::read_dir(&table_dir).unwrap() {
+        let part = part.unwrap().path();
+        if !part.is_dir() {
+            continue;
+        }
+        for file in std::fs::read_dir(&part).unwrap() {
+            let file = file.unwrap().path();
+            if file.extension().is_some_and(|extension| extension == "bin") {
+                std::fs::remove_file(file).unwrap();
+            }
+        }
+    }
+    assert_eq!(count("SELECT COUNT(*) FROM count_db.t").await, 5);
+    assert!(client.query("SELECT id FROM count_db.t").await.is_err());
+}
+
+#[tokio::test]
+async fn test_select_from_empty_table() {
+    let server = TestServer::start("empty_table", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    for sql in [
+        "CREATE DATABASE empty_db",
+        "CREATE TABLE empty_db.t (id UInt64, name String, d Date) ORDER BY id",
+        "CREATE TABLE empty_db.p (id UInt64, name String) ORDER BY id PARTITION BY name",
+        "INSERT INTO empty_db.p (id, name) VALUES (1, 'a'), (2, 'a')",
+        "ALTER TABLE empty_db.p DROP PARTITION 'a'",
+    ] {
+        client.query(sql).await.unwrap();
+    }
+
+    let id = ("id", ValueType::UInt64);
+    let name = ("name", ValueType::String);
+    let d = ("d", ValueType::Date);
+    // parts do not give column types, the schema does
+    for (sql, expected) in [
+        ("SELECT * FROM empty_db.t", vec![&id, &name, &d]),
+        (
+            "SELECT id, name FROM empty_db.t WHERE id > 1 ORDER BY id LIMIT 5",
+            vec![&id, &name],
+        ),
+        ("SELECT name, id FROM empty_db.p", vec![&name, &id]),
+    ] {
+        let table = client.query(sql).await.unwrap();
+        assert_eq!(table.row_count(), 0, "{sql}");
+        let columns: Vec<_> = table
+            .columns
+            .iter()
+            .map(|column| {
+                (
+                    column.column_def.name.as_str(),
+                    column.column_def.field_type.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(columns.iter().collect::<Vec<_>>(), expected, "{sql}");
+    }
+
+    // headers are printed without rows
+    let table = client
+        .query("SELECT id, name FROM empty_db.t FORMAT PRETTY")
+        .await
+        .unwrap();
+    let pretty = table
+        .rows()
+        .next()
+        .unwrap()
+        .get::<String>("pretty")
+        .unwrap();
+    assert_eq!(
+        pretty,
+        "\
++----+------+
+| id | name |
++----+------+
+"
+    );
+}
+
+#[tokio::test]
+async fn test_explain_analyze() {
+    let server = TestServer::start("explain_analyze", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    // small granules, so a part has many of them, and parts are kept apart
+    for sql in [
+        "CREATE DATABASE explain_db",
+        "CREATE TABLE explain_db.t (id UInt64, value Int64) ORDER BY id \
+         SETTINGS adaptive_index_granularity = 1, index_granularity_bytes = 1000",
+        "ALTER TABLE explain_db.t MODIFY SETTING no_merges = 1",
+    ] {
+        client.query(sql).await.unwrap();
+    }
+    for part in 0..3 {
+        let values: Vec<_> = (part * 1000..(part + 1) * 1000)
+            .map(|id| format!("({id}, {})", id % 7))
+            .collect();
+        client
+            .query(&format!(
+                "INSERT INTO explain_db.t (id, value) VALUES {}",
+                values.join(", ")
+            ))
+            .await
+            .unwrap();
+    }
+
+    // parts are listed in creation order, which is the order of their ids
+    let parts = client
+        .query("SELECT name, marks FROM system.parts WHERE database = 'explain_db'")
+        .await
+        .unwrap();
+    let parts: Vec<(String, u64)> = parts
+        .rows()
+        .map(|row| (row.get("name").unwrap(), row.get("marks").unwrap()))
+        .collect();
+    assert_eq!(parts.len(), 3);
+    assert!(parts.iter().all(|(_, marks)| *marks > 1), "{parts:?}");
+    let total_marks: u64 = parts.iter().map(|(_, marks)| marks).sum();
+
+    // step -> [granules, granules_pruned, granules_scanned, rows]
+    let explain = async |client: &mut Client, sql: &str| {
+        let table = client.query(sql).await.unwrap();
+        let columns = ["granules", "granules_pruned", "granules_scanned", "rows"];
+        table
+            .rows()
+            .map(|row| {
+                let step: String = row.get("step").unwrap();
+                let counts: Vec<Option<u64>> = columns
+                    .iter()
+                    .map(|column| row.get(column).unwrap())
+                    .collect();
+                (step, counts)
+            })
+            .collect::<Vec<_>>()
+    };
+    let step = |steps: &[(String, Vec<Option<u64>>)], name: &str| -> Vec<Option<u64>> {
+        steps
+            .iter()
+            .find(|(step, _)| step == name)
+            .unwrap_or_else(|| panic!("{name} not in {steps:?}"))
+            .1
+            .clone()
+    };
+
+    // no filter, every granule is scanned
+    let steps = explain(&mut client, "EXPLAIN ANALYZE SELECT id FROM explain_db.t").await;
+    let names: Vec<&str> = steps.iter().map(|(step, _)| step.as_str()).collect();
+    assert_eq!(names[..2], ["Parse and plan", "Scan (explain_db.t)"]);
+    assert_eq!(names[names.len() - 1], "Total");
+    assert_eq!(
+        step(&steps, "Total"),
+        [Some(total_marks), Some(0), Some(total_marks), Some(3000)]
+    );
+    for (name, marks) in &parts {
+        assert_eq!(
+            step(&steps, &format!("  Part {name}")),
+            [Some(*marks), Some(0), Some(*marks), Some(1000)]
+        );
+    }
+
+    // first two parts are pruned by their min/max, the last one is scanned completely
+    let steps = explain(
+        &mut client,
+        "EXPLAIN ANALYZE SELECT id FROM explain_db.t WHERE id >= 2000 ORDER BY id",
+    )
+    .await;
+    let last_marks = parts[2].1;
+    assert_eq!(
+        step(&steps, "Scan (explain_db.t)"),
+        [
+            Some(total_marks),
+            Some(total_marks - last_marks),
+            Some(last_marks),
+            Some(1000)
+        ]
+    );
+    for (name, marks) in &parts[..2] {
+        assert_eq!(
+            step(&steps, &format!("  Part {name}")),
+            [Some(*marks), Some(*marks), Some(0), Some(0)]
+        );
+    }
+    assert_eq!(step(&steps, "Post-processing")[3], Some(1000));
+
+    // primary key selects a few granules of the last part, filter keeps 10 rows of them
+    let steps = explain(
+        &mut client,
+        "EXPLAIN ANALYZE SELECT id FROM explain_db.t WHERE id >= 2500 AND id < 2510",
+    )
+    .await;
+    let [granules, pruned, scanned, rows] = step(&steps, &format!("  Part {}", parts[2].0))[..]
+    else {
+        unreachable!()
+    };
+    assert_eq!(granules, Some(last_marks));
+    assert!(
+        scanned.unwrap() >= 1 && scanned.unwrap() <= 2,
+        "{scanned:?}"
+    );
+    assert_eq!(pruned.unwrap() + scanned.unwrap(), last_marks);
+    assert_eq!(rows, Some(10));
+
+    // normal result is discarded, only statistics are returned
+    let table = client
+        .query("EXPLAIN ANALYZE SELECT id, value FROM explain_db.t")
+        .await
+        .unwrap();
+    assert!(table.column("id").is_none());
+
+    assert!(matches!(
+        client
+            .query("EXPLAIN ANALYZE INSERT INTO explain_db.t (id, value) VALUES (1, 1)")
+            .await,
+        Err(Error::Server(error)) if error.contains("EXPLAIN ANALYZE")
+    ));
+    let table = client.query("SELECT id FROM explain_db.t").await.unwrap();
+    assert_eq!(table.row_count(), 3000);
+}
+
+#[tokio::test]
+async fn test_order_by_key_merges_parts() {
+    let server = TestServer::start("order_by_key_merge", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    for sql in [
+        "CREATE DATABASE merge_order_db",
+        "CREATE TABLE merge_order_db.t (id UInt64, value Int64) ORDER BY id \
+         SETTINGS adaptive_index_granularity = 1, index_granularity_bytes = 1000",
+        "ALTER TABLE merge_order_db.t MODIFY SETTING no_merges = 1",
+    ] {
+        client.query(sql).await.unwrap();
+    }
+    // ids of parts interleave, so every part contributes to each range of the result
+    for part in 0..3 {
+        let values: Vec<_> = (0..1000)
+            .map(|row| row * 3 + part)
+            .map(|id| format!("({id}, {})", id % 7))
+            .collect();
+        client
+            .query(&format!(
+                "INSERT INTO merge_order_db.t (id, value) VALUES {}",
+                values.join(", ")
+            ))
+            .await
+            .unwrap();
+    }
+
+    let rows = async |client: &mut Client, sql: &str| -> Vec<(u64, i64)> {
+        let table = client.query(sql).await.unwrap();
+        table
+            .rows()
+            .map(|row| (row.get("id").unwrap(), row.get("value").unwrap()))
+            .collect()
+    };
+    let expected: Vec<(u64, i64)> = (0..3000).map(|id| (id, (id % 7) as i64)).collect();
+
+    let merged = rows(
+        &mut client,
+        "SELECT id, value FROM merge_order_db.t ORDER BY id LIMIT 10 OFFSET 1495",
+    )
+    .await;
+    assert_eq!(merged, expected[1495..1505]);
+
+    let merged = rows(
+        &mut client,
+        "SELECT id, value FROM merge_order_db.t WHERE value = 3 ORDER BY id LIMIT 20",
+    )
+    .await;
+    let filtered: Vec<_> = expected.iter().filter(|(_, value)| *value == 3).collect();
+    assert_eq!(merged.iter().collect::<Vec<_>>(), filtered[..20]);
+
+    // without LIMIT, and by a column, which is not the table key, rows are sorted in memory
+    let sorted = rows(
+        &mut client,
+        "SELECT id, value FROM merge_order_db.t ORDER BY id",
+    )
+    .await;
+    assert_eq!(sorted, expected);
+    let sorted = rows(
+        &mut client,
+        "SELECT id, value FROM merge_order_db.t ORDER BY value LIMIT 400",
+    )
+    .await;
+    assert_eq!(sorted.len(), 400);
+    assert!(sorted.iter().all(|(id, value)| *value == 0 && id % 7 == 0));
+
+    // only first granule of each part is read
+    let explain = client
+        .query("EXPLAIN ANALYZE SELECT id FROM merge_order_db.t ORDER BY id LIMIT 5")
+        .await
+        .unwrap();
+    let scan = explain
+        .rows()
+        .find(|row| row.get::<String>("step").unwrap() == "Scan (merge_order_db.t)")
+        .unwrap();
+    let granules: u64 = scan.get("granules").unwrap();
+    assert!(granules > 30, "{granules}");
+    assert_eq!(scan.get::<u64>("granules_scanned").unwrap(), 3);
+}
+
+#[tokio::test]
+async fn test_max_parts_per_table() {
+    let server = TestServer::start_with_config("max_parts", 0, "max_parts_per_table = 2\n").await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    for sql in [
+        "CREATE DATABASE parts_limit_db",
+        "CREATE TABLE parts_limit_db.t (id UInt64) ORDER BY id",
+        "CREATE TABLE parts_limit_db.other (id UInt64) ORDER BY id",
+        "ALTER TABLE parts_limit_db.t MODIFY SETTING no_merges = 1",
+        "INSERT INTO parts_limit_db.t (id) VALUES (1)",
+        "INSERT INTO parts_limit_db.t (id) VALUES (2)",
+    ] {
+        client.query(sql).await.unwrap();
+    }
+
+    let insert = "INSERT INTO parts_limit_db.t (id) VALUES (3)";
+    assert!(matches!(
+        client.query(insert).await,
+        Err(Error::Server(error)) if error.contains("Too many parts (2) in table (parts_limit_db.t)")
+    ));
+    // limit is per table
+    client
+        .query("INSERT INTO parts_limit_db.other (id) VALUES (1)")
+        .await
+        .unwrap();
+
+    // merge reduces parts, so inserts are accepted again
+    client
+        .query("ALTER TABLE parts_limit_db.t MODIFY SETTING no_merges = 0")
+        .await
+        .unwrap();
+    let mut inserted = false;
+    for _ in 0..100 {
+        if client.query(insert).await.is_ok() {
+            inserted = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(inserted);
+    let table = client
+        .query("SELECT id FROM parts_limit_db.t")
+        .await
+        .unwrap();
+    assert_eq!(table.row_count(), 3);
+}
+
+/// Returns `status`, `rows_before`, `rows_after`, `parts_before` and `parts_after` of
+/// `OPTIMIZE TABLE`.
+async fn optimize(client: &mut Client, table: &str) -> (String, u64, u64, u64, u64) {
+    let output = client
+        .query(&format!("OPTIMIZE TABLE {table}"))
+        .await
+        .unwrap();
+    let row = output.rows().next().unwrap();
+    (
+        row.get("status").unwrap(),
+        row.get("rows_before").unwrap(),
+        row.get("rows_after").unwrap(),
+        row.get("parts_before").unwrap(),
+        row.get("parts_after").unwrap(),
+    )
+}
+
+#[tokio::test]
+async fn test_optimize_table_merges_all_parts() {
+    // automatic merges do not interfere
+    let server = TestServer::start_without_merges("optimize", "").await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    for sql in [
+        "CREATE DATABASE optimize_db",
+        "CREATE TABLE optimize_db.t (id UInt64, month String) PARTITION BY month ORDER BY id",
+        "CREATE TABLE optimize_db.replacing (id UInt64, name String) \
+         ENGINE = ReplacingMergeTree ORDER BY id",
+        "INSERT INTO optimize_db.t (id, month) VALUES (1, 'jan'), (2, 'feb')",
+        "INSERT INTO optimize_db.t (id, month) VALUES (3, 'jan'), (4, 'feb')",
+        "INSERT INTO optimize_db.t (id, month) VALUES (5, 'jan'), (6, 'mar')",
+        "INSERT INTO optimize_db.replacing (id, name) VALUES (1, 'a'), (2, 'b')",
+        "INSERT INTO optimize_db.replacing (id, name) VALUES (2, 'c'), (3, 'd')",
+        "INSERT INTO optimize_db.replacing (id, name) VALUES (3, 'e'), (4, 'f')",
+    ] {
+        client.query(sql).await.unwrap();
+    }
+
+    // part per partition remains, single part of `mar` is not touched
+    assert_eq!(
+        optimize(&mut client, "optimize_db.t").await,
+        ("merged".to_string(), 6, 6, 6, 3)
+    );
+    let table = client
+        .query("SELECT id FROM optimize_db.t ORDER BY id")
+        .await
+        .unwrap();
+    let ids: Vec<u64> = table.rows().map(|row| row.get("id").unwrap()).collect();
+    assert_eq!(ids, [1, 2, 3, 4, 5, 6]);
+    assert_eq!(
+        optimize(&mut client, "optimize_db.t").await,
+        ("nothing to merge".to_string(), 6, 6, 3, 3)
+    );
+
+    // the last inserted row of a key is kept
+    assert_eq!(
+        optimize(&mut client, "optimize_db.replacing").await,
+        ("merged".to_string(), 6, 4, 3, 1)
+    );
+    let table = client
+        .query("SELECT id, name FROM optimize_db.replacing")
+        .await
+        .unwrap();
+    let mut rows: Vec<(u64, String)> = table
+        .rows()
+        .map(|row| (row.get("id").unwrap(), row.get("name").unwrap()))
+        .collect();
+    rows.sort();
+    let expected = [(1, "a"), (2, "c"), (3, "e"), (4, "f")];
+    assert_eq!(
+        rows,
+        expected.map(|(id, name)| (id, name.to_string())).to_vec()
+    );
+
+    // no merge is running
+    let merges = client.query("SELECT * FROM system.merges").await.unwrap();
+    assert_eq!(merges.row_count(), 0);
+    assert_eq!(merges.columns.len(), 9);
+
+    for sql in [
+        "OPTIMIZE TABLE optimize_db.missing",
+        "OPTIMIZE TABLE optimize_db.t PARTITION 'jan'",
+    ] {
+        assert!(client.query(sql).await.is_err(), "{sql}");
+    }
+}
+
+#[tokio::test]
+async fn test_optimize_table_without_waiting() {
+    let server =
+        TestServer::start_without_merges("optimize_timeout", "optimize_timeout_secs = 0\n").await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    for sql in [
+        "CREATE DATABASE optimize_timeout_db",
+        "CREATE TABLE optimize_timeout_db.t (id UInt64) ORDER BY id",
+        "INSERT INTO optimize_timeout_db.t (id) VALUES (1)",
+        "INSERT INTO optimize_timeout_db.t (id) VALUES (2)",
+        "INSERT INTO optimize_timeout_db.t (id) VALUES (3)",
+    ] {
+        client.query(sql).await.unwrap();
+    }
+
+    let (status, rows_before, _, parts_before, _) =
+        optimize(&mut client, "optimize_timeout_db.t").await;
+    assert_eq!(
+        (status.as_str(), rows_before, parts_before),
+        ("merge scheduled", 3, 3)
+    );
+
+    let mut part_count = 0;
+    for _ in 0..100 {
+        let table = client
+            .query("SELECT part_count FROM system.tables WHERE database = 'optimize_timeout_db'")
+            .await
+            .unwrap();
+        part_count = table.rows().next().unwrap().get("part_count").unwrap();
+        if part_count == 1 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(part_count, 1);
+    let table = client
+        .query("SELECT id FROM optimize_timeout_db.t")
+        .await
+        .unwrap();
+    assert_eq!(table.row_count(), 3);
+}
+
+#[tokio::test]
+async fn test_resample_by_interval() {
+    let server = TestServer::start("resample", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    for sql in [
+        "CREATE DATABASE resample_db",
+        "CREATE TABLE resample_db.metrics (ts Int64, host String, value Nullable(Int32)) \
+         ORDER BY ts SAMPLE BY ts",
+        "INSERT INTO resample_db.metrics (ts, host, value) VALUES \
+         (-10, 'a', 7), (0, 'a', 1), (1800, 'b', NULL), (3599, 'a', 5)",
+        "INSERT INTO resample_db.metrics (ts, host, value) VALUES \
+         (7300, 'b', 2), (7400, 'b', -4)",
+    ] {
+        client.query(sql).await.unwrap();
+    }
+
+    let table = client
+        .query("SHOW CREATE TABLE resample_db.metrics")
+        .await
+        .unwrap();
+    let statement: String = table.rows().next().unwrap().get("statement").unwrap();
+    assert!(
+        statement.ends_with("ORDER BY (ts) SAMPLE BY ts"),
+        "{statement}"
+    );
+
+    // bucket column is added in front, when it is not selected
+    let table = client
+        .query(
+            "SELECT count(*), count(value), sum(value) AS total, min(host), max(value) \
+             FROM resample_db.metrics RESAMPLE BY INTERVAL 1 HOUR",
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        column_names(&table),
+        [
+            "ts",
+            "count(*)",
+            "count(value)",
+            "total",
+            "min(host)",
+            "max(value)"
+        ]
+    );
+    let rows: Vec<_> = table
+        .rows()
+        .map(|row| {
+            (
+                row.get::<i64>("ts").unwrap(),
+                row.get::<u64>("count(*)").unwrap(),
+                row.get::<u64>("count(value)").unwrap(),
+                row.get::<Option<i64>>("total").unwrap(),
+                row.get::<String>("min(host)").unwrap(),
+                row.get::<Option<i32>>("max(value)").unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        rows,
+        [
+            (-3600, 1, 1, Some(7), "a".to_string(), Some(7)),
+            (0, 3, 2, Some(6), "a".to_string(), Some(5)),
+            (7200, 2, 2, Some(-2), "b".to_string(), Some(2)),
+        ]
+    );
+
+    // filter applies before grouping
+    let table = client
+        .query(
+            "SELECT max(value), ts FROM resample_db.metrics WHERE host = 'b' \
+             RESAMPLE BY INTERVAL 2 MINUTE",
+        )
+        .await
+        .unwrap();
+    assert_eq!(column_names(&table), ["max(value)", "ts"]);
+    let buckets: Vec<_> = table
+        .rows()
+        .map(|row| row.get::<i64>("ts").unwrap())
+        .collect();
+    assert_eq!(buckets, [1800, 7200, 7320]);
+
+    client
+        .query("CREATE TABLE resample_db.plain (ts Int64) ORDER BY ts")
+        .await
+        .unwrap();
+    let error = client
+        .query("SELECT count(*) FROM resample_db.plain RESAMPLE BY INTERVAL 1 DAY")
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("SAMPLE BY"), "{error}");
+}
+
+#[tokio::test]
+async fn test_resample_by_date_time() {
+    let server = TestServer::start("resample_time", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    for sql in [
+        "CREATE DATABASE resample_time_db",
+        "CREATE TABLE resample_time_db.metrics (at DateTime, value UInt32) ORDER BY at \
+         SAMPLE BY at",
+        "INSERT INTO resample_time_db.metrics (at, value) VALUES \
+         ('2024-01-01 00:10:00', 1), ('2024-01-01 00:59:59', 2), ('2024-01-01 02:00:00', 4), \
+         ('2024-01-02 23:00:00', 8)",
+        "CREATE TABLE resample_time_db.daily (day Date, value UInt32) ORDER BY day SAMPLE BY day",
+        "INSERT INTO resample_time_db.daily (day, value) VALUES \
+         ('2024-01-01', 1), ('2024-01-07', 2), ('2024-01-08', 4)",
+    ] {
+        client.query(sql).await.unwrap();
+    }
+
+    let table = client
+        .query(
+            "SELECT at, sum(value) AS total FROM resample_time_db.metrics \
+             RESAMPLE BY INTERVAL 1 HOUR",
+        )
+        .await
+        .unwrap();
+    let rows: Vec<(String, u64)> = table
+        .rows()
+        .map(|row| (row.get("at").unwrap(), row.get("total").unwrap()))
+        .collect();
+    assert_eq!(
+        rows,
+        [
+            ("2024-01-01 00:00:00".to_string(), 3),
+            ("2024-01-01 02:00:00".to_string(), 4),
+            ("2024-01-02 23:00:00".to_string(), 8),
+        ]
+    );
+
+    let table = client
+        .query(
+            "SELECT at, count(*) FROM resample_time_db.metrics WHERE value < 8 \
+             RESAMPLE BY INTERVAL 1 DAY",
+        )
+        .await
+        .unwrap();
+    let rows: Vec<(String, u64)> = table
+        .rows()
+        .map(|row| (row.get("at").unwrap(), row.get("count(*)").unwrap()))
+        .collect();
+    assert_eq!(rows, [("2024-01-01 00:00:00".to_string(), 3)]);
+
+    // 1970-01-01 is a Thursday, so weeks start on Thursdays
+    let table = client
+        .query(
+            "SELECT day, sum(value) AS total FROM resample_time_db.daily \
+             RESAMPLE BY INTERVAL 1 WEEK",
+        )
+        .await
+        .unwrap();
+    let rows: Vec<(String, u64)> = table
+        .rows()
+        .map(|row| (row.get("day").unwrap(), row.get("total").unwrap()))
+        .collect();
+    assert_eq!(
+        rows,
+        [("2023-12-28".to_string(), 1), ("2024-01-04".to_string(), 6),]
+    );
+}
+
+#[tokio::test]
+async fn test_flatten_array_column() {
+    let server = TestServer::start("flatten", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    for sql in [
+        "CREATE DATABASE flatten_db",
+        "CREATE TABLE flatten_db.t (id UInt64, tags Array(String)) ORDER BY id",
+        "INSERT INTO flatten_db.t (id, tags) VALUES \
+         (1, ['a', 'b']), (2, []), (3, ['c']), (4, NULL)",
+    ] {
+        client.query(sql).await.unwrap();
+    }
+
+    let rows = |table: OutputTable| -> Vec<(u64, u64, String)> {
+        table
+            .rows()
+            .map(|row| {
+                (
+                    row.get("id").unwrap(),
+                    row.get("index").unwrap(),
+                    row.get("value").unwrap(),
+                )
+            })
+            .collect()
+    };
+    let expected = [
+        (1, 0, "a".to_string()),
+        (1, 1, "b".to_string()),
+        (3, 0, "c".to_string()),
+    ];
+    for sql in [
+        "SELECT id, index, value FROM flatten_db.t, FLATTEN(t.tags) ORDER BY id, index",
+        "SELECT id, index, value FROM flatten_db.t, flatten(tags) ORDER BY id, index",
+        "SELECT id, index, value FROM flatten_db.t AS x, LATERAL FLATTEN(input => x.tags) \
+         ORDER BY id, index",
+    ] {
+        let table = client.query(sql).await.unwrap();
+        assert_eq!(rows(table), expected, "{sql}");
+    }
+
+    // other columns are duplicated, and outer clauses apply to flattened rows
+    let table = client
+        .query("SELECT * FROM flatten_db.t, FLATTEN(t.tags) WHERE value != 'a' LIMIT 1")
+        .await
+        .unwrap();
+    assert_eq!(column_names(&table), ["id", "tags", "index", "value"]);
+    let row = table.rows().next().unwrap();
+    assert_eq!(row.get::<u64>("id").unwrap(), 1);
+    assert_eq!(row.get::<Vec<String>>("tags").unwrap(), ["a", "b"]);
+    assert_eq!(row.get::<String>("value").unwrap(), "b");
+
+    for sql in [
+        "SELECT id FROM flatten_db.t, FLATTEN(id)",
+        "SELECT id FROM flatten_db.t, FLATTEN(other.tags)",
+        "SELECT id FROM flatten_db.t, FLATTEN(t.tags) AS f",
+    ] {
+        assert!(client.query(sql).await.is_err(), "{sql}");
+    }
+}
+
+#[tokio::test]
+async fn test_write_responses_report_rows_and_objects() {
+    let server = TestServer::start("write_responses", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    // first column stays "OK", the second one names the object
+    for (sql, object) in [
+        ("CREATE DATABASE response_db", "response_db"),
+        (
+            "CREATE TABLE response_db.replacing (id UInt64, name String) \
+             ENGINE = ReplacingMergeTree ORDER BY id",
+            "response_db.replacing",
+        ),
+        (
+            "CREATE TABLE response_db.t (id UInt64, month String) PARTITION BY month ORDER BY id",
+            "response_db.t",
+        ),
+    ] {
+        let table = client.query(sql).await.unwrap();
+        assert_eq!(
+            table.columns[0].data,
+            [Value::String("OK".to_string())],
+            "{sql}"
+        );
+        assert_eq!(
+            table.column("object").unwrap().data,
+            [Value::String(object.to_string())],
+            "{sql}"
+        );
+    }
+
+    let rows_written = |table: &OutputTable| {
+        assert_eq!(table.columns[0].data, [Value::String("OK".to_string())]);
+        table.column("rows_written").unwrap().data[0].clone()
+    };
+    // duplicates of the key within one insert are collapsed by the engine
+    let table = client
+        .query(
+            "INSERT INTO response_db.replacing (id, name) VALUES \
+             (1, 'a'), (2, 'b'), (1, 'c'), (3, 'd'), (2, 'e')",
+        )
+        .await
+        .unwrap();
+    assert_eq!(rows_written(&table), Value::UInt64(3));
+    let table = client
+        .query("SELECT count(*) FROM response_db.replacing")
+        .await
+        .unwrap();
+    assert_eq!(table.columns[0].data, [Value::UInt64(3)]);
+
+    // rows of all partitions are counted
+    let table = client
+        .query("INSERT INTO response_db.t (id, month) VALUES (1, 'jan'), (2, 'feb'), (3, 'jan')")
+        .await
+        .unwrap();
+    assert_eq!(rows_written(&table), Value::UInt64(3));
+
+    for (sql, object) in [
+        (
+            "ALTER TABLE response_db.t DROP PARTITION 'jan'",
+            "response_db.t/jan",
+        ),
+        ("DROP TABLE response_db.t", "response_db.t"),
+        ("DROP DATABASE response_db", "response_db"),
+    ] {
+        let table = client.query(sql).await.unwrap();
+        assert_eq!(
+            table.column("object").unwrap().data,
+            [Value::String(object.to_string())],
+            "{sql}"
+        );
+    }
+
+    let table = client
+        .query("CREATE DATABASE IF NOT EXISTS other_db")
+        .await
+        .unwrap();
+    assert!(table.column("object").is_some());
+    // existing database is skipped, so there is no object
+    let table = client
+        .query("CREATE DATABASE IF NOT EXISTS other_db")
+        .await
+        .unwrap();
+    assert_eq!(
+        table.column("OK").unwrap().data,
+        [Value::String("OK".to_string())]
+    );
+    assert!(table.column("object").is_none());
+}
+
+#[tokio::test]
+async fn test_uuid_filters_prune_granules() {
+    let server = TestServer::start("uuid_filters", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+    let uid = |n: u64| format!("00000000-0000-0000-0000-{n:012}");
+
+    // 4 granules of 4 rows, inserted in reverse order, `other` descends as `uid` ascends
+    let values: Vec<String> = (0..16)
+        .rev()
+        .map(|n| format!("('{}', {n}, '{}', {})", uid(n), uid(15 - n), n / 8))
+        .collect();
+    let tenant_values: Vec<String> = (0..16)
+        .map(|n| format!("('{}', {n}, {})", uid(15 - n), n / 8))
+        .collect();
+    for sql in [
+        "CREATE DATABASE uuid_db".to_string(),
+        "CREATE TABLE uuid_db.t (uid UUID, n UInt64, other UUID, tenant UInt8) ORDER BY uid \
+         SETTINGS index_granularity = 4"
+            .to_string(),
+        // key columns are in other order than columns of the table
+        "CREATE TABLE uuid_db.by_tenant (uid UUID, n UInt64, tenant UInt8) \
+         ORDER BY (tenant, uid) SETTINGS index_granularity = 4"
+            .to_string(),
+        format!(
+            "INSERT INTO uuid_db.t (uid, n, other, tenant) VALUES {}",
+            values.join(", ")
+        ),
+        format!(
+            "INSERT INTO uuid_db.by_tenant (uid, n, tenant) VALUES {}",
+            tenant_values.join(", ")
+        ),
+    ] {
+        client.query(&sql).await.unwrap();
+    }
+
+    let numbers = async |client: &mut Client, sql: &str| -> Vec<u64> {
+        let table = client.query(sql).await.unwrap();
+        table.rows().map(|row| row.get("n").unwrap()).collect()
+    };
+    let pruned = async |client: &mut Client, sql: &str| -> u64 {
+        let table = client
+            .query(&format!("EXPLAIN ANALYZE {sql}"))
+            .await
+            .unwrap();
+        let total = table
+            .rows()
+            .find(|row| row.get::<String>("step").unwrap() == "Total")
+            .unwrap();
+        total.get("granules_pruned").unwrap()
+    };
+
+    for (filter, expected, expected_pruned) in [
+        (format!("uid = '{}'", uid(5)), vec![5], 3),
+        (format!("'{}' = uid", uid(5)), vec![5], 3),
+        (
+            format!("uid >= '{}' AND uid < '{}'", uid(10), uid(13)),
+            vec![10, 11, 12],
+            2,
+        ),
+        (format!("'{}' > uid", uid(3)), vec![0, 1, 2], 3),
+        (
+            format!("uid IN ('{}', '{}')", uid(1), uid(14)),
+            vec![1, 14],
+            2,
+        ),
+        (format!("other = '{}'", uid(15)), vec![0], 0),
+    ] {
+        let sql = format!("SELECT n FROM uuid_db.t WHERE {filter} ORDER BY n");
+        assert_eq!(numbers(&mut client, &sql).await, expected, "{sql}");
+        assert_eq!(pruned(&mut client, &sql).await, expected_pruned, "{sql}");
+    }
+
+    let sql = "SELECT n FROM uuid_db.t ORDER BY uid";
+    assert_eq!(numbers(&mut client, sql).await, (0..16).collect::<Vec<_>>());
+    let sql = "SELECT n FROM uuid_db.t ORDER BY other";
+    assert_eq!(
+        numbers(&mut client, sql).await,
+        (0..16).rev().collect::<Vec<_>>()
+    );
+
+    // `uid` of every tenant is found, though it is not the first key column
+    let sql = format!(
+        "SELECT n FROM uuid_db.by_tenant WHERE uid = '{}' ORDER BY n",
+        uid(10)
+    );
+    assert_eq!(numbers(&mut client, &sql).await, [5]);
+    let sql = format!(
+        "SELECT n FROM uuid_db.by_tenant WHERE tenant = 1 AND uid <= '{}' ORDER BY n",
+        uid(2)
+    );
+    assert_eq!(numbers(&mut client, &sql).await, [13, 14, 15]);
+
+    for sql in [
+        "SELECT n FROM uuid_db.t WHERE uid = n",
+        "SELECT n FROM uuid_db.t WHERE uid = 'not a uuid'",
+    ] {
+        assert!(
+            matches!(client.query(sql).await, Err(Error::Server(_))),
+            "{sql}"
+        );
+    }
+    let sql = "SELECT n FROM uuid_db.t WHERE uid = other ORDER BY n";
+    assert_eq!(numbers(&mut client, sql).await, Vec::<u64>::new());
+}
+
+#[tokio::test]
+async fn test_date_conversion_functions() {
+    let server = TestServer::start("date_functions", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    for sql in [
+        "CREATE DATABASE date_db",
+        "CREATE TABLE date_db.events (id UInt64, ts UInt32, day Date, at DateTime, text String) \
+         ORDER BY id",
+        // 2023-12-31 23:59:59, 2024-01-01 00:00:00 and 2024-02-29 12:30:05
+        "INSERT INTO date_db.events (id, ts, day, at, text) VALUES \
+         (1, 1704067199, '2023-12-31', '2023-12-31 23:59:59', '2023-12-31 23:59:59'), \
+         (2, 1704067200, '2024-01-01', '2024-01-01', '2024-01-01'), \
+         (3, 1709209805, '2024-02-29', 1709209805, '2024-02-29 12:30:05')",
+    ] {
+        client.query(sql).await.unwrap();
+    }
+
+    let strings = |table: &OutputTable, column: &str| -> Vec<String> {
+        table.rows().map(|row| row.get(column).unwrap()).collect()
+    };
+
+    let table = client
+        .query(
+            "SELECT toDateTime(ts), toDate(ts), at, day, toDate(at), toDateTime(day), \
+             toDateTime(text) FROM date_db.events ORDER BY id",
+        )
+        .await
+        .unwrap();
+    let times = [
+        "2023-12-31 23:59:59",
+        "2024-01-01 00:00:00",
+        "2024-02-29 12:30:05",
+    ];
+    let dates = ["2023-12-31", "2024-01-01", "2024-02-29"];
+    assert_eq!(strings(&table, "toDateTime(ts)"), times);
+    assert_eq!(strings(&table, "at"), times);
+    assert_eq!(strings(&table, "toDateTime(text)"), times);
+    assert_eq!(strings(&table, "toDate(ts)"), dates);
+    assert_eq!(strings(&table, "day"), dates);
+    assert_eq!(strings(&table, "toDate(at)"), dates);
+    assert_eq!(
+        strings(&table, "toDateTime(day)"),
+        [
+            "2023-12-31 00:00:00",
+            "2024-01-01 00:00:00",
+            "2024-02-29 00:00:00"
+        ]
+    );
+
+    let ids = async |client: &mut Client, filter: &str| -> Vec<u64> {
+        let table = client
+            .query(&format!(
+                "SELECT id FROM date_db.events WHERE {filter} ORDER BY id"
+            ))
+            .await
+            .unwrap();
+        table.rows().map(|row| row.get("id").unwrap()).collect()
+    };
+    for (filter, expected) in [
+        ("toDateTime(ts) >= '2024-01-01 00:00:00'", vec![2, 3]),
+        ("toDateTime(ts) < '2024-01-01'", vec![1]),
+        ("toDate(ts) = '2024-02-29'", vec![3]),
+        ("at BETWEEN '2024-01-01' AND '2024-02-01'", vec![2]),
+        ("day > '2024-01-01'", vec![3]),
+        ("toDate(at) IN ('2023-12-31', '2024-02-29')", vec![1, 3]),
+    ] {
+        assert_eq!(ids(&mut client, filter).await, expected, "{filter}");
+    }
+
+    // dates are sorted as dates, not as strings
+    let table = client
+        .query("SELECT id FROM date_db.events ORDER BY toDateTime(text)")
+        .await
+        .unwrap();
+    let sorted: Vec<u64> = table.rows().map(|row| row.get("id").unwrap()).collect();
+    assert_eq!(sorted, [1, 2, 3]);
+
+    for sql in [
+        "INSERT INTO date_db.events (id, day) VALUES (4, '2024-02-30')",
+        "SELECT id FROM date_db.events WHERE toDateTime(ts) > 'tomorrow'",
+        // `id - 2` wraps around for the first row, which is out of range of DateTime
+        "SELECT toDateTime(id - 2) FROM date_db.events",
+        "SELECT toDate(toDate(ts), 1) FROM date_db.events",
+    ] {
+        assert!(
+            matches!(client.query(sql).await, Err(Error::Server(_))),
+            "{sql}"
+        );
+    }
+    client
+        .query("INSERT INTO date_db.events (id, text) VALUES (5, 'soon')")
+        .await
+        .unwrap();
+    let result = client
+        .query("SELECT toDate(text) FROM date_db.events")
+        .await;
+    assert!(
+        matches!(&result, Err(Error::Server(error)) if error.contains("'soon'")),
+        "{result:?}"
+    );
+}
+
+#[tokio::test]
+async fn test_insert_on_duplicate_key_update() {
+    let server = TestServer::start("on_duplicate", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    for sql in [
+        "CREATE DATABASE upsert_db",
+        "CREATE TABLE upsert_db.t (id UInt64, month String, hits UInt64, note String) \
+         PARTITION BY month ORDER BY id",
+        "ALTER TABLE upsert_db.t MODIFY SETTING no_merges = 1",
+        "CREATE TABLE upsert_db.replacing (id UInt64, hits UInt64) \
+         ENGINE = ReplacingMergeTree ORDER BY id",
+        "INSERT INTO upsert_db.t (id, month, hits, note) VALUES \
+         (1, '2024-01', 1, 'a'), (2, '2024-01', 1, 'b'), (3, '2024-02', 1, 'c')",
+        "INSERT INTO upsert_db.t (id, month, hits, note) VALUES (4, '2024-01', 1, 'd')",
+    ] {
+        client.query(sql).await.unwrap();
+    }
+
+    let rows = async |client: &mut Client| -> Vec<(u64, u64, String)> {
+        let table = client
+            .query("SELECT id, hits, note FROM upsert_db.t ORDER BY id")
+            .await
+            .unwrap();
+        table
+            .rows()
+            .map(|row| {
+                (
+                    row.get("id").unwrap(),
+                    row.get("hits").unwrap(),
+                    row.get("note").unwrap(),
+                )
+            })
+            .collect()
+    };
+    let parts = async |client: &mut Client, month: &str| -> usize {
+        let table = client
+            .query(&format!(
+                "SELECT name FROM system.parts WHERE database = 'upsert_db' AND table = 't' \
+                 AND partition = '{month}'"
+            ))
+            .await
+            .unwrap();
+        table.row_count()
+    };
+
+    assert_eq!(parts(&mut client, "2024-01").await, 2);
+
+    // existing key 2 is updated, new key 5 is inserted, repeated key 5 updates the new row
+    let table = client
+        .query(
+            "INSERT INTO upsert_db.t (id, month, hits, note) VALUES \
+             (2, '2024-01', 7, 'x'), (5, '2024-01', 1, 'e'), (5, '2024-01', 8, 'y') \
+             ON DUPLICATE KEY UPDATE hits = 10, note = 'updated'",
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        table.column("rows_written").unwrap().data,
+        [Value::UInt64(2)]
+    );
+    assert_eq!(
+        rows(&mut client).await,
+        [
+            (1, 1, "a".to_string()),
+            (2, 10, "updated".to_string()),
+            (3, 1, "c".to_string()),
+            (4, 1, "d".to_string()),
+            (5, 10, "updated".to_string()),
+        ]
+    );
+    // parts of the updated partition are replaced by a single part, the other one is kept
+    assert_eq!(parts(&mut client, "2024-01").await, 1);
+    assert_eq!(parts(&mut client, "2024-02").await, 1);
+
+    // rows with new keys only are inserted as a new part
+    client
+        .query(
+            "INSERT INTO upsert_db.t (id, month, hits, note) VALUES (6, '2024-02', 1, 'f') \
+             ON DUPLICATE KEY UPDATE hits = 10",
+        )
+        .await
+        .unwrap();
+    assert_eq!(parts(&mut client, "2024-02").await, 2);
+    assert_eq!(rows(&mut client).await.len(), 6);
+
+    // expressions use the existing row, a repeated key increments it again
+    client
+        .query(
+            "INSERT INTO upsert_db.t (id, month, hits, note) VALUES \
+             (1, '2024-01', 1, 'z'), (1, '2024-01', 1, 'z') \
+             ON DUPLICATE KEY UPDATE hits = hits + 1",
+        )
+        .await
+        .unwrap();
+    assert_eq!(rows(&mut client).await[0], (1, 3, "a".to_string()));
+
+    // `ReplacingMergeTree` keeps the inserted row
+    for sql in [
+        "INSERT INTO upsert_db.replacing (id, hits) VALUES (1, 1)",
+        "INSERT INTO upsert_db.replacing (id, hits) VALUES (1, 2) \
+         ON DUPLICATE KEY UPDATE hits = 10",
+        "OPTIMIZE TABLE upsert_db.replacing FINAL",
+    ] {
+        client.query(sql).await.unwrap();
+    }
+    let table = client
+        .query("SELECT hits FROM upsert_db.replacing")
+        .await
+        .unwrap();
+    assert_eq!(table.column("hits").unwrap().data, [Value::UInt64(2)]);
+
+    for (sql, expected) in [
+        ("id = 1", "PRIMARY KEY column id"),
+        ("hits = 'x'", "could not use ('x')"),
+        ("hits = note", "could not use (note)"),
+        ("missing = 1", "missing"),
+    ] {
+        let error = client
+            .query(&format!(
+                "INSERT INTO upsert_db.t (id, month, hits, note) VALUES (1, '2024-01', 1, 'a') \
+                 ON DUPLICATE KEY UPDATE {sql}"
+            ))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains(expected), "{sql}: {error}");
+    }
+}
+
+#[tokio::test]
+async fn test_max_memory_per_query() {
+    const LIMIT: u64 = 1024 * 1024;
+    let server = TestServer::start_with_config(
+        "memory_limit",
+        0,
+        &format!("max_memory_per_query = {LIMIT}"),
+    )
+    .await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    for sql in [
+        "CREATE DATABASE memory_db",
+        "CREATE TABLE memory_db.big (id UInt64, payload String) ORDER BY id",
+        "CREATE TABLE memory_db.small (id UInt64, payload String) ORDER BY id",
+        "INSERT INTO memory_db.small (id, payload) VALUES (1, 'a'), (2, 'b'), (3, 'c')",
+    ] {
+        client.query(sql).await.unwrap();
+    }
+    for batch in 0..10 {
+        let values: Vec<_> = (0..10_000)
+            .map(|row| format!("({}, 'payload-{row}')", batch * 10_000 + row))
+            .collect();
+        client
+            .query(&format!(
+                "INSERT INTO memory_db.big (id, payload) VALUES {}",
+                values.join(", ")
+            ))
+            .await
+            .unwrap();
+    }
+
+    // scan of the whole table is stopped, once its rows exceed the limit
+    for sql in [
+        "SELECT id, payload FROM memory_db.big",
+        "SELECT id, payload FROM memory_db.big ORDER BY payload",
+    ] {
+        let started = Instant::now();
+        let error = client.query(sql).await.unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains(&format!("above max_memory_per_query of {LIMIT} bytes")),
+            "{sql}: {error}"
+        );
+        assert!(started.elapsed() < Duration::from_secs(10), "{sql}");
+    }
+
+    // queries under the limit succeed, also with the same connection
+    let table = client
+        .query("SELECT id, payload FROM memory_db.small ORDER BY payload")
+        .await
+        .unwrap();
+    assert_eq!(table.row_count(), 3);
+    let table = client
+        .query("SELECT id FROM memory_db.big WHERE id < 10")
+        .await
+        .unwrap();
+    assert_eq!(table.row_count(), 10);
+
+    let table = client
+        .query("EXPLAIN ANALYZE SELECT id, payload FROM memory_db.small")
+        .await
+        .unwrap();
+    let peak_memory: Vec<Option<u64>> = table
+        .rows()
+        .map(|row| row.get("peak_memory_bytes").unwrap())
+        .collect();
+    let (total, steps) = peak_memory.split_last().unwrap();
+    assert!(steps.iter().all(Option::is_none), "{peak_memory:?}");
+    assert!(
+        total.is_some_and(|bytes| bytes > 0 && bytes < LIMIT),
+        "{peak_memory:?}"
+    );
+}
+
+#[tokio::test]
+async fn test_skipped_parts_release_memory() {
+    const LIMIT: u64 = 400_000;
+    let server =
+        TestServer::start_without_merges("skip_memory", &format!("max_memory_per_query = {LIMIT}"))
+            .await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+
+    client
+        .query("CREATE DATABASE skip_memory_db")
+        .await
+        .unwrap();
+    client
+        .query("CREATE TABLE skip_memory_db.t (id UInt64) ORDER BY id SETTINGS index_granularity = 100")
+        .await
+        .unwrap();
+    for part in 0..10 {
+        let values: Vec<_> = (0..2000)
+            .map(|row| format!("({})", part * 2000 + row))
+            .collect();
+        client
+            .query(&format!(
+                "INSERT INTO skip_memory_db.t (id) VALUES {}",
+                values.join(", ")
+            ))
+            .await
+            .unwrap();
+    }
+
+    // the last granule of all parts but one is garbage under a valid checksum, so the parts
+    // fail after most of their rows were read
+    let table_dir = server.dir.join("db").join("skip_memory_db").join("t");
+    let part_dirs: Vec<PathBuf> = std::fs::read_dir(&table_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.join("id.bin").is_file())
+        .collect();
+    assert_eq!(part_dirs.len(), 10);
+    for part_dir in &part_dirs[1..] {
+        let marks = std::fs::read(part_dir.join("id.mrk")).unwrap();
+        let last_mark = &marks[marks.len() - 4 - 16..marks.len() - 4];
+        let start = u64::from_le_bytes(last_mark[..8].try_into().unwrap()) as usize;
+        let end = u64::from_le_bytes(last_mark[8..].try_into().unwrap()) as usize;
+
+        edit_checksummed_file(&part_dir.join("id.bin"), |bytes| {
+            bytes[start..end].fill(0xFF)
+        });
+    }
+
+    // rows of skipped parts would exceed the limit together, unless their memory is released
+    let table = client
+        .query("SELECT id FROM skip_memory_db.t SETTINGS skip_unreadable_parts = 1, scan_chunk_granules = 20")
+        .await
+        .unwrap();
+    assert_eq!(table.skipped_parts, Some(9));
+    assert_eq!(table.row_count(), 2000);
+}
+
+#[tokio::test]
+async fn test_nullable_key() {
+    let server = TestServer::start("nullable_key", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+    client.query("CREATE DATABASE key_db").await.unwrap();
+
+    // key columns are NOT NULL, unless nullable keys are allowed
+    let error = client
+        .query("CREATE TABLE key_db.t (k Nullable(UInt64), v UInt64) ORDER BY (k, v)")
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("allow_nullable_key"), "{error}");
+    client
+        .query("CREATE TABLE key_db.implicit (k UInt64, v UInt64) ORDER BY k")
+        .await
+        .unwrap();
+    for sql in [
+        "INSERT INTO key_db.implicit (k, v) VALUES (1, 1), (NULL, 2)",
+        "INSERT INTO key_db.implicit (v) VALUES (3)",
+    ] {
+        assert!(client.query(sql).await.is_err(), "{sql}");
+    }
+
+    client
+        .query(
+            "CREATE TABLE key_db.t (k Nullable(UInt64), v UInt64) ORDER BY (k, v) \
+             SETTINGS index_granularity = 2, allow_nullable_key = 1",
+        )
+        .await
+        .unwrap();
+    client
+        .query("ALTER TABLE key_db.t MODIFY SETTING no_merges = 1")
+        .await
+        .unwrap();
+    for sql in [
+        "INSERT INTO key_db.t (k, v) VALUES (NULL, 1), (3, 2), (1, 3), (NULL, 4), (2, 5)",
+        "INSERT INTO key_db.t (k, v) VALUES (2, 6), (NULL, 7)",
+        "INSERT INTO key_db.t (v) VALUES (8)",
+    ] {
+        client.query(sql).await.unwrap();
+    }
+
+    // `NULL` keys are ordered last: when parts are read in order, merged and pruned
+    let keys = |table: OutputTable| -> Vec<(Option<u64>, u64)> {
+        table
+            .rows()
+            .map(|row| (row.get("k").unwrap(), row.get("v").unwrap()))
+            .collect()
+    };
+    let ordered = vec![
+        (Some(1), 3),
+        (Some(2), 5),
+        (Some(2), 6),
+        (Some(3), 2),
+        (None, 1),
+        (None, 4),
+        (None, 7),
+        (None, 8),
+    ];
+    let query = "SELECT k, v FROM key_db.t ORDER BY k, v";
+    assert_eq!(keys(client.query(query).await.unwrap()), ordered);
+    let table = client
+        .query("SELECT k, v FROM key_db.t WHERE k >= 2 ORDER BY k, v")
+        .await
+        .unwrap();
+    assert_eq!(keys(table), ordered[1..4]);
+
+    client
+        .query("ALTER TABLE key_db.t MODIFY SETTING no_merges = 0")
+        .await
+        .unwrap();
+    let (status, ..) = optimize(&mut client, "key_db.t").await;
+    assert_eq!(status, "merged");
+    assert_eq!(keys(client.query(query).await.unwrap()), ordered);
+    let table = client
+        .query("SELECT k, v FROM key_db.t WHERE k = 3")
+        .await
+        .unwrap();
+    assert_eq!(keys(table), [(Some(3), 2)]);
+
+    let table = client.query("SHOW CREATE TABLE key_db.t").await.unwrap();
+    let statement: String = table.rows().next().unwrap().get("statement").unwrap();
+    assert!(
+        statement.ends_with("SETTINGS index_granularity = 2, allow_nullable_key = 1"),
+        "{statement}"
+    );
+}
+
+#[tokio::test]
+async fn test_date_extraction_functions() {
+    let server = TestServer::start("date_extraction", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+    for sql in [
+        "CREATE DATABASE parts_db",
+        "CREATE TABLE parts_db.events (id UInt64, day Date, at DateTime) ORDER BY id",
+        "INSERT INTO parts_db.events (id, day, at) VALUES \
+         (1, '2023-12-31', '2023-12-31 23:59:59'), \
+         (2, '2024-01-01', '2024-01-01 08:05:00'), \
+         (3, '2024-02-29', '2024-02-29 12:30:05')",
+    ] {
+        client.query(sql).await.unwrap();
+    }
+
+    let table = client
+        .query(
+            "SELECT toYear(at), toMonth(at), toDayOfMonth(day), toDayOfWeek(day), toHour(at), \
+             toMinute(at), toSecond(at), toYYYYMM(day), toYYYYMMDD(at) FROM parts_db.events \
+             ORDER BY id",
+        )
+        .await
+        .unwrap();
+    type DateParts = (u16, u8, u8, u8, u8, u8, u8, u32, u32);
+    let rows: Vec<DateParts> = table
+        .rows()
+        .map(|row| {
+            (
+                row.get("toYear(at)").unwrap(),
+                row.get("toMonth(at)").unwrap(),
+                row.get("toDayOfMonth(day)").unwrap(),
+                row.get("toDayOfWeek(day)").unwrap(),
+                row.get("toHour(at)").unwrap(),
+                row.get("toMinute(at)").unwrap(),
+                row.get("toSecond(at)").unwrap(),
+                row.get("toYYYYMM(day)").unwrap(),
+                row.get("toYYYYMMDD(at)").unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        rows,
+        [
+            (2023, 12, 31, 7, 23, 59, 59, 202312, 20231231),
+            (2024, 1, 1, 1, 8, 5, 0, 202401, 20240101),
+            (2024, 2, 29, 4, 12, 30, 5, 202402, 20240229),
+        ]
+    );
+
+    for (filter, expected) in [
+        ("toYear(day) = 2024", vec![2, 3]),
+        ("toYYYYMM(at) = 202402", vec![3]),
+        ("toDayOfWeek(at) >= 6", vec![1]),
+        ("toHour(at) < 12", vec![2]),
+    ] {
+        let table = client
+            .query(&format!(
+                "SELECT id FROM parts_db.events WHERE {filter} ORDER BY id"
+            ))
+            .await
+            .unwrap();
+        let ids: Vec<u64> = table.rows().map(|row| row.get("id").unwrap()).collect();
+        assert_eq!(ids, expected, "{filter}");
+    }
+
+    let result = client.query("SELECT toYear(id) FROM parts_db.events").await;
+    assert!(matches!(result, Err(Error::Server(_))), "{result:?}");
+}
+
+#[tokio::test]
+async fn test_date_arithmetic() {
+    let server = TestServer::start("date_arithmetic", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+    for sql in [
+        "CREATE DATABASE shift_db",
+        "CREATE TABLE shift_db.events (id UInt64, day Date, at DateTime) ORDER BY id",
+        "INSERT INTO shift_db.events (id, day, at) VALUES \
+         (1, '2020-01-31', '2020-01-31 10:00:00'), \
+         (2, '2024-02-29', '2024-02-29 23:30:00'), \
+         (3, '2100-03-01', '2100-03-01 00:00:00')",
+    ] {
+        client.query(sql).await.unwrap();
+    }
+
+    let strings = |table: &OutputTable, column: &str| -> Vec<String> {
+        table.rows().map(|row| row.get(column).unwrap()).collect()
+    };
+    let table = client
+        .query(
+            "SELECT addMonths(day, 1), subtractYears(day, 1), addDays(at, 1), \
+             at + INTERVAL 45 MINUTE, day - INTERVAL 1 WEEK, day + INTERVAL 12 HOUR \
+             FROM shift_db.events ORDER BY id",
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        strings(&table, "addMonths(day, 1)"),
+        ["2020-02-29", "2024-03-29", "2100-04-01"]
+    );
+    assert_eq!(
+        strings(&table, "subtractYears(day, 1)"),
+        ["2019-01-31", "2023-02-28", "2099-03-01"]
+    );
+    assert_eq!(
+        strings(&table, "addDays(at, 1)"),
+        [
+            "2020-02-01 10:00:00",
+            "2024-03-01 23:30:00",
+            "2100-03-02 00:00:00"
+        ]
+    );
+    assert_eq!(
+        strings(&table, "at + INTERVAL 45 MINUTE"),
+        [
+            "2020-01-31 10:45:00",
+            "2024-03-01 00:15:00",
+            "2100-03-01 00:45:00"
+        ]
+    );
+    assert_eq!(
+        strings(&table, "day - INTERVAL 1 WEEK"),
+        ["2020-01-24", "2024-02-22", "2100-02-22"]
+    );
+    assert_eq!(
+        strings(&table, "day + INTERVAL 12 HOUR"),
+        [
+            "2020-01-31 12:00:00",
+            "2024-02-29 12:00:00",
+            "2100-03-01 12:00:00"
+        ]
+    );
+
+    for (filter, expected) in [
+        ("at > toDateTime(now()) - INTERVAL 7 DAY", vec![3]),
+        ("day <= toDate(now()) + INTERVAL 1 YEAR", vec![1, 2]),
+        ("addYears(day, 4) = '2024-01-31'", vec![1]),
+        (
+            "at - INTERVAL 30 MINUTE >= '2024-02-29 23:00:00'",
+            vec![2, 3],
+        ),
+    ] {
+        let table = client
+            .query(&format!(
+                "SELECT id FROM shift_db.events WHERE {filter} ORDER BY id"
+            ))
+            .await
+            .unwrap();
+        let ids: Vec<u64> = table.rows().map(|row| row.get("id").unwrap()).collect();
+        assert_eq!(ids, expected, "{filter}");
+    }
+
+    for sql in [
+        "SELECT id + INTERVAL 1 DAY FROM shift_db.events",
+        "SELECT addDays(day, 'a') FROM shift_db.events",
+    ] {
+        let result = client.query(sql).await;
+        assert!(matches!(result, Err(Error::Server(_))), "{sql}: {result:?}");
+    }
+}
+
+#[tokio::test]
+async fn test_json_functions() {
+    let server = TestServer::start("json", 0).await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+    for sql in [
+        "CREATE DATABASE json_db",
+        "CREATE TABLE json_db.events (id UInt64, payload String) ORDER BY id",
+        "INSERT INTO json_db.events (id, payload) VALUES \
+         (1, '{\"name\": \"click\", \"count\": 7, \"tags\": [\"a\", \"b\"]}'), \
+         (2, '{\"name\": \"view\", \"count\": 2}'), \
+         (3, 'not json'), \
+         (4, NULL)",
+    ] {
+        client.query(sql).await.unwrap();
+    }
+
+    let table = client
+        .query(
+            "SELECT id, JSONExtractString(payload, '$.name'), JSONExtractInt(payload, '$.count'), \
+             JSONHas(payload, '$.tags'), JSONLength(payload, '$.tags') FROM json_db.events \
+             ORDER BY id",
+        )
+        .await
+        .unwrap();
+    type JsonRow = (u64, Option<String>, Option<i64>, Option<bool>, Option<u64>);
+    let rows: Vec<JsonRow> = table
+        .rows()
+        .map(|row| {
+            (
+                row.get("id").unwrap(),
+                row.get("JSONExtractString(payload, '$.name')").unwrap(),
+                row.get("JSONExtractInt(payload, '$.count')").unwrap(),
+                row.get("JSONHas(payload, '$.tags')").unwrap(),
+                row.get("JSONLength(payload, '$.tags')").unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        rows,
+        [
+            (1, Some("click".to_string()), Some(7), Some(true), Some(2)),
+            (2, Some("view".to_string()), Some(2), Some(false), None),
+            (3, None, None, None, None),
+            (4, None, None, None, None),
+        ]
+    );
+
+    // malformed JSON and missing paths do not match filters, but match their negation
+    for (filter, expected) in [
+        ("JSONHas(payload, '$.tags')", vec![1]),
+        ("NOT JSONHas(payload, '$.tags')", vec![2, 3, 4]),
+        ("JSONExtractInt(payload, '$.count') > 5", vec![1]),
+        ("JSONExtractString(payload, '$.name') = 'view'", vec![2]),
+        ("JSONLength(payload, '$.tags') = 2", vec![1]),
+    ] {
+        let table = client
+            .query(&format!(
+                "SELECT id FROM json_db.events WHERE {filter} ORDER BY id"
+            ))
+            .await
+            .unwrap();
+        let ids: Vec<u64> = table.rows().map(|row| row.get("id").unwrap()).collect();
+        assert_eq!(ids, expected, "{filter}");
+    }
+
+    let error = client
+        .query("SELECT JSONHas(payload, 'tags') FROM json_db.events")
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("JSON path"), "{error}");
+}
+
+#[tokio::test]
+async fn test_import_csv_and_tsv() {
+    let files =
+        std::env::temp_dir().join(format!("touchhouse-import-files-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&files);
+    std::fs::create_dir_all(&files).unwrap();
+    let server = TestServer::start_with_config(
+        "import",
+        0,
+        &format!(
+            "max_insert_block_rows = 2\nimport_directory = \"{}\"",
+            files.display()
+        ),
+    )
+    .await;
+    let mut client = Client::connect(&server.addr).await.unwrap();
+    for sql in [
+        "CREATE DATABASE import_db",
+        "CREATE TABLE import_db.users (id UInt64, name String, age UInt8 NULL, \
+         note String DEFAULT 'none') ORDER BY id",
+        "CREATE TABLE import_db.strict (id UInt64, age UInt8 NULL) ORDER BY id",
+        "CREATE TABLE import_db.tolerant (id UInt64, age UInt8 NULL) ORDER BY id",
+    ] {
+        client.query(sql).await.unwrap();
+    }
+    let ids = async |client: &mut Client, table: &str| -> Vec<u64> {
+        let table = client
+            .query(&format!("SELECT id FROM import_db.{table} ORDER BY id"))
+            .await
+            .unwrap();
+        table.rows().map(|row| row.get("id").unwrap()).collect()
+    };
+
+    // quoted fields hold delimiters, quotes and line breaks, empty field is NULL
+    std::fs::write(
+        files.join("users.csv"),
+        "id,name,age\n\
+         1,\"Smith, John\",30\n\
+         2,\"say \"\"hi\"\"\",\n\
+         3,\"two\nlines\",41\n",
+    )
+    .unwrap();
+    let table = client
+        .query(
+            "IMPORT INTO import_db.users (id, name, age) FROM 'users.csv' FORMAT CSV WITH HEADER",
+        )
+        .await
+        .unwrap();
+    let row = table.rows().next().unwrap();
+    assert_eq!(row.get::<u64>("rows_imported").unwrap(), 3);
+    assert_eq!(row.get::<u64>("rows_skipped").unwrap(), 0);
+
+    // fields of TSV are escaped instead, `\N` is NULL
+    std::fs::write(files.join("users.tsv"), "4\ttab\\there\t\\N\n5\tplain\t7\n").unwrap();
+    client
+        .query("IMPORT INTO import_db.users (id, name, age) FROM 'users.tsv' FORMAT TSV")
+        .await
+        .unwrap();
+    // other delimiter, with absolute path inside of the directory
+    std::fs::write(files.join("users.txt"), "6;semi;\n").unwrap();
+    client
+        .query(&format!(
+            "IMPORT INTO import_db.users (id, name, age) FROM '{}' FORMAT CSV DELIMITER ';'",
+            files.join("users.txt").display()
+        ))
+        .await
+        .unwrap();
+
+    let table = client
+        .query("SELECT id, name, age, note FROM import_db.users ORDER BY id")
+        .await
+        .unwrap();
+    let rows: Vec<(u64, String, Option<u8>, String)> = table
+        .rows()
+        .map(|row| {
+            (
+                row.get("id").unwrap(),
+                row.get("name").unwrap(),
+                row.get("age").unwrap(),
+                row.get("note").unwrap(),
+            )
+        })
+        .collect();
+    let none = || "none".to_string();
+    assert_eq!(
+        rows,
+        [
+            (1, "Smith, John".to_string(), Some(30), none()),
+            (2, "say \"hi\"".to_string(), None, none()),
+            (3, "two\nlines".to_string(), Some(41), none()),
+            (4, "tab\there".to_string(), None, none()),
+            (5, "plain".to_string(), Some(7), none()),
+            (6, "semi".to_string(), None, none()),
+        ]
+    );
+
+    // line 3 is bad: strict import fails after the first block, naming line and column
+    std::fs::write(
+        files.join("bad.csv"),
+        "10,1\n11,2\n12,abc\n13,4\n14\n15,6\n",
+    )
+    .unwrap();
+    let error = client
+        .query("IMPORT INTO import_db.strict (id, age) FROM 'bad.csv' FORMAT CSV")
+        .await
+        .unwrap_err()
+        .to_string();
+    assert!(
+        error.contains("line 3, column 'age': could not parse 'abc' as UInt8"),
+        "{error}"
+    );
+    assert!(error.contains("2 rows were imported before"), "{error}");
+    assert_eq!(ids(&mut client, "strict").await, [10, 11]);
+
+    // tolerant import skips up to `input_allow_errors_num` bad lines
+    let table = client
+        .query(
+            "IMPORT INTO import_db.tolerant (id, age) FROM 'bad.csv' FORMAT CSV \
+             SETTINGS input_allow_errors_num = 2",
+        )
+        .await
+        .unwrap();
+    let row = table.rows().next().unwrap();
+    assert_eq!(row.get::<u64>("rows_imported").unwrap(), 4);
+    assert_eq!(row.get::<u64>("rows_skipped").unwrap(), 2);
+    assert_eq!(ids(&mut client, "tolerant").await, [10, 11, 13, 15]);
+    let error = client
+        .query(
+            "IMPORT INTO import_db.tolerant (id, age) FROM 'bad.csv' FORMAT CSV \
+             SETTINGS input_allow_errors_num = 1",
+        )
+        .await
+        .unwrap_err();
+    assert!(
+        error.to_string().contains("line 5: expected 2 fields"),
+        "{error}"
+    );
+
+    // files outside of the directory are not read
+    std::fs::write(files.with_extension("csv"), "1,1\n").unwrap();
+    for path in [
+        "../touchhouse-import-files.csv".to_string(),
+        files.with_extension("csv").display().to_string(),
+        "/etc/passwd".to_string(),
+    ] {
+        let error = client
+            .query(&format!(
+                "IMPORT INTO import_db.strict (id, age) FROM '{path}' FORMAT CSV"
+            ))
+            .await
+            .unwrap_err();
+        assert!(
+            error.to_string().contains("outside of import_directory"),
+            "{path}: {error}"
+        );
+    }
+    let error = client
+        .query("IMPORT INTO import_db.strict (id, age) FROM 'missing.csv' FORMAT CSV")
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("Could not open"), "{error}");
+    let error = client
+        .query("IMPORT INTO import_db.strict (age) FROM 'bad.csv' FORMAT CSV")
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("(id)"), "{error}");
+
+    let _ = std::fs::remove_file(files.with_extension("csv"));
+    let _ = std::fs::remove_dir_all(&files);
+}
+
+/// Copies directory `from` with its contents to `to`.
+fn copy_dir(from: &Path, to: &Path) {
+    std::fs::create_dir_all(to).unwrap();
+    for entry in std::fs::read_dir(from).unwrap() {
+        let entry = entry.unwrap();
+        let path = entry.path();
+        if path.is_dir() {
+            copy_dir(&path, &to.join(entry.file_name()));
+        } else {
+            std::fs::copy(&path, to.join(entry.file_name())).unwrap();
+        }
+    }
+}
+
+/// Storage directories in `tests/fixtures/legacy`, written by older versions in layouts, which
+/// later changed, and whether they have the partitioned table. `baseline` is written by the
+/// first release, before column types had parameters. Each has database `legacy` with
+/// `t` (two parts, the second one with the default `score`), `r` (`ReplacingMergeTree`, both
+/// parts have key 1) and, once `PARTITION BY` was added, `p` (two partitions).
+const LEGACY_STORAGES: [(&str, bool); 4] = [
+    ("without_sequence", true),
+    ("literal_defaults", true),
+    ("without_partition", false),
+    ("baseline", false),
+];
+
+#[tokio::test]
+async fn test_legacy_storage_is_readable() {
+    for (fixture, partitioned) in LEGACY_STORAGES {
+        let dir = TestServer::create_dir(&format!("legacy-{fixture}"));
+        let storage_dir = dir.join("db");
+        copy_dir(
+            &Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("tests/fixtures/legacy")
+                .join(fixture),
+            &storage_dir,
+        );
+        let server = TestServer::start_on_storage(dir, &storage_dir, 0, "").await;
+        let mut client = Client::connect(&server.addr).await.unwrap();
+
+        client
+            .query("INSERT INTO legacy.t (id, name) VALUES (4, 'd')")
+            .await
+            .unwrap();
+        let table = client
+            .query("SELECT id, name, score FROM legacy.t ORDER BY id")
+            .await
+            .unwrap();
+        let rows: Vec<(u64, String, i32)> = table
+            .rows()
+            .map(|row| {
+                (
+                    row.get("id").unwrap(),
+                    row.get("name").unwrap(),
+                    row.get("score").unwrap(),
+                )
+            })
+            .collect();
+        let expected = [(1, "a", -1), (2, "b", 5), (3, "c", 7), (4, "d", 7)];
+        assert_eq!(
+            rows,
+            expected
+                .map(|(id, name, score)| (id, name.to_string(), score))
+                .to_vec(),
+            "{fixture}"
+        );
+
+        // parts are merged in the order they were written
+        client.query("OPTIMIZE TABLE legacy.r").await.unwrap();
+        let table = client
+            .query("SELECT id, v FROM legacy.r ORDER BY id")
+            .await
+            .unwrap();
+        let rows: Vec<(u64, String)> = table
+            .rows()
+            .map(|row| (row.get("id").unwrap(), row.get("v").unwrap()))
+            .collect();
+        let expected = [(1, "new"), (2, "keep")];
+        assert_eq!(
+            rows,
+            expected.map(|(id, v)| (id, v.to_string())).to_vec(),
+            "{fixture}"
+        );
+
+        if partitioned {
+            let table = client
+                .query("SELECT id FROM legacy.p WHERE country = 'de' ORDER BY id")
+                .await
+                .unwrap();
+            let ids: Vec<u64> = table.rows().map(|row| row.get("id").unwrap()).collect();
+            assert_eq!(ids, [1, 3], "{fixture}");
+            let table = client
+                .query("SELECT id FROM legacy.p WHERE month = '2024-02' ORDER BY id")
+                .await
+                .unwrap();
+            let ids: Vec<u64> = table.rows().map(|row| row.get("id").unwrap()).collect();
+            assert_eq!(ids, [2, 4], "{fixture}");
+        }
+        assert!(
+            !server.log().contains("Failed"),
+            "{fixture}: {}",
+            server.log()
+        );
+    }
+}
@@ -0,0 +1,69 @@
+//! Checks that columns, which are missing in a part, are read as constants instead of a `NULL`
+//! per row, by measuring peak heap usage of the scan.
+
+mod common;
+
+use common::{CountingAllocator, execute, open_default_instance, peak, reset_peak, test_dir};
+use touchhouse::runtime_config::TABLE_DATA;
+use touchhouse::sql::CommandRunner;
+use touchhouse::storage::{Column, ColumnData, TableDef, Value};
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+const ROWS: usize = 200_000;
+const MISSING_COLUMNS: usize = 9;
+
+#[test]
+fn test_missing_columns_are_not_materialized() {
+    let dir = test_dir("column-allocations");
+    open_default_instance(&dir, "");
+    let missing: Vec<_> = (0..MISSING_COLUMNS).map(|idx| format!("c{idx}")).collect();
+    execute("CREATE DATABASE alloc_db").unwrap();
+    execute(&format!(
+        "CREATE TABLE alloc_db.t (id UInt64, {}) ORDER BY id",
+        missing
+            .iter()
+            .map(|name| format!("{name} String"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    ))
+    .unwrap();
+
+    // part is written with `id` only, as if other columns were added after it
+    let table_def = TableDef {
+        database: "alloc_db".to_string(),
+        table: "t".to_string(),
+    };
+    let id_def = TABLE_DATA.get(&table_def).unwrap().metadata.schema.columns[0].clone();
+    CommandRunner::insert(
+        &table_def,
+        vec![Column {
+            column_def: id_def,
+            data: (0..ROWS as u64).map(Value::UInt64).collect(),
+        }],
+    )
+    .unwrap();
+
+    let baseline = reset_peak();
+    let output = execute(&format!(
+        "SELECT id, {} FROM alloc_db.t",
+        missing.join(", ")
+    ))
+    .unwrap();
+    let peak = peak() - baseline;
+
+    assert_eq!(output.row_count(), ROWS);
+    for column in &output.columns[1..] {
+        assert!(matches!(column.data, ColumnData::Constant { .. }));
+        assert_eq!(column.data, ColumnData::from(vec![Value::Null; ROWS]));
+    }
+    // `NULL` per row of every missing column would take more than all other allocations
+    let dense_nulls = ROWS * MISSING_COLUMNS * size_of::<Value>();
+    assert!(
+        peak < dense_nulls / 2,
+        "peak {peak} bytes, dense NULL columns {dense_nulls} bytes"
+    );
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,98 @@
+//! Helpers of test binaries, which run the engine in process. Each binary uses some of them.
+#![allow(dead_code)]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use touchhouse::CONFIG;
+use touchhouse::access::AccessLevel;
+use touchhouse::error::Result;
+use touchhouse::sql::CommandRunner;
+use touchhouse::storage::{self, OutputTable};
+use uuid::Uuid;
+
+/// Counts bytes allocated by the process, and the peak since the last `reset_peak`. Binary,
+/// which measures its allocations, declares it as its `#[global_allocator]`.
+pub struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // SAFETY: forwarded to the system allocator with the same layout.
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            let allocated = ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(allocated, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: `ptr` was allocated by `alloc` with the same layout.
+        unsafe { System.dealloc(ptr, layout) };
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+/// Resets peak to the current allocation, and returns it.
+pub fn reset_peak() -> usize {
+    let allocated = ALLOCATED.load(Ordering::Relaxed);
+    PEAK.store(allocated, Ordering::Relaxed);
+    allocated
+}
+
+/// Returns the peak allocation since the last `reset_peak`.
+pub fn peak() -> usize {
+    PEAK.load(Ordering::Relaxed)
+}
+
+/// Returns an empty directory of the test in the temp directory. Directory, left by a previous
+/// run, is removed.
+pub fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("touchhouse-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Configures the default instance with storage in `dir/db` and `extra_config` lines appended
+/// to the config file, and loads its tables. Called once, before any other thread starts.
+pub fn open_default_instance(dir: &Path, extra_config: &str) {
+    // `extra_config` may stop automatic merges with its own value
+    let merges = if extra_config.contains("background_merge_available_under") {
+        ""
+    } else {
+        "background_merge_available_under = 5\n"
+    };
+    let config_path = dir.join("touch_config.toml");
+    std::fs::write(
+        &config_path,
+        format!(
+            "storage_directory = \"{}\"\n\
+             tcp_socket = \"127.0.0.1:0\"\n\
+             max_connections = 1\n\
+             log_level = 3\n\
+             {merges}\
+             {extra_config}",
+            dir.join("db").display()
+        ),
+    )
+    .unwrap();
+    // SAFETY: no other threads are running yet, and `CONFIG` was not read before.
+    unsafe { std::env::set_var("CONFIG_PATH", &config_path) };
+    storage::load_all_parts_on_startup(CONFIG.get_db_dir()).unwrap();
+}
+
+/// Points `CONFIG_PATH` at a missing file, so code, which runs against the default instance
+/// instead of an opened one, panics. Called once, before any other thread starts.
+pub fn without_default_instance(dir: &Path) {
+    // SAFETY: no other threads are running yet.
+    unsafe { std::env::set_var("CONFIG_PATH", dir.join("missing").join("touch_config.toml")) };
+}
+
+/// Executes SQL command against the default instance with `readwrite` access.
+pub fn execute(sql: &str) -> Result<OutputTable> {
+    CommandRunner::execute_command(sql, AccessLevel::ReadWrite, Uuid::now_v7())
+}
@@ -0,0 +1,101 @@
+//! Checks that inserts and merges leave `min_free_bytes` of free disk space and stay within
+//! database quotas, with free space reported by a fake `SpaceChecker`.
+
+mod common;
+
+use common::{execute, open_default_instance, test_dir};
+use touchhouse::background_merge::BackgroundMerge;
+use touchhouse::error::Error;
+use touchhouse::runtime_config::TABLE_DATA;
+use touchhouse::storage::{SpaceChecker, TableDef, set_space_checker};
+
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+const MIN_FREE_BYTES: u64 = 1_000_000;
+
+/// Reports free space, set by the test.
+struct FakeChecker(AtomicU64);
+
+impl SpaceChecker for FakeChecker {
+    fn available_bytes(&self, _path: &Path) -> std::io::Result<u64> {
+        Ok(self.0.load(Ordering::Relaxed))
+    }
+}
+
+fn part_count(table_def: &TableDef) -> usize {
+    TABLE_DATA.get(table_def).unwrap().infos.len()
+}
+
+#[test]
+fn test_writes_respect_free_space_and_quotas() {
+    let dir = test_dir("disk-space");
+    open_default_instance(
+        &dir,
+        &format!("min_free_bytes = {MIN_FREE_BYTES}\n[quotas]\nquota_db = 100\n"),
+    );
+
+    let checker = Arc::new(FakeChecker(AtomicU64::new(10 * MIN_FREE_BYTES)));
+    set_space_checker(checker.clone());
+
+    execute("CREATE DATABASE space_db").unwrap();
+    execute("CREATE TABLE space_db.t (id UInt64) ORDER BY id").unwrap();
+    let table_def = TableDef {
+        database: "space_db".to_string(),
+        table: "t".to_string(),
+    };
+
+    // stop merges, until the disk is full
+    execute("ALTER TABLE space_db.t MODIFY SETTING no_merges = 1").unwrap();
+    execute("INSERT INTO space_db.t (id) VALUES (1), (2)").unwrap();
+    execute("INSERT INTO space_db.t (id) VALUES (3)").unwrap();
+    assert_eq!(part_count(&table_def), 2);
+
+    // insert would leave less than `min_free_bytes`
+    checker.0.store(MIN_FREE_BYTES + 10, Ordering::Relaxed);
+    let files_before = std::fs::read_dir(table_def.get_path()).unwrap().count();
+    let error = execute("INSERT INTO space_db.t (id) VALUES (4), (5)").unwrap_err();
+    assert!(matches!(error, Error::DiskFull(_)), "{error}");
+    assert_eq!(part_count(&table_def), 2);
+    assert_eq!(
+        std::fs::read_dir(table_def.get_path()).unwrap().count(),
+        files_before
+    );
+
+    // merges are paused while the disk is full
+    execute("ALTER TABLE space_db.t MODIFY SETTING no_merges = 0").unwrap();
+    let (shutdown, _) = tokio::sync::watch::channel(false);
+    let stopped = BackgroundMerge::start(&shutdown).unwrap();
+    std::thread::sleep(Duration::from_millis(500));
+    assert_eq!(part_count(&table_def), 2);
+
+    // and resume after free space is back
+    checker.0.store(10 * MIN_FREE_BYTES, Ordering::Relaxed);
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while part_count(&table_def) > 1 && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    assert_eq!(part_count(&table_def), 1);
+    execute("INSERT INTO space_db.t (id) VALUES (4), (5)").unwrap();
+
+    shutdown.send(true).unwrap();
+    for worker in stopped {
+        worker.blocking_recv().unwrap();
+    }
+
+    // first part fits into the quota, next one does not, because of files of the first one
+    execute("CREATE DATABASE quota_db").unwrap();
+    execute("CREATE TABLE quota_db.t (id UInt64) ORDER BY id").unwrap();
+    execute("INSERT INTO quota_db.t (id) VALUES (1)").unwrap();
+    let error = execute("INSERT INTO quota_db.t (id) VALUES (2)").unwrap_err();
+    assert!(
+        matches!(&error, Error::QuotaExceeded(database, _, 100) if database == "quota_db"),
+        "{error}"
+    );
+    // quota is per database
+    execute("INSERT INTO space_db.t (id) VALUES (6)").unwrap();
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
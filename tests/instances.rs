@@ -0,0 +1,78 @@
+//! Runs engine instances in process, each on its own storage directory, without the config file
+//! of the default instance.
+
+mod common;
+
+use common::{test_dir, without_default_instance};
+use touchhouse::error::Error;
+use touchhouse::runtime_config::Instance;
+use touchhouse::storage::{TableDef, Value};
+
+fn ids(instance: &'static Instance) -> Vec<Value> {
+    let output = instance
+        .execute_command("SELECT id FROM shared_db.t ORDER BY id")
+        .unwrap();
+    output.columns[0].data.iter().cloned().collect()
+}
+
+#[test]
+fn test_instances_are_isolated() {
+    without_default_instance(&test_dir("instances"));
+
+    let first_dir = test_dir("instances-first");
+    let second_dir = test_dir("instances-second");
+    let first = Instance::open(&first_dir).unwrap();
+    let second = Instance::open(&second_dir).unwrap();
+
+    // the same tables are created and filled at the same time
+    std::thread::scope(|scope| {
+        for (instance, values) in [(first, "(1), (2), (3)"), (second, "(10), (20)")] {
+            scope.spawn(move || {
+                instance
+                    .execute_command("CREATE DATABASE shared_db")
+                    .unwrap();
+                instance
+                    .execute_command("CREATE TABLE shared_db.t (id UInt64) ORDER BY id")
+                    .unwrap();
+                instance
+                    .execute_command(&format!("INSERT INTO shared_db.t (id) VALUES {values}"))
+                    .unwrap();
+            });
+        }
+    });
+
+    assert_eq!(
+        ids(first),
+        vec![Value::UInt64(1), Value::UInt64(2), Value::UInt64(3)]
+    );
+    assert_eq!(ids(second), vec![Value::UInt64(10), Value::UInt64(20)]);
+
+    let table_def = TableDef {
+        database: "shared_db".to_string(),
+        table: "t".to_string(),
+    };
+    for (instance, dir) in [(first, &first_dir), (second, &second_dir)] {
+        assert_eq!(instance.config().get_db_dir(), dir);
+        assert_eq!(
+            instance.table_data().get(&table_def).unwrap().infos.len(),
+            1
+        );
+        assert!(instance.enter(|| table_def.get_path()).starts_with(dir));
+    }
+
+    // dropping a database of one instance keeps the other one
+    first.execute_command("DROP DATABASE shared_db").unwrap();
+    let error = first
+        .execute_command("SELECT id FROM shared_db.t")
+        .unwrap_err();
+    assert!(matches!(error, Error::TableNotFound), "{error}");
+    assert!(!first_dir.join("shared_db").exists());
+    assert_eq!(ids(second), vec![Value::UInt64(10), Value::UInt64(20)]);
+
+    // tables are loaded from the storage directory
+    let reopened = Instance::open(&second_dir).unwrap();
+    assert_eq!(ids(reopened), vec![Value::UInt64(10), Value::UInt64(20)]);
+
+    let _ = std::fs::remove_dir_all(&first_dir);
+    let _ = std::fs::remove_dir_all(&second_dir);
+}
@@ -0,0 +1,94 @@
+//! Checks that memory of a background merge of sorted parts is bounded by their granules, not
+//! by their rows, by measuring peak heap usage of `OPTIMIZE TABLE`.
+
+mod common;
+
+use common::{CountingAllocator, execute, open_default_instance, peak, reset_peak, test_dir};
+use touchhouse::background_merge::BackgroundMerge;
+use touchhouse::runtime_config::TABLE_DATA;
+use touchhouse::sql::CommandRunner;
+use touchhouse::storage::{Column, TableDef, Value};
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+const PART_ROWS: u64 = 100_000;
+const PAYLOAD_BYTES: usize = 256;
+
+#[test]
+fn test_merge_memory_is_bounded_by_granules() {
+    let dir = test_dir("merge-memory");
+    // only the requested merge runs
+    open_default_instance(&dir, "background_merge_available_under = 0\n");
+    execute("CREATE DATABASE merge_db").unwrap();
+    execute(
+        "CREATE TABLE merge_db.t (id UInt64, payload String) ORDER BY id \
+         SETTINGS index_granularity = 1024",
+    )
+    .unwrap();
+
+    // rows of both parts interleave
+    let table_def = TableDef {
+        database: "merge_db".to_string(),
+        table: "t".to_string(),
+    };
+    let column_defs = TABLE_DATA
+        .get(&table_def)
+        .unwrap()
+        .metadata
+        .schema
+        .columns
+        .clone();
+    for offset in 0..2 {
+        let ids: Vec<u64> = (0..PART_ROWS).map(|idx| idx * 2 + offset).collect();
+        CommandRunner::insert(
+            &table_def,
+            vec![
+                Column {
+                    column_def: column_defs[0].clone(),
+                    data: ids.iter().copied().map(Value::UInt64).collect(),
+                },
+                Column {
+                    column_def: column_defs[1].clone(),
+                    data: ids
+                        .iter()
+                        .map(|id| Value::String(format!("{id:>PAYLOAD_BYTES$}")))
+                        .collect(),
+                },
+            ],
+        )
+        .unwrap();
+    }
+
+    let (shutdown, _) = tokio::sync::watch::channel(false);
+    let _stopped = BackgroundMerge::start(&shutdown).unwrap();
+    let baseline = reset_peak();
+    let output = execute("OPTIMIZE TABLE merge_db.t").unwrap();
+    let peak = peak() - baseline;
+    shutdown.send(true).unwrap();
+
+    assert_eq!(
+        output.columns[0].data.get(0),
+        Some(&Value::String("merged".to_string()))
+    );
+    let table_config = TABLE_DATA.get(&table_def).unwrap();
+    assert_eq!(table_config.infos.len(), 1);
+    assert_eq!(table_config.total_rows(), 2 * PART_ROWS);
+    drop(table_config);
+    let ids = execute("SELECT id FROM merge_db.t").unwrap();
+    assert!(
+        ids.columns[0].data.iter().eq((0..2 * PART_ROWS)
+            .map(Value::UInt64)
+            .collect::<Vec<_>>()
+            .iter())
+    );
+
+    // both parts in memory would take more than all other allocations together
+    let loaded = 2 * PART_ROWS as usize * (PAYLOAD_BYTES + 2 * size_of::<Value>());
+    assert!(
+        peak < loaded / 8,
+        "peak {peak} bytes, parts in memory {loaded} bytes"
+    );
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,59 @@
+//! Runs `OPTIMIZE TABLE` of an instance, opened in process, while merge workers run for the
+//! default instance, which has a table of the same name.
+
+mod common;
+
+use common::{execute, open_default_instance, test_dir};
+use touchhouse::background_merge::BackgroundMerge;
+use touchhouse::runtime_config::{Instance, TABLE_DATA, TableData};
+use touchhouse::storage::{TableDef, Value};
+
+#[test]
+fn test_optimize_merges_table_of_its_instance() {
+    let dir = test_dir("optimize-instances");
+    open_default_instance(&dir, "");
+    let other_dir = test_dir("optimize-instances-other");
+    let other = Instance::open(&other_dir).unwrap();
+
+    let (shutdown, _) = tokio::sync::watch::channel(false);
+    let _stopped = BackgroundMerge::start(&shutdown).unwrap();
+
+    // table of the default instance is never merged
+    execute("CREATE DATABASE shared_db").unwrap();
+    execute("CREATE TABLE shared_db.t (id UInt64) ORDER BY id").unwrap();
+    execute("ALTER TABLE shared_db.t MODIFY SETTING no_merges = 1").unwrap();
+    other.execute_command("CREATE DATABASE shared_db").unwrap();
+    other
+        .execute_command("CREATE TABLE shared_db.t (id UInt64) ORDER BY id")
+        .unwrap();
+    for id in 1..4 {
+        let sql = format!("INSERT INTO shared_db.t (id) VALUES ({id})");
+        execute(&sql).unwrap();
+        other.execute_command(&sql).unwrap();
+    }
+
+    let output = other.execute_command("OPTIMIZE TABLE shared_db.t").unwrap();
+    shutdown.send(true).unwrap();
+    assert_eq!(
+        output.columns[0].data.get(0),
+        Some(&Value::String("merged".to_string()))
+    );
+
+    let table_def = TableDef {
+        database: "shared_db".to_string(),
+        table: "t".to_string(),
+    };
+    let parts = |table_data: &TableData| table_data.get(&table_def).unwrap().infos.len();
+    assert_eq!(parts(other.table_data()), 1);
+    assert_eq!(parts(&TABLE_DATA), 3);
+    let ids = other
+        .execute_command("SELECT id FROM shared_db.t ORDER BY id")
+        .unwrap();
+    assert_eq!(
+        ids.columns[0].data.iter().cloned().collect::<Vec<_>>(),
+        [Value::UInt64(1), Value::UInt64(2), Value::UInt64(3)]
+    );
+
+    let _ = std::fs::remove_dir_all(&dir);
+    let _ = std::fs::remove_dir_all(&other_dir);
+}
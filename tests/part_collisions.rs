@@ -0,0 +1,66 @@
+//! Checks that a part is not moved over an active part or a directory with its name, which
+//! would lose rows of the existing part.
+
+mod common;
+
+use common::{execute, open_default_instance, test_dir};
+use touchhouse::runtime_config::TABLE_DATA;
+use touchhouse::storage::{Column, TableDef, TablePart, Value, WriteOrigin};
+use uuid::Uuid;
+
+#[test]
+fn test_part_name_collision_is_detected() {
+    let dir = test_dir("part-collisions");
+    open_default_instance(&dir, "");
+    execute("CREATE DATABASE collision_db").unwrap();
+    execute("CREATE TABLE collision_db.t (id UInt64) ORDER BY id").unwrap();
+    execute("INSERT INTO collision_db.t (id) VALUES (1), (2), (3)").unwrap();
+
+    let table_def = TableDef {
+        database: "collision_db".to_string(),
+        table: "t".to_string(),
+    };
+    let (id_def, existing) = {
+        let table_config = TABLE_DATA.get(&table_def).unwrap();
+        (
+            table_config.metadata.schema.columns[0].clone(),
+            table_config.infos[0].name.clone(),
+        )
+    };
+    let new_part = |name: &str| {
+        let mut part = TablePart::try_new(
+            &table_def,
+            vec![Column {
+                column_def: id_def.clone(),
+                data: vec![Value::UInt64(7)].into(),
+            }],
+            Some(name.to_string()),
+            None,
+        )
+        .unwrap();
+        part.save_raw(&table_def, WriteOrigin::Insert).unwrap();
+        part
+    };
+
+    // name of an active part
+    let error = new_part(&existing).move_to_normal(&table_def).unwrap_err();
+    assert!(error.to_string().contains("already exists"), "{error}");
+
+    // directory, which is not a known part, e.g., copied by hand
+    let copied = Uuid::now_v7().to_string();
+    let copied_dir = table_def.get_path().join(&copied);
+    std::fs::create_dir(&copied_dir).unwrap();
+    let error = new_part(&copied).move_to_normal(&table_def).unwrap_err();
+    assert!(error.to_string().contains("already exists"), "{error}");
+    assert_eq!(std::fs::read_dir(&copied_dir).unwrap().count(), 0);
+
+    // existing part is intact
+    assert_eq!(TABLE_DATA.get(&table_def).unwrap().infos.len(), 1);
+    let output = execute("SELECT id FROM collision_db.t").unwrap();
+    assert_eq!(
+        output.columns[0].data,
+        vec![Value::UInt64(1), Value::UInt64(2), Value::UInt64(3)].into()
+    );
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
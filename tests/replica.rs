@@ -0,0 +1,73 @@
+//! Runs a read-only default instance as the replica of an instance, opened in process on the
+//! same storage directory, and rescans the storage directory after changes of the primary.
+
+mod common;
+
+use common::{execute, open_default_instance, test_dir};
+use touchhouse::CONFIG;
+use touchhouse::background_merge::BackgroundMerge;
+use touchhouse::runtime_config::{Instance, TABLE_DATA};
+use touchhouse::storage::{self, TableDef};
+
+/// Asserts that the replica returns the same rows and count of the table as the primary, and
+/// has `parts` parts of it.
+fn assert_same(primary: &'static Instance, table: &str, parts: usize) {
+    for sql in [
+        format!("SELECT * FROM replica_db.{table} ORDER BY id"),
+        format!("SELECT count(*) FROM replica_db.{table}"),
+    ] {
+        let expected = primary.execute_command(&sql).unwrap().columns;
+        assert_eq!(execute(&sql).unwrap().columns, expected, "{sql}");
+    }
+    let table_def = TableDef {
+        database: "replica_db".to_string(),
+        table: table.to_string(),
+    };
+    assert_eq!(TABLE_DATA.get(&table_def).unwrap().infos.len(), parts);
+}
+
+#[test]
+fn test_replica_picks_up_replaced_parts_and_tables() {
+    let dir = test_dir("replica");
+    // workers only merge requested parts of the primary
+    open_default_instance(
+        &dir,
+        "read_only = true\nbackground_merge_available_under = 0\n",
+    );
+    let primary = Instance::open(dir.join("db")).unwrap();
+    let rescan = || storage::rescan_parts(CONFIG.get_db_dir()).unwrap();
+
+    for sql in [
+        "CREATE DATABASE replica_db",
+        "CREATE TABLE replica_db.t (id UInt64, name String) ORDER BY id",
+        "INSERT INTO replica_db.t (id, name) VALUES (1, 'a'), (3, 'c')",
+        "INSERT INTO replica_db.t (id, name) VALUES (2, 'b')",
+    ] {
+        primary.execute_command(sql).unwrap();
+    }
+    rescan();
+    assert_same(primary, "t", 2);
+
+    // merged part takes the name of the newer part
+    let (shutdown, _) = tokio::sync::watch::channel(false);
+    let _stopped = primary.enter(|| BackgroundMerge::start(&shutdown)).unwrap();
+    primary
+        .execute_command("OPTIMIZE TABLE replica_db.t")
+        .unwrap();
+    shutdown.send(true).unwrap();
+    rescan();
+    assert_same(primary, "t", 1);
+
+    // table is re-created with another schema in the same directory
+    for sql in [
+        "DROP TABLE replica_db.t",
+        "CREATE TABLE replica_db.t (id UInt64, score Int32) ORDER BY id",
+        "INSERT INTO replica_db.t (id, score) VALUES (7, -7)",
+    ] {
+        primary.execute_command(sql).unwrap();
+    }
+    rescan();
+    assert_same(primary, "t", 1);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
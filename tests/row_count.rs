@@ -0,0 +1,85 @@
+//! Checks that a part, whose `row_count` in `part.inf` does not match its marks, is loaded as
+//! suspect, and that `count(*)` of its table fails instead of returning a wrong number.
+
+mod common;
+
+use common::{test_dir, without_default_instance};
+use touchhouse::error::Error;
+use touchhouse::runtime_config::Instance;
+use touchhouse::storage::{TableDef, TablePartInfo, Value};
+
+/// Rewrites `part.inf` of the only part of the table with `row_count` and a valid checksum.
+fn set_row_count(instance: &'static Instance, table_def: &TableDef, row_count: u64) {
+    instance.enter(|| {
+        let table_config = instance.table_data().get(table_def).unwrap();
+        let mut info = table_config.infos[0].clone();
+        info.row_count = row_count;
+        info.write_to(table_def, false).unwrap();
+    });
+}
+
+#[test]
+fn test_corrupted_row_count_is_detected() {
+    let dir = test_dir("row-count");
+    without_default_instance(&dir);
+    let instance = Instance::open(&dir).unwrap();
+    for sql in [
+        "CREATE DATABASE rows_db",
+        "CREATE TABLE rows_db.t (id UInt64) ORDER BY id SETTINGS index_granularity = 2",
+        "INSERT INTO rows_db.t (id) VALUES (1), (2), (3), (4), (5)",
+    ] {
+        instance.execute_command(sql).unwrap();
+    }
+    let table_def = TableDef {
+        database: "rows_db".to_string(),
+        table: "t".to_string(),
+    };
+    let count =
+        |instance: &'static Instance| instance.execute_command("SELECT count(*) FROM rows_db.t");
+    assert_eq!(
+        count(instance).unwrap().columns[0].data.get(0),
+        Some(&Value::UInt64(5))
+    );
+    {
+        let table_config = instance.table_data().get(&table_def).unwrap();
+        assert_eq!(table_config.total_rows(), 5);
+        let part_path = instance.enter(|| table_config.infos[0].get_path(&table_def));
+        let info = TablePartInfo::read_from(&part_path, &table_config.metadata.settings).unwrap();
+        assert_eq!(info.suspect, None);
+    }
+
+    // 3 granules can not hold 7 rows, so part is loaded as suspect
+    set_row_count(instance, &table_def, 7);
+    let reopened = Instance::open(&dir).unwrap();
+    {
+        let table_config = reopened.table_data().get(&table_def).unwrap();
+        let reason = table_config.infos[0].suspect.as_deref().unwrap();
+        assert!(reason.contains("3 marks"), "{reason}");
+    }
+    let error = count(reopened).unwrap_err();
+    assert!(matches!(error, Error::CouldNotReadData(_)), "{error}");
+    let error = reopened
+        .execute_command("SELECT id FROM rows_db.t")
+        .unwrap_err();
+    assert!(matches!(error, Error::Internal(_)), "{error}");
+    // filtered scan does not know, how many rows to expect
+    let output = reopened
+        .execute_command("SELECT id FROM rows_db.t WHERE id > 3 ORDER BY id")
+        .unwrap();
+    assert_eq!(output.columns[0].data.len(), 2);
+
+    // 6 rows fit into 3 granules, so only a full scan finds the mismatch
+    set_row_count(reopened, &table_def, 6);
+    let reopened = Instance::open(&dir).unwrap();
+    assert_eq!(
+        reopened.table_data().get(&table_def).unwrap().infos[0].suspect,
+        None
+    );
+    let error = reopened
+        .execute_command("SELECT id FROM rows_db.t")
+        .unwrap_err();
+    let Error::Internal(message) = error else {
+        panic!("Expected Internal error, got {error}");
+    };
+    assert!(message.contains("Read 5 rows"), "{message}");
+}